@@ -1,8 +1,10 @@
 ESP_IMAGE := target/esp.img
 
-$(ESP_IMAGE): $(TARGET_FILES)
+$(ESP_IMAGE): $(TARGET_FILES) $(INITRD)
 	mkdir -p $(TARGET_DIR)/boot/EFI/BOOT
 	cp $(KERNEL) $(TARGET_DIR)/boot/EFI/BOOT/BOOTX64.EFI
+	cp $(INITRD) $(TARGET_DIR)/boot/EFI/BOOT/initrd.cpio
+	echo "$(CMDLINE)" > $(TARGET_DIR)/boot/EFI/BOOT/cmdline.txt
 	dd if=/dev/zero of=$@ bs=1M count=64
 	mkfs.vfat -F 32 $@ -n EFISYS
 	mcopy -i $@ -s $(TARGET_DIR)/boot/EFI ::
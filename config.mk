@@ -11,6 +11,14 @@ MODULES := kernel
 # The directory where the target file structure will be built
 TARGET_DIR := target_root
 
+# The directory whose contents are packed into the initial ramdisk
+INITRD_DIR := initrd
+# The initial ramdisk that will be placed on the EFI system partition
+INITRD := $(BUILD_DIR)/initrd.cpio
+
+# The kernel command line that will be placed on the EFI system partition
+CMDLINE ?=
+
 # The name of the iso file that will be generated in the end
 ISO := $(BUILD_DIR)/image.iso
 
@@ -24,11 +32,14 @@ RUST_COMPILER_FLAGS :=
 
 # The path to the OVMF image
 OVMF ?= /usr/share/ovmf/OVMF.fd
+# The display of qemu, which also takes the input for the PS/2 keyboard
+DISPLAY_TYPE ?= none
 # The flags to use for qemu
 QEMU_FLAGS := --no-reboot \
 			  -smp cores=4 \
 			  -s \
 			  -serial stdio \
+			  -serial tcp::1235,server,nowait \
 			  -net none \
-			  -display none \
+			  -display $(DISPLAY_TYPE) \
 			  -bios $(OVMF)
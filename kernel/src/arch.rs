@@ -3,28 +3,31 @@
 //! This module acts as an interface between the individual architecure specific parts and the rest of the kernel.
 //! It is supposed to abstract over the fact that there may be different architectures that the kernel is running on.
 
-use core::fmt;
+use crate::{
+    io_port::IoPortSet,
+    memory::{Access, PhysicalAddress, VirtualAddress},
+    time::Instant,
+};
 
-/// Prints text to the screen.
+/// Prints text to the consoles.
 #[macro_export]
 macro_rules! print {
     ($($arg:tt)*) => ({
-        use $crate::arch::Architecture;
-        $crate::arch::Arch::write_fmt(format_args!($($arg)*));
+        $crate::console::write_fmt(None, format_args!($($arg)*));
     });
 }
 
-/// Prints a line to the screen.
+/// Prints a line to the consoles.
 #[macro_export]
 macro_rules! println {
     () => ({
-        use $crate::arch::Architecture;
-        $crate::arch::Arch::write_line_break();
+        $crate::console::write_fmt(None, format_args!("\n"));
     });
-    ($($arg:tt)*) => ({
-        use $crate::arch::Architecture;
-        $crate::arch::Arch::write_fmt(format_args!($($arg)*));
-        $crate::arch::Arch::write_line_break();
+    ($fmt:expr) => ({
+        $crate::console::write_fmt(None, format_args!(concat!($fmt, "\n")));
+    });
+    ($fmt:expr, $($arg:tt)*) => ({
+        $crate::console::write_fmt(None, format_args!(concat!($fmt, "\n"), $($arg)*));
     });
 }
 
@@ -36,17 +39,91 @@ pub mod x86_64;
 #[cfg(target_arch = "x86_64")]
 pub type Arch = x86_64::x86_64;
 
+/// The page table hierarchy of the current architecture (x86_64).
+#[cfg(target_arch = "x86_64")]
+pub type PageTable = x86_64::memory::PageTable;
+
+/// The register state of user threads on the current architecture (x86_64).
+#[cfg(target_arch = "x86_64")]
+pub type UserContext = x86_64::context::UserContext;
+
+/// The reasons for user code to enter the kernel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KernelEntry {
+    /// The user code requested a system call.
+    Syscall,
+    /// The user code was interrupted by hardware.
+    Interrupt,
+    /// The user code accessed memory that is not mapped or does not allow the access.
+    PageFault {
+        /// The address that was accessed.
+        address: VirtualAddress,
+        /// The kind of the access.
+        access: Access,
+    },
+    /// The user code caused an exception.
+    Exception {
+        /// The architecture specific number of the exception.
+        vector: usize,
+        /// The architecture specific error code of the exception.
+        error_code: usize,
+    },
+}
+
 /// This type represents an abstraction of the underlying architecture.
 ///
 /// Each supported architecture implements this trait on a type which is then used for architecture specific actions.
 ///
 /// Using a trait here ensures that all the functions are implemented correctly by the corresponding architecture.
 pub trait Architecture {
-    /// Writes the formatted string to the screen.
-    fn write_fmt(args: fmt::Arguments);
+    /// The size of a page in bytes.
+    const PAGE_SIZE: usize;
+
+    /// The lowest virtual address that is available to user space.
+    const USER_SPACE_START: VirtualAddress;
+
+    /// The first virtual address above user space.
+    const USER_SPACE_END: VirtualAddress;
+
+    /// The number of hardware interrupt lines that can be handed to drivers.
+    const IRQ_COUNT: usize;
+
+    /// The maximum number of processors.
+    const MAXIMUM_CPU_COUNT: usize;
 
-    /// Writes a line break to the screen.
-    fn write_line_break();
+    /// Returns the index of the current processor, which is below `MAXIMUM_CPU_COUNT`.
+    fn current_cpu() -> usize;
+
+    /// Returns the virtual address at which the kernel can access the given physical address.
+    fn physical_to_virtual(address: PhysicalAddress) -> VirtualAddress;
+
+    /// Runs user code with the given register state until it enters the kernel again.
+    ///
+    /// The register state of the user code is stored in `context` before this returns.
+    ///
+    /// # Safety
+    /// The address space the user code belongs to must be active.
+    unsafe fn enter_user(context: &mut UserContext) -> KernelEntry;
+
+    /// Masks the hardware interrupt line, so that its interrupts are not delivered.
+    fn mask_irq(number: usize);
+
+    /// Unmasks the hardware interrupt line, so that its interrupts are delivered again.
+    fn unmask_irq(number: usize);
+
+    /// Allows user mode to access exactly the I/O ports in the set, or none if no set is given.
+    fn set_io_ports(ports: Option<&IoPortSet>);
+
+    /// Programs the timer interrupt of the current processor to occur once `deadline` has passed.
+    ///
+    /// The timer is stopped if no deadline is given.
+    /// The timer interrupt calls `time::run_timers`.
+    fn set_timer(deadline: Option<Instant>);
+
+    /// Enables interrupts, waits until an interrupt occurs and disables interrupts again.
+    ///
+    /// This lets an idle processor sleep.
+    fn wait_for_interrupt();
 
     /// Determines if interrupts are currently enabled.
     ///
@@ -2,18 +2,34 @@
 //!
 //! This abstracts the details of the x86_64 platform.
 
+pub mod acpi;
+mod apic;
 mod architecture_implementation;
 #[macro_use]
 pub mod serial;
+pub mod context;
+mod debugcon;
+pub mod gdb;
+mod gdt;
+mod interrupts;
 mod logger;
+pub mod memory;
+pub mod msi;
+pub mod pci;
+pub mod ps2;
+pub mod shell;
+mod time;
 pub mod uefi;
 
+use log::LevelFilter;
 use raw_cpuid::CpuId;
 use x86_64_crate::registers::{
     control::{Cr0, Cr0Flags},
     model_specific::{Efer, EferFlags},
 };
 
+use crate::{cmdline, console};
+
 pub use self::architecture_implementation::x86_64;
 pub use crate::sync::GlobalRuntimeConfiguration;
 
@@ -27,19 +43,31 @@ enum BootMethod {
 /// The method used to boot the system.
 static BOOT_METHOD: GlobalRuntimeConfiguration<BootMethod> = GlobalRuntimeConfiguration::new();
 
-/// Returns the current boot method.
-fn get_boot_method() -> BootMethod {
-    *BOOT_METHOD.get().expect("Could not read boot method.")
-}
-
 /// Performs early initialization for the x86_64 architecture.
-fn early_init() {
+///
+/// The kernel is configured by the given command line.
+fn early_init(command_line: &'static str) {
+    serial::init();
+
+    if serial::is_present(0) {
+        console::register("serial", &serial::SerialSink, LevelFilter::Trace);
+    }
+
+    console::register("debugcon", &debugcon::DebugConsoleSink, LevelFilter::Trace);
+    console::register("memory", &console::memory::MemorySink, LevelFilter::Trace);
+
     // Initialize the logger. If initialization fails, logging won't work.
     match log::set_logger(&logger::KERNEL_LOGGER) {
         _ => (),
     }
     log::set_max_level(crate::LOG_LEVEL);
 
+    cmdline::init(command_line);
+
+    if let Some(configuration) = cmdline::get() {
+        serial::configure(configuration);
+    }
+
     // Check that the CPU supports the necessary features.
     let cpuid = CpuId::new();
 
@@ -108,6 +136,15 @@ fn early_init() {
     }
 }
 
+/// Performs the initialization of the x86_64 architecture that requires exclusive control over the machine.
+///
+/// This must be called after the firmware was left.
+fn late_init() {
+    gdt::init();
+    interrupts::init();
+    context::init();
+}
+
 /// Exits qemu during an integration test.
 #[cfg(feature = "qemu_integration_test")]
 pub fn exit_integration_test(exit_code: IntegrationTestExitCode) -> ! {
@@ -0,0 +1,264 @@
+//! Finds the ACPI tables provided by the firmware.
+//!
+//! The firmware passes the physical address of the root system description pointer (RSDP),
+//! which leads to the root table listing all other tables.
+//! ACPI 2.0 and later use the extended root table (XSDT) with 64 bit addresses,
+//! while ACPI 1.0 uses the root table (RSDT) with 32 bit addresses.
+//!
+//! The tables are only located here, interpreting their contents is left to the code that needs them.
+
+use alloc::vec::Vec;
+use core::{mem::size_of, ptr, slice, str};
+
+use crate::{
+    arch::{Arch, Architecture},
+    memory::PhysicalAddress,
+    sync::GlobalRuntimeConfiguration,
+};
+
+/// The signature at the start of the root system description pointer.
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+
+/// The number of bytes of the root system description pointer covered by its first checksum.
+const RSDP_V1_LENGTH: usize = 20;
+
+/// The signature of the root table of ACPI 1.0.
+const RSDT_SIGNATURE: &str = "RSDT";
+
+/// The signature of the extended root table of ACPI 2.0 and later.
+const XSDT_SIGNATURE: &str = "XSDT";
+
+/// The root table, once it was found.
+static ROOT: GlobalRuntimeConfiguration<Root> = GlobalRuntimeConfiguration::new();
+
+/// The root system description pointer as defined by ACPI 2.0.
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+struct Rsdp {
+    /// Always `RSD PTR `.
+    signature: [u8; 8],
+    /// Makes the sum of the first 20 bytes zero.
+    checksum: u8,
+    /// Identifies the manufacturer of the firmware.
+    oem_id: [u8; 6],
+    /// Zero for ACPI 1.0 and two for later versions.
+    revision: u8,
+    /// The physical address of the RSDT.
+    rsdt_address: u32,
+    /// The length of the structure, only valid from ACPI 2.0 on.
+    length: u32,
+    /// The physical address of the XSDT, only valid from ACPI 2.0 on.
+    xsdt_address: u64,
+    /// Makes the sum of all bytes zero.
+    extended_checksum: u8,
+    /// Reserved by the specification.
+    reserved: [u8; 3],
+}
+
+/// The header shared by all system description tables.
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+struct Header {
+    /// Identifies the kind of the table.
+    signature: [u8; 4],
+    /// The length of the table, including the header.
+    length: u32,
+    /// The revision of the table format.
+    revision: u8,
+    /// Makes the sum of all bytes of the table zero.
+    checksum: u8,
+    /// Identifies the manufacturer of the firmware.
+    oem_id: [u8; 6],
+    /// Identifies the table among the tables of the manufacturer.
+    oem_table_id: [u8; 8],
+    /// The revision of the table given by the manufacturer.
+    oem_revision: u32,
+    /// Identifies the tool that created the table.
+    creator_id: u32,
+    /// The revision of the tool that created the table.
+    creator_revision: u32,
+}
+
+/// The root table that lists the other tables.
+#[derive(Clone, Copy)]
+struct Root {
+    /// The root table itself.
+    table: Table,
+    /// The size of the addresses of the other tables, which is 4 in the RSDT and 8 in the XSDT.
+    entry_size: usize,
+    /// The revision of the root system description pointer.
+    revision: u8,
+}
+
+/// A system description table.
+#[derive(Clone, Copy)]
+pub struct Table {
+    /// The physical address of the table.
+    address: PhysicalAddress,
+    /// The header of the table.
+    header: Header,
+}
+
+impl Table {
+    /// Reads the header of the table at the given physical address.
+    ///
+    /// # Safety
+    /// The address must point to a system description table.
+    unsafe fn at(address: PhysicalAddress) -> Table {
+        let header = ptr::read_unaligned(Arch::physical_to_virtual(address).as_ptr::<Header>());
+
+        Table { address, header }
+    }
+
+    /// Returns the physical address of the table.
+    pub fn address(&self) -> PhysicalAddress {
+        self.address
+    }
+
+    /// Returns the signature of the table, like `APIC` or `MCFG`.
+    pub fn signature(&self) -> &str {
+        let signature = &self.header.signature;
+
+        str::from_utf8(signature).unwrap_or("????")
+    }
+
+    /// Returns the length of the table in bytes, including the header.
+    pub fn length(&self) -> usize {
+        self.header.length as usize
+    }
+
+    /// Returns the revision of the table format.
+    pub fn revision(&self) -> u8 {
+        self.header.revision
+    }
+
+    /// Returns the identifier of the manufacturer of the firmware.
+    pub fn oem_id(&self) -> &str {
+        let oem_id = &self.header.oem_id;
+
+        str::from_utf8(oem_id).unwrap_or("").trim_end()
+    }
+
+    /// Returns the identifier of the table among the tables of the manufacturer.
+    pub fn oem_table_id(&self) -> &str {
+        let oem_table_id = &self.header.oem_table_id;
+
+        str::from_utf8(oem_table_id).unwrap_or("").trim_end()
+    }
+
+    /// Returns the bytes of the table, including the header.
+    pub fn bytes(&self) -> &'static [u8] {
+        // This is safe, because the firmware keeps the tables in memory that is never handed out.
+        unsafe {
+            slice::from_raw_parts(
+                Arch::physical_to_virtual(self.address).as_ptr::<u8>(),
+                self.length(),
+            )
+        }
+    }
+
+    /// Returns the bytes of the table after the header.
+    pub fn data(&self) -> &'static [u8] {
+        &self.bytes()[size_of::<Header>().min(self.length())..]
+    }
+
+    /// Checks if the checksum of the table is correct.
+    pub fn is_valid(&self) -> bool {
+        checksum(self.bytes()) == 0
+    }
+}
+
+/// Returns the sum of the bytes, which is zero for valid tables.
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |sum, &byte| sum.wrapping_add(byte))
+}
+
+/// Finds the root table using the root system description pointer at the given physical address.
+///
+/// The tables stay unavailable if the pointer or the root table is invalid.
+pub fn init(rsdp_address: PhysicalAddress) {
+    // This is safe, because the firmware passed the address of the RSDP, whose first version is always complete.
+    let rsdp_bytes = unsafe {
+        slice::from_raw_parts(
+            Arch::physical_to_virtual(rsdp_address).as_ptr::<u8>(),
+            RSDP_V1_LENGTH,
+        )
+    };
+
+    if &rsdp_bytes[..RSDP_SIGNATURE.len()] != RSDP_SIGNATURE || checksum(rsdp_bytes) != 0 {
+        log::warn!("The ACPI root system description pointer is invalid.");
+        return;
+    }
+
+    // This is safe, because the revision tells whether the extended fields are present.
+    let rsdp =
+        unsafe { ptr::read_unaligned(Arch::physical_to_virtual(rsdp_address).as_ptr::<Rsdp>()) };
+
+    let (address, entry_size, signature) = if rsdp.revision >= 2 && rsdp.xsdt_address != 0 {
+        (rsdp.xsdt_address as usize, 8, XSDT_SIGNATURE)
+    } else {
+        (rsdp.rsdt_address as usize, 4, RSDT_SIGNATURE)
+    };
+
+    // This is safe, because the RSDP points to the root table.
+    let table = unsafe { Table::at(PhysicalAddress::new(address)) };
+
+    if table.signature() != signature || !table.is_valid() {
+        log::warn!("The ACPI {} at {:#x} is invalid.", signature, address);
+        return;
+    }
+
+    ROOT.init(Root {
+        table,
+        entry_size,
+        revision: rsdp.revision,
+    });
+
+    log::debug!(
+        "Found the ACPI {} at {:#x} provided by \"{}\".",
+        signature,
+        address,
+        table.oem_id()
+    );
+}
+
+/// Returns the revision of the root system description pointer, if the tables were found.
+///
+/// It is zero for ACPI 1.0 and two for later versions.
+pub fn revision() -> Option<u8> {
+    ROOT.get().map(|root| root.revision)
+}
+
+/// Returns the root table, if the tables were found.
+pub fn root_table() -> Option<Table> {
+    ROOT.get().map(|root| root.table)
+}
+
+/// Returns the tables listed in the root table.
+pub fn tables() -> Vec<Table> {
+    let root = match ROOT.get() {
+        Some(root) => root,
+        None => return Vec::new(),
+    };
+
+    root.table
+        .data()
+        .chunks_exact(root.entry_size)
+        .map(|entry| {
+            let address = entry
+                .iter()
+                .rev()
+                .fold(0, |address, &byte| address << 8 | byte as usize);
+
+            // This is safe, because the root table lists the addresses of the other tables.
+            unsafe { Table::at(PhysicalAddress::new(address)) }
+        })
+        .collect()
+}
+
+/// Returns the first table with the given signature.
+pub fn find(signature: &str) -> Option<Table> {
+    tables()
+        .into_iter()
+        .find(|table| table.signature() == signature)
+}
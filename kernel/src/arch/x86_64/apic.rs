@@ -0,0 +1,335 @@
+//! Provides the interrupt controllers of the x86_64 architecture.
+//!
+//! Hardware interrupts are routed through the I/O APIC to the local APIC of the processor.
+//! The address of the I/O APIC and the routing of the ISA interrupt lines are taken from the MADT.
+//! The legacy 8259 interrupt controllers are disabled.
+//! The application processors are not started, so the boot processor handles all interrupts.
+
+use core::slice;
+use lazy_static::lazy_static;
+use x86_64_crate::{instructions::port::Port, registers::model_specific::Msr};
+
+use super::acpi;
+use crate::{
+    arch::{Arch, Architecture},
+    memory::PhysicalAddress,
+    sync::Mutex,
+};
+
+/// The vector of the first interrupt line.
+pub const IRQ_BASE_VECTOR: usize = 32;
+
+/// The vector of the timer interrupt of the local APIC, which follows the vectors of the interrupt lines.
+pub const TIMER_VECTOR: usize = IRQ_BASE_VECTOR + Arch::IRQ_COUNT;
+
+/// The first vector handed out for message signaled interrupts.
+pub const MSI_BASE_VECTOR: usize = 64;
+
+/// The number of vectors of each processor handed out for message signaled interrupts.
+pub const MSI_VECTOR_COUNT: usize = 128;
+
+/// The vector used for spurious interrupts of the local APIC.
+pub const SPURIOUS_VECTOR: usize = 0xff;
+
+/// The model specific register holding the physical address of the local APIC.
+const APIC_BASE: u32 = 0x1b;
+
+/// The bits of the `APIC_BASE` register that contain the physical address.
+const APIC_BASE_ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+/// The offset of the spurious interrupt vector register of the local APIC.
+const SPURIOUS_INTERRUPT_REGISTER: usize = 0xf0;
+
+/// Enables the local APIC when set in the spurious interrupt vector register.
+const APIC_SOFTWARE_ENABLE: u32 = 1 << 8;
+
+/// The offset of the end of interrupt register of the local APIC.
+const END_OF_INTERRUPT_REGISTER: usize = 0xb0;
+
+/// The offset of the ID register of the local APIC.
+const ID_REGISTER: usize = 0x20;
+
+/// The physical address of the I/O APIC if the firmware provides no MADT.
+const DEFAULT_IO_APIC_ADDRESS: PhysicalAddress = PhysicalAddress::new(0xfec0_0000);
+
+/// The signature of the multiple APIC description table (MADT).
+const MADT_SIGNATURE: &str = "APIC";
+
+/// The size of the fields of the MADT before its entries.
+const MADT_FIELDS_SIZE: usize = 8;
+
+/// The type of the MADT entries describing an I/O APIC.
+const IO_APIC_ENTRY: u8 = 1;
+
+/// The type of the MADT entries describing an interrupt source override.
+const INTERRUPT_SOURCE_OVERRIDE_ENTRY: u8 = 2;
+
+/// The mask of the polarity in the flags of an interrupt source override.
+const POLARITY_MASK: u16 = 0b11;
+
+/// The polarity of active low interrupts in the flags of an interrupt source override.
+const POLARITY_ACTIVE_LOW: u16 = 0b11;
+
+/// The polarity of active high interrupts in the flags of an interrupt source override.
+const POLARITY_ACTIVE_HIGH: u16 = 0b01;
+
+/// The mask of the trigger mode in the flags of an interrupt source override.
+const TRIGGER_MASK: u16 = 0b11 << 2;
+
+/// The trigger mode of level triggered interrupts in the flags of an interrupt source override.
+const TRIGGER_LEVEL: u16 = 0b11 << 2;
+
+/// The trigger mode of edge triggered interrupts in the flags of an interrupt source override.
+const TRIGGER_EDGE: u16 = 0b01 << 2;
+
+/// The first redirection table register of the I/O APIC.
+const REDIRECTION_TABLE: u32 = 0x10;
+
+/// Masks an interrupt line in its redirection table entry.
+const REDIRECTION_MASKED: u32 = 1 << 16;
+
+/// Makes an interrupt line level triggered in its redirection table entry.
+const REDIRECTION_LEVEL_TRIGGERED: u32 = 1 << 15;
+
+/// Makes an interrupt line active low in its redirection table entry.
+const REDIRECTION_ACTIVE_LOW: u32 = 1 << 13;
+
+/// The number of interrupt lines that belong to ISA devices.
+///
+/// These are edge triggered and active high, while the remaining lines are used by PCI devices,
+/// which are level triggered and active low.
+const ISA_IRQ_COUNT: usize = 16;
+
+/// Serializes accesses to the I/O APIC, which uses an index register.
+static IO_APIC_LOCK: Mutex<()> = Mutex::new(());
+
+lazy_static! {
+    /// The local APIC ID of the boot processor, which is the only one running.
+    static ref BOOT_PROCESSOR: usize = id();
+
+    /// The I/O APIC and the pins the interrupt lines are connected to.
+    static ref ROUTING: Routing = Routing::from_madt();
+}
+
+/// The pin of the I/O APIC that an interrupt line is connected to.
+#[derive(Clone, Copy)]
+struct Pin {
+    /// The index of the pin in the redirection table.
+    index: u32,
+    /// The trigger mode and polarity bits of the redirection table entry.
+    flags: u32,
+}
+
+/// How the interrupt lines are connected to the I/O APIC.
+struct Routing {
+    /// The physical address of the I/O APIC.
+    address: PhysicalAddress,
+    /// The pins of the interrupt lines, or `None` if a line is not connected.
+    pins: [Option<Pin>; Arch::IRQ_COUNT],
+}
+
+impl Routing {
+    /// Reads the routing from the MADT.
+    ///
+    /// Lines without an interrupt source override are connected to the pin with their number.
+    /// Without a MADT the I/O APIC is assumed to be at its usual address.
+    fn from_madt() -> Routing {
+        let mut routing = Routing {
+            address: DEFAULT_IO_APIC_ADDRESS,
+            pins: [None; Arch::IRQ_COUNT],
+        };
+
+        for (irq, pin) in routing.pins.iter_mut().enumerate() {
+            *pin = Some(Pin {
+                index: irq as u32,
+                flags: default_flags(irq),
+            });
+        }
+
+        let table = match acpi::find(MADT_SIGNATURE).filter(|table| table.is_valid()) {
+            Some(table) => table,
+            None => {
+                log::warn!("No MADT found, assuming the default I/O APIC routing.");
+                return routing;
+            }
+        };
+
+        let mut overrides = [None; ISA_IRQ_COUNT];
+        let mut entries = table.data().get(MADT_FIELDS_SIZE..).unwrap_or(&[]);
+
+        while entries.len() >= 2 && entries[1] >= 2 && entries.len() >= usize::from(entries[1]) {
+            let (entry, rest) = entries.split_at(usize::from(entries[1]));
+
+            match entry[0] {
+                // Only the I/O APIC handling the first interrupt lines is used.
+                IO_APIC_ENTRY if entry.len() >= 12 && read_u32(&entry[8..]) == 0 => {
+                    routing.address = PhysicalAddress::new(read_u32(&entry[4..]) as usize);
+                }
+                INTERRUPT_SOURCE_OVERRIDE_ENTRY if entry.len() >= 10 => {
+                    let source = usize::from(entry[3]);
+
+                    if source < ISA_IRQ_COUNT {
+                        let flags = u16::from_le_bytes([entry[8], entry[9]]);
+                        overrides[source] = Some((read_u32(&entry[4..]), flags));
+                    }
+                }
+                _ => (),
+            }
+
+            entries = rest;
+        }
+
+        for (source, entry) in overrides.iter().enumerate() {
+            let (gsi, flags) = match *entry {
+                Some(entry) => entry,
+                None => continue,
+            };
+            // The global system interrupts of the used I/O APIC start at zero.
+            let index = gsi;
+
+            if index as usize >= Arch::IRQ_COUNT {
+                continue;
+            }
+
+            // A line whose pin was taken over by another line is not connected anymore.
+            for pin in routing.pins.iter_mut() {
+                if pin.map_or(false, |pin| pin.index == index) {
+                    *pin = None;
+                }
+            }
+
+            routing.pins[source] = Some(Pin {
+                index,
+                flags: override_flags(source, flags),
+            });
+        }
+
+        routing
+    }
+}
+
+/// Reads the little endian 32 bit value at the start of the bytes.
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Returns the trigger mode and polarity of the line without an interrupt source override.
+fn default_flags(irq: usize) -> u32 {
+    if irq >= ISA_IRQ_COUNT {
+        REDIRECTION_LEVEL_TRIGGERED | REDIRECTION_ACTIVE_LOW
+    } else {
+        0
+    }
+}
+
+/// Returns the trigger mode and polarity given by the flags of an interrupt source override for the line.
+///
+/// Fields that conform to the bus keep the defaults of the line.
+fn override_flags(irq: usize, flags: u16) -> u32 {
+    let defaults = default_flags(irq);
+
+    let polarity = match flags & POLARITY_MASK {
+        POLARITY_ACTIVE_LOW => REDIRECTION_ACTIVE_LOW,
+        POLARITY_ACTIVE_HIGH => 0,
+        _ => defaults & REDIRECTION_ACTIVE_LOW,
+    };
+    let trigger = match flags & TRIGGER_MASK {
+        TRIGGER_LEVEL => REDIRECTION_LEVEL_TRIGGERED,
+        TRIGGER_EDGE => 0,
+        _ => defaults & REDIRECTION_LEVEL_TRIGGERED,
+    };
+
+    polarity | trigger
+}
+
+/// Returns a pointer to the register of the local APIC at the given offset.
+pub(super) fn local_apic_register(offset: usize) -> *mut u32 {
+    // This is safe, because the APIC base register is always present on supported processors.
+    let base = unsafe { Msr::new(APIC_BASE).read() } & APIC_BASE_ADDRESS_MASK;
+
+    (Arch::physical_to_virtual(PhysicalAddress::new(base as usize)) + offset).as_mut_ptr()
+}
+
+/// Writes to the register of the I/O APIC with the given index.
+fn write_io_apic(index: u32, value: u32) {
+    let base = Arch::physical_to_virtual(ROUTING.address);
+    let _lock = IO_APIC_LOCK.lock();
+
+    // This is safe, because the I/O APIC registers are memory mapped at this address.
+    unsafe {
+        (base.as_mut_ptr::<u32>()).write_volatile(index);
+        ((base + 0x10).as_mut_ptr::<u32>()).write_volatile(value);
+    }
+}
+
+/// Writes the low half of the redirection table entry of the pin the interrupt line is connected to.
+///
+/// The entry delivers the interrupts of the line to its vector. Lines that are not connected are ignored.
+fn write_redirection_entry(irq: usize, masked: bool) {
+    if let Some(pin) = ROUTING.pins[irq] {
+        let mut entry = (IRQ_BASE_VECTOR + irq) as u32 | pin.flags;
+
+        if masked {
+            entry |= REDIRECTION_MASKED;
+        }
+
+        write_io_apic(REDIRECTION_TABLE + 2 * pin.index, entry);
+    }
+}
+
+/// Masks the interrupt line, so that its interrupts are not delivered.
+pub fn mask(irq: usize) {
+    write_redirection_entry(irq, true);
+}
+
+/// Unmasks the interrupt line, so that its interrupts are delivered again.
+pub fn unmask(irq: usize) {
+    write_redirection_entry(irq, false);
+}
+
+/// Signals the end of the current interrupt to the local APIC.
+pub fn end_of_interrupt() {
+    // This is safe, because writing zero to the end of interrupt register has no other effects.
+    unsafe { local_apic_register(END_OF_INTERRUPT_REGISTER).write_volatile(0) };
+}
+
+/// Returns the ID of the local APIC of the current processor.
+pub fn id() -> usize {
+    // This is safe, because reading the ID register has no side effects.
+    (unsafe { local_apic_register(ID_REGISTER).read_volatile() } >> 24) as usize
+}
+
+/// Returns the local APIC IDs of the running processors, which can be the targets of interrupts.
+///
+/// This is only the boot processor.
+pub fn online_processors() -> &'static [usize] {
+    slice::from_ref(&*BOOT_PROCESSOR)
+}
+
+/// Initializes the interrupt controllers.
+///
+/// All interrupt lines are masked until a driver asks for their interrupts.
+pub fn init() {
+    // This is safe, because the legacy interrupt controllers are not used anymore.
+    unsafe {
+        Port::<u8>::new(0x21).write(0xff);
+        Port::<u8>::new(0xa1).write(0xff);
+    }
+
+    // This is safe, because the local APIC is present and only enabled here.
+    unsafe {
+        let spurious_register = local_apic_register(SPURIOUS_INTERRUPT_REGISTER);
+        spurious_register.write_volatile(APIC_SOFTWARE_ENABLE | SPURIOUS_VECTOR as u32);
+    }
+
+    // Pins that no line is connected to stay masked.
+    for index in 0..Arch::IRQ_COUNT as u32 {
+        write_io_apic(REDIRECTION_TABLE + 2 * index, REDIRECTION_MASKED);
+        // Deliver the interrupts to this processor.
+        write_io_apic(REDIRECTION_TABLE + 2 * index + 1, (id() as u32) << 24);
+    }
+
+    for irq in 0..Arch::IRQ_COUNT {
+        mask(irq);
+    }
+}
@@ -1,12 +1,15 @@
 //! This module exposes a type that implements the Architecture trait.
 
-use core::fmt;
-
 use x86_64_crate::instructions::interrupts;
 
-use crate::arch::{
-    x86_64::{get_boot_method, serial, uefi, BootMethod},
-    Architecture,
+use crate::{
+    arch::{
+        x86_64::{apic, context, gdt, interrupts::wait_for_interrupt, memory, shell, time},
+        Architecture, KernelEntry, UserContext,
+    },
+    io_port::IoPortSet,
+    memory::{PhysicalAddress, VirtualAddress},
+    time::Instant,
 };
 
 /// The struct that implements the architecture trait and repressents this architecture.
@@ -14,22 +17,57 @@ use crate::arch::{
 pub struct x86_64;
 
 impl Architecture for x86_64 {
-    fn write_fmt(args: fmt::Arguments) {
-        match get_boot_method() {
-            BootMethod::UEFI => {
-                uefi::write_fmt(args);
-                serial::write_fmt(args);
-            }
-        }
+    const PAGE_SIZE: usize = 0x1000;
+
+    const USER_SPACE_START: VirtualAddress = memory::USER_SPACE_START;
+
+    const USER_SPACE_END: VirtualAddress = memory::USER_SPACE_END;
+
+    // The number of inputs of the I/O APIC found in common chipsets.
+    const IRQ_COUNT: usize = 24;
+
+    // The local APIC IDs have eight bits.
+    const MAXIMUM_CPU_COUNT: usize = 256;
+
+    fn current_cpu() -> usize {
+        apic::id()
+    }
+
+    fn physical_to_virtual(address: PhysicalAddress) -> VirtualAddress {
+        // The kernel uses the identity mapping set up by the UEFI firmware.
+        VirtualAddress::new(address.as_usize())
     }
 
-    fn write_line_break() {
-        match get_boot_method() {
-            BootMethod::UEFI => {
-                uefi::write_fmt(format_args!("\r\n"));
-                serial::write_fmt(format_args!("\n"));
-            }
+    unsafe fn enter_user(context: &mut UserContext) -> KernelEntry {
+        let entry = context::enter_user(context);
+
+        // The shell requested by an interrupt of the serial port runs before the user code continues.
+        if let KernelEntry::Interrupt = entry {
+            shell::run_if_requested();
         }
+
+        entry
+    }
+
+    fn mask_irq(number: usize) {
+        apic::mask(number);
+    }
+
+    fn unmask_irq(number: usize) {
+        apic::unmask(number);
+    }
+
+    fn set_io_ports(ports: Option<&IoPortSet>) {
+        gdt::set_io_ports(ports);
+    }
+
+    fn set_timer(deadline: Option<Instant>) {
+        time::set_deadline(deadline);
+    }
+
+    fn wait_for_interrupt() {
+        wait_for_interrupt();
+        shell::run_if_requested();
     }
 
     fn interrupts_enabled() -> bool {
@@ -0,0 +1,432 @@
+//! Provides the user mode register state and the transitions between kernel mode and user mode.
+//!
+//! Running user code works like a function call: `enter_user` switches to user mode
+//! and returns as soon as the user code executes a system call, causes an exception or is interrupted.
+//! The kernel stack is left untouched in the meantime, so the registers of the user code
+//! are saved directly into the context that was passed to `enter_user`.
+//!
+//! The kernel stack pointer, the current context and the user stack pointer are kept in single global variables,
+//! like the task state segment, so only one processor can run user code.
+//! This matches the kernel, which never starts the application processors.
+//! Supporting more processors requires moving them into per-processor data reached through the `gs` base.
+
+use x86_64_crate::registers::{control::Cr2, model_specific::Msr};
+
+use super::{
+    apic::IRQ_BASE_VECTOR,
+    gdt::{KERNEL_CODE_SELECTOR, USER_DATA_SELECTOR},
+    interrupts::{self, PAGE_FAULT_VECTOR},
+};
+use crate::{
+    arch::{Arch, Architecture, KernelEntry},
+    memory::{Access, VirtualAddress},
+};
+
+/// The model specific register holding the segments used by `syscall` and `sysret`.
+const STAR: u32 = 0xc000_0081;
+
+/// The model specific register holding the entry point of `syscall`.
+const LSTAR: u32 = 0xc000_0082;
+
+/// The model specific register holding the flags that are cleared by `syscall`.
+const SFMASK: u32 = 0xc000_0084;
+
+/// The interrupt flag in the flags register.
+const INTERRUPT_FLAG: u64 = 1 << 9;
+
+/// The flags that user code is allowed to change.
+///
+/// These are the arithmetic flags, the trap flag, the direction flag and the alignment check flag.
+const USER_FLAGS: u64 = 0x0004_0dd5;
+
+/// The flags that are cleared when entering the kernel through `syscall`.
+///
+/// These are the trap flag, the interrupt flag, the direction flag and the alignment check flag.
+const SYSCALL_FLAG_MASK: u64 = 0x0004_0700;
+
+/// The value returned by the entry code if the kernel was entered through a system call.
+const SYSCALL_ENTRY: u64 = u64::max_value();
+
+/// The bit of the page fault error code that is set for write accesses.
+const PAGE_FAULT_WRITE: u64 = 1 << 1;
+
+/// The bit of the page fault error code that is set for instruction fetches.
+const PAGE_FAULT_INSTRUCTION_FETCH: u64 = 1 << 4;
+
+/// The initial value of the x87 FPU control word.
+const INITIAL_FPU_CONTROL_WORD: u16 = 0x037f;
+
+/// The initial value of the SSE control and status register.
+const INITIAL_MXCSR: u32 = 0x1f80;
+
+/// The registers used for the arguments of system calls, in order.
+///
+/// These are the offsets of the registers in the context in units of 8 bytes.
+const ARGUMENT_REGISTERS: [usize; 6] = [5, 4, 3, 9, 7, 8];
+
+/// The memory area used by `fxsave` and `fxrstor`.
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+struct FpuState([u64; 64]);
+
+/// The register state of a thread while it is not running in user mode.
+///
+/// The entry code depends on the exact layout of this type.
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+pub struct UserContext {
+    /// The general purpose registers in the order rax, rbx, rcx, rdx, rsi, rdi, rbp and r8 to r15.
+    registers: [u64; 15],
+    /// The instruction pointer.
+    rip: u64,
+    /// The stack pointer.
+    rsp: u64,
+    /// The flags register.
+    rflags: u64,
+    /// The error code of the last exception.
+    error_code: u64,
+    /// Aligns the FPU state to 16 bytes.
+    padding: u64,
+    /// The state of the FPU and the SSE registers.
+    fpu_state: FpuState,
+}
+
+impl UserContext {
+    /// Creates a context that starts executing at `entry` with the stack pointer `stack`.
+    pub fn new(entry: VirtualAddress, stack: VirtualAddress) -> UserContext {
+        let mut fpu_state = FpuState([0; 64]);
+        fpu_state.0[0] = u64::from(INITIAL_FPU_CONTROL_WORD);
+        fpu_state.0[3] = u64::from(INITIAL_MXCSR);
+
+        UserContext {
+            registers: [0; 15],
+            rip: entry.as_usize() as u64,
+            rsp: stack.as_usize() as u64,
+            rflags: INTERRUPT_FLAG,
+            error_code: 0,
+            padding: 0,
+            fpu_state,
+        }
+    }
+
+    /// Returns the number of the system call that was requested.
+    pub fn syscall_number(&self) -> usize {
+        self.registers[0] as usize
+    }
+
+    /// Returns the system call argument with the given index.
+    pub fn argument(&self, index: usize) -> usize {
+        self.registers[ARGUMENT_REGISTERS[index]] as usize
+    }
+
+    /// Sets the system call argument with the given index.
+    ///
+    /// Arguments are also used to return values from system calls.
+    pub fn set_argument(&mut self, index: usize, value: usize) {
+        self.registers[ARGUMENT_REGISTERS[index]] = value as u64;
+    }
+
+    /// Sets the result of the system call.
+    pub fn set_result(&mut self, value: usize) {
+        self.registers[0] = value as u64;
+    }
+
+    /// Returns the address of the next instruction.
+    pub fn instruction_pointer(&self) -> VirtualAddress {
+        VirtualAddress::new(self.rip as usize)
+    }
+
+    /// Returns the stack pointer.
+    pub fn stack_pointer(&self) -> VirtualAddress {
+        VirtualAddress::new(self.rsp as usize)
+    }
+
+    /// Returns the general purpose registers in the order rax, rbx, rcx, rdx, rsi, rdi, rbp and r8 to r15.
+    pub fn general_purpose_registers(&self) -> [u64; 15] {
+        self.registers
+    }
+
+    /// Returns the flags register.
+    pub fn flags(&self) -> u64 {
+        self.rflags
+    }
+}
+
+extern "sysv64" {
+    /// Switches to user mode using the context and saves the user state into it when the kernel is entered again.
+    ///
+    /// Returns the vector of the exception or interrupt that occurred or `SYSCALL_ENTRY`.
+    fn beetle_enter_user(context: *mut UserContext) -> u64;
+
+    /// The entry point of the `syscall` instruction.
+    fn beetle_syscall_entry();
+}
+
+// The offsets used here must match the layout of `UserContext`.
+// The variables are shared by all processors, see the module documentation.
+global_asm!(
+    "
+    .intel_syntax noprefix
+    .data
+    .balign 8
+beetle_kernel_stack_pointer:
+    .quad 0
+beetle_current_context:
+    .quad 0
+beetle_user_stack_pointer:
+    .quad 0
+
+    .text
+    .global beetle_enter_user
+beetle_enter_user:
+    push rbx
+    push rbp
+    push r12
+    push r13
+    push r14
+    push r15
+    mov [rip + beetle_kernel_stack_pointer], rsp
+    mov [rip + beetle_current_context], rdi
+
+    # Build the frame for iretq using the user data and code segments.
+    push 0x23
+    push qword ptr [rdi + 128]
+    push qword ptr [rdi + 136]
+    push 0x2b
+    push qword ptr [rdi + 120]
+
+    fxrstor [rdi + 160]
+    mov rax, [rdi]
+    mov rbx, [rdi + 8]
+    mov rcx, [rdi + 16]
+    mov rdx, [rdi + 24]
+    mov rsi, [rdi + 32]
+    mov rbp, [rdi + 48]
+    mov r8, [rdi + 56]
+    mov r9, [rdi + 64]
+    mov r10, [rdi + 72]
+    mov r11, [rdi + 80]
+    mov r12, [rdi + 88]
+    mov r13, [rdi + 96]
+    mov r14, [rdi + 104]
+    mov r15, [rdi + 112]
+    mov rdi, [rdi + 40]
+    iretq
+
+    # Returns from beetle_enter_user with the reason for entering the kernel in rax.
+beetle_return_to_kernel:
+    mov rsp, [rip + beetle_kernel_stack_pointer]
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop rbp
+    pop rbx
+    ret
+
+    .global beetle_syscall_entry
+beetle_syscall_entry:
+    # The context is used as the stack here, without ever pushing anything.
+    mov [rip + beetle_user_stack_pointer], rsp
+    mov rsp, [rip + beetle_current_context]
+    mov [rsp], rax
+    mov [rsp + 8], rbx
+    mov [rsp + 16], rcx
+    mov [rsp + 24], rdx
+    mov [rsp + 32], rsi
+    mov [rsp + 40], rdi
+    mov [rsp + 48], rbp
+    mov [rsp + 56], r8
+    mov [rsp + 64], r9
+    mov [rsp + 72], r10
+    mov [rsp + 80], r11
+    mov [rsp + 88], r12
+    mov [rsp + 96], r13
+    mov [rsp + 104], r14
+    mov [rsp + 112], r15
+    mov [rsp + 120], rcx
+    mov [rsp + 136], r11
+    mov rax, [rip + beetle_user_stack_pointer]
+    mov [rsp + 128], rax
+    mov qword ptr [rsp + 144], 0
+    fxsave [rsp + 160]
+    mov rax, -1
+    jmp beetle_return_to_kernel
+
+    # The common part of all exception entry points.
+    # The stack contains the vector, the error code and the frame pushed by the processor.
+    .global beetle_exception_common
+beetle_exception_common:
+    test qword ptr [rsp + 24], 3
+    jz beetle_kernel_mode_exception
+
+    push rax
+    mov rax, [rip + beetle_current_context]
+    mov [rax + 8], rbx
+    mov [rax + 16], rcx
+    mov [rax + 24], rdx
+    mov [rax + 32], rsi
+    mov [rax + 40], rdi
+    mov [rax + 48], rbp
+    mov [rax + 56], r8
+    mov [rax + 64], r9
+    mov [rax + 72], r10
+    mov [rax + 80], r11
+    mov [rax + 88], r12
+    mov [rax + 96], r13
+    mov [rax + 104], r14
+    mov [rax + 112], r15
+    pop rbx
+    mov [rax], rbx
+    mov rbx, [rsp + 8]
+    mov [rax + 144], rbx
+    mov rbx, [rsp + 16]
+    mov [rax + 120], rbx
+    mov rbx, [rsp + 32]
+    mov [rax + 136], rbx
+    mov rbx, [rsp + 40]
+    mov [rax + 128], rbx
+    fxsave [rax + 160]
+    mov rax, [rsp]
+    jmp beetle_return_to_kernel
+
+    # Exceptions and interrupts in kernel mode are handled on the current stack,
+    # unless their interrupt descriptor selects a stack of their own.
+beetle_kernel_mode_exception:
+    cmp qword ptr [rsp], 32
+    jae beetle_kernel_mode_interrupt
+    cmp qword ptr [rsp], 1
+    je beetle_kernel_mode_debug_exception
+    cmp qword ptr [rsp], 3
+    je beetle_kernel_mode_debug_exception
+    mov rdi, rsp
+    and rsp, -16
+    call beetle_kernel_exception
+    ud2
+
+    # Debug exceptions and breakpoints save all registers, so that the debugger can read and change them.
+    # The offsets used here must match the layout of `DebugFrame`.
+    # The stack is aligned to 16 bytes after pushing them.
+beetle_kernel_mode_debug_exception:
+    push r15
+    push r14
+    push r13
+    push r12
+    push r11
+    push r10
+    push r9
+    push r8
+    push rbp
+    push rdi
+    push rsi
+    push rdx
+    push rcx
+    push rbx
+    push rax
+    mov rdi, rsp
+    call beetle_kernel_debug_exception
+    pop rax
+    pop rbx
+    pop rcx
+    pop rdx
+    pop rsi
+    pop rdi
+    pop rbp
+    pop r8
+    pop r9
+    pop r10
+    pop r11
+    pop r12
+    pop r13
+    pop r14
+    pop r15
+    add rsp, 16
+    iretq
+
+    # Only the registers that the called function may change need to be saved.
+    # The stack is aligned to 16 bytes after pushing them.
+beetle_kernel_mode_interrupt:
+    push rax
+    push rcx
+    push rdx
+    push rsi
+    push rdi
+    push r8
+    push r9
+    push r10
+    push r11
+    mov rdi, [rsp + 72]
+    call beetle_kernel_interrupt
+    pop r11
+    pop r10
+    pop r9
+    pop r8
+    pop rdi
+    pop rsi
+    pop rdx
+    pop rcx
+    pop rax
+    add rsp, 16
+    iretq
+    .att_syntax prefix
+"
+);
+
+/// Runs the user code described by the context until it enters the kernel again.
+///
+/// The registers of the user code are saved into the context before this returns.
+///
+/// # Safety
+/// The address space the context belongs to must be active.
+pub unsafe fn enter_user(context: &mut UserContext) -> KernelEntry {
+    // User code cannot disable interrupts or change the privilege level for I/O.
+    context.rflags = context.rflags & USER_FLAGS | INTERRUPT_FLAG;
+
+    let interrupts_enabled = Arch::interrupts_enabled();
+    Arch::disable_interrupts();
+
+    let reason = beetle_enter_user(context);
+
+    Arch::set_interrupts_enabled(interrupts_enabled);
+
+    if reason == SYSCALL_ENTRY {
+        KernelEntry::Syscall
+    } else if reason as usize >= IRQ_BASE_VECTOR {
+        interrupts::handle_interrupt(reason as usize);
+
+        KernelEntry::Interrupt
+    } else if reason as usize == PAGE_FAULT_VECTOR {
+        let error_code = context.error_code;
+
+        let access = if error_code & PAGE_FAULT_INSTRUCTION_FETCH != 0 {
+            Access::Execute
+        } else if error_code & PAGE_FAULT_WRITE != 0 {
+            Access::Write
+        } else {
+            Access::Read
+        };
+
+        KernelEntry::PageFault {
+            address: VirtualAddress::new(Cr2::read().as_u64() as usize),
+            access,
+        }
+    } else {
+        KernelEntry::Exception {
+            vector: reason as usize,
+            error_code: context.error_code as usize,
+        }
+    }
+}
+
+/// Initializes the model specific registers used by the `syscall` instruction.
+pub fn init() {
+    // `sysret` would use the segments directly below the user data segment.
+    let star = u64::from(USER_DATA_SELECTOR - 8) << 48 | u64::from(KERNEL_CODE_SELECTOR) << 32;
+
+    // This is safe, because the entry point is able to handle system calls from user mode.
+    unsafe {
+        Msr::new(STAR).write(star);
+        Msr::new(LSTAR).write(beetle_syscall_entry as usize as u64);
+        Msr::new(SFMASK).write(SYSCALL_FLAG_MASK);
+    }
+}
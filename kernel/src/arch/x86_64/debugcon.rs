@@ -0,0 +1,41 @@
+//! This module writes to the debug console of QEMU and Bochs.
+//!
+//! Every byte written to I/O port 0xe9 is shown on the debug console, if it is enabled with `-debugcon`.
+//! Unlike the serial port, the port needs no initialization and never blocks.
+
+use core::fmt::{self, Write};
+use x86_64_crate::instructions::port::Port;
+
+use crate::{console::Sink, sync::Mutex};
+
+/// The I/O port of the debug console.
+const DEBUG_CONSOLE_PORT: u16 = 0xe9;
+
+/// Provides access to the debug console.
+static DEBUG_CONSOLE: Mutex<DebugConsole> = Mutex::new(DebugConsole);
+
+/// The debug console of QEMU and Bochs.
+struct DebugConsole;
+
+impl Write for DebugConsole {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        let mut port = Port::<u8>::new(DEBUG_CONSOLE_PORT);
+
+        for &byte in string.as_bytes() {
+            // This is safe, because the port is either the debug console or unused.
+            unsafe { port.write(byte) };
+        }
+
+        Ok(())
+    }
+}
+
+/// The console sink of the debug console.
+pub struct DebugConsoleSink;
+
+impl Sink for DebugConsoleSink {
+    fn write_fmt(&self, args: fmt::Arguments) {
+        // Writing to the debug console cannot fail.
+        DEBUG_CONSOLE.lock().write_fmt(args).ok();
+    }
+}
@@ -0,0 +1,1416 @@
+//! Provides a stub for the remote serial protocol of GDB, so that GDB can debug the kernel itself.
+//!
+//! Unlike the stub of QEMU, which debugs the virtual processor, this stub knows about the threads of the kernel.
+//! GDB connects to it on the second serial port, for example with `target remote :1235`
+//! if QEMU was started with `-serial stdio -serial tcp::1235,server,nowait`.
+//!
+//! The kernel stops when GDB sends a packet or an interrupt request, when it hits a breakpoint
+//! and after a single step.
+//! While the kernel is stopped, interrupts are disabled and the stub answers the requests of GDB until it
+//! lets the kernel continue.
+//! A breakpoint that is hit before GDB attached keeps the kernel stopped until it does.
+//!
+//! The kernel itself is the first thread, with the registers at the point where it stopped,
+//! which are the only registers that can be changed.
+//! Every thread of the processes is another thread, with the registers it has in user mode.
+//! Memory is accessed in the active address space.
+//! Software breakpoints replace an instruction with `int3`,
+//! while hardware breakpoints and watchpoints use the debug registers.
+//!
+//! After a panic, the kernel stops for GDB if it is attached and otherwise keeps waiting for it.
+//! Since the stub uses the heap and the list of processes while the kernel is stopped,
+//! the code that holds their locks should not be stopped.
+
+use alloc::{format, sync::Arc};
+use core::{
+    fmt::{self, Write},
+    ptr,
+    sync::atomic::{spin_loop_hint, AtomicBool, Ordering},
+};
+use lazy_static::lazy_static;
+use x86_64_crate::registers::control::{Cr0, Cr0Flags};
+
+use super::{
+    context::UserContext,
+    gdt::{KERNEL_DATA_SELECTOR, USER_CODE_SELECTOR, USER_DATA_SELECTOR},
+    interrupts::{DebugFrame, BREAKPOINT_VECTOR, DEBUG_VECTOR},
+    memory::is_mapped,
+    serial,
+};
+use crate::{
+    memory::{VirtualAddress, PAGE_SIZE},
+    process::{self, Process},
+    sync::Mutex,
+    thread::Thread,
+};
+
+/// The index of the serial port that GDB connects to.
+pub const SERIAL_PORT: usize = 1;
+
+/// The maximum size of a packet in bytes, which is announced to GDB.
+const PACKET_SIZE: usize = 0x400;
+
+/// The features announced to GDB.
+const FEATURES: &str = "PacketSize=400;swbreak+;hwbreak+";
+
+/// The number of software breakpoints that can be set at the same time.
+const SOFTWARE_BREAKPOINT_COUNT: usize = 32;
+
+/// The number of hardware breakpoints, one for each debug address register.
+const HARDWARE_BREAKPOINT_COUNT: usize = 4;
+
+/// The `int3` instruction, which replaces the instruction of a software breakpoint.
+const INT3: u8 = 0xcc;
+
+/// The byte sent by GDB to stop the kernel, for example when `Ctrl-C` is typed.
+const INTERRUPT_REQUEST: u8 = 0x03;
+
+/// The trap flag in the flags register, which causes a debug exception after the next instruction.
+const TRAP_FLAG: u64 = 1 << 8;
+
+/// The resume flag in the flags register, which suppresses instruction breakpoints for the next instruction.
+const RESUME_FLAG: u64 = 1 << 16;
+
+/// The bit in the debug status register that reports a single step.
+const SINGLE_STEP_STATUS: u64 = 1 << 14;
+
+/// The GDB thread identifier of the kernel.
+const KERNEL_THREAD: usize = 1;
+
+/// The offset between the identifiers of threads and their GDB thread identifiers.
+///
+/// GDB doesn't accept zero as a thread identifier and the first one belongs to the kernel.
+const THREAD_OFFSET: usize = 2;
+
+/// The signal reported when the kernel stops.
+const SIGTRAP: u8 = 5;
+
+/// The signal reported when the kernel stops after a panic.
+const SIGABRT: u8 = 6;
+
+/// The number of registers GDB expects for x86_64.
+///
+/// They are rax, rbx, rcx, rdx, rsi, rdi, rbp, rsp, r8 to r15, rip, eflags, cs, ss, ds, es, fs and gs.
+const REGISTER_COUNT: usize = 24;
+
+/// The index of the stack pointer among the registers.
+const STACK_POINTER: usize = 7;
+
+/// The index of the instruction pointer among the registers.
+const INSTRUCTION_POINTER: usize = 16;
+
+/// The index of the flags among the registers.
+///
+/// The flags and the segment registers after them are 32 bits wide, the registers before them 64 bits.
+const FLAGS: usize = 17;
+
+/// The digits used for hexadecimal numbers.
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+lazy_static! {
+    /// The state of the stub.
+    static ref STUB: Mutex<Stub> = Mutex::new(Stub::new());
+}
+
+/// Whether the kernel panicked, which is reported to GDB.
+static PANICKED: AtomicBool = AtomicBool::new(false);
+
+extern "sysv64" {
+    /// Executes `int3`, which stops the kernel for GDB.
+    fn beetle_breakpoint();
+
+    /// Loads the debug address registers and the debug control register.
+    fn beetle_load_debug_registers(addresses: &[u64; HARDWARE_BREAKPOINT_COUNT], control: u64);
+
+    /// Returns the value of the debug status register.
+    fn beetle_read_debug_status() -> u64;
+
+    /// Sets the value of the debug status register.
+    fn beetle_write_debug_status(status: u64);
+}
+
+global_asm!(
+    "
+    .intel_syntax noprefix
+    .text
+    .global beetle_breakpoint
+    .global beetle_load_debug_registers
+    .global beetle_read_debug_status
+    .global beetle_write_debug_status
+beetle_breakpoint:
+    int3
+    ret
+
+beetle_load_debug_registers:
+    mov rax, [rdi]
+    mov dr0, rax
+    mov rax, [rdi + 8]
+    mov dr1, rax
+    mov rax, [rdi + 16]
+    mov dr2, rax
+    mov rax, [rdi + 24]
+    mov dr3, rax
+    mov dr7, rsi
+    ret
+
+beetle_read_debug_status:
+    mov rax, dr6
+    ret
+
+beetle_write_debug_status:
+    mov dr6, rdi
+    ret
+"
+);
+
+/// A connection to GDB.
+///
+/// Connections synchronize themselves, so that they can be used from interrupt handlers.
+pub trait Connection: Sync {
+    /// Returns the next received byte, if there is one.
+    fn receive(&self) -> Option<u8>;
+
+    /// Sends the bytes.
+    fn send(&self, bytes: &[u8]);
+}
+
+/// The connection over the serial port.
+struct SerialConnection;
+
+impl Connection for SerialConnection {
+    fn receive(&self) -> Option<u8> {
+        let mut buffer = [0];
+
+        match serial::read(SERIAL_PORT, &mut buffer) {
+            Ok(1) => Some(buffer[0]),
+            _ => None,
+        }
+    }
+
+    fn send(&self, bytes: &[u8]) {
+        // The port was found when the connection was set up.
+        serial::write(SERIAL_PORT, bytes).ok();
+    }
+}
+
+/// The errors reported to GDB.
+///
+/// They are sent as the `errno` values GDB knows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum StubError {
+    /// The thread doesn't exist.
+    NoSuchThread = 3,
+    /// The memory is not mapped.
+    BadAddress = 14,
+    /// The request is malformed or not possible for the selected thread.
+    InvalidRequest = 22,
+    /// There is no room for another breakpoint or for the reply.
+    NoSpace = 28,
+}
+
+impl From<fmt::Error> for StubError {
+    fn from(_: fmt::Error) -> StubError {
+        StubError::NoSpace
+    }
+}
+
+/// Something received from GDB.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Event {
+    /// A packet with a correct checksum was received.
+    Packet,
+    /// A packet with a wrong checksum was received.
+    Corrupted,
+    /// GDB asked to stop the kernel.
+    InterruptRequest,
+}
+
+/// The states of the receiver.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ReceiveState {
+    /// Bytes outside of packets are ignored, except for interrupt requests.
+    Idle,
+    /// The data of a packet is received.
+    Data,
+    /// The first digit of the checksum is expected.
+    Checksum,
+    /// The second digit of the checksum is expected, after the given first digit.
+    SecondChecksumDigit(u8),
+}
+
+/// Collects the received bytes into packets.
+struct Receiver {
+    /// The state of the receiver.
+    state: ReceiveState,
+    /// The data of the current packet.
+    data: [u8; PACKET_SIZE],
+    /// The length of the current packet, which exceeds the buffer if the packet is too long.
+    length: usize,
+    /// The sum of the data bytes.
+    checksum: u8,
+}
+
+impl Receiver {
+    /// Creates a receiver that waits for a packet.
+    const fn new() -> Receiver {
+        Receiver {
+            state: ReceiveState::Idle,
+            data: [0; PACKET_SIZE],
+            length: 0,
+            checksum: 0,
+        }
+    }
+
+    /// Feeds a received byte to the receiver.
+    ///
+    /// Returns the event, if the byte completed one.
+    fn receive(&mut self, byte: u8) -> Option<Event> {
+        match self.state {
+            ReceiveState::Idle if byte == INTERRUPT_REQUEST => Some(Event::InterruptRequest),
+            ReceiveState::Idle | ReceiveState::Data if byte == b'$' => {
+                self.state = ReceiveState::Data;
+                self.length = 0;
+                self.checksum = 0;
+
+                None
+            }
+            ReceiveState::Idle => None,
+            ReceiveState::Data if byte == b'#' => {
+                self.state = ReceiveState::Checksum;
+
+                None
+            }
+            ReceiveState::Data => {
+                if self.length < PACKET_SIZE {
+                    self.data[self.length] = byte;
+                }
+
+                self.length += 1;
+                self.checksum = self.checksum.wrapping_add(byte);
+
+                None
+            }
+            ReceiveState::Checksum => match hex_digit(byte) {
+                Some(digit) => {
+                    self.state = ReceiveState::SecondChecksumDigit(digit);
+
+                    None
+                }
+                None => {
+                    self.state = ReceiveState::Idle;
+
+                    Some(Event::Corrupted)
+                }
+            },
+            ReceiveState::SecondChecksumDigit(first) => {
+                self.state = ReceiveState::Idle;
+
+                match hex_digit(byte) {
+                    Some(second)
+                        if first << 4 | second == self.checksum && self.length <= PACKET_SIZE =>
+                    {
+                        Some(Event::Packet)
+                    }
+                    _ => Some(Event::Corrupted),
+                }
+            }
+        }
+    }
+
+    /// Returns the data of the last packet.
+    fn packet(&self) -> &[u8] {
+        &self.data[..self.length.min(PACKET_SIZE)]
+    }
+}
+
+/// A reply to GDB that is being built.
+struct Reply {
+    /// The data of the reply.
+    data: [u8; PACKET_SIZE],
+    /// The length of the reply.
+    length: usize,
+}
+
+impl Reply {
+    /// Creates an empty reply, which tells GDB that a request is not supported.
+    fn new() -> Reply {
+        Reply {
+            data: [0; PACKET_SIZE],
+            length: 0,
+        }
+    }
+
+    /// Returns the data of the reply.
+    fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.length]
+    }
+
+    /// Appends the bytes as pairs of hexadecimal digits.
+    fn push_hex(&mut self, bytes: &[u8]) -> fmt::Result {
+        for &byte in bytes {
+            write!(self, "{:02x}", byte)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Write for Reply {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        let end = self.length + string.len();
+
+        if end > PACKET_SIZE {
+            return Err(fmt::Error);
+        }
+
+        self.data[self.length..end].copy_from_slice(string.as_bytes());
+        self.length = end;
+
+        Ok(())
+    }
+}
+
+/// A software breakpoint, which replaced an instruction byte with `int3`.
+#[derive(Clone, Copy, Debug)]
+struct SoftwareBreakpoint {
+    /// The address of the breakpoint.
+    address: usize,
+    /// The byte that was replaced.
+    original: u8,
+}
+
+/// The kinds of hardware breakpoints.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum HardwareBreakpointKind {
+    /// Breaks when the instruction is executed.
+    Execute,
+    /// Breaks when the memory is written.
+    Write,
+    /// Breaks when the memory is read.
+    Read,
+    /// Breaks when the memory is read or written.
+    Access,
+}
+
+impl HardwareBreakpointKind {
+    /// Returns the kind with the given number in the breakpoint packets.
+    fn from_packet(number: usize) -> Option<HardwareBreakpointKind> {
+        match number {
+            1 => Some(HardwareBreakpointKind::Execute),
+            2 => Some(HardwareBreakpointKind::Write),
+            3 => Some(HardwareBreakpointKind::Read),
+            4 => Some(HardwareBreakpointKind::Access),
+            _ => None,
+        }
+    }
+
+    /// Returns the condition bits in the debug control register.
+    ///
+    /// The processor cannot break on reads alone, so read watchpoints also break on writes.
+    fn condition(self) -> u64 {
+        match self {
+            HardwareBreakpointKind::Execute => 0b00,
+            HardwareBreakpointKind::Write => 0b01,
+            HardwareBreakpointKind::Read | HardwareBreakpointKind::Access => 0b11,
+        }
+    }
+
+    /// Returns the name of the stop reason in stop replies.
+    fn stop_reason(self) -> &'static str {
+        match self {
+            HardwareBreakpointKind::Execute => "hwbreak",
+            HardwareBreakpointKind::Write => "watch",
+            HardwareBreakpointKind::Read => "rwatch",
+            HardwareBreakpointKind::Access => "awatch",
+        }
+    }
+}
+
+/// A breakpoint or watchpoint in a debug address register.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct HardwareBreakpoint {
+    /// The address of the breakpoint.
+    address: usize,
+    /// The number of watched bytes, which is one for instruction breakpoints.
+    length: usize,
+    /// The accesses that trigger the breakpoint.
+    kind: HardwareBreakpointKind,
+}
+
+impl HardwareBreakpoint {
+    /// Returns the length bits in the debug control register.
+    fn length_bits(&self) -> u64 {
+        match self.length {
+            2 => 0b01,
+            8 => 0b10,
+            4 => 0b11,
+            _ => 0b00,
+        }
+    }
+}
+
+/// The reasons for stopping the kernel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum StopReason {
+    /// The kernel was interrupted, single stepped or hit a breakpoint that doesn't belong to GDB.
+    Trap,
+    /// A software breakpoint of GDB was hit.
+    SoftwareBreakpoint,
+    /// A hardware breakpoint or watchpoint was hit.
+    HardwareBreakpoint(HardwareBreakpoint),
+}
+
+/// What happens after a packet was handled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Action {
+    /// The reply is sent and the kernel stays stopped.
+    Reply,
+    /// The kernel continues, for a single instruction if `step` is set.
+    Resume {
+        /// Whether only a single instruction is executed.
+        step: bool,
+    },
+    /// The breakpoints are removed and the kernel continues, after replying if `reply` is set.
+    Detach {
+        /// Whether GDB expects a reply.
+        reply: bool,
+    },
+}
+
+/// The state of the stub.
+struct Stub {
+    /// The connection to GDB, which enables the stub.
+    connection: Option<&'static dyn Connection>,
+    /// Collects the received bytes into packets.
+    receiver: Receiver,
+    /// The event that stopped the kernel, which was received while it was running.
+    pending: Option<Event>,
+    /// Whether GDB is attached, which is assumed from its first packet until it detaches.
+    attached: bool,
+    /// The GDB thread whose registers are accessed.
+    selected_thread: usize,
+    /// The software breakpoints.
+    software_breakpoints: [Option<SoftwareBreakpoint>; SOFTWARE_BREAKPOINT_COUNT],
+    /// The hardware breakpoints, at the index of their debug address register.
+    hardware_breakpoints: [Option<HardwareBreakpoint>; HARDWARE_BREAKPOINT_COUNT],
+}
+
+impl Stub {
+    /// Creates a disabled stub.
+    fn new() -> Stub {
+        Stub {
+            connection: None,
+            receiver: Receiver::new(),
+            pending: None,
+            attached: false,
+            selected_thread: KERNEL_THREAD,
+            software_breakpoints: [None; SOFTWARE_BREAKPOINT_COUNT],
+            hardware_breakpoints: [None; HARDWARE_BREAKPOINT_COUNT],
+        }
+    }
+
+    /// Feeds a received byte to the receiver and acknowledges the packets.
+    ///
+    /// Returns the event, unless the byte completed nothing or a corrupted packet.
+    fn receive_byte(&mut self, connection: &dyn Connection, byte: u8) -> Option<Event> {
+        match self.receiver.receive(byte)? {
+            Event::Packet => {
+                connection.send(b"+");
+                self.attached = true;
+
+                Some(Event::Packet)
+            }
+            Event::Corrupted => {
+                connection.send(b"-");
+
+                None
+            }
+            Event::InterruptRequest => Some(Event::InterruptRequest),
+        }
+    }
+
+    /// Receives the available bytes until an event should stop the kernel.
+    ///
+    /// Returns true if the kernel should stop.
+    fn poll_connection(&mut self) -> bool {
+        let connection = match self.connection {
+            Some(connection) => connection,
+            None => return false,
+        };
+
+        while self.pending.is_none() {
+            match connection.receive() {
+                Some(byte) => self.pending = self.receive_byte(connection, byte),
+                None => break,
+            }
+        }
+
+        self.pending.is_some()
+    }
+
+    /// Waits for the next event.
+    fn wait_for_event(&mut self, connection: &dyn Connection) -> Event {
+        if let Some(event) = self.pending.take() {
+            return event;
+        }
+
+        loop {
+            match connection.receive() {
+                Some(byte) => {
+                    if let Some(event) = self.receive_byte(connection, byte) {
+                        return event;
+                    }
+                }
+                None => spin_loop_hint(),
+            }
+        }
+    }
+
+    /// Sends a packet with the data and waits until GDB acknowledges it.
+    fn send_packet(&mut self, connection: &dyn Connection, data: &[u8]) {
+        let checksum = data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        let trailer = [
+            b'#',
+            HEX_DIGITS[usize::from(checksum >> 4)],
+            HEX_DIGITS[usize::from(checksum & 0xf)],
+        ];
+
+        loop {
+            connection.send(b"$");
+            connection.send(data);
+            connection.send(&trailer);
+
+            loop {
+                match connection.receive() {
+                    Some(b'+') => return,
+                    Some(b'-') => break,
+                    // GDB may send the next request without acknowledging the reply.
+                    Some(byte) => {
+                        self.pending = self.receive_byte(connection, byte);
+
+                        return;
+                    }
+                    None => spin_loop_hint(),
+                }
+            }
+        }
+    }
+
+    /// Sends a stop reply for the reason.
+    fn send_stop_reply(&mut self, connection: &dyn Connection, reason: StopReason) {
+        let mut reply = Reply::new();
+
+        // A stop reply always fits into a packet.
+        write_stop_reply(&mut reply, reason).ok();
+
+        self.send_packet(connection, reply.as_bytes());
+    }
+
+    /// Determines why the kernel stopped.
+    ///
+    /// The instruction pointer is moved back to software breakpoints, so that their instruction is executed
+    /// once GDB removed them.
+    fn stop_reason(&self, frame: &mut DebugFrame) -> StopReason {
+        match frame.vector as usize {
+            BREAKPOINT_VECTOR => {
+                let address = (frame.instruction_pointer as usize).wrapping_sub(1);
+
+                if self.software_breakpoint(address).is_some() {
+                    frame.instruction_pointer = address as u64;
+
+                    StopReason::SoftwareBreakpoint
+                } else {
+                    StopReason::Trap
+                }
+            }
+            DEBUG_VECTOR => {
+                // This is safe, because the debug status is only used by the stub.
+                let status = unsafe {
+                    let status = beetle_read_debug_status();
+                    beetle_write_debug_status(0);
+
+                    status
+                };
+
+                frame.flags &= !TRAP_FLAG;
+
+                if status & SINGLE_STEP_STATUS != 0 {
+                    return StopReason::Trap;
+                }
+
+                self.hardware_breakpoints
+                    .iter()
+                    .enumerate()
+                    .filter(|&(index, _)| status & 1 << index != 0)
+                    .filter_map(|(_, breakpoint)| *breakpoint)
+                    .next()
+                    .map_or(StopReason::Trap, StopReason::HardwareBreakpoint)
+            }
+            _ => StopReason::Trap,
+        }
+    }
+
+    /// Removes all breakpoints and forgets that GDB was attached.
+    fn detach(&mut self) {
+        for breakpoint in self.software_breakpoints.iter_mut() {
+            if let Some(breakpoint) = breakpoint.take() {
+                write_byte(breakpoint.address, breakpoint.original);
+            }
+        }
+
+        self.hardware_breakpoints = [None; HARDWARE_BREAKPOINT_COUNT];
+        self.load_debug_registers();
+        self.attached = false;
+    }
+
+    /// Handles the packet, writing the reply if the kernel stays stopped.
+    fn handle_packet(
+        &mut self,
+        packet: &[u8],
+        frame: &mut DebugFrame,
+        reason: StopReason,
+        reply: &mut Reply,
+    ) -> Result<Action, StubError> {
+        let (&command, arguments) = match packet.split_first() {
+            Some(split) => split,
+            None => return Ok(Action::Reply),
+        };
+
+        match command {
+            b'?' => write_stop_reply(reply, reason)?,
+            b'g' => {
+                let registers = self.registers(frame)?;
+
+                for (index, value) in registers.iter().enumerate() {
+                    reply.push_hex(&value.to_le_bytes()[..register_size(index)])?;
+                }
+            }
+            b'G' => {
+                self.check_kernel_selected()?;
+
+                let mut registers = kernel_registers(frame);
+                let mut rest = arguments;
+
+                for (index, register) in registers.iter_mut().enumerate() {
+                    let digits = register_size(index) * 2;
+
+                    if rest.len() < digits {
+                        return Err(StubError::InvalidRequest);
+                    }
+
+                    *register =
+                        parse_little_endian(&rest[..digits]).ok_or(StubError::InvalidRequest)?;
+                    rest = &rest[digits..];
+                }
+
+                set_kernel_registers(frame, &registers);
+                reply.write_str("OK")?;
+            }
+            b'P' => {
+                self.check_kernel_selected()?;
+
+                let (index, value) = split(arguments, b'=').ok_or(StubError::InvalidRequest)?;
+                let index = parse_hex(index)
+                    .filter(|&index| index < REGISTER_COUNT)
+                    .ok_or(StubError::InvalidRequest)?;
+
+                let mut registers = kernel_registers(frame);
+                registers[index] = parse_little_endian(value).ok_or(StubError::InvalidRequest)?;
+
+                set_kernel_registers(frame, &registers);
+                reply.write_str("OK")?;
+            }
+            b'm' => {
+                let (address, length) = parse_range(arguments)?;
+
+                self.read_memory(address, length, reply)?;
+            }
+            b'M' => {
+                let (range, data) = split(arguments, b':').ok_or(StubError::InvalidRequest)?;
+                let (address, length) = parse_range(range)?;
+
+                self.write_memory(address, length, data)?;
+                reply.write_str("OK")?;
+            }
+            b'c' | b's' => {
+                if !arguments.is_empty() {
+                    frame.instruction_pointer =
+                        parse_hex(arguments).ok_or(StubError::InvalidRequest)? as u64;
+                }
+
+                return Ok(Action::Resume {
+                    step: command == b's',
+                });
+            }
+            b'D' => return Ok(Action::Detach { reply: true }),
+            b'k' => return Ok(Action::Detach { reply: false }),
+            b'H' => {
+                match arguments.split_first() {
+                    Some((b'g', thread)) => {
+                        let thread = parse_thread(thread)?;
+
+                        if !thread_exists(thread) {
+                            return Err(StubError::NoSuchThread);
+                        }
+
+                        self.selected_thread = thread;
+                    }
+                    // All threads continue together, so the thread is ignored.
+                    Some((b'c', _)) => (),
+                    _ => return Err(StubError::InvalidRequest),
+                }
+
+                reply.write_str("OK")?;
+            }
+            b'T' => {
+                if !thread_exists(parse_thread(arguments)?) {
+                    return Err(StubError::NoSuchThread);
+                }
+
+                reply.write_str("OK")?;
+            }
+            b'Z' | b'z' => {
+                let (kind, rest) = split(arguments, b',').ok_or(StubError::InvalidRequest)?;
+                let (address, length) = parse_range(rest)?;
+                let kind = parse_hex(kind).ok_or(StubError::InvalidRequest)?;
+
+                if kind == 0 {
+                    if command == b'Z' {
+                        self.insert_software_breakpoint(address)?;
+                    } else {
+                        self.remove_software_breakpoint(address)?;
+                    }
+                } else if let Some(kind) = HardwareBreakpointKind::from_packet(kind) {
+                    if command == b'Z' {
+                        self.insert_hardware_breakpoint(address, length, kind)?;
+                    } else {
+                        self.remove_hardware_breakpoint(address, length, kind)?;
+                    }
+                } else {
+                    return Ok(Action::Reply);
+                }
+
+                reply.write_str("OK")?;
+            }
+            b'q' => handle_query(arguments, reply)?,
+            _ => (),
+        }
+
+        Ok(Action::Reply)
+    }
+
+    /// Fails unless the kernel is the selected thread, whose registers can be changed.
+    fn check_kernel_selected(&self) -> Result<(), StubError> {
+        if self.selected_thread == KERNEL_THREAD {
+            Ok(())
+        } else {
+            Err(StubError::InvalidRequest)
+        }
+    }
+
+    /// Returns the registers of the selected thread.
+    fn registers(&self, frame: &DebugFrame) -> Result<[u64; REGISTER_COUNT], StubError> {
+        if self.selected_thread == KERNEL_THREAD {
+            return Ok(kernel_registers(frame));
+        }
+
+        let (_, thread) = find_thread(self.selected_thread).ok_or(StubError::NoSuchThread)?;
+
+        Ok(user_registers(&thread.context()))
+    }
+
+    /// Returns the software breakpoint at the address, if there is one.
+    fn software_breakpoint(&self, address: usize) -> Option<SoftwareBreakpoint> {
+        self.software_breakpoints
+            .iter()
+            .filter_map(|&breakpoint| breakpoint)
+            .find(|breakpoint| breakpoint.address == address)
+    }
+
+    /// Writes the memory as hexadecimal digits to the reply.
+    ///
+    /// Software breakpoints are hidden by showing the bytes they replaced.
+    fn read_memory(
+        &self,
+        address: usize,
+        length: usize,
+        reply: &mut Reply,
+    ) -> Result<(), StubError> {
+        check_mapped(address, length)?;
+
+        for address in address..address + length {
+            let byte = match self.software_breakpoint(address) {
+                Some(breakpoint) => breakpoint.original,
+                // This is safe, because the memory is mapped.
+                None => unsafe { ptr::read_volatile(address as *const u8) },
+            };
+
+            reply.push_hex(&[byte])?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the bytes given as hexadecimal digits to the memory.
+    ///
+    /// Bytes replaced by software breakpoints are written once the breakpoints are removed.
+    fn write_memory(
+        &mut self,
+        address: usize,
+        length: usize,
+        data: &[u8],
+    ) -> Result<(), StubError> {
+        let mut bytes = [0; PACKET_SIZE / 2];
+
+        if data.len() != length * 2 || length > bytes.len() {
+            return Err(StubError::InvalidRequest);
+        }
+
+        for (byte, digits) in bytes.iter_mut().zip(data.chunks(2)) {
+            *byte = parse_hex(digits).ok_or(StubError::InvalidRequest)? as u8;
+        }
+
+        check_mapped(address, length)?;
+
+        for (address, &byte) in (address..).zip(&bytes[..length]) {
+            let breakpoint = self
+                .software_breakpoints
+                .iter_mut()
+                .filter_map(|breakpoint| breakpoint.as_mut())
+                .find(|breakpoint| breakpoint.address == address);
+
+            match breakpoint {
+                Some(breakpoint) => breakpoint.original = byte,
+                None => write_byte(address, byte),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Inserts a software breakpoint at the address.
+    fn insert_software_breakpoint(&mut self, address: usize) -> Result<(), StubError> {
+        if self.software_breakpoint(address).is_some() {
+            return Ok(());
+        }
+
+        check_mapped(address, 1)?;
+
+        let slot = self
+            .software_breakpoints
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(StubError::NoSpace)?;
+
+        // This is safe, because the memory is mapped.
+        let original = unsafe { ptr::read_volatile(address as *const u8) };
+
+        *slot = Some(SoftwareBreakpoint { address, original });
+        write_byte(address, INT3);
+
+        Ok(())
+    }
+
+    /// Removes the software breakpoint at the address.
+    fn remove_software_breakpoint(&mut self, address: usize) -> Result<(), StubError> {
+        let breakpoint = self
+            .software_breakpoints
+            .iter_mut()
+            .find(|slot| slot.map_or(false, |breakpoint| breakpoint.address == address))
+            .and_then(|slot| slot.take())
+            .ok_or(StubError::InvalidRequest)?;
+
+        write_byte(breakpoint.address, breakpoint.original);
+
+        Ok(())
+    }
+
+    /// Inserts a hardware breakpoint or watchpoint.
+    ///
+    /// Watchpoints must cover one, two, four or eight bytes and be aligned to their length.
+    fn insert_hardware_breakpoint(
+        &mut self,
+        address: usize,
+        length: usize,
+        kind: HardwareBreakpointKind,
+    ) -> Result<(), StubError> {
+        let length = if kind == HardwareBreakpointKind::Execute {
+            1
+        } else {
+            length
+        };
+
+        match length {
+            1 | 2 | 4 | 8 if address % length == 0 => (),
+            _ => return Err(StubError::InvalidRequest),
+        }
+
+        let breakpoint = HardwareBreakpoint {
+            address,
+            length,
+            kind,
+        };
+
+        if self.hardware_breakpoints.contains(&Some(breakpoint)) {
+            return Ok(());
+        }
+
+        let slot = self
+            .hardware_breakpoints
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(StubError::NoSpace)?;
+
+        *slot = Some(breakpoint);
+        self.load_debug_registers();
+
+        Ok(())
+    }
+
+    /// Removes a hardware breakpoint or watchpoint.
+    fn remove_hardware_breakpoint(
+        &mut self,
+        address: usize,
+        length: usize,
+        kind: HardwareBreakpointKind,
+    ) -> Result<(), StubError> {
+        let slot = self
+            .hardware_breakpoints
+            .iter_mut()
+            .find(|slot| {
+                slot.map_or(false, |breakpoint| {
+                    breakpoint.address == address
+                        && breakpoint.kind == kind
+                        && (kind == HardwareBreakpointKind::Execute || breakpoint.length == length)
+                })
+            })
+            .ok_or(StubError::InvalidRequest)?;
+
+        *slot = None;
+        self.load_debug_registers();
+
+        Ok(())
+    }
+
+    /// Loads the hardware breakpoints into the debug registers.
+    fn load_debug_registers(&self) {
+        let mut addresses = [0; HARDWARE_BREAKPOINT_COUNT];
+        let mut control = 0;
+
+        for (index, breakpoint) in self.hardware_breakpoints.iter().enumerate() {
+            if let Some(breakpoint) = breakpoint {
+                addresses[index] = breakpoint.address as u64;
+                control |= 1 << (2 * index)
+                    | breakpoint.kind.condition() << (16 + 4 * index)
+                    | breakpoint.length_bits() << (18 + 4 * index);
+            }
+        }
+
+        // This is safe, because the resulting debug exceptions are handled by the stub.
+        unsafe { beetle_load_debug_registers(&addresses, control) };
+    }
+}
+
+/// Answers a general query.
+fn handle_query(query: &[u8], reply: &mut Reply) -> Result<(), StubError> {
+    if query.starts_with(b"Supported") {
+        reply.write_str(FEATURES)?;
+    } else if query.starts_with(b"Attached") {
+        // GDB should detach instead of killing the kernel when it quits.
+        reply.write_str("1")?;
+    } else if query == b"C" {
+        write!(reply, "QC{:x}", KERNEL_THREAD)?;
+    } else if query == b"fThreadInfo" {
+        write!(reply, "m{:x}", KERNEL_THREAD)?;
+
+        for process in process::processes() {
+            for thread in process.threads() {
+                write!(reply, ",{:x}", thread.id().as_usize() + THREAD_OFFSET)?;
+            }
+        }
+    } else if query == b"sThreadInfo" {
+        reply.write_str("l")?;
+    } else if query.starts_with(b"ThreadExtraInfo,") {
+        let thread = parse_thread(&query[b"ThreadExtraInfo,".len()..])?;
+
+        if thread == KERNEL_THREAD {
+            reply.push_hex(b"kernel")?;
+        } else {
+            let (process, thread) = find_thread(thread).ok_or(StubError::NoSuchThread)?;
+            let description = format!(
+                "process {}, {}",
+                process.id().as_usize(),
+                thread.state().description()
+            );
+
+            reply.push_hex(description.as_bytes())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the stop reply for the reason.
+///
+/// The kernel is always reported as the thread that stopped.
+fn write_stop_reply(reply: &mut Reply, reason: StopReason) -> fmt::Result {
+    let signal = if PANICKED.load(Ordering::SeqCst) {
+        SIGABRT
+    } else {
+        SIGTRAP
+    };
+
+    write!(reply, "T{:02x}thread:{:x};", signal, KERNEL_THREAD)?;
+
+    match reason {
+        StopReason::Trap => Ok(()),
+        StopReason::SoftwareBreakpoint => reply.write_str("swbreak:;"),
+        StopReason::HardwareBreakpoint(HardwareBreakpoint {
+            kind: HardwareBreakpointKind::Execute,
+            ..
+        }) => reply.write_str("hwbreak:;"),
+        StopReason::HardwareBreakpoint(breakpoint) => write!(
+            reply,
+            "{}:{:x};",
+            breakpoint.kind.stop_reason(),
+            breakpoint.address
+        ),
+    }
+}
+
+/// Lets the kernel continue after it stopped.
+fn resume(frame: &mut DebugFrame, step: bool) {
+    if step {
+        frame.flags |= TRAP_FLAG;
+    } else {
+        frame.flags &= !TRAP_FLAG;
+    }
+
+    frame.flags |= RESUME_FLAG;
+}
+
+/// Returns the size of the register with the given index in bytes.
+fn register_size(index: usize) -> usize {
+    if index < FLAGS {
+        8
+    } else {
+        4
+    }
+}
+
+/// Returns the registers of the kernel at the point where it stopped.
+fn kernel_registers(frame: &DebugFrame) -> [u64; REGISTER_COUNT] {
+    let mut registers = [0; REGISTER_COUNT];
+    let data_segment = u64::from(KERNEL_DATA_SELECTOR);
+
+    registers[..STACK_POINTER].copy_from_slice(&frame.registers[..STACK_POINTER]);
+    registers[STACK_POINTER] = frame.stack_pointer;
+    registers[STACK_POINTER + 1..INSTRUCTION_POINTER]
+        .copy_from_slice(&frame.registers[STACK_POINTER..]);
+    registers[INSTRUCTION_POINTER] = frame.instruction_pointer;
+    registers[FLAGS] = frame.flags;
+    registers[FLAGS + 1..].copy_from_slice(&[
+        frame.code_segment,
+        frame.stack_segment,
+        data_segment,
+        data_segment,
+        0,
+        0,
+    ]);
+
+    registers
+}
+
+/// Changes the registers of the kernel, except for the segment registers.
+fn set_kernel_registers(frame: &mut DebugFrame, registers: &[u64; REGISTER_COUNT]) {
+    frame.registers[..STACK_POINTER].copy_from_slice(&registers[..STACK_POINTER]);
+    frame.stack_pointer = registers[STACK_POINTER];
+    frame.registers[STACK_POINTER..]
+        .copy_from_slice(&registers[STACK_POINTER + 1..INSTRUCTION_POINTER]);
+    frame.instruction_pointer = registers[INSTRUCTION_POINTER];
+    frame.flags = registers[FLAGS];
+}
+
+/// Returns the registers a thread has in user mode.
+fn user_registers(context: &UserContext) -> [u64; REGISTER_COUNT] {
+    let mut registers = [0; REGISTER_COUNT];
+    let general_purpose_registers = context.general_purpose_registers();
+    let data_segment = u64::from(USER_DATA_SELECTOR);
+
+    registers[..STACK_POINTER].copy_from_slice(&general_purpose_registers[..STACK_POINTER]);
+    registers[STACK_POINTER] = context.stack_pointer().as_usize() as u64;
+    registers[STACK_POINTER + 1..INSTRUCTION_POINTER]
+        .copy_from_slice(&general_purpose_registers[STACK_POINTER..]);
+    registers[INSTRUCTION_POINTER] = context.instruction_pointer().as_usize() as u64;
+    registers[FLAGS] = context.flags();
+    registers[FLAGS + 1..].copy_from_slice(&[
+        u64::from(USER_CODE_SELECTOR),
+        data_segment,
+        data_segment,
+        data_segment,
+        0,
+        0,
+    ]);
+
+    registers
+}
+
+/// Returns the thread with the GDB thread identifier and its process.
+fn find_thread(id: usize) -> Option<(Arc<Process>, Arc<Thread>)> {
+    if id < THREAD_OFFSET {
+        return None;
+    }
+
+    process::processes().into_iter().find_map(|process| {
+        process
+            .threads()
+            .into_iter()
+            .find(|thread| thread.id().as_usize() == id - THREAD_OFFSET)
+            .map(|thread| (process, thread))
+    })
+}
+
+/// Checks if the GDB thread identifier belongs to the kernel or an existing thread.
+fn thread_exists(id: usize) -> bool {
+    id == KERNEL_THREAD || find_thread(id).is_some()
+}
+
+/// Parses a GDB thread identifier.
+///
+/// The identifiers for any thread and all threads stand for the kernel.
+fn parse_thread(digits: &[u8]) -> Result<usize, StubError> {
+    match digits {
+        b"0" | b"-1" => Ok(KERNEL_THREAD),
+        _ => parse_hex(digits).ok_or(StubError::InvalidRequest),
+    }
+}
+
+/// Parses an address and a length separated by a comma.
+fn parse_range(arguments: &[u8]) -> Result<(usize, usize), StubError> {
+    let (address, length) = split(arguments, b',').ok_or(StubError::InvalidRequest)?;
+
+    match (parse_hex(address), parse_hex(length)) {
+        (Some(address), Some(length)) => Ok((address, length)),
+        _ => Err(StubError::InvalidRequest),
+    }
+}
+
+/// Splits the bytes at the first occurrence of the separator.
+fn split(bytes: &[u8], separator: u8) -> Option<(&[u8], &[u8])> {
+    let position = bytes.iter().position(|&byte| byte == separator)?;
+
+    Some((&bytes[..position], &bytes[position + 1..]))
+}
+
+/// Returns the value of a hexadecimal digit.
+fn hex_digit(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|value| value as u8)
+}
+
+/// Parses a hexadecimal number with the most significant digit first.
+fn parse_hex(digits: &[u8]) -> Option<usize> {
+    if digits.is_empty() {
+        return None;
+    }
+
+    digits.iter().try_fold(0usize, |value, &digit| {
+        value
+            .checked_mul(16)?
+            .checked_add(usize::from(hex_digit(digit)?))
+    })
+}
+
+/// Parses a value given as bytes in hexadecimal, with the least significant byte first.
+fn parse_little_endian(digits: &[u8]) -> Option<u64> {
+    if digits.is_empty() || digits.len() % 2 != 0 || digits.len() > 16 {
+        return None;
+    }
+
+    digits.chunks(2).rev().try_fold(0u64, |value, pair| {
+        Some(value << 8 | parse_hex(pair)? as u64)
+    })
+}
+
+/// Checks that the memory range is mapped.
+fn check_mapped(address: usize, length: usize) -> Result<(), StubError> {
+    let end = address.checked_add(length).ok_or(StubError::BadAddress)?;
+
+    if length == 0 {
+        return Ok(());
+    }
+
+    if !is_canonical(address) || !is_canonical(end - 1) {
+        return Err(StubError::BadAddress);
+    }
+
+    for page in (address & !(PAGE_SIZE - 1)..end).step_by(PAGE_SIZE) {
+        if !is_mapped(VirtualAddress::new(page)) {
+            return Err(StubError::BadAddress);
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks if the address is canonical, so that accessing it cannot cause a general protection fault.
+fn is_canonical(address: usize) -> bool {
+    let upper_bits = address >> 47;
+
+    upper_bits == 0 || upper_bits == (1 << 17) - 1
+}
+
+/// Writes the byte to mapped memory, even if the page is read-only.
+fn write_byte(address: usize, byte: u8) {
+    // This is safe, because GDB asked for the write and the memory is mapped.
+    // Write protection is restored right after the write, while interrupts are still disabled.
+    unsafe {
+        let write_protect = Cr0::read().contains(Cr0Flags::WRITE_PROTECT);
+
+        Cr0::update(|flags| flags.remove(Cr0Flags::WRITE_PROTECT));
+        ptr::write_volatile(address as *mut u8, byte);
+
+        if write_protect {
+            Cr0::update(|flags| flags.insert(Cr0Flags::WRITE_PROTECT));
+        }
+    }
+}
+
+/// Stops the kernel for GDB after a debug exception or a breakpoint in kernel mode.
+///
+/// Returns false if the stub is disabled, in which case the exception is a kernel bug.
+pub(super) fn handle_exception(frame: &mut DebugFrame) -> bool {
+    let mut stub = STUB.lock();
+    let connection = match stub.connection {
+        Some(connection) => connection,
+        None => return false,
+    };
+
+    let reason = stub.stop_reason(frame);
+    stub.selected_thread = KERNEL_THREAD;
+
+    // GDB is only told about the stop if it is attached or asked for it.
+    // A packet that stopped the kernel is answered first, since it may come from a new connection.
+    match stub.pending {
+        Some(Event::Packet) => (),
+        Some(_) => {
+            stub.pending = None;
+            stub.send_stop_reply(connection, reason);
+        }
+        None if stub.attached => stub.send_stop_reply(connection, reason),
+        None => (),
+    }
+
+    loop {
+        match stub.wait_for_event(connection) {
+            Event::Packet => {
+                let mut packet = [0; PACKET_SIZE];
+                let length = stub.receiver.packet().len();
+                packet[..length].copy_from_slice(stub.receiver.packet());
+
+                let mut reply = Reply::new();
+
+                match stub.handle_packet(&packet[..length], frame, reason, &mut reply) {
+                    Ok(Action::Reply) => stub.send_packet(connection, reply.as_bytes()),
+                    Ok(Action::Resume { step }) => {
+                        resume(frame, step);
+                        break;
+                    }
+                    Ok(Action::Detach { reply }) => {
+                        if reply {
+                            stub.send_packet(connection, b"OK");
+                        }
+
+                        stub.detach();
+                        resume(frame, false);
+                        break;
+                    }
+                    Err(error) => {
+                        let mut reply = Reply::new();
+
+                        // An error reply always fits into a packet.
+                        write!(reply, "E{:02x}", error as u8).ok();
+
+                        stub.send_packet(connection, reply.as_bytes());
+                    }
+                }
+            }
+            Event::InterruptRequest => stub.send_stop_reply(connection, reason),
+            // Corrupted packets were already rejected.
+            Event::Corrupted => (),
+        }
+    }
+
+    true
+}
+
+/// Enables the stub on the second serial port, if it exists.
+pub fn init() {
+    if serial::is_present(SERIAL_PORT) {
+        set_connection(Some(&SerialConnection));
+
+        log::info!("GDB can connect to the kernel on the second serial port.");
+    }
+}
+
+/// Sets the connection to GDB, disabling the stub if there is none.
+///
+/// The breakpoints of the previous connection are removed.
+pub fn set_connection(connection: Option<&'static dyn Connection>) {
+    let mut stub = STUB.lock();
+
+    stub.detach();
+    stub.receiver = Receiver::new();
+    stub.pending = None;
+    stub.connection = connection;
+}
+
+/// Checks if the stub has a connection to GDB.
+pub fn is_enabled() -> bool {
+    STUB.lock().connection.is_some()
+}
+
+/// Receives the bytes sent by GDB and stops the kernel if a packet or an interrupt request arrived.
+///
+/// This is called by the interrupt handler of the serial port and wherever the kernel waits
+/// with interrupts disabled.
+pub fn poll() {
+    let stop = STUB
+        .try_lock()
+        .map_or(false, |mut stub| stub.poll_connection());
+
+    if stop {
+        breakpoint();
+    }
+}
+
+/// Stops the kernel for GDB, if the stub is enabled.
+///
+/// This returns once GDB lets the kernel continue.
+pub fn breakpoint() {
+    if is_enabled() {
+        // This is safe, because the breakpoint is handled by the stub, which returns to the caller.
+        unsafe { beetle_breakpoint() };
+    }
+}
+
+/// Reports a panic to GDB, stopping the kernel if GDB is attached.
+pub fn report_panic() {
+    PANICKED.store(true, Ordering::SeqCst);
+
+    // The panic may have happened in the stub itself.
+    let attached = STUB.try_lock().map_or(false, |stub| stub.attached);
+
+    if attached {
+        breakpoint();
+    }
+}
+
+/// Keeps waiting for GDB, so that it can still attach after a panic.
+///
+/// This returns right away if the stub is disabled.
+pub fn wait_for_debugger() {
+    while is_enabled() {
+        poll();
+        spin_loop_hint();
+    }
+}
@@ -0,0 +1,243 @@
+//! Provides the global descriptor table and the task state segment.
+//!
+//! The layout of the segments is dictated by the `syscall` instruction,
+//! which expects the kernel data segment directly after the kernel code segment.
+
+use core::mem::size_of;
+
+use crate::io_port::{IoPortSet, IO_PORT_COUNT};
+
+/// The selector of the kernel code segment.
+pub const KERNEL_CODE_SELECTOR: u16 = 0x08;
+
+/// The selector of the kernel data segment.
+pub const KERNEL_DATA_SELECTOR: u16 = 0x10;
+
+/// The selector of the user data segment, including the requested privilege level.
+pub const USER_DATA_SELECTOR: u16 = 0x20 | 3;
+
+/// The selector of the 64-bit user code segment, including the requested privilege level.
+pub const USER_CODE_SELECTOR: u16 = 0x28 | 3;
+
+/// The selector of the task state segment.
+const TSS_SELECTOR: u16 = 0x30;
+
+/// The interrupt stack table index of the stack used for double faults.
+pub const DOUBLE_FAULT_STACK_INDEX: u8 = 1;
+
+/// The interrupt stack table index of the stack used for debug exceptions.
+pub const DEBUG_STACK_INDEX: u8 = 2;
+
+/// The interrupt stack table index of the stack used for non-maskable interrupts.
+pub const NMI_STACK_INDEX: u8 = 3;
+
+/// The size of the stacks used when entering the kernel.
+const STACK_SIZE: usize = 0x4000;
+
+/// The number of words in the I/O permission bitmap.
+const IO_BITMAP_WORDS: usize = IO_PORT_COUNT / 64;
+
+/// The number of entries in the global descriptor table.
+const GDT_ENTRY_COUNT: usize = 8;
+
+/// A stack that the processor switches to when entering the kernel.
+#[repr(C, align(16))]
+struct Stack([u8; STACK_SIZE]);
+
+/// The stack used when an interrupt or exception occurs in user mode.
+static mut ENTRY_STACK: Stack = Stack([0; STACK_SIZE]);
+
+/// The stack used for double faults, so that they can be reported even if the kernel stack overflowed.
+static mut DOUBLE_FAULT_STACK: Stack = Stack([0; STACK_SIZE]);
+
+/// The stack used for debug exceptions.
+///
+/// They can occur on the first instruction of the system call entry, before it switched away from the user stack.
+static mut DEBUG_STACK: Stack = Stack([0; STACK_SIZE]);
+
+/// The stack used for non-maskable interrupts, which can occur anywhere, including on the system call entry.
+static mut NMI_STACK: Stack = Stack([0; STACK_SIZE]);
+
+/// The task state segment, which tells the processor which stacks to use.
+#[repr(C, packed)]
+struct TaskStateSegment {
+    /// Reserved by the architecture.
+    reserved0: u32,
+    /// The stacks used when switching to the privilege level of the index.
+    privilege_stacks: [u64; 3],
+    /// Reserved by the architecture.
+    reserved1: u64,
+    /// The stacks that interrupts can be configured to use.
+    ///
+    /// The index 0 in this array corresponds to the index 1 in interrupt descriptors.
+    interrupt_stacks: [u64; 7],
+    /// Reserved by the architecture.
+    reserved2: u64,
+    /// Reserved by the architecture.
+    reserved3: u16,
+    /// The offset of the I/O permission bitmap from the start of the segment.
+    io_map_base: u16,
+    /// The I/O permission bitmap, where a set bit denies access to the corresponding port.
+    io_bitmap: [u64; IO_BITMAP_WORDS],
+    /// Terminates the I/O permission bitmap, which the architecture requires to be all ones.
+    io_bitmap_end: u8,
+}
+
+/// The task state segment of the processor.
+static mut TSS: TaskStateSegment = TaskStateSegment {
+    reserved0: 0,
+    privilege_stacks: [0; 3],
+    reserved1: 0,
+    interrupt_stacks: [0; 7],
+    reserved2: 0,
+    reserved3: 0,
+    io_map_base: 0,
+    io_bitmap: [u64::max_value(); IO_BITMAP_WORDS],
+    io_bitmap_end: 0xff,
+};
+
+/// The version of the I/O port set that is loaded into the I/O permission bitmap.
+///
+/// Versions start at one, so zero means that no set is loaded.
+static mut LOADED_IO_PORTS: usize = 0;
+
+/// The global descriptor table.
+///
+/// The last two entries hold the descriptor of the task state segment, which is filled in at runtime.
+static mut GDT: [u64; GDT_ENTRY_COUNT] = [
+    // The null descriptor.
+    0,
+    // The kernel code segment.
+    0x00af_9a00_0000_ffff,
+    // The kernel data segment.
+    0x00cf_9200_0000_ffff,
+    // A 32-bit user code segment, which is unused, but required by the layout `sysret` expects.
+    0x00cf_fa00_0000_ffff,
+    // The user data segment.
+    0x00cf_f200_0000_ffff,
+    // The 64-bit user code segment, whose selector is 0x2b including the requested privilege level.
+    0x00af_fa00_0000_ffff,
+    // The task state segment.
+    0,
+    0,
+];
+
+/// The operand of the `lgdt` and `lidt` instructions.
+#[repr(C, packed)]
+pub struct DescriptorTablePointer {
+    /// The size of the table in bytes minus one.
+    pub limit: u16,
+    /// The address of the table.
+    pub base: u64,
+}
+
+extern "sysv64" {
+    /// Loads the global descriptor table, reloads all segment registers and loads the task register.
+    fn beetle_load_gdt(
+        pointer: *const DescriptorTablePointer,
+        code_selector: u64,
+        data_selector: u64,
+        tss_selector: u64,
+    );
+}
+
+global_asm!(
+    "
+    .intel_syntax noprefix
+    .text
+    .global beetle_load_gdt
+beetle_load_gdt:
+    lgdt [rdi]
+    mov ds, dx
+    mov es, dx
+    mov ss, dx
+    xor eax, eax
+    mov fs, ax
+    mov gs, ax
+    ltr cx
+    # Reload the code segment by returning to the caller through a far return.
+    pop rax
+    push rsi
+    push rax
+    retfq
+    .att_syntax prefix
+"
+);
+
+/// The offset of the I/O permission bitmap in the task state segment.
+const IO_BITMAP_OFFSET: u16 = 104;
+
+/// An I/O map base beyond the segment limit, which means that there is no I/O permission bitmap.
+const NO_IO_BITMAP: u16 = size_of::<TaskStateSegment>() as u16;
+
+/// Returns the address just above the given stack.
+fn stack_top(stack: &Stack) -> u64 {
+    stack as *const Stack as u64 + STACK_SIZE as u64
+}
+
+/// Initializes the global descriptor table and the task state segment.
+pub fn init() {
+    // This is safe, because this runs once during initialization, before anything else uses these structures.
+    unsafe {
+        TSS.privilege_stacks = [stack_top(&ENTRY_STACK), 0, 0];
+        TSS.interrupt_stacks = [
+            stack_top(&DOUBLE_FAULT_STACK),
+            stack_top(&DEBUG_STACK),
+            stack_top(&NMI_STACK),
+            0,
+            0,
+            0,
+            0,
+        ];
+        TSS.io_map_base = NO_IO_BITMAP;
+
+        let base = &TSS as *const TaskStateSegment as u64;
+        let limit = size_of::<TaskStateSegment>() as u64 - 1;
+
+        GDT[TSS_SELECTOR as usize / 8] = (limit & 0xffff)
+            | (base & 0xff_ffff) << 16
+            | 0x89 << 40
+            | (limit >> 16 & 0xf) << 48
+            | (base >> 24 & 0xff) << 56;
+        GDT[TSS_SELECTOR as usize / 8 + 1] = base >> 32;
+
+        let pointer = DescriptorTablePointer {
+            limit: (size_of::<[u64; GDT_ENTRY_COUNT]>() - 1) as u16,
+            base: &GDT as *const _ as u64,
+        };
+
+        beetle_load_gdt(
+            &pointer,
+            u64::from(KERNEL_CODE_SELECTOR),
+            u64::from(KERNEL_DATA_SELECTOR),
+            u64::from(TSS_SELECTOR),
+        );
+    }
+}
+
+/// Allows user mode to access exactly the I/O ports in the set.
+///
+/// If no set is given, user mode cannot access any I/O ports.
+pub fn set_io_ports(ports: Option<&IoPortSet>) {
+    // This is safe, because the bitmap is only read by the processor when user mode accesses I/O ports,
+    // which cannot happen while the kernel runs.
+    unsafe {
+        match ports {
+            Some(ports) => {
+                if LOADED_IO_PORTS != ports.version() {
+                    let mut bitmap = [0; IO_BITMAP_WORDS];
+
+                    for (denied, &allowed) in bitmap.iter_mut().zip(ports.bitmap()) {
+                        *denied = !allowed;
+                    }
+
+                    TSS.io_bitmap = bitmap;
+                    LOADED_IO_PORTS = ports.version();
+                }
+
+                TSS.io_map_base = IO_BITMAP_OFFSET;
+            }
+            None => TSS.io_map_base = NO_IO_BITMAP,
+        }
+    }
+}
@@ -0,0 +1,515 @@
+//! Provides the interrupt descriptor table and the handling of exceptions and hardware interrupts.
+//!
+//! Exceptions and interrupts in user mode return to the kernel through the user mode entry code.
+//! Exceptions in kernel mode are kernel bugs and cause a panic,
+//! except for debug exceptions and breakpoints, which are passed to the GDB stub if it is enabled.
+//! Interrupts in kernel mode are handled on the current stack.
+//! Double faults, debug exceptions and non-maskable interrupts always switch to stacks of their own,
+//! since they can occur while the current stack is unusable, like on the first instructions of the system call entry.
+
+use core::mem::size_of;
+use x86_64_crate::registers::control::Cr2;
+
+use super::{
+    apic::{
+        self, IRQ_BASE_VECTOR, MSI_BASE_VECTOR, MSI_VECTOR_COUNT, SPURIOUS_VECTOR, TIMER_VECTOR,
+    },
+    gdb,
+    gdt::{
+        DescriptorTablePointer, DEBUG_STACK_INDEX, DOUBLE_FAULT_STACK_INDEX, KERNEL_CODE_SELECTOR,
+        NMI_STACK_INDEX,
+    },
+    msi,
+};
+use crate::{
+    arch::{Arch, Architecture},
+    irq, time,
+};
+
+/// The number of exception vectors defined by the architecture.
+const EXCEPTION_COUNT: usize = 32;
+
+/// The number of entries in the interrupt descriptor table.
+const IDT_ENTRY_COUNT: usize = 256;
+
+/// The vector of the debug exception.
+pub const DEBUG_VECTOR: usize = 1;
+
+/// The vector of the non-maskable interrupt.
+const NMI_VECTOR: usize = 2;
+
+/// The vector of the breakpoint exception.
+pub const BREAKPOINT_VECTOR: usize = 3;
+
+/// The vector of the double fault exception.
+const DOUBLE_FAULT_VECTOR: usize = 8;
+
+/// The vector of the page fault exception.
+pub const PAGE_FAULT_VECTOR: usize = 14;
+
+/// The names of the exceptions.
+const EXCEPTION_NAMES: [&str; EXCEPTION_COUNT] = [
+    "divide error",
+    "debug",
+    "non-maskable interrupt",
+    "breakpoint",
+    "overflow",
+    "bound range exceeded",
+    "invalid opcode",
+    "device not available",
+    "double fault",
+    "coprocessor segment overrun",
+    "invalid TSS",
+    "segment not present",
+    "stack-segment fault",
+    "general protection fault",
+    "page fault",
+    "reserved",
+    "x87 floating-point exception",
+    "alignment check",
+    "machine check",
+    "SIMD floating-point exception",
+    "virtualization exception",
+    "control protection exception",
+    "reserved",
+    "reserved",
+    "reserved",
+    "reserved",
+    "reserved",
+    "reserved",
+    "hypervisor injection exception",
+    "VMM communication exception",
+    "security exception",
+    "reserved",
+];
+
+/// An entry of the interrupt descriptor table.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct IdtEntry {
+    /// Bits 0 to 15 of the handler address.
+    offset_low: u16,
+    /// The code segment the handler runs in.
+    selector: u16,
+    /// The index of the interrupt stack to switch to, or zero to not switch stacks.
+    interrupt_stack: u8,
+    /// The type and the privilege level of the entry.
+    attributes: u8,
+    /// Bits 16 to 31 of the handler address.
+    offset_middle: u16,
+    /// Bits 32 to 63 of the handler address.
+    offset_high: u32,
+    /// Reserved by the architecture.
+    reserved: u32,
+}
+
+impl IdtEntry {
+    /// An entry that is not present.
+    const MISSING: IdtEntry = IdtEntry {
+        offset_low: 0,
+        selector: 0,
+        interrupt_stack: 0,
+        attributes: 0,
+        offset_middle: 0,
+        offset_high: 0,
+        reserved: 0,
+    };
+
+    /// Creates an interrupt gate for the handler at the given address.
+    ///
+    /// If `user_accessible` is true, the interrupt can be raised by user mode using the `int` instruction.
+    fn new(handler: u64, interrupt_stack: u8, user_accessible: bool) -> IdtEntry {
+        // A present 64-bit interrupt gate.
+        let attributes = if user_accessible { 0xee } else { 0x8e };
+
+        IdtEntry {
+            offset_low: handler as u16,
+            selector: KERNEL_CODE_SELECTOR,
+            interrupt_stack,
+            attributes,
+            offset_middle: (handler >> 16) as u16,
+            offset_high: (handler >> 32) as u32,
+            reserved: 0,
+        }
+    }
+}
+
+/// The interrupt descriptor table.
+static mut IDT: [IdtEntry; IDT_ENTRY_COUNT] = [IdtEntry::MISSING; IDT_ENTRY_COUNT];
+
+extern "C" {
+    /// The addresses of the entry points of the exceptions.
+    static beetle_exception_entries: [u64; EXCEPTION_COUNT];
+
+    /// The addresses of the entry points of the interrupt lines.
+    static beetle_irq_entries: [u64; Arch::IRQ_COUNT];
+
+    /// The addresses of the entry points of the vectors for message signaled interrupts.
+    static beetle_msi_entries: [u64; MSI_VECTOR_COUNT];
+}
+
+extern "sysv64" {
+    /// Loads the interrupt descriptor table.
+    fn beetle_load_idt(pointer: *const DescriptorTablePointer);
+
+    /// The entry point of spurious interrupts, which only returns.
+    fn beetle_spurious_interrupt_entry();
+
+    /// The entry point of the timer interrupt.
+    fn beetle_timer_interrupt_entry();
+
+    /// Enables interrupts, halts until an interrupt occurs and disables interrupts again.
+    fn beetle_wait_for_interrupt();
+}
+
+// Every entry point pushes a zero for exceptions without an error code and the vector,
+// so that all exceptions and interrupts share the same stack layout.
+global_asm!(
+    "
+    .intel_syntax noprefix
+    .text
+    .global beetle_load_idt
+beetle_load_idt:
+    lidt [rdi]
+    ret
+
+    .macro exception_entry vector, has_error_code
+beetle_exception_entry_\\vector:
+    .if \\has_error_code == 0
+    push 0
+    .endif
+    push \\vector
+    jmp beetle_exception_common
+    .endm
+
+    exception_entry 0, 0
+    exception_entry 1, 0
+    exception_entry 2, 0
+    exception_entry 3, 0
+    exception_entry 4, 0
+    exception_entry 5, 0
+    exception_entry 6, 0
+    exception_entry 7, 0
+    exception_entry 8, 1
+    exception_entry 9, 0
+    exception_entry 10, 1
+    exception_entry 11, 1
+    exception_entry 12, 1
+    exception_entry 13, 1
+    exception_entry 14, 1
+    exception_entry 15, 0
+    exception_entry 16, 0
+    exception_entry 17, 1
+    exception_entry 18, 0
+    exception_entry 19, 0
+    exception_entry 20, 0
+    exception_entry 21, 1
+    exception_entry 22, 0
+    exception_entry 23, 0
+    exception_entry 24, 0
+    exception_entry 25, 0
+    exception_entry 26, 0
+    exception_entry 27, 0
+    exception_entry 28, 0
+    exception_entry 29, 1
+    exception_entry 30, 1
+    exception_entry 31, 0
+    exception_entry 32, 0
+    exception_entry 33, 0
+    exception_entry 34, 0
+    exception_entry 35, 0
+    exception_entry 36, 0
+    exception_entry 37, 0
+    exception_entry 38, 0
+    exception_entry 39, 0
+    exception_entry 40, 0
+    exception_entry 41, 0
+    exception_entry 42, 0
+    exception_entry 43, 0
+    exception_entry 44, 0
+    exception_entry 45, 0
+    exception_entry 46, 0
+    exception_entry 47, 0
+    exception_entry 48, 0
+    exception_entry 49, 0
+    exception_entry 50, 0
+    exception_entry 51, 0
+    exception_entry 52, 0
+    exception_entry 53, 0
+    exception_entry 54, 0
+    exception_entry 55, 0
+
+    # The vectors from `MSI_BASE_VECTOR` on are used for message signaled interrupts.
+    .irp high, 4, 5, 6, 7, 8, 9, a, b
+    .irp low, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, a, b, c, d, e, f
+    exception_entry 0x\\high\\()\\low, 0
+    .endr
+    .endr
+
+    .global beetle_spurious_interrupt_entry
+beetle_spurious_interrupt_entry:
+    iretq
+
+    # The vector of the timer interrupt is `TIMER_VECTOR`.
+    .global beetle_timer_interrupt_entry
+beetle_timer_interrupt_entry:
+    push 0
+    push 56
+    jmp beetle_exception_common
+
+    # Interrupts are only recognized after the instruction following `sti`,
+    # so an interrupt cannot be lost between enabling interrupts and halting.
+    .global beetle_wait_for_interrupt
+beetle_wait_for_interrupt:
+    sti
+    hlt
+    cli
+    ret
+
+    .data
+    .balign 8
+    .global beetle_exception_entries
+beetle_exception_entries:
+    .quad beetle_exception_entry_0
+    .quad beetle_exception_entry_1
+    .quad beetle_exception_entry_2
+    .quad beetle_exception_entry_3
+    .quad beetle_exception_entry_4
+    .quad beetle_exception_entry_5
+    .quad beetle_exception_entry_6
+    .quad beetle_exception_entry_7
+    .quad beetle_exception_entry_8
+    .quad beetle_exception_entry_9
+    .quad beetle_exception_entry_10
+    .quad beetle_exception_entry_11
+    .quad beetle_exception_entry_12
+    .quad beetle_exception_entry_13
+    .quad beetle_exception_entry_14
+    .quad beetle_exception_entry_15
+    .quad beetle_exception_entry_16
+    .quad beetle_exception_entry_17
+    .quad beetle_exception_entry_18
+    .quad beetle_exception_entry_19
+    .quad beetle_exception_entry_20
+    .quad beetle_exception_entry_21
+    .quad beetle_exception_entry_22
+    .quad beetle_exception_entry_23
+    .quad beetle_exception_entry_24
+    .quad beetle_exception_entry_25
+    .quad beetle_exception_entry_26
+    .quad beetle_exception_entry_27
+    .quad beetle_exception_entry_28
+    .quad beetle_exception_entry_29
+    .quad beetle_exception_entry_30
+    .quad beetle_exception_entry_31
+
+    .global beetle_irq_entries
+beetle_irq_entries:
+    .quad beetle_exception_entry_32
+    .quad beetle_exception_entry_33
+    .quad beetle_exception_entry_34
+    .quad beetle_exception_entry_35
+    .quad beetle_exception_entry_36
+    .quad beetle_exception_entry_37
+    .quad beetle_exception_entry_38
+    .quad beetle_exception_entry_39
+    .quad beetle_exception_entry_40
+    .quad beetle_exception_entry_41
+    .quad beetle_exception_entry_42
+    .quad beetle_exception_entry_43
+    .quad beetle_exception_entry_44
+    .quad beetle_exception_entry_45
+    .quad beetle_exception_entry_46
+    .quad beetle_exception_entry_47
+    .quad beetle_exception_entry_48
+    .quad beetle_exception_entry_49
+    .quad beetle_exception_entry_50
+    .quad beetle_exception_entry_51
+    .quad beetle_exception_entry_52
+    .quad beetle_exception_entry_53
+    .quad beetle_exception_entry_54
+    .quad beetle_exception_entry_55
+
+    .global beetle_msi_entries
+beetle_msi_entries:
+    .irp high, 4, 5, 6, 7, 8, 9, a, b
+    .irp low, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, a, b, c, d, e, f
+    .quad beetle_exception_entry_0x\\high\\()\\low
+    .endr
+    .endr
+
+    .text
+    .att_syntax prefix
+"
+);
+
+/// The state saved by the processor and the entry code when an exception occurs.
+#[repr(C)]
+#[derive(Debug)]
+struct ExceptionFrame {
+    /// The vector of the exception.
+    vector: u64,
+    /// The error code of the exception, or zero if it has none.
+    error_code: u64,
+    /// The address of the instruction that caused the exception.
+    instruction_pointer: u64,
+    /// The code segment that was active.
+    code_segment: u64,
+    /// The flags register.
+    flags: u64,
+    /// The stack pointer.
+    stack_pointer: u64,
+    /// The stack segment that was active.
+    stack_segment: u64,
+}
+
+/// The state saved by the processor and the entry code when a debug exception or a breakpoint occurs in kernel mode.
+///
+/// The entry code depends on the exact layout of this type and restores the registers from it when returning.
+#[repr(C)]
+#[derive(Debug)]
+pub struct DebugFrame {
+    /// The general purpose registers in the order rax, rbx, rcx, rdx, rsi, rdi, rbp and r8 to r15.
+    pub registers: [u64; 15],
+    /// The vector of the exception.
+    pub vector: u64,
+    /// Always zero, since these exceptions have no error code.
+    pub error_code: u64,
+    /// The address of the instruction that is executed next.
+    pub instruction_pointer: u64,
+    /// The code segment that was active.
+    pub code_segment: u64,
+    /// The flags register.
+    pub flags: u64,
+    /// The stack pointer.
+    pub stack_pointer: u64,
+    /// The stack segment that was active.
+    pub stack_segment: u64,
+}
+
+/// Returns the name of the exception with the given vector.
+fn exception_name(vector: usize) -> &'static str {
+    EXCEPTION_NAMES.get(vector).cloned().unwrap_or("unknown")
+}
+
+/// Handles exceptions that occur in kernel mode.
+///
+/// This is called by the exception entry code.
+#[no_mangle]
+extern "sysv64" fn beetle_kernel_exception(frame: &ExceptionFrame) -> ! {
+    let vector = frame.vector as usize;
+
+    // The kernel accesses user memory only through the physical memory mapping,
+    // so a page fault in kernel mode is always a bug.
+    if vector == PAGE_FAULT_VECTOR {
+        panic!(
+            "Page fault accessing {:#x} in the kernel: {:#x?}",
+            Cr2::read().as_u64(),
+            frame
+        );
+    } else {
+        panic!(
+            "Exception {} ({}) in the kernel: {:#x?}",
+            vector,
+            exception_name(vector),
+            frame
+        );
+    }
+}
+
+/// Handles debug exceptions and breakpoints that occur in kernel mode.
+///
+/// They stop the kernel for the debugger, which may change the registers before the kernel continues.
+/// Without a debugger, they are kernel bugs like other exceptions.
+///
+/// This is called by the exception entry code.
+#[no_mangle]
+extern "sysv64" fn beetle_kernel_debug_exception(frame: &mut DebugFrame) {
+    if !gdb::handle_exception(frame) {
+        panic!(
+            "Exception {} ({}) in the kernel: {:#x?}",
+            frame.vector,
+            exception_name(frame.vector as usize),
+            frame
+        );
+    }
+}
+
+/// Handles the hardware interrupt with the given vector.
+///
+/// The interrupt line stays masked until the driver acknowledges the interrupt.
+/// Message signaled interrupts are not masked, since they are only sent once for each event.
+pub fn handle_interrupt(vector: usize) {
+    if vector == TIMER_VECTOR {
+        apic::end_of_interrupt();
+        time::run_timers();
+
+        return;
+    }
+
+    if vector >= MSI_BASE_VECTOR {
+        apic::end_of_interrupt();
+        msi::handle(vector);
+
+        return;
+    }
+
+    let irq = vector - IRQ_BASE_VECTOR;
+
+    apic::mask(irq);
+    apic::end_of_interrupt();
+
+    irq::handle(irq);
+}
+
+/// Handles hardware interrupts that occur in kernel mode.
+///
+/// This is called by the interrupt entry code.
+#[no_mangle]
+extern "sysv64" fn beetle_kernel_interrupt(vector: u64) {
+    handle_interrupt(vector as usize);
+}
+
+/// Enables interrupts, waits until an interrupt occurs and disables interrupts again.
+pub fn wait_for_interrupt() {
+    // This is safe, because interrupts are handled once the interrupt descriptor table is loaded.
+    unsafe { beetle_wait_for_interrupt() };
+}
+
+/// Initializes the interrupt descriptor table.
+pub fn init() {
+    // This is safe, because this runs once during initialization, before any interrupts are expected.
+    unsafe {
+        for (vector, &handler) in beetle_exception_entries.iter().enumerate() {
+            let interrupt_stack = match vector {
+                DOUBLE_FAULT_VECTOR => DOUBLE_FAULT_STACK_INDEX,
+                DEBUG_VECTOR => DEBUG_STACK_INDEX,
+                NMI_VECTOR => NMI_STACK_INDEX,
+                _ => 0,
+            };
+
+            IDT[vector] = IdtEntry::new(handler, interrupt_stack, vector == BREAKPOINT_VECTOR);
+        }
+
+        for (irq, &handler) in beetle_irq_entries.iter().enumerate() {
+            IDT[IRQ_BASE_VECTOR + irq] = IdtEntry::new(handler, 0, false);
+        }
+
+        for (index, &handler) in beetle_msi_entries.iter().enumerate() {
+            IDT[MSI_BASE_VECTOR + index] = IdtEntry::new(handler, 0, false);
+        }
+
+        IDT[SPURIOUS_VECTOR] =
+            IdtEntry::new(beetle_spurious_interrupt_entry as usize as u64, 0, false);
+        IDT[TIMER_VECTOR] = IdtEntry::new(beetle_timer_interrupt_entry as usize as u64, 0, false);
+
+        let pointer = DescriptorTablePointer {
+            limit: (size_of::<[IdtEntry; IDT_ENTRY_COUNT]>() - 1) as u16,
+            base: &IDT as *const _ as u64,
+        };
+
+        beetle_load_idt(&pointer);
+    }
+
+    apic::init();
+}
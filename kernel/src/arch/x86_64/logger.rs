@@ -1,6 +1,8 @@
 //! This module defines the kernel logger.
 
-use log::{Level, Log, Metadata, Record};
+use log::{Log, Metadata, Record};
+
+use crate::{console, log_buffer};
 
 /// The type of the kernel logger.
 pub struct KernelLogger;
@@ -9,43 +11,24 @@ pub struct KernelLogger;
 pub static KERNEL_LOGGER: KernelLogger = KernelLogger;
 
 impl Log for KernelLogger {
-    #[cfg(feature = "integration_test")]
+    #[cfg(feature = "qemu_integration_test")]
     fn enabled(&self, _metadata: &Metadata) -> bool {
         false
     }
 
-    #[cfg(not(feature = "integration_test"))]
-    fn enabled(&self, _metadata: &Metadata) -> bool {
-        true
+    #[cfg(not(feature = "qemu_integration_test"))]
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        crate::log_filter::enabled(metadata)
     }
 
     fn log(&self, record: &Record) {
-        // The colors to use for terminal output.
-        let reset = "\x1b[0m";
-        let error = "\x1b[31m"; // Red
-        let warn = "\x1b[33m"; // Yellow
-        let debug = ""; // Default
-        let trace = "\x1b[90m"; // Dark gray
-
-        match record.metadata().level() {
-            Level::Error => {
-                println!("{}", record.args());
-                serial_println!("{}{}{}: {}", error, record.level(), reset, record.args());
-            }
-            Level::Warn => {
-                println!("{}", record.args());
-                serial_println!("{}{}{}: {}", warn, record.level(), reset, record.args());
-            }
-            Level::Info => {
-                serial_println!("{}", record.args());
-            }
-            Level::Debug => {
-                serial_println!("{}{}{}: {}", debug, record.level(), reset, record.args());
-            }
-            Level::Trace => {
-                serial_println!("{}{}{}: {}", trace, record.level(), reset, record.args());
-            }
+        // The maximum log level only filters by the most verbose module.
+        if !self.enabled(record.metadata()) {
+            return;
         }
+
+        let stored = log_buffer::push(record);
+        console::write_log(&stored, *record.args());
     }
 
     fn flush(&self) {}
@@ -0,0 +1,546 @@
+//! Handles the x86_64 specific parts of memory management.
+//!
+//! The kernel keeps using the identity mapping set up by the UEFI firmware.
+//! Its page table entries are shared by every address space, while the entries that cover user space are
+//! private to each address space.
+//! Every top level entry of the kernel points to a table from the start,
+//! so kernel mappings that are added later are seen by all address spaces.
+
+use bitflags::bitflags;
+use x86_64_crate::{
+    instructions::tlb,
+    registers::control::{Cr3, Cr3Flags},
+    structures::paging::PhysFrame,
+    PhysAddr, VirtAddr,
+};
+
+use crate::{
+    arch::{Arch, Architecture},
+    memory::{
+        allocate_zeroed_frame, MemoryError, PageFlags, PhysicalAddress, VirtualAddress,
+        FRAME_ALLOCATOR, PAGE_SIZE,
+    },
+    sync::GlobalRuntimeConfiguration,
+};
+
+/// The number of entries in a page table.
+const ENTRY_COUNT: usize = 512;
+
+/// The number of bytes covered by a single entry in the top level page table.
+const TOP_LEVEL_ENTRY_SIZE: usize = 1 << 39;
+
+/// The first top level entry that belongs to user space.
+const FIRST_USER_ENTRY: usize = 1;
+
+/// The first top level entry after user space.
+const LAST_USER_ENTRY: usize = 256;
+
+/// The lowest virtual address that is available to user space.
+pub const USER_SPACE_START: VirtualAddress =
+    VirtualAddress::new(FIRST_USER_ENTRY * TOP_LEVEL_ENTRY_SIZE);
+
+/// The first virtual address above user space.
+pub const USER_SPACE_END: VirtualAddress =
+    VirtualAddress::new(LAST_USER_ENTRY * TOP_LEVEL_ENTRY_SIZE);
+
+/// The bits of a page table entry that contain the physical address.
+const ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+/// The top level page table of the kernel.
+///
+/// Its kernel entries are copied into every new address space.
+static KERNEL_PAGE_TABLE: GlobalRuntimeConfiguration<PhysicalAddress> =
+    GlobalRuntimeConfiguration::new();
+
+bitflags! {
+    /// The flags of a page table entry.
+    pub(super) struct EntryFlags: u64 {
+        /// The entry is valid.
+        const PRESENT = 1 << 0;
+        /// The memory can be written to.
+        const WRITABLE = 1 << 1;
+        /// The memory can be accessed from ring 3.
+        const USER_ACCESSIBLE = 1 << 2;
+        /// Writes go directly to memory.
+        const WRITE_THROUGH = 1 << 3;
+        /// The memory is not cached.
+        const NO_CACHE = 1 << 4;
+        /// The entry maps a huge page instead of pointing to another table.
+        const HUGE_PAGE = 1 << 7;
+        /// The mapping is not flushed from the TLB on address space switches.
+        const GLOBAL = 1 << 8;
+        /// Ignored by the hardware, marks pages whose frame is copied before the first write.
+        const COPY_ON_WRITE = 1 << 9;
+        /// Code cannot be executed from the memory.
+        const NO_EXECUTE = 1 << 63;
+    }
+}
+
+impl EntryFlags {
+    /// Converts the architecture independent page flags to entry flags for a mapped page.
+    fn from_page_flags(flags: PageFlags) -> EntryFlags {
+        let mut entry_flags = EntryFlags::PRESENT;
+
+        if flags.contains(PageFlags::WRITABLE) {
+            entry_flags |= EntryFlags::WRITABLE;
+        }
+        if !flags.contains(PageFlags::EXECUTABLE) {
+            entry_flags |= EntryFlags::NO_EXECUTE;
+        }
+        if flags.contains(PageFlags::USER_ACCESSIBLE) {
+            entry_flags |= EntryFlags::USER_ACCESSIBLE;
+        }
+        if flags.contains(PageFlags::NO_CACHE) {
+            entry_flags |= EntryFlags::NO_CACHE | EntryFlags::WRITE_THROUGH;
+        }
+        if flags.contains(PageFlags::COPY_ON_WRITE) {
+            entry_flags |= EntryFlags::COPY_ON_WRITE;
+        }
+
+        entry_flags
+    }
+
+    /// Converts the entry flags of a mapped page to architecture independent page flags.
+    fn to_page_flags(self) -> PageFlags {
+        let mut flags = PageFlags::empty();
+
+        if self.contains(EntryFlags::WRITABLE) {
+            flags |= PageFlags::WRITABLE;
+        }
+        if !self.contains(EntryFlags::NO_EXECUTE) {
+            flags |= PageFlags::EXECUTABLE;
+        }
+        if self.contains(EntryFlags::USER_ACCESSIBLE) {
+            flags |= PageFlags::USER_ACCESSIBLE;
+        }
+        if self.contains(EntryFlags::NO_CACHE) {
+            flags |= PageFlags::NO_CACHE;
+        }
+        if self.contains(EntryFlags::COPY_ON_WRITE) {
+            flags |= PageFlags::COPY_ON_WRITE;
+        }
+
+        flags
+    }
+}
+
+/// An entry in a page table.
+#[derive(Clone, Copy)]
+#[repr(transparent)]
+struct Entry(u64);
+
+impl Entry {
+    /// An entry that doesn't map anything.
+    const UNUSED: Entry = Entry(0);
+
+    /// Creates an entry pointing to the given physical address.
+    fn new(address: PhysicalAddress, flags: EntryFlags) -> Entry {
+        Entry(address.as_usize() as u64 & ADDRESS_MASK | flags.bits())
+    }
+
+    /// Returns the flags of the entry.
+    fn flags(self) -> EntryFlags {
+        EntryFlags::from_bits_truncate(self.0)
+    }
+
+    /// Returns the physical address the entry points to.
+    fn address(self) -> PhysicalAddress {
+        PhysicalAddress::new((self.0 & ADDRESS_MASK) as usize)
+    }
+
+    /// Checks if the entry is valid.
+    fn is_present(self) -> bool {
+        self.flags().contains(EntryFlags::PRESENT)
+    }
+}
+
+/// A single page table of the hierarchy.
+#[repr(C, align(4096))]
+struct Table {
+    /// The entries of the table.
+    entries: [Entry; ENTRY_COUNT],
+}
+
+impl Table {
+    /// Returns a reference to the table stored in the given frame.
+    ///
+    /// # Safety
+    /// The frame must contain a page table and no other reference to it may be in use.
+    unsafe fn at(frame: PhysicalAddress) -> &'static mut Table {
+        &mut *Arch::physical_to_virtual(frame).as_mut_ptr::<Table>()
+    }
+}
+
+/// Returns the index into the table at the given level (4 being the top level) for the address.
+fn table_index(address: VirtualAddress, level: usize) -> usize {
+    (address.as_usize() >> (12 + 9 * (level - 1))) & (ENTRY_COUNT - 1)
+}
+
+/// Returns the physical address of the currently active top level page table.
+fn active_table() -> PhysicalAddress {
+    PhysicalAddress::new(Cr3::read().0.start_address().as_u64() as usize)
+}
+
+/// Walks the active page table hierarchy down to the entry that maps the address.
+///
+/// `f` is called with the level, the index, the address and the flags of each entry on the way.
+/// The walk stops at the first entry that is not present or that maps a page.
+pub(super) fn walk_active_table<F>(address: VirtualAddress, mut f: F)
+where
+    F: FnMut(usize, usize, PhysicalAddress, EntryFlags),
+{
+    let mut table = active_table();
+
+    for level in (1..=4).rev() {
+        let index = table_index(address, level);
+
+        // This is safe, because the active page table is only read here.
+        let entry = unsafe { Table::at(table).entries[index] };
+
+        f(level, index, entry.address(), entry.flags());
+
+        if !entry.is_present() || level == 1 || entry.flags().contains(EntryFlags::HUGE_PAGE) {
+            break;
+        }
+
+        table = entry.address();
+    }
+}
+
+/// Checks if the address is mapped in the active page table hierarchy.
+pub(super) fn is_mapped(address: VirtualAddress) -> bool {
+    let mut mapped = false;
+
+    walk_active_table(address, |level, _, _, flags| {
+        mapped = flags.contains(EntryFlags::PRESENT)
+            && (level == 1 || flags.contains(EntryFlags::HUGE_PAGE));
+    });
+
+    mapped
+}
+
+/// Makes the top level page table at the given physical address the active one.
+///
+/// # Safety
+/// The table must stay alive as long as it is active.
+unsafe fn load_table(table: PhysicalAddress) {
+    Cr3::write(
+        PhysFrame::containing_address(PhysAddr::new(table.as_usize() as u64)),
+        Cr3Flags::empty(),
+    );
+}
+
+/// Initializes the memory management of the x86_64 architecture.
+///
+/// This copies the page tables set up by the firmware into a top level table of the kernel,
+/// in which every kernel entry points to a table, and activates it.
+/// This must be called while the page tables set up by the firmware are active
+/// and after the frame allocator received the usable memory.
+pub fn init() {
+    let firmware_table = active_table();
+    let kernel_table =
+        allocate_zeroed_frame().expect("There is no memory for the kernel page table.");
+
+    // This is safe, because the firmware table is only read, the kernel table isn't used anywhere else yet
+    // and it maps everything that the firmware table maps.
+    unsafe {
+        let source = Table::at(firmware_table);
+        let target = Table::at(kernel_table);
+
+        for index in FIRST_USER_ENTRY..LAST_USER_ENTRY {
+            if source.entries[index].is_present() {
+                panic!(
+                    "The firmware mapped memory at {}, which is reserved for user space.",
+                    VirtualAddress::new(index * TOP_LEVEL_ENTRY_SIZE)
+                );
+            }
+        }
+
+        for index in (0..FIRST_USER_ENTRY).chain(LAST_USER_ENTRY..ENTRY_COUNT) {
+            target.entries[index] = if source.entries[index].is_present() {
+                source.entries[index]
+            } else {
+                // The address spaces only copy the top level entries, so the tables below must never change.
+                let table = allocate_zeroed_frame()
+                    .expect("There is no memory for the kernel page tables.");
+
+                Entry::new(table, EntryFlags::PRESENT | EntryFlags::WRITABLE)
+            };
+        }
+
+        load_table(kernel_table);
+    }
+
+    KERNEL_PAGE_TABLE.init(kernel_table);
+}
+
+/// A four level page table hierarchy of an address space.
+///
+/// Only the user space part of the hierarchy is owned by this type, the kernel part is shared.
+pub struct PageTable {
+    /// The physical address of the top level table.
+    root: PhysicalAddress,
+}
+
+impl PageTable {
+    /// Creates a new page table hierarchy that contains only the kernel mappings.
+    pub fn new() -> Option<PageTable> {
+        let kernel_table = *KERNEL_PAGE_TABLE
+            .get()
+            .expect("Memory management is not initialized.");
+        let root = allocate_zeroed_frame()?;
+
+        // This is safe, because the kernel table is only read and the new table isn't used anywhere else yet.
+        unsafe {
+            let source = Table::at(kernel_table);
+            let target = Table::at(root);
+
+            for index in (0..FIRST_USER_ENTRY).chain(LAST_USER_ENTRY..ENTRY_COUNT) {
+                target.entries[index] = source.entries[index];
+            }
+        }
+
+        Some(PageTable { root })
+    }
+
+    /// Returns the lowest level entry for the page, creating missing tables if `create` is set.
+    fn entry(&mut self, page: VirtualAddress, create: bool) -> Result<&mut Entry, MemoryError> {
+        let mut table = self.root;
+
+        for level in (2..=4).rev() {
+            // This is safe, because the table belongs to this hierarchy, which is borrowed mutably.
+            let entry = unsafe { &mut Table::at(table).entries[table_index(page, level)] };
+
+            if !entry.is_present() {
+                if !create {
+                    return Err(MemoryError::NotMapped(page));
+                }
+
+                let new_table = allocate_zeroed_frame().ok_or(MemoryError::OutOfMemory)?;
+
+                // Access is restricted in the lowest level, so the upper levels allow everything.
+                *entry = Entry::new(
+                    new_table,
+                    EntryFlags::PRESENT | EntryFlags::WRITABLE | EntryFlags::USER_ACCESSIBLE,
+                );
+            }
+
+            table = entry.address();
+        }
+
+        // This is safe, because the table belongs to this hierarchy, which is borrowed mutably.
+        Ok(unsafe { &mut Table::at(table).entries[table_index(page, 1)] })
+    }
+
+    /// Maps the page starting at `page` to the given frame.
+    pub fn map(
+        &mut self,
+        page: VirtualAddress,
+        frame: PhysicalAddress,
+        flags: PageFlags,
+    ) -> Result<(), MemoryError> {
+        let entry = self.entry(page, true)?;
+
+        if entry.is_present() {
+            return Err(MemoryError::AlreadyMapped(page));
+        }
+
+        *entry = Entry::new(frame, EntryFlags::from_page_flags(flags));
+
+        Ok(())
+    }
+
+    /// Removes the mapping of the page starting at `page`, returning the frame it was mapped to.
+    pub fn unmap(&mut self, page: VirtualAddress) -> Option<PhysicalAddress> {
+        let entry = self.entry(page, false).ok()?;
+
+        if !entry.is_present() {
+            return None;
+        }
+
+        let frame = entry.address();
+        *entry = Entry::UNUSED;
+
+        self.flush(page);
+
+        Some(frame)
+    }
+
+    /// Changes the flags of the page starting at `page`.
+    pub fn set_flags(&mut self, page: VirtualAddress, flags: PageFlags) -> Result<(), MemoryError> {
+        let entry = self.entry(page, false)?;
+
+        if !entry.is_present() {
+            return Err(MemoryError::NotMapped(page));
+        }
+
+        *entry = Entry::new(entry.address(), EntryFlags::from_page_flags(flags));
+
+        self.flush(page);
+
+        Ok(())
+    }
+
+    /// Returns the frame and flags the page starting at `page` is mapped to.
+    pub fn translate(&self, page: VirtualAddress) -> Option<(PhysicalAddress, PageFlags)> {
+        let mut table = self.root;
+
+        for level in (1..=4).rev() {
+            // This is safe, because the table belongs to this hierarchy and is only read.
+            let entry = unsafe { Table::at(table).entries[table_index(page, level)] };
+
+            if !entry.is_present() {
+                return None;
+            }
+
+            if level == 1 || entry.flags().contains(EntryFlags::HUGE_PAGE) {
+                let page_size = PAGE_SIZE << (9 * (level - 1));
+                let offset = page.as_usize() & (page_size - 1) & !(PAGE_SIZE - 1);
+
+                return Some((entry.address() + offset, entry.flags().to_page_flags()));
+            }
+
+            table = entry.address();
+        }
+
+        unreachable!();
+    }
+
+    /// Calls `f` with the page, frame and flags of each user space mapping.
+    pub fn for_each_mapping<F>(&self, mut f: F)
+    where
+        F: FnMut(VirtualAddress, PhysicalAddress, PageFlags),
+    {
+        /// Calls `f` for each mapping in the table, which is at the given level.
+        fn walk<F>(table: PhysicalAddress, level: usize, base: usize, f: &mut F)
+        where
+            F: FnMut(VirtualAddress, PhysicalAddress, PageFlags),
+        {
+            for index in 0..ENTRY_COUNT {
+                // This is safe, because the table belongs to the hierarchy and is only read.
+                let entry = unsafe { Table::at(table).entries[index] };
+
+                if !entry.is_present() {
+                    continue;
+                }
+
+                let address = base | index << (12 + 9 * (level - 1));
+
+                if level == 1 {
+                    f(
+                        VirtualAddress::new(address),
+                        entry.address(),
+                        entry.flags().to_page_flags(),
+                    );
+                } else {
+                    walk(entry.address(), level - 1, address, f);
+                }
+            }
+        }
+
+        for index in FIRST_USER_ENTRY..LAST_USER_ENTRY {
+            // This is safe, because the top level table belongs to this hierarchy and is only read.
+            let entry = unsafe { Table::at(self.root).entries[index] };
+
+            if entry.is_present() {
+                walk(entry.address(), 3, index * TOP_LEVEL_ENTRY_SIZE, &mut f);
+            }
+        }
+    }
+
+    /// Removes all user space mappings, calling `f` for each of them, and frees the user space tables.
+    pub fn unmap_all<F>(&mut self, mut f: F)
+    where
+        F: FnMut(VirtualAddress, PhysicalAddress, PageFlags),
+    {
+        /// Unmaps everything in the table, which is at the given level, and frees the table.
+        fn clear<F>(table: PhysicalAddress, level: usize, base: usize, f: &mut F)
+        where
+            F: FnMut(VirtualAddress, PhysicalAddress, PageFlags),
+        {
+            for index in 0..ENTRY_COUNT {
+                // This is safe, because the table belongs to the hierarchy that is being cleared.
+                let entry = unsafe { Table::at(table).entries[index] };
+
+                if !entry.is_present() {
+                    continue;
+                }
+
+                let address = base | index << (12 + 9 * (level - 1));
+
+                if level == 1 {
+                    f(
+                        VirtualAddress::new(address),
+                        entry.address(),
+                        entry.flags().to_page_flags(),
+                    );
+                } else {
+                    clear(entry.address(), level - 1, address, f);
+                }
+            }
+
+            // This is safe, because the table is not referenced anymore.
+            unsafe { FRAME_ALLOCATOR.lock().deallocate(table) };
+        }
+
+        for index in FIRST_USER_ENTRY..LAST_USER_ENTRY {
+            // This is safe, because the top level table belongs to this hierarchy.
+            let entry = unsafe { &mut Table::at(self.root).entries[index] };
+
+            if entry.is_present() {
+                clear(entry.address(), 3, index * TOP_LEVEL_ENTRY_SIZE, &mut f);
+                *entry = Entry::UNUSED;
+            }
+        }
+
+        if self.is_active() {
+            // This is safe, because reloading the same table only flushes the TLB.
+            unsafe { self.activate() };
+        }
+    }
+
+    /// Checks if this page table hierarchy is currently active.
+    pub fn is_active(&self) -> bool {
+        active_table() == self.root
+    }
+
+    /// Activates this page table hierarchy.
+    ///
+    /// # Safety
+    /// The hierarchy must stay alive as long as it is active.
+    pub unsafe fn activate(&self) {
+        load_table(self.root);
+    }
+
+    /// Activates the page table hierarchy of the kernel, which only contains kernel mappings.
+    pub fn activate_kernel() {
+        let kernel_table = *KERNEL_PAGE_TABLE
+            .get()
+            .expect("Memory management is not initialized.");
+
+        // This is safe, because the kernel page table hierarchy is never freed.
+        unsafe { load_table(kernel_table) };
+    }
+
+    /// Removes the given page from the TLB, if this hierarchy is active.
+    fn flush(&self, page: VirtualAddress) {
+        if self.is_active() {
+            tlb::flush(VirtAddr::new(page.as_usize() as u64));
+        }
+    }
+}
+
+impl Drop for PageTable {
+    fn drop(&mut self) {
+        assert!(
+            !self.is_active(),
+            "An active page table hierarchy was dropped."
+        );
+
+        // The frames are owned by the address space, which already released them.
+        self.unmap_all(|_, _, _| ());
+
+        // This is safe, because the top level table is not active and not referenced anymore.
+        unsafe { FRAME_ALLOCATOR.lock().deallocate(self.root) };
+    }
+}
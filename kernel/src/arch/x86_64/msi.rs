@@ -0,0 +1,680 @@
+//! Delivers the interrupts of PCI functions as messages (MSI and MSI-X).
+//!
+//! Instead of raising a line that is routed through the I/O APIC, a function writes a message to an address
+//! that names the local APIC of a processor, with the vector of the interrupt as the data.
+//! The vectors from `MSI_BASE_VECTOR` on are handed out separately for each processor,
+//! so every interrupt of a function can get its own vector on the processor that should handle it.
+//!
+//! MSI-X keeps a message for each interrupt in a table in one of the BARs of the function,
+//! so each interrupt can target another processor and be masked on its own.
+//! MSI uses a single message for up to 32 interrupts, which are told apart by the lowest bits of the vector.
+//! Its vectors are therefore allocated as an aligned block and always move between processors together.
+//! MSI-X is preferred if a function supports both.
+//!
+//! Like interrupt lines, the interrupts are handled by a function in the kernel or signal a notification.
+//! No acknowledgement is needed, because messages are never shared between functions.
+
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use core::{fmt, mem};
+use lazy_static::lazy_static;
+
+use super::{
+    apic::{self, MSI_BASE_VECTOR, MSI_VECTOR_COUNT},
+    pci::{
+        capability::{Msi, MsiX},
+        Address, Bar, Command, Device,
+    },
+};
+use crate::{
+    arch::{Arch, Architecture},
+    ipc::Notification,
+    memory::VirtualAddress,
+    sync::Mutex,
+};
+
+/// The address that messages to the local APICs are written to.
+const MESSAGE_ADDRESS: u64 = 0xfee0_0000;
+
+/// The position of the local APIC ID of the target in the message address.
+const MESSAGE_DESTINATION_SHIFT: u64 = 12;
+
+/// The maximum number of interrupts a function can use with MSI.
+const MAXIMUM_MSI_COUNT: usize = 32;
+
+/// The offset of the message control register in the MSI and MSI-X capabilities.
+const MESSAGE_CONTROL: u16 = 2;
+
+/// The offset of the message address register in the MSI capability.
+const MSI_ADDRESS: u16 = 4;
+
+/// The offset of the upper half of the message address in an MSI capability with 64 bit addresses.
+const MSI_UPPER_ADDRESS: u16 = 8;
+
+/// The offset of the message data register in an MSI capability with 32 bit addresses.
+const MSI_DATA_32: u16 = 8;
+
+/// The offset of the message data register in an MSI capability with 64 bit addresses.
+const MSI_DATA_64: u16 = 12;
+
+/// The offset of the mask bits register in an MSI capability with 32 bit addresses.
+const MSI_MASK_32: u16 = 12;
+
+/// The offset of the mask bits register in an MSI capability with 64 bit addresses.
+const MSI_MASK_64: u16 = 16;
+
+/// The bit in the message control register that enables MSI.
+const MSI_ENABLE: u16 = 1 << 0;
+
+/// The position of the field that sets the number of enabled MSI interrupts as a power of two.
+const MSI_MULTIPLE_MESSAGE_SHIFT: u16 = 4;
+
+/// The field that sets the number of enabled MSI interrupts.
+const MSI_MULTIPLE_MESSAGE_MASK: u16 = 0x7 << MSI_MULTIPLE_MESSAGE_SHIFT;
+
+/// The bit in the message control register that enables MSI-X.
+const MSI_X_ENABLE: u16 = 1 << 15;
+
+/// The bit in the message control register that masks all MSI-X interrupts.
+const MSI_X_FUNCTION_MASK: u16 = 1 << 14;
+
+/// The length of an entry in the MSI-X table.
+const MSI_X_ENTRY_LENGTH: usize = 16;
+
+/// The offset of the lower half of the message address in an MSI-X table entry.
+const MSI_X_ENTRY_ADDRESS: usize = 0;
+
+/// The offset of the upper half of the message address in an MSI-X table entry.
+const MSI_X_ENTRY_UPPER_ADDRESS: usize = 4;
+
+/// The offset of the message data in an MSI-X table entry.
+const MSI_X_ENTRY_DATA: usize = 8;
+
+/// The offset of the vector control register in an MSI-X table entry.
+const MSI_X_ENTRY_CONTROL: usize = 12;
+
+/// The bit in the vector control register that masks the interrupt.
+const MSI_X_ENTRY_MASKED: u32 = 1 << 0;
+
+lazy_static! {
+    /// The handler of each allocated vector, by the processor and the number of the vector.
+    ///
+    /// Allocated vectors without a handler are kept as `None`.
+    static ref VECTORS: Mutex<BTreeMap<(usize, usize), Option<Handler>>> = Mutex::new(BTreeMap::new());
+
+    /// The functions whose message signaled interrupts are enabled.
+    static ref ENABLED_DEVICES: Mutex<Vec<Address>> = Mutex::new(Vec::new());
+}
+
+/// A function in the kernel that handles a message signaled interrupt, given the index it was set for.
+///
+/// It runs with interrupts disabled and must not block.
+pub type KernelHandler = fn(usize);
+
+/// Receives the interrupts on a vector.
+#[derive(Clone)]
+enum Handler {
+    /// The notification is signalled with the badge.
+    Notification(Arc<Notification>, u64),
+    /// The function in the kernel is called with the index.
+    Kernel(KernelHandler, usize),
+}
+
+/// The errors that can occur while setting up message signaled interrupts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MsiError {
+    /// The function supports neither MSI nor MSI-X.
+    Unsupported,
+    /// The message signaled interrupts of the function are already enabled.
+    AlreadyEnabled,
+    /// The function cannot use this number of interrupts.
+    InvalidCount,
+    /// The processor has no free vectors left.
+    NoFreeVectors,
+    /// There is no running processor with the local APIC ID.
+    UnknownProcessor,
+    /// The function has no interrupt with the index.
+    InvalidIndex,
+    /// The MSI-X table does not lie in a memory BAR of the function.
+    InvalidTable,
+    /// The interrupts of the function cannot be masked individually.
+    MaskingUnsupported,
+}
+
+impl fmt::Display for MsiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MsiError::Unsupported => write!(f, "message signaled interrupts are not supported"),
+            MsiError::AlreadyEnabled => {
+                write!(f, "message signaled interrupts are already enabled")
+            }
+            MsiError::InvalidCount => write!(f, "the number of interrupts is not supported"),
+            MsiError::NoFreeVectors => write!(f, "there are no free vectors"),
+            MsiError::UnknownProcessor => write!(f, "there is no such running processor"),
+            MsiError::InvalidIndex => write!(f, "there is no interrupt with this index"),
+            MsiError::InvalidTable => write!(f, "the MSI-X table is not in a memory BAR"),
+            MsiError::MaskingUnsupported => write!(f, "the interrupts cannot be masked"),
+        }
+    }
+}
+
+/// Returns the local APIC IDs of the processors that interrupts can target.
+///
+/// Only running processors can handle interrupts, which is just the boot processor,
+/// since the application processors are not started.
+pub fn processors() -> &'static [usize] {
+    apic::online_processors()
+}
+
+/// A vector of a processor that is allocated for a message signaled interrupt.
+///
+/// The vector is freed again when dropped.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Vector {
+    /// The local APIC ID of the processor.
+    cpu: usize,
+    /// The number of the vector.
+    number: usize,
+}
+
+impl Vector {
+    /// Allocates a free vector on the processor with the local APIC ID.
+    pub fn allocate(cpu: usize) -> Result<Vector, MsiError> {
+        Vector::allocate_block(cpu, 1).map(|mut vectors| vectors.remove(0))
+    }
+
+    /// Allocates consecutive free vectors on the processor with the local APIC ID.
+    ///
+    /// The count must be a power of two and the first vector is aligned to it, as MSI requires.
+    pub fn allocate_block(cpu: usize, count: usize) -> Result<Vec<Vector>, MsiError> {
+        if count == 0 || !count.is_power_of_two() || count > MSI_VECTOR_COUNT {
+            return Err(MsiError::InvalidCount);
+        }
+
+        if !processors().contains(&cpu) {
+            return Err(MsiError::UnknownProcessor);
+        }
+
+        let mut vectors = VECTORS.lock();
+
+        // `MSI_BASE_VECTOR` is aligned to the largest block, so aligning the index aligns the vector.
+        let first = (MSI_BASE_VECTOR..MSI_BASE_VECTOR + MSI_VECTOR_COUNT)
+            .step_by(count)
+            .find(|&first| {
+                (first..first + count).all(|number| !vectors.contains_key(&(cpu, number)))
+            })
+            .ok_or(MsiError::NoFreeVectors)?;
+
+        for number in first..first + count {
+            vectors.insert((cpu, number), None);
+        }
+
+        Ok((first..first + count)
+            .map(|number| Vector { cpu, number })
+            .collect())
+    }
+
+    /// Returns the local APIC ID of the processor that the vector belongs to.
+    pub fn cpu(&self) -> usize {
+        self.cpu
+    }
+
+    /// Returns the number of the vector.
+    pub fn number(&self) -> usize {
+        self.number
+    }
+
+    /// Returns the address and the data of the message that raises an interrupt on the vector.
+    pub fn message(&self) -> (u64, u32) {
+        (
+            MESSAGE_ADDRESS | (self.cpu as u64) << MESSAGE_DESTINATION_SHIFT,
+            self.number as u32,
+        )
+    }
+
+    /// Sets the notification that is signalled with the badge on an interrupt.
+    pub fn set_notification(&self, notification: Option<(Arc<Notification>, u64)>) {
+        self.set_handler(
+            notification.map(|(notification, badge)| Handler::Notification(notification, badge)),
+        );
+    }
+
+    /// Sets the function in the kernel that handles the interrupts, which is called with the index.
+    pub fn set_kernel_handler(&self, handler: Option<KernelHandler>, index: usize) {
+        self.set_handler(handler.map(|handler| Handler::Kernel(handler, index)));
+    }
+
+    /// Returns the handler of the vector.
+    fn handler(&self) -> Option<Handler> {
+        VECTORS
+            .lock()
+            .get(&(self.cpu, self.number))
+            .cloned()
+            .unwrap_or(None)
+    }
+
+    /// Sets the handler of the vector.
+    fn set_handler(&self, handler: Option<Handler>) {
+        let previous = {
+            let mut vectors = VECTORS.lock();
+
+            vectors
+                .get_mut(&(self.cpu, self.number))
+                .map(|entry| mem::replace(entry, handler))
+        };
+
+        // The previous notification may be freed here, which is done outside of the lock.
+        drop(previous);
+    }
+}
+
+impl Drop for Vector {
+    fn drop(&mut self) {
+        let previous = VECTORS.lock().remove(&(self.cpu, self.number));
+
+        // The notification may be freed here, which is done outside of the lock.
+        drop(previous);
+    }
+}
+
+/// Handles a message signaled interrupt on the vector of the current processor.
+///
+/// This signals the notification set for the vector or calls its function in the kernel.
+pub(super) fn handle(vector: usize) {
+    let handler = VECTORS
+        .lock()
+        .get(&(Arch::current_cpu(), vector))
+        .cloned()
+        .unwrap_or(None);
+
+    match handler {
+        Some(Handler::Notification(notification, badge)) => notification.signal(badge),
+        Some(Handler::Kernel(function, index)) => function(index),
+        None => log::warn!(
+            "Received a spurious message signaled interrupt on vector {:#x}.",
+            vector
+        ),
+    }
+}
+
+/// The way the interrupts of a function are delivered.
+enum Mode {
+    /// With MSI.
+    Msi(Msi),
+    /// With MSI-X, using the table at the virtual address.
+    MsiX(MsiX, VirtualAddress),
+}
+
+/// The message signaled interrupts of a PCI function.
+///
+/// They are disabled again when this is dropped.
+pub struct Interrupts {
+    /// The function that raises the interrupts.
+    device: &'static Device,
+    /// The way the interrupts are delivered.
+    mode: Mode,
+    /// The vector of each interrupt.
+    vectors: Vec<Vector>,
+}
+
+impl Interrupts {
+    /// Enables `count` message signaled interrupts of the function, targeting the current processor.
+    ///
+    /// MSI-X is used if the function supports it, otherwise MSI is used, which may enable more interrupts,
+    /// because their number is rounded up to a power of two.
+    /// The interrupts have no handlers yet.
+    pub fn enable(device: &'static Device, count: usize) -> Result<Interrupts, MsiError> {
+        let mut enabled_devices = ENABLED_DEVICES.lock();
+
+        if enabled_devices.contains(&device.address()) {
+            return Err(MsiError::AlreadyEnabled);
+        }
+
+        let interrupts = if let Some(capability) = device.msi_x() {
+            Interrupts::enable_msi_x(device, capability, count)?
+        } else if let Some(capability) = device.msi() {
+            Interrupts::enable_msi(device, capability, count)?
+        } else {
+            return Err(MsiError::Unsupported);
+        };
+
+        enabled_devices.push(device.address());
+
+        Ok(interrupts)
+    }
+
+    /// Enables the interrupts with MSI.
+    fn enable_msi(
+        device: &'static Device,
+        capability: Msi,
+        count: usize,
+    ) -> Result<Interrupts, MsiError> {
+        if count == 0 || count > capability.vector_count().min(MAXIMUM_MSI_COUNT) {
+            return Err(MsiError::InvalidCount);
+        }
+
+        let count = count.next_power_of_two();
+        let vectors = Vector::allocate_block(Arch::current_cpu(), count)?;
+
+        device.set_command(device.command() | Command::BUS_MASTER | Command::INTERRUPT_DISABLE);
+
+        let interrupts = Interrupts {
+            device,
+            mode: Mode::Msi(capability),
+            vectors,
+        };
+
+        if capability.per_vector_masking() {
+            device.write_u32(interrupts.msi_mask_register(capability), 0);
+        }
+
+        interrupts.write_msi_message(capability);
+
+        let control = device.read_u16(capability.offset() + MESSAGE_CONTROL);
+        device.write_u16(
+            capability.offset() + MESSAGE_CONTROL,
+            control & !MSI_MULTIPLE_MESSAGE_MASK
+                | (count.trailing_zeros() as u16) << MSI_MULTIPLE_MESSAGE_SHIFT
+                | MSI_ENABLE,
+        );
+
+        Ok(interrupts)
+    }
+
+    /// Enables the interrupts with MSI-X.
+    fn enable_msi_x(
+        device: &'static Device,
+        capability: MsiX,
+        count: usize,
+    ) -> Result<Interrupts, MsiError> {
+        if count == 0 || count > capability.table_size() {
+            return Err(MsiError::InvalidCount);
+        }
+
+        let table_length = capability.table_size() * MSI_X_ENTRY_LENGTH;
+        let table = match device.bar(capability.table_bar()) {
+            Some(Bar::Memory { address, size, .. })
+                if capability.table_offset() + table_length <= size =>
+            {
+                Arch::physical_to_virtual(address) + capability.table_offset()
+            }
+            _ => return Err(MsiError::InvalidTable),
+        };
+
+        let vectors = (0..count)
+            .map(|_| Vector::allocate(Arch::current_cpu()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        device.set_command(
+            device.command()
+                | Command::MEMORY_SPACE
+                | Command::BUS_MASTER
+                | Command::INTERRUPT_DISABLE,
+        );
+
+        // The table may only be written while MSI-X is enabled, so all interrupts are masked meanwhile.
+        let control = device.read_u16(capability.offset() + MESSAGE_CONTROL);
+        device.write_u16(
+            capability.offset() + MESSAGE_CONTROL,
+            control | MSI_X_ENABLE | MSI_X_FUNCTION_MASK,
+        );
+
+        let interrupts = Interrupts {
+            device,
+            mode: Mode::MsiX(capability, table),
+            vectors,
+        };
+
+        for index in 0..capability.table_size() {
+            match interrupts.vectors.get(index) {
+                Some(vector) => interrupts.write_msi_x_entry(table, index, vector.message(), false),
+                None => interrupts.set_msi_x_masked(table, index, true),
+            }
+        }
+
+        device.write_u16(
+            capability.offset() + MESSAGE_CONTROL,
+            (control | MSI_X_ENABLE) & !MSI_X_FUNCTION_MASK,
+        );
+
+        Ok(interrupts)
+    }
+
+    /// Returns the function that raises the interrupts.
+    pub fn device(&self) -> &'static Device {
+        self.device
+    }
+
+    /// Checks if the interrupts are delivered with MSI-X.
+    pub fn is_msi_x(&self) -> bool {
+        match self.mode {
+            Mode::MsiX(..) => true,
+            Mode::Msi(_) => false,
+        }
+    }
+
+    /// Returns the number of enabled interrupts.
+    pub fn count(&self) -> usize {
+        self.vectors.len()
+    }
+
+    /// Returns the vectors of the interrupts, in the order of their indices.
+    pub fn vectors(&self) -> &[Vector] {
+        &self.vectors
+    }
+
+    /// Returns the vector of the interrupt with the index.
+    fn vector(&self, index: usize) -> Result<&Vector, MsiError> {
+        self.vectors.get(index).ok_or(MsiError::InvalidIndex)
+    }
+
+    /// Sets the notification that is signalled with the badge on the interrupt with the index.
+    pub fn set_notification(
+        &self,
+        index: usize,
+        notification: Option<(Arc<Notification>, u64)>,
+    ) -> Result<(), MsiError> {
+        self.vector(index)?.set_notification(notification);
+
+        Ok(())
+    }
+
+    /// Sets the function in the kernel that handles the interrupt with the index.
+    ///
+    /// The function is called with the index.
+    pub fn set_kernel_handler(
+        &self,
+        index: usize,
+        handler: Option<KernelHandler>,
+    ) -> Result<(), MsiError> {
+        self.vector(index)?.set_kernel_handler(handler, index);
+
+        Ok(())
+    }
+
+    /// Moves the interrupt with the index to the running processor with the local APIC ID, keeping its handler.
+    ///
+    /// With MSI all interrupts of the function move together.
+    pub fn set_affinity(&mut self, index: usize, cpu: usize) -> Result<(), MsiError> {
+        if self.vector(index)?.cpu() == cpu {
+            return Ok(());
+        }
+
+        match self.mode {
+            Mode::MsiX(_, table) => {
+                let vector = Vector::allocate(cpu)?;
+                vector.set_handler(self.vectors[index].handler());
+
+                let masked = self.is_msi_x_masked(table, index);
+                self.write_msi_x_entry(table, index, vector.message(), masked);
+
+                self.vectors[index] = vector;
+            }
+            Mode::Msi(capability) => {
+                let vectors = Vector::allocate_block(cpu, self.vectors.len())?;
+
+                for (old, new) in self.vectors.iter().zip(&vectors) {
+                    new.set_handler(old.handler());
+                }
+
+                let offset = capability.offset() + MESSAGE_CONTROL;
+                let control = self.device.read_u16(offset);
+
+                // The message is not written atomically, so MSI is disabled meanwhile.
+                self.device.write_u16(offset, control & !MSI_ENABLE);
+                self.vectors = vectors;
+                self.write_msi_message(capability);
+                self.device.write_u16(offset, control);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Masks the interrupt with the index, so that it is held back by the function.
+    pub fn mask(&self, index: usize) -> Result<(), MsiError> {
+        self.set_masked(index, true)
+    }
+
+    /// Unmasks the interrupt with the index, so that it is delivered again.
+    pub fn unmask(&self, index: usize) -> Result<(), MsiError> {
+        self.set_masked(index, false)
+    }
+
+    /// Sets whether the interrupt with the index is masked.
+    fn set_masked(&self, index: usize, masked: bool) -> Result<(), MsiError> {
+        self.vector(index)?;
+
+        match self.mode {
+            Mode::MsiX(_, table) => self.set_msi_x_masked(table, index, masked),
+            Mode::Msi(capability) => {
+                if !capability.per_vector_masking() {
+                    return Err(MsiError::MaskingUnsupported);
+                }
+
+                let register = self.msi_mask_register(capability);
+                let mask = self.device.read_u32(register);
+
+                self.device.write_u32(
+                    register,
+                    if masked {
+                        mask | 1 << index
+                    } else {
+                        mask & !(1 << index)
+                    },
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the offset of the mask bits register of the MSI capability.
+    fn msi_mask_register(&self, capability: Msi) -> u16 {
+        capability.offset()
+            + if capability.is_64_bit() {
+                MSI_MASK_64
+            } else {
+                MSI_MASK_32
+            }
+    }
+
+    /// Writes the message of the first vector to the MSI capability.
+    fn write_msi_message(&self, capability: Msi) {
+        let (address, data) = self.vectors[0].message();
+        let offset = capability.offset();
+
+        self.device.write_u32(offset + MSI_ADDRESS, address as u32);
+
+        if capability.is_64_bit() {
+            self.device
+                .write_u32(offset + MSI_UPPER_ADDRESS, (address >> 32) as u32);
+            self.device.write_u16(offset + MSI_DATA_64, data as u16);
+        } else {
+            self.device.write_u16(offset + MSI_DATA_32, data as u16);
+        }
+    }
+
+    /// Returns a pointer to the register at the offset in the MSI-X table entry with the index.
+    fn msi_x_register(&self, table: VirtualAddress, index: usize, offset: usize) -> *mut u32 {
+        (table + index * MSI_X_ENTRY_LENGTH + offset).as_mut_ptr()
+    }
+
+    /// Checks if the MSI-X table entry with the index is masked.
+    fn is_msi_x_masked(&self, table: VirtualAddress, index: usize) -> bool {
+        let control = self.msi_x_register(table, index, MSI_X_ENTRY_CONTROL);
+
+        // This is safe, because the table lies in a memory BAR of the function.
+        unsafe { control.read_volatile() & MSI_X_ENTRY_MASKED != 0 }
+    }
+
+    /// Sets whether the MSI-X table entry with the index is masked.
+    fn set_msi_x_masked(&self, table: VirtualAddress, index: usize, masked: bool) {
+        let control = self.msi_x_register(table, index, MSI_X_ENTRY_CONTROL);
+
+        // This is safe, because the table lies in a memory BAR of the function.
+        unsafe {
+            let value = control.read_volatile();
+
+            control.write_volatile(if masked {
+                value | MSI_X_ENTRY_MASKED
+            } else {
+                value & !MSI_X_ENTRY_MASKED
+            });
+        }
+    }
+
+    /// Writes the message to the MSI-X table entry with the index.
+    ///
+    /// The entry is masked while it is written and stays masked if `masked` is set.
+    fn write_msi_x_entry(
+        &self,
+        table: VirtualAddress,
+        index: usize,
+        (address, data): (u64, u32),
+        masked: bool,
+    ) {
+        self.set_msi_x_masked(table, index, true);
+
+        // This is safe, because the table lies in a memory BAR of the function.
+        unsafe {
+            self.msi_x_register(table, index, MSI_X_ENTRY_ADDRESS)
+                .write_volatile(address as u32);
+            self.msi_x_register(table, index, MSI_X_ENTRY_UPPER_ADDRESS)
+                .write_volatile((address >> 32) as u32);
+            self.msi_x_register(table, index, MSI_X_ENTRY_DATA)
+                .write_volatile(data);
+        }
+
+        self.set_msi_x_masked(table, index, masked);
+    }
+}
+
+impl Drop for Interrupts {
+    fn drop(&mut self) {
+        match self.mode {
+            Mode::MsiX(capability, table) => {
+                for index in 0..self.vectors.len() {
+                    self.set_msi_x_masked(table, index, true);
+                }
+
+                let offset = capability.offset() + MESSAGE_CONTROL;
+                let control = self.device.read_u16(offset);
+
+                self.device.write_u16(offset, control & !MSI_X_ENABLE);
+            }
+            Mode::Msi(capability) => {
+                let offset = capability.offset() + MESSAGE_CONTROL;
+                let control = self.device.read_u16(offset);
+
+                self.device.write_u16(offset, control & !MSI_ENABLE);
+            }
+        }
+
+        self.device
+            .set_command(self.device.command() - Command::INTERRUPT_DISABLE);
+
+        ENABLED_DEVICES
+            .lock()
+            .retain(|&address| address != self.device.address());
+    }
+}
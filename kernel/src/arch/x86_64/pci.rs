@@ -0,0 +1,688 @@
+//! Finds the devices on the PCI buses.
+//!
+//! The buses are scanned once during boot, starting at the buses of the host bridges
+//! and descending through the bridges to the buses behind them.
+//! The bus numbers assigned by the firmware are kept.
+//! For each function the identifiers, the BARs and the capabilities are read,
+//! so that drivers can find their devices in the device list.
+//!
+//! The BARs are sized while the function doesn't decode its address ranges,
+//! so sizing doesn't disturb other devices.
+
+pub mod capability;
+pub mod configuration;
+
+use alloc::vec::Vec;
+use bitflags::bitflags;
+use core::{fmt, str::FromStr};
+
+use self::capability::{Capability, Msi, MsiX, PciExpress};
+use crate::{memory::PhysicalAddress, sync::GlobalRuntimeConfiguration};
+
+/// The offset of the vendor identifier in the configuration space.
+const VENDOR_ID: u16 = 0x00;
+
+/// The offset of the device identifier in the configuration space.
+const DEVICE_ID: u16 = 0x02;
+
+/// The offset of the command register in the configuration space.
+const COMMAND: u16 = 0x04;
+
+/// The offset of the status register in the configuration space.
+const STATUS: u16 = 0x06;
+
+/// The offset of the revision in the configuration space.
+const REVISION: u16 = 0x08;
+
+/// The offset of the programming interface in the configuration space.
+const INTERFACE: u16 = 0x09;
+
+/// The offset of the subclass in the configuration space.
+const SUBCLASS: u16 = 0x0a;
+
+/// The offset of the class in the configuration space.
+const CLASS: u16 = 0x0b;
+
+/// The offset of the header type in the configuration space.
+const HEADER_TYPE: u16 = 0x0e;
+
+/// The offset of the first BAR in the configuration space.
+const FIRST_BAR: u16 = 0x10;
+
+/// The offset of the number of the bus behind a bridge in the configuration space.
+const SECONDARY_BUS: u16 = 0x19;
+
+/// The offset of the capabilities pointer in the configuration space.
+const CAPABILITIES_POINTER: u16 = 0x34;
+
+/// The offset of the interrupt line in the configuration space.
+const INTERRUPT_LINE: u16 = 0x3c;
+
+/// The offset of the interrupt pin in the configuration space.
+const INTERRUPT_PIN: u16 = 0x3d;
+
+/// The vendor identifier read from functions that don't exist.
+const NO_VENDOR: u16 = 0xffff;
+
+/// The bit in the header type that shows that the device has more than one function.
+const MULTI_FUNCTION: u8 = 0x80;
+
+/// The bit in the status register that shows that the function has a capability list.
+const STATUS_CAPABILITIES: u16 = 1 << 4;
+
+/// The bit in a BAR that shows that it describes I/O ports.
+const BAR_IO: u32 = 1 << 0;
+
+/// The bits in a memory BAR that give its type.
+const BAR_MEMORY_TYPE: u32 = 0x3 << 1;
+
+/// The type of memory BARs that take up two BARs for a 64 bit address.
+const BAR_MEMORY_64_BIT: u32 = 0x2 << 1;
+
+/// The bit in a memory BAR that shows that the memory is prefetchable.
+const BAR_PREFETCHABLE: u32 = 1 << 3;
+
+/// The maximum number of BARs of a function.
+pub const MAXIMUM_BAR_COUNT: usize = 6;
+
+/// The number of devices on a bus.
+const DEVICES_PER_BUS: u8 = 32;
+
+/// The number of functions of a device.
+const FUNCTIONS_PER_DEVICE: u8 = 8;
+
+/// The list of devices, once the buses were scanned.
+static DEVICES: GlobalRuntimeConfiguration<Vec<Device>> = GlobalRuntimeConfiguration::new();
+
+/// The errors that can occur when parsing a PCI address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PciError {
+    /// The address is not of the form `[segment:]bus:device.function`.
+    InvalidAddress,
+}
+
+impl fmt::Display for PciError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PciError::InvalidAddress => write!(f, "invalid PCI address"),
+        }
+    }
+}
+
+/// The address of a PCI function.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Address {
+    /// The segment of the bus.
+    segment: u16,
+    /// The bus of the device.
+    bus: u8,
+    /// The device on the bus, which is below 32.
+    device: u8,
+    /// The function of the device, which is below 8.
+    function: u8,
+}
+
+impl Address {
+    /// Creates the address of a function.
+    ///
+    /// The device must be below 32 and the function below 8.
+    pub const fn new(segment: u16, bus: u8, device: u8, function: u8) -> Address {
+        Address {
+            segment,
+            bus,
+            device,
+            function,
+        }
+    }
+
+    /// Returns the segment of the bus.
+    pub fn segment(self) -> u16 {
+        self.segment
+    }
+
+    /// Returns the bus of the device.
+    pub fn bus(self) -> u8 {
+        self.bus
+    }
+
+    /// Returns the device on the bus.
+    pub fn device(self) -> u8 {
+        self.device
+    }
+
+    /// Returns the function of the device.
+    pub fn function(self) -> u8 {
+        self.function
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04x}:{:02x}:{:02x}.{}",
+            self.segment, self.bus, self.device, self.function
+        )
+    }
+}
+
+impl FromStr for Address {
+    type Err = PciError;
+
+    /// Parses an address of the form `[segment:]bus:device.function` with hexadecimal numbers.
+    fn from_str(address: &str) -> Result<Address, PciError> {
+        let mut parts = address.rsplit(':');
+        let (device, function) = {
+            let mut slot = parts.next().ok_or(PciError::InvalidAddress)?.split('.');
+
+            (
+                slot.next().ok_or(PciError::InvalidAddress)?,
+                slot.next().ok_or(PciError::InvalidAddress)?,
+            )
+        };
+        let bus = parts.next().ok_or(PciError::InvalidAddress)?;
+        let segment = parts.next().unwrap_or("0");
+
+        if parts.next().is_some() {
+            return Err(PciError::InvalidAddress);
+        }
+
+        let parse = |number, limit| match u16::from_str_radix(number, 16) {
+            Ok(number) if number <= limit => Ok(number),
+            _ => Err(PciError::InvalidAddress),
+        };
+
+        Ok(Address::new(
+            parse(segment, 0xffff)?,
+            parse(bus, 0xff)? as u8,
+            parse(device, DEVICES_PER_BUS as u16 - 1)? as u8,
+            parse(function, FUNCTIONS_PER_DEVICE as u16 - 1)? as u8,
+        ))
+    }
+}
+
+/// A range of addresses decoded by a function, described by a base address register (BAR).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bar {
+    /// A range of physical memory.
+    Memory {
+        /// The physical address of the range.
+        address: PhysicalAddress,
+        /// The size of the range in bytes.
+        size: usize,
+        /// Whether reads have no side effects, so that the memory may be prefetched.
+        prefetchable: bool,
+        /// Whether the address takes up two BARs.
+        is_64_bit: bool,
+    },
+    /// A range of I/O ports.
+    Io {
+        /// The first port of the range.
+        port: u16,
+        /// The number of ports in the range.
+        size: u16,
+    },
+}
+
+bitflags! {
+    /// The bits of the command register that control how a function takes part in the bus.
+    pub struct Command: u16 {
+        /// The function decodes its I/O port ranges.
+        const IO_SPACE = 1 << 0;
+        /// The function decodes its memory ranges.
+        const MEMORY_SPACE = 1 << 1;
+        /// The function may access memory on its own, which includes sending MSI messages.
+        const BUS_MASTER = 1 << 2;
+        /// The function doesn't raise its interrupt pin.
+        const INTERRUPT_DISABLE = 1 << 10;
+    }
+}
+
+/// Describes which devices a driver handles.
+///
+/// Fields that are `None` match all devices.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DeviceMatch {
+    /// The vendor identifier of the devices.
+    pub vendor_id: Option<u16>,
+    /// The device identifier of the devices.
+    pub device_id: Option<u16>,
+    /// The class of the devices.
+    pub class: Option<u8>,
+    /// The subclass of the devices.
+    pub subclass: Option<u8>,
+    /// The programming interface of the devices.
+    pub interface: Option<u8>,
+}
+
+impl DeviceMatch {
+    /// Matches the devices with the vendor and device identifier.
+    pub const fn id(vendor_id: u16, device_id: u16) -> DeviceMatch {
+        DeviceMatch {
+            vendor_id: Some(vendor_id),
+            device_id: Some(device_id),
+            class: None,
+            subclass: None,
+            interface: None,
+        }
+    }
+
+    /// Matches the devices of the class and subclass.
+    pub const fn class(class: u8, subclass: u8) -> DeviceMatch {
+        DeviceMatch {
+            vendor_id: None,
+            device_id: None,
+            class: Some(class),
+            subclass: Some(subclass),
+            interface: None,
+        }
+    }
+}
+
+/// A PCI function found while scanning the buses.
+#[derive(Debug)]
+pub struct Device {
+    /// The address of the function.
+    address: Address,
+    /// The vendor identifier.
+    vendor_id: u16,
+    /// The device identifier.
+    device_id: u16,
+    /// The revision of the device.
+    revision: u8,
+    /// The class of the device.
+    class: u8,
+    /// The subclass of the device.
+    subclass: u8,
+    /// The programming interface of the device.
+    interface: u8,
+    /// The layout of the configuration space, without the multi function bit.
+    header_type: u8,
+    /// The BARs, where the upper half of a 64 bit BAR is `None`.
+    bars: [Option<Bar>; MAXIMUM_BAR_COUNT],
+    /// The capabilities of the function.
+    capabilities: Vec<Capability>,
+    /// The bus behind the function, if it is a bridge.
+    secondary_bus: Option<u8>,
+}
+
+impl Device {
+    /// Reads the function at the address, if it exists.
+    fn read(address: Address) -> Option<Device> {
+        let vendor_id = configuration::read_u16(address, VENDOR_ID);
+
+        if vendor_id == NO_VENDOR {
+            return None;
+        }
+
+        let header_type = configuration::read_u8(address, HEADER_TYPE) & !MULTI_FUNCTION;
+        let (bar_count, secondary_bus) = match header_type {
+            0 => (MAXIMUM_BAR_COUNT, None),
+            1 => (2, Some(configuration::read_u8(address, SECONDARY_BUS))),
+            _ => (0, None),
+        };
+        let capabilities = if header_type <= 1
+            && configuration::read_u16(address, STATUS) & STATUS_CAPABILITIES != 0
+        {
+            capability::read_list(
+                address,
+                configuration::read_u8(address, CAPABILITIES_POINTER),
+            )
+        } else {
+            Vec::new()
+        };
+
+        Some(Device {
+            address,
+            vendor_id,
+            device_id: configuration::read_u16(address, DEVICE_ID),
+            revision: configuration::read_u8(address, REVISION),
+            class: configuration::read_u8(address, CLASS),
+            subclass: configuration::read_u8(address, SUBCLASS),
+            interface: configuration::read_u8(address, INTERFACE),
+            header_type,
+            bars: read_bars(address, bar_count),
+            capabilities,
+            secondary_bus,
+        })
+    }
+
+    /// Returns the address of the function.
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// Returns the vendor identifier.
+    pub fn vendor_id(&self) -> u16 {
+        self.vendor_id
+    }
+
+    /// Returns the device identifier.
+    pub fn device_id(&self) -> u16 {
+        self.device_id
+    }
+
+    /// Returns the revision of the device.
+    pub fn revision(&self) -> u8 {
+        self.revision
+    }
+
+    /// Returns the class of the device.
+    pub fn class(&self) -> u8 {
+        self.class
+    }
+
+    /// Returns the subclass of the device.
+    pub fn subclass(&self) -> u8 {
+        self.subclass
+    }
+
+    /// Returns the programming interface of the device.
+    pub fn interface(&self) -> u8 {
+        self.interface
+    }
+
+    /// Returns the layout of the configuration space, which is 0 for devices, 1 for bridges and 2 for CardBus bridges.
+    pub fn header_type(&self) -> u8 {
+        self.header_type
+    }
+
+    /// Returns the BARs of the function, where the upper half of a 64 bit BAR is `None`.
+    pub fn bars(&self) -> &[Option<Bar>; MAXIMUM_BAR_COUNT] {
+        &self.bars
+    }
+
+    /// Returns the BAR with the index, if it is implemented.
+    pub fn bar(&self, index: usize) -> Option<Bar> {
+        self.bars.get(index).cloned().unwrap_or(None)
+    }
+
+    /// Returns the capabilities of the function.
+    pub fn capabilities(&self) -> &[Capability] {
+        &self.capabilities
+    }
+
+    /// Returns the MSI capability, if the function has one.
+    pub fn msi(&self) -> Option<Msi> {
+        self.capabilities
+            .iter()
+            .find_map(|capability| match capability {
+                Capability::Msi(msi) => Some(*msi),
+                _ => None,
+            })
+    }
+
+    /// Returns the MSI-X capability, if the function has one.
+    pub fn msi_x(&self) -> Option<MsiX> {
+        self.capabilities
+            .iter()
+            .find_map(|capability| match capability {
+                Capability::MsiX(msi_x) => Some(*msi_x),
+                _ => None,
+            })
+    }
+
+    /// Returns the PCI Express capability, if the function is a PCI Express function.
+    pub fn pci_express(&self) -> Option<PciExpress> {
+        self.capabilities
+            .iter()
+            .find_map(|capability| match capability {
+                Capability::PciExpress(pci_express) => Some(*pci_express),
+                _ => None,
+            })
+    }
+
+    /// Returns the bus behind the function, if it is a bridge.
+    pub fn secondary_bus(&self) -> Option<u8> {
+        self.secondary_bus
+    }
+
+    /// Returns the interrupt pin used by the function, where 1 to 4 stand for `INTA#` to `INTD#`.
+    pub fn interrupt_pin(&self) -> Option<u8> {
+        match self.read_u8(INTERRUPT_PIN) {
+            0 => None,
+            pin => Some(pin),
+        }
+    }
+
+    /// Returns the interrupt line the firmware routed the interrupt pin to.
+    pub fn interrupt_line(&self) -> u8 {
+        self.read_u8(INTERRUPT_LINE)
+    }
+
+    /// Checks if the device matches the description.
+    pub fn matches(&self, pattern: &DeviceMatch) -> bool {
+        pattern.vendor_id.map_or(true, |id| id == self.vendor_id)
+            && pattern.device_id.map_or(true, |id| id == self.device_id)
+            && pattern.class.map_or(true, |class| class == self.class)
+            && pattern
+                .subclass
+                .map_or(true, |class| class == self.subclass)
+            && pattern
+                .interface
+                .map_or(true, |interface| interface == self.interface)
+    }
+
+    /// Returns the bits of the command register.
+    pub fn command(&self) -> Command {
+        Command::from_bits_truncate(self.read_u16(COMMAND))
+    }
+
+    /// Sets the bits of the command register, keeping the other bits.
+    pub fn set_command(&self, command: Command) {
+        let value = self.read_u16(COMMAND) & !Command::all().bits() | command.bits();
+
+        self.write_u16(COMMAND, value);
+    }
+
+    /// Reads the byte at the offset in the configuration space of the function.
+    pub fn read_u8(&self, offset: u16) -> u8 {
+        configuration::read_u8(self.address, offset)
+    }
+
+    /// Reads the 16 bit word at the offset in the configuration space of the function.
+    pub fn read_u16(&self, offset: u16) -> u16 {
+        configuration::read_u16(self.address, offset)
+    }
+
+    /// Reads the 32 bit word at the offset in the configuration space of the function.
+    pub fn read_u32(&self, offset: u16) -> u32 {
+        configuration::read_u32(self.address, offset)
+    }
+
+    /// Writes the byte at the offset in the configuration space of the function.
+    pub fn write_u8(&self, offset: u16, value: u8) {
+        configuration::write_u8(self.address, offset, value);
+    }
+
+    /// Writes the 16 bit word at the offset in the configuration space of the function.
+    pub fn write_u16(&self, offset: u16, value: u16) {
+        configuration::write_u16(self.address, offset, value);
+    }
+
+    /// Writes the 32 bit word at the offset in the configuration space of the function.
+    pub fn write_u32(&self, offset: u16, value: u32) {
+        configuration::write_u32(self.address, offset, value);
+    }
+}
+
+/// Returns the bits of the BAR that can be changed, restoring its value afterwards.
+fn bar_mask(address: Address, offset: u16) -> u32 {
+    let value = configuration::read_u32(address, offset);
+
+    configuration::write_u32(address, offset, !0);
+    let mask = configuration::read_u32(address, offset);
+    configuration::write_u32(address, offset, value);
+
+    mask
+}
+
+/// Reads and sizes the given number of BARs of the function.
+fn read_bars(address: Address, count: usize) -> [Option<Bar>; MAXIMUM_BAR_COUNT] {
+    let mut bars = [None; MAXIMUM_BAR_COUNT];
+
+    if count == 0 {
+        return bars;
+    }
+
+    let command = configuration::read_u16(address, COMMAND);
+    let decoding = (Command::IO_SPACE | Command::MEMORY_SPACE).bits();
+
+    configuration::write_u16(address, COMMAND, command & !decoding);
+
+    let mut index = 0;
+
+    while index < count {
+        let offset = FIRST_BAR + index as u16 * 4;
+        let value = configuration::read_u32(address, offset);
+
+        if value & BAR_IO != 0 {
+            let mask = bar_mask(address, offset) as u16 & !0x3;
+
+            if mask != 0 {
+                bars[index] = Some(Bar::Io {
+                    port: value as u16 & !0x3,
+                    size: (!mask).wrapping_add(1),
+                });
+            }
+
+            index += 1;
+            continue;
+        }
+
+        let is_64_bit = value & BAR_MEMORY_TYPE == BAR_MEMORY_64_BIT && index + 1 < count;
+        let (high_value, high_mask) = if is_64_bit {
+            (
+                configuration::read_u32(address, offset + 4),
+                bar_mask(address, offset + 4),
+            )
+        } else {
+            (0, !0)
+        };
+        let mask = (high_mask as u64) << 32 | (bar_mask(address, offset) & !0xf) as u64;
+
+        if mask as u32 != 0 {
+            bars[index] = Some(Bar::Memory {
+                address: PhysicalAddress::new(
+                    ((high_value as u64) << 32 | (value & !0xf) as u64) as usize,
+                ),
+                size: (!mask).wrapping_add(1) as usize,
+                prefetchable: value & BAR_PREFETCHABLE != 0,
+                is_64_bit,
+            });
+        }
+
+        index += if is_64_bit { 2 } else { 1 };
+    }
+
+    configuration::write_u16(address, COMMAND, command);
+
+    bars
+}
+
+/// Scans the devices on the bus and the buses behind their bridges.
+fn scan_bus(segment: u16, bus: u8, scanned: &mut [bool; 256], devices: &mut Vec<Device>) {
+    // Misconfigured bridges could lead back to a bus that was already scanned.
+    if scanned[bus as usize] {
+        return;
+    }
+
+    scanned[bus as usize] = true;
+
+    for device in 0..DEVICES_PER_BUS {
+        let first_function = Address::new(segment, bus, device, 0);
+
+        if configuration::read_u16(first_function, VENDOR_ID) == NO_VENDOR {
+            continue;
+        }
+
+        let function_count =
+            if configuration::read_u8(first_function, HEADER_TYPE) & MULTI_FUNCTION != 0 {
+                FUNCTIONS_PER_DEVICE
+            } else {
+                1
+            };
+
+        for function in 0..function_count {
+            if let Some(found) = Device::read(Address::new(segment, bus, device, function)) {
+                let secondary_bus = found.secondary_bus;
+
+                log::trace!(
+                    "Found the PCI function {} ({:04x}:{:04x}, class {:02x}.{:02x}.{:02x}).",
+                    found.address,
+                    found.vendor_id,
+                    found.device_id,
+                    found.class,
+                    found.subclass,
+                    found.interface
+                );
+
+                devices.push(found);
+
+                if let Some(secondary_bus) = secondary_bus.filter(|&secondary| secondary > bus) {
+                    scan_bus(segment, secondary_bus, scanned, devices);
+                }
+            }
+        }
+    }
+}
+
+/// Scans the buses of the segment, starting at the buses of its host bridges.
+fn scan_segment(segment: u16, start_bus: u8, devices: &mut Vec<Device>) {
+    let mut scanned = [false; 256];
+    let host_bridge = Address::new(segment, start_bus, 0, 0);
+
+    // Each function of a host bridge with more than one function is the host bridge of another bus.
+    if configuration::read_u8(host_bridge, HEADER_TYPE) & MULTI_FUNCTION != 0 {
+        for function in 0..FUNCTIONS_PER_DEVICE {
+            let address = Address::new(segment, start_bus, 0, function);
+
+            if configuration::read_u16(address, VENDOR_ID) != NO_VENDOR {
+                if let Some(bus) = start_bus.checked_add(function) {
+                    scan_bus(segment, bus, &mut scanned, devices);
+                }
+            }
+        }
+    } else {
+        scan_bus(segment, start_bus, &mut scanned, devices);
+    }
+}
+
+/// Chooses how the configuration space is accessed and scans the buses.
+///
+/// This must be called after the ACPI tables were found and the heap was set up.
+pub fn init() {
+    configuration::init();
+
+    let mut devices = Vec::new();
+    let regions = configuration::ecam_regions();
+
+    if regions.is_empty() {
+        scan_segment(0, 0, &mut devices);
+    } else {
+        for region in regions {
+            scan_segment(region.segment(), region.start_bus(), &mut devices);
+        }
+    }
+
+    log::debug!("Found {} PCI functions.", devices.len());
+
+    DEVICES.init(devices);
+}
+
+/// Returns the functions found while scanning the buses.
+pub fn devices() -> &'static [Device] {
+    DEVICES.get().map(|devices| &devices[..]).unwrap_or(&[])
+}
+
+/// Returns the function at the address, if it was found.
+pub fn get(address: Address) -> Option<&'static Device> {
+    devices().iter().find(|device| device.address == address)
+}
+
+/// Returns the functions that match the description.
+pub fn find(pattern: DeviceMatch) -> impl Iterator<Item = &'static Device> {
+    devices()
+        .iter()
+        .filter(move |device| device.matches(&pattern))
+}
@@ -0,0 +1,304 @@
+//! Reads the capability lists of PCI functions.
+//!
+//! Functions that set the capability bit in their status register link a list of capabilities
+//! from their capabilities pointer, each starting with its identifier and the offset of the next capability.
+//! The capabilities for MSI, MSI-X and PCI Express are decoded, all others are only recorded.
+
+use alloc::vec::Vec;
+
+use super::{configuration, Address};
+
+/// The identifier of the MSI capability.
+pub const MSI_ID: u8 = 0x05;
+
+/// The identifier of the PCI Express capability.
+pub const PCI_EXPRESS_ID: u8 = 0x10;
+
+/// The identifier of the MSI-X capability.
+pub const MSI_X_ID: u8 = 0x11;
+
+/// The maximum number of capabilities that fit into the configuration space after the header.
+///
+/// This ends lists that loop.
+const MAXIMUM_CAPABILITY_COUNT: usize = 48;
+
+/// The offset of the message control register in the MSI and MSI-X capabilities.
+const MESSAGE_CONTROL: u16 = 2;
+
+/// The bit in the message control register of MSI that shows that 64 bit addresses are supported.
+const MSI_64_BIT: u16 = 1 << 7;
+
+/// The bit in the message control register of MSI that shows that the vectors can be masked.
+const MSI_PER_VECTOR_MASKING: u16 = 1 << 8;
+
+/// The offset of the table offset register in the MSI-X capability.
+const MSI_X_TABLE: u16 = 4;
+
+/// The offset of the pending bit array offset register in the MSI-X capability.
+const MSI_X_PENDING_BITS: u16 = 8;
+
+/// The offset of the capabilities register in the PCI Express capability.
+const PCI_EXPRESS_CAPABILITIES: u16 = 2;
+
+/// A capability of a PCI function.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Capability {
+    /// The function can signal interrupts with message signaled interrupts.
+    Msi(Msi),
+    /// The function can signal interrupts with the extended message signaled interrupts.
+    MsiX(MsiX),
+    /// The function is a PCI Express function.
+    PciExpress(PciExpress),
+    /// A capability that is not decoded.
+    Other {
+        /// The identifier of the capability.
+        id: u8,
+        /// The offset of the capability in the configuration space.
+        offset: u16,
+    },
+}
+
+impl Capability {
+    /// Returns the identifier of the capability.
+    pub fn id(&self) -> u8 {
+        match self {
+            Capability::Msi(_) => MSI_ID,
+            Capability::MsiX(_) => MSI_X_ID,
+            Capability::PciExpress(_) => PCI_EXPRESS_ID,
+            Capability::Other { id, .. } => *id,
+        }
+    }
+
+    /// Returns the offset of the capability in the configuration space.
+    pub fn offset(&self) -> u16 {
+        match self {
+            Capability::Msi(msi) => msi.offset,
+            Capability::MsiX(msi_x) => msi_x.offset,
+            Capability::PciExpress(pci_express) => pci_express.offset,
+            Capability::Other { offset, .. } => *offset,
+        }
+    }
+
+    /// Returns the name of the capability.
+    pub fn name(&self) -> &'static str {
+        match self.id() {
+            0x01 => "power management",
+            0x03 => "VPD",
+            MSI_ID => "MSI",
+            0x09 => "vendor specific",
+            0x0d => "bridge subsystem ID",
+            PCI_EXPRESS_ID => "PCI Express",
+            MSI_X_ID => "MSI-X",
+            0x12 => "SATA",
+            0x13 => "advanced features",
+            _ => "unknown",
+        }
+    }
+}
+
+/// The MSI capability.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Msi {
+    /// The offset of the capability in the configuration space.
+    offset: u16,
+    /// Whether the function supports 64 bit message addresses.
+    is_64_bit: bool,
+    /// Whether the vectors can be masked individually.
+    per_vector_masking: bool,
+    /// The maximum number of vectors the function can use.
+    vector_count: usize,
+}
+
+impl Msi {
+    /// Returns the offset of the capability in the configuration space.
+    pub fn offset(&self) -> u16 {
+        self.offset
+    }
+
+    /// Checks if the function supports 64 bit message addresses.
+    pub fn is_64_bit(&self) -> bool {
+        self.is_64_bit
+    }
+
+    /// Checks if the vectors can be masked individually.
+    pub fn per_vector_masking(&self) -> bool {
+        self.per_vector_masking
+    }
+
+    /// Returns the maximum number of vectors the function can use, which is a power of two.
+    pub fn vector_count(&self) -> usize {
+        self.vector_count
+    }
+}
+
+/// The MSI-X capability.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MsiX {
+    /// The offset of the capability in the configuration space.
+    offset: u16,
+    /// The number of entries in the table.
+    table_size: usize,
+    /// The index of the BAR that contains the table.
+    table_bar: usize,
+    /// The offset of the table in the BAR.
+    table_offset: usize,
+    /// The index of the BAR that contains the pending bit array.
+    pending_bits_bar: usize,
+    /// The offset of the pending bit array in the BAR.
+    pending_bits_offset: usize,
+}
+
+impl MsiX {
+    /// Returns the offset of the capability in the configuration space.
+    pub fn offset(&self) -> u16 {
+        self.offset
+    }
+
+    /// Returns the number of entries in the table, which is the number of vectors the function can use.
+    pub fn table_size(&self) -> usize {
+        self.table_size
+    }
+
+    /// Returns the index of the BAR that contains the table.
+    pub fn table_bar(&self) -> usize {
+        self.table_bar
+    }
+
+    /// Returns the offset of the table in its BAR.
+    pub fn table_offset(&self) -> usize {
+        self.table_offset
+    }
+
+    /// Returns the index of the BAR that contains the pending bit array.
+    pub fn pending_bits_bar(&self) -> usize {
+        self.pending_bits_bar
+    }
+
+    /// Returns the offset of the pending bit array in its BAR.
+    pub fn pending_bits_offset(&self) -> usize {
+        self.pending_bits_offset
+    }
+}
+
+/// The kinds of PCI Express functions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PortType {
+    /// An endpoint.
+    Endpoint,
+    /// An endpoint that may use features that PCI Express deprecated.
+    LegacyEndpoint,
+    /// A root port of a root complex.
+    RootPort,
+    /// The upstream port of a switch.
+    UpstreamPort,
+    /// A downstream port of a switch.
+    DownstreamPort,
+    /// A bridge from PCI Express to PCI.
+    PciExpressToPciBridge,
+    /// A bridge from PCI to PCI Express.
+    PciToPciExpressBridge,
+    /// An endpoint that is part of the root complex.
+    RootComplexIntegratedEndpoint,
+    /// An event collector of the root complex.
+    RootComplexEventCollector,
+    /// A reserved kind.
+    Reserved(u8),
+}
+
+/// The PCI Express capability.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PciExpress {
+    /// The offset of the capability in the configuration space.
+    offset: u16,
+    /// The version of the capability.
+    version: u8,
+    /// The kind of the function.
+    port_type: PortType,
+}
+
+impl PciExpress {
+    /// Returns the offset of the capability in the configuration space.
+    pub fn offset(&self) -> u16 {
+        self.offset
+    }
+
+    /// Returns the version of the capability.
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Returns the kind of the function.
+    pub fn port_type(&self) -> PortType {
+        self.port_type
+    }
+}
+
+/// Decodes the capability with the identifier at the offset.
+fn read(address: Address, id: u8, offset: u16) -> Capability {
+    match id {
+        MSI_ID => {
+            let control = configuration::read_u16(address, offset + MESSAGE_CONTROL);
+
+            Capability::Msi(Msi {
+                offset,
+                is_64_bit: control & MSI_64_BIT != 0,
+                per_vector_masking: control & MSI_PER_VECTOR_MASKING != 0,
+                vector_count: 1 << ((control >> 1) & 0x7).min(5),
+            })
+        }
+        MSI_X_ID => {
+            let control = configuration::read_u16(address, offset + MESSAGE_CONTROL);
+            let table = configuration::read_u32(address, offset + MSI_X_TABLE);
+            let pending_bits = configuration::read_u32(address, offset + MSI_X_PENDING_BITS);
+
+            Capability::MsiX(MsiX {
+                offset,
+                table_size: (control & 0x7ff) as usize + 1,
+                table_bar: (table & 0x7) as usize,
+                table_offset: (table & !0x7) as usize,
+                pending_bits_bar: (pending_bits & 0x7) as usize,
+                pending_bits_offset: (pending_bits & !0x7) as usize,
+            })
+        }
+        PCI_EXPRESS_ID => {
+            let capabilities = configuration::read_u16(address, offset + PCI_EXPRESS_CAPABILITIES);
+            let port_type = match (capabilities >> 4) as u8 & 0xf {
+                0 => PortType::Endpoint,
+                1 => PortType::LegacyEndpoint,
+                4 => PortType::RootPort,
+                5 => PortType::UpstreamPort,
+                6 => PortType::DownstreamPort,
+                7 => PortType::PciExpressToPciBridge,
+                8 => PortType::PciToPciExpressBridge,
+                9 => PortType::RootComplexIntegratedEndpoint,
+                10 => PortType::RootComplexEventCollector,
+                other => PortType::Reserved(other),
+            };
+
+            Capability::PciExpress(PciExpress {
+                offset,
+                version: capabilities as u8 & 0xf,
+                port_type,
+            })
+        }
+        _ => Capability::Other { id, offset },
+    }
+}
+
+/// Reads the capability list starting at the pointer.
+pub(super) fn read_list(address: Address, pointer: u8) -> Vec<Capability> {
+    let mut capabilities = Vec::new();
+    // The lowest two bits of the pointers are reserved.
+    let mut offset = pointer & !0x3;
+
+    // The header of the configuration space ends at 0x40.
+    while offset >= 0x40 && capabilities.len() < MAXIMUM_CAPABILITY_COUNT {
+        let id = configuration::read_u8(address, offset as u16);
+
+        capabilities.push(read(address, id, offset as u16));
+
+        offset = configuration::read_u8(address, offset as u16 + 1) & !0x3;
+    }
+
+    capabilities
+}
@@ -0,0 +1,302 @@
+//! Accesses the configuration space of PCI functions.
+//!
+//! PCI Express maps the configuration space of all functions into memory (ECAM),
+//! whose regions the firmware lists in the ACPI MCFG table.
+//! Without the table, the configuration space of segment 0 is accessed through the I/O ports `0xcf8` and `0xcfc`,
+//! which only reach the first 256 bytes of each function.
+//!
+//! Like the hardware, reads of functions that cannot be reached return all ones and writes to them are dropped.
+
+use alloc::vec::Vec;
+use core::ptr;
+use x86_64_crate::instructions::port::{Port, PortReadWrite};
+
+use super::{super::acpi, Address};
+use crate::{
+    arch::{Arch, Architecture},
+    io_port::IoPortRange,
+    memory::PhysicalAddress,
+    sync::{GlobalRuntimeConfiguration, Mutex},
+};
+
+/// The I/O port that selects the register accessed through the data port.
+const ADDRESS_PORT: u16 = 0xcf8;
+
+/// The I/O port that accesses the selected register.
+const DATA_PORT: u16 = 0xcfc;
+
+/// The number of I/O ports used to access the configuration space.
+const PORT_COUNT: usize = 8;
+
+/// The bit in the address port that enables the access through the data port.
+const ENABLE: u32 = 1 << 31;
+
+/// The size of the configuration space that can be accessed through the I/O ports.
+pub const LEGACY_SIZE: u16 = 0x100;
+
+/// The size of the configuration space of a function in PCI Express.
+pub const EXTENDED_SIZE: u16 = 0x1000;
+
+/// The number of reserved bytes at the start of the MCFG table after the header.
+const MCFG_RESERVED_LENGTH: usize = 8;
+
+/// The length of an entry in the MCFG table.
+const MCFG_ENTRY_LENGTH: usize = 16;
+
+/// The way the configuration space is accessed, once it was chosen.
+static MECHANISM: GlobalRuntimeConfiguration<Mechanism> = GlobalRuntimeConfiguration::new();
+
+/// Keeps the address port selected until the data port was accessed.
+static PORT_LOCK: Mutex<()> = Mutex::new(());
+
+/// The ways the configuration space can be accessed.
+enum Mechanism {
+    /// Through the claimed I/O ports.
+    Ports(IoPortRange),
+    /// Through the memory regions listed in the MCFG table.
+    Ecam(Vec<EcamRegion>),
+}
+
+/// A region of memory that contains the configuration space of a range of buses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EcamRegion {
+    /// The physical address of the configuration space of the first bus.
+    base: PhysicalAddress,
+    /// The segment of the buses.
+    segment: u16,
+    /// The first bus in the region.
+    start_bus: u8,
+    /// The last bus in the region.
+    end_bus: u8,
+}
+
+impl EcamRegion {
+    /// Returns the physical address of the configuration space of the first bus.
+    pub fn base(&self) -> PhysicalAddress {
+        self.base
+    }
+
+    /// Returns the segment of the buses.
+    pub fn segment(&self) -> u16 {
+        self.segment
+    }
+
+    /// Returns the first bus in the region.
+    pub fn start_bus(&self) -> u8 {
+        self.start_bus
+    }
+
+    /// Returns the last bus in the region.
+    pub fn end_bus(&self) -> u8 {
+        self.end_bus
+    }
+
+    /// Returns the physical address of the register, if the function is in the region.
+    fn register(&self, address: Address, offset: u16) -> Option<PhysicalAddress> {
+        if address.segment() != self.segment
+            || address.bus() < self.start_bus
+            || address.bus() > self.end_bus
+        {
+            return None;
+        }
+
+        let offset = ((address.bus() - self.start_bus) as usize) << 20
+            | (address.device() as usize & 0x1f) << 15
+            | (address.function() as usize & 0x7) << 12
+            | offset as usize;
+
+        self.base.checked_add(offset)
+    }
+}
+
+/// Reads the regions listed in the data of the MCFG table.
+fn parse_mcfg(data: &[u8]) -> Vec<EcamRegion> {
+    data.get(MCFG_RESERVED_LENGTH..)
+        .unwrap_or(&[])
+        .chunks_exact(MCFG_ENTRY_LENGTH)
+        .map(|entry| {
+            let base = entry[..8]
+                .iter()
+                .rev()
+                .fold(0, |base, &byte| base << 8 | byte as usize);
+
+            EcamRegion {
+                base: PhysicalAddress::new(base),
+                segment: u16::from_le_bytes([entry[8], entry[9]]),
+                start_bus: entry[10],
+                end_bus: entry[11],
+            }
+        })
+        .collect()
+}
+
+/// Chooses how the configuration space is accessed.
+///
+/// ECAM is used if the firmware provides an MCFG table, otherwise the I/O ports are used.
+/// This must be called after the ACPI tables were found.
+pub fn init() {
+    let regions = acpi::find("MCFG")
+        .filter(|table| table.is_valid())
+        .map(|table| parse_mcfg(table.data()))
+        .unwrap_or_default();
+
+    if !regions.is_empty() {
+        for region in &regions {
+            log::debug!(
+                "Using ECAM at {:#x} for the PCI buses {:02x} to {:02x} of segment {:04x}.",
+                region.base.as_usize(),
+                region.start_bus,
+                region.end_bus,
+                region.segment
+            );
+        }
+
+        MECHANISM.init(Mechanism::Ecam(regions));
+    } else if let Some(ports) = IoPortRange::claim(ADDRESS_PORT, PORT_COUNT) {
+        log::debug!("Using the I/O ports to access the PCI configuration space.");
+
+        MECHANISM.init(Mechanism::Ports(ports));
+    } else {
+        log::warn!("Could not claim the I/O ports of the PCI configuration space.");
+    }
+}
+
+/// Checks if the configuration space is accessed through ECAM.
+pub fn uses_ecam() -> bool {
+    match MECHANISM.get() {
+        Some(Mechanism::Ecam(_)) => true,
+        _ => false,
+    }
+}
+
+/// Returns the regions used for ECAM, which are empty if the I/O ports are used.
+pub fn ecam_regions() -> &'static [EcamRegion] {
+    match MECHANISM.get() {
+        Some(Mechanism::Ecam(regions)) => regions,
+        _ => &[],
+    }
+}
+
+/// Returns the size of the configuration space of the functions.
+pub fn size() -> u16 {
+    if uses_ecam() {
+        EXTENDED_SIZE
+    } else {
+        LEGACY_SIZE
+    }
+}
+
+/// Returns the value of the address port that selects the register, if the I/O ports can reach it.
+fn port_address(address: Address, offset: u16) -> Option<u32> {
+    if address.segment() != 0 || offset >= LEGACY_SIZE {
+        return None;
+    }
+
+    Some(
+        ENABLE
+            | (address.bus() as u32) << 16
+            | (address.device() as u32 & 0x1f) << 11
+            | (address.function() as u32 & 0x7) << 8
+            | (offset as u32 & 0xfc),
+    )
+}
+
+/// Reads the register of the given width at the offset, if the function can be reached.
+///
+/// The offset must be aligned to the width.
+fn read<T: PortReadWrite + Copy>(address: Address, offset: u16) -> Option<T> {
+    match MECHANISM.get()? {
+        Mechanism::Ports(_) => {
+            let port_address = port_address(address, offset)?;
+            let _lock = PORT_LOCK.lock();
+
+            // This is safe, because the I/O ports belong to the configuration space
+            // and the register was selected before it is read.
+            unsafe {
+                Port::<u32>::new(ADDRESS_PORT).write(port_address);
+                Some(Port::<T>::new(DATA_PORT + (offset & 3)).read())
+            }
+        }
+        Mechanism::Ecam(regions) => {
+            let register = regions
+                .iter()
+                .find_map(|region| region.register(address, offset))?;
+
+            // This is safe, because the firmware reserves the ECAM regions for the configuration space.
+            Some(unsafe { ptr::read_volatile(Arch::physical_to_virtual(register).as_ptr::<T>()) })
+        }
+    }
+}
+
+/// Writes the register of the given width at the offset, if the function can be reached.
+///
+/// The offset must be aligned to the width.
+fn write<T: PortReadWrite + Copy>(address: Address, offset: u16, value: T) {
+    match MECHANISM.get() {
+        Some(Mechanism::Ports(_)) => {
+            if let Some(port_address) = port_address(address, offset) {
+                let _lock = PORT_LOCK.lock();
+
+                // This is safe, because the I/O ports belong to the configuration space
+                // and the register was selected before it is written.
+                unsafe {
+                    Port::<u32>::new(ADDRESS_PORT).write(port_address);
+                    Port::<T>::new(DATA_PORT + (offset & 3)).write(value);
+                }
+            }
+        }
+        Some(Mechanism::Ecam(regions)) => {
+            if let Some(register) = regions
+                .iter()
+                .find_map(|region| region.register(address, offset))
+            {
+                // This is safe, because the firmware reserves the ECAM regions for the configuration space.
+                unsafe {
+                    ptr::write_volatile(
+                        Arch::physical_to_virtual(register).as_mut_ptr::<T>(),
+                        value,
+                    )
+                }
+            }
+        }
+        None => (),
+    }
+}
+
+/// Reads the byte at the offset in the configuration space of the function.
+pub fn read_u8(address: Address, offset: u16) -> u8 {
+    read(address, offset).unwrap_or(!0)
+}
+
+/// Reads the 16 bit word at the offset in the configuration space of the function.
+///
+/// The offset must be aligned to two bytes.
+pub fn read_u16(address: Address, offset: u16) -> u16 {
+    read(address, offset).unwrap_or(!0)
+}
+
+/// Reads the 32 bit word at the offset in the configuration space of the function.
+///
+/// The offset must be aligned to four bytes.
+pub fn read_u32(address: Address, offset: u16) -> u32 {
+    read(address, offset).unwrap_or(!0)
+}
+
+/// Writes the byte at the offset in the configuration space of the function.
+pub fn write_u8(address: Address, offset: u16, value: u8) {
+    write(address, offset, value);
+}
+
+/// Writes the 16 bit word at the offset in the configuration space of the function.
+///
+/// The offset must be aligned to two bytes.
+pub fn write_u16(address: Address, offset: u16, value: u16) {
+    write(address, offset, value);
+}
+
+/// Writes the 32 bit word at the offset in the configuration space of the function.
+///
+/// The offset must be aligned to four bytes.
+pub fn write_u32(address: Address, offset: u16, value: u32) {
+    write(address, offset, value);
+}
@@ -0,0 +1,427 @@
+//! Provides a driver for the i8042 PS/2 controller and the keyboard on its first port.
+//!
+//! The controller is tested and the keyboard is enabled once interrupts can be handled.
+//! If the controller translates the scancodes of the keyboard, they arrive in set 1,
+//! otherwise in set 2, which is the default of PS/2 keyboards.
+//! Every received byte raises an interrupt, whose handler decodes it and delivers the key events to the consoles.
+//! The LEDs of the keyboard show the state of the lock keys.
+//! The second port, which is usually used by a mouse, stays disabled.
+
+use core::fmt;
+use x86_64_crate::instructions::port::Port;
+
+use crate::{
+    console::keyboard::{self, scancode::ScancodeSet, KeyEvent, Keyboard, Modifiers},
+    io_port::IoPortRange,
+    irq::IrqLine,
+    sync::Mutex,
+};
+
+/// The I/O port of the data register.
+const DATA_PORT: u16 = 0x60;
+
+/// The I/O port of the status register when read and of the command register when written.
+const COMMAND_PORT: u16 = 0x64;
+
+/// The interrupt line of the first port.
+const IRQ: usize = 1;
+
+/// The number of times the status is checked before giving up on the controller or the keyboard.
+const TIMEOUT: usize = 100_000;
+
+/// The number of times a command is sent to the keyboard if it asks for it to be sent again.
+const KEYBOARD_COMMAND_ATTEMPTS: usize = 3;
+
+/// The bit in the status register that is set while a byte can be read from the data register.
+const OUTPUT_FULL: u8 = 1 << 0;
+
+/// The bit in the status register that is set while the controller hasn't taken the last written byte.
+const INPUT_FULL: u8 = 1 << 1;
+
+/// The command that reads the configuration byte.
+const READ_CONFIGURATION: u8 = 0x20;
+
+/// The command that writes the configuration byte.
+const WRITE_CONFIGURATION: u8 = 0x60;
+
+/// The command that disables the second port.
+const DISABLE_SECOND_PORT: u8 = 0xa7;
+
+/// The command that tests the controller.
+const SELF_TEST: u8 = 0xaa;
+
+/// The command that tests the first port.
+const TEST_FIRST_PORT: u8 = 0xab;
+
+/// The command that disables the first port.
+const DISABLE_FIRST_PORT: u8 = 0xad;
+
+/// The command that enables the first port.
+const ENABLE_FIRST_PORT: u8 = 0xae;
+
+/// The command that pulses the reset line of the processor.
+const PULSE_RESET_LINE: u8 = 0xfe;
+
+/// The response of a controller that passed its test.
+const SELF_TEST_PASSED: u8 = 0x55;
+
+/// The response of a port that passed its test.
+const PORT_TEST_PASSED: u8 = 0x00;
+
+/// The bit in the configuration byte that enables the interrupts of the first port.
+const FIRST_PORT_INTERRUPT: u8 = 1 << 0;
+
+/// The bit in the configuration byte that enables the interrupts of the second port.
+const SECOND_PORT_INTERRUPT: u8 = 1 << 1;
+
+/// The bit in the configuration byte that disables the clock of the first port.
+const FIRST_PORT_CLOCK_DISABLED: u8 = 1 << 4;
+
+/// The bit in the configuration byte that enables the translation of the scancodes to set 1.
+const TRANSLATION: u8 = 1 << 6;
+
+/// The keyboard command that sets the LEDs, followed by the LED bits.
+const SET_LEDS: u8 = 0xed;
+
+/// The keyboard command that makes the keyboard send scancodes.
+const ENABLE_SCANNING: u8 = 0xf4;
+
+/// The response of the keyboard to a command it accepted.
+const ACKNOWLEDGE: u8 = 0xfa;
+
+/// The response of the keyboard to a command it wants to have sent again.
+const RESEND: u8 = 0xfe;
+
+/// The LED bit for scroll lock.
+const SCROLL_LOCK_LED: u8 = 1 << 0;
+
+/// The LED bit for num lock.
+const NUM_LOCK_LED: u8 = 1 << 1;
+
+/// The LED bit for caps lock.
+const CAPS_LOCK_LED: u8 = 1 << 2;
+
+/// The controller, if it was found with a keyboard.
+static CONTROLLER: Mutex<Option<Controller>> = Mutex::new(None);
+
+/// The errors that can occur while setting up the controller and the keyboard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ps2Error {
+    /// No controller responds at the I/O ports.
+    NoController,
+    /// The controller failed its test.
+    SelfTestFailed,
+    /// The first port failed its test.
+    PortTestFailed,
+    /// No keyboard responds on the first port.
+    NoKeyboard,
+}
+
+impl fmt::Display for Ps2Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Ps2Error::NoController => write!(f, "no PS/2 controller was found"),
+            Ps2Error::SelfTestFailed => write!(f, "the PS/2 controller failed its test"),
+            Ps2Error::PortTestFailed => write!(f, "the first PS/2 port failed its test"),
+            Ps2Error::NoKeyboard => write!(f, "no keyboard responds on the first PS/2 port"),
+        }
+    }
+}
+
+/// The state of the controller and its keyboard.
+struct Controller {
+    /// The claimed data port.
+    _data_port: IoPortRange,
+    /// The claimed command port.
+    _command_port: IoPortRange,
+    /// The interrupt line of the keyboard.
+    irq_line: IrqLine,
+    /// The state of the keyboard.
+    keyboard: Keyboard,
+    /// The LED bits that were last sent to the keyboard.
+    leds: u8,
+    /// The LED bits that are sent once the keyboard acknowledged the command to set them.
+    pending_leds: Option<u8>,
+}
+
+impl Controller {
+    /// Handles a byte received from the keyboard.
+    ///
+    /// Returns the key event and the modifiers, if the byte completed an event.
+    fn receive_byte(&mut self, byte: u8) -> Option<(KeyEvent, Modifiers)> {
+        if byte == ACKNOWLEDGE {
+            if let Some(leds) = self.pending_leds.take() {
+                // A keyboard that doesn't take the LEDs just doesn't show them.
+                write_data(leds).ok();
+            }
+
+            return None;
+        }
+
+        let event = self.keyboard.receive_byte(byte)?;
+        let modifiers = self.keyboard.modifiers();
+        let leds = led_bits(modifiers);
+
+        if leds != self.leds && write_data(SET_LEDS).is_ok() {
+            self.leds = leds;
+            self.pending_leds = Some(leds);
+        }
+
+        Some((event, modifiers))
+    }
+}
+
+/// Returns the LED bits that show the lock keys.
+fn led_bits(modifiers: Modifiers) -> u8 {
+    let mut leds = 0;
+
+    if modifiers.contains(Modifiers::SCROLL_LOCK) {
+        leds |= SCROLL_LOCK_LED;
+    }
+    if modifiers.contains(Modifiers::NUM_LOCK) {
+        leds |= NUM_LOCK_LED;
+    }
+    if modifiers.contains(Modifiers::CAPS_LOCK) {
+        leds |= CAPS_LOCK_LED;
+    }
+
+    leds
+}
+
+/// Reads the status register.
+fn read_status() -> u8 {
+    // This is safe, because the I/O port belongs to the controller.
+    unsafe { Port::<u8>::new(COMMAND_PORT).read() }
+}
+
+/// Reads the data register.
+fn read_data() -> u8 {
+    // This is safe, because the I/O port belongs to the controller.
+    unsafe { Port::<u8>::new(DATA_PORT).read() }
+}
+
+/// Waits until the controller can take another byte.
+fn wait_for_input() -> Result<(), Ps2Error> {
+    if (0..TIMEOUT).any(|_| read_status() & INPUT_FULL == 0) {
+        Ok(())
+    } else {
+        Err(Ps2Error::NoController)
+    }
+}
+
+/// Waits for a byte from the controller or the keyboard and returns it.
+fn wait_for_output() -> Option<u8> {
+    if (0..TIMEOUT).any(|_| read_status() & OUTPUT_FULL != 0) {
+        Some(read_data())
+    } else {
+        None
+    }
+}
+
+/// Sends a command to the controller.
+fn write_command(command: u8) -> Result<(), Ps2Error> {
+    wait_for_input()?;
+
+    // This is safe, because the I/O port belongs to the controller.
+    unsafe { Port::<u8>::new(COMMAND_PORT).write(command) };
+
+    Ok(())
+}
+
+/// Sends a byte to the keyboard or a parameter of a command to the controller.
+fn write_data(byte: u8) -> Result<(), Ps2Error> {
+    wait_for_input()?;
+
+    // This is safe, because the I/O port belongs to the controller.
+    unsafe { Port::<u8>::new(DATA_PORT).write(byte) };
+
+    Ok(())
+}
+
+/// Sends a command to the controller and returns its response.
+fn query(command: u8) -> Result<u8, Ps2Error> {
+    write_command(command)?;
+
+    wait_for_output().ok_or(Ps2Error::NoController)
+}
+
+/// Writes the configuration byte.
+fn write_configuration(configuration: u8) -> Result<(), Ps2Error> {
+    write_command(WRITE_CONFIGURATION)?;
+    write_data(configuration)
+}
+
+/// Sends a byte to the keyboard and waits until the keyboard acknowledges it.
+fn send_to_keyboard(byte: u8) -> Result<(), Ps2Error> {
+    for _ in 0..KEYBOARD_COMMAND_ATTEMPTS {
+        write_data(byte)?;
+
+        match wait_for_output() {
+            Some(ACKNOWLEDGE) => return Ok(()),
+            Some(RESEND) => continue,
+            _ => break,
+        }
+    }
+
+    Err(Ps2Error::NoKeyboard)
+}
+
+/// Tests the controller and enables the keyboard, with the interrupts of both ports disabled.
+///
+/// Returns the configuration byte and the scancode set that the keyboard uses.
+fn set_up() -> Result<(u8, ScancodeSet), Ps2Error> {
+    // Nothing drives the bus without a controller, so that all bits read as set.
+    if read_status() == 0xff {
+        return Err(Ps2Error::NoController);
+    }
+
+    write_command(DISABLE_FIRST_PORT)?;
+    write_command(DISABLE_SECOND_PORT)?;
+
+    for _ in 0..TIMEOUT {
+        if read_status() & OUTPUT_FULL == 0 {
+            break;
+        }
+
+        read_data();
+    }
+
+    let configuration =
+        query(READ_CONFIGURATION)? & !(FIRST_PORT_INTERRUPT | SECOND_PORT_INTERRUPT);
+    write_configuration(configuration)?;
+
+    if query(SELF_TEST)? != SELF_TEST_PASSED {
+        return Err(Ps2Error::SelfTestFailed);
+    }
+
+    // Some controllers are reset by their test.
+    write_configuration(configuration)?;
+
+    if query(TEST_FIRST_PORT)? != PORT_TEST_PASSED {
+        return Err(Ps2Error::PortTestFailed);
+    }
+
+    write_command(ENABLE_FIRST_PORT)?;
+    send_to_keyboard(ENABLE_SCANNING)?;
+
+    let set = if configuration & TRANSLATION != 0 {
+        ScancodeSet::Set1
+    } else {
+        ScancodeSet::Set2
+    };
+
+    Ok((configuration, set))
+}
+
+/// Detects the controller and lets the keyboard on its first port raise interrupts.
+///
+/// This must be called after the heap and the interrupt controllers are set up.
+pub fn init() {
+    let (data_port, command_port) = match (
+        IoPortRange::claim(DATA_PORT, 1),
+        IoPortRange::claim(COMMAND_PORT, 1),
+    ) {
+        (Some(data_port), Some(command_port)) => (data_port, command_port),
+        _ => {
+            log::warn!("Could not claim the I/O ports of the PS/2 controller.");
+            return;
+        }
+    };
+
+    let (configuration, set) = match set_up() {
+        Ok(result) => result,
+        Err(error) => {
+            log::info!("Not using a PS/2 keyboard, because {}.", error);
+            return;
+        }
+    };
+
+    let irq_line = match IrqLine::claim(IRQ) {
+        Some(irq_line) => irq_line,
+        None => {
+            log::warn!(
+                "Could not claim the interrupt line {} of the PS/2 keyboard.",
+                IRQ
+            );
+            return;
+        }
+    };
+
+    let keyboard = Keyboard::new(set);
+    let leds = led_bits(keyboard.modifiers());
+
+    // The interrupts are still disabled, so the acknowledgements can be waited for.
+    if send_to_keyboard(SET_LEDS)
+        .and_then(|()| send_to_keyboard(leds))
+        .is_err()
+    {
+        log::debug!("The PS/2 keyboard did not take the LEDs.");
+    }
+
+    {
+        let mut controller = CONTROLLER.lock();
+        let controller = controller.get_or_insert(Controller {
+            _data_port: data_port,
+            _command_port: command_port,
+            irq_line,
+            keyboard,
+            leds,
+            pending_leds: None,
+        });
+
+        controller
+            .irq_line
+            .set_kernel_handler(Some(handle_interrupt));
+
+        let enabled = write_configuration(
+            (configuration | FIRST_PORT_INTERRUPT) & !FIRST_PORT_CLOCK_DISABLED,
+        );
+
+        if enabled.is_err() {
+            log::warn!("Could not enable the interrupts of the PS/2 keyboard.");
+        }
+    }
+
+    log::debug!(
+        "Using the PS/2 keyboard with scancode set {} on interrupt line {}.",
+        match set {
+            ScancodeSet::Set1 => 1,
+            ScancodeSet::Set2 => 2,
+        },
+        IRQ
+    );
+}
+
+/// Handles an interrupt of the keyboard.
+fn handle_interrupt(_irq: usize) {
+    let event = {
+        let mut controller = CONTROLLER.lock();
+
+        controller
+            .as_mut()
+            .filter(|_| read_status() & OUTPUT_FULL != 0)
+            .and_then(|controller| controller.receive_byte(read_data()))
+    };
+
+    // The consoles are used without holding the lock, since echoing the input may take a while.
+    if let Some((event, modifiers)) = event {
+        keyboard::deliver(event, modifiers);
+    }
+}
+
+/// Resets the machine by letting the controller pulse the reset line of the processor.
+///
+/// This returns if no controller takes the command.
+pub fn pulse_reset_line() {
+    if write_command(PULSE_RESET_LINE).is_err() {
+        log::warn!("The PS/2 controller did not take the reset command.");
+    }
+}
+
+/// Returns the scancode set used by the keyboard, if a keyboard was found.
+pub fn scancode_set() -> Option<ScancodeSet> {
+    CONTROLLER
+        .lock()
+        .as_ref()
+        .map(|controller| controller.keyboard.scancode_set())
+}
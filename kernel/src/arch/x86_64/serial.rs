@@ -1,28 +1,977 @@
-//! This module handles serial IO for the x86_64 architecture.
+//! Provides a driver for the 16550 UARTs of the serial ports of the x86_64 architecture.
+//!
+//! The four serial ports at the standard I/O port addresses are detected at boot time.
+//! They start out polled, so that the first serial port can show the output of the kernel from the beginning.
+//! Once the heap is set up, the ports raise interrupts when bytes are received or the transmitter runs empty.
+//! Output is then queued in a ring buffer that the interrupt handler passes to the transmitter,
+//! unless interrupts are disabled while writing, in which case the output is sent right away.
+//!
+//! The bytes received on the first serial port are passed to the line discipline of the consoles,
+//! while the other ports keep them until they are read.
+//! Typing `Ctrl-]` on the first serial port requests the debug shell instead,
+//! which is entered after the interrupt was handled.
+//! The bytes received on the second serial port are passed to the GDB stub.
+//! The line settings of the first serial port can be changed with the `serial` option on the command line.
 
-use core::fmt::{self, Write};
-use lazy_static::lazy_static;
-use uart_16550::SerialPort;
+use core::{
+    fmt::{self, Write},
+    str::FromStr,
+    sync::atomic::{spin_loop_hint, AtomicBool, Ordering},
+};
+use x86_64_crate::instructions::port::Port;
 
-use crate::sync::Mutex;
+use super::{gdb, shell};
+use crate::{
+    arch::{Arch, Architecture},
+    cmdline::Configuration,
+    console::{self, Sink},
+    io_port::IoPortRange,
+    irq::IrqLine,
+    sync::Mutex,
+};
 
-lazy_static! {
-    /// Provides access to the serial port.
-    pub static ref SERIAL: Mutex<SerialPort> = {
-        let mut serial_port = SerialPort::new(0x3f8);
+/// The number of serial ports.
+pub const SERIAL_PORT_COUNT: usize = 4;
 
-        serial_port.init();
+/// The baud rate that is divided by the divisor of a UART.
+const BASE_BAUD_RATE: u32 = 115_200;
 
-        Mutex::new(serial_port)
+/// The number of I/O ports used by a UART.
+const REGISTER_COUNT: usize = 8;
+
+/// The number of bytes the transmitter FIFO holds.
+const FIFO_SIZE: usize = 16;
+
+/// The number of bytes that are buffered in each direction.
+const BUFFER_SIZE: usize = 0x1000;
+
+/// The number of times the loopback test checks for the byte it sent.
+const LOOPBACK_ATTEMPTS: usize = 1000;
+
+/// The register holding the received byte and taking the byte to send.
+///
+/// It holds the low byte of the divisor while the divisor latch is accessed.
+const DATA: u16 = 0;
+
+/// The register that selects the conditions that raise interrupts.
+///
+/// It holds the high byte of the divisor while the divisor latch is accessed.
+const INTERRUPT_ENABLE: u16 = 1;
+
+/// The register that tells the reason of an interrupt when read and controls the FIFOs when written.
+const INTERRUPT_IDENTIFICATION: u16 = 2;
+
+/// The register holding the line settings.
+const LINE_CONTROL: u16 = 3;
+
+/// The register controlling the modem signals.
+const MODEM_CONTROL: u16 = 4;
+
+/// The register holding the state of the receiver and the transmitter.
+const LINE_STATUS: u16 = 5;
+
+/// The register holding the state of the modem signals.
+const MODEM_STATUS: u16 = 6;
+
+/// A register without a function, which tells if a UART is present.
+const SCRATCH: u16 = 7;
+
+/// Raises an interrupt when a byte was received.
+const RECEIVED_DATA_INTERRUPT: u8 = 1 << 0;
+
+/// Raises an interrupt when the transmitter can take more bytes.
+const TRANSMITTER_EMPTY_INTERRUPT: u8 = 1 << 1;
+
+/// Raises an interrupt when a receive error occurred.
+const LINE_STATUS_INTERRUPT: u8 = 1 << 2;
+
+/// Set in the interrupt identification register if no interrupt is pending.
+const NO_INTERRUPT_PENDING: u8 = 1 << 0;
+
+/// The bits of the interrupt identification register that tell the reason of the interrupt.
+const INTERRUPT_REASON_MASK: u8 = 0x0e;
+
+/// The reason of an interrupt caused by a change of the modem signals.
+const MODEM_STATUS_REASON: u8 = 0x00;
+
+/// The reason of an interrupt caused by the transmitter running empty.
+const TRANSMITTER_EMPTY_REASON: u8 = 0x02;
+
+/// The reason of an interrupt caused by received bytes.
+const RECEIVED_DATA_REASON: u8 = 0x04;
+
+/// The reason of an interrupt caused by a receive error.
+const LINE_STATUS_REASON: u8 = 0x06;
+
+/// The reason of an interrupt caused by bytes that waited in the receiver FIFO for a while.
+const CHARACTER_TIMEOUT_REASON: u8 = 0x0c;
+
+/// Enables and clears the FIFOs, with an interrupt once 14 bytes were received.
+const FIFO_CONTROL: u8 = 0xc7;
+
+/// Makes the first two registers access the divisor when set in the line control register.
+const DIVISOR_LATCH_ACCESS: u8 = 1 << 7;
+
+/// Signals that the computer is ready.
+const DATA_TERMINAL_READY: u8 = 1 << 0;
+
+/// Signals that the computer can receive bytes.
+const REQUEST_TO_SEND: u8 = 1 << 1;
+
+/// Connects the interrupt output of the UART to the interrupt line.
+const INTERRUPT_OUTPUT: u8 = 1 << 3;
+
+/// Connects the transmitter of the UART to its receiver.
+const LOOPBACK: u8 = 1 << 4;
+
+/// Set in the line status register if a received byte can be read.
+const DATA_READY: u8 = 1 << 0;
+
+/// Set in the line status register if the transmitter can take more bytes.
+const TRANSMITTER_EMPTY: u8 = 1 << 5;
+
+/// The first I/O ports of the UARTs of the serial ports.
+const BASES: [u16; SERIAL_PORT_COUNT] = [0x3f8, 0x2f8, 0x3e8, 0x2e8];
+
+/// The serial ports, which are found at the same I/O ports and interrupt lines on all PCs.
+static PORTS: [Mutex<SerialPort>; SERIAL_PORT_COUNT] = [
+    Mutex::new(SerialPort::new(BASES[0], 4, true)),
+    Mutex::new(SerialPort::new(BASES[1], 3, false)),
+    Mutex::new(SerialPort::new(BASES[2], 4, false)),
+    Mutex::new(SerialPort::new(BASES[3], 3, false)),
+];
+
+/// Whether a UART was found for each serial port, which can be checked without taking the lock of the port.
+static PRESENT: [AtomicBool; SERIAL_PORT_COUNT] = [
+    AtomicBool::new(false),
+    AtomicBool::new(false),
+    AtomicBool::new(false),
+    AtomicBool::new(false),
+];
+
+/// The interrupt lines shared by the serial ports, once they are claimed.
+static IRQ_LINES: Mutex<[Option<IrqLine>; 2]> = Mutex::new([None, None]);
+
+/// The errors that can occur when using a serial port.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SerialError {
+    /// There is no serial port with the given index.
+    NoSuchPort,
+    /// No UART was found at the I/O ports of the serial port.
+    NotPresent,
+    /// The baud rate cannot be derived from the base baud rate of the UART.
+    InvalidBaudRate,
+    /// The number of data bits is not between five and eight.
+    InvalidDataBits,
+    /// The line settings could not be parsed.
+    InvalidSettings,
+}
+
+impl fmt::Display for SerialError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SerialError::NoSuchPort => write!(f, "the serial port does not exist"),
+            SerialError::NotPresent => write!(f, "the serial port is not present"),
+            SerialError::InvalidBaudRate => write!(f, "the baud rate is not supported"),
+            SerialError::InvalidDataBits => write!(f, "the number of data bits is not supported"),
+            SerialError::InvalidSettings => write!(f, "the line settings are invalid"),
+        }
+    }
+}
+
+/// The parity bit sent after the data bits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Parity {
+    /// No parity bit is sent.
+    None,
+    /// The parity bit makes the number of set bits odd.
+    Odd,
+    /// The parity bit makes the number of set bits even.
+    Even,
+    /// The parity bit is always set.
+    Mark,
+    /// The parity bit is always clear.
+    Space,
+}
+
+/// The number of stop bits sent after every byte.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopBits {
+    /// One stop bit is sent.
+    One,
+    /// Two stop bits are sent, or one and a half with five data bits.
+    Two,
+}
+
+/// The settings of a serial line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LineSettings {
+    /// The number of bits sent per second.
+    baud_rate: u32,
+    /// The number of data bits in a byte.
+    data_bits: u8,
+    /// The parity bit sent after the data bits.
+    parity: Parity,
+    /// The number of stop bits.
+    stop_bits: StopBits,
+}
+
+impl LineSettings {
+    /// The settings the serial ports use at boot time, which are 115200 baud with eight data bits,
+    /// no parity and one stop bit.
+    pub const DEFAULT: LineSettings = LineSettings {
+        baud_rate: BASE_BAUD_RATE,
+        data_bits: 8,
+        parity: Parity::None,
+        stop_bits: StopBits::One,
     };
+
+    /// Creates line settings, if the UART supports them.
+    pub fn new(
+        baud_rate: u32,
+        data_bits: u8,
+        parity: Parity,
+        stop_bits: StopBits,
+    ) -> Result<LineSettings, SerialError> {
+        // The divisor is a 16 bit number.
+        if baud_rate < 2 || BASE_BAUD_RATE % baud_rate != 0 {
+            return Err(SerialError::InvalidBaudRate);
+        }
+
+        if data_bits < 5 || data_bits > 8 {
+            return Err(SerialError::InvalidDataBits);
+        }
+
+        Ok(LineSettings {
+            baud_rate,
+            data_bits,
+            parity,
+            stop_bits,
+        })
+    }
+
+    /// Returns the number of bits sent per second.
+    pub fn baud_rate(&self) -> u32 {
+        self.baud_rate
+    }
+
+    /// Returns the number of data bits in a byte.
+    pub fn data_bits(&self) -> u8 {
+        self.data_bits
+    }
+
+    /// Returns the parity bit sent after the data bits.
+    pub fn parity(&self) -> Parity {
+        self.parity
+    }
+
+    /// Returns the number of stop bits.
+    pub fn stop_bits(&self) -> StopBits {
+        self.stop_bits
+    }
+
+    /// Returns the divisor of the base baud rate that results in the baud rate.
+    fn divisor(&self) -> u16 {
+        (BASE_BAUD_RATE / self.baud_rate) as u16
+    }
+
+    /// Returns the value of the line control register for the settings.
+    fn line_control(&self) -> u8 {
+        let parity = match self.parity {
+            Parity::None => 0x00,
+            Parity::Odd => 0x08,
+            Parity::Even => 0x18,
+            Parity::Mark => 0x28,
+            Parity::Space => 0x38,
+        };
+        let stop_bits = match self.stop_bits {
+            StopBits::One => 0x00,
+            StopBits::Two => 0x04,
+        };
+
+        (self.data_bits - 5) | stop_bits | parity
+    }
+}
+
+impl FromStr for LineSettings {
+    type Err = SerialError;
+
+    /// Parses settings of the form `<baud rate>[,<data bits><parity><stop bits>]`, like `9600,7e1`.
+    ///
+    /// The parity is `n`, `o`, `e`, `m` or `s`.
+    /// Eight data bits, no parity and one stop bit are used if only the baud rate is given.
+    fn from_str(settings: &str) -> Result<LineSettings, SerialError> {
+        let (baud_rate, format) = match settings.find(',') {
+            Some(index) => (&settings[..index], &settings[index + 1..]),
+            None => (settings, "8n1"),
+        };
+
+        let baud_rate = u32::from_str(baud_rate).map_err(|_| SerialError::InvalidSettings)?;
+
+        let mut characters = format.chars();
+        let (data_bits, parity, stop_bits) =
+            match (characters.next(), characters.next(), characters.next()) {
+                (Some(data_bits), Some(parity), Some(stop_bits)) => (data_bits, parity, stop_bits),
+                _ => return Err(SerialError::InvalidSettings),
+            };
+
+        if characters.next().is_some() {
+            return Err(SerialError::InvalidSettings);
+        }
+
+        let data_bits = data_bits.to_digit(10).ok_or(SerialError::InvalidSettings)? as u8;
+        let parity = match parity {
+            'n' => Parity::None,
+            'o' => Parity::Odd,
+            'e' => Parity::Even,
+            'm' => Parity::Mark,
+            's' => Parity::Space,
+            _ => return Err(SerialError::InvalidSettings),
+        };
+        let stop_bits = match stop_bits {
+            '1' => StopBits::One,
+            '2' => StopBits::Two,
+            _ => return Err(SerialError::InvalidSettings),
+        };
+
+        LineSettings::new(baud_rate, data_bits, parity, stop_bits)
+    }
+}
+
+impl fmt::Display for LineSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parity = match self.parity {
+            Parity::None => 'n',
+            Parity::Odd => 'o',
+            Parity::Even => 'e',
+            Parity::Mark => 'm',
+            Parity::Space => 's',
+        };
+        let stop_bits = match self.stop_bits {
+            StopBits::One => 1,
+            StopBits::Two => 2,
+        };
+
+        write!(
+            f,
+            "{},{}{}{}",
+            self.baud_rate, self.data_bits, parity, stop_bits
+        )
+    }
+}
+
+/// A buffer of bytes that are sent or were received.
+struct RingBuffer {
+    /// The stored bytes.
+    bytes: [u8; BUFFER_SIZE],
+    /// The index of the oldest byte.
+    start: usize,
+    /// The number of stored bytes.
+    length: usize,
+}
+
+impl RingBuffer {
+    /// An empty buffer.
+    const EMPTY: RingBuffer = RingBuffer {
+        bytes: [0; BUFFER_SIZE],
+        start: 0,
+        length: 0,
+    };
+
+    /// Checks if the buffer holds no bytes.
+    fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Checks if the buffer cannot take more bytes.
+    fn is_full(&self) -> bool {
+        self.length == BUFFER_SIZE
+    }
+
+    /// Appends the byte, unless the buffer is full.
+    fn push(&mut self, byte: u8) -> bool {
+        if self.is_full() {
+            return false;
+        }
+
+        self.bytes[(self.start + self.length) % BUFFER_SIZE] = byte;
+        self.length += 1;
+
+        true
+    }
+
+    /// Removes the oldest byte.
+    fn pop(&mut self) -> Option<u8> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let byte = self.bytes[self.start];
+
+        self.start = (self.start + 1) % BUFFER_SIZE;
+        self.length -= 1;
+
+        Some(byte)
+    }
+}
+
+/// A serial port with a 16550 compatible UART.
+struct SerialPort {
+    /// The first I/O port of the UART.
+    base: u16,
+    /// The interrupt line of the UART.
+    irq: usize,
+    /// The I/O ports of the UART, which are only claimed if the UART is present.
+    ports: Option<IoPortRange>,
+    /// The settings of the line.
+    settings: LineSettings,
+    /// Whether the UART raises interrupts.
+    interrupt_driven: bool,
+    /// Whether the transmitter raises an interrupt when it runs empty.
+    transmitting: bool,
+    /// The bytes that are not sent yet.
+    transmit: RingBuffer,
+    /// The bytes that were received and not read yet.
+    receive: RingBuffer,
+    /// Whether the received bytes are passed to the line discipline of the consoles.
+    console_input: bool,
+}
+
+impl SerialPort {
+    /// Creates a serial port with the UART at the given I/O ports, which is not detected yet.
+    const fn new(base: u16, irq: usize, console_input: bool) -> SerialPort {
+        SerialPort {
+            base,
+            irq,
+            ports: None,
+            settings: LineSettings::DEFAULT,
+            interrupt_driven: false,
+            transmitting: false,
+            transmit: RingBuffer::EMPTY,
+            receive: RingBuffer::EMPTY,
+            console_input,
+        }
+    }
+
+    /// Reads the register of the UART at the given offset.
+    fn read_register(&self, offset: u16) -> u8 {
+        read_register(self.base, offset)
+    }
+
+    /// Writes to the register of the UART at the given offset.
+    fn write_register(&self, offset: u16, value: u8) {
+        write_register(self.base, offset, value)
+    }
+
+    /// Checks if the UART was found.
+    fn is_present(&self) -> bool {
+        self.ports.is_some()
+    }
+
+    /// Checks if a working UART is connected to the I/O ports and programs it with the default settings.
+    ///
+    /// The UART sends a byte to itself to make sure that it works.
+    fn detect(&mut self) -> bool {
+        self.write_register(SCRATCH, 0x5a);
+
+        if self.read_register(SCRATCH) != 0x5a {
+            return false;
+        }
+
+        self.program(LineSettings::DEFAULT);
+        self.write_register(
+            MODEM_CONTROL,
+            LOOPBACK | REQUEST_TO_SEND | DATA_TERMINAL_READY,
+        );
+        self.write_register(DATA, 0xae);
+
+        let received = (0..LOOPBACK_ATTEMPTS)
+            .any(|_| self.read_register(LINE_STATUS) & DATA_READY != 0)
+            && self.read_register(DATA) == 0xae;
+
+        self.write_register(
+            MODEM_CONTROL,
+            INTERRUPT_OUTPUT | REQUEST_TO_SEND | DATA_TERMINAL_READY,
+        );
+
+        received
+    }
+
+    /// Programs the UART with the line settings.
+    ///
+    /// The divisor of the baud rate and the line control register are set, and the FIFOs are enabled and reset.
+    fn program(&mut self, settings: LineSettings) {
+        let divisor = settings.divisor();
+
+        self.write_register(INTERRUPT_ENABLE, 0);
+        self.write_register(LINE_CONTROL, DIVISOR_LATCH_ACCESS);
+        self.write_register(DATA, divisor as u8);
+        self.write_register(INTERRUPT_ENABLE, (divisor >> 8) as u8);
+        self.write_register(LINE_CONTROL, settings.line_control());
+        self.write_register(INTERRUPT_IDENTIFICATION, FIFO_CONTROL);
+        self.write_register(
+            MODEM_CONTROL,
+            INTERRUPT_OUTPUT | REQUEST_TO_SEND | DATA_TERMINAL_READY,
+        );
+
+        self.settings = settings;
+        self.update_interrupts();
+    }
+
+    /// Selects the conditions that raise interrupts, depending on the state of the port.
+    fn update_interrupts(&self) {
+        let mut enabled = 0;
+
+        if self.interrupt_driven {
+            enabled |= RECEIVED_DATA_INTERRUPT | LINE_STATUS_INTERRUPT;
+
+            if self.transmitting {
+                enabled |= TRANSMITTER_EMPTY_INTERRUPT;
+            }
+        }
+
+        self.write_register(INTERRUPT_ENABLE, enabled);
+    }
+
+    /// Moves the received bytes from the UART to the receive buffer.
+    ///
+    /// Bytes are dropped if the buffer is full.
+    fn receive_pending(&mut self) {
+        while self.read_register(LINE_STATUS) & DATA_READY != 0 {
+            let byte = self.read_register(DATA);
+
+            self.receive.push(byte);
+        }
+    }
+
+    /// Passes as many buffered bytes to the transmitter as it can take without waiting.
+    fn fill_transmitter(&mut self) {
+        if self.read_register(LINE_STATUS) & TRANSMITTER_EMPTY == 0 {
+            return;
+        }
+
+        for _ in 0..FIFO_SIZE {
+            match self.transmit.pop() {
+                Some(byte) => self.write_register(DATA, byte),
+                None => break,
+            }
+        }
+    }
+
+    /// Waits until the transmitter can take more bytes and passes them to it.
+    fn transmit_waiting(&mut self) {
+        while self.read_register(LINE_STATUS) & TRANSMITTER_EMPTY == 0 {
+            spin_loop_hint();
+        }
+
+        self.fill_transmitter();
+    }
+
+    /// Sends all buffered bytes, waiting for the transmitter.
+    fn flush(&mut self) {
+        while !self.transmit.is_empty() {
+            self.transmit_waiting();
+        }
+    }
+
+    /// Queues the bytes for sending.
+    ///
+    /// If `synchronous` is set or the port doesn't use interrupts, the bytes are sent before this returns.
+    fn write(&mut self, bytes: &[u8], synchronous: bool) {
+        if !self.is_present() {
+            return;
+        }
+
+        for &byte in bytes {
+            while self.transmit.is_full() {
+                self.transmit_waiting();
+            }
+
+            self.transmit.push(byte);
+        }
+
+        if synchronous || !self.interrupt_driven {
+            self.flush();
+        } else {
+            self.fill_transmitter();
+
+            if !self.transmit.is_empty() && !self.transmitting {
+                self.transmitting = true;
+                self.update_interrupts();
+            }
+        }
+    }
+
+    /// Handles all pending interrupts of the UART.
+    fn handle_interrupt(&mut self) {
+        loop {
+            let identification = self.read_register(INTERRUPT_IDENTIFICATION);
+
+            if identification & NO_INTERRUPT_PENDING != 0 {
+                break;
+            }
+
+            match identification & INTERRUPT_REASON_MASK {
+                RECEIVED_DATA_REASON | CHARACTER_TIMEOUT_REASON => self.receive_pending(),
+                TRANSMITTER_EMPTY_REASON => {
+                    self.fill_transmitter();
+
+                    if self.transmit.is_empty() {
+                        self.transmitting = false;
+                        self.update_interrupts();
+                    }
+                }
+                LINE_STATUS_REASON => {
+                    // Reading the line status acknowledges the error, which is otherwise ignored.
+                    self.read_register(LINE_STATUS);
+                }
+                MODEM_STATUS_REASON => {
+                    self.read_register(MODEM_STATUS);
+                }
+                _ => break,
+            }
+        }
+    }
+}
+
+/// Writes formatted output to a serial port.
+struct Writer<'a> {
+    /// The port that is written to.
+    port: &'a mut SerialPort,
+    /// Whether the output is sent before the write returns.
+    synchronous: bool,
 }
 
-/// Prints the formatted arguments to the serial port.
+impl<'a> Write for Writer<'a> {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        self.port.write(string.as_bytes(), self.synchronous);
+
+        Ok(())
+    }
+}
+
+/// Reads the register at the given offset of the UART whose registers start at `base`.
+fn read_register(base: u16, offset: u16) -> u8 {
+    // This is safe, because the I/O ports belong to the UART or are unused.
+    unsafe { Port::<u8>::new(base + offset).read() }
+}
+
+/// Writes to the register at the given offset of the UART whose registers start at `base`.
+fn write_register(base: u16, offset: u16, value: u8) {
+    // This is safe, because the I/O ports belong to the UART or are unused.
+    unsafe { Port::<u8>::new(base + offset).write(value) }
+}
+
+/// Returns the serial port with the given index.
+fn get(port: usize) -> Result<&'static Mutex<SerialPort>, SerialError> {
+    PORTS.get(port).ok_or(SerialError::NoSuchPort)
+}
+
+/// Returns the first I/O port of the UART of the serial port, if it was found.
+fn present_base(port: usize) -> Result<u16, SerialError> {
+    let base = *BASES.get(port).ok_or(SerialError::NoSuchPort)?;
+
+    if PRESENT[port].load(Ordering::SeqCst) {
+        Ok(base)
+    } else {
+        Err(SerialError::NotPresent)
+    }
+}
+
+/// Detects the serial ports and sets them up with the default settings.
+///
+/// The ports are polled until `enable_interrupts` is called.
+pub fn init() {
+    for (port, present) in PORTS.iter().zip(PRESENT.iter()) {
+        let mut port = port.lock();
+
+        if port.detect() {
+            port.ports = IoPortRange::claim(port.base, REGISTER_COUNT);
+        }
+
+        present.store(port.is_present(), Ordering::SeqCst);
+    }
+}
+
+/// Applies the line settings given on the command line to the first serial port.
+pub fn configure(configuration: &Configuration) {
+    if let Some(settings) = configuration.serial_settings() {
+        let result = LineSettings::from_str(settings)
+            .and_then(|line_settings| set_line_settings(0, line_settings));
+
+        if let Err(error) = result {
+            log::warn!(
+                "Ignoring the serial line settings \"{}\": {}.",
+                settings,
+                error
+            );
+        }
+    }
+}
+
+/// Lets the serial ports raise interrupts, so that output is buffered and input is received.
+///
+/// This must be called after the heap and the interrupt controllers are set up.
+pub fn enable_interrupts() {
+    let mut irq_lines = IRQ_LINES.lock();
+
+    for (index, port) in PORTS.iter().enumerate() {
+        // Nothing is logged while a port is locked, since the log may be written to the port.
+        let (base, irq, settings) = {
+            let port = port.lock();
+
+            if !port.is_present() {
+                continue;
+            }
+
+            (port.base, port.irq, port.settings)
+        };
+
+        // The first and the third port share one line, as do the second and the fourth.
+        let line = &mut irq_lines[index % 2];
+
+        if line.is_none() {
+            *line = IrqLine::claim(irq);
+
+            match line {
+                Some(line) => line.set_kernel_handler(Some(handle_interrupt)),
+                None => {
+                    log::warn!(
+                        "Could not claim the interrupt line {} of the serial port COM{}.",
+                        irq,
+                        index + 1
+                    );
+
+                    continue;
+                }
+            }
+        }
+
+        {
+            let mut port = port.lock();
+
+            port.interrupt_driven = true;
+            port.update_interrupts();
+        }
+
+        log::debug!(
+            "Using the serial port COM{} at {:#x} with {} on interrupt line {}.",
+            index + 1,
+            base,
+            settings,
+            irq
+        );
+    }
+}
+
+/// Handles an interrupt on a line of the serial ports.
+fn handle_interrupt(irq: usize) {
+    for (index, port) in PORTS.iter().enumerate() {
+        let console_input = {
+            let mut port = port.lock();
+
+            if port.irq != irq || !port.interrupt_driven {
+                continue;
+            }
+
+            port.handle_interrupt();
+            port.console_input
+        };
+
+        // The consoles are used without holding the lock, since they echo the input to this port.
+        if console_input {
+            let mut buffer = [0; FIFO_SIZE];
+
+            loop {
+                let count = read(index, &mut buffer).unwrap_or(0);
+
+                if count == 0 {
+                    break;
+                }
+
+                for &byte in &buffer[..count] {
+                    if index == 0 && byte == shell::ENTER_CHARACTER {
+                        shell::request();
+                    } else {
+                        console::receive_byte(byte);
+                    }
+                }
+            }
+        } else if index == gdb::SERIAL_PORT {
+            gdb::poll();
+        }
+    }
+}
+
+/// Checks if the serial port with the given index was found.
+///
+/// This doesn't take the lock of the port.
+pub fn is_present(port: usize) -> bool {
+    present_base(port).is_ok()
+}
+
+/// Returns the line settings of the serial port.
+pub fn line_settings(port: usize) -> Result<LineSettings, SerialError> {
+    let port = get(port)?.lock();
+
+    if port.is_present() {
+        Ok(port.settings)
+    } else {
+        Err(SerialError::NotPresent)
+    }
+}
+
+/// Changes the line settings of the serial port.
+///
+/// The buffered output is sent with the previous settings first.
+pub fn set_line_settings(port: usize, settings: LineSettings) -> Result<(), SerialError> {
+    let mut port = get(port)?.lock();
+
+    if !port.is_present() {
+        return Err(SerialError::NotPresent);
+    }
+
+    port.flush();
+
+    // The last bytes may still be in the FIFO, which is cleared when programming the UART.
+    while port.read_register(LINE_STATUS) & TRANSMITTER_EMPTY == 0 {
+        spin_loop_hint();
+    }
+
+    port.program(settings);
+
+    Ok(())
+}
+
+/// Sets whether the bytes received on the serial port are passed to the line discipline of the consoles.
+///
+/// Otherwise they are kept until they are read.
+pub fn set_console_input(port: usize, console_input: bool) -> Result<(), SerialError> {
+    get(port)?.lock().console_input = console_input;
+
+    Ok(())
+}
+
+/// Queues the bytes for sending on the serial port.
+///
+/// The bytes are sent right away if interrupts are disabled, since they could otherwise stay in the buffer indefinitely.
+pub fn write(port: usize, bytes: &[u8]) -> Result<(), SerialError> {
+    let synchronous = !Arch::interrupts_enabled();
+    let mut port = get(port)?.lock();
+
+    if !port.is_present() {
+        return Err(SerialError::NotPresent);
+    }
+
+    port.write(bytes, synchronous);
+
+    Ok(())
+}
+
+/// Sends all bytes queued for the serial port before returning.
+pub fn flush(port: usize) -> Result<(), SerialError> {
+    get(port)?.lock().flush();
+
+    Ok(())
+}
+
+/// Reads the received bytes of the serial port into the buffer and returns their number.
+///
+/// This doesn't wait for bytes to arrive.
+/// Bytes passed to the line discipline of the consoles are not returned.
+pub fn read(port: usize, buffer: &mut [u8]) -> Result<usize, SerialError> {
+    let mut port = get(port)?.lock();
+
+    if !port.is_present() {
+        return Err(SerialError::NotPresent);
+    }
+
+    // This also makes reading work while interrupts are disabled.
+    port.receive_pending();
+
+    let mut count = 0;
+
+    for byte in buffer.iter_mut() {
+        match port.receive.pop() {
+            Some(received) => *byte = received,
+            None => break,
+        }
+
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// Sends the bytes on the serial port without taking its lock, waiting for the transmitter.
+///
+/// This is meant for the debug shell after a panic, when the lock may never be released.
+/// Output that was queued before is not sent.
+pub fn write_unlocked(port: usize, bytes: &[u8]) -> Result<(), SerialError> {
+    let base = present_base(port)?;
+
+    for &byte in bytes {
+        while read_register(base, LINE_STATUS) & TRANSMITTER_EMPTY == 0 {
+            spin_loop_hint();
+        }
+
+        write_register(base, DATA, byte);
+    }
+
+    Ok(())
+}
+
+/// Reads the bytes waiting in the UART of the serial port into the buffer without taking its lock.
+///
+/// Returns the number of bytes read. Like `write_unlocked`, this is meant for the debug shell after a panic.
+/// Bytes that were already moved to the receive buffer are not returned.
+pub fn read_unlocked(port: usize, buffer: &mut [u8]) -> Result<usize, SerialError> {
+    let base = present_base(port)?;
+    let mut count = 0;
+
+    for byte in buffer.iter_mut() {
+        if read_register(base, LINE_STATUS) & DATA_READY == 0 {
+            break;
+        }
+
+        *byte = read_register(base, DATA);
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// Prints the formatted arguments to the first serial port.
+///
+/// The output is sent before this returns, so that it is not lost if the kernel stops afterwards.
 pub fn write_fmt(args: fmt::Arguments) {
-    SERIAL
-        .lock()
+    let mut port = PORTS[0].lock();
+
+    // Writing to a serial port cannot fail.
+    Writer {
+        port: &mut port,
+        synchronous: true,
+    }
+    .write_fmt(args)
+    .ok();
+}
+
+/// The console sink of the first serial port.
+pub struct SerialSink;
+
+impl Sink for SerialSink {
+    fn write_fmt(&self, args: fmt::Arguments) {
+        let synchronous = !Arch::interrupts_enabled();
+        let mut port = PORTS[0].lock();
+
+        // Writing to a serial port cannot fail.
+        Writer {
+            port: &mut port,
+            synchronous,
+        }
         .write_fmt(args)
-        .expect("Could not write to serial.")
+        .ok();
+    }
 }
 
 /// Print to the serial output.
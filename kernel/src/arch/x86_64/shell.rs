@@ -0,0 +1,1152 @@
+//! Provides the debug shell, a monitor for inspecting the kernel over the first serial port.
+//!
+//! The shell is entered by typing `Ctrl-]` on the first serial port or from the panic handler.
+//! The interrupt handler of the serial port only requests the shell, which is entered once the interrupt was handled,
+//! either when the idle processor wakes up or before returning to user mode.
+//! While it runs, interrupts are disabled, so that the rest of the kernel stands still while it is inspected.
+//! The input is read by polling the serial port, which also works after a panic.
+//! After a panic the registers of the UART are used directly, since the lock of the port may never be released.
+//!
+//! Numbers given to the commands are decimal, unless they start with `0x`.
+//! Type `help` for a list of the commands.
+//!
+//! The frame allocator, the heap and the log filter may be in use by the interrupted code,
+//! so the commands using them report that they are busy instead of waiting.
+//! Other commands that need a lock that was held when the kernel panicked don't return.
+
+use core::{
+    fmt::{self, Write},
+    ptr,
+    sync::atomic::{spin_loop_hint, AtomicBool, Ordering},
+};
+use log::LevelFilter;
+use nuefil::runtime::ResetType;
+use raw_cpuid::CpuId;
+use size_format::SizeFormatterBinary;
+use x86_64_crate::{instructions::port::Port, registers::model_specific::Msr};
+
+use super::{
+    acpi, gdb,
+    memory::{self, EntryFlags},
+    pci::{self, Bar},
+    ps2, serial,
+    uefi::runtime,
+};
+use crate::{
+    arch::{Arch, Architecture},
+    console::{
+        self,
+        line_discipline::{LineDiscipline, MAXIMUM_LINE_LENGTH},
+    },
+    log_buffer,
+    log_filter::{self, LogFilterError},
+    memory::{heap, VirtualAddress, FRAME_ALLOCATOR, PAGE_SIZE},
+    process::{self, ProcessState},
+    scheduler,
+};
+
+/// The character that enters the shell when it is received on the first serial port, sent by `Ctrl-]`.
+pub const ENTER_CHARACTER: u8 = 0x1d;
+
+/// The text shown before the input.
+const PROMPT: &str = "beetle> ";
+
+/// The maximum number of arguments of a command.
+const MAXIMUM_ARGUMENT_COUNT: usize = 4;
+
+/// The number of bytes shown by `mem` if no length is given.
+const DEFAULT_DUMP_LENGTH: usize = 0x80;
+
+/// The maximum number of bytes shown by `mem`.
+const MAXIMUM_DUMP_LENGTH: usize = 0x1000;
+
+/// The number of bytes shown in a row of a memory dump.
+const ROW_LENGTH: usize = 16;
+
+/// The number of records shown by `log` if no count is given.
+const DEFAULT_RECORD_COUNT: usize = 20;
+
+/// The names of the page table levels, starting with the lowest level.
+const LEVEL_NAMES: [&str; 4] = ["PT", "PD", "PDPT", "PML4"];
+
+/// The I/O port of the reset control register of the chipset.
+const RESET_CONTROL_PORT: u16 = 0xcf9;
+
+/// The bit in the reset control register that selects a reset of the whole system.
+const SYSTEM_RESET: u8 = 1 << 1;
+
+/// The bit in the reset control register that starts the reset.
+const RESET_PROCESSOR: u8 = 1 << 2;
+
+/// The bit in the reset control register that turns the power off during the reset.
+const FULL_RESET: u8 = 1 << 3;
+
+/// The model specific register holding the extended feature enables.
+const EFER: u32 = 0xc000_0080;
+
+/// The model specific register holding the base of the `fs` segment.
+const FS_BASE: u32 = 0xc000_0100;
+
+/// The model specific register holding the base of the `gs` segment.
+const GS_BASE: u32 = 0xc000_0101;
+
+/// The model specific register holding the base that `swapgs` exchanges with the base of the `gs` segment.
+const KERNEL_GS_BASE: u32 = 0xc000_0102;
+
+/// Whether the shell is running.
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Whether `Ctrl-]` was typed and the shell should be entered after the interrupt.
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// The commands of the shell.
+static COMMANDS: [Command; 16] = [
+    Command {
+        name: "help",
+        usage: "",
+        description: "shows the commands",
+        run: help,
+    },
+    Command {
+        name: "mem",
+        usage: "<address> [length]",
+        description: "dumps the memory at the virtual address",
+        run: dump_memory,
+    },
+    Command {
+        name: "pt",
+        usage: "<address>",
+        description: "shows the page table entries that map the virtual address",
+        run: page_tables,
+    },
+    Command {
+        name: "frames",
+        usage: "",
+        description: "shows the free physical memory and heap memory",
+        run: frames,
+    },
+    Command {
+        name: "acpi",
+        usage: "[signature]",
+        description: "lists the ACPI tables or dumps the table with the signature",
+        run: acpi_tables,
+    },
+    Command {
+        name: "pci",
+        usage: "[address]",
+        description: "lists the PCI functions or shows the BARs and capabilities of the function",
+        run: pci_devices,
+    },
+    Command {
+        name: "regs",
+        usage: "",
+        description: "shows the control registers and segment registers",
+        run: registers,
+    },
+    Command {
+        name: "cpuid",
+        usage: "",
+        description: "shows the processor model and its features",
+        run: cpuid,
+    },
+    Command {
+        name: "ps",
+        usage: "",
+        description: "lists the processes",
+        run: processes,
+    },
+    Command {
+        name: "threads",
+        usage: "",
+        description: "lists the threads and their states",
+        run: threads,
+    },
+    Command {
+        name: "log",
+        usage: "[count]",
+        description: "shows the newest records of the log buffer",
+        run: log_records,
+    },
+    Command {
+        name: "loglevel",
+        usage: "[<level> | <module>=<level> | <module>=]",
+        description: "shows or changes the log levels",
+        run: log_level,
+    },
+    Command {
+        name: "console",
+        usage: "[<sink> <level> | <sink> on | <sink> off]",
+        description: "shows or changes the console sinks",
+        run: console_sinks,
+    },
+    Command {
+        name: "reboot",
+        usage: "",
+        description: "restarts the machine",
+        run: reboot,
+    },
+    Command {
+        name: "poweroff",
+        usage: "",
+        description: "turns the machine off",
+        run: power_off,
+    },
+    Command {
+        name: "exit",
+        usage: "",
+        description: "leaves the shell and lets the kernel continue",
+        run: exit,
+    },
+];
+
+/// The errors that can occur when running a command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommandError {
+    /// There is no command with the name.
+    UnknownCommand,
+    /// The arguments don't match the usage of the command.
+    InvalidArguments,
+    /// The memory is not mapped.
+    NotMapped,
+    /// The firmware doesn't provide ACPI tables.
+    NoAcpi,
+    /// There is no ACPI table with the signature.
+    NoSuchTable,
+    /// There is no PCI function at the address.
+    NoSuchDevice,
+    /// There is no console sink with the name.
+    NoSuchSink,
+    /// The log level cannot be changed.
+    LogFilter(log_filter::LogFilterError),
+    /// The kernel cannot continue after a panic.
+    AfterPanic,
+    /// The data is in use by the interrupted code.
+    Busy,
+    /// The machine could not be reset.
+    ResetFailed,
+    /// The machine could not be turned off.
+    PowerOffFailed,
+    /// The output could not be written.
+    Output,
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandError::UnknownCommand => write!(f, "unknown command, type `help` for a list"),
+            CommandError::InvalidArguments => write!(f, "invalid arguments"),
+            CommandError::NotMapped => write!(f, "the memory is not mapped"),
+            CommandError::NoAcpi => write!(f, "the firmware doesn't provide ACPI tables"),
+            CommandError::NoSuchTable => write!(f, "there is no ACPI table with this signature"),
+            CommandError::NoSuchDevice => write!(f, "there is no PCI function at this address"),
+            CommandError::NoSuchSink => write!(f, "there is no console sink with this name"),
+            CommandError::LogFilter(error) => write!(f, "{}", error),
+            CommandError::AfterPanic => write!(f, "the kernel cannot continue after a panic"),
+            CommandError::Busy => write!(f, "busy, the data is in use by the interrupted code"),
+            CommandError::ResetFailed => write!(f, "the machine could not be reset"),
+            CommandError::PowerOffFailed => write!(f, "the machine could not be turned off"),
+            CommandError::Output => write!(f, "the output could not be written"),
+        }
+    }
+}
+
+impl From<fmt::Error> for CommandError {
+    fn from(_: fmt::Error) -> CommandError {
+        CommandError::Output
+    }
+}
+
+/// What the shell does after a command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The shell reads the next command.
+    Continue,
+    /// The shell is left.
+    Exit,
+}
+
+/// A command of the shell.
+struct Command {
+    /// The name that runs the command.
+    name: &'static str,
+    /// The arguments of the command.
+    usage: &'static str,
+    /// What the command does.
+    description: &'static str,
+    /// Runs the command with the arguments and writes its output.
+    run: fn(&[&str], &mut dyn Write) -> Result<Outcome, CommandError>,
+}
+
+/// The control registers, the flags, the stack pointer and the segment selectors.
+#[repr(C)]
+#[derive(Default)]
+struct Registers {
+    /// The value of `cr0`.
+    cr0: u64,
+    /// The value of `cr2`.
+    cr2: u64,
+    /// The value of `cr3`.
+    cr3: u64,
+    /// The value of `cr4`.
+    cr4: u64,
+    /// The value of `rflags`.
+    rflags: u64,
+    /// The stack pointer of the caller.
+    rsp: u64,
+    /// The code segment selector.
+    cs: u64,
+    /// The stack segment selector.
+    ss: u64,
+    /// The data segment selector.
+    ds: u64,
+    /// The extra segment selector.
+    es: u64,
+    /// The `fs` segment selector.
+    fs: u64,
+    /// The `gs` segment selector.
+    gs: u64,
+}
+
+/// Writes the output of the shell to the first serial port.
+struct SerialOutput {
+    /// Whether the shell runs after a panic, which writes to the UART without taking the lock of the port.
+    after_panic: bool,
+}
+
+impl Write for SerialOutput {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        if self.after_panic {
+            serial::write_unlocked(0, string.as_bytes())
+        } else {
+            serial::write(0, string.as_bytes())
+        }
+        .map_err(|_| fmt::Error)
+    }
+}
+
+extern "sysv64" {
+    /// Stores the current register values in `registers`.
+    fn beetle_read_registers(registers: *mut Registers);
+}
+
+// The offsets used here must match the layout of `Registers`.
+global_asm!(
+    "
+    .intel_syntax noprefix
+    .text
+    .global beetle_read_registers
+beetle_read_registers:
+    mov rax, cr0
+    mov [rdi], rax
+    mov rax, cr2
+    mov [rdi + 8], rax
+    mov rax, cr3
+    mov [rdi + 16], rax
+    mov rax, cr4
+    mov [rdi + 24], rax
+    pushfq
+    pop rax
+    mov [rdi + 32], rax
+    lea rax, [rsp + 8]
+    mov [rdi + 40], rax
+    xor eax, eax
+    mov ax, cs
+    mov [rdi + 48], rax
+    mov ax, ss
+    mov [rdi + 56], rax
+    mov ax, ds
+    mov [rdi + 64], rax
+    mov ax, es
+    mov [rdi + 72], rax
+    mov ax, fs
+    mov [rdi + 80], rax
+    mov ax, gs
+    mov [rdi + 88], rax
+    ret
+"
+);
+
+/// Asks for the shell to be entered once the current interrupt was handled.
+///
+/// The shell must not run in an interrupt handler, since it waits for input with the interrupted code stopped.
+pub fn request() {
+    REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Runs the shell if it was requested since the last call.
+///
+/// This is called after interrupts were handled, outside of the interrupt handlers.
+pub fn run_if_requested() {
+    if REQUESTED.swap(false, Ordering::SeqCst) {
+        run(false);
+    }
+}
+
+/// Runs the shell on the first serial port until it is left with `exit`.
+///
+/// This must not be called from an interrupt handler, use `request` there instead.
+/// After a panic the shell cannot be left and it uses the UART directly, since the interrupted code may hold its lock.
+/// Nothing happens if the first serial port is missing or if the shell is already running,
+/// unless it is entered because of a panic.
+pub fn run(after_panic: bool) {
+    if !serial::is_present(0) || (RUNNING.swap(true, Ordering::SeqCst) && !after_panic) {
+        return;
+    }
+
+    let interrupts_enabled = Arch::interrupts_enabled();
+    Arch::disable_interrupts();
+
+    let mut output = SerialOutput { after_panic };
+    let mut input = LineDiscipline::new();
+    let mut buffer = [0; MAXIMUM_LINE_LENGTH];
+
+    if after_panic {
+        writeln!(output, "\nThe kernel panicked, entering the debug shell.").ok();
+    } else {
+        writeln!(
+            output,
+            "\nEntering the debug shell, type `exit` to leave it."
+        )
+        .ok();
+    }
+    output.write_str(PROMPT).ok();
+
+    loop {
+        let count = if after_panic {
+            serial::read_unlocked(0, &mut buffer)
+        } else {
+            serial::read(0, &mut buffer)
+        }
+        .unwrap_or(0);
+
+        if count == 0 {
+            // GDB can still stop the kernel while the shell waits with interrupts disabled.
+            gdb::poll();
+            spin_loop_hint();
+            continue;
+        }
+
+        for &byte in &buffer[..count] {
+            if let Some(edit) = input.receive_byte(byte) {
+                write!(output, "{}", edit).ok();
+            }
+        }
+
+        while let Some(line) = input.read_line() {
+            match execute(line.as_str(), &mut output, after_panic) {
+                Ok(Outcome::Continue) => (),
+                Ok(Outcome::Exit) => {
+                    RUNNING.store(false, Ordering::SeqCst);
+                    Arch::set_interrupts_enabled(interrupts_enabled);
+
+                    return;
+                }
+                Err(error) => {
+                    writeln!(output, "error: {}", error).ok();
+                }
+            }
+
+            output.write_str(PROMPT).ok();
+        }
+    }
+}
+
+/// Runs the command on the line and writes its output.
+///
+/// After a panic the shell cannot be left, so `exit` fails.
+pub fn execute(
+    line: &str,
+    output: &mut dyn Write,
+    after_panic: bool,
+) -> Result<Outcome, CommandError> {
+    let mut words = line.split_whitespace();
+
+    let name = match words.next() {
+        Some(name) => name,
+        None => return Ok(Outcome::Continue),
+    };
+
+    let mut arguments = [""; MAXIMUM_ARGUMENT_COUNT];
+    let mut argument_count = 0;
+
+    for word in words {
+        if argument_count == MAXIMUM_ARGUMENT_COUNT {
+            return Err(CommandError::InvalidArguments);
+        }
+
+        arguments[argument_count] = word;
+        argument_count += 1;
+    }
+
+    let command = COMMANDS
+        .iter()
+        .find(|command| command.name == name)
+        .ok_or(CommandError::UnknownCommand)?;
+
+    match (command.run)(&arguments[..argument_count], output) {
+        Ok(Outcome::Exit) if after_panic => Err(CommandError::AfterPanic),
+        Err(CommandError::InvalidArguments) => {
+            writeln!(output, "usage: {} {}", command.name, command.usage)?;
+
+            Err(CommandError::InvalidArguments)
+        }
+        result => result,
+    }
+}
+
+/// Parses a number, which is hexadecimal if it starts with `0x` and decimal otherwise.
+fn parse_number(argument: &str) -> Result<usize, CommandError> {
+    if argument.starts_with("0x") {
+        usize::from_str_radix(&argument[2..], 16)
+    } else {
+        usize::from_str_radix(argument, 10)
+    }
+    .map_err(|_| CommandError::InvalidArguments)
+}
+
+/// Writes a row of a memory dump, showing the bytes as hexadecimal numbers and as ASCII characters.
+fn write_row(output: &mut dyn Write, address: usize, bytes: &[u8]) -> fmt::Result {
+    write!(output, "{:016x}:", address)?;
+
+    for index in 0..ROW_LENGTH {
+        match bytes.get(index) {
+            Some(byte) => write!(output, " {:02x}", byte)?,
+            None => output.write_str("   ")?,
+        }
+    }
+
+    output.write_str("  ")?;
+
+    for &byte in bytes {
+        if byte == b' ' || byte.is_ascii_graphic() {
+            output.write_char(byte as char)?;
+        } else {
+            output.write_char('.')?;
+        }
+    }
+
+    output.write_char('\n')
+}
+
+/// Writes a memory dump of the bytes, labeling them with addresses starting at `address`.
+fn write_dump(output: &mut dyn Write, address: usize, bytes: &[u8]) -> fmt::Result {
+    for (index, row) in bytes.chunks(ROW_LENGTH).enumerate() {
+        write_row(output, address + index * ROW_LENGTH, row)?;
+    }
+
+    Ok(())
+}
+
+/// Shows the commands.
+fn help(arguments: &[&str], output: &mut dyn Write) -> Result<Outcome, CommandError> {
+    if !arguments.is_empty() {
+        return Err(CommandError::InvalidArguments);
+    }
+
+    for command in COMMANDS.iter() {
+        let usage_length = command.name.len() + 1 + command.usage.len();
+
+        write!(output, "{} {}", command.name, command.usage)?;
+
+        // The descriptions are aligned, unless the usage is too long.
+        for _ in usage_length..24 {
+            output.write_char(' ')?;
+        }
+
+        writeln!(output, " {}", command.description)?;
+    }
+
+    Ok(Outcome::Continue)
+}
+
+/// Dumps the memory at the virtual address.
+///
+/// The memory is read byte by byte, which may have side effects for memory mapped devices.
+fn dump_memory(arguments: &[&str], output: &mut dyn Write) -> Result<Outcome, CommandError> {
+    let (start, length) = match arguments {
+        [address] => (parse_number(address)?, DEFAULT_DUMP_LENGTH),
+        [address, length] => (parse_number(address)?, parse_number(length)?),
+        _ => return Err(CommandError::InvalidArguments),
+    };
+
+    let end = start
+        .checked_add(length.min(MAXIMUM_DUMP_LENGTH))
+        .ok_or(CommandError::InvalidArguments)?;
+
+    let mut row = [0; ROW_LENGTH];
+    let mut mapped_page = None;
+    let mut row_start = start;
+
+    while row_start < end {
+        let row_end = end.min(row_start + ROW_LENGTH);
+
+        for (byte, address) in row.iter_mut().zip(row_start..row_end) {
+            let page = address & !(PAGE_SIZE - 1);
+
+            if mapped_page != Some(page) {
+                if !memory::is_mapped(VirtualAddress::new(page)) {
+                    return Err(CommandError::NotMapped);
+                }
+
+                mapped_page = Some(page);
+            }
+
+            // This is safe, because the page is mapped and only read.
+            *byte = unsafe { ptr::read_volatile(address as *const u8) };
+        }
+
+        write_row(output, row_start, &row[..row_end - row_start])?;
+
+        row_start = row_end;
+    }
+
+    Ok(Outcome::Continue)
+}
+
+/// Shows the page table entries that map the virtual address.
+fn page_tables(arguments: &[&str], output: &mut dyn Write) -> Result<Outcome, CommandError> {
+    let address = match arguments {
+        [address] => parse_number(address)?,
+        _ => return Err(CommandError::InvalidArguments),
+    };
+
+    let mut result = Ok(());
+    let mut mapping = None;
+
+    memory::walk_active_table(
+        VirtualAddress::new(address),
+        |level, index, frame, flags| {
+            if result.is_ok() {
+                result = writeln!(
+                    output,
+                    "{:<4} {:3}: {:#014x} {:?}",
+                    LEVEL_NAMES[level - 1],
+                    index,
+                    frame.as_usize(),
+                    flags
+                );
+            }
+
+            if flags.contains(EntryFlags::PRESENT)
+                && (level == 1 || flags.contains(EntryFlags::HUGE_PAGE))
+            {
+                let page_size = PAGE_SIZE << (9 * (level - 1));
+
+                mapping = Some(frame + (address & (page_size - 1)));
+            }
+        },
+    );
+
+    result?;
+
+    match mapping {
+        Some(physical_address) => writeln!(output, "{:#x} maps to {}", address, physical_address)?,
+        None => writeln!(output, "{:#x} is not mapped", address)?,
+    }
+
+    Ok(Outcome::Continue)
+}
+
+/// Shows the free physical memory and heap memory.
+fn frames(arguments: &[&str], output: &mut dyn Write) -> Result<Outcome, CommandError> {
+    if !arguments.is_empty() {
+        return Err(CommandError::InvalidArguments);
+    }
+
+    let free_frames = FRAME_ALLOCATOR
+        .try_lock()
+        .ok_or(CommandError::Busy)?
+        .free_frames();
+    let free_heap_memory = heap::try_free_memory().ok_or(CommandError::Busy)?;
+
+    writeln!(
+        output,
+        "free frames: {} ({}B)",
+        free_frames,
+        SizeFormatterBinary::new((free_frames * PAGE_SIZE) as u64)
+    )?;
+    writeln!(
+        output,
+        "free heap memory: {}B",
+        SizeFormatterBinary::new(free_heap_memory as u64)
+    )?;
+
+    Ok(Outcome::Continue)
+}
+
+/// Lists the ACPI tables or dumps the table with the signature.
+fn acpi_tables(arguments: &[&str], output: &mut dyn Write) -> Result<Outcome, CommandError> {
+    let root = acpi::root_table().ok_or(CommandError::NoAcpi)?;
+
+    match arguments {
+        [] => {
+            writeln!(
+                output,
+                "ACPI revision {}",
+                acpi::revision().unwrap_or_default()
+            )?;
+
+            for table in Some(root).into_iter().chain(acpi::tables()) {
+                writeln!(
+                    output,
+                    "{} at {:#014x}, {:6} bytes, revision {:2}, {:6} {:8}{}",
+                    table.signature(),
+                    table.address().as_usize(),
+                    table.length(),
+                    table.revision(),
+                    table.oem_id(),
+                    table.oem_table_id(),
+                    if table.is_valid() {
+                        ""
+                    } else {
+                        ", invalid checksum"
+                    }
+                )?;
+            }
+        }
+        [signature] => {
+            let table = if *signature == root.signature() {
+                root
+            } else {
+                acpi::find(signature).ok_or(CommandError::NoSuchTable)?
+            };
+
+            write_dump(output, table.address().as_usize(), table.bytes())?;
+        }
+        _ => return Err(CommandError::InvalidArguments),
+    }
+
+    Ok(Outcome::Continue)
+}
+
+/// Lists the PCI functions or shows the BARs and capabilities of the function at the address.
+fn pci_devices(arguments: &[&str], output: &mut dyn Write) -> Result<Outcome, CommandError> {
+    match arguments {
+        [] => {
+            for device in pci::devices() {
+                writeln!(
+                    output,
+                    "{} {:04x}:{:04x} class {:02x}.{:02x}.{:02x} revision {:02x}",
+                    device.address(),
+                    device.vendor_id(),
+                    device.device_id(),
+                    device.class(),
+                    device.subclass(),
+                    device.interface(),
+                    device.revision()
+                )?;
+            }
+        }
+        [address] => {
+            let address = address
+                .parse()
+                .map_err(|_| CommandError::InvalidArguments)?;
+            let device = pci::get(address).ok_or(CommandError::NoSuchDevice)?;
+
+            for (index, bar) in device.bars().iter().enumerate() {
+                match bar {
+                    Some(Bar::Memory {
+                        address,
+                        size,
+                        prefetchable,
+                        is_64_bit,
+                    }) => writeln!(
+                        output,
+                        "BAR{} memory at {:#014x}, {}B{}{}",
+                        index,
+                        address.as_usize(),
+                        SizeFormatterBinary::new(*size as u64),
+                        if *is_64_bit { ", 64 bit" } else { "" },
+                        if *prefetchable { ", prefetchable" } else { "" }
+                    )?,
+                    Some(Bar::Io { port, size }) => writeln!(
+                        output,
+                        "BAR{} I/O ports at {:#06x}, {} ports",
+                        index, port, size
+                    )?,
+                    None => (),
+                }
+            }
+
+            for capability in device.capabilities() {
+                writeln!(
+                    output,
+                    "capability {:02x} at {:#04x}: {}",
+                    capability.id(),
+                    capability.offset(),
+                    capability.name()
+                )?;
+            }
+        }
+        _ => return Err(CommandError::InvalidArguments),
+    }
+
+    Ok(Outcome::Continue)
+}
+
+/// Shows the control registers and segment registers.
+fn registers(arguments: &[&str], output: &mut dyn Write) -> Result<Outcome, CommandError> {
+    if !arguments.is_empty() {
+        return Err(CommandError::InvalidArguments);
+    }
+
+    let mut registers = Registers::default();
+
+    // This is safe, because the registers are only read.
+    let (efer, fs_base, gs_base, kernel_gs_base) = unsafe {
+        beetle_read_registers(&mut registers);
+
+        (
+            Msr::new(EFER).read(),
+            Msr::new(FS_BASE).read(),
+            Msr::new(GS_BASE).read(),
+            Msr::new(KERNEL_GS_BASE).read(),
+        )
+    };
+
+    writeln!(
+        output,
+        "cr0 {:016x} cr2 {:016x} cr3 {:016x} cr4 {:016x}",
+        registers.cr0, registers.cr2, registers.cr3, registers.cr4
+    )?;
+    writeln!(
+        output,
+        "rflags {:016x} rsp {:016x} efer {:016x}",
+        registers.rflags, registers.rsp, efer
+    )?;
+    writeln!(
+        output,
+        "cs {:04x} ss {:04x} ds {:04x} es {:04x} fs {:04x} gs {:04x}",
+        registers.cs, registers.ss, registers.ds, registers.es, registers.fs, registers.gs
+    )?;
+    writeln!(
+        output,
+        "fs base {:016x} gs base {:016x} kernel gs base {:016x}",
+        fs_base, gs_base, kernel_gs_base
+    )?;
+
+    Ok(Outcome::Continue)
+}
+
+/// Shows the processor model and its features.
+fn cpuid(arguments: &[&str], output: &mut dyn Write) -> Result<Outcome, CommandError> {
+    if !arguments.is_empty() {
+        return Err(CommandError::InvalidArguments);
+    }
+
+    let cpuid = CpuId::new();
+    let mut features = [("", false); 32];
+    let mut feature_count = 0;
+
+    if let Some(vendor_info) = cpuid.get_vendor_info() {
+        writeln!(output, "vendor: {}", vendor_info)?;
+    }
+
+    if let Some(extended_info) = cpuid.get_extended_function_info() {
+        if let Some(brand) = extended_info.processor_brand_string() {
+            writeln!(output, "model: {}", brand.trim())?;
+        }
+
+        if let (Some(physical), Some(linear)) = (
+            extended_info.physical_address_bits(),
+            extended_info.linear_address_bits(),
+        ) {
+            writeln!(
+                output,
+                "address bits: {} physical, {} virtual",
+                physical, linear
+            )?;
+        }
+
+        for &feature in &[
+            ("long_mode", extended_info.has_64bit_mode()),
+            ("syscall", extended_info.has_syscall_sysret()),
+            ("nx", extended_info.has_execute_disable()),
+            ("1gb_pages", extended_info.has_1gib_pages()),
+            ("rdtscp", extended_info.has_rdtscp()),
+            ("invariant_tsc", extended_info.has_invariant_tsc()),
+        ] {
+            features[feature_count] = feature;
+            feature_count += 1;
+        }
+    }
+
+    if let Some(info) = cpuid.get_feature_info() {
+        let mut family = u32::from(info.family_id());
+        let mut model = u32::from(info.model_id());
+
+        if family == 0xf {
+            family += u32::from(info.extended_family_id());
+        }
+        if family == 0x6 || family >= 0xf {
+            model += u32::from(info.extended_model_id()) << 4;
+        }
+
+        writeln!(
+            output,
+            "family {:#x}, model {:#x}, stepping {}",
+            family,
+            model,
+            info.stepping_id()
+        )?;
+
+        for &feature in &[
+            ("fpu", info.has_fpu()),
+            ("pse", info.has_pse()),
+            ("tsc", info.has_tsc()),
+            ("msr", info.has_msr()),
+            ("pae", info.has_pae()),
+            ("apic", info.has_apic()),
+            ("pge", info.has_pge()),
+            ("mmx", info.has_mmx()),
+            ("sse", info.has_sse()),
+            ("sse2", info.has_sse2()),
+            ("sse3", info.has_sse3()),
+            ("ssse3", info.has_ssse3()),
+            ("sse4.1", info.has_sse41()),
+            ("sse4.2", info.has_sse42()),
+            ("x2apic", info.has_x2apic()),
+            ("tsc_deadline", info.has_tsc_deadline()),
+            ("popcnt", info.has_popcnt()),
+            ("xsave", info.has_xsave()),
+            ("avx", info.has_avx()),
+            ("rdrand", info.has_rdrand()),
+            ("pcid", info.has_pcid()),
+        ] {
+            features[feature_count] = feature;
+            feature_count += 1;
+        }
+    }
+
+    if let Some(extended_features) = cpuid.get_extended_feature_info() {
+        for &feature in &[
+            ("fsgsbase", extended_features.has_fsgsbase()),
+            ("smep", extended_features.has_smep()),
+            ("smap", extended_features.has_smap()),
+            ("avx2", extended_features.has_avx2()),
+            ("invpcid", extended_features.has_invpcid()),
+        ] {
+            features[feature_count] = feature;
+            feature_count += 1;
+        }
+    }
+
+    output.write_str("features:")?;
+
+    for &(name, _) in features[..feature_count]
+        .iter()
+        .filter(|&&(_, present)| present)
+    {
+        write!(output, " {}", name)?;
+    }
+
+    output.write_char('\n')?;
+
+    Ok(Outcome::Continue)
+}
+
+/// Lists the processes.
+fn processes(arguments: &[&str], output: &mut dyn Write) -> Result<Outcome, CommandError> {
+    if !arguments.is_empty() {
+        return Err(CommandError::InvalidArguments);
+    }
+
+    for process in process::processes() {
+        let state = match process.state() {
+            ProcessState::Created => "created",
+            ProcessState::Running => "running",
+            ProcessState::Exited(_) => "exited",
+        };
+
+        write!(
+            output,
+            "{:?}: {}, {} threads",
+            process.id(),
+            state,
+            process.threads().len()
+        )?;
+
+        if let Some(status) = process.exit_status() {
+            write!(output, ", exit status {:#x}", status)?;
+        }
+
+        output.write_char('\n')?;
+    }
+
+    Ok(Outcome::Continue)
+}
+
+/// Lists the threads and their states.
+fn threads(arguments: &[&str], output: &mut dyn Write) -> Result<Outcome, CommandError> {
+    if !arguments.is_empty() {
+        return Err(CommandError::InvalidArguments);
+    }
+
+    let current = scheduler::current().map(|thread| thread.id());
+
+    for process in process::processes() {
+        for thread in process.threads() {
+            let context = thread.context();
+
+            writeln!(
+                output,
+                "{:?} of {:?}: {}{}, instruction pointer {}, stack pointer {}",
+                thread.id(),
+                process.id(),
+                thread.state().description(),
+                if current == Some(thread.id()) {
+                    " (current)"
+                } else {
+                    ""
+                },
+                context.instruction_pointer(),
+                context.stack_pointer()
+            )?;
+        }
+    }
+
+    Ok(Outcome::Continue)
+}
+
+/// Shows the newest records of the log buffer.
+fn log_records(arguments: &[&str], output: &mut dyn Write) -> Result<Outcome, CommandError> {
+    let count = match arguments {
+        [] => DEFAULT_RECORD_COUNT,
+        [count] => parse_number(count)?,
+        _ => return Err(CommandError::InvalidArguments),
+    };
+
+    let end = log_buffer::next_sequence();
+
+    for record in log_buffer::records_since(end.saturating_sub(count)) {
+        writeln!(output, "{}", record)?;
+    }
+
+    Ok(Outcome::Continue)
+}
+
+/// Shows or changes the log levels.
+fn log_level(arguments: &[&str], output: &mut dyn Write) -> Result<Outcome, CommandError> {
+    let directive = match arguments {
+        [] => {
+            let mut result = Ok(());
+
+            log_filter::try_for_each_level(|module, level| {
+                if result.is_ok() {
+                    result = writeln!(output, "{}: {}", module.unwrap_or("default"), level);
+                }
+            })
+            .map_err(|_| CommandError::Busy)?;
+
+            result?;
+
+            return Ok(Outcome::Continue);
+        }
+        [directive] => directive,
+        _ => return Err(CommandError::InvalidArguments),
+    };
+
+    // The filter is changed without waiting, since the shell may have interrupted a user of the filter.
+    match log_filter::try_apply(directive) {
+        Ok(true) => (),
+        Ok(false) => writeln!(
+            output,
+            "{} has no log level of its own",
+            &directive[..directive.len() - 1]
+        )?,
+        Err(LogFilterError::InvalidDirective) => return Err(CommandError::InvalidArguments),
+        Err(LogFilterError::Busy) => return Err(CommandError::Busy),
+        Err(error) => return Err(CommandError::LogFilter(error)),
+    }
+
+    Ok(Outcome::Continue)
+}
+
+/// Shows or changes the console sinks.
+fn console_sinks(arguments: &[&str], output: &mut dyn Write) -> Result<Outcome, CommandError> {
+    let found = match arguments {
+        [] => {
+            for sink in console::sinks() {
+                writeln!(
+                    output,
+                    "{}: {}{}",
+                    sink.name(),
+                    sink.level(),
+                    if sink.enabled() { "" } else { " (disabled)" }
+                )?;
+            }
+
+            return Ok(Outcome::Continue);
+        }
+        [name, "on"] => console::set_enabled(name, true),
+        [name, "off"] => console::set_enabled(name, false),
+        [name, level] => {
+            let level = level
+                .parse::<LevelFilter>()
+                .map_err(|_| CommandError::InvalidArguments)?;
+
+            console::set_level(name, level)
+        }
+        _ => return Err(CommandError::InvalidArguments),
+    };
+
+    if found {
+        Ok(Outcome::Continue)
+    } else {
+        Err(CommandError::NoSuchSink)
+    }
+}
+
+/// Resets the machine through the reset control register of the chipset.
+///
+/// This returns if the chipset doesn't have the register.
+fn reset_chipset() {
+    let mut port = Port::<u8>::new(RESET_CONTROL_PORT);
+
+    // This is safe, because the register only resets the machine.
+    unsafe {
+        port.write(SYSTEM_RESET);
+        port.write(SYSTEM_RESET | RESET_PROCESSOR | FULL_RESET);
+    }
+}
+
+/// Restarts the machine.
+///
+/// If the firmware can't reset the machine, the chipset and the PS/2 controller are tried.
+fn reboot(arguments: &[&str], _: &mut dyn Write) -> Result<Outcome, CommandError> {
+    if !arguments.is_empty() {
+        return Err(CommandError::InvalidArguments);
+    }
+
+    let status = runtime::reset_system(ResetType::Cold);
+    log::warn!("The firmware could not reset the machine ({:?}).", status);
+
+    reset_chipset();
+    ps2::pulse_reset_line();
+
+    Err(CommandError::ResetFailed)
+}
+
+/// Turns the machine off.
+fn power_off(arguments: &[&str], _: &mut dyn Write) -> Result<Outcome, CommandError> {
+    if !arguments.is_empty() {
+        return Err(CommandError::InvalidArguments);
+    }
+
+    let status = runtime::reset_system(ResetType::Shutdown);
+    log::warn!(
+        "The firmware could not turn the machine off ({:?}).",
+        status
+    );
+
+    Err(CommandError::PowerOffFailed)
+}
+
+/// Leaves the shell.
+fn exit(arguments: &[&str], _: &mut dyn Write) -> Result<Outcome, CommandError> {
+    if !arguments.is_empty() {
+        return Err(CommandError::InvalidArguments);
+    }
+
+    Ok(Outcome::Exit)
+}
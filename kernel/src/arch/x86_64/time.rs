@@ -0,0 +1,74 @@
+//! Provides the clock sources and the timer interrupt of the x86_64 architecture.
+//!
+//! The invariant time stamp counter is preferred, since it is the cheapest to read.
+//! Otherwise the HPET is used, and the PIT if there is no HPET.
+//! The time stamp counter is calibrated against one of the other two.
+//! The counter of the PIT wraps around every 55 milliseconds, so it loses time if the clock is read less often.
+//! The timer interrupt is raised by the timer of the local APIC.
+
+mod apic_timer;
+mod hpet;
+mod pit;
+mod rtc;
+mod tsc;
+
+pub use self::apic_timer::set_deadline;
+
+use self::{hpet::Hpet, pit::Pit, tsc::Tsc};
+use crate::{
+    sync::GlobalRuntimeConfiguration,
+    time::{self, ClockSource, DateTime},
+};
+
+/// The HPET, if the machine has one.
+static HPET: GlobalRuntimeConfiguration<Option<Hpet>> = GlobalRuntimeConfiguration::new();
+
+/// The PIT.
+static PIT: GlobalRuntimeConfiguration<Pit> = GlobalRuntimeConfiguration::new();
+
+/// The time stamp counter, if it is invariant.
+static TSC: GlobalRuntimeConfiguration<Tsc> = GlobalRuntimeConfiguration::new();
+
+/// Returns the initialized value of the configuration.
+fn get<T>(configuration: &'static GlobalRuntimeConfiguration<T>) -> &'static T {
+    configuration
+        .get()
+        .expect("The clock source was not initialized.")
+}
+
+/// Starts the clocks and the timer interrupt.
+///
+/// The wall clock is set to `now` if the firmware told the time, or to the time of the real time clock otherwise.
+pub fn init(now: Option<DateTime>) {
+    HPET.init(Hpet::new());
+    PIT.init(Pit::new());
+
+    let reference: &'static dyn ClockSource = match get(&HPET) {
+        Some(hpet) => hpet,
+        None => {
+            log::warn!("No HPET found, falling back to the PIT.");
+
+            get::<Pit>(&PIT)
+        }
+    };
+
+    let source: &'static dyn ClockSource = if tsc::is_invariant() {
+        TSC.init(Tsc::calibrate(reference));
+
+        get::<Tsc>(&TSC)
+    } else {
+        reference
+    };
+
+    time::init(source);
+    apic_timer::init(TSC.get().map(Tsc::frequency));
+
+    match now.or_else(rtc::read) {
+        Some(now) => {
+            time::set_wall_clock(now);
+
+            log::info!("The current time is {}.", now);
+        }
+        None => log::warn!("Could not read the current time."),
+    }
+}
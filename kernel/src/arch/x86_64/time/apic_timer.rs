@@ -0,0 +1,166 @@
+//! Provides the timer of the local APIC, which raises the timer interrupt at the next deadline.
+//!
+//! The timer is programmed on demand for a single deadline instead of ticking periodically.
+//! If the processor supports it and the invariant time stamp counter drives the monotonic clock,
+//! the deadline is given directly as a value of the time stamp counter.
+//! Otherwise the timer counts down in one-shot mode at a frequency that is calibrated against the clock source.
+
+use core::cmp;
+use raw_cpuid::CpuId;
+use x86_64_crate::registers::model_specific::Msr;
+
+use super::{
+    super::apic::{local_apic_register, TIMER_VECTOR},
+    tsc,
+};
+use crate::{
+    sync::GlobalRuntimeConfiguration,
+    time::{self, measure_frequency, Duration, Instant},
+};
+
+/// The offset of the local vector table entry of the timer.
+const TIMER_ENTRY_REGISTER: usize = 0x320;
+
+/// The offset of the initial count register of the timer.
+const INITIAL_COUNT_REGISTER: usize = 0x380;
+
+/// The offset of the current count register of the timer.
+const CURRENT_COUNT_REGISTER: usize = 0x390;
+
+/// The offset of the divide configuration register of the timer.
+const DIVIDE_CONFIGURATION_REGISTER: usize = 0x3e0;
+
+/// Divides the bus frequency by 16 for the timer.
+const DIVIDE_BY_16: u32 = 0b0011;
+
+/// Masks the timer interrupt in the local vector table entry.
+const TIMER_MASKED: u32 = 1 << 16;
+
+/// Selects the TSC-deadline mode in the local vector table entry.
+const TSC_DEADLINE_MODE: u32 = 0b10 << 17;
+
+/// The model specific register that holds the deadline in TSC-deadline mode.
+const TSC_DEADLINE: u32 = 0x6e0;
+
+/// The time the frequency of the timer is measured for.
+const CALIBRATION_TIME: Duration = Duration::from_millis(10);
+
+/// The number of nanoseconds in a second.
+const NANOSECONDS_PER_SECOND: u128 = 1_000_000_000;
+
+/// The ways the timer can be programmed.
+#[derive(Clone, Copy, Debug)]
+enum Mode {
+    /// The deadline is written as a value of the time stamp counter, which runs at the given frequency.
+    TscDeadline(u64),
+    /// The timer counts down from the initial count at the given frequency.
+    OneShot(u64),
+}
+
+/// The mode the timer is programmed in.
+static MODE: GlobalRuntimeConfiguration<Mode> = GlobalRuntimeConfiguration::new();
+
+/// Returns the number of ticks at the given frequency that pass until the deadline, or zero if it passed.
+fn ticks_until(deadline: Instant, frequency: u64) -> u64 {
+    let nanoseconds = deadline.duration_since(Instant::now()).as_nanos();
+
+    cmp::min(
+        nanoseconds * u128::from(frequency) / NANOSECONDS_PER_SECOND,
+        u128::from(u64::max_value()),
+    ) as u64
+}
+
+/// Initializes the timer of the current processor.
+///
+/// `tsc_frequency` is the frequency of the time stamp counter if it drives the monotonic clock.
+pub fn init(tsc_frequency: Option<u64>) {
+    let tsc_deadline = CpuId::new()
+        .get_feature_info()
+        .map_or(false, |features| features.has_tsc_deadline());
+
+    let mode = match tsc_frequency {
+        Some(frequency) if tsc_deadline => {
+            // This is safe, because the timer interrupt is handled.
+            unsafe {
+                local_apic_register(TIMER_ENTRY_REGISTER)
+                    .write_volatile(TSC_DEADLINE_MODE | TIMER_VECTOR as u32)
+            };
+
+            Mode::TscDeadline(frequency)
+        }
+        _ => {
+            let reference = time::clock_source().expect("The monotonic clock was not started.");
+
+            // This is safe, because the timer interrupt is masked while the timer is calibrated.
+            unsafe {
+                local_apic_register(DIVIDE_CONFIGURATION_REGISTER).write_volatile(DIVIDE_BY_16);
+                local_apic_register(TIMER_ENTRY_REGISTER)
+                    .write_volatile(TIMER_MASKED | TIMER_VECTOR as u32);
+                local_apic_register(INITIAL_COUNT_REGISTER).write_volatile(u32::max_value());
+            }
+
+            let frequency = measure_frequency(
+                || {
+                    // This is safe, because reading the current count has no side effects.
+                    let count =
+                        unsafe { local_apic_register(CURRENT_COUNT_REGISTER).read_volatile() };
+
+                    u64::from(u32::max_value() - count)
+                },
+                reference,
+                CALIBRATION_TIME,
+            );
+
+            // This is safe, because the timer interrupt is handled.
+            unsafe {
+                local_apic_register(INITIAL_COUNT_REGISTER).write_volatile(0);
+                local_apic_register(TIMER_ENTRY_REGISTER).write_volatile(TIMER_VECTOR as u32);
+            }
+
+            Mode::OneShot(frequency)
+        }
+    };
+
+    log::debug!("Using the APIC timer in {:?} mode.", mode);
+
+    MODE.init(mode);
+}
+
+/// Programs the timer to raise the timer interrupt once the deadline has passed or stops it.
+///
+/// The timer does nothing before it is initialized.
+pub fn set_deadline(deadline: Option<Instant>) {
+    match (MODE.get(), deadline) {
+        (Some(&Mode::TscDeadline(frequency)), Some(deadline)) => {
+            let now = tsc::read_counter();
+
+            // A deadline of zero stops the timer, while a passed deadline raises the interrupt immediately.
+            let value = cmp::max(now.saturating_add(ticks_until(deadline, frequency)), 1);
+
+            // This is safe, because the timer interrupt is handled.
+            unsafe { Msr::new(TSC_DEADLINE).write(value) };
+        }
+        (Some(&Mode::TscDeadline(_)), None) => {
+            // This is safe, because a deadline of zero only stops the timer.
+            unsafe { Msr::new(TSC_DEADLINE).write(0) };
+        }
+        (Some(&Mode::OneShot(frequency)), Some(deadline)) => {
+            // Deadlines beyond the range of the counter raise the interrupt early and the timer is programmed again.
+            let count = cmp::max(
+                cmp::min(
+                    ticks_until(deadline, frequency),
+                    u64::from(u32::max_value()),
+                ),
+                1,
+            );
+
+            // This is safe, because the timer interrupt is handled.
+            unsafe { local_apic_register(INITIAL_COUNT_REGISTER).write_volatile(count as u32) };
+        }
+        (Some(&Mode::OneShot(_)), None) => {
+            // This is safe, because an initial count of zero only stops the timer.
+            unsafe { local_apic_register(INITIAL_COUNT_REGISTER).write_volatile(0) };
+        }
+        (None, _) => (),
+    }
+}
@@ -0,0 +1,140 @@
+//! Provides the high precision event timer (HPET) as a clock source.
+//!
+//! Only the main counter is used, the comparators of the HPET stay disabled.
+//! The address of the registers is taken from the ACPI table describing the HPET.
+
+use super::super::acpi;
+use crate::{
+    arch::{Arch, Architecture},
+    memory::{PhysicalAddress, VirtualAddress},
+    time::ClockSource,
+};
+
+/// The signature of the ACPI table describing the HPET.
+const HPET_SIGNATURE: &str = "HPET";
+
+/// The offset of the generic address structure of the registers in the data of the ACPI table.
+const BASE_ADDRESS_OFFSET: usize = 4;
+
+/// The size of a generic address structure in bytes.
+const GENERIC_ADDRESS_SIZE: usize = 12;
+
+/// The offset of the address in a generic address structure.
+const GENERIC_ADDRESS_OFFSET: usize = 4;
+
+/// The address space identifier of system memory in a generic address structure.
+const SYSTEM_MEMORY: u8 = 0;
+
+/// The offset of the general capabilities and ID register.
+const CAPABILITIES_REGISTER: usize = 0x000;
+
+/// The offset of the general configuration register.
+const CONFIGURATION_REGISTER: usize = 0x010;
+
+/// The offset of the main counter value register.
+const MAIN_COUNTER_REGISTER: usize = 0x0f0;
+
+/// Set in the capabilities register if the main counter has 64 bits instead of 32.
+const COUNTER_64_BIT: u64 = 1 << 13;
+
+/// Starts the main counter when set in the configuration register.
+const ENABLE: u64 = 1 << 0;
+
+/// The largest period of the main counter allowed by the specification in femtoseconds.
+const MAXIMUM_PERIOD: u64 = 100_000_000;
+
+/// The number of femtoseconds in a second.
+const FEMTOSECONDS_PER_SECOND: u64 = 1_000_000_000_000_000;
+
+/// The high precision event timer.
+pub struct Hpet {
+    /// The address of the registers.
+    base: VirtualAddress,
+    /// The mask of the bits of the main counter.
+    mask: u64,
+    /// The frequency of the main counter in Hz.
+    frequency: u64,
+}
+
+/// Reads the physical address of the registers of the HPET from its ACPI table.
+///
+/// Returns `None` if there is no valid table or the registers are not in memory.
+fn find_address() -> Option<PhysicalAddress> {
+    let table = acpi::find(HPET_SIGNATURE).filter(|table| table.is_valid())?;
+    let base_address = table
+        .data()
+        .get(BASE_ADDRESS_OFFSET..BASE_ADDRESS_OFFSET + GENERIC_ADDRESS_SIZE)?;
+
+    if base_address[0] != SYSTEM_MEMORY {
+        return None;
+    }
+
+    let mut address = [0; 8];
+    address.copy_from_slice(&base_address[GENERIC_ADDRESS_OFFSET..]);
+
+    Some(PhysicalAddress::new(u64::from_le_bytes(address) as usize))
+}
+
+impl Hpet {
+    /// Starts the main counter of the HPET.
+    ///
+    /// Returns `None` if the machine has no HPET, which is assumed if the firmware doesn't describe one.
+    /// Whether the HPET really exists is still checked with its capabilities register.
+    pub fn new() -> Option<Hpet> {
+        let base = Arch::physical_to_virtual(find_address()?);
+
+        // This is safe, because the firmware reserved the address for the HPET and reading its capabilities has no effects.
+        let capabilities = unsafe {
+            (base + CAPABILITIES_REGISTER)
+                .as_ptr::<u64>()
+                .read_volatile()
+        };
+        let period = capabilities >> 32;
+
+        // Reads from addresses without a device return all ones.
+        if capabilities == u64::max_value() || period == 0 || period > MAXIMUM_PERIOD {
+            return None;
+        }
+
+        // This is safe, because the main counter is not used by anything else.
+        unsafe {
+            let configuration = (base + CONFIGURATION_REGISTER).as_mut_ptr::<u64>();
+
+            configuration.write_volatile(configuration.read_volatile() | ENABLE);
+        }
+
+        Some(Hpet {
+            base,
+            mask: if capabilities & COUNTER_64_BIT != 0 {
+                u64::max_value()
+            } else {
+                u64::from(u32::max_value())
+            },
+            frequency: FEMTOSECONDS_PER_SECOND / period,
+        })
+    }
+}
+
+impl ClockSource for Hpet {
+    fn name(&self) -> &'static str {
+        "HPET"
+    }
+
+    fn read(&self) -> u64 {
+        // This is safe, because reading the main counter has no side effects.
+        unsafe {
+            (self.base + MAIN_COUNTER_REGISTER)
+                .as_ptr::<u64>()
+                .read_volatile()
+                & self.mask
+        }
+    }
+
+    fn mask(&self) -> u64 {
+        self.mask
+    }
+
+    fn frequency(&self) -> u64 {
+        self.frequency
+    }
+}
@@ -0,0 +1,79 @@
+//! Provides the programmable interval timer (PIT) as a clock source.
+//!
+//! Channel 0 runs as a rate generator that counts down over its full 16 bit range.
+//! Its output raises the timer interrupt line, which stays masked in the I/O APIC.
+
+use x86_64_crate::instructions::port::Port;
+
+use crate::{sync::Mutex, time::ClockSource};
+
+/// The frequency the counters of the PIT run at in Hz.
+const FREQUENCY: u64 = 1_193_182;
+
+/// The data port of channel 0.
+const CHANNEL_0_PORT: u16 = 0x40;
+
+/// The mode and command port.
+const COMMAND_PORT: u16 = 0x43;
+
+/// Selects channel 0, low and high byte access, the rate generator mode and binary counting.
+const RATE_GENERATOR_COMMAND: u8 = 0x34;
+
+/// Latches the current count of channel 0, so that both bytes belong to the same count.
+const LATCH_COMMAND: u8 = 0x00;
+
+/// The programmable interval timer.
+pub struct Pit {
+    /// Serializes the accesses to the ports, since a count is read in two steps.
+    lock: Mutex<()>,
+}
+
+impl Pit {
+    /// Starts channel 0 counting down from its maximum.
+    pub fn new() -> Pit {
+        // This is safe, because channel 0 is not used by anything else.
+        unsafe {
+            Port::<u8>::new(COMMAND_PORT).write(RATE_GENERATOR_COMMAND);
+            // A reload value of zero is the maximum of 65536.
+            Port::<u8>::new(CHANNEL_0_PORT).write(0);
+            Port::<u8>::new(CHANNEL_0_PORT).write(0);
+        }
+
+        Pit {
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+impl ClockSource for Pit {
+    fn name(&self) -> &'static str {
+        "PIT"
+    }
+
+    fn read(&self) -> u64 {
+        let _lock = self.lock.lock();
+
+        // This is safe, because latching and reading the count has no side effects.
+        let count = unsafe {
+            let mut data_port = Port::<u8>::new(CHANNEL_0_PORT);
+
+            Port::<u8>::new(COMMAND_PORT).write(LATCH_COMMAND);
+
+            let low = data_port.read();
+            let high = data_port.read();
+
+            u64::from(low) | u64::from(high) << 8
+        };
+
+        // The counter counts down, so it is inverted to count up.
+        0x1_0000u64.wrapping_sub(count) & self.mask()
+    }
+
+    fn mask(&self) -> u64 {
+        0xffff
+    }
+
+    fn frequency(&self) -> u64 {
+        FREQUENCY
+    }
+}
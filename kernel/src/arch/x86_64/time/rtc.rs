@@ -0,0 +1,119 @@
+//! Reads the date and time from the real time clock (RTC) in the CMOS.
+//!
+//! The real time clock is assumed to keep the time in UTC.
+//! Its century register can only be found through the ACPI tables, so years are taken to be in the 21st century.
+
+use x86_64_crate::instructions::port::Port;
+
+use crate::{sync::Mutex, time::DateTime};
+
+/// The port used to select a CMOS register.
+const ADDRESS_PORT: u16 = 0x70;
+
+/// The port used to read the selected CMOS register.
+const DATA_PORT: u16 = 0x71;
+
+/// The registers that hold the seconds, minutes, hours, day, month and year, in that order.
+const TIME_REGISTERS: [u8; 6] = [0x00, 0x02, 0x04, 0x07, 0x08, 0x09];
+
+/// The status register that tells if the clock is being updated.
+const STATUS_A_REGISTER: u8 = 0x0a;
+
+/// The status register that holds the format of the time registers.
+const STATUS_B_REGISTER: u8 = 0x0b;
+
+/// Set in status register A while the time registers are being updated.
+const UPDATE_IN_PROGRESS: u8 = 1 << 7;
+
+/// Set in status register B if the time registers are binary instead of BCD encoded.
+const BINARY_MODE: u8 = 1 << 2;
+
+/// Set in status register B if the hours are counted from 0 to 23 instead of 1 to 12.
+const HOUR_24_MODE: u8 = 1 << 1;
+
+/// Set in the hour register for afternoon hours in the 12 hour mode.
+const PM_FLAG: u8 = 1 << 7;
+
+/// The number of times the time is read before giving up on getting two identical readings.
+const MAXIMUM_ATTEMPTS: usize = 10;
+
+/// Serializes accesses to the CMOS, which uses an index register.
+static CMOS_LOCK: Mutex<()> = Mutex::new(());
+
+/// Reads the CMOS register with the given index.
+fn read_register(index: u8) -> u8 {
+    // This is safe, because reading the clock registers has no side effects.
+    unsafe {
+        Port::<u8>::new(ADDRESS_PORT).write(index);
+        Port::<u8>::new(DATA_PORT).read()
+    }
+}
+
+/// Reads the raw time registers once no update is in progress.
+fn read_time_registers() -> [u8; 6] {
+    let mut values = [0; 6];
+
+    while read_register(STATUS_A_REGISTER) & UPDATE_IN_PROGRESS != 0 {}
+
+    for (value, &register) in values.iter_mut().zip(TIME_REGISTERS.iter()) {
+        *value = read_register(register);
+    }
+
+    values
+}
+
+/// Converts a BCD encoded value to binary.
+fn from_bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0xf)
+}
+
+/// Reads the current date and time.
+///
+/// Returns `None` if the clock does not hold a valid time.
+pub fn read() -> Option<DateTime> {
+    let _lock = CMOS_LOCK.lock();
+
+    // An update may start right after checking for it, so the time is read until two readings match.
+    let mut values = read_time_registers();
+    let mut attempts = 1;
+
+    loop {
+        let next_values = read_time_registers();
+
+        if next_values == values {
+            break;
+        }
+        if attempts == MAXIMUM_ATTEMPTS {
+            return None;
+        }
+
+        values = next_values;
+        attempts += 1;
+    }
+
+    let format = read_register(STATUS_B_REGISTER);
+    let [second, minute, hour, day, month, year] = values;
+    let pm = hour & PM_FLAG != 0;
+    let decode = |value| {
+        if format & BINARY_MODE != 0 {
+            value
+        } else {
+            from_bcd(value)
+        }
+    };
+
+    let mut hour = decode(hour & !PM_FLAG);
+    if format & HOUR_24_MODE == 0 {
+        hour = hour % 12 + if pm { 12 } else { 0 };
+    }
+
+    DateTime::new(
+        2000 + u16::from(decode(year)),
+        decode(month),
+        decode(day),
+        hour,
+        decode(minute),
+        decode(second),
+        0,
+    )
+}
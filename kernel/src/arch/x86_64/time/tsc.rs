@@ -0,0 +1,75 @@
+//! Provides the time stamp counter (TSC) as a clock source.
+//!
+//! The time stamp counter can only be used as a clock source if it is invariant,
+//! which means that it runs at a constant rate regardless of the power state of the processor.
+//! Its frequency is calibrated against another clock source.
+
+use core::{arch::x86_64::_rdtsc, cmp::min};
+use raw_cpuid::CpuId;
+
+use crate::time::{measure_frequency, ClockSource, Duration};
+
+/// The time the frequency is measured for in each calibration round.
+const CALIBRATION_TIME: Duration = Duration::from_millis(10);
+
+/// The number of calibration rounds.
+///
+/// The lowest measured frequency is used, since delays like system management interrupts only increase it.
+const CALIBRATION_ROUNDS: usize = 3;
+
+/// Reads the time stamp counter.
+pub fn read_counter() -> u64 {
+    // This is safe, because the time stamp counter exists on all x86_64 processors.
+    unsafe { _rdtsc() as u64 }
+}
+
+/// Checks if the time stamp counter of the processor is invariant.
+pub fn is_invariant() -> bool {
+    CpuId::new()
+        .get_extended_function_info()
+        .map_or(false, |features| features.has_invariant_tsc())
+}
+
+/// The time stamp counter.
+pub struct Tsc {
+    /// The calibrated frequency of the counter in Hz.
+    frequency: u64,
+}
+
+impl Tsc {
+    /// Calibrates the time stamp counter against the reference clock source.
+    pub fn calibrate(reference: &dyn ClockSource) -> Tsc {
+        let frequency = (0..CALIBRATION_ROUNDS).fold(u64::max_value(), |frequency, _| {
+            min(
+                frequency,
+                measure_frequency(read_counter, reference, CALIBRATION_TIME),
+            )
+        });
+
+        log::debug!(
+            "Calibrated the TSC against the {} to {} Hz.",
+            reference.name(),
+            frequency
+        );
+
+        Tsc { frequency }
+    }
+}
+
+impl ClockSource for Tsc {
+    fn name(&self) -> &'static str {
+        "TSC"
+    }
+
+    fn read(&self) -> u64 {
+        read_counter()
+    }
+
+    fn mask(&self) -> u64 {
+        u64::max_value()
+    }
+
+    fn frequency(&self) -> u64 {
+        self.frequency
+    }
+}
@@ -2,26 +2,90 @@
 //!
 //! This gets invoked, when the kernel is loaded directly by UEFI.
 
-use core::fmt::{self, Write};
-use nuefil::{memory::NamedMemoryType, system::SystemTable, Handle};
+mod protocols;
+pub mod runtime;
+
+use core::{
+    char::{decode_utf16, REPLACEMENT_CHARACTER},
+    fmt::{self, Write},
+    mem::{size_of, size_of_val},
+    ptr, slice, str,
+};
+use log::LevelFilter;
+use nuefil::{
+    guid::Guid,
+    memory::{AllocateType, NamedMemoryType},
+    status::Status,
+    system::SystemTable,
+    Handle,
+};
 use size_format::SizeFormatterBinary;
 
-use super::{early_init, BootMethod, BOOT_METHOD};
-use crate::sync::GlobalRuntimeConfiguration;
+use self::protocols::{
+    File, GraphicsOutput, LoadedImage, SimpleFileSystem, ACPI_20_TABLE, ACPI_TABLE, FILE_INFO,
+    FILE_INFO_FILE_SIZE_OFFSET, FILE_MODE_READ, GRAPHICS_OUTPUT_PROTOCOL, LOADED_IMAGE_PROTOCOL,
+    PIXEL_BIT_MASK, PIXEL_BLUE_GREEN_RED_RESERVED, PIXEL_RED_GREEN_BLUE_RESERVED,
+    SIMPLE_FILE_SYSTEM_PROTOCOL,
+};
+use super::{
+    acpi, early_init, gdb, late_init, memory, pci, ps2, serial, time, BootMethod, BOOT_METHOD,
+};
+use crate::{
+    arch::{Arch, Architecture},
+    console::{
+        self,
+        ansi::{Action, Parser},
+        framebuffer::{self, Framebuffer, PixelFormat},
+        Sink,
+    },
+    initrd,
+    memory::{heap, PhysicalAddress, FRAME_ALLOCATOR},
+    sync::{GlobalRuntimeConfiguration, Mutex},
+};
+
+/// The path of the initial ramdisk on the EFI system partition.
+const INITRD_PATH: &str = "\\EFI\\BOOT\\initrd.cpio";
+
+/// The path of the file on the EFI system partition that holds the kernel command line.
+const COMMAND_LINE_PATH: &str = "\\EFI\\BOOT\\cmdline.txt";
+
+/// The maximum length of a path passed to the firmware, including the null terminator.
+const MAX_PATH_LENGTH: usize = 256;
+
+/// The size of a page as used by the UEFI firmware.
+const UEFI_PAGE_SIZE: usize = 0x1000;
 
 /// A reference to the UEFI system table.
 static SYSTEM_TABLE: GlobalRuntimeConfiguration<&'static SystemTable> =
     GlobalRuntimeConfiguration::new();
 
+/// The parser that filters the escape sequences out of the output to the firmware console.
+static ESCAPE_FILTER: Mutex<Parser> = Mutex::new(Parser::new());
+
 /// The entry point for UEFI applications.
 pub fn uefi_init(image_handle: Handle, system_table: &'static SystemTable) {
     SYSTEM_TABLE.init(system_table);
+    runtime::init(&*system_table.RuntimeServices);
     BOOT_METHOD.init(BootMethod::UEFI);
 
     // Fail silently if the screen cannot be cleared.
     get_system_table().ConsoleOut.clear_screen().ok();
+    console::register("uefi", &FirmwareConsoleSink, LevelFilter::Warn);
+
+    early_init(read_command_line(image_handle));
 
-    early_init();
+    let initrd = load_file(image_handle, INITRD_PATH)
+        .map_err(|status| log::warn!("Could not load the initial ramdisk ({:?}).", status))
+        .ok();
+
+    match find_configuration_table(&ACPI_20_TABLE).or_else(|| find_configuration_table(&ACPI_TABLE))
+    {
+        Some(rsdp) => acpi::init(PhysicalAddress::new(rsdp)),
+        None => log::warn!("The firmware does not provide ACPI tables."),
+    }
+
+    let now = runtime::get_time();
+    let framebuffer = get_framebuffer();
 
     log::info!("Exiting UEFI boot services...");
 
@@ -30,7 +94,10 @@ pub fn uefi_init(image_handle: Handle, system_table: &'static SystemTable) {
         .exit_boot_services(image_handle)
         .expect("Could not exit UEFI boot services.");
 
-    // The boot services are now disabled.
+    // The boot services are now disabled, so the framebuffer console takes over from the firmware console.
+    console::unregister("uefi");
+
+    late_init();
 
     let mut usable_pages = 0;
     let mut total_pages = 0;
@@ -38,25 +105,371 @@ pub fn uefi_init(image_handle: Handle, system_table: &'static SystemTable) {
     for entry in memory_map.iter() {
         if entry.Type == NamedMemoryType::ConventionalMemory.into() {
             usable_pages += entry.NumberOfPages;
+
+            // This is safe, because conventional memory is unused after exiting the boot services.
+            unsafe {
+                FRAME_ALLOCATOR.lock().add_region(
+                    PhysicalAddress::new(entry.PhysicalStart.0 as usize),
+                    entry.NumberOfPages as usize,
+                );
+            }
         }
         total_pages += entry.NumberOfPages;
     }
 
+    memory::init();
+
     log::info!(
         "The usable amount of memory is {}B, the total amount of memory is {}B.",
         SizeFormatterBinary::new(usable_pages * 0x1000),
         SizeFormatterBinary::new(total_pages * 0x1000)
     );
+
+    heap::init();
+    runtime::set_virtual_address_map(&memory_map);
+    serial::enable_interrupts();
+    gdb::init();
+    pci::init();
+    ps2::init();
+
+    if let Some(framebuffer) = framebuffer {
+        framebuffer::init(framebuffer);
+    }
+
+    time::init(now);
+
+    if let Some(initrd) = initrd {
+        initrd::init(initrd);
+    }
+}
+
+/// Returns the address of the configuration table with the given GUID, if the firmware provides it.
+fn find_configuration_table(guid: &Guid) -> Option<usize> {
+    let system_table = get_system_table();
+
+    // This is safe, because the firmware passes the configuration tables with the given number of entries.
+    let tables =
+        unsafe { slice::from_raw_parts(system_table.ConfigurationTables, system_table.Entries) };
+
+    tables
+        .iter()
+        .find(|table| table.VendorGuid == *guid)
+        .map(|table| table.VendorTable)
+}
+
+/// Converts a UEFI status to a result.
+fn status_to_result(status: Status) -> Result<(), Status> {
+    if status.0 == 0 {
+        Ok(())
+    } else {
+        Err(status)
+    }
 }
 
-/// Writes the formatted string.
-pub(super) fn write_fmt(args: fmt::Arguments) {
-    let mut console_out = &*get_system_table().ConsoleOut;
+/// Reads the kernel command line from the command line file and the load options of the image.
+///
+/// The load options come last, so that they override the options in the file.
+/// Errors are ignored, since the logger is not set up yet and the command line is optional.
+fn read_command_line(image_handle: Handle) -> &'static str {
+    let file = load_file(image_handle, COMMAND_LINE_PATH)
+        .ok()
+        .and_then(|file| str::from_utf8(file).ok())
+        .unwrap_or("");
+
+    let load_options = match handle_protocol::<LoadedImage>(image_handle, &LOADED_IMAGE_PROTOCOL) {
+        Ok(ref loaded_image) if !loaded_image.load_options.is_null() => {
+            // This is safe, because the firmware passes the load options with the given size.
+            unsafe {
+                slice::from_raw_parts(
+                    loaded_image.load_options,
+                    loaded_image.load_options_size as usize / size_of::<u16>(),
+                )
+            }
+        }
+        _ => &[],
+    };
+    let load_options = load_options
+        .split(|&character| character == 0)
+        .next()
+        .unwrap_or(&[]);
+
+    // Each UTF-16 code unit takes at most three bytes in UTF-8.
+    let options_start = file.len() + 1;
+    let size = options_start + load_options.len() * 3;
+    let buffer = match allocate_pages(size) {
+        // This is safe, because the buffer was just allocated with the given size.
+        Ok(buffer) => unsafe { slice::from_raw_parts_mut(buffer as *mut u8, size) },
+        Err(_) => return file,
+    };
 
-    if console_out as *const _ as usize != 0 {
-        console_out
+    buffer[..file.len()].copy_from_slice(file.as_bytes());
+    buffer[file.len()] = b' ';
+
+    let mut length = options_start;
+    for character in decode_utf16(load_options.iter().cloned()) {
+        let character = character.unwrap_or(REPLACEMENT_CHARACTER);
+
+        length += character.encode_utf8(&mut buffer[length..]).len();
+    }
+
+    // The UEFI shell passes the path of the image as the first word, which is blanked out.
+    let image_path = {
+        let options = str::from_utf8(&buffer[options_start..length]).unwrap_or("");
+        let first_word = options.split_whitespace().next().unwrap_or("");
+        let start = options_start + options.len() - options.trim_start().len();
+
+        if first_word.len() >= 4
+            && first_word.as_bytes()[first_word.len() - 4..].eq_ignore_ascii_case(b".efi")
+        {
+            start..start + first_word.len()
+        } else {
+            0..0
+        }
+    };
+    for byte in &mut buffer[image_path] {
+        *byte = b' ';
+    }
+
+    str::from_utf8(&buffer[..length]).unwrap_or(file)
+}
+
+/// Returns the interface of the given protocol that the handle supports.
+///
+/// `T` must be the type of the protocol interface.
+fn handle_protocol<T>(handle: Handle, protocol: &Guid) -> Result<&'static mut T, Status> {
+    let mut interface = 0;
+
+    status_to_result((get_system_table().BootServices.HandleProtocol)(
+        handle,
+        protocol,
+        &mut interface,
+    ))?;
+
+    // This is safe, because the firmware returned an interface of the requested protocol.
+    Ok(unsafe { &mut *(interface as *mut T) })
+}
+
+/// Returns the first interface of the given protocol.
+///
+/// `T` must be the type of the protocol interface.
+fn locate_protocol<T>(protocol: &Guid) -> Result<&'static mut T, Status> {
+    let mut interface = 0;
+
+    status_to_result((get_system_table().BootServices.LocateProtocol)(
+        protocol,
+        0,
+        &mut interface,
+    ))?;
+
+    // This is safe, because the firmware returned an interface of the requested protocol.
+    Ok(unsafe { &mut *(interface as *mut T) })
+}
+
+/// Returns the framebuffer of the graphics output, if it can be accessed directly.
+fn get_framebuffer() -> Option<Framebuffer> {
+    let graphics_output = locate_protocol::<GraphicsOutput>(&GRAPHICS_OUTPUT_PROTOCOL).ok()?;
+    let mode = graphics_output.mode;
+    let info = mode.info;
+
+    let format = match info.pixel_format {
+        PIXEL_RED_GREEN_BLUE_RESERVED => PixelFormat::Rgb,
+        PIXEL_BLUE_GREEN_RED_RESERVED => PixelFormat::Bgr,
+        PIXEL_BIT_MASK => PixelFormat::Bitmask {
+            red: info.pixel_information.red_mask,
+            green: info.pixel_information.green_mask,
+            blue: info.pixel_information.blue_mask,
+        },
+        // The framebuffer can only be accessed through the firmware.
+        _ => return None,
+    };
+
+    // This is safe, because the framebuffer is reserved for the graphics output, which the kernel takes over.
+    Some(unsafe {
+        Framebuffer::new(
+            Arch::physical_to_virtual(PhysicalAddress::new(mode.frame_buffer_base as usize)),
+            info.horizontal_resolution as usize,
+            info.vertical_resolution as usize,
+            info.pixels_per_scan_line as usize,
+            format,
+        )
+    })
+}
+
+/// Reads the file at `path` from the volume the kernel was loaded from.
+///
+/// The contents are stored in loader data pages, which stay reserved after exiting the boot services.
+fn load_file(image_handle: Handle, path: &str) -> Result<&'static [u8], Status> {
+    let loaded_image = handle_protocol::<LoadedImage>(image_handle, &LOADED_IMAGE_PROTOCOL)?;
+    let file_system = handle_protocol::<SimpleFileSystem>(
+        loaded_image.device_handle,
+        &SIMPLE_FILE_SYSTEM_PROTOCOL,
+    )?;
+
+    let mut root = ptr::null_mut();
+    status_to_result((file_system.open_volume)(file_system, &mut root))?;
+
+    // This is safe, because the firmware returned a valid file.
+    let root = unsafe { &mut *root };
+
+    let mut file_name = [0; MAX_PATH_LENGTH];
+    debug_assert!(path.len() < MAX_PATH_LENGTH);
+    for (target, character) in file_name[..MAX_PATH_LENGTH - 1]
+        .iter_mut()
+        .zip(path.encode_utf16())
+    {
+        *target = character;
+    }
+
+    let mut file = ptr::null_mut();
+    let result = status_to_result((root.open)(
+        root,
+        &mut file,
+        file_name.as_ptr(),
+        FILE_MODE_READ,
+        0,
+    ));
+    (root.close)(root);
+    result?;
+
+    // This is safe, because the firmware returned a valid file.
+    let file = unsafe { &mut *file };
+    let result = read_file(file);
+    (file.close)(file);
+
+    result
+}
+
+/// Allocates loader data pages for at least `size` bytes and returns their address.
+///
+/// Loader data pages stay reserved after exiting the boot services.
+fn allocate_pages(size: usize) -> Result<usize, Status> {
+    let mut buffer = 0;
+
+    status_to_result((get_system_table().BootServices.AllocatePages)(
+        AllocateType::AllocateAnyPages,
+        NamedMemoryType::LoaderData.into(),
+        (size + UEFI_PAGE_SIZE - 1) / UEFI_PAGE_SIZE,
+        &mut buffer,
+    ))?;
+
+    Ok(buffer)
+}
+
+/// Frees the pages for `size` bytes at the address that were allocated with `allocate_pages`.
+fn free_pages(buffer: usize, size: usize) {
+    let result = status_to_result((get_system_table().BootServices.FreePages)(
+        buffer,
+        (size + UEFI_PAGE_SIZE - 1) / UEFI_PAGE_SIZE,
+    ));
+
+    if let Err(status) = result {
+        log::warn!("Could not free the pages at {:#x} ({:?}).", buffer, status);
+    }
+}
+
+/// Reads the whole contents of the file into newly allocated memory.
+fn read_file(file: &mut File) -> Result<&'static [u8], Status> {
+    let mut info = [0u64; 64];
+    let mut info_size = size_of_val(&info);
+
+    status_to_result((file.get_info)(
+        file,
+        &FILE_INFO,
+        &mut info_size,
+        info.as_mut_ptr() as *mut u8,
+    ))?;
+
+    let size = info[FILE_INFO_FILE_SIZE_OFFSET / 8] as usize;
+
+    if size == 0 {
+        return Ok(&[]);
+    }
+
+    let buffer = allocate_pages(size)?;
+
+    let mut read = 0;
+    while read < size {
+        let mut chunk_size = size - read;
+
+        let result = status_to_result((file.read)(
+            file,
+            &mut chunk_size,
+            (buffer + read) as *mut u8,
+        ));
+
+        if let Err(status) = result {
+            free_pages(buffer, size);
+
+            return Err(status);
+        }
+
+        if chunk_size == 0 {
+            break;
+        }
+
+        read += chunk_size;
+    }
+
+    // This is safe, because the buffer was allocated above and `read` bytes were initialized.
+    Ok(unsafe { slice::from_raw_parts(buffer as *const u8, read) })
+}
+
+/// The console sink of the firmware console.
+///
+/// It is only usable while the boot services are active.
+struct FirmwareConsoleSink;
+
+impl Sink for FirmwareConsoleSink {
+    fn write_fmt(&self, args: fmt::Arguments) {
+        let console_out = &*get_system_table().ConsoleOut;
+
+        if console_out as *const _ as usize != 0 {
+            EscapeFilter {
+                parser: &mut ESCAPE_FILTER.lock(),
+                output: console_out,
+            }
             .write_fmt(args)
             .expect("Could not output to UEFI output.");
+        }
+    }
+}
+
+/// Removes the escape sequences from the text written to the output and ends lines with a carriage return.
+///
+/// The firmware console doesn't understand the escape sequences, so they would be shown as garbage.
+struct EscapeFilter<'a, W: Write> {
+    /// The parser that recognizes the escape sequences.
+    parser: &'a mut Parser,
+    /// The output that the remaining text is written to.
+    output: W,
+}
+
+impl<'a, W: Write> Write for EscapeFilter<'a, W> {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        let mut run_start = None;
+
+        for (index, character) in string.char_indices() {
+            match self.parser.advance(character) {
+                Some(Action::Character(character)) if character != '\n' => {
+                    run_start = run_start.or(Some(index));
+                }
+                action => {
+                    if let Some(start) = run_start.take() {
+                        self.output.write_str(&string[start..index])?;
+                    }
+
+                    if action == Some(Action::Character('\n')) {
+                        self.output.write_str("\r\n")?;
+                    }
+                }
+            }
+        }
+
+        if let Some(start) = run_start {
+            self.output.write_str(&string[start..])?;
+        }
+
+        Ok(())
     }
 }
 
@@ -0,0 +1,207 @@
+//! Definitions of the UEFI protocols used by the kernel.
+//!
+//! Only the members that are used by the kernel have precise types, the others are kept as opaque pointers.
+
+use nuefil::{guid::Guid, status::Status, Handle};
+
+/// The GUID of the loaded image protocol.
+pub const LOADED_IMAGE_PROTOCOL: Guid = Guid(
+    0x5b1b_31a1,
+    0x9562,
+    0x11d2,
+    [0x8e, 0x3f, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b],
+);
+
+/// The GUID of the simple file system protocol.
+pub const SIMPLE_FILE_SYSTEM_PROTOCOL: Guid = Guid(
+    0x964e_5b22,
+    0x6459,
+    0x11d2,
+    [0x8e, 0x39, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b],
+);
+
+/// The GUID of the graphics output protocol.
+pub const GRAPHICS_OUTPUT_PROTOCOL: Guid = Guid(
+    0x9042_a9de,
+    0x23dc,
+    0x4a38,
+    [0x96, 0xfb, 0x7a, 0xde, 0xd0, 0x80, 0x51, 0x6a],
+);
+
+/// The GUID of the file information type.
+pub const FILE_INFO: Guid = Guid(
+    0x0957_6e92,
+    0x6d3f,
+    0x11d2,
+    [0x8e, 0x39, 0x00, 0xa0, 0xc9, 0x69, 0x72, 0x3b],
+);
+
+/// The GUID of the configuration table that holds the ACPI 2.0 root system description pointer.
+pub const ACPI_20_TABLE: Guid = Guid(
+    0x8868_e871,
+    0xe4f1,
+    0x11d3,
+    [0xbc, 0x22, 0x00, 0x80, 0xc7, 0x3c, 0x88, 0x81],
+);
+
+/// The GUID of the configuration table that holds the ACPI 1.0 root system description pointer.
+pub const ACPI_TABLE: Guid = Guid(
+    0xeb9d_2d30,
+    0x2d88,
+    0x11d3,
+    [0x9a, 0x16, 0x00, 0x90, 0x27, 0x3f, 0xc1, 0x4d],
+);
+
+/// Opens a file for reading.
+pub const FILE_MODE_READ: u64 = 1;
+
+/// The offset of the file size in the file information structure.
+pub const FILE_INFO_FILE_SIZE_OFFSET: usize = 8;
+
+/// Pixels store red, green and blue in one byte each, followed by a reserved byte.
+pub const PIXEL_RED_GREEN_BLUE_RESERVED: u32 = 0;
+
+/// Pixels store blue, green and red in one byte each, followed by a reserved byte.
+pub const PIXEL_BLUE_GREEN_RED_RESERVED: u32 = 1;
+
+/// Pixels store the components in the bits given by the pixel bitmask.
+pub const PIXEL_BIT_MASK: u32 = 2;
+
+/// The loaded image protocol, which describes a loaded UEFI image.
+#[repr(C)]
+pub struct LoadedImage {
+    /// The revision of the protocol.
+    pub revision: u32,
+    /// The image that loaded this image.
+    pub parent_handle: Handle,
+    /// The system table of the image.
+    pub system_table: usize,
+    /// The device the image was loaded from.
+    pub device_handle: Handle,
+    /// The device path of the image file.
+    pub file_path: usize,
+    /// Reserved.
+    pub reserved: usize,
+    /// The size of the load options in bytes.
+    pub load_options_size: u32,
+    /// The load options of the image.
+    pub load_options: *const u16,
+    /// The address the image was loaded at.
+    pub image_base: usize,
+    /// The size of the loaded image in bytes.
+    pub image_size: u64,
+    /// The memory type of the code sections.
+    pub image_code_type: u32,
+    /// The memory type of the data sections.
+    pub image_data_type: u32,
+    /// The unload function of the image.
+    pub unload: usize,
+}
+
+/// The simple file system protocol, which provides access to a FAT volume.
+#[repr(C)]
+pub struct SimpleFileSystem {
+    /// The revision of the protocol.
+    pub revision: u64,
+    /// Opens the root directory of the volume.
+    pub open_volume: extern "win64" fn(this: &SimpleFileSystem, root: &mut *mut File) -> Status,
+}
+
+/// The file protocol, which provides access to a file or directory.
+#[repr(C)]
+pub struct File {
+    /// The revision of the protocol.
+    pub revision: u64,
+    /// Opens a file relative to this directory.
+    pub open: extern "win64" fn(
+        this: &mut File,
+        new_handle: &mut *mut File,
+        file_name: *const u16,
+        open_mode: u64,
+        attributes: u64,
+    ) -> Status,
+    /// Closes the file.
+    pub close: extern "win64" fn(this: &mut File) -> Status,
+    /// Deletes the file.
+    pub delete: usize,
+    /// Reads from the file.
+    pub read:
+        extern "win64" fn(this: &mut File, buffer_size: &mut usize, buffer: *mut u8) -> Status,
+    /// Writes to the file.
+    pub write: usize,
+    /// Returns the current position in the file.
+    pub get_position: usize,
+    /// Sets the current position in the file.
+    pub set_position: usize,
+    /// Returns information about the file.
+    pub get_info: extern "win64" fn(
+        this: &mut File,
+        information_type: &Guid,
+        buffer_size: &mut usize,
+        buffer: *mut u8,
+    ) -> Status,
+    /// Sets information about the file.
+    pub set_info: usize,
+    /// Flushes the file.
+    pub flush: usize,
+}
+
+/// The graphics output protocol, which provides access to the framebuffer of a display.
+#[repr(C)]
+pub struct GraphicsOutput {
+    /// Returns information about a mode.
+    pub query_mode: usize,
+    /// Switches to another mode.
+    pub set_mode: usize,
+    /// Copies rectangles of pixels.
+    pub blt: usize,
+    /// The current mode.
+    pub mode: &'static GraphicsOutputMode,
+}
+
+/// Describes the current mode of a graphics output.
+#[repr(C)]
+pub struct GraphicsOutputMode {
+    /// The number of supported modes.
+    pub max_mode: u32,
+    /// The number of the current mode.
+    pub mode: u32,
+    /// Information about the current mode.
+    pub info: &'static GraphicsOutputModeInformation,
+    /// The size of the information in bytes.
+    pub size_of_info: usize,
+    /// The physical address of the framebuffer.
+    pub frame_buffer_base: u64,
+    /// The size of the framebuffer in bytes.
+    pub frame_buffer_size: usize,
+}
+
+/// Describes a mode of a graphics output.
+#[repr(C)]
+pub struct GraphicsOutputModeInformation {
+    /// The version of the structure.
+    pub version: u32,
+    /// The number of visible pixels in a line.
+    pub horizontal_resolution: u32,
+    /// The number of visible lines.
+    pub vertical_resolution: u32,
+    /// The layout of the pixels.
+    pub pixel_format: u32,
+    /// The bits of the components, if the pixel format uses a bitmask.
+    pub pixel_information: PixelBitmask,
+    /// The number of pixels from the start of one line to the start of the next.
+    pub pixels_per_scan_line: u32,
+}
+
+/// The bits that hold the components of a pixel.
+#[repr(C)]
+pub struct PixelBitmask {
+    /// The bits of the red component.
+    pub red_mask: u32,
+    /// The bits of the green component.
+    pub green_mask: u32,
+    /// The bits of the blue component.
+    pub blue_mask: u32,
+    /// The reserved bits.
+    pub reserved_mask: u32,
+}
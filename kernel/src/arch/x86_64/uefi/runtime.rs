@@ -0,0 +1,268 @@
+//! Provides the UEFI runtime services, which stay usable after exiting the boot services.
+//!
+//! The firmware is told to keep using the identity mapping for its runtime regions,
+//! which the kernel shares between all address spaces and never hands out as free frames.
+//! The runtime services are not reentrant, so every call goes through a single lock.
+
+use alloc::{string::String, vec::Vec};
+use core::{mem::size_of, ptr};
+use nuefil::{
+    guid::Guid,
+    memory::{MemoryDescriptor, MemoryMap, VirtualAddress},
+    runtime::{ResetType, RuntimeServices, Time},
+    status::Status,
+};
+
+use super::status_to_result;
+use crate::{sync::Mutex, time::DateTime};
+
+/// The attribute of memory regions that the runtime services use.
+const MEMORY_RUNTIME: u64 = 1 << 63;
+
+/// The version of the memory descriptors passed to the firmware.
+const MEMORY_DESCRIPTOR_VERSION: u32 = 1;
+
+/// Marks a time whose time zone is unknown.
+const UNSPECIFIED_TIME_ZONE: i16 = 0x07ff;
+
+/// The bit that is set in all error codes.
+const ERROR_BIT: usize = 1 << 63;
+
+/// The status returned if the runtime services are unavailable.
+const UNSUPPORTED: Status = Status(ERROR_BIT | 3);
+
+/// The status returned if a buffer is too small for the result.
+const BUFFER_TOO_SMALL: Status = Status(ERROR_BIT | 5);
+
+/// The status returned if no more variables exist.
+const NOT_FOUND: Status = Status(ERROR_BIT | 14);
+
+/// The variable is kept across resets.
+pub const VARIABLE_NON_VOLATILE: u32 = 1 << 0;
+
+/// The variable can be accessed before exiting the boot services.
+pub const VARIABLE_BOOTSERVICE_ACCESS: u32 = 1 << 1;
+
+/// The variable can be accessed after exiting the boot services.
+pub const VARIABLE_RUNTIME_ACCESS: u32 = 1 << 2;
+
+/// The vendor GUID of the variables defined by the UEFI specification.
+pub const GLOBAL_VARIABLE: Guid = Guid(
+    0x8be4_df61,
+    0x93ca,
+    0x11d2,
+    [0xaa, 0x0d, 0x00, 0xe0, 0x98, 0x03, 0x2b, 0x8c],
+);
+
+/// The runtime services of the firmware.
+static RUNTIME_SERVICES: Mutex<Option<&'static RuntimeServices>> = Mutex::new(None);
+
+/// An EFI variable.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Variable {
+    /// The attributes of the variable.
+    pub attributes: u32,
+    /// The contents of the variable.
+    pub data: Vec<u8>,
+}
+
+/// Makes the runtime services available to the kernel.
+pub(super) fn init(runtime_services: &'static RuntimeServices) {
+    *RUNTIME_SERVICES.lock() = Some(runtime_services);
+}
+
+/// Switches the runtime services to the kernel's mapping of their regions.
+///
+/// This must be called once after exiting the boot services.
+pub(super) fn set_virtual_address_map(memory_map: &MemoryMap) {
+    let mut runtime_regions: Vec<MemoryDescriptor> = memory_map
+        .iter()
+        .filter(|entry| entry.Attribute & MEMORY_RUNTIME != 0)
+        .cloned()
+        .collect();
+
+    for region in &mut runtime_regions {
+        region.VirtualStart = VirtualAddress(region.PhysicalStart.0);
+    }
+
+    let result = call(|runtime_services| {
+        status_to_result((runtime_services.SetVirtualAddressMap)(
+            runtime_regions.len() * size_of::<MemoryDescriptor>(),
+            size_of::<MemoryDescriptor>(),
+            MEMORY_DESCRIPTOR_VERSION,
+            runtime_regions.as_mut_ptr(),
+        ))
+    });
+
+    match result {
+        Ok(()) => log::debug!(
+            "Mapped {} UEFI runtime regions for the kernel.",
+            runtime_regions.len()
+        ),
+        Err(status) => {
+            log::warn!(
+                "Could not set the virtual address map of the UEFI runtime services ({:?}).",
+                status
+            );
+
+            // The firmware may be in an inconsistent state, so it isn't called again.
+            *RUNTIME_SERVICES.lock() = None;
+        }
+    }
+}
+
+/// Calls the function with the runtime services while holding the lock.
+///
+/// Fails with an unsupported status if the runtime services are unavailable.
+fn call<T, F>(function: F) -> Result<T, Status>
+where
+    F: FnOnce(&'static RuntimeServices) -> Result<T, Status>,
+{
+    let runtime_services = RUNTIME_SERVICES.lock();
+
+    match *runtime_services {
+        Some(runtime_services) => function(runtime_services),
+        None => Err(UNSUPPORTED),
+    }
+}
+
+/// Encodes the name as a null terminated UTF-16 string.
+fn encode_name(name: &str) -> Vec<u16> {
+    name.encode_utf16().chain(Some(0)).collect()
+}
+
+/// Reads the current date and time.
+///
+/// Firmware usually leaves the time zone unspecified, so the time is taken to be in UTC.
+pub fn get_time() -> Option<DateTime> {
+    let mut time = Time::default();
+
+    call(|runtime_services| {
+        status_to_result((runtime_services.GetTime)(&mut time, ptr::null_mut()))
+    })
+    .ok()?;
+
+    DateTime::new(
+        time.Year,
+        time.Month,
+        time.Day,
+        time.Hour,
+        time.Minute,
+        time.Second,
+        time.Nanosecond,
+    )
+}
+
+/// Sets the current date and time.
+pub fn set_time(now: DateTime) -> Result<(), Status> {
+    let time = Time {
+        Year: now.year(),
+        Month: now.month(),
+        Day: now.day(),
+        Hour: now.hour(),
+        Minute: now.minute(),
+        Second: now.second(),
+        Nanosecond: now.nanosecond(),
+        TimeZone: UNSPECIFIED_TIME_ZONE,
+        ..Time::default()
+    };
+
+    call(|runtime_services| status_to_result((runtime_services.SetTime)(&time)))
+}
+
+/// Reads the variable with the given name and vendor.
+pub fn get_variable(name: &str, vendor: &Guid) -> Result<Variable, Status> {
+    let name = encode_name(name);
+    let mut attributes = 0;
+    let mut data = Vec::new();
+
+    call(|runtime_services| loop {
+        let mut size = data.len();
+
+        let status = (runtime_services.GetVariable)(
+            name.as_ptr(),
+            vendor,
+            &mut attributes,
+            &mut size,
+            data.as_mut_ptr(),
+        );
+
+        if status == BUFFER_TOO_SMALL {
+            data.resize(size, 0);
+        } else {
+            status_to_result(status)?;
+            data.truncate(size);
+
+            return Ok(());
+        }
+    })?;
+
+    Ok(Variable { attributes, data })
+}
+
+/// Sets the variable with the given name and vendor.
+///
+/// Setting a variable to empty data deletes it.
+pub fn set_variable(name: &str, vendor: &Guid, attributes: u32, data: &[u8]) -> Result<(), Status> {
+    let name = encode_name(name);
+
+    call(|runtime_services| {
+        status_to_result((runtime_services.SetVariable)(
+            name.as_ptr(),
+            vendor,
+            attributes,
+            data.len(),
+            data.as_ptr(),
+        ))
+    })
+}
+
+/// Returns the names and vendors of all variables.
+pub fn variable_names() -> Result<Vec<(String, Guid)>, Status> {
+    let mut names = Vec::new();
+    let mut vendor = Guid(0, 0, 0, [0; 8]);
+
+    // The search starts with an empty name and continues with the name of the previous variable.
+    let mut name = Vec::new();
+    name.push(0);
+
+    call(|runtime_services| loop {
+        let mut size = name.len() * size_of::<u16>();
+
+        let status =
+            (runtime_services.GetNextVariableName)(&mut size, name.as_mut_ptr(), &mut vendor);
+
+        if status == BUFFER_TOO_SMALL {
+            name.resize(size / size_of::<u16>(), 0);
+        } else if status == NOT_FOUND {
+            return Ok(());
+        } else {
+            status_to_result(status)?;
+
+            let length = name
+                .iter()
+                .position(|&character| character == 0)
+                .unwrap_or(name.len());
+            names.push((String::from_utf16_lossy(&name[..length]), vendor));
+        }
+    })?;
+
+    Ok(names)
+}
+
+/// Resets or shuts down the machine.
+///
+/// This only returns if the runtime services are unavailable, with the status describing the failure.
+pub fn reset_system(reset_type: ResetType) -> Status {
+    // The lock is held until the machine resets.
+    let guard = RUNTIME_SERVICES.lock();
+
+    match *guard {
+        Some(runtime_services) => {
+            log::info!("Resetting the system ({:?})...", reset_type);
+
+            (runtime_services.ResetSystem)(reset_type, Status(0), 0, ptr::null())
+        }
+        None => UNSUPPORTED,
+    }
+}
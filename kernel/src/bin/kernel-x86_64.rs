@@ -6,7 +6,10 @@
 #![no_main]
 
 use core::panic::PanicInfo;
-use kernel::{arch::x86_64::uefi::uefi_init, main, println};
+use kernel::{
+    arch::x86_64::{gdb, shell, uefi::uefi_init},
+    main, println,
+};
 use nuefil::{system::SystemTable, Handle};
 
 /// The entry point for the UEFI loader.
@@ -20,11 +23,18 @@ pub extern "C" fn efi_main(image_handle: Handle, system_table: &'static SystemTa
 }
 
 /// The panic implementation of BeetleOS.
+///
+/// After showing the panic, the debug shell is run for inspecting the state of the kernel
+/// and GDB can still attach to it.
 #[panic_handler]
 fn panic_fmt(panic_info: &PanicInfo) -> ! {
     log::error!("Panic:");
 
     println!("{}", panic_info);
 
+    gdb::report_panic();
+    shell::run(true);
+    gdb::wait_for_debugger();
+
     loop {}
 }
@@ -0,0 +1,236 @@
+//! This binary runs the ANSI escape sequence test.
+//!
+//! This test makes sure that escape sequences are split from the text correctly
+//! and that the framebuffer console moves the cursor, erases and selects the rendition as requested.
+
+#![no_std]
+#![no_main]
+#![feature(alloc)]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::{fmt::Write, panic::PanicInfo};
+use kernel::{
+    arch::x86_64::{exit_integration_test, uefi::uefi_init, IntegrationTestExitCode},
+    console::{
+        ansi::{Action, Parser},
+        framebuffer::{
+            Color, Framebuffer, FramebufferConsole, PixelFormat, DEFAULT_BACKGROUND,
+            DEFAULT_FOREGROUND,
+        },
+    },
+    memory::{VirtualAddress, FRAME_ALLOCATOR},
+    serial_println,
+};
+use nuefil::{system::SystemTable, Handle};
+
+/// The number of pixels in a line of the test framebuffer, which fits ten characters.
+const WIDTH: usize = 80;
+
+/// The number of lines of the test framebuffer, which fits three rows of characters.
+const HEIGHT: usize = 48;
+
+/// The pixel format of the test framebuffer.
+const FORMAT: PixelFormat = PixelFormat::Rgb;
+
+/// Fails the test with the given message if the condition doesn't hold.
+fn check(condition: bool, message: &'static str) {
+    if !condition {
+        exit_integration_test(IntegrationTestExitCode::Failure(message));
+    }
+}
+
+/// Feeds the string to the parser and returns the resulting actions.
+fn parse(parser: &mut Parser, string: &str) -> Vec<Action> {
+    string
+        .chars()
+        .filter_map(|character| parser.advance(character))
+        .collect()
+}
+
+/// Returns the pixels of the character cell at the column and row.
+fn cell(pixels: &[u32], column: usize, row: usize) -> Vec<u32> {
+    (0..16)
+        .flat_map(|y| {
+            let start = (row * 16 + y) * WIDTH + column * 8;
+
+            pixels[start..start + 8].iter().cloned()
+        })
+        .collect()
+}
+
+/// Checks that the cell contains both colors and nothing else.
+fn shows_character(cell: &[u32], foreground: Color, background: Color) -> bool {
+    let foreground = FORMAT.encode(foreground);
+    let background = FORMAT.encode(background);
+
+    cell.iter().any(|&pixel| pixel == foreground)
+        && cell.iter().any(|&pixel| pixel == background)
+        && cell
+            .iter()
+            .all(|&pixel| pixel == foreground || pixel == background)
+}
+
+/// Checks that the cell only contains the default background.
+fn is_empty(cell: &[u32]) -> bool {
+    cell.iter()
+        .all(|&pixel| pixel == FORMAT.encode(DEFAULT_BACKGROUND))
+}
+
+/// Tests the splitting of text and escape sequences.
+fn test_parser() {
+    let mut parser = Parser::new();
+
+    check(
+        parse(&mut parser, "a\n") == [Action::Character('a'), Action::Character('\n')],
+        "Text was not passed through.",
+    );
+    check(
+        parse(&mut parser, "\x1b7\x1b[") == [Action::Escape('7')],
+        "An escape sequence was not recognized.",
+    );
+
+    match parse(&mut parser, "12;;3H").as_slice() {
+        [Action::ControlSequence(sequence)] => check(
+            sequence.function() == 'H'
+                && sequence.parameters() == [12, 0, 3]
+                && sequence.parameter(1, 1) == 1
+                && sequence.parameter(2, 1) == 3
+                && sequence.parameter(3, 1) == 1
+                && !sequence.is_private(),
+            "The parameters of a control sequence are wrong.",
+        ),
+        _ => check(
+            false,
+            "A control sequence split across writes was not recognized.",
+        ),
+    }
+
+    match parse(&mut parser, "\x1b[?25lb").as_slice() {
+        [Action::ControlSequence(sequence), Action::Character('b')] => check(
+            sequence.function() == 'l' && sequence.is_private(),
+            "A private control sequence was not marked as such.",
+        ),
+        _ => check(false, "A private control sequence was not recognized."),
+    }
+
+    check(
+        parse(&mut parser, "\x1b[3\x1b[mc").len() == 2,
+        "An escape character did not abort the previous sequence.",
+    );
+}
+
+/// Tests the interpretation of the escape sequences by the framebuffer console.
+fn test_console() {
+    let mut pixels = Vec::new();
+    pixels.resize(WIDTH * HEIGHT, 0);
+
+    // This is safe, because the pixels are only used by the console while it exists.
+    let framebuffer = unsafe {
+        Framebuffer::new(
+            VirtualAddress::new(pixels.as_mut_ptr() as usize),
+            WIDTH,
+            HEIGHT,
+            WIDTH,
+            FORMAT,
+        )
+    };
+    let mut console = FramebufferConsole::new(framebuffer).expect("Could not create the console.");
+
+    write!(console, "\x1b[2;5H").expect("Could not write to the console.");
+    check(console.cursor() == (4, 1), "The cursor was not positioned.");
+
+    write!(console, "\x1b[A\x1b[3C").expect("Could not write to the console.");
+    check(
+        console.cursor() == (7, 0),
+        "The cursor was not moved relative to its position.",
+    );
+
+    write!(console, "\x1b[99B\x1b[99D").expect("Could not write to the console.");
+    check(
+        console.cursor() == (0, 2),
+        "The cursor was moved off the screen.",
+    );
+
+    write!(console, "\x1b[H\x1b[1;31mA\x1b[22mB\x1b[7mC\x1b[0mD")
+        .expect("Could not write to the console.");
+    console.flush();
+
+    check(
+        shows_character(
+            &cell(&pixels, 0, 0),
+            Color::new(255, 85, 85),
+            DEFAULT_BACKGROUND,
+        ) && shows_character(
+            &cell(&pixels, 1, 0),
+            Color::new(170, 0, 0),
+            DEFAULT_BACKGROUND,
+        ),
+        "Bold text was not shown in the bright color.",
+    );
+    check(
+        shows_character(
+            &cell(&pixels, 2, 0),
+            DEFAULT_BACKGROUND,
+            Color::new(170, 0, 0),
+        ) && shows_character(&cell(&pixels, 3, 0), DEFAULT_FOREGROUND, DEFAULT_BACKGROUND),
+        "The colors were not reversed.",
+    );
+
+    write!(console, "\x1b7\x1b[2;1HEFGH\nIJ\x1b8").expect("Could not write to the console.");
+    check(console.cursor() == (4, 0), "The cursor was not restored.");
+
+    write!(console, "\x1b[2;3H\x1b[K\x1b[3;1H\x1b[1K").expect("Could not write to the console.");
+    console.flush();
+
+    check(
+        !is_empty(&cell(&pixels, 1, 1))
+            && is_empty(&cell(&pixels, 2, 1))
+            && is_empty(&cell(&pixels, 3, 1))
+            && is_empty(&cell(&pixels, 0, 2))
+            && !is_empty(&cell(&pixels, 1, 2)),
+        "A line was not erased.",
+    );
+
+    write!(console, "\x1b[2J").expect("Could not write to the console.");
+    console.flush();
+
+    check(
+        pixels
+            .iter()
+            .all(|&pixel| pixel == FORMAT.encode(DEFAULT_BACKGROUND))
+            && console.cursor() == (0, 2),
+        "The screen was not erased.",
+    );
+
+    drop(console);
+}
+
+/// The entry point for the UEFI loader.
+///
+/// This is the first function that gets called by the UEFI firmware.
+#[no_mangle]
+pub extern "C" fn efi_main(image_handle: Handle, system_table: &'static SystemTable) -> ! {
+    uefi_init(image_handle, system_table);
+
+    let free_frames = FRAME_ALLOCATOR.lock().free_frames();
+
+    test_parser();
+    test_console();
+
+    check(
+        FRAME_ALLOCATOR.lock().free_frames() == free_frames,
+        "The back buffer of the console was leaked.",
+    );
+
+    exit_integration_test(IntegrationTestExitCode::Success);
+}
+
+/// The panic implementation of the ANSI escape sequence test.
+#[panic_handler]
+fn panic_fmt(panic_info: &PanicInfo) -> ! {
+    serial_println!("{}", panic_info);
+
+    exit_integration_test(IntegrationTestExitCode::Failure(""));
+}
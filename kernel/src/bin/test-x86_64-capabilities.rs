@@ -0,0 +1,254 @@
+//! This binary runs the capabilities test.
+//!
+//! This test makes sure that capabilities are derived, moved, deleted and revoked correctly.
+
+#![no_std]
+#![no_main]
+#![feature(alloc)]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use core::panic::PanicInfo;
+use kernel::{
+    arch::x86_64::{exit_integration_test, uefi::uefi_init, IntegrationTestExitCode},
+    capability::{CSpace, CapabilityError, Rights},
+    ipc::Endpoint,
+    memory::{AddressSpace, MemoryRegion, FRAME_ALLOCATOR},
+    object::{KernelObject, ObjectType},
+    serial_println,
+    sync::Mutex,
+    syscall::{self, SyscallError},
+    thread::Thread,
+};
+use nuefil::{system::SystemTable, Handle};
+
+/// Fails the test with the given message if the condition doesn't hold.
+fn check(condition: bool, message: &'static str) {
+    if !condition {
+        exit_integration_test(IntegrationTestExitCode::Failure(message));
+    }
+}
+
+/// Checks that the operation succeeded.
+fn check_ok(result: Result<(), CapabilityError>, message: &'static str) {
+    check(result.is_ok(), message);
+}
+
+/// Checks that the operation failed with the expected error.
+fn check_error(
+    result: Result<(), CapabilityError>,
+    expected: CapabilityError,
+    message: &'static str,
+) {
+    check(result.err() == Some(expected), message);
+}
+
+/// Checks that the slot is empty.
+fn check_empty(cspace: &CSpace, slot: usize, message: &'static str) {
+    check(
+        cspace.lookup(slot).err() == Some(CapabilityError::EmptySlot(slot)),
+        message,
+    );
+}
+
+/// The entry point for the UEFI loader.
+///
+/// This is the first function that gets called by the UEFI firmware.
+#[no_mangle]
+pub extern "C" fn efi_main(image_handle: Handle, system_table: &'static SystemTable) -> ! {
+    uefi_init(image_handle, system_table);
+
+    let free_frames = FRAME_ALLOCATOR.lock().free_frames();
+
+    {
+        let cspace = CSpace::new(16);
+        let endpoint = KernelObject::Endpoint(Arc::new(Endpoint::new()));
+
+        check_ok(
+            CSpace::insert(&cspace, 0, endpoint.clone(), Rights::all()),
+            "Could not insert a capability.",
+        );
+        check_error(
+            CSpace::insert(&cspace, 0, endpoint.clone(), Rights::all()),
+            CapabilityError::SlotOccupied(0),
+            "A capability overwrote another one.",
+        );
+
+        // Copies can only have fewer rights than their source.
+        check_ok(
+            cspace.copy(0, &cspace, 1, Rights::READ | Rights::WRITE),
+            "Could not copy a capability.",
+        );
+        check_ok(
+            cspace.copy(1, &cspace, 2, Rights::all()),
+            "Could not copy a copied capability.",
+        );
+        match cspace.lookup(2) {
+            Ok(capability) => check(
+                capability.rights() == Rights::READ | Rights::WRITE
+                    && capability.object().is_same_object(&endpoint)
+                    && capability.object().object_type() == ObjectType::Endpoint,
+                "The copy has the wrong rights or object.",
+            ),
+            Err(_) => check(false, "The copy was not stored."),
+        }
+        check_error(
+            cspace.copy(0, &cspace, 16, Rights::all()),
+            CapabilityError::InvalidSlot(16),
+            "A capability was copied to a nonexistent slot.",
+        );
+        check_error(
+            cspace.copy(15, &cspace, 14, Rights::all()),
+            CapabilityError::EmptySlot(15),
+            "An empty slot was copied.",
+        );
+
+        // Badges can only be given once.
+        check_ok(
+            cspace.mint(0, &cspace, 3, Rights::WRITE, 42),
+            "Could not mint a capability.",
+        );
+        check(
+            cspace.lookup(3).map(|capability| capability.badge()) == Ok(42),
+            "The minted capability has the wrong badge.",
+        );
+        check_error(
+            cspace.mint(3, &cspace, 4, Rights::WRITE, 43),
+            CapabilityError::AlreadyBadged,
+            "A badged capability was badged again.",
+        );
+        check_error(
+            cspace.mint(0, &cspace, 4, Rights::WRITE, 0),
+            CapabilityError::InvalidBadge,
+            "Zero was accepted as a badge.",
+        );
+
+        check_ok(
+            cspace.move_capability(2, &cspace, 5),
+            "Could not move a capability.",
+        );
+        check_empty(&cspace, 2, "The moved capability is still in its old slot.");
+
+        // Deleting a capability keeps the capabilities derived from it.
+        check_ok(cspace.delete(1), "Could not delete a capability.");
+        check_empty(&cspace, 1, "The deleted capability is still there.");
+        check(
+            cspace.lookup(5).is_ok(),
+            "Deleting a capability removed a derived capability.",
+        );
+
+        // Capabilities in other capability spaces are revoked as well.
+        let other_cspace = CSpace::new(4);
+        check_ok(
+            cspace.copy(0, &other_cspace, 0, Rights::READ),
+            "Could not copy a capability to another capability space.",
+        );
+
+        check_ok(cspace.revoke(0), "Could not revoke a capability.");
+        check(
+            cspace.lookup(0).is_ok(),
+            "The revoked capability was deleted itself.",
+        );
+        check_empty(&cspace, 3, "The minted capability was not revoked.");
+        check_empty(&cspace, 5, "The moved capability was not revoked.");
+        check_empty(
+            &other_cspace,
+            0,
+            "The capability in the other capability space was not revoked.",
+        );
+
+        // Memory is freed when the last capability to it is deleted.
+        let region_frames = FRAME_ALLOCATOR.lock().free_frames();
+        let region = match MemoryRegion::new(4) {
+            Ok(region) => KernelObject::MemoryRegion(Arc::new(region)),
+            Err(_) => exit_integration_test(IntegrationTestExitCode::Failure(
+                "Could not allocate a memory region.",
+            )),
+        };
+        check_ok(
+            CSpace::insert(&cspace, 6, region, Rights::READ | Rights::WRITE),
+            "Could not insert a memory region.",
+        );
+        check_error(
+            cspace.mint(6, &cspace, 7, Rights::READ, 1),
+            CapabilityError::NotBadgeable,
+            "A memory region was badged.",
+        );
+        check_ok(
+            cspace.copy(6, &cspace, 7, Rights::READ),
+            "Could not copy a memory region.",
+        );
+        check_ok(cspace.delete(6), "Could not delete a memory region.");
+        check(
+            FRAME_ALLOCATOR.lock().free_frames() == region_frames - 4,
+            "A memory region was freed while a capability to it existed.",
+        );
+        check_ok(cspace.delete(7), "Could not delete a memory region.");
+        check(
+            FRAME_ALLOCATOR.lock().free_frames() == region_frames,
+            "A memory region was not freed with its last capability.",
+        );
+
+        // The capability operations are available as system calls.
+        let address_space = match AddressSpace::new() {
+            Ok(address_space) => Arc::new(Mutex::new(address_space)),
+            Err(_) => exit_integration_test(IntegrationTestExitCode::Failure(
+                "Could not create an address space.",
+            )),
+        };
+        let thread = Arc::new(Thread::new(cspace.clone(), address_space));
+
+        check(
+            syscall::handle(
+                &thread,
+                syscall::number::CAPABILITY_COPY,
+                [0, 8, Rights::READ.bits() as usize, 0, 0, 0],
+            ) == Ok(0),
+            "The copy system call failed.",
+        );
+        check(
+            cspace.lookup(8).map(|capability| capability.rights()) == Ok(Rights::READ),
+            "The copy system call did not copy the capability.",
+        );
+        check(
+            syscall::handle(
+                &thread,
+                syscall::number::CAPABILITY_DELETE,
+                [8, 0, 0, 0, 0, 0],
+            ) == Ok(0),
+            "The delete system call failed.",
+        );
+        check(
+            syscall::handle(
+                &thread,
+                syscall::number::CAPABILITY_DELETE,
+                [8, 0, 0, 0, 0, 0],
+            ) == Err(SyscallError::Capability(CapabilityError::EmptySlot(8))),
+            "An empty slot was deleted.",
+        );
+        check(
+            syscall::handle(
+                &thread,
+                syscall::number::CAPABILITY_COPY,
+                [0, 8, 0x100, 0, 0, 0],
+            ) == Err(SyscallError::InvalidArgument),
+            "Invalid rights were accepted.",
+        );
+    }
+
+    check(
+        FRAME_ALLOCATOR.lock().free_frames() == free_frames,
+        "Frames were leaked by the capability system.",
+    );
+
+    exit_integration_test(IntegrationTestExitCode::Success);
+}
+
+/// The panic implementation of the capabilities test.
+#[panic_handler]
+fn panic_fmt(panic_info: &PanicInfo) -> ! {
+    serial_println!("{}", panic_info);
+
+    exit_integration_test(IntegrationTestExitCode::Failure(""));
+}
@@ -0,0 +1,115 @@
+//! This binary runs the command line test.
+//!
+//! This test makes sure that the kernel command line is read during boot and that its options are parsed correctly.
+
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+use kernel::{
+    arch::x86_64::{exit_integration_test, uefi::uefi_init, IntegrationTestExitCode},
+    cmdline::{self, Configuration},
+    serial_println,
+};
+use log::LevelFilter;
+use nuefil::{system::SystemTable, Handle};
+
+/// Fails the test with the given message if the condition doesn't hold.
+fn check(condition: bool, message: &'static str) {
+    if !condition {
+        exit_integration_test(IntegrationTestExitCode::Failure(message));
+    }
+}
+
+/// Tests that an empty command line keeps the defaults.
+fn test_defaults() {
+    let configuration = Configuration::parse("  ");
+
+    check(
+        configuration.log_level("kernel::time") == LevelFilter::Info
+            && configuration.max_log_level() == LevelFilter::Info
+            && configuration.log_filters().next().is_none(),
+        "The default log level is wrong.",
+    );
+    check(
+        configuration.init().is_none() && configuration.console_enabled("serial"),
+        "The defaults of the options are wrong.",
+    );
+    check(
+        cmdline::get().is_some(),
+        "The command line was not read during boot.",
+    );
+}
+
+/// Tests the parsing of the log directives.
+fn test_log_directives() {
+    let configuration = Configuration::parse(
+        "loglevel=warn,kernel::memory=debug,kernel::memory::heap=off,kernel::time=nonsense",
+    );
+
+    check(
+        configuration.log_level("kernel") == LevelFilter::Warn
+            && configuration.log_level("kernel::time") == LevelFilter::Warn,
+        "The log level for all modules was not applied.",
+    );
+    check(
+        configuration.log_level("kernel::memory") == LevelFilter::Debug
+            && configuration.log_level("kernel::memory::paging") == LevelFilter::Debug
+            && configuration.log_level("kernel::memory_map") == LevelFilter::Warn,
+        "The log level of a module was not applied to it and its submodules.",
+    );
+    check(
+        configuration.log_level("kernel::memory::heap") == LevelFilter::Off,
+        "The most specific log directive was not used.",
+    );
+    check(
+        configuration.max_log_level() == LevelFilter::Debug
+            && configuration.log_filters().count() == 2,
+        "The invalid log directive was not ignored.",
+    );
+    check(
+        Configuration::parse("loglevel=trace loglevel=error").log_level("kernel")
+            == LevelFilter::Error,
+        "The last log level was not used.",
+    );
+}
+
+/// Tests the parsing of the other options.
+fn test_options() {
+    let configuration =
+        Configuration::parse("init=/bin/server unknown nosmp console=serial,framebuffer\tinit=");
+
+    check(
+        configuration.init() == Some("/bin/server"),
+        "The init option was not parsed.",
+    );
+    check(
+        configuration.console_enabled("serial")
+            && configuration.console_enabled("framebuffer")
+            && !configuration.console_enabled("uefi")
+            && !configuration.console_enabled("serial,framebuffer"),
+        "The console option was not parsed.",
+    );
+}
+
+/// The entry point for the UEFI loader.
+///
+/// This is the first function that gets called by the UEFI firmware.
+#[no_mangle]
+pub extern "C" fn efi_main(image_handle: Handle, system_table: &'static SystemTable) -> ! {
+    uefi_init(image_handle, system_table);
+
+    test_defaults();
+    test_log_directives();
+    test_options();
+
+    exit_integration_test(IntegrationTestExitCode::Success);
+}
+
+/// The panic implementation of the command line test.
+#[panic_handler]
+fn panic_fmt(panic_info: &PanicInfo) -> ! {
+    serial_println!("{}", panic_info);
+
+    exit_integration_test(IntegrationTestExitCode::Failure(""));
+}
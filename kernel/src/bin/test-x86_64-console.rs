@@ -0,0 +1,169 @@
+//! This binary runs the console registry test.
+//!
+//! This test makes sure that the output reaches the registered sinks according to their log levels,
+//! that sinks can be disabled, replaced and removed, that the kept log records are replayed to new sinks
+//! and that the firmware console is removed during boot.
+
+#![no_std]
+#![no_main]
+#![feature(alloc)]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use core::{
+    fmt::{self, Write},
+    panic::PanicInfo,
+};
+use kernel::{
+    arch::x86_64::{exit_integration_test, uefi::uefi_init, IntegrationTestExitCode},
+    console::{self, memory, Sink},
+    log_buffer, print, println, serial_println,
+    sync::Mutex,
+};
+use log::{Level, LevelFilter, Record};
+use nuefil::{system::SystemTable, Handle};
+
+/// The output received by the test sink.
+static OUTPUT: Mutex<Option<String>> = Mutex::new(None);
+
+/// A sink that collects its output in memory.
+struct TestSink;
+
+impl Sink for TestSink {
+    fn write_fmt(&self, args: fmt::Arguments) {
+        OUTPUT
+            .lock()
+            .get_or_insert_with(String::new)
+            .write_fmt(args)
+            .expect("Could not write to the test sink.");
+    }
+}
+
+/// A sink that ignores its output.
+struct NullSink;
+
+impl Sink for NullSink {
+    fn write_fmt(&self, _args: fmt::Arguments) {}
+}
+
+/// Fails the test with the given message if the condition doesn't hold.
+fn check(condition: bool, message: &'static str) {
+    if !condition {
+        exit_integration_test(IntegrationTestExitCode::Failure(message));
+    }
+}
+
+/// Returns and forgets the output received by the test sink.
+fn take_output() -> String {
+    OUTPUT.lock().take().unwrap_or_default()
+}
+
+/// Tests the sinks registered during boot.
+fn test_boot_sinks() {
+    let names: Vec<_> = console::sinks()
+        .iter()
+        .map(|registration| registration.name())
+        .collect();
+
+    check(
+        names.contains(&"serial") && names.contains(&"memory") && !names.contains(&"uefi"),
+        "The sinks were not switched when exiting the boot services.",
+    );
+
+    println!("Kept in memory.");
+
+    let mut contents = Vec::new();
+    memory::read(|bytes| contents.extend_from_slice(bytes));
+
+    check(
+        String::from_utf8_lossy(&contents).ends_with("Kept in memory.\n"),
+        "The output was not kept in memory.",
+    );
+}
+
+/// Tests the level filters and the management of the sinks.
+fn test_registry() {
+    log_buffer::push(
+        &Record::builder()
+            .level(Level::Error)
+            .args(format_args!("replayed"))
+            .build(),
+    );
+    console::register("test", &TestSink, LevelFilter::Warn);
+
+    check(
+        take_output().ends_with("\x1b[31mERROR\x1b[0m: replayed\n"),
+        "The kept log records were not replayed to a new sink.",
+    );
+
+    console::write_fmt(Some(Level::Error), format_args!("error "));
+    console::write_fmt(Some(Level::Warn), format_args!("warn "));
+    console::write_fmt(Some(Level::Info), format_args!("info "));
+    print!("print");
+    println!();
+
+    check(
+        take_output() == "error warn print\n",
+        "The level filter of the sink was not applied.",
+    );
+
+    check(
+        console::set_level("test", LevelFilter::Off),
+        "The level of the sink was not changed.",
+    );
+    println!("hidden");
+    check(
+        take_output().is_empty(),
+        "A sink with the level off received output.",
+    );
+
+    console::set_level("test", LevelFilter::Trace);
+    check(
+        console::set_enabled("test", false),
+        "The sink was not disabled.",
+    );
+    console::write_fmt(Some(Level::Error), format_args!("hidden"));
+    check(take_output().is_empty(), "A disabled sink received output.");
+
+    console::set_enabled("test", true);
+    console::write_fmt(Some(Level::Trace), format_args!("shown"));
+    check(take_output() == "shown", "The sink was not enabled again.");
+
+    let count = console::sinks().len();
+    console::register("test", &NullSink, LevelFilter::Trace);
+    println!("hidden");
+
+    check(
+        take_output().is_empty() && console::sinks().len() == count,
+        "The sink was not replaced.",
+    );
+    check(
+        console::unregister("test")
+            && !console::unregister("test")
+            && !console::set_enabled("test", true)
+            && console::sinks().len() == count - 1,
+        "The sink was not removed.",
+    );
+}
+
+/// The entry point for the UEFI loader.
+///
+/// This is the first function that gets called by the UEFI firmware.
+#[no_mangle]
+pub extern "C" fn efi_main(image_handle: Handle, system_table: &'static SystemTable) -> ! {
+    uefi_init(image_handle, system_table);
+
+    test_boot_sinks();
+    test_registry();
+
+    exit_integration_test(IntegrationTestExitCode::Success);
+}
+
+/// The panic implementation of the console registry test.
+#[panic_handler]
+fn panic_fmt(panic_info: &PanicInfo) -> ! {
+    serial_println!("{}", panic_info);
+
+    exit_integration_test(IntegrationTestExitCode::Failure(""));
+}
@@ -0,0 +1,289 @@
+//! This binary runs the drivers test.
+//!
+//! This test makes sure that user space drivers can access their I/O ports, device memory and interrupts.
+
+#![no_std]
+#![no_main]
+#![feature(alloc)]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use core::panic::PanicInfo;
+use kernel::{
+    arch::{
+        x86_64::{exit_integration_test, uefi::uefi_init, IntegrationTestExitCode},
+        Arch, Architecture,
+    },
+    capability::{CSpace, Rights},
+    io_port::IoPortRange,
+    ipc::Notification,
+    irq::{self, IrqLine},
+    memory::{allocate_zeroed_frame, AddressSpace, DeviceMemory, PageFlags, FRAME_ALLOCATOR},
+    object::KernelObject,
+    scheduler, serial_println,
+    sync::Mutex,
+    thread::{Thread, ThreadState},
+};
+use nuefil::{system::SystemTable, Handle};
+
+/// The code of the test thread.
+///
+/// It enables the I/O ports in slot 0 and writes to the first of them.
+/// Then it maps the device memory in slot 1 and reads its first word.
+/// Afterwards it lets the interrupt line in slot 2 signal the notification in slot 4 and waits on the notification in slot 3.
+/// Finally it acknowledges the interrupt and writes to a port it was not granted.
+/// All results are stored in its data page.
+const CODE: [u8; 132] = [
+    0x48, 0xbd, 0x00, 0x10, 0x00, 0x00, 0x80, 0x00, 0x00, 0x00, 0xb8, 0x30, 0x00, 0x00, 0x00, 0x31,
+    0xff, 0x0f, 0x05, 0x48, 0x89, 0x45, 0x00, 0xb0, 0x42, 0xe6, 0x80, 0x48, 0xc7, 0x45, 0x08, 0x01,
+    0x00, 0x00, 0x00, 0x48, 0xbb, 0x00, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0xb8, 0x31, 0x00,
+    0x00, 0x00, 0xbf, 0x01, 0x00, 0x00, 0x00, 0x48, 0x89, 0xde, 0x0f, 0x05, 0x48, 0x89, 0x45, 0x10,
+    0x48, 0x8b, 0x03, 0x48, 0x89, 0x45, 0x18, 0xb8, 0x32, 0x00, 0x00, 0x00, 0xbf, 0x02, 0x00, 0x00,
+    0x00, 0xbe, 0x04, 0x00, 0x00, 0x00, 0x0f, 0x05, 0x48, 0x89, 0x45, 0x20, 0xb8, 0x29, 0x00, 0x00,
+    0x00, 0xbf, 0x03, 0x00, 0x00, 0x00, 0x0f, 0x05, 0x48, 0x89, 0x45, 0x28, 0x48, 0x89, 0x7d, 0x30,
+    0xb8, 0x33, 0x00, 0x00, 0x00, 0xbf, 0x02, 0x00, 0x00, 0x00, 0x0f, 0x05, 0x48, 0x89, 0x45, 0x38,
+    0xe6, 0x84, 0x0f, 0x0b,
+];
+
+/// The offset of the data page from the start of user space.
+const DATA_OFFSET: usize = 0x1000;
+
+/// The offset of the top of the stack from the start of user space.
+const STACK_TOP_OFFSET: usize = 0x3000;
+
+/// The offset of the device memory from the start of user space.
+const DEVICE_MEMORY_OFFSET: usize = 0x1_0000;
+
+/// The I/O port the thread is allowed to use.
+const PORT: u16 = 0x80;
+
+/// The interrupt line the thread waits for.
+const IRQ: usize = 5;
+
+/// The badge of the capability the interrupt line signals the notification with.
+const BADGE: u64 = 0x40;
+
+/// The value stored in the device memory.
+const DEVICE_VALUE: u64 = 0x0123_4567_89ab_cdef;
+
+/// Fails the test with the given message if the condition doesn't hold.
+fn check(condition: bool, message: &'static str) {
+    if !condition {
+        exit_integration_test(IntegrationTestExitCode::Failure(message));
+    }
+}
+
+/// Creates an address space containing the code, a data page and a stack.
+fn create_address_space() -> Arc<Mutex<AddressSpace>> {
+    let start = Arch::USER_SPACE_START;
+    let writable = PageFlags::USER_ACCESSIBLE | PageFlags::WRITABLE;
+
+    let address_space = AddressSpace::new().and_then(|mut address_space| {
+        address_space.map(start, PageFlags::USER_ACCESSIBLE | PageFlags::EXECUTABLE)?;
+        address_space.write(start, &CODE)?;
+        address_space.map_range(
+            start + DATA_OFFSET,
+            STACK_TOP_OFFSET - DATA_OFFSET,
+            writable,
+        )?;
+
+        Ok(address_space)
+    });
+
+    match address_space {
+        Ok(address_space) => Arc::new(Mutex::new(address_space)),
+        Err(_) => exit_integration_test(IntegrationTestExitCode::Failure(
+            "Could not create an address space.",
+        )),
+    }
+}
+
+/// Reads the word with the given index from the data page of the address space.
+fn read_data(address_space: &Mutex<AddressSpace>, index: usize) -> usize {
+    let mut bytes = [0; 8];
+
+    check(
+        address_space
+            .lock()
+            .read(Arch::USER_SPACE_START + DATA_OFFSET + index * 8, &mut bytes)
+            .is_ok(),
+        "Could not read the data page.",
+    );
+
+    usize::from_le_bytes(bytes)
+}
+
+/// The entry point for the UEFI loader.
+///
+/// This is the first function that gets called by the UEFI firmware.
+#[no_mangle]
+pub extern "C" fn efi_main(image_handle: Handle, system_table: &'static SystemTable) -> ! {
+    uefi_init(image_handle, system_table);
+
+    let free_frames = FRAME_ALLOCATOR.lock().free_frames();
+
+    // A frame of normal memory stands in for the registers of a device.
+    let device_frame = match allocate_zeroed_frame() {
+        Some(frame) => frame,
+        None => exit_integration_test(IntegrationTestExitCode::Failure(
+            "Could not allocate the device frame.",
+        )),
+    };
+
+    // This is safe, because the frame was just allocated and is not used by anything else.
+    unsafe {
+        Arch::physical_to_virtual(device_frame)
+            .as_mut_ptr::<u64>()
+            .write(DEVICE_VALUE)
+    };
+
+    {
+        let ports = IoPortRange::claim(PORT, 1);
+        let line = IrqLine::claim(IRQ);
+
+        check(
+            IoPortRange::claim(PORT, 4).is_none(),
+            "An I/O port was claimed twice.",
+        );
+        check(
+            IrqLine::claim(IRQ).is_none(),
+            "An interrupt line was claimed twice.",
+        );
+        check(
+            IrqLine::claim(Arch::IRQ_COUNT).is_none(),
+            "A nonexistent interrupt line was claimed.",
+        );
+
+        let (ports, line) = match (ports, line) {
+            (Some(ports), Some(line)) => (ports, line),
+            _ => exit_integration_test(IntegrationTestExitCode::Failure(
+                "Could not claim the resources of the driver.",
+            )),
+        };
+
+        // This is safe, because the frame is only used as device memory.
+        let device_memory = unsafe { DeviceMemory::new(device_frame, 1) };
+        let notification = Arc::new(Notification::new());
+        let cspace = CSpace::new(5);
+
+        check(
+            CSpace::insert(
+                &cspace,
+                0,
+                KernelObject::IoPorts(Arc::new(ports)),
+                Rights::READ | Rights::WRITE,
+            )
+            .is_ok()
+                && CSpace::insert(
+                    &cspace,
+                    1,
+                    KernelObject::DeviceMemory(Arc::new(device_memory)),
+                    Rights::READ,
+                )
+                .is_ok()
+                && CSpace::insert(&cspace, 2, KernelObject::Irq(Arc::new(line)), Rights::WRITE)
+                    .is_ok()
+                && CSpace::insert(
+                    &cspace,
+                    3,
+                    KernelObject::Notification(notification),
+                    Rights::READ | Rights::WRITE,
+                )
+                .is_ok()
+                && cspace.mint(3, &cspace, 4, Rights::WRITE, BADGE).is_ok(),
+            "Could not set up the capability space.",
+        );
+
+        let address_space = create_address_space();
+        let thread = Arc::new(Thread::new(cspace, address_space.clone()));
+
+        Thread::start(
+            &thread,
+            Arch::USER_SPACE_START,
+            Arch::USER_SPACE_START + STACK_TOP_OFFSET,
+        );
+
+        scheduler::run_until_idle();
+
+        check(
+            read_data(&address_space, 0) == 0 && read_data(&address_space, 1) == 1,
+            "The thread could not use its I/O port.",
+        );
+        check(
+            read_data(&address_space, 2) == 0,
+            "Could not map the device memory.",
+        );
+        check(
+            read_data(&address_space, 3) == DEVICE_VALUE as usize,
+            "The device memory does not contain the value of the device.",
+        );
+        check(
+            address_space
+                .lock()
+                .flags(Arch::USER_SPACE_START + DEVICE_MEMORY_OFFSET)
+                .map_or(false, |flags| {
+                    flags.contains(PageFlags::NO_CACHE) && !flags.contains(PageFlags::WRITABLE)
+                }),
+            "The device memory is mapped with the wrong flags.",
+        );
+        check(
+            read_data(&address_space, 4) == 0,
+            "Could not set the notification of the interrupt line.",
+        );
+
+        match thread.state() {
+            ThreadState::BlockedOnNotification(_) => (),
+            _ => exit_integration_test(IntegrationTestExitCode::Failure(
+                "The thread does not wait for the interrupt.",
+            )),
+        }
+
+        // Interrupts arrive with their line masked.
+        Arch::mask_irq(IRQ);
+        irq::handle(IRQ);
+
+        scheduler::run_until_idle();
+
+        check(
+            read_data(&address_space, 5) == 0 && read_data(&address_space, 6) == BADGE as usize,
+            "The interrupt was not delivered to the notification.",
+        );
+        check(
+            read_data(&address_space, 7) == 0,
+            "Could not acknowledge the interrupt.",
+        );
+
+        match thread.state() {
+            ThreadState::Faulted => (),
+            _ => exit_integration_test(IntegrationTestExitCode::Failure(
+                "The thread could use an I/O port it was not granted.",
+            )),
+        }
+
+        Thread::suspend(&thread);
+    }
+
+    check(
+        IoPortRange::claim(PORT, 1).is_some() && IrqLine::claim(IRQ).is_some(),
+        "The resources of the driver were not released.",
+    );
+
+    // This is safe, because the device memory is no longer mapped anywhere.
+    unsafe { FRAME_ALLOCATOR.lock().deallocate(device_frame) };
+
+    check(
+        FRAME_ALLOCATOR.lock().free_frames() == free_frames,
+        "The frames of the device memory were freed or frames were leaked.",
+    );
+
+    exit_integration_test(IntegrationTestExitCode::Success);
+}
+
+/// The panic implementation of the drivers test.
+#[panic_handler]
+fn panic_fmt(panic_info: &PanicInfo) -> ! {
+    serial_println!("{}", panic_info);
+
+    exit_integration_test(IntegrationTestExitCode::Failure(""));
+}
@@ -0,0 +1,223 @@
+//! This binary runs the elf_loader test.
+//!
+//! This test makes sure that ELF executables are loaded correctly and malformed ones are rejected.
+
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+use kernel::{
+    arch::{
+        x86_64::{exit_integration_test, uefi::uefi_init, IntegrationTestExitCode},
+        Arch, Architecture,
+    },
+    elf::{load, ElfError, PIE_LOAD_BASE, STACK_TOP},
+    memory::{VirtualAddress, FRAME_ALLOCATOR, PAGE_SIZE},
+    serial_println,
+};
+use nuefil::{system::SystemTable, Handle};
+
+/// The size of the test binaries.
+const BINARY_SIZE: usize = 0x2000;
+
+/// The offset of the code in the test binaries.
+const CODE_OFFSET: usize = 0x1000;
+
+/// The code of the test binaries (an endless loop).
+const CODE: [u8; 2] = [0xeb, 0xfe];
+
+/// Writes a little endian integer of `size` bytes into the binary.
+fn put(binary: &mut [u8], offset: usize, size: usize, value: u64) {
+    for i in 0..size {
+        binary[offset + i] = (value >> (8 * i)) as u8;
+    }
+}
+
+/// Builds a minimal executable of the given type with a single segment at `address`.
+fn build_binary(file_type: u16, address: u64) -> [u8; BINARY_SIZE] {
+    let mut binary = [0; BINARY_SIZE];
+
+    // The file header.
+    binary[0..8].copy_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+    put(&mut binary, 16, 2, u64::from(file_type));
+    put(&mut binary, 18, 2, 62);
+    put(&mut binary, 20, 4, 1);
+    put(&mut binary, 24, 8, address);
+    put(&mut binary, 32, 8, 64);
+    put(&mut binary, 52, 2, 64);
+    put(&mut binary, 54, 2, 56);
+    put(&mut binary, 56, 2, 1);
+
+    // A readable and executable load segment with one page of uninitialized data.
+    put(&mut binary, 64, 4, 1);
+    put(&mut binary, 68, 4, 5);
+    put(&mut binary, 72, 8, CODE_OFFSET as u64);
+    put(&mut binary, 80, 8, address);
+    put(&mut binary, 88, 8, address);
+    put(&mut binary, 96, 8, CODE.len() as u64);
+    put(&mut binary, 104, 8, 2 * PAGE_SIZE as u64);
+    put(&mut binary, 112, 8, PAGE_SIZE as u64);
+
+    binary[CODE_OFFSET..CODE_OFFSET + CODE.len()].copy_from_slice(&CODE);
+
+    binary
+}
+
+/// Fails the test with the given message if the condition doesn't hold.
+fn check(condition: bool, message: &'static str) {
+    if !condition {
+        exit_integration_test(IntegrationTestExitCode::Failure(message));
+    }
+}
+
+/// Checks that the binary is rejected with the expected error.
+fn check_rejected(binary: &[u8], expected: ElfError, message: &'static str) {
+    check(load(binary, &[], &[]).err() == Some(expected), message);
+}
+
+/// The entry point for the UEFI loader.
+///
+/// This is the first function that gets called by the UEFI firmware.
+#[no_mangle]
+pub extern "C" fn efi_main(image_handle: Handle, system_table: &'static SystemTable) -> ! {
+    uefi_init(image_handle, system_table);
+
+    let free_frames = FRAME_ALLOCATOR.lock().free_frames();
+    let address = Arch::USER_SPACE_START.as_usize() as u64 + 0x1000;
+
+    {
+        let mut program = match load(
+            &build_binary(2, address),
+            &["test", "argument"],
+            &["KEY=value"],
+        ) {
+            Ok(program) => program,
+            Err(_) => exit_integration_test(IntegrationTestExitCode::Failure(
+                "A valid executable was rejected.",
+            )),
+        };
+
+        check(
+            program.entry_point == VirtualAddress::new(address as usize),
+            "The entry point is wrong.",
+        );
+
+        let mut code = [0; 2];
+        let mut uninitialized = [0xff; 1];
+        check(
+            program
+                .address_space
+                .read(program.entry_point, &mut code)
+                .is_ok()
+                && code == CODE,
+            "The code was not loaded correctly.",
+        );
+        check(
+            program
+                .address_space
+                .read(program.entry_point + PAGE_SIZE, &mut uninitialized)
+                .is_ok()
+                && uninitialized == [0],
+            "The uninitialized data was not zeroed.",
+        );
+
+        check(
+            program.stack_pointer.as_usize() % 16 == 0 && program.stack_pointer < STACK_TOP,
+            "The stack pointer is invalid.",
+        );
+
+        let mut word = [0; 8];
+        check(
+            program
+                .address_space
+                .read(program.stack_pointer, &mut word)
+                .is_ok()
+                && u64::from_le_bytes(word) == 2,
+            "The argument count is wrong.",
+        );
+
+        let mut argument = [0; 5];
+        check(
+            program
+                .address_space
+                .read(program.stack_pointer + 8, &mut word)
+                .is_ok()
+                && program
+                    .address_space
+                    .read(
+                        VirtualAddress::new(u64::from_le_bytes(word) as usize),
+                        &mut argument,
+                    )
+                    .is_ok()
+                && &argument == b"test\0",
+            "The first argument is wrong.",
+        );
+    }
+
+    match load(&build_binary(3, 0x1000), &[], &[]) {
+        Ok(program) => check(
+            program.entry_point == PIE_LOAD_BASE,
+            "The position independent executable was not relocated.",
+        ),
+        Err(_) => check(
+            false,
+            "A valid position independent executable was rejected.",
+        ),
+    }
+
+    let mut binary = build_binary(2, address);
+    binary[0] = 0;
+    check_rejected(&binary, ElfError::InvalidMagic, "Invalid magic accepted.");
+
+    let binary = build_binary(2, address);
+    check_rejected(
+        &binary[..40],
+        ElfError::Truncated("file header"),
+        "Truncated file accepted.",
+    );
+
+    let mut binary = build_binary(2, address);
+    put(&mut binary, 18, 2, 3);
+    check_rejected(
+        &binary,
+        ElfError::UnsupportedMachine(3),
+        "Wrong machine accepted.",
+    );
+
+    let mut binary = build_binary(2, address);
+    put(&mut binary, 104, 8, 1);
+    check_rejected(
+        &binary,
+        ElfError::SegmentFileSizeTooLarge(0),
+        "Segment larger in the file than in memory accepted.",
+    );
+
+    let mut binary = build_binary(2, address);
+    put(&mut binary, 68, 4, 6);
+    check_rejected(
+        &binary,
+        ElfError::InvalidEntryPoint(VirtualAddress::new(address as usize)),
+        "Entry point in a non-executable segment accepted.",
+    );
+
+    check_rejected(
+        &build_binary(2, 0x40_0000),
+        ElfError::SegmentOutsideUserSpace(0),
+        "Segment outside of user space accepted.",
+    );
+
+    check(
+        FRAME_ALLOCATOR.lock().free_frames() == free_frames,
+        "Frames were leaked by the loader.",
+    );
+
+    exit_integration_test(IntegrationTestExitCode::Success);
+}
+
+/// The panic implementation of the elf_loader test.
+#[panic_handler]
+fn panic_fmt(panic_info: &PanicInfo) -> ! {
+    serial_println!("{}", panic_info);
+
+    exit_integration_test(IntegrationTestExitCode::Failure(""));
+}
@@ -0,0 +1,228 @@
+//! This binary runs the framebuffer console test.
+//!
+//! This test makes sure that the framebuffer console encodes colors for all pixel formats,
+//! draws characters only after flushing and within the visible part of each line,
+//! and handles colors, line wrapping, tabs and scrolling.
+//! Framebuffers smaller than a character are refused.
+
+#![no_std]
+#![no_main]
+#![feature(alloc)]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::{fmt::Write, panic::PanicInfo};
+use kernel::{
+    arch::x86_64::{exit_integration_test, uefi::uefi_init, IntegrationTestExitCode},
+    console::framebuffer::{
+        Color, ConsoleError, Framebuffer, FramebufferConsole, PixelFormat, DEFAULT_BACKGROUND,
+        DEFAULT_FOREGROUND,
+    },
+    memory::{VirtualAddress, FRAME_ALLOCATOR},
+    serial_println,
+};
+use nuefil::{system::SystemTable, Handle};
+
+/// The number of visible pixels in a line of the test framebuffer, which fits ten characters.
+const WIDTH: usize = 80;
+
+/// The number of lines of the test framebuffer, which fits two rows of characters.
+const HEIGHT: usize = 40;
+
+/// The number of pixels from the start of one line of the test framebuffer to the start of the next.
+const STRIDE: usize = 96;
+
+/// The value of the pixels that the console hasn't drawn.
+const UNTOUCHED: u32 = 0xdead_beef;
+
+/// The pixel format of the test framebuffer.
+const FORMAT: PixelFormat = PixelFormat::Rgb;
+
+/// Fails the test with the given message if the condition doesn't hold.
+fn check(condition: bool, message: &'static str) {
+    if !condition {
+        exit_integration_test(IntegrationTestExitCode::Failure(message));
+    }
+}
+
+/// Returns the pixels of the character cell at the column and row.
+fn cell(pixels: &[u32], column: usize, row: usize) -> Vec<u32> {
+    (0..16)
+        .flat_map(|y| {
+            let start = (row * 16 + y) * STRIDE + column * 8;
+
+            pixels[start..start + 8].iter().cloned()
+        })
+        .collect()
+}
+
+/// Checks that the cell only contains the background and at least one pixel of the foreground.
+fn shows_character(cell: &[u32], foreground: Color) -> bool {
+    let foreground = FORMAT.encode(foreground);
+    let background = FORMAT.encode(DEFAULT_BACKGROUND);
+
+    cell.iter().any(|&pixel| pixel == foreground)
+        && cell
+            .iter()
+            .all(|&pixel| pixel == foreground || pixel == background)
+}
+
+/// Tests that colors are encoded correctly for all pixel formats.
+fn test_pixel_formats() {
+    let color = Color::new(0x11, 0x22, 0x33);
+    let rgb565 = PixelFormat::Bitmask {
+        red: 0xf800,
+        green: 0x07e0,
+        blue: 0x001f,
+    };
+
+    check(
+        PixelFormat::Rgb.encode(color) == 0x0033_2211
+            && PixelFormat::Bgr.encode(color) == 0x0011_2233,
+        "A color was not encoded correctly for a byte format.",
+    );
+    check(
+        rgb565.encode(Color::new(255, 255, 255)) == 0xffff
+            && rgb565.encode(Color::new(255, 0, 0)) == 0xf800
+            && rgb565.encode(Color::new(0, 128, 0)) == 31 << 5,
+        "A color was not encoded correctly for a bitmask format.",
+    );
+}
+
+/// Tests that no console is created on framebuffers that cannot hold a character.
+fn test_too_small() {
+    let mut pixels = Vec::new();
+    pixels.resize(STRIDE * HEIGHT, UNTOUCHED);
+
+    for &(width, height) in &[(7, HEIGHT), (WIDTH, 15), (0, 0)] {
+        // This is safe, because the pixels outlive the console, in case it is created anyway.
+        let framebuffer = unsafe {
+            Framebuffer::new(
+                VirtualAddress::new(pixels.as_mut_ptr() as usize),
+                width,
+                height,
+                STRIDE,
+                FORMAT,
+            )
+        };
+
+        check(
+            FramebufferConsole::new(framebuffer).err() == Some(ConsoleError::TooSmall),
+            "A console was created on a framebuffer smaller than a character.",
+        );
+    }
+}
+
+/// Tests the text output of the console.
+fn test_console() {
+    let mut pixels = Vec::new();
+    pixels.resize(STRIDE * HEIGHT, UNTOUCHED);
+
+    // This is safe, because the pixels are only used by the console while it exists.
+    let framebuffer = unsafe {
+        Framebuffer::new(
+            VirtualAddress::new(pixels.as_mut_ptr() as usize),
+            WIDTH,
+            HEIGHT,
+            STRIDE,
+            FORMAT,
+        )
+    };
+    let mut console = FramebufferConsole::new(framebuffer).expect("Could not create the console.");
+
+    check(
+        console.columns() == 10 && console.rows() == 2,
+        "The size of the console is wrong.",
+    );
+
+    write!(console, "A").expect("Could not write to the console.");
+
+    check(
+        pixels.iter().all(|&pixel| pixel == UNTOUCHED) && console.cursor() == (1, 0),
+        "The console was drawn before flushing.",
+    );
+
+    console.flush();
+
+    check(
+        shows_character(&cell(&pixels, 0, 0), DEFAULT_FOREGROUND),
+        "A character was not drawn.",
+    );
+    check(
+        (0..HEIGHT).all(|y| {
+            pixels[y * STRIDE + WIDTH..(y + 1) * STRIDE]
+                .iter()
+                .all(|&pixel| pixel == UNTOUCHED)
+        }),
+        "Pixels outside the visible lines were drawn.",
+    );
+
+    write!(console, "\x1b[31mB\x1b[0mC").expect("Could not write to the console.");
+    console.flush();
+
+    check(
+        shows_character(&cell(&pixels, 1, 0), Color::new(170, 0, 0))
+            && shows_character(&cell(&pixels, 2, 0), DEFAULT_FOREGROUND),
+        "The colors were not selected by the escape sequences.",
+    );
+
+    write!(console, "\tD").expect("Could not write to the console.");
+
+    check(
+        console.cursor() == (9, 0),
+        "A tab did not move to the next tab stop.",
+    );
+
+    write!(console, "EF").expect("Could not write to the console.");
+    console.flush();
+
+    let wrapped = cell(&pixels, 0, 1);
+
+    check(
+        console.cursor() == (1, 1) && shows_character(&wrapped, DEFAULT_FOREGROUND),
+        "A full row was not wrapped.",
+    );
+
+    write!(console, "\nG").expect("Could not write to the console.");
+    console.flush();
+
+    check(
+        console.cursor() == (1, 1)
+            && cell(&pixels, 0, 0) == wrapped
+            && shows_character(&cell(&pixels, 0, 1), DEFAULT_FOREGROUND)
+            && cell(&pixels, 0, 1) != wrapped,
+        "The console did not scroll.",
+    );
+
+    drop(console);
+}
+
+/// The entry point for the UEFI loader.
+///
+/// This is the first function that gets called by the UEFI firmware.
+#[no_mangle]
+pub extern "C" fn efi_main(image_handle: Handle, system_table: &'static SystemTable) -> ! {
+    uefi_init(image_handle, system_table);
+
+    let free_frames = FRAME_ALLOCATOR.lock().free_frames();
+
+    test_pixel_formats();
+    test_too_small();
+    test_console();
+
+    check(
+        FRAME_ALLOCATOR.lock().free_frames() == free_frames,
+        "The back buffer of the console was leaked.",
+    );
+
+    exit_integration_test(IntegrationTestExitCode::Success);
+}
+
+/// The panic implementation of the framebuffer console test.
+#[panic_handler]
+fn panic_fmt(panic_info: &PanicInfo) -> ! {
+    serial_println!("{}", panic_info);
+
+    exit_integration_test(IntegrationTestExitCode::Failure(""));
+}
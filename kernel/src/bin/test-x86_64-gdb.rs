@@ -0,0 +1,279 @@
+//! This binary runs the GDB stub test.
+//!
+//! This test makes sure that the stub answers the requests of GDB while the kernel is stopped,
+//! that software breakpoints and watchpoints stop the kernel and that the kernel continues afterwards.
+
+#![no_std]
+#![no_main]
+#![feature(alloc)]
+
+extern crate alloc;
+
+use alloc::{collections::VecDeque, format, string::String, vec::Vec};
+use core::{panic::PanicInfo, ptr};
+use kernel::{
+    arch::x86_64::{
+        exit_integration_test,
+        gdb::{self, Connection},
+        uefi::uefi_init,
+        IntegrationTestExitCode,
+    },
+    serial_println,
+    sync::Mutex,
+};
+use nuefil::{system::SystemTable, Handle};
+
+/// A static that is read by GDB.
+static READ: [u8; 8] = *b"BeetleOS";
+
+/// A static that is written by GDB.
+static mut WRITTEN: [u8; 4] = [0; 4];
+
+/// A static that is watched by GDB.
+static mut WATCHED: u64 = 0;
+
+/// An address in the higher half, which the firmware doesn't map.
+const UNMAPPED_ADDRESS: usize = 0xffff_8000_0000_0000;
+
+/// The bytes that GDB sends next.
+static INPUT: Mutex<Option<VecDeque<u8>>> = Mutex::new(None);
+
+/// The bytes sent to GDB.
+static OUTPUT: Mutex<Option<Vec<u8>>> = Mutex::new(None);
+
+/// A connection that plays the part of GDB with scripted input.
+struct TestConnection;
+
+impl Connection for TestConnection {
+    fn receive(&self) -> Option<u8> {
+        INPUT.lock().as_mut().and_then(|input| input.pop_front())
+    }
+
+    fn send(&self, bytes: &[u8]) {
+        OUTPUT
+            .lock()
+            .get_or_insert_with(Vec::new)
+            .extend_from_slice(bytes);
+    }
+}
+
+/// Fails the test with the given message if the condition doesn't hold.
+fn check(condition: bool, message: &'static str) {
+    if !condition {
+        exit_integration_test(IntegrationTestExitCode::Failure(message));
+    }
+}
+
+/// Queues the bytes as input from GDB.
+fn send(bytes: &[u8]) {
+    INPUT
+        .lock()
+        .get_or_insert_with(VecDeque::new)
+        .extend(bytes.iter().cloned());
+}
+
+/// Queues the packets as requests from GDB, acknowledging the replies to all but the last one.
+fn send_requests(requests: &[&str]) {
+    for (index, request) in requests.iter().enumerate() {
+        let checksum = request
+            .bytes()
+            .fold(0u8, |sum, byte| sum.wrapping_add(byte));
+
+        send(format!("${}#{:02x}", request, checksum).as_bytes());
+
+        if index + 1 < requests.len() {
+            send(b"+");
+        }
+    }
+}
+
+/// Returns and forgets the packets sent to GDB.
+fn take_replies() -> Vec<String> {
+    let output = OUTPUT.lock().take().unwrap_or_default();
+
+    output
+        .split(|&byte| byte == b'$')
+        .skip(1)
+        .map(|packet| {
+            let end = packet
+                .iter()
+                .position(|&byte| byte == b'#')
+                .expect("A packet was not terminated.");
+
+            String::from_utf8_lossy(&packet[..end]).into_owned()
+        })
+        .collect()
+}
+
+/// Returns the bytes as pairs of hexadecimal digits.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// A function that hits a software breakpoint set by GDB.
+#[inline(never)]
+fn breakpoint_target() -> u8 {
+    // The volatile read keeps the call from being optimized away.
+    // This is safe, because the static is always valid.
+    unsafe { ptr::read_volatile(&READ[0]) }
+}
+
+/// Tests the requests that are answered while the kernel is stopped.
+fn test_requests() {
+    let target = breakpoint_target as usize;
+    // This is safe, because functions are mapped.
+    let original = unsafe { ptr::read_volatile(target as *const u8) };
+    // This is safe, because only the address is taken.
+    let written = unsafe { WRITTEN.as_ptr() as usize };
+
+    send_requests(&[
+        "qSupported:multiprocess+;swbreak+;hwbreak+",
+        "?",
+        "qfThreadInfo",
+        "qsThreadInfo",
+        "Hg1",
+        "g",
+        &format!("m{:x},8", READ.as_ptr() as usize),
+        &format!("m{:x},1", UNMAPPED_ADDRESS),
+        &format!("M{:x},4:deadbeef", written),
+        "Hg7fff",
+        "Hg0",
+        &format!("Z0,{:x},1", target),
+        &format!("m{:x},1", target),
+        "c",
+    ]);
+
+    gdb::breakpoint();
+
+    let replies = take_replies();
+
+    check(replies.len() == 13, "The wrong number of replies was sent.");
+    check(
+        replies[0].contains("PacketSize=400") && replies[0].contains("swbreak+"),
+        "The features were not announced.",
+    );
+    check(
+        replies[1].starts_with("T05thread:1;"),
+        "The stop reason was wrong.",
+    );
+    check(
+        replies[2].starts_with("m1") && replies[3] == "l",
+        "The threads were not listed.",
+    );
+    check(replies[4] == "OK", "The kernel thread was not selected.");
+    check(
+        replies[5].len() == (17 * 8 + 7 * 4) * 2,
+        "The registers have the wrong size.",
+    );
+    check(replies[6] == hex(&READ), "The memory was read wrongly.");
+    check(replies[7] == "E0e", "Unmapped memory was read.");
+    check(replies[8] == "OK", "The memory was not written.");
+    // This is safe, because the kernel is not running anything else.
+    check(
+        unsafe { ptr::read_volatile(&WRITTEN) } == [0xde, 0xad, 0xbe, 0xef],
+        "The memory was written wrongly.",
+    );
+    check(replies[9] == "E03", "A missing thread was selected.");
+    check(replies[10] == "OK", "Any thread was not selected.");
+    check(replies[11] == "OK", "The breakpoint was not inserted.");
+    check(
+        replies[12] == hex(&[original]),
+        "The software breakpoint was not hidden.",
+    );
+}
+
+/// Tests that breakpoints and watchpoints stop the kernel.
+fn test_breakpoints() {
+    let target = breakpoint_target as usize;
+    // This is safe, because functions are mapped.
+    let original = unsafe { ptr::read_volatile(target as *const u8) };
+    // This is safe, because only the address is taken.
+    let watched = unsafe { &WATCHED as *const u64 as usize };
+
+    // The stop reply is acknowledged before the requests.
+    send(b"+");
+    send_requests(&[
+        &format!("z0,{:x},1", target),
+        &format!("Z2,{:x},8", watched),
+        "c",
+    ]);
+
+    check(
+        breakpoint_target() == READ[0],
+        "The function returned the wrong value.",
+    );
+
+    let replies = take_replies();
+
+    check(
+        replies.len() == 3 && replies[0] == "T05thread:1;swbreak:;",
+        "The software breakpoint was not reported.",
+    );
+    check(
+        replies[1] == "OK" && replies[2] == "OK",
+        "The breakpoints were not changed.",
+    );
+    // This is safe, because functions are mapped.
+    check(
+        unsafe { ptr::read_volatile(target as *const u8) } == original,
+        "The software breakpoint was not removed.",
+    );
+
+    send(b"+");
+    send_requests(&[&format!("z2,{:x},8", watched), "D"]);
+    // Detaching is acknowledged, unlike continuing.
+    send(b"+");
+
+    // This is safe, because the kernel is not running anything else.
+    unsafe { ptr::write_volatile(&mut WATCHED, 42) };
+
+    let replies = take_replies();
+
+    check(
+        replies.len() == 3 && replies[0] == format!("T05thread:1;watch:{:x};", watched),
+        "The watchpoint was not reported.",
+    );
+    check(
+        replies[1] == "OK" && replies[2] == "OK",
+        "The stub did not detach.",
+    );
+    // This is safe, because the kernel is not running anything else.
+    check(
+        unsafe { ptr::read_volatile(&WATCHED) } == 42,
+        "The watched memory was not written.",
+    );
+}
+
+/// The entry point for the UEFI loader.
+///
+/// This is the first function that gets called by the UEFI firmware.
+#[no_mangle]
+pub extern "C" fn efi_main(image_handle: Handle, system_table: &'static SystemTable) -> ! {
+    uefi_init(image_handle, system_table);
+
+    check(
+        !gdb::is_enabled(),
+        "The stub was enabled without a second serial port.",
+    );
+
+    gdb::set_connection(Some(&TestConnection));
+
+    check(gdb::is_enabled(), "The stub was not enabled.");
+
+    test_requests();
+    test_breakpoints();
+
+    gdb::set_connection(None);
+
+    check(!gdb::is_enabled(), "The stub was not disabled.");
+
+    exit_integration_test(IntegrationTestExitCode::Success);
+}
+
+/// The panic implementation of the GDB stub test.
+#[panic_handler]
+fn panic_fmt(panic_info: &PanicInfo) -> ! {
+    serial_println!("{}", panic_info);
+
+    exit_integration_test(IntegrationTestExitCode::Failure(""));
+}
@@ -0,0 +1,189 @@
+//! This binary runs the high resolution timer test.
+//!
+//! This test makes sure that high resolution timers expire in order, that the timer interrupt is programmed
+//! for the next deadline both while the processor is idle and while user code runs,
+//! and that timers can be added from the callbacks of expiring timers.
+
+#![no_std]
+#![no_main]
+#![feature(alloc)]
+
+extern crate alloc;
+
+use alloc::{boxed::Box, sync::Arc};
+use core::{
+    panic::PanicInfo,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use kernel::{
+    arch::{
+        x86_64::{exit_integration_test, uefi::uefi_init, IntegrationTestExitCode},
+        Arch, Architecture,
+    },
+    memory::{AddressSpace, PageFlags, FRAME_ALLOCATOR},
+    process::Process,
+    scheduler, serial_println,
+    time::{self, Duration, HrTimerQueue, Instant},
+};
+use nuefil::{system::SystemTable, Handle};
+
+/// The code of a process that loops forever.
+const SPIN_CODE: [u8; 2] = [0xeb, 0xfe];
+
+/// The exit status the spinning process is killed with.
+const KILL_STATUS: usize = 7;
+
+/// The interval of the timer that adds itself again.
+const INTERVAL: Duration = Duration::from_millis(2);
+
+/// The number of times the timer that adds itself again expires.
+const REPETITIONS: usize = 3;
+
+/// Counts the expirations of the timer that adds itself again.
+static EXPIRATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Fails the test with the given message if the condition doesn't hold.
+fn check(condition: bool, message: &'static str) {
+    if !condition {
+        exit_integration_test(IntegrationTestExitCode::Failure(message));
+    }
+}
+
+/// Returns the instant the given number of microseconds after the clock was started.
+fn microseconds(microseconds: u64) -> Instant {
+    Instant::from_nanoseconds(microseconds * 1000)
+}
+
+/// Tests that a queue expires its timers in order and only after their deadline.
+fn test_queue() {
+    static ORDER: AtomicUsize = AtomicUsize::new(0);
+
+    let mut queue = HrTimerQueue::new(Arch::current_cpu());
+    let record = |digit| {
+        Box::new(move || {
+            ORDER.store(ORDER.load(Ordering::SeqCst) * 10 + digit, Ordering::SeqCst);
+        })
+    };
+
+    queue.add(microseconds(30), record(3));
+    queue.add(microseconds(10), record(1));
+    let cancelled = queue.add(microseconds(20), record(9));
+    queue.add(microseconds(20), record(2));
+
+    check(
+        queue.cancel(cancelled)
+            && !queue.cancel(cancelled)
+            && cancelled.cpu() == Arch::current_cpu(),
+        "A high resolution timer could not be cancelled exactly once.",
+    );
+    check(
+        queue.next_deadline() == Some(microseconds(10)),
+        "The next deadline of the queue is wrong.",
+    );
+
+    for mut callback in queue.expire(microseconds(29)) {
+        callback();
+    }
+    check(
+        ORDER.load(Ordering::SeqCst) == 12 && queue.next_deadline() == Some(microseconds(30)),
+        "The high resolution timers did not expire in order of their deadlines.",
+    );
+
+    for mut callback in queue.expire(microseconds(30)) {
+        callback();
+    }
+    check(
+        ORDER.load(Ordering::SeqCst) == 123 && queue.is_empty(),
+        "A high resolution timer did not expire at its deadline.",
+    );
+}
+
+/// Adds the timer that adds itself again until it expired `REPETITIONS` times.
+fn add_repeating_timer() {
+    time::add_hrtimer(Instant::now() + INTERVAL, || {
+        if EXPIRATIONS.fetch_add(1, Ordering::SeqCst) + 1 < REPETITIONS {
+            add_repeating_timer();
+        }
+    });
+}
+
+/// Tests that the idle processor wakes up for the next deadline.
+fn test_idle() {
+    let start = Instant::now();
+
+    add_repeating_timer();
+
+    // No thread is runnable, so the scheduler only waits for the timers.
+    scheduler::run_until_idle();
+
+    check(
+        EXPIRATIONS.load(Ordering::SeqCst) == REPETITIONS
+            && start.elapsed() >= INTERVAL * REPETITIONS as u32
+            && time::next_deadline().is_none(),
+        "The idle processor did not wake up for the timers.",
+    );
+}
+
+/// Tests that the timer interrupt occurs while user code runs.
+fn test_user_interrupt() {
+    let address_space = AddressSpace::new()
+        .and_then(|mut address_space| {
+            address_space.map(
+                Arch::USER_SPACE_START,
+                PageFlags::USER_ACCESSIBLE | PageFlags::EXECUTABLE,
+            )?;
+            address_space.write(Arch::USER_SPACE_START, &SPIN_CODE)?;
+
+            Ok(address_space)
+        })
+        .expect("Could not create an address space.");
+    let process = Process::new(address_space, 1);
+    let killed = Arc::downgrade(&process);
+
+    check(
+        Process::start(&process, Arch::USER_SPACE_START, Arch::USER_SPACE_START).is_some(),
+        "Could not start the spinning process.",
+    );
+
+    time::add_hrtimer(Instant::now() + Duration::from_millis(10), move || {
+        if let Some(process) = killed.upgrade() {
+            Process::kill(&process, KILL_STATUS);
+        }
+    });
+
+    scheduler::run_until_idle();
+
+    check(
+        process.exit_status() == Some(KILL_STATUS),
+        "The timer interrupt did not stop the spinning process.",
+    );
+}
+
+/// The entry point for the UEFI loader.
+///
+/// This is the first function that gets called by the UEFI firmware.
+#[no_mangle]
+pub extern "C" fn efi_main(image_handle: Handle, system_table: &'static SystemTable) -> ! {
+    uefi_init(image_handle, system_table);
+
+    let free_frames = FRAME_ALLOCATOR.lock().free_frames();
+
+    test_queue();
+    test_idle();
+    test_user_interrupt();
+
+    check(
+        FRAME_ALLOCATOR.lock().free_frames() == free_frames,
+        "Frames were leaked by the spinning process.",
+    );
+
+    exit_integration_test(IntegrationTestExitCode::Success);
+}
+
+/// The panic implementation of the high resolution timer test.
+#[panic_handler]
+fn panic_fmt(panic_info: &PanicInfo) -> ! {
+    serial_println!("{}", panic_info);
+
+    exit_integration_test(IntegrationTestExitCode::Failure(""));
+}
@@ -0,0 +1,51 @@
+//! This binary runs the initrd test.
+//!
+//! This test makes sure that the initial ramdisk is loaded from the EFI system partition.
+
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+use kernel::{
+    arch::x86_64::{exit_integration_test, uefi::uefi_init, IntegrationTestExitCode},
+    initrd, serial_println,
+};
+use nuefil::{system::SystemTable, Handle};
+
+/// The entry point for the UEFI loader.
+///
+/// This is the first function that gets called by the UEFI firmware.
+#[no_mangle]
+pub extern "C" fn efi_main(image_handle: Handle, system_table: &'static SystemTable) -> ! {
+    uefi_init(image_handle, system_table);
+
+    let initrd = match initrd::get() {
+        Some(initrd) => initrd,
+        None => exit_integration_test(IntegrationTestExitCode::Failure(
+            "The initial ramdisk was not loaded.",
+        )),
+    };
+
+    match initrd.find("/README") {
+        Some(ref file) if file.is_regular_file() && !file.data.is_empty() => (),
+        _ => exit_integration_test(IntegrationTestExitCode::Failure(
+            "The README was not found in the initial ramdisk.",
+        )),
+    }
+
+    if initrd.find("missing").is_some() {
+        exit_integration_test(IntegrationTestExitCode::Failure(
+            "A missing file was found in the initial ramdisk.",
+        ));
+    }
+
+    exit_integration_test(IntegrationTestExitCode::Success);
+}
+
+/// The panic implementation of the initrd test.
+#[panic_handler]
+fn panic_fmt(panic_info: &PanicInfo) -> ! {
+    serial_println!("{}", panic_info);
+
+    exit_integration_test(IntegrationTestExitCode::Failure(""));
+}
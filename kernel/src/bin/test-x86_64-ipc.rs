@@ -0,0 +1,229 @@
+//! This binary runs the ipc test.
+//!
+//! This test makes sure that messages and capabilities are exchanged correctly between user threads
+//! and that a thread forgets a caller that was suspended before the reply.
+
+#![no_std]
+#![no_main]
+#![feature(alloc)]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use core::panic::PanicInfo;
+use kernel::{
+    arch::{
+        x86_64::{exit_integration_test, uefi::uefi_init, IntegrationTestExitCode},
+        Arch, Architecture,
+    },
+    capability::{CSpace, Rights},
+    ipc::Endpoint,
+    memory::{AddressSpace, PageFlags, FRAME_ALLOCATOR},
+    object::KernelObject,
+    scheduler, serial_println,
+    sync::Mutex,
+    thread::Thread,
+};
+use nuefil::{system::SystemTable, Handle};
+
+/// The code of the server.
+///
+/// It receives a message on the endpoint in slot 0, accepting a capability into slot 5.
+/// It then replies to every message with the first word incremented, the badge as the second word,
+/// the fifth word in the IPC buffer incremented and without capabilities.
+const SERVER_CODE: [u8; 99] = [
+    0xb8, 0x02, 0x00, 0x00, 0x00, 0x48, 0xbf, 0x00, 0x20, 0x00, 0x00, 0x80, 0x00, 0x00, 0x00, 0x0f,
+    0x05, 0x48, 0xbb, 0x00, 0x20, 0x00, 0x00, 0x80, 0x00, 0x00, 0x00, 0x48, 0xc7, 0x83, 0x18, 0x02,
+    0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x48, 0xc7, 0x83, 0x20, 0x02, 0x00, 0x00, 0xff, 0xff, 0xff,
+    0xff, 0x48, 0xc7, 0x83, 0x28, 0x02, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0xb8, 0x21, 0x00, 0x00,
+    0x00, 0x31, 0xff, 0x0f, 0x05, 0x48, 0x83, 0xc2, 0x01, 0x49, 0x89, 0xfa, 0x48, 0x83, 0x43, 0x20,
+    0x01, 0x48, 0x81, 0xe6, 0xff, 0xfc, 0xff, 0xff, 0xb8, 0x24, 0x00, 0x00, 0x00, 0x31, 0xff, 0x0f,
+    0x05, 0xeb, 0xe2,
+];
+
+/// The code of the client.
+///
+/// It calls the server through the endpoint in slot 0 with the word 41 and stores the result and the reply in its data page.
+/// It then calls the server with a long message and the capability in slot 2 and stores the reply.
+/// Finally it waits for a message on the endpoint in slot 1, which never arrives.
+const CLIENT_CODE: [u8; 155] = [
+    0xb8, 0x02, 0x00, 0x00, 0x00, 0x48, 0xbf, 0x00, 0x20, 0x00, 0x00, 0x80, 0x00, 0x00, 0x00, 0x0f,
+    0x05, 0x48, 0xbb, 0x00, 0x20, 0x00, 0x00, 0x80, 0x00, 0x00, 0x00, 0x48, 0xbd, 0x00, 0x10, 0x00,
+    0x00, 0x80, 0x00, 0x00, 0x00, 0xb8, 0x22, 0x00, 0x00, 0x00, 0x31, 0xff, 0xbe, 0x02, 0x00, 0x00,
+    0x00, 0xba, 0x29, 0x00, 0x00, 0x00, 0x45, 0x31, 0xd2, 0x0f, 0x05, 0x48, 0x89, 0x45, 0x00, 0x48,
+    0x89, 0x55, 0x08, 0x4c, 0x89, 0x55, 0x10, 0x48, 0xc7, 0x43, 0x20, 0x64, 0x00, 0x00, 0x00, 0x48,
+    0xc7, 0x43, 0x28, 0xc8, 0x00, 0x00, 0x00, 0x48, 0xc7, 0x83, 0x00, 0x02, 0x00, 0x00, 0x02, 0x00,
+    0x00, 0x00, 0xb8, 0x22, 0x00, 0x00, 0x00, 0x31, 0xff, 0xbe, 0x06, 0x01, 0x05, 0x00, 0xba, 0x01,
+    0x00, 0x00, 0x00, 0x0f, 0x05, 0x48, 0x89, 0x45, 0x18, 0x48, 0x89, 0x75, 0x20, 0x48, 0x8b, 0x43,
+    0x20, 0x48, 0x89, 0x45, 0x28, 0x48, 0x8b, 0x43, 0x28, 0x48, 0x89, 0x45, 0x30, 0xb8, 0x21, 0x00,
+    0x00, 0x00, 0xbf, 0x01, 0x00, 0x00, 0x00, 0x0f, 0x05, 0x0f, 0x0b,
+];
+
+/// The offset of the data page from the start of user space.
+const DATA_OFFSET: usize = 0x1000;
+
+/// The offset of the top of the stack from the start of user space.
+const STACK_TOP_OFFSET: usize = 0x4000;
+
+/// The badge of the client's endpoint capability.
+const CLIENT_BADGE: u64 = 7;
+
+/// Fails the test with the given message if the condition doesn't hold.
+fn check(condition: bool, message: &'static str) {
+    if !condition {
+        exit_integration_test(IntegrationTestExitCode::Failure(message));
+    }
+}
+
+/// Creates an address space containing the code, a data page, an IPC buffer and a stack.
+///
+/// The IPC buffer is the page following the data page, which is where the test programs expect it.
+fn create_address_space(code: &[u8]) -> Arc<Mutex<AddressSpace>> {
+    let start = Arch::USER_SPACE_START;
+    let writable = PageFlags::USER_ACCESSIBLE | PageFlags::WRITABLE;
+
+    let address_space = AddressSpace::new().and_then(|mut address_space| {
+        address_space.map(start, PageFlags::USER_ACCESSIBLE | PageFlags::EXECUTABLE)?;
+        address_space.write(start, code)?;
+        address_space.map_range(
+            start + DATA_OFFSET,
+            STACK_TOP_OFFSET - DATA_OFFSET,
+            writable,
+        )?;
+
+        Ok(address_space)
+    });
+
+    match address_space {
+        Ok(address_space) => Arc::new(Mutex::new(address_space)),
+        Err(_) => exit_integration_test(IntegrationTestExitCode::Failure(
+            "Could not create an address space.",
+        )),
+    }
+}
+
+/// Reads the word with the given index from the data page of the address space.
+fn read_data(address_space: &Mutex<AddressSpace>, index: usize) -> usize {
+    let mut bytes = [0; 8];
+
+    check(
+        address_space
+            .lock()
+            .read(Arch::USER_SPACE_START + DATA_OFFSET + index * 8, &mut bytes)
+            .is_ok(),
+        "Could not read the data page.",
+    );
+
+    usize::from_le_bytes(bytes)
+}
+
+/// The entry point for the UEFI loader.
+///
+/// This is the first function that gets called by the UEFI firmware.
+#[no_mangle]
+pub extern "C" fn efi_main(image_handle: Handle, system_table: &'static SystemTable) -> ! {
+    uefi_init(image_handle, system_table);
+
+    let free_frames = FRAME_ALLOCATOR.lock().free_frames();
+
+    {
+        let endpoint = KernelObject::Endpoint(Arc::new(Endpoint::new()));
+        let unused_endpoint = KernelObject::Endpoint(Arc::new(Endpoint::new()));
+        let granted_endpoint = KernelObject::Endpoint(Arc::new(Endpoint::new()));
+
+        let server_cspace = CSpace::new(8);
+        let client_cspace = CSpace::new(8);
+
+        check(
+            CSpace::insert(&server_cspace, 0, endpoint.clone(), Rights::all()).is_ok()
+                && server_cspace
+                    .mint(
+                        0,
+                        &client_cspace,
+                        0,
+                        Rights::WRITE | Rights::GRANT,
+                        CLIENT_BADGE,
+                    )
+                    .is_ok()
+                && CSpace::insert(&client_cspace, 1, unused_endpoint, Rights::READ).is_ok()
+                && CSpace::insert(&client_cspace, 2, granted_endpoint.clone(), Rights::all())
+                    .is_ok(),
+            "Could not set up the capability spaces.",
+        );
+
+        let server_address_space = create_address_space(&SERVER_CODE);
+        let client_address_space = create_address_space(&CLIENT_CODE);
+
+        let server = Arc::new(Thread::new(server_cspace.clone(), server_address_space));
+        let client = Arc::new(Thread::new(client_cspace, client_address_space.clone()));
+
+        let stack = Arch::USER_SPACE_START + STACK_TOP_OFFSET;
+        Thread::start(&server, Arch::USER_SPACE_START, stack);
+        Thread::start(&client, Arch::USER_SPACE_START, stack);
+
+        scheduler::run_until_idle();
+
+        check(
+            read_data(&client_address_space, 0) == 0,
+            "The first call failed.",
+        );
+        check(
+            read_data(&client_address_space, 1) == 42,
+            "The reply to the first call is wrong.",
+        );
+        check(
+            read_data(&client_address_space, 2) == CLIENT_BADGE as usize,
+            "The server did not receive the badge.",
+        );
+        check(
+            read_data(&client_address_space, 3) == 0,
+            "The second call failed.",
+        );
+        check(
+            read_data(&client_address_space, 4) == 0x50006,
+            "The reply to the second call has the wrong message info.",
+        );
+        check(
+            read_data(&client_address_space, 5) == 101
+                && read_data(&client_address_space, 6) == 200,
+            "The long message was not transferred through the IPC buffers.",
+        );
+        check(
+            server_cspace
+                .lookup(5)
+                .map(|capability| capability.object().is_same_object(&granted_endpoint))
+                == Ok(true),
+            "The capability was not transferred.",
+        );
+        check(
+            server.state().is_blocked_on_receive() && client.state().is_blocked_on_receive(),
+            "The threads are not waiting for messages.",
+        );
+
+        // A caller that is suspended while it waits for the reply is forgotten by the thread it called.
+        Thread::set_caller(&server, client.clone());
+        Thread::suspend(&client);
+
+        check(
+            server.take_caller().is_none(),
+            "The server still refers to a suspended caller.",
+        );
+
+        Thread::suspend(&server);
+    }
+
+    check(
+        FRAME_ALLOCATOR.lock().free_frames() == free_frames,
+        "Frames were leaked by the threads.",
+    );
+
+    exit_integration_test(IntegrationTestExitCode::Success);
+}
+
+/// The panic implementation of the ipc test.
+#[panic_handler]
+fn panic_fmt(panic_info: &PanicInfo) -> ! {
+    serial_println!("{}", panic_info);
+
+    exit_integration_test(IntegrationTestExitCode::Failure(""));
+}
@@ -0,0 +1,254 @@
+//! This binary runs the keyboard test.
+//!
+//! This test makes sure that the scancodes of both sets are decoded into the right key events,
+//! that the modifiers and layouts produce the right characters, that the key events reach the consoles
+//! and that the keyboard of the PS/2 controller emulated by QEMU is found.
+
+#![no_std]
+#![no_main]
+#![feature(alloc)]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use core::panic::PanicInfo;
+use kernel::{
+    arch::x86_64::{exit_integration_test, ps2, uefi::uefi_init, IntegrationTestExitCode},
+    cmdline::Configuration,
+    console::{
+        self,
+        keyboard::{
+            self,
+            layout::{self, Layout},
+            scancode::{Decoder, ScancodeSet},
+            KeyCode, KeyEvent, KeyState, Keyboard, Modifiers,
+        },
+    },
+    serial_println,
+};
+use nuefil::{system::SystemTable, Handle};
+
+/// Fails the test with the given message if the condition doesn't hold.
+fn check(condition: bool, message: &'static str) {
+    if !condition {
+        exit_integration_test(IntegrationTestExitCode::Failure(message));
+    }
+}
+
+/// Returns the key events decoded from the bytes.
+fn decode(set: ScancodeSet, bytes: &[u8]) -> Vec<KeyEvent> {
+    let mut decoder = Decoder::new(set);
+
+    bytes
+        .iter()
+        .filter_map(|&byte| decoder.advance(byte))
+        .collect()
+}
+
+/// Returns the characters typed with the bytes on a keyboard with the layout.
+fn type_bytes(set: ScancodeSet, layout: Layout, bytes: &[u8]) -> String {
+    let mut keyboard = Keyboard::new(set);
+    let mut characters = String::new();
+
+    for &byte in bytes {
+        if let Some(event) = keyboard.receive_byte(byte) {
+            if event.state == KeyState::Pressed {
+                characters.extend(layout.character(event.key, keyboard.modifiers()));
+            }
+        }
+    }
+
+    characters
+}
+
+/// Tests the decoding of scancode set 1.
+fn test_set_1() {
+    check(
+        decode(ScancodeSet::Set1, &[0x1e, 0x9e])
+            == [
+                KeyEvent::new(KeyCode::A, KeyState::Pressed),
+                KeyEvent::new(KeyCode::A, KeyState::Released),
+            ],
+        "A key was decoded wrongly in set 1.",
+    );
+    check(
+        decode(ScancodeSet::Set1, &[0xe0, 0x48, 0xe0, 0xc8])
+            == [
+                KeyEvent::new(KeyCode::Up, KeyState::Pressed),
+                KeyEvent::new(KeyCode::Up, KeyState::Released),
+            ],
+        "An extended key was decoded wrongly in set 1.",
+    );
+    check(
+        decode(
+            ScancodeSet::Set1,
+            &[0xe0, 0x2a, 0xe0, 0x53, 0xe0, 0xd3, 0xe0, 0xaa],
+        ) == [
+            KeyEvent::new(KeyCode::Delete, KeyState::Pressed),
+            KeyEvent::new(KeyCode::Delete, KeyState::Released),
+        ],
+        "The additional shift keys were not ignored in set 1.",
+    );
+    check(
+        decode(
+            ScancodeSet::Set1,
+            &[0xe1, 0x1d, 0x45, 0xe1, 0x9d, 0xc5, 0x10],
+        ) == [
+            KeyEvent::new(KeyCode::Pause, KeyState::Pressed),
+            KeyEvent::new(KeyCode::Q, KeyState::Pressed),
+        ],
+        "The pause key was decoded wrongly in set 1.",
+    );
+    check(
+        decode(ScancodeSet::Set1, &[0xfa, 0xff, 0x7f]).is_empty(),
+        "Responses or unknown scancodes produced key events in set 1.",
+    );
+}
+
+/// Tests the decoding of scancode set 2.
+fn test_set_2() {
+    check(
+        decode(ScancodeSet::Set2, &[0x1c, 0xf0, 0x1c])
+            == [
+                KeyEvent::new(KeyCode::A, KeyState::Pressed),
+                KeyEvent::new(KeyCode::A, KeyState::Released),
+            ],
+        "A key was decoded wrongly in set 2.",
+    );
+    check(
+        decode(ScancodeSet::Set2, &[0xe0, 0x11, 0xe0, 0xf0, 0x11])
+            == [
+                KeyEvent::new(KeyCode::RightAlt, KeyState::Pressed),
+                KeyEvent::new(KeyCode::RightAlt, KeyState::Released),
+            ],
+        "An extended key was decoded wrongly in set 2.",
+    );
+    check(
+        decode(
+            ScancodeSet::Set2,
+            &[0xe1, 0x14, 0x77, 0xe1, 0xf0, 0x14, 0xf0, 0x77, 0x83],
+        ) == [
+            KeyEvent::new(KeyCode::Pause, KeyState::Pressed),
+            KeyEvent::new(KeyCode::F7, KeyState::Pressed),
+        ],
+        "The pause key was decoded wrongly in set 2.",
+    );
+}
+
+/// Tests the modifiers and the characters of the layouts.
+fn test_layouts() {
+    let mut modifiers = Modifiers::empty();
+    modifiers.update(KeyEvent::new(KeyCode::CapsLock, KeyState::Pressed));
+    modifiers.update(KeyEvent::new(KeyCode::CapsLock, KeyState::Released));
+    modifiers.update(KeyEvent::new(KeyCode::LeftShift, KeyState::Pressed));
+
+    check(
+        modifiers == Modifiers::CAPS_LOCK | Modifiers::LEFT_SHIFT,
+        "The modifiers were not tracked.",
+    );
+
+    // Shift, h, release shift, i, 1, shift, 1.
+    let bytes = [0x2a, 0x23, 0xaa, 0x17, 0x02, 0x2a, 0x02];
+
+    check(
+        type_bytes(ScancodeSet::Set1, Layout::Us, &bytes) == "Hi1!",
+        "The US layout produced the wrong characters.",
+    );
+    check(
+        type_bytes(ScancodeSet::Set1, Layout::German, &bytes) == "Hi1!",
+        "The German layout produced the wrong characters.",
+    );
+    // Caps lock, y, shift, z, release shift, AltGr, q, semicolon.
+    check(
+        type_bytes(
+            ScancodeSet::Set2,
+            Layout::German,
+            &[
+                0x58, 0xf0, 0x58, 0x35, 0x12, 0x1a, 0xf0, 0x12, 0xe0, 0x11, 0x15, 0xe0, 0xf0, 0x11,
+                0x4c,
+            ],
+        ) == "Zy@Ö",
+        "The German layout didn't apply the modifiers.",
+    );
+
+    check(
+        Layout::Us.character(KeyCode::C, Modifiers::LEFT_CONTROL) == Some('\x03'),
+        "Control didn't produce a control character.",
+    );
+    check(
+        Layout::German.character(KeyCode::KeypadPeriod, Modifiers::NUM_LOCK) == Some(',')
+            && Layout::Us.character(KeyCode::Keypad7, Modifiers::NUM_LOCK) == Some('7'),
+        "The keypad produced the wrong characters with num lock.",
+    );
+    check(
+        Layout::Us
+            .character(KeyCode::Keypad8, Modifiers::empty())
+            .is_none()
+            && layout::escape_sequence(KeyCode::Keypad8, Modifiers::empty()) == Some("\x1b[A"),
+        "The keypad didn't produce cursor keys without num lock.",
+    );
+
+    check(
+        "de".parse() == Ok(Layout::German)
+            && "us".parse() == Ok(Layout::Us)
+            && "fr".parse::<Layout>().is_err(),
+        "The layout names were parsed wrongly.",
+    );
+}
+
+/// Tests that the key events reach the consoles with the configured layout.
+fn test_delivery() {
+    keyboard::configure(&Configuration::parse("keymap=de"));
+
+    check(
+        keyboard::layout() == Layout::German,
+        "The layout was not configured.",
+    );
+
+    console::set_echo(false);
+
+    let mut device = Keyboard::new(ScancodeSet::Set1);
+
+    // The keys labelled z, left arrow and y on US keyboards, backspace, a, enter.
+    for &byte in &[0x2c, 0xac, 0xe0, 0x4b, 0x15, 0x0e, 0x1e, 0x1c] {
+        if let Some(event) = device.receive_byte(byte) {
+            keyboard::deliver(event, device.modifiers());
+        }
+    }
+
+    check(
+        console::read_line().map_or(false, |line| line.as_str() == "ya"),
+        "The key events didn't reach the consoles.",
+    );
+
+    console::set_echo(true);
+    keyboard::set_layout(Layout::Us);
+}
+
+/// The entry point for the UEFI loader.
+///
+/// This is the first function that gets called by the UEFI firmware.
+#[no_mangle]
+pub extern "C" fn efi_main(image_handle: Handle, system_table: &'static SystemTable) -> ! {
+    uefi_init(image_handle, system_table);
+
+    check(
+        ps2::scancode_set().is_some(),
+        "The PS/2 keyboard was not found.",
+    );
+
+    test_set_1();
+    test_set_2();
+    test_layouts();
+    test_delivery();
+
+    exit_integration_test(IntegrationTestExitCode::Success);
+}
+
+/// The panic implementation of the keyboard test.
+#[panic_handler]
+fn panic_fmt(panic_info: &PanicInfo) -> ! {
+    serial_println!("{}", panic_info);
+
+    exit_integration_test(IntegrationTestExitCode::Failure(""));
+}
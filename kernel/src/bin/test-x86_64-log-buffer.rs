@@ -0,0 +1,291 @@
+//! This binary runs the log buffer test.
+//!
+//! This test makes sure that log records are kept with their metadata, that long messages are truncated,
+//! that the oldest records are overwritten once the buffer is full
+//! and that the records can be read with the `LOG_READ` system call, but not into read-only memory
+//! and not without a capability to the log.
+
+#![no_std]
+#![no_main]
+#![feature(alloc)]
+
+extern crate alloc;
+
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::panic::PanicInfo;
+use kernel::{
+    arch::{
+        x86_64::{exit_integration_test, uefi::uefi_init, IntegrationTestExitCode},
+        Arch, Architecture,
+    },
+    capability::{CSpace, CapabilityError, Rights},
+    log_buffer,
+    memory::{AddressSpace, MemoryError, PageFlags, FRAME_ALLOCATOR, PAGE_SIZE},
+    object::KernelObject,
+    serial_println,
+    sync::Mutex,
+    syscall::{self, number, SyscallError, ARGUMENT_COUNT},
+    thread::Thread,
+};
+use log::{Level, Record};
+use nuefil::{system::SystemTable, Handle};
+
+/// The slot of the capability to the log.
+const LOG_SLOT: usize = 0;
+
+/// The slot of the capability to the log that does not grant reading.
+const WRITE_ONLY_LOG_SLOT: usize = 1;
+
+/// Fails the test with the given message if the condition doesn't hold.
+fn check(condition: bool, message: &'static str) {
+    if !condition {
+        exit_integration_test(IntegrationTestExitCode::Failure(message));
+    }
+}
+
+/// Adds a record with the given level and message to the log buffer.
+///
+/// Returns the sequence number of the record.
+fn push(level: Level, message: &str) -> usize {
+    let sequence = log_buffer::next_sequence();
+
+    log_buffer::push(
+        &Record::builder()
+            .level(level)
+            .module_path(Some("test::log_buffer"))
+            .file(Some("src/bin/test-x86_64-log-buffer.rs"))
+            .line(Some(42))
+            .args(format_args!("{}", message))
+            .build(),
+    );
+
+    sequence
+}
+
+/// Performs the system call with the given arguments for the thread.
+fn call(thread: &Arc<Thread>, number: usize, arguments: &[usize]) -> Result<usize, SyscallError> {
+    let mut all_arguments = [0; ARGUMENT_COUNT];
+    all_arguments[..arguments.len()].copy_from_slice(arguments);
+
+    syscall::handle(thread, number, all_arguments)
+}
+
+/// Tests that records are kept with their metadata.
+fn test_records() {
+    let sequence = push(Level::Warn, "A warning.");
+    let record = log_buffer::get(sequence).expect("The record was not kept.");
+
+    check(
+        record.sequence() == sequence
+            && record.level() == Level::Warn
+            && record.module() == "test::log_buffer"
+            && record.file() == "src/bin/test-x86_64-log-buffer.rs"
+            && record.line() == 42
+            && record.cpu() == Arch::current_cpu()
+            && record.message() == "A warning.",
+        "The record was not kept correctly.",
+    );
+
+    let mut long_message = String::new();
+    for _ in 0..100 {
+        long_message.push('ä');
+    }
+
+    let record =
+        log_buffer::get(push(Level::Info, &long_message)).expect("The long record was not kept.");
+
+    check(
+        !record.message().is_empty()
+            && record.message().len() < long_message.len()
+            && long_message.starts_with(record.message()),
+        "A long message was not truncated at a character boundary.",
+    );
+    check(
+        log_buffer::records().last().map(|record| record.sequence()) == Some(sequence + 1)
+            && log_buffer::records_since(sequence + 2).next().is_none(),
+        "The records were not iterated in order.",
+    );
+}
+
+/// Tests that the oldest records are overwritten once the buffer is full.
+fn test_overwriting() {
+    let first = push(Level::Debug, "The first record.");
+    let mut last = first;
+
+    while log_buffer::get(first).is_some() {
+        last = push(Level::Trace, "Another record.");
+    }
+
+    let sequences: Vec<_> = log_buffer::records()
+        .map(|record| record.sequence())
+        .collect();
+
+    check(
+        last > first
+            && sequences.first() == Some(&(first + 1))
+            && sequences.last() == Some(&last)
+            && sequences.len() == last - first,
+        "The oldest record was not the only one overwritten.",
+    );
+    check(
+        log_buffer::dropped_count() == 0,
+        "A record was dropped without concurrent writers.",
+    );
+}
+
+/// Tests reading the records with a system call.
+fn test_syscall() {
+    let address_space = AddressSpace::new()
+        .and_then(|mut address_space| {
+            address_space.map(
+                Arch::USER_SPACE_START,
+                PageFlags::USER_ACCESSIBLE | PageFlags::WRITABLE,
+            )?;
+            address_space.map(
+                Arch::USER_SPACE_START + PAGE_SIZE,
+                PageFlags::USER_ACCESSIBLE,
+            )?;
+
+            Ok(address_space)
+        })
+        .expect("Could not create an address space.");
+    let address_space = Arc::new(Mutex::new(address_space));
+    let cspace = CSpace::new(2);
+
+    check(
+        CSpace::insert(&cspace, LOG_SLOT, KernelObject::Log, Rights::READ).is_ok()
+            && CSpace::insert(
+                &cspace,
+                WRITE_ONLY_LOG_SLOT,
+                KernelObject::Log,
+                Rights::WRITE,
+            )
+            .is_ok(),
+        "Could not insert the capabilities to the log.",
+    );
+
+    let thread = Arc::new(Thread::new(cspace, address_space.clone()));
+    let unprivileged = Arc::new(Thread::new(CSpace::new(1), address_space.clone()));
+    let sequence = push(Level::Error, "Read by a system call.");
+    let buffer = Arch::USER_SPACE_START.as_usize();
+
+    check(
+        call(
+            &thread,
+            number::LOG_READ,
+            &[LOG_SLOT, sequence, buffer, PAGE_SIZE],
+        ) == Ok(0)
+            && thread.context().argument(0) == sequence,
+        "The record could not be read with a system call.",
+    );
+
+    let length = thread.context().argument(1);
+    let mut text = Vec::new();
+    text.resize(length, 0);
+    address_space
+        .lock()
+        .read(Arch::USER_SPACE_START, &mut text)
+        .expect("Could not read the text of the record.");
+
+    check(
+        log_buffer::get(sequence).map(|record| record.to_string().into_bytes()) == Some(text),
+        "The text of the record is wrong.",
+    );
+
+    check(
+        call(&thread, number::LOG_READ, &[LOG_SLOT, sequence, buffer, 10]) == Ok(0)
+            && thread.context().argument(1) == 10,
+        "The text of the record was not truncated.",
+    );
+    check(
+        call(&thread, number::LOG_READ, &[LOG_SLOT, 0, buffer, PAGE_SIZE]) == Ok(0)
+            && thread.context().argument(0) > 0,
+        "The oldest kept record was not read.",
+    );
+    check(
+        call(
+            &thread,
+            number::LOG_READ,
+            &[LOG_SLOT, sequence + 1, buffer, PAGE_SIZE],
+        ) == Ok(0)
+            && thread.context().argument(0) == log_buffer::next_sequence()
+            && thread.context().argument(1) == 0,
+        "A record was read after the newest one.",
+    );
+    check(
+        call(
+            &thread,
+            number::LOG_READ,
+            &[LOG_SLOT, sequence, 0, PAGE_SIZE],
+        )
+        .is_err(),
+        "A record was written outside of user space.",
+    );
+
+    let read_only = Arch::USER_SPACE_START + PAGE_SIZE;
+
+    check(
+        call(
+            &thread,
+            number::LOG_READ,
+            &[LOG_SLOT, sequence, read_only.as_usize(), PAGE_SIZE],
+        ) == Err(SyscallError::Memory(MemoryError::AccessViolation(
+            read_only,
+        ))),
+        "A record was written to read-only memory.",
+    );
+
+    check(
+        call(
+            &unprivileged,
+            number::LOG_READ,
+            &[LOG_SLOT, sequence, buffer, PAGE_SIZE],
+        ) == Err(SyscallError::Capability(CapabilityError::EmptySlot(
+            LOG_SLOT,
+        ))),
+        "A thread without a capability to the log could read it.",
+    );
+    check(
+        call(
+            &thread,
+            number::LOG_READ,
+            &[WRITE_ONLY_LOG_SLOT, sequence, buffer, PAGE_SIZE],
+        ) == Err(SyscallError::Capability(
+            CapabilityError::InsufficientRights,
+        )),
+        "The log could be read with a capability that does not grant reading.",
+    );
+}
+
+/// The entry point for the UEFI loader.
+///
+/// This is the first function that gets called by the UEFI firmware.
+#[no_mangle]
+pub extern "C" fn efi_main(image_handle: Handle, system_table: &'static SystemTable) -> ! {
+    uefi_init(image_handle, system_table);
+
+    let free_frames = FRAME_ALLOCATOR.lock().free_frames();
+
+    test_records();
+    test_overwriting();
+    test_syscall();
+
+    check(
+        FRAME_ALLOCATOR.lock().free_frames() == free_frames,
+        "The address space of the test thread was leaked.",
+    );
+
+    exit_integration_test(IntegrationTestExitCode::Success);
+}
+
+/// The panic implementation of the log buffer test.
+#[panic_handler]
+fn panic_fmt(panic_info: &PanicInfo) -> ! {
+    serial_println!("{}", panic_info);
+
+    exit_integration_test(IntegrationTestExitCode::Failure(""));
+}
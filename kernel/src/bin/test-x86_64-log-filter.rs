@@ -0,0 +1,208 @@
+//! This binary runs the log filter test.
+//!
+//! This test makes sure that the log levels of modules can be changed at runtime,
+//! that invalid changes are rejected and that the selected prefixes are shown before log messages.
+
+#![no_std]
+#![no_main]
+#![feature(alloc)]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use core::{
+    fmt::{self, Write},
+    panic::PanicInfo,
+    str::FromStr,
+};
+use kernel::{
+    arch::x86_64::{exit_integration_test, uefi::uefi_init, IntegrationTestExitCode},
+    console::{self, Sink},
+    log_buffer,
+    log_filter::{self, LogFilterError, Prefixes},
+    serial_println,
+    sync::Mutex,
+};
+use log::{Level, LevelFilter, Metadata, Record};
+use nuefil::{system::SystemTable, Handle};
+
+/// The output received by the test sink.
+static OUTPUT: Mutex<Option<String>> = Mutex::new(None);
+
+/// A sink that collects its output in memory.
+struct TestSink;
+
+impl Sink for TestSink {
+    fn write_fmt(&self, args: fmt::Arguments) {
+        OUTPUT
+            .lock()
+            .get_or_insert_with(String::new)
+            .write_fmt(args)
+            .expect("Could not write to the test sink.");
+    }
+}
+
+/// Fails the test with the given message if the condition doesn't hold.
+fn check(condition: bool, message: &'static str) {
+    if !condition {
+        exit_integration_test(IntegrationTestExitCode::Failure(message));
+    }
+}
+
+/// Checks if a message of the level from the module would be logged.
+fn enabled(level: Level, module: &str) -> bool {
+    log_filter::enabled(&Metadata::builder().level(level).target(module).build())
+}
+
+/// Tests changing the log levels at runtime.
+fn test_levels() {
+    check(
+        log_filter::set_directives("warn,kernel::memory=debug,kernel::memory::heap=off").is_ok(),
+        "The log directives were not accepted.",
+    );
+    check(
+        enabled(Level::Warn, "kernel::time")
+            && !enabled(Level::Info, "kernel::time")
+            && enabled(Level::Debug, "kernel::memory::paging")
+            && !enabled(Level::Trace, "kernel::memory::paging")
+            && !enabled(Level::Error, "kernel::memory::heap")
+            && !enabled(Level::Debug, "kernel::memory_map"),
+        "The log directives were not applied.",
+    );
+    check(
+        log::max_level() == LevelFilter::Debug,
+        "The maximum log level was not updated.",
+    );
+
+    check(
+        log_filter::set_level("kernel::time", LevelFilter::Trace).is_ok()
+            && log_filter::level("kernel::time::hrtimer") == LevelFilter::Trace
+            && log::max_level() == LevelFilter::Trace,
+        "The log level of a module was not set.",
+    );
+    check(
+        log_filter::remove_level("kernel::time")
+            && !log_filter::remove_level("kernel::time")
+            && log_filter::level("kernel::time") == LevelFilter::Warn
+            && log::max_level() == LevelFilter::Debug,
+        "The log level of a module was not removed.",
+    );
+
+    log_filter::set_default_level(LevelFilter::Error);
+    check(
+        log_filter::level("kernel::time") == LevelFilter::Error,
+        "The default log level was not set.",
+    );
+
+    let mut levels = Vec::new();
+    log_filter::for_each_level(|module, level| levels.push((module.map(String::from), level)));
+
+    check(
+        levels.len() == 3
+            && levels[0] == (None, LevelFilter::Error)
+            && levels.contains(&(Some(String::from("kernel::memory")), LevelFilter::Debug)),
+        "The log levels were not listed.",
+    );
+}
+
+/// Tests that invalid changes are rejected.
+fn test_invalid_changes() {
+    check(
+        log_filter::set_directives("trace,kernel::time=sometimes")
+            == Err(LogFilterError::InvalidDirective)
+            && log_filter::level("kernel::time") == LevelFilter::Error,
+        "An invalid directive changed the log levels.",
+    );
+
+    let mut long_module = String::from("kernel");
+    while long_module.len() <= 64 {
+        long_module.push_str("::module");
+    }
+
+    check(
+        log_filter::set_level(&long_module, LevelFilter::Off)
+            == Err(LogFilterError::ModulePathTooLong),
+        "A too long module path was accepted.",
+    );
+
+    let mut result = Ok(());
+    for index in 0..17 {
+        let mut module = String::new();
+        write!(module, "module{}", index).expect("Could not format the module path.");
+
+        result = log_filter::set_level(&module, LevelFilter::Info);
+    }
+
+    check(
+        log_filter::set_directives("").is_ok() && result == Err(LogFilterError::TooManyDirectives),
+        "Too many module levels were accepted.",
+    );
+}
+
+/// Tests the prefixes of log messages.
+fn test_prefixes() {
+    check(
+        Prefixes::from_str("timestamp,location") == Ok(Prefixes::TIMESTAMP | Prefixes::LOCATION)
+            && Prefixes::from_str("none") == Ok(Prefixes::empty())
+            && Prefixes::from_str("cpu,date") == Err(LogFilterError::InvalidPrefix),
+        "The prefixes were not parsed.",
+    );
+
+    console::register("test", &TestSink, LevelFilter::Trace);
+    OUTPUT.lock().take();
+
+    let record = log_buffer::push(
+        &Record::builder()
+            .level(Level::Info)
+            .file(Some("src/bin/test-x86_64-log-filter.rs"))
+            .line(Some(7))
+            .args(format_args!("With prefixes."))
+            .build(),
+    );
+
+    console::write_log(&record, format_args!("{}", record.message()));
+    log_filter::set_prefixes(Prefixes::CPU | Prefixes::LOCATION);
+    console::write_log(&record, format_args!("{}", record.message()));
+    log_filter::set_prefixes(Prefixes::empty());
+
+    let output = OUTPUT.lock().take().unwrap_or_default();
+    let mut lines = output.lines();
+
+    check(
+        lines.next() == Some("With prefixes."),
+        "A prefix was shown without being selected.",
+    );
+    check(
+        lines.next().map_or(false, |line| {
+            line.contains("CPU")
+                && line.contains("src/bin/test-x86_64-log-filter.rs:7")
+                && line.ends_with("With prefixes.")
+                && !line.contains('[')
+        }),
+        "The selected prefixes were not shown.",
+    );
+
+    console::unregister("test");
+}
+
+/// The entry point for the UEFI loader.
+///
+/// This is the first function that gets called by the UEFI firmware.
+#[no_mangle]
+pub extern "C" fn efi_main(image_handle: Handle, system_table: &'static SystemTable) -> ! {
+    uefi_init(image_handle, system_table);
+
+    test_levels();
+    test_invalid_changes();
+    test_prefixes();
+
+    exit_integration_test(IntegrationTestExitCode::Success);
+}
+
+/// The panic implementation of the log filter test.
+#[panic_handler]
+fn panic_fmt(panic_info: &PanicInfo) -> ! {
+    serial_println!("{}", panic_info);
+
+    exit_integration_test(IntegrationTestExitCode::Failure(""));
+}
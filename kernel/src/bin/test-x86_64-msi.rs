@@ -0,0 +1,363 @@
+//! This binary runs the MSI test.
+//!
+//! This test makes sure that vectors are only allocated on running processors, that the interrupts of the
+//! educational device emulated by QEMU arrive through MSI and that the MSI-X table of the
+//! virtio random number generator is programmed, masked and retargeted.
+
+#![no_std]
+#![no_main]
+#![feature(alloc)]
+
+extern crate alloc;
+
+use core::{
+    panic::PanicInfo,
+    ptr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use kernel::{
+    arch::{
+        x86_64::{
+            exit_integration_test,
+            msi::{self, Interrupts, MsiError, Vector},
+            pci::{self, Bar, Command, DeviceMatch},
+            uefi::uefi_init,
+            IntegrationTestExitCode,
+        },
+        Arch, Architecture,
+    },
+    memory::VirtualAddress,
+    serial_println,
+};
+use nuefil::{system::SystemTable, Handle};
+
+/// The register of the educational device that raises an interrupt with the written status.
+const EDU_RAISE_INTERRUPT: usize = 0x60;
+
+/// The register of the educational device that acknowledges the written status.
+const EDU_ACKNOWLEDGE_INTERRUPT: usize = 0x64;
+
+/// The offset of the message control register in the MSI and MSI-X capabilities.
+const MESSAGE_CONTROL: u16 = 2;
+
+/// The offset of the message address register in the MSI capability.
+const MSI_ADDRESS: u16 = 4;
+
+/// The bit in the message control register that enables MSI.
+const MSI_ENABLE: u16 = 1 << 0;
+
+/// The bit in the message control register that enables MSI-X.
+const MSI_X_ENABLE: u16 = 1 << 15;
+
+/// The virtual address of the registers of the educational device.
+static EDU_REGISTERS: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of interrupts received from the educational device.
+static EDU_INTERRUPTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Fails the test with the given message if the condition doesn't hold.
+fn check(condition: bool, message: &'static str) {
+    if !condition {
+        exit_integration_test(IntegrationTestExitCode::Failure(message));
+    }
+}
+
+/// Returns the local APIC ID of a processor other than the current one.
+///
+/// QEMU runs the tests with several processors, so this usually belongs to a processor that is never started.
+fn other_processor() -> usize {
+    (0..=255)
+        .find(|&cpu| cpu != Arch::current_cpu())
+        .expect("There is no other local APIC ID.")
+}
+
+/// Writes the register of the educational device at the offset.
+fn write_edu_register(offset: usize, value: u32) {
+    let register = VirtualAddress::new(EDU_REGISTERS.load(Ordering::SeqCst)) + offset;
+
+    // This is safe, because the registers of the educational device lie in its first BAR.
+    unsafe { ptr::write_volatile(register.as_mut_ptr::<u32>(), value) };
+}
+
+/// Handles the interrupt of the educational device.
+fn handle_edu_interrupt(index: usize) {
+    check(index == 0, "The handler was called with the wrong index.");
+
+    write_edu_register(EDU_ACKNOWLEDGE_INTERRUPT, 1);
+    EDU_INTERRUPTS.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Raises an interrupt on the educational device and waits for it.
+fn raise_edu_interrupt() {
+    let received = EDU_INTERRUPTS.load(Ordering::SeqCst);
+
+    write_edu_register(EDU_RAISE_INTERRUPT, 1);
+
+    while EDU_INTERRUPTS.load(Ordering::SeqCst) == received {
+        Arch::wait_for_interrupt();
+    }
+}
+
+/// Tests the vector allocator.
+fn test_vectors() {
+    let cpu = Arch::current_cpu();
+
+    check(
+        msi::processors() == [cpu],
+        "The current processor is not the only target of interrupts.",
+    );
+
+    let block = Vector::allocate_block(cpu, 4).expect("No vectors could be allocated.");
+
+    check(
+        block[0].number() % 4 == 0
+            && block
+                .iter()
+                .enumerate()
+                .all(|(index, vector)| vector.number() == block[0].number() + index),
+        "The block of vectors is not aligned.",
+    );
+    check(
+        block.iter().all(|vector| vector.cpu() == cpu)
+            && block[1].message() == (0xfee0_0000 | (cpu as u64) << 12, block[1].number() as u32),
+        "The message of a vector is wrong.",
+    );
+
+    let single = Vector::allocate(cpu).expect("No vector could be allocated.");
+
+    check(
+        block
+            .iter()
+            .all(|vector| vector.number() != single.number()),
+        "A vector was allocated twice.",
+    );
+
+    let first = block[0].number();
+    drop(block);
+
+    check(
+        Vector::allocate_block(cpu, 4).map(|block| block[0].number()) == Ok(first),
+        "The vectors were not freed.",
+    );
+    check(
+        Vector::allocate(other_processor()) == Err(MsiError::UnknownProcessor),
+        "A vector was allocated on a processor that is not running.",
+    );
+    check(
+        Vector::allocate_block(cpu, 3) == Err(MsiError::InvalidCount)
+            && Vector::allocate_block(cpu, 0) == Err(MsiError::InvalidCount),
+        "A block with an invalid size was allocated.",
+    );
+}
+
+/// Tests MSI with the educational device.
+fn test_msi() {
+    let edu = pci::find(DeviceMatch::id(0x1234, 0x11e8))
+        .next()
+        .expect("The educational device was not found.");
+    let capability = edu.msi().expect("The educational device has no MSI.");
+
+    match edu.bar(0) {
+        Some(Bar::Memory { address, .. }) => EDU_REGISTERS.store(
+            Arch::physical_to_virtual(address).as_usize(),
+            Ordering::SeqCst,
+        ),
+        _ => check(
+            false,
+            "The registers of the educational device were not found.",
+        ),
+    }
+
+    edu.set_command(edu.command() | Command::MEMORY_SPACE);
+
+    check(
+        Interrupts::enable(edu, 2).err() == Some(MsiError::InvalidCount),
+        "Too many interrupts were enabled.",
+    );
+
+    let mut interrupts = Interrupts::enable(edu, 1).expect("MSI could not be enabled.");
+
+    check(!interrupts.is_msi_x(), "MSI-X was used without support.");
+    check(
+        Interrupts::enable(edu, 1).err() == Some(MsiError::AlreadyEnabled),
+        "MSI was enabled twice.",
+    );
+    check(
+        edu.read_u16(capability.offset() + MESSAGE_CONTROL) & MSI_ENABLE != 0
+            && edu.command().contains(Command::INTERRUPT_DISABLE),
+        "MSI was not enabled in the function.",
+    );
+    check(
+        interrupts.vectors()[0].cpu() == Arch::current_cpu()
+            && edu.read_u32(capability.offset() + MSI_ADDRESS) as u64
+                == interrupts.vectors()[0].message().0,
+        "The message doesn't target the current processor.",
+    );
+    check(
+        interrupts.set_kernel_handler(1, Some(handle_edu_interrupt)) == Err(MsiError::InvalidIndex),
+        "A handler was set for a missing interrupt.",
+    );
+
+    interrupts
+        .set_kernel_handler(0, Some(handle_edu_interrupt))
+        .unwrap();
+
+    raise_edu_interrupt();
+
+    check(
+        interrupts.set_affinity(0, other_processor()) == Err(MsiError::UnknownProcessor)
+            && interrupts.vectors()[0].cpu() == Arch::current_cpu(),
+        "An interrupt was moved to a processor that is not running.",
+    );
+
+    interrupts.set_affinity(0, Arch::current_cpu()).unwrap();
+
+    check(
+        interrupts.vectors()[0].cpu() == Arch::current_cpu()
+            && edu.read_u32(capability.offset() + MSI_ADDRESS) as u64
+                == interrupts.vectors()[0].message().0,
+        "The interrupt was not moved to the current processor again.",
+    );
+
+    raise_edu_interrupt();
+
+    check(
+        EDU_INTERRUPTS.load(Ordering::SeqCst) == 2,
+        "The interrupts were not received once each.",
+    );
+
+    drop(interrupts);
+
+    check(
+        edu.read_u16(capability.offset() + MESSAGE_CONTROL) & MSI_ENABLE == 0
+            && !edu.command().contains(Command::INTERRUPT_DISABLE),
+        "MSI was not disabled again.",
+    );
+    check(
+        Interrupts::enable(edu, 1).is_ok(),
+        "MSI could not be enabled again.",
+    );
+}
+
+/// Returns the virtual address of the MSI-X table entry with the index of the virtio device.
+fn msi_x_entry(interrupts: &Interrupts, index: usize) -> VirtualAddress {
+    let capability = interrupts.device().msi_x().unwrap();
+
+    match interrupts.device().bar(capability.table_bar()) {
+        Some(Bar::Memory { address, .. }) => {
+            Arch::physical_to_virtual(address) + capability.table_offset() + index * 16
+        }
+        _ => panic!("The MSI-X table was not found."),
+    }
+}
+
+/// Reads the 32 bit word at the offset in the MSI-X table entry with the index.
+fn read_msi_x_entry(interrupts: &Interrupts, index: usize, offset: usize) -> u32 {
+    // This is safe, because the table lies in a memory BAR of the function.
+    unsafe { ptr::read_volatile((msi_x_entry(interrupts, index) + offset).as_ptr::<u32>()) }
+}
+
+/// Tests MSI-X with the virtio random number generator.
+fn test_msi_x() {
+    let virtio = pci::find(DeviceMatch {
+        vendor_id: Some(0x1af4),
+        ..DeviceMatch::default()
+    })
+    .find(|device| device.msi_x().is_some())
+    .expect("The virtio device with MSI-X was not found.");
+    let capability = virtio.msi_x().unwrap();
+    let count = capability.table_size().min(2);
+
+    let mut interrupts = Interrupts::enable(virtio, count).expect("MSI-X could not be enabled.");
+
+    check(interrupts.is_msi_x(), "MSI was used instead of MSI-X.");
+    check(
+        interrupts.count() == count
+            && virtio.read_u16(capability.offset() + MESSAGE_CONTROL) & MSI_X_ENABLE != 0,
+        "MSI-X was not enabled in the function.",
+    );
+    check(
+        interrupts
+            .vectors()
+            .iter()
+            .enumerate()
+            .all(|(index, vector)| {
+                let (address, data) = vector.message();
+
+                read_msi_x_entry(&interrupts, index, 0) == address as u32
+                    && read_msi_x_entry(&interrupts, index, 8) == data
+                    && read_msi_x_entry(&interrupts, index, 12) & 1 == 0
+            }),
+        "The MSI-X table was programmed wrongly.",
+    );
+
+    interrupts.mask(0).unwrap();
+
+    check(
+        read_msi_x_entry(&interrupts, 0, 12) & 1 != 0,
+        "The interrupt was not masked.",
+    );
+
+    let target = Arch::current_cpu();
+    interrupts.set_affinity(0, target).unwrap();
+
+    check(
+        interrupts.vectors()[0].cpu() == target
+            && read_msi_x_entry(&interrupts, 0, 0) == interrupts.vectors()[0].message().0 as u32
+            && read_msi_x_entry(&interrupts, 0, 8) == interrupts.vectors()[0].message().1,
+        "The interrupt was not moved.",
+    );
+    check(
+        read_msi_x_entry(&interrupts, 0, 12) & 1 != 0,
+        "Moving the interrupt unmasked it.",
+    );
+
+    interrupts.unmask(0).unwrap();
+
+    check(
+        read_msi_x_entry(&interrupts, 0, 12) & 1 == 0,
+        "The interrupt was not unmasked.",
+    );
+    check(
+        interrupts.mask(count) == Err(MsiError::InvalidIndex),
+        "A missing interrupt was masked.",
+    );
+
+    drop(interrupts);
+
+    check(
+        virtio.read_u16(capability.offset() + MESSAGE_CONTROL) & MSI_X_ENABLE == 0,
+        "MSI-X was not disabled again.",
+    );
+
+    let host_bridge = pci::find(DeviceMatch::class(0x06, 0x00))
+        .next()
+        .expect("The host bridge was not found.");
+
+    check(
+        Interrupts::enable(host_bridge, 1).err() == Some(MsiError::Unsupported),
+        "Message signaled interrupts were enabled without support.",
+    );
+}
+
+/// The entry point for the UEFI loader.
+///
+/// This is the first function that gets called by the UEFI firmware.
+#[no_mangle]
+pub extern "C" fn efi_main(image_handle: Handle, system_table: &'static SystemTable) -> ! {
+    uefi_init(image_handle, system_table);
+
+    test_vectors();
+    test_msi();
+    test_msi_x();
+
+    exit_integration_test(IntegrationTestExitCode::Success);
+}
+
+/// The panic implementation of the MSI test.
+#[panic_handler]
+fn panic_fmt(panic_info: &PanicInfo) -> ! {
+    serial_println!("{}", panic_info);
+
+    exit_integration_test(IntegrationTestExitCode::Failure(""));
+}
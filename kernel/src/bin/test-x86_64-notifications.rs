@@ -0,0 +1,235 @@
+//! This binary runs the notifications test.
+//!
+//! This test makes sure that notifications deliver signals to waiting threads and to threads bound to them.
+
+#![no_std]
+#![no_main]
+#![feature(alloc)]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use core::panic::PanicInfo;
+use kernel::{
+    arch::{
+        x86_64::{exit_integration_test, uefi::uefi_init, IntegrationTestExitCode},
+        Arch, Architecture,
+    },
+    capability::{CSpace, Rights},
+    ipc::{Endpoint, Notification, NOTIFICATION_LABEL},
+    memory::{AddressSpace, PageFlags, FRAME_ALLOCATOR},
+    object::KernelObject,
+    scheduler, serial_println,
+    sync::Mutex,
+    thread::{Thread, ThreadState},
+};
+use nuefil::{system::SystemTable, Handle};
+
+/// The code of the test thread.
+///
+/// It binds the notification in slot 0 to itself and waits on it.
+/// Then it waits for a message on the endpoint in slot 1, signals the notification through slot 2 and polls it twice.
+/// Finally it waits on the notification again. All results are stored in its data page.
+const CODE: [u8; 117] = [
+    0x48, 0xbd, 0x00, 0x10, 0x00, 0x00, 0x80, 0x00, 0x00, 0x00, 0xb8, 0x2b, 0x00, 0x00, 0x00, 0x31,
+    0xff, 0x0f, 0x05, 0x48, 0x89, 0x45, 0x00, 0xb8, 0x29, 0x00, 0x00, 0x00, 0x31, 0xff, 0x0f, 0x05,
+    0x48, 0x89, 0x45, 0x08, 0x48, 0x89, 0x7d, 0x10, 0xb8, 0x21, 0x00, 0x00, 0x00, 0xbf, 0x01, 0x00,
+    0x00, 0x00, 0x0f, 0x05, 0x48, 0x89, 0x45, 0x18, 0x48, 0x89, 0x7d, 0x20, 0x48, 0x89, 0x75, 0x28,
+    0xb8, 0x28, 0x00, 0x00, 0x00, 0xbf, 0x02, 0x00, 0x00, 0x00, 0x0f, 0x05, 0x48, 0x89, 0x45, 0x30,
+    0xb8, 0x2a, 0x00, 0x00, 0x00, 0x31, 0xff, 0x0f, 0x05, 0x48, 0x89, 0x7d, 0x38, 0xb8, 0x2a, 0x00,
+    0x00, 0x00, 0x31, 0xff, 0x0f, 0x05, 0x48, 0x89, 0x7d, 0x40, 0xb8, 0x29, 0x00, 0x00, 0x00, 0x31,
+    0xff, 0x0f, 0x05, 0x0f, 0x0b,
+];
+
+/// The offset of the data page from the start of user space.
+const DATA_OFFSET: usize = 0x1000;
+
+/// The offset of the top of the stack from the start of user space.
+const STACK_TOP_OFFSET: usize = 0x3000;
+
+/// The badge of the capability the thread signals the notification with.
+const BADGE: u64 = 0x100;
+
+/// Fails the test with the given message if the condition doesn't hold.
+fn check(condition: bool, message: &'static str) {
+    if !condition {
+        exit_integration_test(IntegrationTestExitCode::Failure(message));
+    }
+}
+
+/// Creates an address space containing the code, a data page and a stack.
+fn create_address_space() -> Arc<Mutex<AddressSpace>> {
+    let start = Arch::USER_SPACE_START;
+    let writable = PageFlags::USER_ACCESSIBLE | PageFlags::WRITABLE;
+
+    let address_space = AddressSpace::new().and_then(|mut address_space| {
+        address_space.map(start, PageFlags::USER_ACCESSIBLE | PageFlags::EXECUTABLE)?;
+        address_space.write(start, &CODE)?;
+        address_space.map_range(
+            start + DATA_OFFSET,
+            STACK_TOP_OFFSET - DATA_OFFSET,
+            writable,
+        )?;
+
+        Ok(address_space)
+    });
+
+    match address_space {
+        Ok(address_space) => Arc::new(Mutex::new(address_space)),
+        Err(_) => exit_integration_test(IntegrationTestExitCode::Failure(
+            "Could not create an address space.",
+        )),
+    }
+}
+
+/// Reads the word with the given index from the data page of the address space.
+fn read_data(address_space: &Mutex<AddressSpace>, index: usize) -> usize {
+    let mut bytes = [0; 8];
+
+    check(
+        address_space
+            .lock()
+            .read(Arch::USER_SPACE_START + DATA_OFFSET + index * 8, &mut bytes)
+            .is_ok(),
+        "Could not read the data page.",
+    );
+
+    usize::from_le_bytes(bytes)
+}
+
+/// Checks if the thread waits on a notification.
+fn is_waiting(thread: &Thread) -> bool {
+    match thread.state() {
+        ThreadState::BlockedOnNotification(_) => true,
+        _ => false,
+    }
+}
+
+/// The entry point for the UEFI loader.
+///
+/// This is the first function that gets called by the UEFI firmware.
+#[no_mangle]
+pub extern "C" fn efi_main(image_handle: Handle, system_table: &'static SystemTable) -> ! {
+    uefi_init(image_handle, system_table);
+
+    let free_frames = FRAME_ALLOCATOR.lock().free_frames();
+
+    {
+        // Signals are accumulated until someone waits.
+        let notification = Notification::new();
+        notification.signal(0b10);
+        notification.signal(0b1000);
+        check(
+            notification.poll() == 0b1010,
+            "The signals were not accumulated.",
+        );
+        check(
+            notification.poll() == 0,
+            "Polling did not clear the signals.",
+        );
+        notification.signal(0);
+        check(
+            notification.poll() == 1,
+            "Signalling without a badge did not set the lowest bit.",
+        );
+
+        let notification = Arc::new(Notification::new());
+        let cspace = CSpace::new(4);
+
+        check(
+            CSpace::insert(
+                &cspace,
+                0,
+                KernelObject::Notification(notification.clone()),
+                Rights::READ | Rights::WRITE,
+            )
+            .is_ok()
+                && CSpace::insert(
+                    &cspace,
+                    1,
+                    KernelObject::Endpoint(Arc::new(Endpoint::new())),
+                    Rights::READ,
+                )
+                .is_ok()
+                && cspace.mint(0, &cspace, 2, Rights::WRITE, BADGE).is_ok(),
+            "Could not set up the capability space.",
+        );
+
+        let address_space = create_address_space();
+        let thread = Arc::new(Thread::new(cspace, address_space.clone()));
+
+        Thread::start(
+            &thread,
+            Arch::USER_SPACE_START,
+            Arch::USER_SPACE_START + STACK_TOP_OFFSET,
+        );
+
+        scheduler::run_until_idle();
+
+        check(
+            read_data(&address_space, 0) == 0,
+            "Could not bind the notification.",
+        );
+        check(
+            is_waiting(&thread),
+            "The thread does not wait on the notification.",
+        );
+
+        notification.signal(5);
+        scheduler::run_until_idle();
+
+        check(
+            read_data(&address_space, 1) == 0 && read_data(&address_space, 2) == 5,
+            "The waiting thread did not receive the signal.",
+        );
+        check(
+            thread.state().is_blocked_on_receive(),
+            "The thread does not wait for a message.",
+        );
+
+        notification.signal(0x30);
+        scheduler::run_until_idle();
+
+        check(
+            read_data(&address_space, 3) == 0 && read_data(&address_space, 4) == 0x30,
+            "The bound thread did not receive the signal while waiting for a message.",
+        );
+        check(
+            read_data(&address_space, 5) == NOTIFICATION_LABEL << 16,
+            "The bound thread was not told that it received a signal.",
+        );
+        check(
+            read_data(&address_space, 6) == 0,
+            "The thread could not signal the notification.",
+        );
+        check(
+            read_data(&address_space, 7) == BADGE as usize,
+            "Polling did not return the badge of the signalling capability.",
+        );
+        check(
+            read_data(&address_space, 8) == 0,
+            "Polling did not clear the signals.",
+        );
+        check(
+            is_waiting(&thread),
+            "The thread does not wait on the notification.",
+        );
+
+        Thread::suspend(&thread);
+    }
+
+    check(
+        FRAME_ALLOCATOR.lock().free_frames() == free_frames,
+        "Frames were leaked by the thread.",
+    );
+
+    exit_integration_test(IntegrationTestExitCode::Success);
+}
+
+/// The panic implementation of the notifications test.
+#[panic_handler]
+fn panic_fmt(panic_info: &PanicInfo) -> ! {
+    serial_println!("{}", panic_info);
+
+    exit_integration_test(IntegrationTestExitCode::Failure(""));
+}
@@ -0,0 +1,346 @@
+//! This binary runs the pager test.
+//!
+//! This test makes sure that page faults in areas managed by a pager are sent to the pager as messages,
+//! that the pages supplied in its replies are mapped, that threads faulting on the same page both continue
+//! and that faults it cannot resolve stop the faulting process.
+
+#![no_std]
+#![no_main]
+#![feature(alloc)]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use core::panic::PanicInfo;
+use kernel::{
+    arch::{
+        x86_64::{exit_integration_test, uefi::uefi_init, IntegrationTestExitCode},
+        Arch, Architecture,
+    },
+    capability::{CSpace, CapabilityError, Rights},
+    ipc::{self, Endpoint, IpcError, MessageInfo, Pager, PAGE_FAULT_LABEL},
+    memory::{
+        Access, AddressSpace, MemoryRegion, PageFlags, VirtualAddress, FRAME_ALLOCATOR, PAGE_SIZE,
+    },
+    object::KernelObject,
+    process::{self, Process},
+    scheduler, serial_println,
+    sync::Mutex,
+    syscall::{self, number, SyscallError, ARGUMENT_COUNT},
+    thread::{Fault, Thread},
+};
+use nuefil::{system::SystemTable, Handle};
+
+/// The code of a pager that replies to every fault with the page of the memory region in slot 1
+/// whose index is the badge of the fault.
+///
+/// The faulting address and the kind of access of the last fault are kept in `r8` and `r9`.
+const PAGER_CODE: [u8; 39] = [
+    0x31, 0xff, 0xb8, 0x21, 0x00, 0x00, 0x00, 0x0f, 0x05, 0x49, 0x89, 0xd0, 0x4d, 0x89, 0xd1, 0x49,
+    0x89, 0xfa, 0xba, 0x01, 0x00, 0x00, 0x00, 0xbe, 0x02, 0x00, 0x00, 0x00, 0x31, 0xff, 0xb8, 0x24,
+    0x00, 0x00, 0x00, 0x0f, 0x05, 0xeb, 0xe2,
+];
+
+/// The code of a process that adds the first words of both paged areas, stores the sum after the first word
+/// and exits with it.
+const CLIENT_CODE: [u8; 33] = [
+    0x48, 0xb8, 0x00, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x48, 0x8b, 0x38, 0x48, 0x03, 0xb8,
+    0x00, 0x10, 0x00, 0x00, 0x48, 0x89, 0x78, 0x08, 0xb8, 0x03, 0x00, 0x00, 0x00, 0x0f, 0x05, 0x0f,
+    0x0b,
+];
+
+/// The code of a process that reads from an area whose pager cannot supply a page.
+const INVALID_CLIENT_CODE: [u8; 15] = [
+    0x48, 0xb8, 0x00, 0x00, 0x02, 0x00, 0x80, 0x00, 0x00, 0x00, 0x48, 0x8b, 0x38, 0x0f, 0x0b,
+];
+
+/// The offset of the first paged area from the start of user space.
+const FIRST_AREA_OFFSET: usize = 0x10000;
+
+/// The offset of the second paged area from the start of user space.
+const SECOND_AREA_OFFSET: usize = 0x11000;
+
+/// The offset of the area whose badge is not a valid page index.
+const INVALID_AREA_OFFSET: usize = 0x20000;
+
+/// The badge used for the area whose pages cannot be supplied.
+const INVALID_BADGE: u64 = 5;
+
+/// The number of pages in the memory region of the pager.
+const PAGE_COUNT: usize = 2;
+
+/// The values at the start of the pages of the memory region.
+const VALUES: [u64; PAGE_COUNT] = [0x11, 0x22];
+
+/// Fails the test with the given message if the condition doesn't hold.
+fn check(condition: bool, message: &'static str) {
+    if !condition {
+        exit_integration_test(IntegrationTestExitCode::Failure(message));
+    }
+}
+
+/// Returns the address at the given offset from the start of user space.
+fn user(offset: usize) -> VirtualAddress {
+    Arch::USER_SPACE_START + offset
+}
+
+/// Creates an address space containing the code and the given areas managed by the pager.
+fn create_address_space(code: &[u8], areas: &[(usize, Pager)]) -> AddressSpace {
+    let address_space = AddressSpace::new().and_then(|mut address_space| {
+        address_space.map(user(0), PageFlags::USER_ACCESSIBLE | PageFlags::EXECUTABLE)?;
+        address_space.write(user(0), code)?;
+
+        for (offset, pager) in areas {
+            address_space.reserve_paged(
+                user(*offset),
+                PAGE_SIZE,
+                PageFlags::USER_ACCESSIBLE | PageFlags::WRITABLE,
+                pager.clone(),
+            )?;
+        }
+
+        Ok(address_space)
+    });
+
+    match address_space {
+        Ok(address_space) => address_space,
+        Err(_) => exit_integration_test(IntegrationTestExitCode::Failure(
+            "Could not create an address space.",
+        )),
+    }
+}
+
+/// Creates the pager thread, which receives faults from the endpoint in slot 0.
+///
+/// The pages of the memory region in slot 1 start with `VALUES`.
+fn create_pager(endpoint: &Arc<Endpoint>) -> Arc<Thread> {
+    let region = match MemoryRegion::new(PAGE_COUNT) {
+        Ok(region) => Arc::new(region),
+        Err(_) => exit_integration_test(IntegrationTestExitCode::Failure(
+            "Could not create a memory region.",
+        )),
+    };
+    let mut address_space = create_address_space(&PAGER_CODE, &[]);
+
+    for (index, value) in VALUES.iter().enumerate() {
+        let page = user(FIRST_AREA_OFFSET + index * PAGE_SIZE);
+        let frame = region
+            .frame(index)
+            .expect("The memory region is too small.");
+
+        check(
+            address_space
+                .map_shared(
+                    page,
+                    frame,
+                    PageFlags::USER_ACCESSIBLE | PageFlags::WRITABLE,
+                    KernelObject::MemoryRegion(region.clone()),
+                )
+                .is_ok()
+                && address_space.write(page, &value.to_le_bytes()).is_ok(),
+            "Could not fill the memory region.",
+        );
+    }
+
+    let cspace = CSpace::new(2);
+
+    check(
+        CSpace::insert(
+            &cspace,
+            0,
+            KernelObject::Endpoint(endpoint.clone()),
+            Rights::all(),
+        )
+        .is_ok()
+            && CSpace::insert(
+                &cspace,
+                1,
+                KernelObject::MemoryRegion(region),
+                Rights::all(),
+            )
+            .is_ok(),
+        "Could not insert the capabilities of the pager.",
+    );
+
+    Arc::new(Thread::new(cspace, Arc::new(Mutex::new(address_space))))
+}
+
+/// Starts a process in the address space at the start of its code.
+///
+/// Returns the process, its thread and its address space, which stays alive after the process exits.
+fn start(address_space: AddressSpace) -> (Arc<Process>, Arc<Thread>, Arc<Mutex<AddressSpace>>) {
+    let process = Process::new(address_space, 1);
+    let address_space = process
+        .address_space()
+        .expect("A new process has no address space.");
+
+    match Process::start(&process, user(0), user(0)) {
+        Some(thread) => (process, thread, address_space),
+        None => exit_integration_test(IntegrationTestExitCode::Failure(
+            "Could not start the process.",
+        )),
+    }
+}
+
+/// Performs the system call with the given arguments for the thread.
+fn call(thread: &Arc<Thread>, number: usize, arguments: &[usize]) -> Result<usize, SyscallError> {
+    let mut all_arguments = [0; ARGUMENT_COUNT];
+    all_arguments[..arguments.len()].copy_from_slice(arguments);
+
+    syscall::handle(thread, number, all_arguments)
+}
+
+/// Reads the word at the address in the address space.
+fn read_word(address_space: &mut AddressSpace, address: VirtualAddress) -> Option<u64> {
+    let mut word = [0; 8];
+
+    address_space.read(address, &mut word).ok()?;
+
+    Some(u64::from_le_bytes(word))
+}
+
+/// The entry point for the UEFI loader.
+///
+/// This is the first function that gets called by the UEFI firmware.
+#[no_mangle]
+pub extern "C" fn efi_main(image_handle: Handle, system_table: &'static SystemTable) -> ! {
+    uefi_init(image_handle, system_table);
+
+    let free_frames = FRAME_ALLOCATOR.lock().free_frames();
+
+    {
+        let endpoint = Arc::new(Endpoint::new());
+        let pager = create_pager(&endpoint);
+        let paged_area = user(INVALID_AREA_OFFSET);
+
+        check(
+            ipc::check_message(
+                &pager,
+                MessageInfo::new(PAGE_FAULT_LABEL, 2, 0)
+                    .expect("The message info is invalid.")
+                    .to_word(),
+            ) == Err(IpcError::InvalidMessage),
+            "A thread could send a message with the page fault label.",
+        );
+        check(
+            call(
+                &pager,
+                number::MEMORY_RESERVE_PAGED,
+                &[paged_area.as_usize(), 1, 1, 1],
+            ) == Err(SyscallError::Capability(CapabilityError::InvalidType)),
+            "An area was reserved for a pager that is no endpoint.",
+        );
+        check(
+            call(
+                &pager,
+                number::MEMORY_RESERVE_PAGED,
+                &[paged_area.as_usize(), 1, 1, 0],
+            ) == Ok(0),
+            "Could not reserve an area for a pager.",
+        );
+        check(
+            pager
+                .address_space()
+                .lock()
+                .handle_fault(paged_area, Access::Write)
+                == Ok(Some(Pager::new(endpoint.clone(), 0))),
+            "A fault in an area managed by a pager was not sent to the pager.",
+        );
+        check(
+            pager
+                .address_space()
+                .lock()
+                .write(paged_area, &[0])
+                .is_err(),
+            "The kernel mapped a page managed by a pager.",
+        );
+
+        let (client, client_thread, client_space) = start(create_address_space(
+            &CLIENT_CODE,
+            &[
+                (FIRST_AREA_OFFSET, Pager::new(endpoint.clone(), 0)),
+                (SECOND_AREA_OFFSET, Pager::new(endpoint.clone(), 1)),
+            ],
+        ));
+
+        // Both threads of the client fault on the same page before the pager waits for faults.
+        check(
+            Process::start(&client, user(0), user(0)).is_some(),
+            "Could not start the second thread of the client.",
+        );
+        Thread::start(&pager, user(0), user(0));
+
+        let (invalid_client, invalid_thread, _) = start(create_address_space(
+            &INVALID_CLIENT_CODE,
+            &[(
+                INVALID_AREA_OFFSET,
+                Pager::new(endpoint.clone(), INVALID_BADGE),
+            )],
+        ));
+
+        scheduler::run_until_idle();
+
+        check(
+            client.exit_status() == Some((VALUES[0] + VALUES[1]) as usize)
+                && client_thread.fault()
+                    == Some(Fault::Page {
+                        address: user(SECOND_AREA_OFFSET),
+                        access: Access::Read,
+                    }),
+            "The client did not read the pages supplied by the pager.",
+        );
+
+        {
+            let mut client_space = client_space.lock();
+            let pager_space = pager.address_space().lock();
+
+            check(
+                client_space.translate(user(FIRST_AREA_OFFSET))
+                    == pager_space.translate(user(FIRST_AREA_OFFSET))
+                    && client_space.translate(user(SECOND_AREA_OFFSET))
+                        == pager_space.translate(user(SECOND_AREA_OFFSET)),
+                "The pages of the memory region were not mapped.",
+            );
+            check(
+                client_space.flags(user(FIRST_AREA_OFFSET))
+                    == Some(PageFlags::USER_ACCESSIBLE | PageFlags::WRITABLE)
+                    && read_word(&mut client_space, user(FIRST_AREA_OFFSET + 8))
+                        == Some(VALUES[0] + VALUES[1]),
+                "The supplied page was not mapped with the permissions of the area.",
+            );
+        }
+
+        check(
+            invalid_client.exit_status() == Some(process::FAULT_EXIT_STATUS)
+                && invalid_thread.fault()
+                    == Some(Fault::Page {
+                        address: user(INVALID_AREA_OFFSET),
+                        access: Access::Read,
+                    }),
+            "A fault the pager could not resolve did not stop the process.",
+        );
+        check(
+            pager.context().argument(4) == user(INVALID_AREA_OFFSET).as_usize()
+                && pager.context().argument(5) == 0,
+            "The pager did not receive the faulting address and access.",
+        );
+
+        // The pager waits on its own endpoint, which keeps it alive until it is stopped.
+        Thread::suspend(&pager);
+    }
+
+    check(
+        FRAME_ALLOCATOR.lock().free_frames() == free_frames,
+        "Frames were leaked by the pager or its clients.",
+    );
+
+    exit_integration_test(IntegrationTestExitCode::Success);
+}
+
+/// The panic implementation of the pager test.
+#[panic_handler]
+fn panic_fmt(panic_info: &PanicInfo) -> ! {
+    serial_println!("{}", panic_info);
+
+    exit_integration_test(IntegrationTestExitCode::Failure(""));
+}
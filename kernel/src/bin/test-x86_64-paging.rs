@@ -0,0 +1,367 @@
+//! This binary runs the demand paging test.
+//!
+//! This test makes sure that reserved pages are mapped on their first access, that stacks grow on demand,
+//! that duplicated address spaces share their frames until they are written to
+//! and that faults which cannot be resolved stop the faulting process.
+
+#![no_std]
+#![no_main]
+#![feature(alloc)]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use core::panic::PanicInfo;
+use kernel::{
+    arch::{
+        x86_64::{exit_integration_test, uefi::uefi_init, IntegrationTestExitCode},
+        Arch, Architecture,
+    },
+    memory::{
+        Access, AddressSpace, MemoryError, PageFlags, VirtualAddress, FRAME_ALLOCATOR, PAGE_SIZE,
+    },
+    process::{self, Process},
+    scheduler, serial_println,
+    sync::Mutex,
+    thread::{Fault, Thread},
+};
+use nuefil::{system::SystemTable, Handle};
+
+/// The code of a process that writes to the second data page, grows its stack and exits with the status 5.
+const GROW_CODE: [u8; 44] = [
+    0x48, 0xb8, 0x00, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x48, 0xc7, 0x80, 0x08, 0x10, 0x00,
+    0x00, 0x05, 0x00, 0x00, 0x00, 0x48, 0x81, 0xec, 0x00, 0x30, 0x00, 0x00, 0xff, 0xb0, 0x08, 0x10,
+    0x00, 0x00, 0x5f, 0xb8, 0x03, 0x00, 0x00, 0x00, 0x0f, 0x05, 0x0f, 0x0b,
+];
+
+/// The code of a process that pushes a value just below the end of its stack.
+const OVERFLOW_CODE: [u8; 10] = [0x48, 0x81, 0xec, 0x00, 0x40, 0x00, 0x00, 0x50, 0x0f, 0x0b];
+
+/// The code of a process that writes to its own code.
+const WRITE_CODE_CODE: [u8; 12] = [
+    0x48, 0x8d, 0x05, 0x00, 0x00, 0x00, 0x00, 0xc6, 0x00, 0x00, 0x0f, 0x0b,
+];
+
+/// The offset of the instruction whose address is written to by `WRITE_CODE_CODE`.
+const WRITE_CODE_TARGET: usize = 7;
+
+/// The code of a process that writes 2 to the first data page and exits with the value it reads back.
+const COPY_ON_WRITE_CODE: [u8; 29] = [
+    0x48, 0xb8, 0x00, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x48, 0xc7, 0x00, 0x02, 0x00, 0x00,
+    0x00, 0x48, 0x8b, 0x38, 0xb8, 0x03, 0x00, 0x00, 0x00, 0x0f, 0x05, 0x0f, 0x0b,
+];
+
+/// The offset of the reserved data pages from the start of user space.
+const DATA_OFFSET: usize = 0x10000;
+
+/// The number of reserved data pages.
+const DATA_PAGES: usize = 2;
+
+/// The offset of the top of the stack from the start of user space.
+const STACK_TOP_OFFSET: usize = 0x40000;
+
+/// The size the stack can grow to.
+const STACK_SIZE: usize = 4 * PAGE_SIZE;
+
+/// The number of slots in the capability space of each process.
+const CSPACE_SIZE: usize = 1;
+
+/// Fails the test with the given message if the condition doesn't hold.
+fn check(condition: bool, message: &'static str) {
+    if !condition {
+        exit_integration_test(IntegrationTestExitCode::Failure(message));
+    }
+}
+
+/// Returns the address at the given offset from the start of user space.
+fn user(offset: usize) -> VirtualAddress {
+    Arch::USER_SPACE_START + offset
+}
+
+/// Creates an address space containing the code, reserved data pages and a growable stack.
+fn create_address_space(code: &[u8]) -> AddressSpace {
+    let start = Arch::USER_SPACE_START;
+
+    let address_space = AddressSpace::new().and_then(|mut address_space| {
+        address_space.map(start, PageFlags::USER_ACCESSIBLE | PageFlags::EXECUTABLE)?;
+        address_space.write(start, code)?;
+        address_space.reserve(
+            user(DATA_OFFSET),
+            DATA_PAGES * PAGE_SIZE,
+            PageFlags::USER_ACCESSIBLE | PageFlags::WRITABLE,
+        )?;
+        address_space.reserve_stack(user(STACK_TOP_OFFSET), STACK_SIZE)?;
+
+        Ok(address_space)
+    });
+
+    match address_space {
+        Ok(address_space) => address_space,
+        Err(_) => exit_integration_test(IntegrationTestExitCode::Failure(
+            "Could not create an address space.",
+        )),
+    }
+}
+
+/// Runs a process in the address space until no thread is runnable anymore.
+///
+/// Returns the process, its thread and its address space, which stays alive after the process exits.
+fn run(address_space: AddressSpace) -> (Arc<Process>, Arc<Thread>, Arc<Mutex<AddressSpace>>) {
+    let process = Process::new(address_space, CSPACE_SIZE);
+    let address_space = process
+        .address_space()
+        .expect("A new process has no address space.");
+    let thread = match Process::start(&process, user(0), user(STACK_TOP_OFFSET)) {
+        Some(thread) => thread,
+        None => exit_integration_test(IntegrationTestExitCode::Failure(
+            "Could not start the process.",
+        )),
+    };
+
+    scheduler::run_until_idle();
+
+    (process, thread, address_space)
+}
+
+/// Reads the word at the address in the address space.
+fn read_word(address_space: &mut AddressSpace, address: VirtualAddress) -> Option<u64> {
+    let mut word = [0; 8];
+
+    address_space.read(address, &mut word).ok()?;
+
+    Some(u64::from_le_bytes(word))
+}
+
+/// Tests faults on reserved pages, guard pages and unreserved pages without running any code.
+fn test_reserved_pages() {
+    let mut address_space = create_address_space(&GROW_CODE);
+    let data = user(DATA_OFFSET);
+    let guard_page = user(STACK_TOP_OFFSET - STACK_SIZE - PAGE_SIZE);
+
+    check(
+        address_space.flags(data).is_none(),
+        "A reserved page was mapped before it was accessed.",
+    );
+    check(
+        address_space.handle_fault(data + 8, Access::Execute)
+            == Err(MemoryError::AccessViolation(data + 8)),
+        "A reserved page was mapped for a forbidden access.",
+    );
+    check(
+        address_space.handle_fault(guard_page, Access::Read)
+            == Err(MemoryError::GuardPage(guard_page)),
+        "A fault on a guard page was resolved.",
+    );
+    check(
+        address_space.map(guard_page, PageFlags::USER_ACCESSIBLE)
+            == Err(MemoryError::GuardPage(guard_page)),
+        "A guard page was mapped.",
+    );
+    check(
+        address_space.handle_fault(data + DATA_PAGES * PAGE_SIZE, Access::Read)
+            == Err(MemoryError::NotMapped(data + DATA_PAGES * PAGE_SIZE)),
+        "A fault outside of the reserved areas was resolved.",
+    );
+    check(
+        address_space.reserve(data + PAGE_SIZE, 2 * PAGE_SIZE, PageFlags::USER_ACCESSIBLE)
+            == Err(MemoryError::AlreadyMapped(data + PAGE_SIZE)),
+        "Overlapping areas were reserved.",
+    );
+
+    let free_frames = FRAME_ALLOCATOR.lock().free_frames();
+
+    check(
+        address_space.handle_fault(data + 8, Access::Write).is_ok()
+            && FRAME_ALLOCATOR.lock().free_frames() == free_frames - 1,
+        "A reserved page was not mapped on its first access.",
+    );
+    check(
+        address_space.flags(data) == Some(PageFlags::USER_ACCESSIBLE | PageFlags::WRITABLE)
+            && read_word(&mut address_space, data) == Some(0),
+        "A reserved page was not mapped with a zeroed frame.",
+    );
+    check(
+        address_space.handle_fault(data + 8, Access::Write).is_ok()
+            && FRAME_ALLOCATOR.lock().free_frames() == free_frames - 1,
+        "A spurious fault was not ignored.",
+    );
+    check(
+        address_space.unmap(data).is_ok() && address_space.handle_fault(data, Access::Read).is_ok(),
+        "An unmapped page did not stay reserved.",
+    );
+    check(
+        address_space.write_user(user(0), &[0]) == Err(MemoryError::AccessViolation(user(0)))
+            && address_space.check_user_write(guard_page, 8)
+                == Err(MemoryError::GuardPage(guard_page)),
+        "The kernel wrote to a page that user mode cannot write to.",
+    );
+    check(
+        address_space
+            .write_user(data + PAGE_SIZE, &1u64.to_le_bytes())
+            .is_ok()
+            && read_word(&mut address_space, data + PAGE_SIZE) == Some(1),
+        "The kernel could not write to a reserved page on behalf of user mode.",
+    );
+}
+
+/// Tests that user programs can use reserved pages and grow their stack.
+fn test_growing_stack() {
+    let (process, thread, address_space) = run(create_address_space(&GROW_CODE));
+    let mut address_space = address_space.lock();
+
+    check(
+        process.exit_status() == Some(5) && thread.fault().is_none(),
+        "The process could not use its reserved pages.",
+    );
+    check(
+        address_space.flags(user(DATA_OFFSET)).is_none()
+            && address_space.flags(user(DATA_OFFSET + PAGE_SIZE)).is_some()
+            && read_word(&mut address_space, user(DATA_OFFSET + PAGE_SIZE + 8)) == Some(5),
+        "Only the accessed data page should be mapped.",
+    );
+    check(
+        address_space
+            .flags(user(STACK_TOP_OFFSET - PAGE_SIZE))
+            .is_none()
+            && address_space
+                .flags(user(STACK_TOP_OFFSET - STACK_SIZE))
+                .is_some(),
+        "Only the accessed stack page should be mapped.",
+    );
+}
+
+/// Tests that faults which cannot be resolved kill the process and are recorded.
+fn test_unresolved_faults() {
+    let (process, thread, _) = run(create_address_space(&OVERFLOW_CODE));
+
+    check(
+        process.exit_status() == Some(process::FAULT_EXIT_STATUS),
+        "A stack overflow did not kill the process.",
+    );
+    check(
+        thread.fault()
+            == Some(Fault::Page {
+                address: user(STACK_TOP_OFFSET - STACK_SIZE - 8),
+                access: Access::Write,
+            }),
+        "The stack overflow was not recorded.",
+    );
+
+    let (process, thread, _) = run(create_address_space(&WRITE_CODE_CODE));
+
+    check(
+        process.exit_status() == Some(process::FAULT_EXIT_STATUS),
+        "A write to a read only page did not kill the process.",
+    );
+    check(
+        thread.fault()
+            == Some(Fault::Page {
+                address: user(WRITE_CODE_TARGET),
+                access: Access::Write,
+            }),
+        "The write to a read only page was not recorded.",
+    );
+}
+
+/// Tests that duplicated address spaces share their frames until they are written to.
+fn test_copy_on_write() {
+    let data = user(DATA_OFFSET);
+    let mut original = create_address_space(&COPY_ON_WRITE_CODE);
+
+    check(
+        original.write(data, &1u64.to_le_bytes()).is_ok(),
+        "Could not write to a reserved page.",
+    );
+
+    let copy = match original.duplicate() {
+        Ok(copy) => copy,
+        Err(_) => exit_integration_test(IntegrationTestExitCode::Failure(
+            "Could not duplicate an address space.",
+        )),
+    };
+    let copy_on_write = PageFlags::USER_ACCESSIBLE | PageFlags::COPY_ON_WRITE;
+
+    check(
+        original.translate(data) == copy.translate(data)
+            && original.flags(data) == Some(copy_on_write)
+            && copy.flags(data) == Some(copy_on_write),
+        "The duplicated address spaces do not share their frames copy-on-write.",
+    );
+    check(
+        original.translate(user(0)) == copy.translate(user(0))
+            && copy.flags(user(0)) == original.flags(user(0)),
+        "The read only code was not shared.",
+    );
+
+    let (process, _, copy) = run(copy);
+
+    check(
+        process.exit_status() == Some(2),
+        "The process could not write to a copy-on-write page.",
+    );
+    check(
+        read_word(&mut original, data) == Some(1),
+        "A write to a copy-on-write page changed the original.",
+    );
+    check(
+        original.translate(data) != copy.lock().translate(data),
+        "The written page was not copied.",
+    );
+    drop(copy);
+
+    let mut second_copy = match original.duplicate() {
+        Ok(copy) => copy,
+        Err(_) => exit_integration_test(IntegrationTestExitCode::Failure(
+            "Could not duplicate an address space.",
+        )),
+    };
+    let frame = second_copy.translate(data);
+
+    check(
+        original.write(data, &3u64.to_le_bytes()).is_ok()
+            && original.translate(data) != frame
+            && read_word(&mut second_copy, data) == Some(1),
+        "A kernel write to a copy-on-write page was not copied.",
+    );
+
+    // The copy is the only user of the frame now, so it can write to it without copying.
+    let free_frames = FRAME_ALLOCATOR.lock().free_frames();
+
+    check(
+        second_copy.handle_fault(data, Access::Write).is_ok()
+            && second_copy.translate(data) == frame
+            && second_copy.flags(data) == Some(PageFlags::USER_ACCESSIBLE | PageFlags::WRITABLE)
+            && FRAME_ALLOCATOR.lock().free_frames() == free_frames,
+        "A frame that is not shared anymore was copied.",
+    );
+}
+
+/// The entry point for the UEFI loader.
+///
+/// This is the first function that gets called by the UEFI firmware.
+#[no_mangle]
+pub extern "C" fn efi_main(image_handle: Handle, system_table: &'static SystemTable) -> ! {
+    uefi_init(image_handle, system_table);
+
+    let free_frames = FRAME_ALLOCATOR.lock().free_frames();
+
+    test_reserved_pages();
+    test_growing_stack();
+    test_unresolved_faults();
+    test_copy_on_write();
+
+    check(
+        FRAME_ALLOCATOR.lock().free_frames() == free_frames,
+        "Frames were leaked by the address spaces.",
+    );
+
+    exit_integration_test(IntegrationTestExitCode::Success);
+}
+
+/// The panic implementation of the demand paging test.
+#[panic_handler]
+fn panic_fmt(panic_info: &PanicInfo) -> ! {
+    serial_println!("{}", panic_info);
+
+    exit_integration_test(IntegrationTestExitCode::Failure(""));
+}
@@ -0,0 +1,198 @@
+//! This binary runs the PCI test.
+//!
+//! This test makes sure that the functions of the machine emulated by QEMU are found with their identifiers,
+//! that their BARs are sized without changing them and that the shell lists them.
+
+#![no_std]
+#![no_main]
+#![feature(alloc)]
+
+extern crate alloc;
+
+use alloc::{format, string::String, vec::Vec};
+use core::panic::PanicInfo;
+use kernel::{
+    arch::x86_64::{
+        exit_integration_test,
+        pci::{self, configuration, Address, Bar, Command, DeviceMatch},
+        shell::{self, CommandError, Outcome},
+        uefi::uefi_init,
+        IntegrationTestExitCode,
+    },
+    serial_println,
+};
+use nuefil::{system::SystemTable, Handle};
+
+/// The offset of the first BAR in the configuration space.
+const FIRST_BAR: u16 = 0x10;
+
+/// Fails the test with the given message if the condition doesn't hold.
+fn check(condition: bool, message: &'static str) {
+    if !condition {
+        exit_integration_test(IntegrationTestExitCode::Failure(message));
+    }
+}
+
+/// Tests parsing and formatting addresses.
+fn test_addresses() {
+    check(
+        "00:02.0".parse() == Ok(Address::new(0, 0, 2, 0))
+            && "0001:ff:1f.7".parse() == Ok(Address::new(1, 0xff, 0x1f, 7)),
+        "The addresses were parsed wrongly.",
+    );
+    check(
+        "00:20.0".parse::<Address>().is_err()
+            && "00:00.8".parse::<Address>().is_err()
+            && "0:0:0:0.0".parse::<Address>().is_err()
+            && "00.0".parse::<Address>().is_err(),
+        "Invalid addresses were parsed.",
+    );
+    check(
+        format!("{}", Address::new(0, 0x12, 3, 4)) == "0000:12:03.4",
+        "The address was formatted wrongly.",
+    );
+}
+
+/// Tests the list of functions.
+fn test_devices() {
+    let devices = pci::devices();
+    let mut addresses: Vec<_> = devices.iter().map(|device| device.address()).collect();
+
+    addresses.sort();
+    addresses.dedup();
+
+    check(!devices.is_empty(), "No PCI functions were found.");
+    check(
+        addresses.len() == devices.len(),
+        "A PCI function was found twice.",
+    );
+
+    let host_bridge = pci::get(Address::new(0, 0, 0, 0)).expect("The host bridge was not found.");
+
+    check(
+        host_bridge.class() == 0x06 && host_bridge.subclass() == 0x00,
+        "The host bridge has the wrong class.",
+    );
+    check(
+        pci::find(DeviceMatch::id(
+            host_bridge.vendor_id(),
+            host_bridge.device_id(),
+        ))
+        .any(|device| device.address() == host_bridge.address()),
+        "The host bridge was not found by its identifiers.",
+    );
+    check(
+        configuration::read_u16(Address::new(0, 0xff, 0x1f, 7), 0) == 0xffff,
+        "A missing function was read.",
+    );
+    check(
+        devices.iter().all(|device| {
+            device
+                .capabilities()
+                .iter()
+                .all(|capability| capability.offset() >= 0x40)
+        }),
+        "A capability lies in the header.",
+    );
+}
+
+/// Tests the BARs of the IDE controller and the display controller.
+fn test_bars() {
+    let ide = pci::find(DeviceMatch::class(0x01, 0x01))
+        .next()
+        .expect("The IDE controller was not found.");
+
+    check(
+        ide.bars().iter().any(|bar| match bar {
+            Some(Bar::Io { size, .. }) => size.is_power_of_two(),
+            _ => false,
+        }),
+        "The I/O ports of the IDE controller were not found.",
+    );
+
+    let display = pci::find(DeviceMatch::class(0x03, 0x00))
+        .next()
+        .expect("The display controller was not found.");
+    let command = display.command();
+
+    match display.bar(0) {
+        Some(Bar::Memory {
+            address,
+            size,
+            prefetchable,
+            ..
+        }) => {
+            check(
+                size.is_power_of_two() && size >= 0x1000 && address.as_usize() % size == 0,
+                "The framebuffer has the wrong size.",
+            );
+            check(prefetchable, "The framebuffer is not prefetchable.");
+            check(
+                display.read_u32(FIRST_BAR) as usize & !0xf == address.as_usize(),
+                "The BAR was changed by sizing it.",
+            );
+        }
+        _ => check(false, "The framebuffer was not found."),
+    }
+
+    check(
+        command.contains(Command::MEMORY_SPACE),
+        "The display controller doesn't decode its memory.",
+    );
+
+    display.set_command(command | Command::BUS_MASTER);
+    check(
+        display.command() == command | Command::BUS_MASTER,
+        "The command register was not written.",
+    );
+    display.set_command(command);
+}
+
+/// Tests the `pci` command of the shell.
+fn test_shell() {
+    let mut output = String::new();
+
+    check(
+        shell::execute("pci", &mut output, false) == Ok(Outcome::Continue)
+            && output.contains("0000:00:00.0"),
+        "The PCI functions were not listed.",
+    );
+
+    output.clear();
+
+    check(
+        shell::execute("pci 00:00.0", &mut output, false) == Ok(Outcome::Continue),
+        "The host bridge was not shown.",
+    );
+    check(
+        shell::execute("pci ff:1f.7", &mut output, false) == Err(CommandError::NoSuchDevice),
+        "A missing function was shown.",
+    );
+    check(
+        shell::execute("pci 00", &mut output, false) == Err(CommandError::InvalidArguments),
+        "An invalid address was accepted.",
+    );
+}
+
+/// The entry point for the UEFI loader.
+///
+/// This is the first function that gets called by the UEFI firmware.
+#[no_mangle]
+pub extern "C" fn efi_main(image_handle: Handle, system_table: &'static SystemTable) -> ! {
+    uefi_init(image_handle, system_table);
+
+    test_addresses();
+    test_devices();
+    test_bars();
+    test_shell();
+
+    exit_integration_test(IntegrationTestExitCode::Success);
+}
+
+/// The panic implementation of the PCI test.
+#[panic_handler]
+fn panic_fmt(panic_info: &PanicInfo) -> ! {
+    serial_println!("{}", panic_info);
+
+    exit_integration_test(IntegrationTestExitCode::Failure(""));
+}
@@ -0,0 +1,311 @@
+//! This binary runs the processes test.
+//!
+//! This test makes sure that processes can exit, be killed and be waited for,
+//! and that all their resources are freed afterwards.
+//! A thread that waits for its own process must stay stopped when the process is killed.
+//! The scheduler must wait for blocked threads and return once no thread exists anymore.
+//! A duplicated process must see the memory of the original as it was when it was duplicated.
+
+#![no_std]
+#![no_main]
+#![feature(alloc)]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use core::panic::PanicInfo;
+use kernel::{
+    arch::{
+        x86_64::{exit_integration_test, uefi::uefi_init, IntegrationTestExitCode},
+        Arch, Architecture,
+    },
+    capability::{CSpace, Rights},
+    ipc::Notification,
+    memory::{AddressSpace, PageFlags, FRAME_ALLOCATOR, PAGE_SIZE},
+    object::KernelObject,
+    process::{Process, ProcessState},
+    scheduler, serial_println,
+    thread::{self, ThreadState},
+    time::{self, Duration, Instant},
+};
+use nuefil::{system::SystemTable, Handle};
+
+/// The code of a process that exits with the status 7.
+const CHILD_CODE: [u8; 14] = [
+    0xb8, 0x03, 0x00, 0x00, 0x00, 0xbf, 0x07, 0x00, 0x00, 0x00, 0x0f, 0x05, 0x0f, 0x0b,
+];
+
+/// The code of a process that waits on the notification in slot 0 forever.
+const BLOCKER_CODE: [u8; 11] = [
+    0xb8, 0x29, 0x00, 0x00, 0x00, 0x31, 0xff, 0x0f, 0x05, 0x0f, 0x0b,
+];
+
+/// The code of a process that waits for the process in slot 0 and exits with its exit status plus one.
+const PARENT_CODE: [u8; 22] = [
+    0xb8, 0x41, 0x00, 0x00, 0x00, 0x31, 0xff, 0x0f, 0x05, 0x48, 0x8d, 0x7f, 0x01, 0xb8, 0x03, 0x00,
+    0x00, 0x00, 0x0f, 0x05, 0x0f, 0x0b,
+];
+
+/// The code of a process that waits on the notification in slot 0 and then exits with the status 7.
+const WAITER_CODE: [u8; 23] = [
+    0xb8, 0x29, 0x00, 0x00, 0x00, 0x31, 0xff, 0x0f, 0x05, 0xb8, 0x03, 0x00, 0x00, 0x00, 0xbf, 0x07,
+    0x00, 0x00, 0x00, 0x0f, 0x05, 0x0f, 0x0b,
+];
+
+/// The code of a process that stores 5 at the bottom of its stack page and duplicates itself into slot 1.
+///
+/// The copy starts at offset 0x4a and exits with the stored value.
+/// The original overwrites the value with 9 afterwards, waits for the copy and exits with its exit status.
+const DUPLICATE_CODE: [u8; 96] = [
+    0x48, 0xbb, 0x00, 0x10, 0x00, 0x00, 0x80, 0x00, 0x00, 0x00, 0x48, 0xc7, 0x03, 0x05, 0x00, 0x00,
+    0x00, 0xb8, 0x42, 0x00, 0x00, 0x00, 0xbf, 0x01, 0x00, 0x00, 0x00, 0x48, 0xbe, 0x4a, 0x00, 0x00,
+    0x00, 0x80, 0x00, 0x00, 0x00, 0x48, 0x8d, 0x93, 0x00, 0x10, 0x00, 0x00, 0x0f, 0x05, 0x48, 0xc7,
+    0x03, 0x09, 0x00, 0x00, 0x00, 0xb8, 0x41, 0x00, 0x00, 0x00, 0xbf, 0x01, 0x00, 0x00, 0x00, 0x0f,
+    0x05, 0xb8, 0x03, 0x00, 0x00, 0x00, 0x0f, 0x05, 0x0f, 0x0b, 0x48, 0xbb, 0x00, 0x10, 0x00, 0x00,
+    0x80, 0x00, 0x00, 0x00, 0x48, 0x8b, 0x3b, 0xb8, 0x03, 0x00, 0x00, 0x00, 0x0f, 0x05, 0x0f, 0x0b,
+];
+
+/// The offset of the top of the stack from the start of user space.
+const STACK_TOP_OFFSET: usize = 0x2000;
+
+/// The number of slots in the capability space of each process.
+const CSPACE_SIZE: usize = 4;
+
+/// The number of times the processes are created.
+const ROUNDS: usize = 64;
+
+/// The exit status the blocking process is killed with.
+const KILL_STATUS: usize = 99;
+
+/// The time after which the notification of the waiting process is signalled.
+const SIGNAL_DELAY: Duration = Duration::from_millis(10);
+
+/// Fails the test with the given message if the condition doesn't hold.
+fn check(condition: bool, message: &'static str) {
+    if !condition {
+        exit_integration_test(IntegrationTestExitCode::Failure(message));
+    }
+}
+
+/// Creates a process running the code, with a stack page.
+fn create_process(code: &[u8]) -> Arc<Process> {
+    let start = Arch::USER_SPACE_START;
+
+    let address_space = AddressSpace::new().and_then(|mut address_space| {
+        address_space.map(start, PageFlags::USER_ACCESSIBLE | PageFlags::EXECUTABLE)?;
+        address_space.write(start, code)?;
+        address_space.map(
+            start + STACK_TOP_OFFSET - PAGE_SIZE,
+            PageFlags::USER_ACCESSIBLE | PageFlags::WRITABLE,
+        )?;
+
+        Ok(address_space)
+    });
+
+    match address_space {
+        Ok(address_space) => Process::new(address_space, CSPACE_SIZE),
+        Err(_) => exit_integration_test(IntegrationTestExitCode::Failure(
+            "Could not create an address space.",
+        )),
+    }
+}
+
+/// Starts a thread of the process at the start of its code.
+fn start(process: &Arc<Process>) {
+    check(
+        Process::start(
+            process,
+            Arch::USER_SPACE_START,
+            Arch::USER_SPACE_START + STACK_TOP_OFFSET,
+        )
+        .is_some(),
+        "Could not start the process.",
+    );
+}
+
+/// Inserts the object into the capability space of the process.
+fn insert(process: &Process, slot: usize, object: KernelObject) {
+    check(
+        CSpace::insert(process.cspace(), slot, object, Rights::all()).is_ok(),
+        "Could not insert a capability.",
+    );
+}
+
+/// Runs one round of creating, running and killing processes.
+fn run_round(round: usize) {
+    let child = create_process(&CHILD_CODE);
+    let parent = create_process(&PARENT_CODE);
+    let blocker = create_process(&BLOCKER_CODE);
+    let unstarted = create_process(&CHILD_CODE);
+    let self_waiter = create_process(&PARENT_CODE);
+
+    insert(&parent, 0, KernelObject::Process(child.clone()));
+    insert(
+        &blocker,
+        0,
+        KernelObject::Notification(Arc::new(Notification::new())),
+    );
+    // The capability to itself must not keep the process alive after it was killed.
+    insert(&blocker, 1, KernelObject::Process(blocker.clone()));
+    insert(&self_waiter, 0, KernelObject::Process(self_waiter.clone()));
+
+    // The parent either waits before or after the child exits.
+    if round % 2 == 0 {
+        start(&parent);
+        start(&child);
+    } else {
+        start(&child);
+        start(&parent);
+    }
+
+    start(&blocker);
+    start(&blocker);
+    start(&self_waiter);
+
+    scheduler::run_until_idle();
+
+    check(
+        child.exit_status() == Some(7),
+        "The child did not exit with its status.",
+    );
+    check(
+        parent.exit_status() == Some(8),
+        "The parent did not receive the exit status of the child.",
+    );
+    check(
+        child.address_space().is_none() && child.threads().is_empty(),
+        "The resources of an exited process were not released.",
+    );
+    check(
+        blocker.state() == ProcessState::Running && blocker.threads().len() == 2,
+        "The blocking process is not running anymore.",
+    );
+    check(
+        blocker.threads().iter().all(|thread| match thread.state() {
+            ThreadState::BlockedOnNotification(_) => true,
+            _ => false,
+        }),
+        "The threads of the blocking process are not blocked.",
+    );
+
+    let self_waiting_thread = match self_waiter.threads().pop() {
+        Some(thread) => thread,
+        None => exit_integration_test(IntegrationTestExitCode::Failure(
+            "The process waiting for itself has no thread.",
+        )),
+    };
+
+    check(
+        match self_waiting_thread.state() {
+            ThreadState::BlockedOnProcess(_) => true,
+            _ => false,
+        },
+        "The thread of the process waiting for itself is not blocked.",
+    );
+
+    Process::kill(&self_waiter, KILL_STATUS);
+
+    check(
+        match self_waiting_thread.state() {
+            ThreadState::Inactive => true,
+            _ => false,
+        },
+        "The thread of a killed process was woken up by its exit.",
+    );
+
+    // A thread that was woken up would run in the released address space here.
+    scheduler::run_until_idle();
+
+    Process::kill(&blocker, KILL_STATUS);
+    Process::kill(&blocker, 0);
+    Process::kill(&unstarted, KILL_STATUS);
+
+    check(
+        blocker.exit_status() == Some(KILL_STATUS),
+        "Killing a process did not set its exit status.",
+    );
+    check(
+        blocker.cspace().lookup(0).is_err() && blocker.cspace().lookup(1).is_err(),
+        "The capabilities of a killed process were not deleted.",
+    );
+    check(
+        unstarted.exit_status() == Some(KILL_STATUS),
+        "Could not kill a process that was never started.",
+    );
+    check(
+        Process::create_thread(&blocker).is_none(),
+        "A thread was created in a killed process.",
+    );
+}
+
+/// Tests that a process duplicated by a system call gets a copy-on-write copy of the memory.
+fn test_duplicate() {
+    let original = create_process(&DUPLICATE_CODE);
+
+    start(&original);
+
+    // The original writes again before the copy runs, so the copy only sees 5 if the page was copied.
+    scheduler::run_until_idle();
+
+    check(
+        original.exit_status() == Some(5),
+        "The duplicated process did not see the memory as it was when it was duplicated.",
+    );
+}
+
+/// Tests that the scheduler keeps waiting while a thread is blocked and returns after its process exited.
+fn test_run() {
+    let waiter = create_process(&WAITER_CODE);
+    let notification = Arc::new(Notification::new());
+
+    insert(&waiter, 0, KernelObject::Notification(notification.clone()));
+    start(&waiter);
+
+    time::add_hrtimer(Instant::now() + SIGNAL_DELAY, move || {
+        notification.signal(0)
+    });
+
+    scheduler::run();
+
+    check(
+        waiter.exit_status() == Some(7),
+        "The scheduler returned before the waiting thread was signalled.",
+    );
+    check(
+        thread::count() == 0,
+        "The scheduler returned while threads still exist.",
+    );
+}
+
+/// The entry point for the UEFI loader.
+///
+/// This is the first function that gets called by the UEFI firmware.
+#[no_mangle]
+pub extern "C" fn efi_main(image_handle: Handle, system_table: &'static SystemTable) -> ! {
+    uefi_init(image_handle, system_table);
+
+    let free_frames = FRAME_ALLOCATOR.lock().free_frames();
+
+    for round in 0..ROUNDS {
+        run_round(round);
+    }
+
+    test_duplicate();
+
+    check(
+        FRAME_ALLOCATOR.lock().free_frames() == free_frames,
+        "Frames were leaked by the processes.",
+    );
+
+    test_run();
+
+    exit_integration_test(IntegrationTestExitCode::Success);
+}
+
+/// The panic implementation of the processes test.
+#[panic_handler]
+fn panic_fmt(panic_info: &PanicInfo) -> ! {
+    serial_println!("{}", panic_info);
+
+    exit_integration_test(IntegrationTestExitCode::Failure(""));
+}
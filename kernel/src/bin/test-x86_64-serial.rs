@@ -0,0 +1,301 @@
+//! This binary runs the serial port test.
+//!
+//! This test makes sure that the serial ports are detected and can be configured,
+//! that interrupt lines can be handled in the kernel
+//! and that the line discipline of the consoles turns the input into lines.
+
+#![no_std]
+#![no_main]
+#![feature(alloc)]
+
+extern crate alloc;
+
+use alloc::string::String;
+use core::{
+    fmt::{self, Write},
+    panic::PanicInfo,
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use kernel::{
+    arch::{
+        x86_64::{
+            exit_integration_test,
+            serial::{self, LineSettings, Parity, SerialError, StopBits},
+            uefi::uefi_init,
+            IntegrationTestExitCode,
+        },
+        Arch, Architecture,
+    },
+    console::{
+        self,
+        line_discipline::{Edit, LineDiscipline, MAXIMUM_LINE_LENGTH},
+        Sink,
+    },
+    irq::{self, IrqLine},
+    serial_println,
+    sync::Mutex,
+};
+use log::LevelFilter;
+use nuefil::{system::SystemTable, Handle};
+
+/// The interrupt line used to test handlers in the kernel.
+const IRQ: usize = 5;
+
+/// The interrupt line of the first serial port.
+const SERIAL_IRQ: usize = 4;
+
+/// The output received by the test sink.
+static OUTPUT: Mutex<Option<String>> = Mutex::new(None);
+
+/// The interrupt lines passed to the test handler.
+static HANDLED_IRQ: AtomicUsize = AtomicUsize::new(0);
+
+/// A sink that collects its output in memory.
+struct TestSink;
+
+impl Sink for TestSink {
+    fn write_fmt(&self, args: fmt::Arguments) {
+        OUTPUT
+            .lock()
+            .get_or_insert_with(String::new)
+            .write_fmt(args)
+            .expect("Could not write to the test sink.");
+    }
+}
+
+/// Fails the test with the given message if the condition doesn't hold.
+fn check(condition: bool, message: &'static str) {
+    if !condition {
+        exit_integration_test(IntegrationTestExitCode::Failure(message));
+    }
+}
+
+/// Records the interrupt line it is called for.
+fn test_handler(irq: usize) {
+    HANDLED_IRQ.store(irq, Ordering::SeqCst);
+}
+
+/// Tests parsing and validating line settings.
+fn test_line_settings() {
+    check(
+        LineSettings::from_str("115200") == Ok(LineSettings::DEFAULT),
+        "The baud rate alone was not parsed.",
+    );
+    check(
+        LineSettings::from_str("9600,7e2")
+            == LineSettings::new(9600, 7, Parity::Even, StopBits::Two),
+        "The line settings were not parsed.",
+    );
+    check(
+        LineSettings::from_str("38400,5m1")
+            .ok()
+            .map_or(false, |settings| {
+                settings.baud_rate() == 38400
+                    && settings.data_bits() == 5
+                    && settings.parity() == Parity::Mark
+                    && settings.stop_bits() == StopBits::One
+            }),
+        "The line settings were stored incorrectly.",
+    );
+    check(
+        LineSettings::from_str("9600,8x1") == Err(SerialError::InvalidSettings)
+            && LineSettings::from_str("fast") == Err(SerialError::InvalidSettings)
+            && LineSettings::from_str("9600,8n1,") == Err(SerialError::InvalidSettings),
+        "Invalid line settings were parsed.",
+    );
+    check(
+        LineSettings::from_str("1000") == Err(SerialError::InvalidBaudRate)
+            && LineSettings::from_str("9600,9n1") == Err(SerialError::InvalidDataBits),
+        "Unsupported line settings were accepted.",
+    );
+
+    let mut text = String::new();
+    write!(text, "{}", LineSettings::DEFAULT).expect("Could not format the line settings.");
+
+    check(
+        text == "115200,8n1",
+        "The line settings were not formatted.",
+    );
+}
+
+/// Tests the detected serial ports.
+fn test_ports() {
+    check(
+        serial::is_present(0),
+        "The first serial port was not found.",
+    );
+    check(
+        !serial::is_present(1) && serial::write(1, b"lost") == Err(SerialError::NotPresent),
+        "A missing serial port was found.",
+    );
+    check(
+        serial::line_settings(serial::SERIAL_PORT_COUNT) == Err(SerialError::NoSuchPort),
+        "A nonexistent serial port has line settings.",
+    );
+    check(
+        serial::line_settings(0) == Ok(LineSettings::DEFAULT),
+        "The first serial port doesn't use the default settings.",
+    );
+
+    let settings = LineSettings::new(57600, 8, Parity::None, StopBits::One)
+        .expect("Could not create the line settings.");
+
+    check(
+        serial::set_line_settings(0, settings).is_ok()
+            && serial::line_settings(0) == Ok(settings)
+            && serial::set_line_settings(0, LineSettings::DEFAULT).is_ok(),
+        "Could not change the line settings.",
+    );
+    check(
+        serial::write(0, b"Buffered serial output.\n").is_ok() && serial::flush(0).is_ok(),
+        "Could not write to the first serial port.",
+    );
+    check(
+        IrqLine::claim(SERIAL_IRQ).is_none(),
+        "The interrupt line of the first serial port is not claimed.",
+    );
+
+    // Without any input, an interrupt of the serial port does nothing.
+    Arch::mask_irq(SERIAL_IRQ);
+    irq::handle(SERIAL_IRQ);
+}
+
+/// Tests handling an interrupt line in the kernel.
+fn test_kernel_handler() {
+    let line = IrqLine::claim(IRQ).expect("Could not claim the interrupt line.");
+
+    line.set_kernel_handler(Some(test_handler));
+
+    // Interrupts arrive with their line masked.
+    Arch::mask_irq(IRQ);
+    irq::handle(IRQ);
+
+    check(
+        HANDLED_IRQ.load(Ordering::SeqCst) == IRQ,
+        "The interrupt was not passed to the kernel handler.",
+    );
+
+    line.set_kernel_handler(None);
+}
+
+/// Tests editing lines with the line discipline.
+fn test_line_discipline() {
+    let mut discipline = LineDiscipline::new();
+    let mut edits = [None; 8];
+
+    for (edit, &byte) in edits.iter_mut().zip(b"ls\x7f\x08\r\n") {
+        *edit = discipline.receive_byte(byte);
+    }
+
+    check(
+        edits[..6]
+            == [
+                Some(Edit::Insert('l')),
+                Some(Edit::Insert('s')),
+                Some(Edit::Erase(1)),
+                Some(Edit::Erase(1)),
+                Some(Edit::Complete),
+                None,
+            ],
+        "The line was not edited.",
+    );
+    check(
+        discipline
+            .read_line()
+            .map_or(false, |line| line.as_str() == "")
+            && discipline.read_line().is_none(),
+        "A carriage return and a line feed completed two lines.",
+    );
+
+    for &byte in "grün\x1b[Aß\x08\n".as_bytes() {
+        discipline.receive_byte(byte);
+    }
+
+    check(
+        discipline
+            .read_line()
+            .map_or(false, |line| line.as_str() == "grün"),
+        "A line with UTF-8 characters was not entered correctly.",
+    );
+
+    for character in "discarded\x03killed\x15kept\n".chars() {
+        discipline.receive(character);
+    }
+
+    check(
+        discipline
+            .read_line()
+            .map_or(false, |line| line.as_str() == "kept"),
+        "A line was not discarded.",
+    );
+
+    for _ in 0..MAXIMUM_LINE_LENGTH {
+        discipline.receive('x');
+    }
+
+    check(
+        discipline.receive('y').is_none() && discipline.current_line().len() == MAXIMUM_LINE_LENGTH,
+        "A line grew beyond its maximum length.",
+    );
+}
+
+/// Tests the input of the consoles.
+fn test_console_input() {
+    console::register("test", &TestSink, LevelFilter::Off);
+    console::set_level("test", LevelFilter::Trace);
+    OUTPUT.lock().take();
+
+    for &byte in b"hi\x7f!\r" {
+        console::receive_byte(byte);
+    }
+
+    check(
+        console::read_line().map_or(false, |line| line.as_str() == "h!"),
+        "The line was not entered on the consoles.",
+    );
+    check(
+        OUTPUT.lock().take().unwrap_or_default() == "hi\x08 \x08!\n",
+        "The input was not echoed.",
+    );
+
+    console::set_echo(false);
+    console::receive('q');
+    console::receive('\n');
+    console::set_echo(true);
+
+    check(
+        OUTPUT.lock().take().is_none(),
+        "The input was echoed while echoing was disabled.",
+    );
+    check(
+        console::read_line().map_or(false, |line| line.as_str() == "q"),
+        "The input was lost while echoing was disabled.",
+    );
+
+    console::unregister("test");
+}
+
+/// The entry point for the UEFI loader.
+///
+/// This is the first function that gets called by the UEFI firmware.
+#[no_mangle]
+pub extern "C" fn efi_main(image_handle: Handle, system_table: &'static SystemTable) -> ! {
+    uefi_init(image_handle, system_table);
+
+    test_line_settings();
+    test_ports();
+    test_kernel_handler();
+    test_line_discipline();
+    test_console_input();
+
+    exit_integration_test(IntegrationTestExitCode::Success);
+}
+
+/// The panic implementation of the serial port test.
+#[panic_handler]
+fn panic_fmt(panic_info: &PanicInfo) -> ! {
+    serial_println!("{}", panic_info);
+
+    exit_integration_test(IntegrationTestExitCode::Failure(""));
+}
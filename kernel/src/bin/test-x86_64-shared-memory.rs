@@ -0,0 +1,338 @@
+//! This binary runs the shared memory test.
+//!
+//! This test makes sure that memory regions can be mapped into several address spaces with different permissions,
+//! that their frames are only freed after the last mapping and capability is gone,
+//! that a range is only unmapped if all of its pages are mapped
+//! and that their size is limited for each call, by the quota of their creator and by the free memory on the kernel heap.
+
+#![no_std]
+#![no_main]
+#![feature(alloc)]
+
+extern crate alloc;
+
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::panic::PanicInfo;
+use kernel::{
+    arch::{
+        x86_64::{exit_integration_test, uefi::uefi_init, IntegrationTestExitCode},
+        Arch, Architecture,
+    },
+    capability::{CSpace, CapabilityError, Rights},
+    memory::{
+        heap, AddressSpace, MemoryError, PageFlags, VirtualAddress, FRAME_ALLOCATOR, PAGE_SIZE,
+    },
+    process, serial_println,
+    sync::Mutex,
+    syscall::{self, number, SyscallError, ARGUMENT_COUNT},
+    thread::Thread,
+};
+use nuefil::{system::SystemTable, Handle};
+
+/// The number of pages in the shared memory region.
+const PAGE_COUNT: usize = 2;
+
+/// The flag that requests a writable mapping.
+const WRITABLE: usize = 1;
+
+/// The flag that requests an executable mapping.
+const EXECUTABLE: usize = 2;
+
+/// Fails the test with the given message if the condition doesn't hold.
+fn check(condition: bool, message: &'static str) {
+    if !condition {
+        exit_integration_test(IntegrationTestExitCode::Failure(message));
+    }
+}
+
+/// Creates a thread with an empty address space and a capability space with four slots.
+fn create_thread() -> Arc<Thread> {
+    match AddressSpace::new() {
+        Ok(address_space) => Arc::new(Thread::new(
+            CSpace::new(4),
+            Arc::new(Mutex::new(address_space)),
+        )),
+        Err(_) => exit_integration_test(IntegrationTestExitCode::Failure(
+            "Could not create an address space.",
+        )),
+    }
+}
+
+/// Performs the system call with the given arguments for the thread.
+fn call(thread: &Arc<Thread>, number: usize, arguments: &[usize]) -> Result<usize, SyscallError> {
+    let mut all_arguments = [0; ARGUMENT_COUNT];
+    all_arguments[..arguments.len()].copy_from_slice(arguments);
+
+    syscall::handle(thread, number, all_arguments)
+}
+
+/// Returns the address of the page with the given index in user space.
+fn page(index: usize) -> VirtualAddress {
+    Arch::USER_SPACE_START + index * PAGE_SIZE
+}
+
+/// Tests that the memory regions created by threads are limited by their quota.
+fn test_quota() {
+    let thread = create_thread();
+    let region_count = process::MEMORY_QUOTA_PAGES / syscall::MAXIMUM_REGION_PAGES;
+
+    // The capability space of the thread has a slot for each region that fits into the quota.
+    for slot in 0..region_count {
+        check(
+            call(
+                &thread,
+                number::MEMORY_CREATE,
+                &[syscall::MAXIMUM_REGION_PAGES, slot],
+            ) == Ok(0),
+            "Could not create a memory region within the quota.",
+        );
+    }
+
+    let free_frames = FRAME_ALLOCATOR.lock().free_frames();
+
+    check(
+        call(&thread, number::MEMORY_CREATE, &[1, region_count])
+            == Err(SyscallError::Memory(MemoryError::QuotaExceeded))
+            && FRAME_ALLOCATOR.lock().free_frames() == free_frames,
+        "A memory region above the quota was created.",
+    );
+    check(
+        thread.cspace().delete(0).is_ok() && call(&thread, number::MEMORY_CREATE, &[1, 0]) == Ok(0),
+        "Deleting a memory region did not release its quota.",
+    );
+}
+
+/// Tests that no memory regions are created if the kernel heap runs low.
+fn test_heap_reserve() {
+    let thread = create_thread();
+    let free_frames = FRAME_ALLOCATOR.lock().free_frames();
+    let mut blocks = Vec::new();
+
+    while heap::free_memory() > heap::HEAP_RESERVE + PAGE_SIZE {
+        blocks.push(vec![0u8; PAGE_SIZE]);
+    }
+
+    check(
+        call(
+            &thread,
+            number::MEMORY_CREATE,
+            &[syscall::MAXIMUM_REGION_PAGES, 0],
+        ) == Err(SyscallError::Memory(MemoryError::HeapExhausted))
+            && FRAME_ALLOCATOR.lock().free_frames() == free_frames,
+        "A memory region was created from the reserve of the kernel heap.",
+    );
+
+    drop(blocks);
+
+    check(
+        call(
+            &thread,
+            number::MEMORY_CREATE,
+            &[syscall::MAXIMUM_REGION_PAGES, 0],
+        ) == Ok(0),
+        "Could not create a memory region after the kernel heap was freed.",
+    );
+}
+
+/// The entry point for the UEFI loader.
+///
+/// This is the first function that gets called by the UEFI firmware.
+#[no_mangle]
+pub extern "C" fn efi_main(image_handle: Handle, system_table: &'static SystemTable) -> ! {
+    uefi_init(image_handle, system_table);
+
+    let free_frames = FRAME_ALLOCATOR.lock().free_frames();
+
+    {
+        let writer = create_thread();
+        let reader = create_thread();
+
+        check(
+            call(&writer, number::MEMORY_CREATE, &[0, 0]) == Err(SyscallError::InvalidArgument),
+            "An empty memory region was created.",
+        );
+        check(
+            call(
+                &writer,
+                number::MEMORY_CREATE,
+                &[syscall::MAXIMUM_REGION_PAGES + 1, 0],
+            ) == Err(SyscallError::InvalidArgument),
+            "A memory region above the size limit was created.",
+        );
+
+        test_quota();
+        test_heap_reserve();
+
+        check(
+            call(&writer, number::MEMORY_CREATE, &[PAGE_COUNT, 0]) == Ok(0),
+            "Could not create a memory region.",
+        );
+        check(
+            writer
+                .cspace()
+                .copy(0, reader.cspace(), 0, Rights::READ)
+                .is_ok(),
+            "Could not copy the capability of the memory region.",
+        );
+
+        check(
+            call(
+                &writer,
+                number::MEMORY_MAP,
+                &[0, page(0).as_usize(), WRITABLE],
+            ) == Ok(0),
+            "Could not map the memory region writable.",
+        );
+        check(
+            writer
+                .address_space()
+                .lock()
+                .map(page(3), PageFlags::USER_ACCESSIBLE)
+                .is_ok(),
+            "Could not map a page.",
+        );
+        check(
+            call(&writer, number::MEMORY_MAP, &[0, page(2).as_usize(), 0])
+                == Err(SyscallError::Memory(MemoryError::AlreadyMapped(page(3)))),
+            "A memory region was mapped over another mapping.",
+        );
+        check(
+            writer.address_space().lock().flags(page(2)).is_none(),
+            "A failed mapping was not undone.",
+        );
+        check(
+            writer.address_space().lock().unmap(page(3)).is_ok(),
+            "Could not unmap a page.",
+        );
+        check(
+            call(&writer, number::MEMORY_MAP, &[0, page(2).as_usize() + 8, 0])
+                == Err(SyscallError::InvalidArgument),
+            "A memory region was mapped at an unaligned address.",
+        );
+        check(
+            call(&writer, number::MEMORY_MAP, &[0, page(2).as_usize(), 0x10])
+                == Err(SyscallError::InvalidArgument),
+            "Invalid mapping flags were accepted.",
+        );
+        check(
+            call(
+                &reader,
+                number::MEMORY_MAP,
+                &[0, page(4).as_usize(), WRITABLE],
+            ) == Err(SyscallError::Capability(
+                CapabilityError::InsufficientRights,
+            )),
+            "A read only capability allowed a writable mapping.",
+        );
+        check(
+            call(
+                &reader,
+                number::MEMORY_MAP,
+                &[0, page(4).as_usize(), EXECUTABLE],
+            ) == Err(SyscallError::Capability(
+                CapabilityError::InsufficientRights,
+            )),
+            "A read only capability allowed an executable mapping.",
+        );
+        check(
+            call(&reader, number::MEMORY_MAP, &[0, page(4).as_usize(), 0]) == Ok(0),
+            "Could not map the memory region read only.",
+        );
+
+        for index in 0..PAGE_COUNT {
+            check(
+                writer.address_space().lock().translate(page(index))
+                    == reader.address_space().lock().translate(page(4 + index)),
+                "The mappings do not share their frames.",
+            );
+        }
+
+        check(
+            writer
+                .address_space()
+                .lock()
+                .flags(page(1))
+                .map_or(false, |flags| flags.contains(PageFlags::WRITABLE))
+                && reader
+                    .address_space()
+                    .lock()
+                    .flags(page(5))
+                    .map_or(false, |flags| !flags.contains(PageFlags::WRITABLE)),
+            "The mappings do not have independent permissions.",
+        );
+
+        let message = b"shared memory";
+        let mut buffer = [0; 13];
+
+        check(
+            writer
+                .address_space()
+                .lock()
+                .write(page(1) + 100, message)
+                .is_ok()
+                && reader
+                    .address_space()
+                    .lock()
+                    .read(page(5) + 100, &mut buffer)
+                    .is_ok()
+                && &buffer == message,
+            "The data written through one mapping cannot be read through the other.",
+        );
+
+        // The mappings keep the memory region alive.
+        check(
+            writer.cspace().delete(0).is_ok() && reader.cspace().delete(0).is_ok(),
+            "Could not delete the capabilities of the memory region.",
+        );
+        check(
+            call(
+                &writer,
+                number::MEMORY_UNMAP,
+                &[page(0).as_usize(), PAGE_COUNT + 1],
+            ) == Err(SyscallError::Memory(MemoryError::NotMapped(page(
+                PAGE_COUNT,
+            )))) && writer.address_space().lock().flags(page(0)).is_some(),
+            "A range containing an unmapped page was partially unmapped.",
+        );
+        check(
+            call(
+                &writer,
+                number::MEMORY_UNMAP,
+                &[page(0).as_usize(), PAGE_COUNT],
+            ) == Ok(0),
+            "Could not unmap the memory region.",
+        );
+        check(
+            call(&writer, number::MEMORY_UNMAP, &[page(0).as_usize(), 1])
+                == Err(SyscallError::Memory(MemoryError::NotMapped(page(0)))),
+            "An unmapped page was unmapped again.",
+        );
+
+        buffer = [0; 13];
+
+        check(
+            reader
+                .address_space()
+                .lock()
+                .read(page(5) + 100, &mut buffer)
+                .is_ok()
+                && &buffer == message,
+            "The memory region was freed while it was still mapped.",
+        );
+    }
+
+    check(
+        FRAME_ALLOCATOR.lock().free_frames() == free_frames,
+        "The frames of the memory region were not freed after the last mapping was gone.",
+    );
+
+    exit_integration_test(IntegrationTestExitCode::Success);
+}
+
+/// The panic implementation of the shared memory test.
+#[panic_handler]
+fn panic_fmt(panic_info: &PanicInfo) -> ! {
+    serial_println!("{}", panic_info);
+
+    exit_integration_test(IntegrationTestExitCode::Failure(""));
+}
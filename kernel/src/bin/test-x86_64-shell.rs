@@ -0,0 +1,245 @@
+//! This binary runs the debug shell test.
+//!
+//! This test makes sure that the ACPI tables are found, that processes are registered
+//! and that the commands of the debug shell produce the expected output,
+//! without waiting for data that is in use by the interrupted code.
+
+#![no_std]
+#![no_main]
+#![feature(alloc)]
+
+extern crate alloc;
+
+use alloc::{format, string::String, sync::Arc};
+use core::panic::PanicInfo;
+use kernel::{
+    arch::x86_64::{
+        acpi, exit_integration_test,
+        shell::{self, CommandError, Outcome},
+        uefi::uefi_init,
+        IntegrationTestExitCode,
+    },
+    log_filter,
+    memory::{AddressSpace, FRAME_ALLOCATOR},
+    process::{self, Process},
+    serial_println,
+};
+use log::LevelFilter;
+use nuefil::{system::SystemTable, Handle};
+
+/// A static that is dumped with the `mem` command.
+static DUMPED: [u8; 16] = *b"BeetleOS memory!";
+
+/// An address in the higher half, which the firmware doesn't map.
+const UNMAPPED_ADDRESS: usize = 0xffff_8000_0000_0000;
+
+/// Fails the test with the given message if the condition doesn't hold.
+fn check(condition: bool, message: &'static str) {
+    if !condition {
+        exit_integration_test(IntegrationTestExitCode::Failure(message));
+    }
+}
+
+/// Runs the command and returns its result and output.
+fn run(line: &str, after_panic: bool) -> (Result<Outcome, CommandError>, String) {
+    let mut output = String::new();
+    let result = shell::execute(line, &mut output, after_panic);
+
+    (result, output)
+}
+
+/// Tests finding the ACPI tables.
+fn test_acpi() {
+    check(
+        acpi::revision().is_some(),
+        "The ACPI tables were not found.",
+    );
+
+    let tables = acpi::tables();
+
+    check(!tables.is_empty(), "The root table lists no tables.");
+    check(
+        tables.iter().all(|table| table.is_valid()),
+        "An ACPI table has an invalid checksum.",
+    );
+
+    let madt = acpi::find("APIC").expect("The MADT was not found.");
+
+    check(madt.signature() == "APIC", "The wrong table was found.");
+    check(
+        madt.bytes().len() == madt.length() && &madt.bytes()[..4] == b"APIC",
+        "The bytes of the table are wrong.",
+    );
+    check(acpi::find("NONE").is_none(), "A missing table was found.");
+}
+
+/// Tests that processes are registered while they exist.
+fn test_process_registry() {
+    let count = process::processes().len();
+
+    let process = Process::new(
+        AddressSpace::new().expect("Could not create an address space."),
+        1,
+    );
+
+    check(
+        process::processes()
+            .iter()
+            .any(|listed| Arc::ptr_eq(listed, &process)),
+        "The process was not registered.",
+    );
+
+    drop(process);
+
+    check(
+        process::processes().len() == count,
+        "The process was still listed after it was dropped.",
+    );
+}
+
+/// Tests the commands of the shell.
+fn test_commands() {
+    let (result, output) = run("help", false);
+    check(
+        result == Ok(Outcome::Continue) && output.contains("mem") && output.contains("poweroff"),
+        "The help was not shown.",
+    );
+
+    check(
+        run("", false).0 == Ok(Outcome::Continue),
+        "An empty line was not ignored.",
+    );
+    check(
+        run("frobnicate", false).0 == Err(CommandError::UnknownCommand),
+        "An unknown command was accepted.",
+    );
+
+    let (result, output) = run("mem", false);
+    check(
+        result == Err(CommandError::InvalidArguments) && output.starts_with("usage: mem"),
+        "The usage was not shown for missing arguments.",
+    );
+
+    let address = DUMPED.as_ptr() as usize;
+    let (result, output) = run(&format!("mem {:#x} 16", address), false);
+    check(
+        result == Ok(Outcome::Continue)
+            && output.contains("42 65 65 74")
+            && output.contains("BeetleOS memory!"),
+        "The memory dump is wrong.",
+    );
+
+    check(
+        run(&format!("mem {:#x}", UNMAPPED_ADDRESS), false).0 == Err(CommandError::NotMapped),
+        "Unmapped memory was dumped.",
+    );
+
+    let (result, output) = run(&format!("pt {:#x}", address), false);
+    check(
+        result == Ok(Outcome::Continue) && output.contains("PML4") && output.contains("maps to"),
+        "The page table entries were not shown.",
+    );
+
+    let (_, output) = run(&format!("pt {:#x}", UNMAPPED_ADDRESS), false);
+    check(
+        output.contains("is not mapped"),
+        "An unmapped address was reported as mapped.",
+    );
+
+    for &command in &["frames", "regs", "cpuid"] {
+        let (result, output) = run(command, false);
+
+        check(
+            result == Ok(Outcome::Continue) && !output.is_empty(),
+            "A command showed nothing.",
+        );
+    }
+
+    {
+        let _frame_allocator = FRAME_ALLOCATOR.lock();
+
+        check(
+            run("frames", false).0 == Err(CommandError::Busy),
+            "The shell waited for the frame allocator.",
+        );
+    }
+
+    // There may be no processes and no log records at this point.
+    for &command in &["ps", "threads", "log 5"] {
+        check(
+            run(command, false).0 == Ok(Outcome::Continue),
+            "A command failed.",
+        );
+    }
+
+    let (result, output) = run("acpi", false);
+    check(
+        result == Ok(Outcome::Continue) && output.contains("APIC"),
+        "The ACPI tables were not listed.",
+    );
+    check(
+        run("acpi APIC", false).0 == Ok(Outcome::Continue),
+        "The MADT was not dumped.",
+    );
+    check(
+        run("acpi NONE", false).0 == Err(CommandError::NoSuchTable),
+        "A missing ACPI table was dumped.",
+    );
+
+    check(
+        run("loglevel kernel::shell_test=trace", false).0 == Ok(Outcome::Continue)
+            && log_filter::level("kernel::shell_test") == LevelFilter::Trace,
+        "The log level was not set.",
+    );
+    check(
+        run("loglevel", false)
+            .1
+            .contains("kernel::shell_test: TRACE"),
+        "The log levels were not listed.",
+    );
+    check(
+        run("loglevel kernel::shell_test=", false).0 == Ok(Outcome::Continue)
+            && log_filter::level("kernel::shell_test") != LevelFilter::Trace,
+        "The log level was not removed.",
+    );
+    check(
+        run("loglevel verbose", false).0 == Err(CommandError::InvalidArguments),
+        "An invalid log level was accepted.",
+    );
+
+    check(
+        run("console missing off", false).0 == Err(CommandError::NoSuchSink),
+        "A missing console sink was changed.",
+    );
+
+    check(
+        run("exit", false).0 == Ok(Outcome::Exit),
+        "The shell could not be left.",
+    );
+    check(
+        run("exit", true).0 == Err(CommandError::AfterPanic),
+        "The shell could be left after a panic.",
+    );
+}
+
+/// The entry point for the UEFI loader.
+///
+/// This is the first function that gets called by the UEFI firmware.
+#[no_mangle]
+pub extern "C" fn efi_main(image_handle: Handle, system_table: &'static SystemTable) -> ! {
+    uefi_init(image_handle, system_table);
+
+    test_acpi();
+    test_process_registry();
+    test_commands();
+
+    exit_integration_test(IntegrationTestExitCode::Success);
+}
+
+/// The panic implementation of the debug shell test.
+#[panic_handler]
+fn panic_fmt(panic_info: &PanicInfo) -> ! {
+    serial_println!("{}", panic_info);
+
+    exit_integration_test(IntegrationTestExitCode::Failure(""));
+}
@@ -0,0 +1,264 @@
+//! This binary runs the time test.
+//!
+//! This test makes sure that the monotonic clock advances at the right rate, that the wall clock is set during boot,
+//! that timeouts expire in order of their deadlines and that sleeping threads wake up after their deadline.
+
+#![no_std]
+#![no_main]
+#![feature(alloc)]
+
+extern crate alloc;
+
+use alloc::{boxed::Box, sync::Arc};
+use core::{
+    panic::PanicInfo,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use kernel::{
+    arch::{
+        x86_64::{exit_integration_test, uefi::uefi_init, IntegrationTestExitCode},
+        Arch, Architecture,
+    },
+    capability::CSpace,
+    memory::{AddressSpace, PageFlags, FRAME_ALLOCATOR},
+    process::Process,
+    scheduler, serial_println,
+    sync::Mutex,
+    syscall::{self, clock, number, SyscallError, ARGUMENT_COUNT},
+    thread::{Thread, ThreadState},
+    time::{self, DateTime, Duration, Instant, TimerWheel},
+};
+use nuefil::{system::SystemTable, Handle};
+
+/// The code of a process that sleeps for 20 milliseconds and exits with the monotonic time after waking up.
+const SLEEPER_CODE: [u8; 30] = [
+    0xbf, 0x00, 0x2d, 0x31, 0x01, 0xb8, 0x04, 0x00, 0x00, 0x00, 0x0f, 0x05, 0x31, 0xff, 0xb8, 0x05,
+    0x00, 0x00, 0x00, 0x0f, 0x05, 0xb8, 0x03, 0x00, 0x00, 0x00, 0x0f, 0x05, 0x0f, 0x0b,
+];
+
+/// The time the sleeper process sleeps for.
+const SLEEP_TIME: Duration = Duration::from_millis(20);
+
+/// Fails the test with the given message if the condition doesn't hold.
+fn check(condition: bool, message: &'static str) {
+    if !condition {
+        exit_integration_test(IntegrationTestExitCode::Failure(message));
+    }
+}
+
+/// Returns the instant the given number of milliseconds after the clock was started.
+fn milliseconds(milliseconds: u64) -> Instant {
+    Instant::from_nanoseconds(milliseconds * 1_000_000)
+}
+
+/// Performs the system call with the given arguments for the thread.
+fn call(thread: &Arc<Thread>, number: usize, arguments: &[usize]) -> Result<usize, SyscallError> {
+    let mut all_arguments = [0; ARGUMENT_COUNT];
+    all_arguments[..arguments.len()].copy_from_slice(arguments);
+
+    syscall::handle(thread, number, all_arguments)
+}
+
+/// Tests the monotonic clock against a delay.
+fn test_monotonic_clock() {
+    check(
+        time::clock_source().map_or(false, |source| source.frequency() > 0),
+        "No clock source drives the monotonic clock.",
+    );
+
+    let start = Instant::now();
+    time::delay(Duration::from_millis(10));
+    let elapsed = start.elapsed();
+
+    check(
+        elapsed >= Duration::from_millis(10) && elapsed < Duration::from_millis(100),
+        "The monotonic clock does not advance at the right rate.",
+    );
+    check(
+        Instant::now() >= start + elapsed,
+        "The monotonic clock went backwards.",
+    );
+
+    let now = Instant::now();
+
+    check(
+        Instant::now_or_latest() >= now,
+        "The monotonic clock went backwards when read without waiting.",
+    );
+}
+
+/// Tests the conversions of dates and the wall clock.
+fn test_wall_clock() {
+    check(
+        DateTime::from_unix_time(Duration::from_secs(0))
+            == DateTime::new(1970, 1, 1, 0, 0, 0, 0).expect("The start of 1970 was rejected."),
+        "The start of 1970 was not converted correctly.",
+    );
+
+    let leap_day = DateTime::new(2000, 2, 29, 12, 34, 56, 0).expect("A leap day was rejected.");
+
+    check(
+        leap_day.unix_time() == Duration::from_secs(951_827_696)
+            && DateTime::from_unix_time(leap_day.unix_time()) == leap_day,
+        "A leap day was not converted correctly.",
+    );
+    check(
+        DateTime::new(2019, 2, 29, 0, 0, 0, 0).is_none()
+            && DateTime::new(2019, 13, 1, 0, 0, 0, 0).is_none()
+            && DateTime::new(2019, 1, 1, 24, 0, 0, 0).is_none(),
+        "An invalid date was accepted.",
+    );
+    check(
+        time::wall_clock().map_or(false, |now| now.year() >= 2019),
+        "The wall clock was not set during boot.",
+    );
+}
+
+/// Tests that a timer wheel expires its timers in order and only after their deadline.
+fn test_timer_wheel() {
+    static ORDER: AtomicUsize = AtomicUsize::new(0);
+
+    let mut wheel = TimerWheel::new();
+    let record = |digit| {
+        Box::new(move || {
+            ORDER.store(ORDER.load(Ordering::SeqCst) * 10 + digit, Ordering::SeqCst);
+        })
+    };
+
+    // The last deadline lies more than one revolution of the wheel ahead.
+    wheel.add(milliseconds(5), record(2));
+    wheel.add(milliseconds(1), record(1));
+    let cancelled = wheel.add(milliseconds(3), record(9));
+    wheel.add(milliseconds(1000), record(3));
+
+    check(
+        wheel.cancel(cancelled) && !wheel.cancel(cancelled),
+        "A timer could not be cancelled exactly once.",
+    );
+    check(
+        wheel.next_deadline() == Some(milliseconds(1)),
+        "The next deadline of the timer wheel is wrong.",
+    );
+
+    for mut callback in wheel.expire(milliseconds(0)) {
+        callback();
+    }
+    check(
+        ORDER.load(Ordering::SeqCst) == 0,
+        "A timer expired before its deadline.",
+    );
+
+    for mut callback in wheel.expire(milliseconds(10)) {
+        callback();
+    }
+    check(
+        ORDER.load(Ordering::SeqCst) == 12,
+        "The timers did not expire in order of their deadlines.",
+    );
+
+    for mut callback in wheel.expire(milliseconds(999)) {
+        callback();
+    }
+    check(
+        ORDER.load(Ordering::SeqCst) == 12,
+        "A timer expired a revolution of the wheel too early.",
+    );
+
+    for mut callback in wheel.expire(milliseconds(1000)) {
+        callback();
+    }
+    check(
+        ORDER.load(Ordering::SeqCst) == 123 && wheel.is_empty(),
+        "A timer did not expire after its deadline.",
+    );
+}
+
+/// Tests that sleeping threads wake up after their deadline and that suspending them cancels their timer.
+fn test_sleep() {
+    let address_space = AddressSpace::new()
+        .and_then(|mut address_space| {
+            address_space.map(
+                Arch::USER_SPACE_START,
+                PageFlags::USER_ACCESSIBLE | PageFlags::EXECUTABLE,
+            )?;
+            address_space.write(Arch::USER_SPACE_START, &SLEEPER_CODE)?;
+
+            Ok(address_space)
+        })
+        .expect("Could not create an address space.");
+    let process = Process::new(address_space, 1);
+    let start = Instant::now();
+
+    check(
+        Process::start(&process, Arch::USER_SPACE_START, Arch::USER_SPACE_START).is_some(),
+        "Could not start the sleeper process.",
+    );
+
+    scheduler::run_until_idle();
+
+    check(
+        process.exit_status().map_or(false, |woken| {
+            woken as u64 >= (start + SLEEP_TIME).as_nanoseconds()
+        }),
+        "The sleeping thread woke up before its deadline.",
+    );
+
+    let thread = Arc::new(Thread::new(
+        CSpace::new(1),
+        Arc::new(Mutex::new(
+            AddressSpace::new().expect("Could not create an address space."),
+        )),
+    ));
+
+    check(
+        call(&thread, number::SLEEP, &[usize::max_value()]) == Ok(0)
+            && time::next_deadline().is_some(),
+        "A thread could not go to sleep.",
+    );
+
+    Thread::suspend(&thread);
+
+    check(
+        match thread.state() {
+            ThreadState::Inactive => time::next_deadline().is_none(),
+            _ => false,
+        },
+        "Suspending a sleeping thread did not cancel its timer.",
+    );
+    check(
+        call(&thread, number::CLOCK_READ, &[clock::MONOTONIC]) == Ok(0)
+            && thread.context().argument(0) as u64 >= start.as_nanoseconds()
+            && call(&thread, number::CLOCK_READ, &[2]) == Err(SyscallError::InvalidArgument),
+        "The clocks could not be read with a system call.",
+    );
+}
+
+/// The entry point for the UEFI loader.
+///
+/// This is the first function that gets called by the UEFI firmware.
+#[no_mangle]
+pub extern "C" fn efi_main(image_handle: Handle, system_table: &'static SystemTable) -> ! {
+    uefi_init(image_handle, system_table);
+
+    let free_frames = FRAME_ALLOCATOR.lock().free_frames();
+
+    test_monotonic_clock();
+    test_wall_clock();
+    test_timer_wheel();
+    test_sleep();
+
+    check(
+        FRAME_ALLOCATOR.lock().free_frames() == free_frames,
+        "Frames were leaked by the sleeping threads.",
+    );
+
+    exit_integration_test(IntegrationTestExitCode::Success);
+}
+
+/// The panic implementation of the time test.
+#[panic_handler]
+fn panic_fmt(panic_info: &PanicInfo) -> ! {
+    serial_println!("{}", panic_info);
+
+    exit_integration_test(IntegrationTestExitCode::Failure(""));
+}
@@ -0,0 +1,131 @@
+//! This binary runs the UEFI runtime services test.
+//!
+//! This test makes sure that the runtime services can still be called after exiting the boot services,
+//! by reading the time and by writing, listing, reading and deleting an EFI variable.
+
+#![no_std]
+#![no_main]
+#![feature(alloc)]
+
+extern crate alloc;
+
+use core::panic::PanicInfo;
+use kernel::{
+    arch::x86_64::{
+        exit_integration_test,
+        uefi::{
+            runtime::{
+                self, Variable, GLOBAL_VARIABLE, VARIABLE_BOOTSERVICE_ACCESS,
+                VARIABLE_RUNTIME_ACCESS,
+            },
+            uefi_init,
+        },
+        IntegrationTestExitCode,
+    },
+    serial_println,
+    time::{self, Duration},
+};
+use nuefil::{guid::Guid, system::SystemTable, Handle};
+
+/// The vendor GUID of the test variable.
+const TEST_VENDOR: Guid = Guid(
+    0x4265_6574,
+    0x6c65,
+    0x4f53,
+    [0x80, 0x00, 0x74, 0x65, 0x73, 0x74, 0x00, 0x01],
+);
+
+/// The name of the test variable.
+const TEST_NAME: &str = "BeetleTest";
+
+/// The attributes of the test variable, which is lost on reset.
+const TEST_ATTRIBUTES: u32 = VARIABLE_BOOTSERVICE_ACCESS | VARIABLE_RUNTIME_ACCESS;
+
+/// The contents of the test variable.
+const TEST_DATA: [u8; 4] = [0xbe, 0xe7, 0x1e, 0x05];
+
+/// Fails the test with the given message if the condition doesn't hold.
+fn check(condition: bool, message: &'static str) {
+    if !condition {
+        exit_integration_test(IntegrationTestExitCode::Failure(message));
+    }
+}
+
+/// Checks if a variable with the given name and vendor exists.
+fn variable_exists(name: &str, vendor: &Guid) -> bool {
+    runtime::variable_names()
+        .expect("Could not list the EFI variables.")
+        .iter()
+        .any(|(variable_name, variable_vendor)| variable_name == name && variable_vendor == vendor)
+}
+
+/// Tests that the time can be read after exiting the boot services.
+fn test_time() {
+    let now = runtime::get_time().expect("The time could not be read from the firmware.");
+    let wall_clock = time::unix_time().expect("The wall clock was not set during boot.");
+
+    // The firmware time only has a resolution of seconds.
+    let difference = if now.unix_time() > wall_clock {
+        now.unix_time() - wall_clock
+    } else {
+        wall_clock - now.unix_time()
+    };
+
+    check(
+        difference < Duration::from_secs(2),
+        "The firmware time does not match the wall clock.",
+    );
+}
+
+/// Tests that variables can be written, listed, read and deleted.
+fn test_variables() {
+    check(
+        variable_exists("BootOrder", &GLOBAL_VARIABLE),
+        "The global variables were not listed.",
+    );
+
+    check(
+        runtime::set_variable(TEST_NAME, &TEST_VENDOR, TEST_ATTRIBUTES, &TEST_DATA).is_ok(),
+        "Could not write the test variable.",
+    );
+    check(
+        variable_exists(TEST_NAME, &TEST_VENDOR),
+        "The test variable was not listed.",
+    );
+    check(
+        runtime::get_variable(TEST_NAME, &TEST_VENDOR)
+            == Ok(Variable {
+                attributes: TEST_ATTRIBUTES,
+                data: TEST_DATA.to_vec(),
+            }),
+        "The test variable could not be read back.",
+    );
+
+    check(
+        runtime::set_variable(TEST_NAME, &TEST_VENDOR, TEST_ATTRIBUTES, &[]).is_ok()
+            && runtime::get_variable(TEST_NAME, &TEST_VENDOR).is_err()
+            && !variable_exists(TEST_NAME, &TEST_VENDOR),
+        "The test variable could not be deleted.",
+    );
+}
+
+/// The entry point for the UEFI loader.
+///
+/// This is the first function that gets called by the UEFI firmware.
+#[no_mangle]
+pub extern "C" fn efi_main(image_handle: Handle, system_table: &'static SystemTable) -> ! {
+    uefi_init(image_handle, system_table);
+
+    test_time();
+    test_variables();
+
+    exit_integration_test(IntegrationTestExitCode::Success);
+}
+
+/// The panic implementation of the UEFI runtime services test.
+#[panic_handler]
+fn panic_fmt(panic_info: &PanicInfo) -> ! {
+    serial_println!("{}", panic_info);
+
+    exit_integration_test(IntegrationTestExitCode::Failure(""));
+}
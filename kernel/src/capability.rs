@@ -0,0 +1,101 @@
+//! Provides the capability system of the kernel.
+//!
+//! Kernel objects can only be used through capabilities, which are stored in capability spaces (CSpaces).
+//! A capability combines a reference to a kernel object with the rights its holder has on the object.
+//! Capabilities can be derived from each other with fewer rights and the kernel remembers the derivation,
+//! so that all capabilities derived from a capability can be revoked at once.
+
+mod cspace;
+mod derivation;
+mod rights;
+
+use core::fmt;
+use lazy_static::lazy_static;
+
+use self::derivation::DerivationTree;
+pub use self::{cspace::CSpace, rights::Rights};
+use crate::{object::KernelObject, sync::Mutex};
+
+lazy_static! {
+    /// The derivation tree of all capabilities in the system.
+    ///
+    /// This lock must be taken before any lock of a capability space.
+    static ref DERIVATION_TREE: Mutex<DerivationTree> = Mutex::new(DerivationTree::new());
+}
+
+/// Uniquely identifies a capability.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CapabilityId(usize);
+
+/// A capability to a kernel object.
+#[derive(Clone, Debug)]
+pub struct Capability {
+    /// The identifier of the capability in the derivation tree.
+    id: CapabilityId,
+    /// The object the capability refers to.
+    object: KernelObject,
+    /// The rights the capability grants on the object.
+    rights: Rights,
+    /// The badge of the capability, which is zero if the capability is unbadged.
+    badge: u64,
+}
+
+impl Capability {
+    /// Returns the identifier of the capability.
+    pub fn id(&self) -> CapabilityId {
+        self.id
+    }
+
+    /// Returns the object the capability refers to.
+    pub fn object(&self) -> &KernelObject {
+        &self.object
+    }
+
+    /// Returns the rights the capability grants on its object.
+    pub fn rights(&self) -> Rights {
+        self.rights
+    }
+
+    /// Returns the badge of the capability, which is zero if the capability is unbadged.
+    pub fn badge(&self) -> u64 {
+        self.badge
+    }
+}
+
+/// The errors that can occur when using capabilities.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CapabilityError {
+    /// The slot with the given index does not exist.
+    InvalidSlot(usize),
+    /// The slot with the given index does not contain a capability.
+    EmptySlot(usize),
+    /// The slot with the given index already contains a capability.
+    SlotOccupied(usize),
+    /// Capabilities to the object cannot carry a badge.
+    NotBadgeable,
+    /// The capability already carries a badge.
+    AlreadyBadged,
+    /// Zero was used as a badge, which marks unbadged capabilities.
+    InvalidBadge,
+    /// The capability refers to an object of the wrong type.
+    InvalidType,
+    /// The capability does not grant the rights needed for the operation.
+    InsufficientRights,
+}
+
+impl fmt::Display for CapabilityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CapabilityError::InvalidSlot(slot) => write!(f, "the slot {} does not exist", slot),
+            CapabilityError::EmptySlot(slot) => write!(f, "the slot {} is empty", slot),
+            CapabilityError::SlotOccupied(slot) => write!(f, "the slot {} is occupied", slot),
+            CapabilityError::NotBadgeable => write!(f, "the object cannot be badged"),
+            CapabilityError::AlreadyBadged => write!(f, "the capability is already badged"),
+            CapabilityError::InvalidBadge => write!(f, "zero is not a valid badge"),
+            CapabilityError::InvalidType => write!(f, "the object has the wrong type"),
+            CapabilityError::InsufficientRights => {
+                write!(f, "the capability does not grant the required rights")
+            }
+        }
+    }
+}
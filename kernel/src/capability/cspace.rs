@@ -0,0 +1,281 @@
+//! Provides capability spaces, which hold the capabilities of a process.
+//!
+//! Objects referenced by a removed capability may be freed, which can in turn remove further capabilities.
+//! Because of that, removed capabilities are only dropped after all locks were released.
+
+use alloc::{sync::Arc, vec::Vec};
+
+use super::{Capability, CapabilityError, Rights, DERIVATION_TREE};
+use crate::{object::KernelObject, sync::Mutex};
+
+/// A table of capabilities, indexed by slot numbers.
+pub struct CSpace {
+    /// The slots that hold the capabilities.
+    slots: Mutex<Vec<Option<Capability>>>,
+}
+
+/// Returns an error if `slot` is not a free slot in `slots`.
+fn check_free(slots: &[Option<Capability>], slot: usize) -> Result<(), CapabilityError> {
+    match slots.get(slot) {
+        None => Err(CapabilityError::InvalidSlot(slot)),
+        Some(Some(_)) => Err(CapabilityError::SlotOccupied(slot)),
+        Some(None) => Ok(()),
+    }
+}
+
+impl CSpace {
+    /// Creates an empty capability space with `size` slots.
+    pub fn new(size: usize) -> Arc<CSpace> {
+        let mut slots = Vec::with_capacity(size);
+        slots.resize(size, None);
+
+        Arc::new(CSpace {
+            slots: Mutex::new(slots),
+        })
+    }
+
+    /// Returns the number of slots in the capability space.
+    pub fn size(&self) -> usize {
+        self.slots.lock().len()
+    }
+
+    /// Returns the capability in the given slot.
+    pub fn lookup(&self, slot: usize) -> Result<Capability, CapabilityError> {
+        match self.slots.lock().get(slot) {
+            None => Err(CapabilityError::InvalidSlot(slot)),
+            Some(None) => Err(CapabilityError::EmptySlot(slot)),
+            Some(Some(capability)) => Ok(capability.clone()),
+        }
+    }
+
+    /// Returns the lowest free slot.
+    pub fn free_slot(&self) -> Option<usize> {
+        self.slots.lock().iter().position(|slot| slot.is_none())
+    }
+
+    /// Stores a new capability to the object in the slot.
+    ///
+    /// The capability is not derived from any other capability, so this should only be used for new objects.
+    pub fn insert(
+        cspace: &Arc<CSpace>,
+        slot: usize,
+        object: KernelObject,
+        rights: Rights,
+    ) -> Result<(), CapabilityError> {
+        let mut tree = DERIVATION_TREE.lock();
+        let mut slots = cspace.slots.lock();
+
+        check_free(&slots, slot)?;
+
+        let id = tree.insert(None, Arc::downgrade(cspace), slot);
+        slots[slot] = Some(Capability {
+            id,
+            object,
+            rights,
+            badge: 0,
+        });
+
+        Ok(())
+    }
+
+    /// Copies the capability in `source_slot` to `destination_slot` in `destination`.
+    ///
+    /// The copy only has the rights contained in both the source and `rights`.
+    pub fn copy(
+        &self,
+        source_slot: usize,
+        destination: &Arc<CSpace>,
+        destination_slot: usize,
+        rights: Rights,
+    ) -> Result<(), CapabilityError> {
+        self.derive(source_slot, destination, destination_slot, rights, None)
+    }
+
+    /// Copies the capability in `source_slot` to `destination_slot` in `destination` and badges the copy.
+    ///
+    /// Only unbadged capabilities to badgeable objects can be minted.
+    pub fn mint(
+        &self,
+        source_slot: usize,
+        destination: &Arc<CSpace>,
+        destination_slot: usize,
+        rights: Rights,
+        badge: u64,
+    ) -> Result<(), CapabilityError> {
+        if badge == 0 {
+            return Err(CapabilityError::InvalidBadge);
+        }
+
+        self.derive(
+            source_slot,
+            destination,
+            destination_slot,
+            rights,
+            Some(badge),
+        )
+    }
+
+    /// Derives a new capability from the one in `source_slot`.
+    fn derive(
+        &self,
+        source_slot: usize,
+        destination: &Arc<CSpace>,
+        destination_slot: usize,
+        rights: Rights,
+        badge: Option<u64>,
+    ) -> Result<(), CapabilityError> {
+        let mut tree = DERIVATION_TREE.lock();
+        let source = self.lookup(source_slot)?;
+
+        let badge = match badge {
+            Some(badge) => {
+                if !source.object.is_badgeable() {
+                    return Err(CapabilityError::NotBadgeable);
+                }
+                if source.badge != 0 {
+                    return Err(CapabilityError::AlreadyBadged);
+                }
+
+                badge
+            }
+            None => source.badge,
+        };
+
+        let mut slots = destination.slots.lock();
+
+        check_free(&slots, destination_slot)?;
+
+        let id = tree.insert(
+            Some(source.id),
+            Arc::downgrade(destination),
+            destination_slot,
+        );
+        slots[destination_slot] = Some(Capability {
+            id,
+            object: source.object,
+            rights: source.rights & rights,
+            badge,
+        });
+
+        Ok(())
+    }
+
+    /// Moves the capability in `source_slot` to `destination_slot` in `destination`.
+    ///
+    /// The capability keeps its place in the derivation tree.
+    pub fn move_capability(
+        &self,
+        source_slot: usize,
+        destination: &Arc<CSpace>,
+        destination_slot: usize,
+    ) -> Result<(), CapabilityError> {
+        let mut tree = DERIVATION_TREE.lock();
+
+        check_free(&destination.slots.lock(), destination_slot)?;
+
+        let capability = self.take(source_slot)?;
+
+        tree.relocate(capability.id, Arc::downgrade(destination), destination_slot);
+        destination.slots.lock()[destination_slot] = Some(capability);
+
+        Ok(())
+    }
+
+    /// Deletes the capability in the slot.
+    ///
+    /// Capabilities derived from it are kept and now count as derived from its parent.
+    pub fn delete(&self, slot: usize) -> Result<(), CapabilityError> {
+        let capability = {
+            let mut tree = DERIVATION_TREE.lock();
+            let capability = self.take(slot)?;
+
+            tree.remove(capability.id);
+
+            capability
+        };
+
+        // The object may be freed here, now that the locks are released.
+        drop(capability);
+
+        Ok(())
+    }
+
+    /// Deletes all capabilities that were derived from the capability in the slot.
+    ///
+    /// The capability itself is kept.
+    pub fn revoke(&self, slot: usize) -> Result<(), CapabilityError> {
+        let mut removed_capabilities = Vec::new();
+        let mut cspaces = Vec::new();
+
+        {
+            let mut tree = DERIVATION_TREE.lock();
+            let capability = self.lookup(slot)?;
+
+            for descendant in tree.descendants(capability.id) {
+                if let Some((cspace, slot)) = tree.location(descendant) {
+                    if let Some(cspace) = cspace.upgrade() {
+                        {
+                            let mut slots = cspace.slots.lock();
+
+                            if slots[slot].as_ref().map(|capability| capability.id)
+                                == Some(descendant)
+                            {
+                                removed_capabilities.push(slots[slot].take());
+                            }
+                        }
+
+                        cspaces.push(cspace);
+                    }
+                }
+
+                tree.remove(descendant);
+            }
+        }
+
+        // The objects and capability spaces may be freed here, now that the locks are released.
+        drop(removed_capabilities);
+        drop(cspaces);
+
+        Ok(())
+    }
+
+    /// Deletes all capabilities in the capability space.
+    ///
+    /// Capabilities derived from them are kept and now count as derived from their parents.
+    pub fn clear(&self) {
+        let capabilities = {
+            let mut tree = DERIVATION_TREE.lock();
+            let mut slots = self.slots.lock();
+            let capabilities: Vec<Capability> = slots.iter_mut().filter_map(Option::take).collect();
+
+            for capability in &capabilities {
+                tree.remove(capability.id);
+            }
+
+            capabilities
+        };
+
+        // The objects may be freed here, now that the locks are released.
+        drop(capabilities);
+    }
+
+    /// Removes the capability from the slot without updating the derivation tree.
+    fn take(&self, slot: usize) -> Result<Capability, CapabilityError> {
+        match self.slots.lock().get_mut(slot) {
+            None => Err(CapabilityError::InvalidSlot(slot)),
+            Some(capability) => capability.take().ok_or(CapabilityError::EmptySlot(slot)),
+        }
+    }
+}
+
+impl Drop for CSpace {
+    fn drop(&mut self) {
+        let mut tree = DERIVATION_TREE.lock();
+
+        for capability in self.slots.lock().iter().flatten() {
+            tree.remove(capability.id);
+        }
+
+        // The capabilities themselves are dropped after the lock is released.
+    }
+}
@@ -0,0 +1,126 @@
+//! Keeps track of how capabilities were derived from each other.
+//!
+//! Every capability is a node in the derivation tree.
+//! Capabilities that were created for a new object are roots, copies and mints are children of their source.
+
+use alloc::{collections::BTreeMap, sync::Weak, vec::Vec};
+
+use super::{CSpace, CapabilityId};
+
+/// A capability in the derivation tree.
+struct Node {
+    /// The capability this capability was derived from.
+    parent: Option<CapabilityId>,
+    /// The capabilities that were derived from this capability.
+    children: Vec<CapabilityId>,
+    /// The capability space that holds the capability.
+    cspace: Weak<CSpace>,
+    /// The slot that holds the capability.
+    slot: usize,
+}
+
+/// The derivation tree of all capabilities.
+pub struct DerivationTree {
+    /// The nodes of the tree.
+    nodes: BTreeMap<CapabilityId, Node>,
+    /// The identifier that is given to the next capability.
+    next_id: usize,
+}
+
+impl DerivationTree {
+    /// Creates an empty derivation tree.
+    pub fn new() -> DerivationTree {
+        DerivationTree {
+            nodes: BTreeMap::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Adds a new capability stored at `slot` in `cspace` that was derived from `parent`.
+    pub fn insert(
+        &mut self,
+        parent: Option<CapabilityId>,
+        cspace: Weak<CSpace>,
+        slot: usize,
+    ) -> CapabilityId {
+        let id = CapabilityId(self.next_id);
+        self.next_id += 1;
+
+        if let Some(parent) = parent {
+            self.nodes
+                .get_mut(&parent)
+                .expect("The parent capability is not in the derivation tree.")
+                .children
+                .push(id);
+        }
+
+        self.nodes.insert(
+            id,
+            Node {
+                parent,
+                children: Vec::new(),
+                cspace,
+                slot,
+            },
+        );
+
+        id
+    }
+
+    /// Records that the capability was moved to `slot` in `cspace`.
+    pub fn relocate(&mut self, id: CapabilityId, cspace: Weak<CSpace>, slot: usize) {
+        let node = self
+            .nodes
+            .get_mut(&id)
+            .expect("The capability is not in the derivation tree.");
+
+        node.cspace = cspace;
+        node.slot = slot;
+    }
+
+    /// Removes the capability from the tree.
+    ///
+    /// The capabilities derived from it are attached to its parent.
+    pub fn remove(&mut self, id: CapabilityId) {
+        let node = match self.nodes.remove(&id) {
+            Some(node) => node,
+            None => return,
+        };
+
+        for child in &node.children {
+            if let Some(child) = self.nodes.get_mut(child) {
+                child.parent = node.parent;
+            }
+        }
+
+        if let Some(parent) = node.parent.and_then(|parent| self.nodes.get_mut(&parent)) {
+            parent.children.retain(|&child| child != id);
+            parent.children.extend_from_slice(&node.children);
+        }
+    }
+
+    /// Returns all capabilities that were derived from the capability, directly or indirectly.
+    pub fn descendants(&self, id: CapabilityId) -> Vec<CapabilityId> {
+        let mut descendants = Vec::new();
+        let mut pending = match self.nodes.get(&id) {
+            Some(node) => node.children.clone(),
+            None => Vec::new(),
+        };
+
+        while let Some(descendant) = pending.pop() {
+            if let Some(node) = self.nodes.get(&descendant) {
+                pending.extend_from_slice(&node.children);
+            }
+            descendants.push(descendant);
+        }
+
+        descendants
+    }
+
+    /// Returns where the capability is stored.
+    pub fn location(&self, id: CapabilityId) -> Option<(Weak<CSpace>, usize)> {
+        self.nodes
+            .get(&id)
+            .map(|node| (node.cspace.clone(), node.slot))
+    }
+}
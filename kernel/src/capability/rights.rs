@@ -0,0 +1,37 @@
+//! Defines the rights a capability grants on its object.
+
+use bitflags::bitflags;
+
+use crate::memory::PageFlags;
+
+bitflags! {
+    /// The operations a capability permits on its object.
+    ///
+    /// Derived capabilities can only have a subset of the rights of their parent.
+    pub struct Rights: u8 {
+        /// The object can be read from, for example by receiving messages or mapping memory readable.
+        const READ = 1 << 0;
+        /// The object can be written to, for example by sending messages or mapping memory writable.
+        const WRITE = 1 << 1;
+        /// The object can be executed, for example by mapping memory executable.
+        const EXECUTE = 1 << 2;
+        /// Capabilities can be transferred through the object.
+        const GRANT = 1 << 3;
+    }
+}
+
+impl Rights {
+    /// Returns the rights needed to map memory with the given page flags.
+    pub fn to_map(flags: PageFlags) -> Rights {
+        let mut rights = Rights::READ;
+
+        if flags.contains(PageFlags::WRITABLE) {
+            rights |= Rights::WRITE;
+        }
+        if flags.contains(PageFlags::EXECUTABLE) {
+            rights |= Rights::EXECUTE;
+        }
+
+        rights
+    }
+}
@@ -0,0 +1,210 @@
+//! Parses the kernel command line, which configures the kernel at boot time.
+//!
+//! The command line consists of options separated by whitespace, which are either a name or a `name=value` pair.
+//! The following options are understood:
+//!
+//! - `loglevel=<directives>` takes comma separated directives in the style of `env_logger`.
+//!   A directive is either a level, which applies to all modules, or `<module path>=<level>`,
+//!   which applies to the module and its submodules.
+//! - `logprefix=<names>` takes the comma separated information shown before log messages,
+//!   which can be `timestamp`, `cpu` and `location`.
+//! - `init=<path>` names the first user space program in the initial ramdisk.
+//! - `console=<names>` takes the comma separated names of the consoles the kernel writes to.
+//!   The consoles are `uefi`, `framebuffer`, `serial`, `debugcon` and `memory`.
+//! - `serial=<baud rate>[,<data bits><parity><stop bits>]` sets the line settings of the first serial port,
+//!   for example `serial=9600,7e1`. The parity is `n`, `o`, `e`, `m` or `s`.
+//! - `keymap=<name>` sets the keyboard layout, which is `us` or `de`.
+//!
+//! If an option is given more than once, its last value is used.
+
+use core::str::{FromStr, Split};
+use log::LevelFilter;
+
+use crate::{
+    console,
+    log_filter::{self, Prefixes},
+    sync::GlobalRuntimeConfiguration,
+};
+
+/// The configuration parsed from the command line the kernel was booted with.
+static CONFIGURATION: GlobalRuntimeConfiguration<Configuration> = GlobalRuntimeConfiguration::new();
+
+/// The boot time configuration of the kernel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Configuration {
+    /// The command line the configuration was parsed from.
+    command_line: &'static str,
+    /// The log level of the modules without their own directive.
+    log_level: LevelFilter,
+    /// The log directives given on the command line.
+    log_directives: &'static str,
+    /// The information shown before log messages.
+    log_prefixes: Prefixes,
+    /// The path of the first user space program.
+    init: Option<&'static str>,
+    /// The names of the consoles that are used, if they are restricted.
+    consoles: Option<&'static str>,
+    /// The line settings of the first serial port, if they were given.
+    serial: Option<&'static str>,
+    /// The name of the keyboard layout, if it was given.
+    keymap: Option<&'static str>,
+}
+
+impl Configuration {
+    /// Parses the configuration from the command line.
+    ///
+    /// Options that cannot be parsed are ignored with a warning.
+    pub fn parse(command_line: &'static str) -> Configuration {
+        let mut configuration = Configuration {
+            command_line,
+            log_level: crate::LOG_LEVEL,
+            log_directives: "",
+            log_prefixes: Prefixes::empty(),
+            init: None,
+            consoles: None,
+            serial: None,
+            keymap: None,
+        };
+
+        for option in command_line.split_whitespace() {
+            let (name, value) = match option.find('=') {
+                Some(index) => (&option[..index], Some(&option[index + 1..])),
+                None => (option, None),
+            };
+
+            match (name, value) {
+                ("loglevel", Some(directives)) => configuration.parse_log_directives(directives),
+                ("logprefix", Some(names)) => match Prefixes::from_str(names) {
+                    Ok(prefixes) => configuration.log_prefixes = prefixes,
+                    Err(_) => log::warn!("Ignoring the invalid log prefixes \"{}\".", names),
+                },
+                ("init", Some(path)) if !path.is_empty() => configuration.init = Some(path),
+                ("console", Some(names)) => configuration.consoles = Some(names),
+                ("serial", Some(settings)) => configuration.serial = Some(settings),
+                ("keymap", Some(name)) => configuration.keymap = Some(name),
+                _ => log::warn!("Ignoring the unknown kernel option \"{}\".", option),
+            }
+        }
+
+        configuration
+    }
+
+    /// Parses the log directives and warns about invalid ones.
+    fn parse_log_directives(&mut self, directives: &'static str) {
+        self.log_level = crate::LOG_LEVEL;
+        self.log_directives = directives;
+
+        for directive in directives.split(',') {
+            match log_filter::parse_directive(directive) {
+                Some((None, level)) => self.log_level = level,
+                Some((Some(_), _)) => (),
+                None => log::warn!("Ignoring the invalid log directive \"{}\".", directive),
+            }
+        }
+    }
+
+    /// Returns the command line the configuration was parsed from.
+    pub fn command_line(&self) -> &'static str {
+        self.command_line
+    }
+
+    /// Returns the module paths with their own log level.
+    pub fn log_filters(&self) -> LogFilters {
+        LogFilters {
+            directives: self.log_directives.split(','),
+        }
+    }
+
+    /// Returns the log level of the modules without their own directive.
+    pub fn default_log_level(&self) -> LevelFilter {
+        self.log_level
+    }
+
+    /// Returns the log level of the module with the given path.
+    ///
+    /// The most specific directive for the module or one of its parent modules is used.
+    pub fn log_level(&self, module_path: &str) -> LevelFilter {
+        self.log_filters()
+            .filter(|(module, _)| log_filter::module_matches(module_path, module))
+            .max_by_key(|(module, _)| module.len())
+            .map_or(self.log_level, |(_, level)| level)
+    }
+
+    /// Returns the information shown before log messages.
+    pub fn log_prefixes(&self) -> Prefixes {
+        self.log_prefixes
+    }
+
+    /// Returns the most verbose log level of all modules.
+    pub fn max_log_level(&self) -> LevelFilter {
+        self.log_filters()
+            .map(|(_, level)| level)
+            .fold(self.log_level, |maximum, level| maximum.max(level))
+    }
+
+    /// Returns the path of the first user space program, if one was given.
+    pub fn init(&self) -> Option<&'static str> {
+        self.init
+    }
+
+    /// Checks if the kernel should write to the console with the given name.
+    ///
+    /// All consoles are used unless the command line names some.
+    pub fn console_enabled(&self, name: &str) -> bool {
+        self.consoles.map_or(true, |consoles| {
+            consoles.split(',').any(|console| console == name)
+        })
+    }
+
+    /// Returns the line settings of the first serial port, if they were given.
+    ///
+    /// The settings are interpreted by the serial driver.
+    pub fn serial_settings(&self) -> Option<&'static str> {
+        self.serial
+    }
+
+    /// Returns the name of the keyboard layout, if it was given.
+    ///
+    /// The name is interpreted by the keyboard layer of the consoles.
+    pub fn keymap(&self) -> Option<&'static str> {
+        self.keymap
+    }
+}
+
+/// An iterator over the module paths with their own log level.
+pub struct LogFilters {
+    /// The remaining log directives.
+    directives: Split<'static, char>,
+}
+
+impl Iterator for LogFilters {
+    type Item = (&'static str, LevelFilter);
+
+    fn next(&mut self) -> Option<(&'static str, LevelFilter)> {
+        loop {
+            if let Some((Some(module), level)) =
+                log_filter::parse_directive(self.directives.next()?)
+            {
+                return Some((module, level));
+            }
+        }
+    }
+}
+
+/// Parses the command line and applies the configuration.
+///
+/// This should be called as early as possible, right after the logger was set up.
+pub fn init(command_line: &'static str) {
+    let configuration = Configuration::parse(command_line);
+
+    log_filter::configure(&configuration);
+    console::configure(&configuration);
+    log::debug!("The kernel command line is \"{}\".", command_line.trim());
+
+    CONFIGURATION.init(configuration);
+}
+
+/// Returns the configuration of the kernel, if the command line was parsed.
+pub fn get() -> Option<&'static Configuration> {
+    CONFIGURATION.get()
+}
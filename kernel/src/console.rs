@@ -0,0 +1,340 @@
+//! Provides the consoles that the kernel writes its output to.
+//!
+//! Consoles register a sink under a unique name, which receives the output of `print!`
+//! and the log messages of the kernel.
+//! Every sink has its own log level and can be disabled, so that for example the screen only shows warnings,
+//! while the serial port receives everything.
+//! The sinks that are used at boot time can be restricted with the `console` option on the command line.
+//!
+//! Input from the consoles, like the characters received on a serial port or typed on a keyboard,
+//! is collected into lines by a single line discipline, which echoes the edits to all sinks.
+
+pub mod ansi;
+mod font;
+pub mod framebuffer;
+pub mod keyboard;
+pub mod line_discipline;
+pub mod memory;
+
+use alloc::vec::Vec;
+use core::fmt;
+use log::{Level, LevelFilter};
+
+use crate::{
+    cmdline::{self, Configuration},
+    console::line_discipline::{Edit, Line, LineDiscipline},
+    log_buffer::{self, Record},
+    log_filter::{self, Prefixes},
+    sync::Mutex,
+};
+
+/// The maximum number of sinks that can be registered at the same time.
+///
+/// The registry has a fixed size, because the first sinks are registered before the heap is set up.
+const MAXIMUM_SINK_COUNT: usize = 8;
+
+/// The registered sinks.
+static SINKS: Mutex<[Option<Registration>; MAXIMUM_SINK_COUNT]> =
+    Mutex::new([None; MAXIMUM_SINK_COUNT]);
+
+/// The line discipline that collects the input of all consoles.
+static INPUT: Mutex<LineDiscipline> = Mutex::new(LineDiscipline::new());
+
+/// A destination for the output of the kernel.
+///
+/// Sinks synchronize themselves, so that they can be written to from anywhere.
+pub trait Sink: Sync {
+    /// Writes the formatted string to the sink.
+    ///
+    /// Lines are ended with a line feed alone.
+    fn write_fmt(&self, args: fmt::Arguments);
+}
+
+/// A sink registered with the console registry.
+#[derive(Clone, Copy)]
+pub struct Registration {
+    /// The unique name of the sink.
+    name: &'static str,
+    /// The sink that receives the output.
+    sink: &'static dyn Sink,
+    /// The most verbose log level that the sink receives.
+    level: LevelFilter,
+    /// Whether the sink receives any output.
+    enabled: bool,
+}
+
+impl Registration {
+    /// Returns the unique name of the sink.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns the most verbose log level that the sink receives.
+    pub fn level(&self) -> LevelFilter {
+        self.level
+    }
+
+    /// Checks if the sink receives any output.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Checks if the sink receives output of the given level.
+    ///
+    /// Output without a level, like the output of `print!`, is received by enabled sinks unless their level is `Off`.
+    fn receives(&self, level: Option<Level>) -> bool {
+        self.enabled && level.map_or(self.level != LevelFilter::Off, |level| level <= self.level)
+    }
+}
+
+/// A log message as it is shown on the consoles.
+struct LogLine<'a> {
+    /// The record of the message.
+    record: &'a Record,
+    /// The message, which may be longer than the one kept in the record.
+    message: fmt::Arguments<'a>,
+}
+
+impl<'a> fmt::Display for LogLine<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The colors to use for terminal output.
+        let reset = "\x1b[0m";
+        let error = "\x1b[31m"; // Red
+        let warn = "\x1b[33m"; // Yellow
+        let debug = ""; // Default
+        let trace = "\x1b[90m"; // Dark gray
+        let prefix = "\x1b[90m"; // Dark gray
+
+        let prefixes = log_filter::prefixes();
+
+        if prefixes.contains(Prefixes::TIMESTAMP) {
+            let nanoseconds = self.record.timestamp().as_nanoseconds();
+
+            write!(
+                f,
+                "{}[{:5}.{:06}]{} ",
+                prefix,
+                nanoseconds / 1_000_000_000,
+                nanoseconds % 1_000_000_000 / 1000,
+                reset
+            )?;
+        }
+
+        if prefixes.contains(Prefixes::CPU) {
+            write!(f, "{}CPU{}{} ", prefix, self.record.cpu(), reset)?;
+        }
+
+        if prefixes.contains(Prefixes::LOCATION) {
+            write!(
+                f,
+                "{}{}:{}{} ",
+                prefix,
+                self.record.file(),
+                self.record.line(),
+                reset
+            )?;
+        }
+
+        let color = match self.record.level() {
+            Level::Error => error,
+            Level::Warn => warn,
+            // Informational messages are shown without a prefix.
+            Level::Info => return writeln!(f, "{}", self.message),
+            Level::Debug => debug,
+            Level::Trace => trace,
+        };
+
+        writeln!(
+            f,
+            "{}{}{}: {}",
+            color,
+            self.record.level(),
+            reset,
+            self.message
+        )
+    }
+}
+
+/// Registers the sink under the given name, replacing a sink with the same name.
+///
+/// The sink receives log messages up to the given level.
+/// It is disabled if the command line restricts the consoles and doesn't name it.
+/// The log records kept in memory are replayed to the sink, so that it shows the messages from before it came online.
+pub fn register(name: &'static str, sink: &'static dyn Sink, level: LevelFilter) {
+    let registration = Registration {
+        name,
+        sink,
+        level,
+        enabled: cmdline::get().map_or(true, |configuration| configuration.console_enabled(name)),
+    };
+
+    let mut sinks = SINKS.lock();
+
+    let slot = match sinks
+        .iter()
+        .position(|slot| slot.map_or(false, |registered| registered.name == name))
+    {
+        Some(index) => Some(index),
+        None => sinks.iter().position(|slot| slot.is_none()),
+    };
+
+    match slot {
+        Some(index) => sinks[index] = Some(registration),
+        None => {
+            drop(sinks);
+
+            log::warn!("Could not register the console \"{}\".", name);
+
+            return;
+        }
+    }
+
+    drop(sinks);
+
+    for record in log_buffer::records() {
+        if registration.receives(Some(record.level())) {
+            sink.write_fmt(format_args!(
+                "{}",
+                LogLine {
+                    record: &record,
+                    message: format_args!("{}", record.message()),
+                }
+            ));
+        }
+    }
+}
+
+/// Removes the sink with the given name.
+///
+/// Returns `false` if no such sink was registered.
+pub fn unregister(name: &str) -> bool {
+    modify(name, |slot| *slot = None)
+}
+
+/// Enables or disables the sink with the given name.
+///
+/// Returns `false` if no such sink was registered.
+pub fn set_enabled(name: &str, enabled: bool) -> bool {
+    modify(name, |slot| {
+        if let Some(registration) = slot {
+            registration.enabled = enabled;
+        }
+    })
+}
+
+/// Sets the most verbose log level that the sink with the given name receives.
+///
+/// Returns `false` if no such sink was registered.
+pub fn set_level(name: &str, level: LevelFilter) -> bool {
+    modify(name, |slot| {
+        if let Some(registration) = slot {
+            registration.level = level;
+        }
+    })
+}
+
+/// Calls the function with the slot of the sink with the given name.
+///
+/// Returns `false` if no such sink was registered.
+fn modify<F: FnOnce(&mut Option<Registration>)>(name: &str, function: F) -> bool {
+    let mut sinks = SINKS.lock();
+
+    match sinks
+        .iter_mut()
+        .find(|slot| slot.map_or(false, |registration| registration.name == name))
+    {
+        Some(slot) => {
+            function(slot);
+
+            true
+        }
+        None => false,
+    }
+}
+
+/// Returns the registered sinks.
+pub fn sinks() -> Vec<Registration> {
+    SINKS.lock().iter().filter_map(|slot| *slot).collect()
+}
+
+/// Disables the sinks that are not named on the command line and applies the keyboard layout given there.
+pub fn configure(configuration: &Configuration) {
+    for slot in SINKS.lock().iter_mut() {
+        if let Some(registration) = slot {
+            registration.enabled = configuration.console_enabled(registration.name);
+        }
+    }
+
+    keyboard::configure(configuration);
+}
+
+/// Writes the log message of the record to all sinks that receive its level.
+///
+/// Levels other than `Info` are shown as a colored prefix, preceded by the prefixes selected in the log filter.
+pub fn write_log(record: &Record, message: fmt::Arguments) {
+    write_fmt(
+        Some(record.level()),
+        format_args!("{}", LogLine { record, message }),
+    );
+}
+
+/// Writes the formatted string to all sinks that receive output of the given level.
+///
+/// Output without a level is written to all enabled sinks.
+pub fn write_fmt(level: Option<Level>, args: fmt::Arguments) {
+    // The sinks are written to without holding the lock, so that they can use the registry while writing.
+    let sinks = *SINKS.lock();
+
+    for registration in sinks.iter().filter_map(|slot| slot.as_ref()) {
+        if registration.receives(level) {
+            registration.sink.write_fmt(args);
+        }
+    }
+}
+
+/// Passes a byte of UTF-8 encoded input to the line discipline of the consoles.
+///
+/// The resulting edit is echoed to all sinks, unless echoing is disabled.
+pub fn receive_byte(byte: u8) {
+    let (edit, echo) = {
+        let mut input = INPUT.lock();
+
+        (input.receive_byte(byte), input.echo())
+    };
+
+    if echo {
+        echo_edit(edit);
+    }
+}
+
+/// Passes a character to the line discipline of the consoles.
+///
+/// The resulting edit is echoed to all sinks, unless echoing is disabled.
+pub fn receive(character: char) {
+    let (edit, echo) = {
+        let mut input = INPUT.lock();
+
+        (input.receive(character), input.echo())
+    };
+
+    if echo {
+        echo_edit(edit);
+    }
+}
+
+/// Shows the edit of the input line on all sinks.
+fn echo_edit(edit: Option<Edit>) {
+    if let Some(edit) = edit {
+        write_fmt(None, format_args!("{}", edit));
+    }
+}
+
+/// Returns the oldest line that was entered on the consoles and not read yet.
+pub fn read_line() -> Option<Line> {
+    INPUT.lock().read_line()
+}
+
+/// Sets whether the input is echoed to the sinks.
+pub fn set_echo(echo: bool) {
+    INPUT.lock().set_echo(echo);
+}
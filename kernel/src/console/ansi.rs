@@ -0,0 +1,152 @@
+//! Provides a parser for ANSI escape sequences, as understood by VT100 compatible terminals.
+//!
+//! The parser splits a stream of characters into characters to print and the escape sequences between them.
+//! It doesn't interpret the sequences, which is left to the consoles that understand them.
+//! Consoles that don't understand them can use the parser to filter them out.
+
+/// The escape character that starts every escape sequence.
+const ESCAPE: char = '\x1b';
+
+/// The maximum number of parameters of a control sequence.
+///
+/// Further parameters are ignored.
+const MAXIMUM_PARAMETER_COUNT: usize = 16;
+
+/// A part of the character stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// A character to print or a control character like a line feed.
+    Character(char),
+    /// An escape sequence consisting of the escape character and the given character.
+    Escape(char),
+    /// A control sequence introduced by `ESC [`.
+    ControlSequence(ControlSequence),
+}
+
+/// A control sequence, which consists of numeric parameters and a final character that selects the function.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ControlSequence {
+    /// The character that selects the function.
+    function: char,
+    /// The parameters, which are zero if they were omitted.
+    parameters: [usize; MAXIMUM_PARAMETER_COUNT],
+    /// The number of parameters.
+    count: usize,
+    /// Whether the sequence contained characters that are not supported, like private markers.
+    private: bool,
+}
+
+impl ControlSequence {
+    /// Returns the character that selects the function.
+    pub fn function(&self) -> char {
+        self.function
+    }
+
+    /// Returns the parameters, which are zero if they were omitted.
+    pub fn parameters(&self) -> &[usize] {
+        &self.parameters[..self.count]
+    }
+
+    /// Returns the parameter at the index, or the default if it is zero or was omitted.
+    pub fn parameter(&self, index: usize, default: usize) -> usize {
+        match self.parameters().get(index) {
+            Some(&parameter) if parameter != 0 => parameter,
+            _ => default,
+        }
+    }
+
+    /// Checks if the sequence contained private markers or intermediate characters.
+    ///
+    /// Such sequences have a meaning specific to some terminals.
+    pub fn is_private(&self) -> bool {
+        self.private
+    }
+}
+
+/// The states of the parser.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    /// Characters are printed.
+    Ground,
+    /// An escape character was received.
+    Escape,
+    /// A control sequence is received.
+    ControlSequence(ControlSequence),
+}
+
+/// Splits a stream of characters into characters and escape sequences.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Parser {
+    /// The current state of the parser.
+    state: State,
+}
+
+impl Parser {
+    /// Creates a parser that expects characters to print.
+    pub const fn new() -> Parser {
+        Parser {
+            state: State::Ground,
+        }
+    }
+
+    /// Feeds the character to the parser.
+    ///
+    /// Returns the action, if the character completed one.
+    pub fn advance(&mut self, character: char) -> Option<Action> {
+        let (state, action) = match (self.state, character) {
+            // An escape character aborts any sequence and starts a new one.
+            (_, ESCAPE) => (State::Escape, None),
+            (State::Ground, _) => (State::Ground, Some(Action::Character(character))),
+            (State::Escape, '[') => (
+                State::ControlSequence(ControlSequence {
+                    function: '\0',
+                    parameters: [0; MAXIMUM_PARAMETER_COUNT],
+                    count: 0,
+                    private: false,
+                }),
+                None,
+            ),
+            (State::Escape, _) => (State::Ground, Some(Action::Escape(character))),
+            (State::ControlSequence(mut sequence), '0'..='9') => {
+                sequence.count = sequence.count.max(1);
+
+                if let Some(parameter) = sequence.parameters.get_mut(sequence.count - 1) {
+                    *parameter = parameter
+                        .saturating_mul(10)
+                        .saturating_add(character as usize - '0' as usize);
+                }
+
+                (State::ControlSequence(sequence), None)
+            }
+            (State::ControlSequence(mut sequence), ';') => {
+                sequence.count = (sequence.count.max(1) + 1).min(MAXIMUM_PARAMETER_COUNT);
+
+                (State::ControlSequence(sequence), None)
+            }
+            (State::ControlSequence(mut sequence), '@'..='~') => {
+                sequence.function = character;
+
+                (State::Ground, Some(Action::ControlSequence(sequence)))
+            }
+            // Control characters are executed in the middle of a sequence.
+            (State::ControlSequence(_), _) if character.is_control() => {
+                (self.state, Some(Action::Character(character)))
+            }
+            (State::ControlSequence(mut sequence), _) => {
+                sequence.private = true;
+
+                (State::ControlSequence(sequence), None)
+            }
+        };
+
+        self.state = state;
+
+        action
+    }
+}
+
+impl Default for Parser {
+    fn default() -> Parser {
+        Parser::new()
+    }
+}
@@ -0,0 +1,1852 @@
+//! Provides the built-in bitmap font of the framebuffer console.
+//!
+//! The glyphs cover the printable ASCII characters and were rasterized from DejaVu Sans Mono.
+//! Each glyph is eight pixels wide and sixteen pixels high, with one byte per row and the most significant bit on the left.
+
+/// The width of a glyph in pixels.
+pub const GLYPH_WIDTH: usize = 8;
+
+/// The height of a glyph in pixels.
+pub const GLYPH_HEIGHT: usize = 16;
+
+/// The first character that has a glyph.
+const FIRST_CHARACTER: char = ' ';
+
+/// The glyph drawn for characters that the font doesn't cover.
+#[rustfmt::skip]
+const REPLACEMENT_GLYPH: [u8; GLYPH_HEIGHT] = [
+    0b00000000,
+    0b00000000,
+    0b00000000,
+    0b01111110,
+    0b01000010,
+    0b01000010,
+    0b01000010,
+    0b01000010,
+    0b01000010,
+    0b01000010,
+    0b01000010,
+    0b01111110,
+    0b00000000,
+    0b00000000,
+    0b00000000,
+    0b00000000,
+];
+
+/// The glyphs of the printable ASCII characters, starting with the space.
+#[rustfmt::skip]
+const GLYPHS: [[u8; GLYPH_HEIGHT]; 95] = [
+    // ' '
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '!'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00000000,
+        0b00010000,
+        0b00010000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '"'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00101000,
+        0b00101000,
+        0b00101000,
+        0b00101000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '#'
+    [
+        0b00000000,
+        0b00000000,
+        0b00010010,
+        0b00010010,
+        0b00010110,
+        0b01111111,
+        0b00100100,
+        0b00100100,
+        0b11111110,
+        0b00101000,
+        0b01001000,
+        0b01001000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '$'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00001000,
+        0b00111110,
+        0b01001001,
+        0b01001000,
+        0b00111000,
+        0b00001110,
+        0b00001001,
+        0b01001001,
+        0b00111110,
+        0b00001000,
+        0b00001000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '%'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01100000,
+        0b10010000,
+        0b10010000,
+        0b01100010,
+        0b00011100,
+        0b01100110,
+        0b00001001,
+        0b00001001,
+        0b00000110,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '&'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00011100,
+        0b00100000,
+        0b00100000,
+        0b00110000,
+        0b01001001,
+        0b01001101,
+        0b01000101,
+        0b01100010,
+        0b00111101,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '\''
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '('
+    [
+        0b00000000,
+        0b00001100,
+        0b00001000,
+        0b00001000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00001000,
+        0b00001000,
+        0b00000100,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // ')'
+    [
+        0b00000000,
+        0b00110000,
+        0b00010000,
+        0b00010000,
+        0b00001000,
+        0b00001000,
+        0b00001000,
+        0b00001000,
+        0b00001000,
+        0b00001000,
+        0b00010000,
+        0b00010000,
+        0b00110000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '*'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00001000,
+        0b01001001,
+        0b00111110,
+        0b00011100,
+        0b01101011,
+        0b00001000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '+'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b11111110,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // ','
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00011000,
+        0b00011000,
+        0b00010000,
+        0b00100000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '-'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00111000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '.'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00011000,
+        0b00011000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '/'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000010,
+        0b00000100,
+        0b00000100,
+        0b00001000,
+        0b00001000,
+        0b00011000,
+        0b00010000,
+        0b00010000,
+        0b00100000,
+        0b00100000,
+        0b01000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '0'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00011100,
+        0b00100010,
+        0b01000001,
+        0b01000001,
+        0b01001001,
+        0b01000001,
+        0b01000001,
+        0b00100010,
+        0b00011100,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '1'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00111000,
+        0b00001000,
+        0b00001000,
+        0b00001000,
+        0b00001000,
+        0b00001000,
+        0b00001000,
+        0b00001000,
+        0b00111110,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '2'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00111110,
+        0b01000011,
+        0b00000001,
+        0b00000001,
+        0b00000010,
+        0b00001100,
+        0b00011000,
+        0b00100000,
+        0b01111111,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '3'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00111110,
+        0b01000001,
+        0b00000001,
+        0b00000011,
+        0b00011100,
+        0b00000011,
+        0b00000001,
+        0b01000011,
+        0b00111110,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '4'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000110,
+        0b00001010,
+        0b00011010,
+        0b00010010,
+        0b00100010,
+        0b01000010,
+        0b01111111,
+        0b00000010,
+        0b00000010,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '5'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01111110,
+        0b01000000,
+        0b01000000,
+        0b01111100,
+        0b00000011,
+        0b00000001,
+        0b00000001,
+        0b01000011,
+        0b00111100,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '6'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00011110,
+        0b00100001,
+        0b01000000,
+        0b01011110,
+        0b01100011,
+        0b01000001,
+        0b01000001,
+        0b00100011,
+        0b00011110,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '7'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01111111,
+        0b00000010,
+        0b00000010,
+        0b00000100,
+        0b00000100,
+        0b00001000,
+        0b00011000,
+        0b00010000,
+        0b00100000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '8'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00111110,
+        0b01000001,
+        0b01000001,
+        0b01000001,
+        0b00111110,
+        0b01100011,
+        0b01000001,
+        0b01100001,
+        0b00111110,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '9'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00111100,
+        0b01100010,
+        0b01000001,
+        0b01000001,
+        0b01100011,
+        0b00111101,
+        0b00000001,
+        0b01000010,
+        0b00111100,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // ':'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00011000,
+        0b00011000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00011000,
+        0b00011000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // ';'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00011000,
+        0b00011000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00011000,
+        0b00011000,
+        0b00010000,
+        0b00100000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '<'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000001,
+        0b00001110,
+        0b01110000,
+        0b01110000,
+        0b00001110,
+        0b00000001,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '='
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01111111,
+        0b00000000,
+        0b00000000,
+        0b01111111,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '>'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01000000,
+        0b00111000,
+        0b00000111,
+        0b00000111,
+        0b00111000,
+        0b01000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '?'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00111000,
+        0b01000100,
+        0b00000100,
+        0b00001000,
+        0b00010000,
+        0b00010000,
+        0b00000000,
+        0b00010000,
+        0b00010000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '@'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00011110,
+        0b00110011,
+        0b00100001,
+        0b01000111,
+        0b01001001,
+        0b01001001,
+        0b01001001,
+        0b01000111,
+        0b00100000,
+        0b00110000,
+        0b00011110,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'A'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00001000,
+        0b00010100,
+        0b00010100,
+        0b00010100,
+        0b00100010,
+        0b00100010,
+        0b00111110,
+        0b01100011,
+        0b01000001,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'B'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01111110,
+        0b01000001,
+        0b01000001,
+        0b01000001,
+        0b01111110,
+        0b01000001,
+        0b01000001,
+        0b01000001,
+        0b01111110,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'C'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00011110,
+        0b00100001,
+        0b01000000,
+        0b01000000,
+        0b01000000,
+        0b01000000,
+        0b01000000,
+        0b00100001,
+        0b00011110,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'D'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01111100,
+        0b01000010,
+        0b01000001,
+        0b01000001,
+        0b01000001,
+        0b01000001,
+        0b01000001,
+        0b01000010,
+        0b01111100,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'E'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01111111,
+        0b01000000,
+        0b01000000,
+        0b01000000,
+        0b01111111,
+        0b01000000,
+        0b01000000,
+        0b01000000,
+        0b01111111,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'F'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01111111,
+        0b01000000,
+        0b01000000,
+        0b01000000,
+        0b01111111,
+        0b01000000,
+        0b01000000,
+        0b01000000,
+        0b01000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'G'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00011110,
+        0b00100001,
+        0b01000000,
+        0b01000000,
+        0b01000011,
+        0b01000001,
+        0b01000001,
+        0b00100001,
+        0b00011110,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'H'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01000001,
+        0b01000001,
+        0b01000001,
+        0b01000001,
+        0b01111111,
+        0b01000001,
+        0b01000001,
+        0b01000001,
+        0b01000001,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'I'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01111100,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b01111100,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'J'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00011100,
+        0b00000100,
+        0b00000100,
+        0b00000100,
+        0b00000100,
+        0b00000100,
+        0b00000100,
+        0b01000100,
+        0b00111000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'K'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01000010,
+        0b01000100,
+        0b01001000,
+        0b01010000,
+        0b01110000,
+        0b01001000,
+        0b01000100,
+        0b01000100,
+        0b01000010,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'L'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01000000,
+        0b01000000,
+        0b01000000,
+        0b01000000,
+        0b01000000,
+        0b01000000,
+        0b01000000,
+        0b01000000,
+        0b01111111,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'M'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01100011,
+        0b01100011,
+        0b01010101,
+        0b01010101,
+        0b01010101,
+        0b01001001,
+        0b01000001,
+        0b01000001,
+        0b01000001,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'N'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01100001,
+        0b01100001,
+        0b01010001,
+        0b01010001,
+        0b01001001,
+        0b01000101,
+        0b01000101,
+        0b01000011,
+        0b01000011,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'O'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00011100,
+        0b00100010,
+        0b01000001,
+        0b01000001,
+        0b01000001,
+        0b01000001,
+        0b01000001,
+        0b00100010,
+        0b00011100,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'P'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01111110,
+        0b01000011,
+        0b01000001,
+        0b01000001,
+        0b01000011,
+        0b01111110,
+        0b01000000,
+        0b01000000,
+        0b01000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'Q'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00011100,
+        0b00100010,
+        0b01000001,
+        0b01000001,
+        0b01000001,
+        0b01000001,
+        0b01000001,
+        0b00100011,
+        0b00011110,
+        0b00000110,
+        0b00000010,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'R'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01111110,
+        0b01000011,
+        0b01000001,
+        0b01000001,
+        0b01111110,
+        0b01000010,
+        0b01000001,
+        0b01000001,
+        0b01000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'S'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00111110,
+        0b01100001,
+        0b01000000,
+        0b01100000,
+        0b00111110,
+        0b00000011,
+        0b00000001,
+        0b01000011,
+        0b00111110,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'T'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b11111110,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'U'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01000001,
+        0b01000001,
+        0b01000001,
+        0b01000001,
+        0b01000001,
+        0b01000001,
+        0b01000001,
+        0b01000001,
+        0b00111110,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'V'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01000001,
+        0b01100011,
+        0b00100010,
+        0b00100010,
+        0b00100010,
+        0b00010100,
+        0b00010100,
+        0b00010100,
+        0b00001000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'W'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b10000001,
+        0b10000001,
+        0b10000001,
+        0b01011010,
+        0b01011010,
+        0b01011010,
+        0b01100110,
+        0b01100110,
+        0b01100110,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'X'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01100011,
+        0b00100010,
+        0b00010100,
+        0b00011100,
+        0b00001000,
+        0b00010100,
+        0b00110110,
+        0b00100010,
+        0b01000001,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'Y'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b10000010,
+        0b01000100,
+        0b00101000,
+        0b00101000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'Z'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01111111,
+        0b00000011,
+        0b00000110,
+        0b00000100,
+        0b00001000,
+        0b00010000,
+        0b00110000,
+        0b01100000,
+        0b01111111,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '['
+    [
+        0b00000000,
+        0b00011100,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00011100,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '\\'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01000000,
+        0b00100000,
+        0b00100000,
+        0b00010000,
+        0b00010000,
+        0b00011000,
+        0b00001000,
+        0b00001000,
+        0b00000100,
+        0b00000100,
+        0b00000010,
+        0b00000000,
+        0b00000000,
+    ],
+    // ']'
+    [
+        0b00000000,
+        0b00111000,
+        0b00001000,
+        0b00001000,
+        0b00001000,
+        0b00001000,
+        0b00001000,
+        0b00001000,
+        0b00001000,
+        0b00001000,
+        0b00001000,
+        0b00001000,
+        0b00111000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '^'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00010000,
+        0b00101000,
+        0b01000100,
+        0b11000110,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '_'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b11111111,
+        0b00000000,
+    ],
+    // '`'
+    [
+        0b00000000,
+        0b00000000,
+        0b00010000,
+        0b00001000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'a'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00011100,
+        0b00100010,
+        0b00000010,
+        0b00111110,
+        0b01000010,
+        0b01000110,
+        0b00111010,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'b'
+    [
+        0b00000000,
+        0b01000000,
+        0b01000000,
+        0b01000000,
+        0b01000000,
+        0b01111100,
+        0b01100110,
+        0b01000010,
+        0b01000010,
+        0b01000010,
+        0b01100110,
+        0b01111100,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'c'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00011100,
+        0b00100010,
+        0b01000000,
+        0b01000000,
+        0b01000000,
+        0b00100010,
+        0b00011100,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'd'
+    [
+        0b00000000,
+        0b00000010,
+        0b00000010,
+        0b00000010,
+        0b00000010,
+        0b00111110,
+        0b01100110,
+        0b01000010,
+        0b01000010,
+        0b01000010,
+        0b01100110,
+        0b00111110,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'e'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00111100,
+        0b01100110,
+        0b01000010,
+        0b01111110,
+        0b01000000,
+        0b01100010,
+        0b00111100,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'f'
+    [
+        0b00000000,
+        0b00001100,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b01111100,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'g'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00111110,
+        0b01100110,
+        0b01000010,
+        0b01000010,
+        0b01000010,
+        0b01100110,
+        0b00111010,
+        0b00000010,
+        0b00100010,
+        0b00011100,
+        0b00000000,
+    ],
+    // 'h'
+    [
+        0b00000000,
+        0b01000000,
+        0b01000000,
+        0b01000000,
+        0b01000000,
+        0b01011100,
+        0b01100010,
+        0b01000010,
+        0b01000010,
+        0b01000010,
+        0b01000010,
+        0b01000010,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'i'
+    [
+        0b00000000,
+        0b00010000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01110000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b01111100,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'j'
+    [
+        0b00000000,
+        0b00001000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00111000,
+        0b00001000,
+        0b00001000,
+        0b00001000,
+        0b00001000,
+        0b00001000,
+        0b00001000,
+        0b00001000,
+        0b00001000,
+        0b01110000,
+        0b00000000,
+    ],
+    // 'k'
+    [
+        0b00000000,
+        0b01000000,
+        0b01000000,
+        0b01000000,
+        0b01000000,
+        0b01000100,
+        0b01001000,
+        0b01010000,
+        0b01110000,
+        0b01001000,
+        0b01000100,
+        0b01000010,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'l'
+    [
+        0b00000000,
+        0b01110000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00001110,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'm'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01111111,
+        0b01001001,
+        0b01001001,
+        0b01001001,
+        0b01001001,
+        0b01001001,
+        0b01001001,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'n'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01011100,
+        0b01100010,
+        0b01000010,
+        0b01000010,
+        0b01000010,
+        0b01000010,
+        0b01000010,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'o'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00111100,
+        0b01100110,
+        0b01000010,
+        0b01000010,
+        0b01000010,
+        0b01100110,
+        0b00111100,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'p'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01111100,
+        0b01100110,
+        0b01000010,
+        0b01000010,
+        0b01000010,
+        0b01100110,
+        0b01111100,
+        0b01000000,
+        0b01000000,
+        0b01000000,
+        0b00000000,
+    ],
+    // 'q'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00111110,
+        0b01100110,
+        0b01000010,
+        0b01000010,
+        0b01000010,
+        0b01100110,
+        0b00111010,
+        0b00000010,
+        0b00000010,
+        0b00000010,
+        0b00000000,
+    ],
+    // 'r'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00111100,
+        0b00110010,
+        0b00100000,
+        0b00100000,
+        0b00100000,
+        0b00100000,
+        0b00100000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 's'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00111100,
+        0b01000010,
+        0b01000000,
+        0b00111100,
+        0b00000010,
+        0b01000010,
+        0b00111100,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 't'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00010000,
+        0b00010000,
+        0b01111110,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00001110,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'u'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01000010,
+        0b01000010,
+        0b01000010,
+        0b01000010,
+        0b01000010,
+        0b01000110,
+        0b00111010,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'v'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01000010,
+        0b01100110,
+        0b00100100,
+        0b00100100,
+        0b00111100,
+        0b00011000,
+        0b00011000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'w'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b10000001,
+        0b10000001,
+        0b01011010,
+        0b01011010,
+        0b01011010,
+        0b00100100,
+        0b00100100,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'x'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01100110,
+        0b00100100,
+        0b00011000,
+        0b00011000,
+        0b00011000,
+        0b00100100,
+        0b01100110,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // 'y'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01000010,
+        0b00100010,
+        0b00100100,
+        0b00100100,
+        0b00010100,
+        0b00011000,
+        0b00001000,
+        0b00001000,
+        0b00010000,
+        0b00110000,
+        0b00000000,
+    ],
+    // 'z'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b01111110,
+        0b00000010,
+        0b00000100,
+        0b00011000,
+        0b00100000,
+        0b01000000,
+        0b01111110,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '{'
+    [
+        0b00000000,
+        0b00011100,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b01100000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00001100,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '|'
+    [
+        0b00000000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '}'
+    [
+        0b00000000,
+        0b01110000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00001100,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b00010000,
+        0b01100000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+    // '~'
+    [
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00111001,
+        0b01000110,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+        0b00000000,
+    ],
+];
+
+/// Returns the glyph of the character.
+pub fn glyph(character: char) -> &'static [u8; GLYPH_HEIGHT] {
+    (character as usize)
+        .checked_sub(FIRST_CHARACTER as usize)
+        .and_then(|index| GLYPHS.get(index))
+        .unwrap_or(&REPLACEMENT_GLYPH)
+}
@@ -0,0 +1,645 @@
+//! Provides a text console that renders into a linear framebuffer.
+//!
+//! The text is drawn with the built-in font into a back buffer in memory, which is copied to the framebuffer
+//! when the console is flushed, since the framebuffer itself is slow to access and shouldn't show half drawn lines.
+//! The back buffer takes contiguous frames, since it is usually too big for the kernel heap.
+//! The console understands the VT100 escape sequences for colors, cursor movement and erasing,
+//! so that the output looks the same as on a serial terminal.
+
+use core::{
+    fmt::{self, Write},
+    mem::size_of,
+    ops::Range,
+    ptr, slice,
+};
+use log::LevelFilter;
+
+use super::{
+    ansi::{Action, ControlSequence, Parser},
+    font::{glyph, GLYPH_HEIGHT, GLYPH_WIDTH},
+    Sink,
+};
+use crate::{
+    arch::{Arch, Architecture},
+    memory::{MemoryError, PhysicalAddress, VirtualAddress, FRAME_ALLOCATOR, PAGE_SIZE},
+    sync::Mutex,
+};
+
+/// The distance between two tab stops in characters.
+const TAB_WIDTH: usize = 8;
+
+/// The colors selected by the ANSI color codes, followed by their bright variants.
+const PALETTE: [Color; 16] = [
+    Color::new(0, 0, 0),
+    Color::new(170, 0, 0),
+    Color::new(0, 170, 0),
+    Color::new(170, 85, 0),
+    Color::new(0, 0, 170),
+    Color::new(170, 0, 170),
+    Color::new(0, 170, 170),
+    Color::new(170, 170, 170),
+    Color::new(85, 85, 85),
+    Color::new(255, 85, 85),
+    Color::new(85, 255, 85),
+    Color::new(255, 255, 85),
+    Color::new(85, 85, 255),
+    Color::new(255, 85, 255),
+    Color::new(85, 255, 255),
+    Color::new(255, 255, 255),
+];
+
+/// The color of the text, unless an escape sequence selects another one.
+pub const DEFAULT_FOREGROUND: Color = PALETTE[Rendition::DEFAULT.foreground];
+
+/// The color behind the text, unless an escape sequence selects another one.
+pub const DEFAULT_BACKGROUND: Color = PALETTE[Rendition::DEFAULT.background];
+
+/// The framebuffer console, once it was set up.
+static CONSOLE: Mutex<Option<FramebufferConsole>> = Mutex::new(None);
+
+/// A color with eight bits per component.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Color {
+    /// The red component.
+    pub red: u8,
+    /// The green component.
+    pub green: u8,
+    /// The blue component.
+    pub blue: u8,
+}
+
+impl Color {
+    /// Creates a color from its components.
+    pub const fn new(red: u8, green: u8, blue: u8) -> Color {
+        Color { red, green, blue }
+    }
+
+    /// Returns the color selected by the ANSI color code from 0 to 15.
+    pub fn ansi(code: usize) -> Color {
+        PALETTE[code % PALETTE.len()]
+    }
+}
+
+/// The ways the color components can be laid out in a pixel of 32 bits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// Red is stored in the lowest byte, followed by green and blue.
+    Rgb,
+    /// Blue is stored in the lowest byte, followed by green and red.
+    Bgr,
+    /// The components are stored in the bits set in their masks.
+    Bitmask {
+        /// The bits of the red component.
+        red: u32,
+        /// The bits of the green component.
+        green: u32,
+        /// The bits of the blue component.
+        blue: u32,
+    },
+}
+
+impl PixelFormat {
+    /// Encodes the color as a pixel.
+    pub fn encode(self, color: Color) -> u32 {
+        match self {
+            PixelFormat::Rgb => {
+                u32::from(color.red) | u32::from(color.green) << 8 | u32::from(color.blue) << 16
+            }
+            PixelFormat::Bgr => {
+                u32::from(color.blue) | u32::from(color.green) << 8 | u32::from(color.red) << 16
+            }
+            PixelFormat::Bitmask { red, green, blue } => {
+                scale(color.red, red) | scale(color.green, green) | scale(color.blue, blue)
+            }
+        }
+    }
+}
+
+/// Returns the number of frames needed for the given number of pixels.
+fn frame_count(pixel_count: usize) -> usize {
+    (pixel_count * size_of::<u32>() + PAGE_SIZE - 1) / PAGE_SIZE
+}
+
+/// Scales the color component to the bits set in the mask.
+fn scale(component: u8, mask: u32) -> u32 {
+    if mask == 0 {
+        return 0;
+    }
+
+    let shift = mask.trailing_zeros();
+    let maximum = u64::from(mask >> shift);
+
+    ((u64::from(component) * maximum / 255) as u32) << shift
+}
+
+/// A linear framebuffer with 32 bits per pixel.
+#[derive(Clone, Copy, Debug)]
+pub struct Framebuffer {
+    /// The address of the first pixel.
+    address: VirtualAddress,
+    /// The number of visible pixels in a line.
+    width: usize,
+    /// The number of lines.
+    height: usize,
+    /// The number of pixels from the start of one line to the start of the next.
+    stride: usize,
+    /// The layout of the pixels.
+    format: PixelFormat,
+}
+
+impl Framebuffer {
+    /// Describes the framebuffer at the given address.
+    ///
+    /// # Safety
+    /// The memory at `address` must hold `stride * height` pixels and must not be used for anything else.
+    pub unsafe fn new(
+        address: VirtualAddress,
+        width: usize,
+        height: usize,
+        stride: usize,
+        format: PixelFormat,
+    ) -> Framebuffer {
+        debug_assert!(width <= stride);
+
+        Framebuffer {
+            address,
+            width,
+            height,
+            stride,
+            format,
+        }
+    }
+
+    /// Returns the number of visible pixels in a line.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the number of lines.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the layout of the pixels.
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+}
+
+/// The way the text is drawn, as selected by SGR sequences.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Rendition {
+    /// The palette index of the text color.
+    foreground: usize,
+    /// The palette index of the color behind the text.
+    background: usize,
+    /// Whether the text is bold, which is shown with the bright variant of the text color.
+    bold: bool,
+    /// Whether the text and background colors are swapped.
+    reverse: bool,
+}
+
+impl Rendition {
+    /// The rendition of text that no SGR sequence changed.
+    const DEFAULT: Rendition = Rendition {
+        foreground: 7,
+        background: 0,
+        bold: false,
+        reverse: false,
+    };
+
+    /// Returns the colors of the text and the background.
+    fn colors(self) -> (Color, Color) {
+        let foreground = if self.bold && self.foreground < 8 {
+            Color::ansi(self.foreground + 8)
+        } else {
+            Color::ansi(self.foreground)
+        };
+        let background = Color::ansi(self.background);
+
+        if self.reverse {
+            (background, foreground)
+        } else {
+            (foreground, background)
+        }
+    }
+}
+
+/// The errors that can occur when creating a framebuffer console.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConsoleError {
+    /// The framebuffer cannot hold a single character.
+    TooSmall,
+    /// There is not enough memory for the back buffer.
+    Memory(MemoryError),
+}
+
+impl From<MemoryError> for ConsoleError {
+    fn from(error: MemoryError) -> ConsoleError {
+        ConsoleError::Memory(error)
+    }
+}
+
+impl fmt::Display for ConsoleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConsoleError::TooSmall => write!(f, "the framebuffer is smaller than a character"),
+            ConsoleError::Memory(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+/// A text console on a framebuffer.
+pub struct FramebufferConsole {
+    /// The framebuffer the console is shown on.
+    framebuffer: Framebuffer,
+    /// The pixels of the console, which are copied to the framebuffer when flushing.
+    back_buffer: &'static mut [u32],
+    /// The first of the frames that hold the back buffer.
+    back_buffer_frames: PhysicalAddress,
+    /// The lines of pixels that changed since the last flush.
+    dirty: Option<Range<usize>>,
+    /// The number of characters in a row.
+    columns: usize,
+    /// The number of rows of characters.
+    rows: usize,
+    /// The column the next character is drawn in.
+    column: usize,
+    /// The row the next character is drawn in.
+    row: usize,
+    /// The column and row saved by the last save cursor sequence.
+    saved_cursor: (usize, usize),
+    /// The way the text is drawn.
+    rendition: Rendition,
+    /// The parser for the escape sequences.
+    parser: Parser,
+}
+
+impl FramebufferConsole {
+    /// Creates an empty console on the framebuffer.
+    ///
+    /// The framebuffer is cleared on the first flush.
+    /// Fails if the framebuffer cannot hold at least one character.
+    pub fn new(framebuffer: Framebuffer) -> Result<FramebufferConsole, ConsoleError> {
+        if framebuffer.width < GLYPH_WIDTH || framebuffer.height < GLYPH_HEIGHT {
+            return Err(ConsoleError::TooSmall);
+        }
+
+        let pixel_count = framebuffer.width * framebuffer.height;
+        let back_buffer_frames = FRAME_ALLOCATOR
+            .lock()
+            .allocate_contiguous(frame_count(pixel_count))
+            .ok_or(MemoryError::OutOfMemory)?;
+
+        // This is safe, because the frames were just allocated and are only used by the console.
+        let back_buffer = unsafe {
+            slice::from_raw_parts_mut(
+                Arch::physical_to_virtual(back_buffer_frames).as_mut_ptr(),
+                pixel_count,
+            )
+        };
+
+        for pixel in back_buffer.iter_mut() {
+            *pixel = framebuffer.format.encode(DEFAULT_BACKGROUND);
+        }
+
+        Ok(FramebufferConsole {
+            framebuffer,
+            back_buffer,
+            back_buffer_frames,
+            dirty: Some(0..framebuffer.height),
+            columns: framebuffer.width / GLYPH_WIDTH,
+            rows: framebuffer.height / GLYPH_HEIGHT,
+            column: 0,
+            row: 0,
+            saved_cursor: (0, 0),
+            rendition: Rendition::DEFAULT,
+            parser: Parser::new(),
+        })
+    }
+
+    /// Returns the number of characters in a row.
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// Returns the number of rows of characters.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Returns the column and the row the next character is drawn in.
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.column, self.row)
+    }
+
+    /// Copies the changed lines to the framebuffer.
+    pub fn flush(&mut self) {
+        let width = self.framebuffer.width;
+
+        if let Some(lines) = self.dirty.take() {
+            for line in lines {
+                let source = &self.back_buffer[line * width..(line + 1) * width];
+
+                // This is safe, because the framebuffer holds `stride * height` pixels, which only the console uses.
+                unsafe {
+                    let target = (self.framebuffer.address.as_usize() as *mut u32)
+                        .add(line * self.framebuffer.stride);
+
+                    ptr::copy_nonoverlapping(source.as_ptr(), target, width);
+                }
+            }
+        }
+    }
+
+    /// Feeds the character to the escape sequence parser and performs the resulting action.
+    fn write_character(&mut self, character: char) {
+        match self.parser.advance(character) {
+            Some(Action::Character(character)) => self.print(character),
+            Some(Action::Escape(function)) => self.escape(function),
+            Some(Action::ControlSequence(ref sequence)) if !sequence.is_private() => {
+                self.execute(sequence)
+            }
+            _ => (),
+        }
+    }
+
+    /// Executes the escape sequence that ends with the given character.
+    fn escape(&mut self, function: char) {
+        match function {
+            '7' => self.saved_cursor = (self.column, self.row),
+            '8' => self.move_to(self.saved_cursor.0, self.saved_cursor.1),
+            'c' => {
+                self.rendition = Rendition::DEFAULT;
+                self.erase_display(2);
+                self.move_to(0, 0);
+            }
+            // Other escape sequences are not supported.
+            _ => (),
+        }
+    }
+
+    /// Executes the control sequence.
+    fn execute(&mut self, sequence: &ControlSequence) {
+        let count = sequence.parameter(0, 1);
+        let (column, row) = (self.column.min(self.columns - 1), self.row);
+
+        match sequence.function() {
+            'A' => self.move_to(column, row.saturating_sub(count)),
+            'B' => self.move_to(column, row.saturating_add(count)),
+            'C' => self.move_to(column.saturating_add(count), row),
+            'D' => self.move_to(column.saturating_sub(count), row),
+            'E' => self.move_to(0, row.saturating_add(count)),
+            'F' => self.move_to(0, row.saturating_sub(count)),
+            'G' => self.move_to(count - 1, row),
+            'd' => self.move_to(column, count - 1),
+            'H' | 'f' => self.move_to(sequence.parameter(1, 1) - 1, count - 1),
+            'J' => self.erase_display(sequence.parameter(0, 0)),
+            'K' => self.erase_line(sequence.parameter(0, 0)),
+            'm' => self.select_graphic_rendition(sequence.parameters()),
+            's' => self.saved_cursor = (self.column, self.row),
+            'u' => self.move_to(self.saved_cursor.0, self.saved_cursor.1),
+            _ => (),
+        }
+    }
+
+    /// Changes the rendition according to the parameters of an SGR sequence.
+    fn select_graphic_rendition(&mut self, parameters: &[usize]) {
+        // A sequence without parameters resets the rendition.
+        let parameters = if parameters.is_empty() {
+            &[0][..]
+        } else {
+            parameters
+        };
+
+        for &parameter in parameters {
+            let rendition = &mut self.rendition;
+
+            match parameter {
+                0 => *rendition = Rendition::DEFAULT,
+                1 => rendition.bold = true,
+                7 => rendition.reverse = true,
+                22 => rendition.bold = false,
+                27 => rendition.reverse = false,
+                30..=37 => rendition.foreground = parameter - 30,
+                39 => rendition.foreground = Rendition::DEFAULT.foreground,
+                40..=47 => rendition.background = parameter - 40,
+                49 => rendition.background = Rendition::DEFAULT.background,
+                90..=97 => rendition.foreground = parameter - 90 + 8,
+                100..=107 => rendition.background = parameter - 100 + 8,
+                _ => (),
+            }
+        }
+    }
+
+    /// Moves the cursor to the column and row, which are limited to the screen.
+    fn move_to(&mut self, column: usize, row: usize) {
+        self.column = column.min(self.columns - 1);
+        self.row = row.min(self.rows - 1);
+    }
+
+    /// Erases a part of the screen, depending on the mode of an ED sequence.
+    ///
+    /// Mode 0 erases from the cursor to the end of the screen, mode 1 from the start of the screen to the cursor
+    /// and mode 2 erases the whole screen.
+    fn erase_display(&mut self, mode: usize) {
+        let rows = match mode {
+            0 => self.row + 1..self.rows,
+            1 => 0..self.row,
+            2 => 0..self.rows,
+            _ => return,
+        };
+
+        if mode != 2 {
+            self.erase_line(mode);
+        }
+
+        for row in rows {
+            self.erase(row, 0..self.columns);
+        }
+    }
+
+    /// Erases a part of the cursor row, depending on the mode of an EL sequence.
+    ///
+    /// Mode 0 erases from the cursor to the end of the row, mode 1 from the start of the row to the cursor
+    /// and mode 2 erases the whole row.
+    fn erase_line(&mut self, mode: usize) {
+        let column = self.column.min(self.columns - 1);
+        let columns = match mode {
+            0 => column..self.columns,
+            1 => 0..column + 1,
+            2 => 0..self.columns,
+            _ => return,
+        };
+
+        self.erase(self.row, columns);
+    }
+
+    /// Fills the cells in the columns of the row with the background color.
+    fn erase(&mut self, row: usize, columns: Range<usize>) {
+        let width = self.framebuffer.width;
+        let (_, background) = self.rendition.colors();
+        let background = self.framebuffer.format.encode(background);
+
+        for y in row * GLYPH_HEIGHT..(row + 1) * GLYPH_HEIGHT {
+            for pixel in &mut self.back_buffer
+                [y * width + columns.start * GLYPH_WIDTH..y * width + columns.end * GLYPH_WIDTH]
+            {
+                *pixel = background;
+            }
+        }
+
+        self.mark_dirty(row * GLYPH_HEIGHT..(row + 1) * GLYPH_HEIGHT);
+    }
+
+    /// Prints the character at the cursor or performs its control function.
+    fn print(&mut self, character: char) {
+        match character {
+            // The kernel ends lines with a line feed alone.
+            '\n' => self.new_line(),
+            '\r' => self.column = 0,
+            '\t' => {
+                let spaces = TAB_WIDTH - self.column % TAB_WIDTH;
+
+                for _ in 0..spaces {
+                    self.print(' ');
+                }
+            }
+            '\x08' => self.column = self.column.saturating_sub(1),
+            _ if character.is_control() => (),
+            _ => {
+                if self.column >= self.columns {
+                    self.new_line();
+                }
+
+                self.draw(self.column, self.row, character);
+                self.column += 1;
+            }
+        }
+    }
+
+    /// Moves the cursor to the start of the next row, scrolling if needed.
+    fn new_line(&mut self) {
+        self.column = 0;
+
+        if self.row + 1 < self.rows {
+            self.row += 1;
+        } else {
+            self.scroll();
+        }
+    }
+
+    /// Moves the contents up by one row and clears the last row.
+    fn scroll(&mut self) {
+        let length = self.back_buffer.len();
+        let offset = GLYPH_HEIGHT * self.framebuffer.width;
+        let (_, background) = self.rendition.colors();
+        let background = self.framebuffer.format.encode(background);
+
+        // This is safe, because both ranges lie within the back buffer.
+        unsafe {
+            ptr::copy(
+                self.back_buffer.as_ptr().add(offset),
+                self.back_buffer.as_mut_ptr(),
+                length - offset,
+            );
+        }
+
+        for pixel in &mut self.back_buffer[length - offset..] {
+            *pixel = background;
+        }
+
+        self.mark_dirty(0..self.framebuffer.height);
+    }
+
+    /// Draws the glyph of the character into the cell at the column and row.
+    fn draw(&mut self, column: usize, row: usize, character: char) {
+        let width = self.framebuffer.width;
+        let (foreground, background) = self.rendition.colors();
+        let (foreground, background) = (
+            self.framebuffer.format.encode(foreground),
+            self.framebuffer.format.encode(background),
+        );
+
+        for (y, &bits) in glyph(character).iter().enumerate() {
+            let start = (row * GLYPH_HEIGHT + y) * width + column * GLYPH_WIDTH;
+
+            for (x, pixel) in self.back_buffer[start..start + GLYPH_WIDTH]
+                .iter_mut()
+                .enumerate()
+            {
+                *pixel = if bits & (0x80 >> x) != 0 {
+                    foreground
+                } else {
+                    background
+                };
+            }
+        }
+
+        self.mark_dirty(row * GLYPH_HEIGHT..(row + 1) * GLYPH_HEIGHT);
+    }
+
+    /// Remembers that the lines of pixels need to be copied to the framebuffer.
+    fn mark_dirty(&mut self, lines: Range<usize>) {
+        self.dirty = Some(match self.dirty.take() {
+            Some(dirty) => dirty.start.min(lines.start)..dirty.end.max(lines.end),
+            None => lines,
+        });
+    }
+}
+
+impl Drop for FramebufferConsole {
+    fn drop(&mut self) {
+        let mut frame_allocator = FRAME_ALLOCATOR.lock();
+
+        for index in 0..frame_count(self.back_buffer.len()) {
+            // This is safe, because the back buffer is not used after the console is dropped.
+            unsafe { frame_allocator.deallocate(self.back_buffer_frames + index * PAGE_SIZE) };
+        }
+    }
+}
+
+impl fmt::Write for FramebufferConsole {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        for character in string.chars() {
+            self.write_character(character);
+        }
+
+        Ok(())
+    }
+}
+
+/// Sets up the framebuffer console on the framebuffer and registers it as the `framebuffer` console.
+///
+/// The console shows warnings and errors by default.
+pub fn init(framebuffer: Framebuffer) {
+    match FramebufferConsole::new(framebuffer) {
+        Ok(console) => {
+            let (columns, rows) = (console.columns(), console.rows());
+
+            *CONSOLE.lock() = Some(console);
+
+            super::register("framebuffer", &FramebufferSink, LevelFilter::Warn);
+
+            log::info!(
+                "Using a framebuffer console with {}x{} characters.",
+                columns,
+                rows
+            );
+        }
+        Err(error) => log::warn!("Could not set up the framebuffer console: {}.", error),
+    }
+}
+
+/// The console sink of the framebuffer console.
+pub struct FramebufferSink;
+
+impl Sink for FramebufferSink {
+    fn write_fmt(&self, args: fmt::Arguments) {
+        if let Some(console) = CONSOLE.lock().as_mut() {
+            // Writing to the console cannot fail.
+            console.write_fmt(args).ok();
+            console.flush();
+        }
+    }
+}
@@ -0,0 +1,434 @@
+//! Turns the bytes sent by keyboards into input for the consoles.
+//!
+//! The bytes pass through several layers:
+//! the scancode decoder turns them into key events for the physical keys, identified by key codes,
+//! the modifier state tracks the modifier and lock keys, and the keyboard layout maps the pressed keys
+//! to characters, which are passed to the line discipline of the consoles.
+//! Keys without a character, like the cursor keys, are passed as the escape sequences a terminal would send.
+//!
+//! The layout is shared by all keyboards and can be chosen with the `keymap` option on the command line.
+
+pub mod layout;
+pub mod scancode;
+
+use bitflags::bitflags;
+use core::fmt;
+
+use self::{
+    layout::Layout,
+    scancode::{Decoder, ScancodeSet},
+};
+use crate::{cmdline::Configuration, console, sync::Mutex};
+
+/// The layout used by all keyboards.
+static LAYOUT: Mutex<Layout> = Mutex::new(Layout::Us);
+
+/// A physical key, named after its label on a US keyboard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyCode {
+    /// The escape key.
+    Escape,
+    /// The `F1` key.
+    F1,
+    /// The `F2` key.
+    F2,
+    /// The `F3` key.
+    F3,
+    /// The `F4` key.
+    F4,
+    /// The `F5` key.
+    F5,
+    /// The `F6` key.
+    F6,
+    /// The `F7` key.
+    F7,
+    /// The `F8` key.
+    F8,
+    /// The `F9` key.
+    F9,
+    /// The `F10` key.
+    F10,
+    /// The `F11` key.
+    F11,
+    /// The `F12` key.
+    F12,
+    /// The print screen key.
+    PrintScreen,
+    /// The scroll lock key.
+    ScrollLock,
+    /// The pause key.
+    Pause,
+    /// The `` ` `` key.
+    Grave,
+    /// The `1` key.
+    Digit1,
+    /// The `2` key.
+    Digit2,
+    /// The `3` key.
+    Digit3,
+    /// The `4` key.
+    Digit4,
+    /// The `5` key.
+    Digit5,
+    /// The `6` key.
+    Digit6,
+    /// The `7` key.
+    Digit7,
+    /// The `8` key.
+    Digit8,
+    /// The `9` key.
+    Digit9,
+    /// The `0` key.
+    Digit0,
+    /// The `-` key.
+    Minus,
+    /// The `=` key.
+    Equals,
+    /// The backspace key.
+    Backspace,
+    /// The tab key.
+    Tab,
+    /// The `Q` key.
+    Q,
+    /// The `W` key.
+    W,
+    /// The `E` key.
+    E,
+    /// The `R` key.
+    R,
+    /// The `T` key.
+    T,
+    /// The `Y` key.
+    Y,
+    /// The `U` key.
+    U,
+    /// The `I` key.
+    I,
+    /// The `O` key.
+    O,
+    /// The `P` key.
+    P,
+    /// The `[` key.
+    LeftBracket,
+    /// The `]` key.
+    RightBracket,
+    /// The `\` key.
+    Backslash,
+    /// The caps lock key.
+    CapsLock,
+    /// The `A` key.
+    A,
+    /// The `S` key.
+    S,
+    /// The `D` key.
+    D,
+    /// The `F` key.
+    F,
+    /// The `G` key.
+    G,
+    /// The `H` key.
+    H,
+    /// The `J` key.
+    J,
+    /// The `K` key.
+    K,
+    /// The `L` key.
+    L,
+    /// The `;` key.
+    Semicolon,
+    /// The `'` key.
+    Apostrophe,
+    /// The enter key.
+    Enter,
+    /// The left shift key.
+    LeftShift,
+    /// The additional key next to the left shift key on ISO keyboards.
+    NonUsBackslash,
+    /// The `Z` key.
+    Z,
+    /// The `X` key.
+    X,
+    /// The `C` key.
+    C,
+    /// The `V` key.
+    V,
+    /// The `B` key.
+    B,
+    /// The `N` key.
+    N,
+    /// The `M` key.
+    M,
+    /// The `,` key.
+    Comma,
+    /// The `.` key.
+    Period,
+    /// The `/` key.
+    Slash,
+    /// The right shift key.
+    RightShift,
+    /// The left control key.
+    LeftControl,
+    /// The left GUI key.
+    LeftGui,
+    /// The left alt key.
+    LeftAlt,
+    /// The space key.
+    Space,
+    /// The right alt key, which is `AltGr` on many layouts.
+    RightAlt,
+    /// The right GUI key.
+    RightGui,
+    /// The menu key.
+    Menu,
+    /// The right control key.
+    RightControl,
+    /// The insert key.
+    Insert,
+    /// The delete key.
+    Delete,
+    /// The home key.
+    Home,
+    /// The end key.
+    End,
+    /// The page up key.
+    PageUp,
+    /// The page down key.
+    PageDown,
+    /// The up arrow key.
+    Up,
+    /// The down arrow key.
+    Down,
+    /// The left arrow key.
+    Left,
+    /// The right arrow key.
+    Right,
+    /// The num lock key.
+    NumLock,
+    /// The `/` key on the keypad.
+    KeypadDivide,
+    /// The `*` key on the keypad.
+    KeypadMultiply,
+    /// The `-` key on the keypad.
+    KeypadMinus,
+    /// The `+` key on the keypad.
+    KeypadPlus,
+    /// The enter key on the keypad.
+    KeypadEnter,
+    /// The `.` key on the keypad.
+    KeypadPeriod,
+    /// The `0` key on the keypad.
+    Keypad0,
+    /// The `1` key on the keypad.
+    Keypad1,
+    /// The `2` key on the keypad.
+    Keypad2,
+    /// The `3` key on the keypad.
+    Keypad3,
+    /// The `4` key on the keypad.
+    Keypad4,
+    /// The `5` key on the keypad.
+    Keypad5,
+    /// The `6` key on the keypad.
+    Keypad6,
+    /// The `7` key on the keypad.
+    Keypad7,
+    /// The `8` key on the keypad.
+    Keypad8,
+    /// The `9` key on the keypad.
+    Keypad9,
+}
+
+/// Whether a key was pressed or released.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyState {
+    /// The key was pressed or is repeated because it is held down.
+    Pressed,
+    /// The key was released.
+    Released,
+}
+
+/// A change of the state of a key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyEvent {
+    /// The key that changed.
+    pub key: KeyCode,
+    /// The new state of the key.
+    pub state: KeyState,
+}
+
+impl KeyEvent {
+    /// Creates an event for the key.
+    pub const fn new(key: KeyCode, state: KeyState) -> KeyEvent {
+        KeyEvent { key, state }
+    }
+}
+
+bitflags! {
+    /// The modifier keys that are held down and the lock keys that are active.
+    pub struct Modifiers: u16 {
+        /// The left shift key is held down.
+        const LEFT_SHIFT = 1 << 0;
+        /// The right shift key is held down.
+        const RIGHT_SHIFT = 1 << 1;
+        /// The left control key is held down.
+        const LEFT_CONTROL = 1 << 2;
+        /// The right control key is held down.
+        const RIGHT_CONTROL = 1 << 3;
+        /// The left alt key is held down.
+        const LEFT_ALT = 1 << 4;
+        /// The right alt key is held down.
+        const RIGHT_ALT = 1 << 5;
+        /// Caps lock is active.
+        const CAPS_LOCK = 1 << 6;
+        /// Num lock is active.
+        const NUM_LOCK = 1 << 7;
+        /// Scroll lock is active.
+        const SCROLL_LOCK = 1 << 8;
+    }
+}
+
+impl Modifiers {
+    /// The lock keys, which are shown by the LEDs of the keyboard.
+    pub const LOCKS: Modifiers = Modifiers {
+        bits: Modifiers::CAPS_LOCK.bits | Modifiers::NUM_LOCK.bits | Modifiers::SCROLL_LOCK.bits,
+    };
+
+    /// Checks if a shift key is held down.
+    pub fn shift(self) -> bool {
+        self.intersects(Modifiers::LEFT_SHIFT | Modifiers::RIGHT_SHIFT)
+    }
+
+    /// Checks if a control key is held down.
+    pub fn control(self) -> bool {
+        self.intersects(Modifiers::LEFT_CONTROL | Modifiers::RIGHT_CONTROL)
+    }
+
+    /// Checks if the left alt key is held down.
+    ///
+    /// The right alt key is `AltGr`, which selects further characters on some layouts.
+    pub fn alt(self) -> bool {
+        self.contains(Modifiers::LEFT_ALT)
+    }
+
+    /// Applies the key event to the modifiers.
+    ///
+    /// Modifier keys are held down while they are pressed, while lock keys toggle when they are pressed.
+    pub fn update(&mut self, event: KeyEvent) {
+        let (modifier, lock) = match event.key {
+            KeyCode::LeftShift => (Modifiers::LEFT_SHIFT, false),
+            KeyCode::RightShift => (Modifiers::RIGHT_SHIFT, false),
+            KeyCode::LeftControl => (Modifiers::LEFT_CONTROL, false),
+            KeyCode::RightControl => (Modifiers::RIGHT_CONTROL, false),
+            KeyCode::LeftAlt => (Modifiers::LEFT_ALT, false),
+            KeyCode::RightAlt => (Modifiers::RIGHT_ALT, false),
+            KeyCode::CapsLock => (Modifiers::CAPS_LOCK, true),
+            KeyCode::NumLock => (Modifiers::NUM_LOCK, true),
+            KeyCode::ScrollLock => (Modifiers::SCROLL_LOCK, true),
+            _ => return,
+        };
+
+        match (event.state, lock) {
+            (KeyState::Pressed, true) => self.toggle(modifier),
+            (KeyState::Pressed, false) => self.insert(modifier),
+            (KeyState::Released, false) => self.remove(modifier),
+            (KeyState::Released, true) => (),
+        }
+    }
+}
+
+/// The state of a keyboard.
+pub struct Keyboard {
+    /// Decodes the bytes sent by the keyboard.
+    decoder: Decoder,
+    /// The modifiers of the keyboard.
+    modifiers: Modifiers,
+}
+
+impl Keyboard {
+    /// Creates the state of a keyboard that sends scancodes of the given set.
+    ///
+    /// Num lock starts out active, as on most computers.
+    pub const fn new(set: ScancodeSet) -> Keyboard {
+        Keyboard {
+            decoder: Decoder::new(set),
+            modifiers: Modifiers {
+                bits: Modifiers::NUM_LOCK.bits,
+            },
+        }
+    }
+
+    /// Returns the scancode set the keyboard sends.
+    pub fn scancode_set(&self) -> ScancodeSet {
+        self.decoder.set()
+    }
+
+    /// Returns the modifiers of the keyboard.
+    pub fn modifiers(&self) -> Modifiers {
+        self.modifiers
+    }
+
+    /// Feeds a byte sent by the keyboard to the keyboard state.
+    ///
+    /// Returns the key event, if the byte completed one.
+    pub fn receive_byte(&mut self, byte: u8) -> Option<KeyEvent> {
+        let event = self.decoder.advance(byte)?;
+
+        self.modifiers.update(event);
+
+        Some(event)
+    }
+}
+
+/// The errors that can occur when choosing a keyboard layout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyboardError {
+    /// No layout has the given name.
+    UnknownLayout,
+}
+
+impl fmt::Display for KeyboardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyboardError::UnknownLayout => write!(f, "unknown keyboard layout"),
+        }
+    }
+}
+
+/// Returns the layout used by all keyboards.
+pub fn layout() -> Layout {
+    *LAYOUT.lock()
+}
+
+/// Sets the layout used by all keyboards.
+pub fn set_layout(layout: Layout) {
+    *LAYOUT.lock() = layout;
+}
+
+/// Applies the layout given on the command line.
+pub fn configure(configuration: &Configuration) {
+    if let Some(name) = configuration.keymap() {
+        match name.parse() {
+            Ok(layout) => set_layout(layout),
+            Err(error) => log::warn!("Ignoring the keyboard layout \"{}\": {}.", name, error),
+        }
+    }
+}
+
+/// Passes the key event to the consoles as input, using the current layout.
+///
+/// Only pressed keys produce input.
+pub fn deliver(event: KeyEvent, modifiers: Modifiers) {
+    if event.state != KeyState::Pressed {
+        return;
+    }
+
+    let layout = layout();
+
+    if let Some(character) = layout.character(event.key, modifiers) {
+        console::receive(character);
+    } else if let Some(sequence) = layout::escape_sequence(event.key, modifiers) {
+        for character in sequence.chars() {
+            console::receive(character);
+        }
+    }
+}
@@ -0,0 +1,290 @@
+//! Maps keys to characters according to keyboard layouts.
+//!
+//! A layout gives each key up to three characters: one without modifiers, one with shift
+//! and one with `AltGr`, the right alt key.
+//! Caps lock inverts shift for letters, control turns letters and some symbols into control characters,
+//! and num lock selects between the digits and the cursor functions of the keypad.
+//! Keys that are dead keys on real layouts, like the accents of the German layout, produce their character directly.
+
+use core::{fmt, str::FromStr};
+
+use super::{KeyCode, KeyboardError, Modifiers};
+
+/// The character sent by the backspace key of most terminals.
+const DELETE: char = '\x7f';
+
+/// The escape character.
+const ESCAPE: char = '\x1b';
+
+/// A keyboard layout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layout {
+    /// The US layout.
+    Us,
+    /// The German layout.
+    German,
+}
+
+impl Layout {
+    /// All layouts.
+    pub const ALL: [Layout; 2] = [Layout::Us, Layout::German];
+
+    /// Returns the name of the layout, which selects it on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Layout::Us => "us",
+            Layout::German => "de",
+        }
+    }
+
+    /// Returns the character the key produces with the modifiers, if it produces one.
+    pub fn character(self, key: KeyCode, modifiers: Modifiers) -> Option<char> {
+        if let Some(character) = self.common_character(key, modifiers) {
+            return Some(character);
+        }
+
+        let (normal, shifted, alt_gr) = match self {
+            Layout::Us => us_characters(key)?,
+            Layout::German => german_characters(key)?,
+        };
+
+        if let Some(alt_gr) = alt_gr.filter(|_| modifiers.contains(Modifiers::RIGHT_ALT)) {
+            return Some(alt_gr);
+        }
+
+        let letter = normal.is_lowercase() && shifted.is_uppercase();
+        let shift = modifiers.shift() != (letter && modifiers.contains(Modifiers::CAPS_LOCK));
+        let character = if shift { shifted } else { normal };
+
+        if modifiers.control() {
+            control_character(character)
+        } else {
+            Some(character)
+        }
+    }
+
+    /// Returns the character of the keys that are the same on all layouts.
+    ///
+    /// The digits of the keypad are only produced while num lock is active.
+    fn common_character(self, key: KeyCode, modifiers: Modifiers) -> Option<char> {
+        let num_lock = modifiers.contains(Modifiers::NUM_LOCK);
+
+        Some(match key {
+            KeyCode::Escape => ESCAPE,
+            KeyCode::Backspace => DELETE,
+            KeyCode::Tab => '\t',
+            KeyCode::Enter | KeyCode::KeypadEnter => '\r',
+            KeyCode::Space => ' ',
+            KeyCode::KeypadDivide => '/',
+            KeyCode::KeypadMultiply => '*',
+            KeyCode::KeypadMinus => '-',
+            KeyCode::KeypadPlus => '+',
+            KeyCode::KeypadPeriod if num_lock => match self {
+                Layout::Us => '.',
+                Layout::German => ',',
+            },
+            KeyCode::Keypad0 if num_lock => '0',
+            KeyCode::Keypad1 if num_lock => '1',
+            KeyCode::Keypad2 if num_lock => '2',
+            KeyCode::Keypad3 if num_lock => '3',
+            KeyCode::Keypad4 if num_lock => '4',
+            KeyCode::Keypad5 if num_lock => '5',
+            KeyCode::Keypad6 if num_lock => '6',
+            KeyCode::Keypad7 if num_lock => '7',
+            KeyCode::Keypad8 if num_lock => '8',
+            KeyCode::Keypad9 if num_lock => '9',
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Layout {
+    type Err = KeyboardError;
+
+    /// Parses the name of a layout, which is `us` or `de`.
+    fn from_str(name: &str) -> Result<Layout, KeyboardError> {
+        Layout::ALL
+            .iter()
+            .cloned()
+            .find(|layout| layout.name() == name)
+            .ok_or(KeyboardError::UnknownLayout)
+    }
+}
+
+/// Returns the escape sequence a terminal sends for the key, if it sends one.
+///
+/// Without num lock, the keypad keys send the sequences of the cursor keys printed on them.
+pub fn escape_sequence(key: KeyCode, modifiers: Modifiers) -> Option<&'static str> {
+    let key = if modifiers.contains(Modifiers::NUM_LOCK) {
+        key
+    } else {
+        keypad_function(key).unwrap_or(key)
+    };
+
+    Some(match key {
+        KeyCode::Up => "\x1b[A",
+        KeyCode::Down => "\x1b[B",
+        KeyCode::Right => "\x1b[C",
+        KeyCode::Left => "\x1b[D",
+        KeyCode::Home => "\x1b[H",
+        KeyCode::End => "\x1b[F",
+        KeyCode::Insert => "\x1b[2~",
+        KeyCode::Delete => "\x1b[3~",
+        KeyCode::PageUp => "\x1b[5~",
+        KeyCode::PageDown => "\x1b[6~",
+        KeyCode::F1 => "\x1b[11~",
+        KeyCode::F2 => "\x1b[12~",
+        KeyCode::F3 => "\x1b[13~",
+        KeyCode::F4 => "\x1b[14~",
+        KeyCode::F5 => "\x1b[15~",
+        KeyCode::F6 => "\x1b[17~",
+        KeyCode::F7 => "\x1b[18~",
+        KeyCode::F8 => "\x1b[19~",
+        KeyCode::F9 => "\x1b[20~",
+        KeyCode::F10 => "\x1b[21~",
+        KeyCode::F11 => "\x1b[23~",
+        KeyCode::F12 => "\x1b[24~",
+        _ => return None,
+    })
+}
+
+/// Returns the cursor key printed on the keypad key, if there is one.
+fn keypad_function(key: KeyCode) -> Option<KeyCode> {
+    Some(match key {
+        KeyCode::Keypad0 => KeyCode::Insert,
+        KeyCode::Keypad1 => KeyCode::End,
+        KeyCode::Keypad2 => KeyCode::Down,
+        KeyCode::Keypad3 => KeyCode::PageDown,
+        KeyCode::Keypad4 => KeyCode::Left,
+        KeyCode::Keypad6 => KeyCode::Right,
+        KeyCode::Keypad7 => KeyCode::Home,
+        KeyCode::Keypad8 => KeyCode::Up,
+        KeyCode::Keypad9 => KeyCode::PageUp,
+        KeyCode::KeypadPeriod => KeyCode::Delete,
+        _ => return None,
+    })
+}
+
+/// Returns the control character produced by the character with control held down, if there is one.
+///
+/// Letters and the symbols from `@` to `_` produce the control characters in the order of their codes.
+fn control_character(character: char) -> Option<char> {
+    match character.to_ascii_uppercase() {
+        character @ '@'..='_' => Some((character as u8 - b'@') as char),
+        _ => None,
+    }
+}
+
+/// Returns the lowercase letter printed on the key on a US keyboard, if there is one.
+fn letter(key: KeyCode) -> Option<char> {
+    use KeyCode::*;
+
+    Some(match key {
+        A => 'a',
+        B => 'b',
+        C => 'c',
+        D => 'd',
+        E => 'e',
+        F => 'f',
+        G => 'g',
+        H => 'h',
+        I => 'i',
+        J => 'j',
+        K => 'k',
+        L => 'l',
+        M => 'm',
+        N => 'n',
+        O => 'o',
+        P => 'p',
+        Q => 'q',
+        R => 'r',
+        S => 's',
+        T => 't',
+        U => 'u',
+        V => 'v',
+        W => 'w',
+        X => 'x',
+        Y => 'y',
+        Z => 'z',
+        _ => return None,
+    })
+}
+
+/// Returns the characters of the key on the US layout, without modifiers, with shift and with `AltGr`.
+fn us_characters(key: KeyCode) -> Option<(char, char, Option<char>)> {
+    use KeyCode::*;
+
+    if let Some(letter) = letter(key) {
+        return Some((letter, letter.to_ascii_uppercase(), None));
+    }
+
+    let (normal, shifted) = match key {
+        Grave => ('`', '~'),
+        Digit1 => ('1', '!'),
+        Digit2 => ('2', '@'),
+        Digit3 => ('3', '#'),
+        Digit4 => ('4', '$'),
+        Digit5 => ('5', '%'),
+        Digit6 => ('6', '^'),
+        Digit7 => ('7', '&'),
+        Digit8 => ('8', '*'),
+        Digit9 => ('9', '('),
+        Digit0 => ('0', ')'),
+        Minus => ('-', '_'),
+        Equals => ('=', '+'),
+        LeftBracket => ('[', '{'),
+        RightBracket => (']', '}'),
+        Backslash | NonUsBackslash => ('\\', '|'),
+        Semicolon => (';', ':'),
+        Apostrophe => ('\'', '"'),
+        Comma => (',', '<'),
+        Period => ('.', '>'),
+        Slash => ('/', '?'),
+        _ => return None,
+    };
+
+    Some((normal, shifted, None))
+}
+
+/// Returns the characters of the key on the German layout, without modifiers, with shift and with `AltGr`.
+fn german_characters(key: KeyCode) -> Option<(char, char, Option<char>)> {
+    use KeyCode::*;
+
+    Some(match key {
+        Y => ('z', 'Z', None),
+        Z => ('y', 'Y', None),
+        Q => ('q', 'Q', Some('@')),
+        E => ('e', 'E', Some('€')),
+        M => ('m', 'M', Some('µ')),
+        _ if letter(key).is_some() => return us_characters(key),
+        Grave => ('^', '°', None),
+        Digit1 => ('1', '!', None),
+        Digit2 => ('2', '"', Some('²')),
+        Digit3 => ('3', '§', Some('³')),
+        Digit4 => ('4', '$', None),
+        Digit5 => ('5', '%', None),
+        Digit6 => ('6', '&', None),
+        Digit7 => ('7', '/', Some('{')),
+        Digit8 => ('8', '(', Some('[')),
+        Digit9 => ('9', ')', Some(']')),
+        Digit0 => ('0', '=', Some('}')),
+        Minus => ('ß', '?', Some('\\')),
+        Equals => ('´', '`', None),
+        LeftBracket => ('ü', 'Ü', None),
+        RightBracket => ('+', '*', Some('~')),
+        Backslash => ('#', '\'', None),
+        Semicolon => ('ö', 'Ö', None),
+        Apostrophe => ('ä', 'Ä', None),
+        NonUsBackslash => ('<', '>', Some('|')),
+        Comma => (',', ';', None),
+        Period => ('.', ':', None),
+        Slash => ('-', '_', None),
+        _ => return None,
+    })
+}
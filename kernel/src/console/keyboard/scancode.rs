@@ -0,0 +1,383 @@
+//! Decodes the scancodes sent by keyboards into key events.
+//!
+//! Keyboards send a sequence of bytes for each key that is pressed or released, which depends on the scancode set.
+//! Set 1 is sent by the original XT keyboards and by PS/2 controllers that translate set 2,
+//! while set 2 is the default of PS/2 keyboards.
+//! In set 1 released keys have the highest bit set, in set 2 they are preceded by `0xf0`.
+//! In both sets `0xe0` introduces the keys that were added later, like the cursor keys,
+//! and the pause key sends a long sequence starting with `0xe1`, but no release.
+
+use super::{KeyCode, KeyEvent, KeyState};
+
+/// The prefix of the keys that were added later.
+const EXTENDED_PREFIX: u8 = 0xe0;
+
+/// The prefix of the pause key.
+const PAUSE_PREFIX: u8 = 0xe1;
+
+/// The prefix of released keys in set 2.
+const RELEASE_PREFIX: u8 = 0xf0;
+
+/// The bit that marks released keys in set 1.
+const RELEASE_BIT: u8 = 0x80;
+
+/// The number of bytes after the first byte of the pause key in set 1.
+const SET_1_PAUSE_LENGTH: usize = 5;
+
+/// The number of bytes after the first byte of the pause key in set 2.
+const SET_2_PAUSE_LENGTH: usize = 7;
+
+/// The bytes keyboards send in response to commands, which are not part of scancodes.
+const RESPONSES: [u8; 3] = [0xfa, 0xfe, 0xff];
+
+/// The scancode sets that can be decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScancodeSet {
+    /// Scancode set 1, which PS/2 controllers produce when they translate.
+    Set1,
+    /// Scancode set 2, which PS/2 keyboards send by default.
+    Set2,
+}
+
+/// Decodes a stream of scancodes into key events.
+pub struct Decoder {
+    /// The scancode set that is decoded.
+    set: ScancodeSet,
+    /// Whether the extended prefix was received.
+    extended: bool,
+    /// Whether the release prefix of set 2 was received.
+    released: bool,
+    /// The number of bytes of the pause key that are still expected.
+    pause_remaining: usize,
+}
+
+impl Decoder {
+    /// Creates a decoder for the scancode set.
+    pub const fn new(set: ScancodeSet) -> Decoder {
+        Decoder {
+            set,
+            extended: false,
+            released: false,
+            pause_remaining: 0,
+        }
+    }
+
+    /// Returns the scancode set that is decoded.
+    pub fn set(&self) -> ScancodeSet {
+        self.set
+    }
+
+    /// Feeds the next byte sent by the keyboard to the decoder.
+    ///
+    /// Returns the key event, if the byte completed one.
+    /// Unknown scancodes and the responses to commands are ignored.
+    pub fn advance(&mut self, byte: u8) -> Option<KeyEvent> {
+        if self.pause_remaining > 0 {
+            self.pause_remaining -= 1;
+
+            return if self.pause_remaining == 0 {
+                Some(KeyEvent::new(KeyCode::Pause, KeyState::Pressed))
+            } else {
+                None
+            };
+        }
+
+        if RESPONSES.contains(&byte) {
+            return None;
+        }
+
+        match byte {
+            EXTENDED_PREFIX => {
+                self.extended = true;
+
+                return None;
+            }
+            PAUSE_PREFIX => {
+                self.pause_remaining = match self.set {
+                    ScancodeSet::Set1 => SET_1_PAUSE_LENGTH,
+                    ScancodeSet::Set2 => SET_2_PAUSE_LENGTH,
+                };
+
+                return None;
+            }
+            RELEASE_PREFIX if self.set == ScancodeSet::Set2 => {
+                self.released = true;
+
+                return None;
+            }
+            _ => (),
+        }
+
+        let extended = self.extended;
+        let released = self.released;
+
+        self.extended = false;
+        self.released = false;
+
+        let (code, state) = match self.set {
+            ScancodeSet::Set1 if byte & RELEASE_BIT != 0 => {
+                (byte & !RELEASE_BIT, KeyState::Released)
+            }
+            ScancodeSet::Set1 => (byte, KeyState::Pressed),
+            ScancodeSet::Set2 if released => (byte, KeyState::Released),
+            ScancodeSet::Set2 => (byte, KeyState::Pressed),
+        };
+
+        let key = match (self.set, extended) {
+            (ScancodeSet::Set1, false) => set_1_key(code),
+            (ScancodeSet::Set1, true) => set_1_extended_key(code),
+            (ScancodeSet::Set2, false) => set_2_key(code),
+            (ScancodeSet::Set2, true) => set_2_extended_key(code),
+        }?;
+
+        Some(KeyEvent::new(key, state))
+    }
+}
+
+/// Returns the key with the scancode in set 1.
+fn set_1_key(code: u8) -> Option<KeyCode> {
+    use KeyCode::*;
+
+    Some(match code {
+        0x01 => Escape,
+        0x02 => Digit1,
+        0x03 => Digit2,
+        0x04 => Digit3,
+        0x05 => Digit4,
+        0x06 => Digit5,
+        0x07 => Digit6,
+        0x08 => Digit7,
+        0x09 => Digit8,
+        0x0a => Digit9,
+        0x0b => Digit0,
+        0x0c => Minus,
+        0x0d => Equals,
+        0x0e => Backspace,
+        0x0f => Tab,
+        0x10 => Q,
+        0x11 => W,
+        0x12 => E,
+        0x13 => R,
+        0x14 => T,
+        0x15 => Y,
+        0x16 => U,
+        0x17 => I,
+        0x18 => O,
+        0x19 => P,
+        0x1a => LeftBracket,
+        0x1b => RightBracket,
+        0x1c => Enter,
+        0x1d => LeftControl,
+        0x1e => A,
+        0x1f => S,
+        0x20 => D,
+        0x21 => F,
+        0x22 => G,
+        0x23 => H,
+        0x24 => J,
+        0x25 => K,
+        0x26 => L,
+        0x27 => Semicolon,
+        0x28 => Apostrophe,
+        0x29 => Grave,
+        0x2a => LeftShift,
+        0x2b => Backslash,
+        0x2c => Z,
+        0x2d => X,
+        0x2e => C,
+        0x2f => V,
+        0x30 => B,
+        0x31 => N,
+        0x32 => M,
+        0x33 => Comma,
+        0x34 => Period,
+        0x35 => Slash,
+        0x36 => RightShift,
+        0x37 => KeypadMultiply,
+        0x38 => LeftAlt,
+        0x39 => Space,
+        0x3a => CapsLock,
+        0x3b => F1,
+        0x3c => F2,
+        0x3d => F3,
+        0x3e => F4,
+        0x3f => F5,
+        0x40 => F6,
+        0x41 => F7,
+        0x42 => F8,
+        0x43 => F9,
+        0x44 => F10,
+        0x45 => NumLock,
+        0x46 => ScrollLock,
+        0x47 => Keypad7,
+        0x48 => Keypad8,
+        0x49 => Keypad9,
+        0x4a => KeypadMinus,
+        0x4b => Keypad4,
+        0x4c => Keypad5,
+        0x4d => Keypad6,
+        0x4e => KeypadPlus,
+        0x4f => Keypad1,
+        0x50 => Keypad2,
+        0x51 => Keypad3,
+        0x52 => Keypad0,
+        0x53 => KeypadPeriod,
+        0x56 => NonUsBackslash,
+        0x57 => F11,
+        0x58 => F12,
+        _ => return None,
+    })
+}
+
+/// Returns the key with the scancode after the extended prefix in set 1.
+///
+/// The shift keys that some keyboards send around the cursor keys are ignored.
+fn set_1_extended_key(code: u8) -> Option<KeyCode> {
+    use KeyCode::*;
+
+    Some(match code {
+        0x1c => KeypadEnter,
+        0x1d => RightControl,
+        0x35 => KeypadDivide,
+        0x37 => PrintScreen,
+        0x38 => RightAlt,
+        0x47 => Home,
+        0x48 => Up,
+        0x49 => PageUp,
+        0x4b => Left,
+        0x4d => Right,
+        0x4f => End,
+        0x50 => Down,
+        0x51 => PageDown,
+        0x52 => Insert,
+        0x53 => Delete,
+        0x5b => LeftGui,
+        0x5c => RightGui,
+        0x5d => Menu,
+        _ => return None,
+    })
+}
+
+/// Returns the key with the scancode in set 2.
+fn set_2_key(code: u8) -> Option<KeyCode> {
+    use KeyCode::*;
+
+    Some(match code {
+        0x01 => F9,
+        0x03 => F5,
+        0x04 => F3,
+        0x05 => F1,
+        0x06 => F2,
+        0x07 => F12,
+        0x09 => F10,
+        0x0a => F8,
+        0x0b => F6,
+        0x0c => F4,
+        0x0d => Tab,
+        0x0e => Grave,
+        0x11 => LeftAlt,
+        0x12 => LeftShift,
+        0x14 => LeftControl,
+        0x15 => Q,
+        0x16 => Digit1,
+        0x1a => Z,
+        0x1b => S,
+        0x1c => A,
+        0x1d => W,
+        0x1e => Digit2,
+        0x21 => C,
+        0x22 => X,
+        0x23 => D,
+        0x24 => E,
+        0x25 => Digit4,
+        0x26 => Digit3,
+        0x29 => Space,
+        0x2a => V,
+        0x2b => F,
+        0x2c => T,
+        0x2d => R,
+        0x2e => Digit5,
+        0x31 => N,
+        0x32 => B,
+        0x33 => H,
+        0x34 => G,
+        0x35 => Y,
+        0x36 => Digit6,
+        0x3a => M,
+        0x3b => J,
+        0x3c => U,
+        0x3d => Digit7,
+        0x3e => Digit8,
+        0x41 => Comma,
+        0x42 => K,
+        0x43 => I,
+        0x44 => O,
+        0x45 => Digit0,
+        0x46 => Digit9,
+        0x49 => Period,
+        0x4a => Slash,
+        0x4b => L,
+        0x4c => Semicolon,
+        0x4d => P,
+        0x4e => Minus,
+        0x52 => Apostrophe,
+        0x54 => LeftBracket,
+        0x55 => Equals,
+        0x58 => CapsLock,
+        0x59 => RightShift,
+        0x5a => Enter,
+        0x5b => RightBracket,
+        0x5d => Backslash,
+        0x61 => NonUsBackslash,
+        0x66 => Backspace,
+        0x69 => Keypad1,
+        0x6b => Keypad4,
+        0x6c => Keypad7,
+        0x70 => Keypad0,
+        0x71 => KeypadPeriod,
+        0x72 => Keypad2,
+        0x73 => Keypad5,
+        0x74 => Keypad6,
+        0x75 => Keypad8,
+        0x76 => Escape,
+        0x77 => NumLock,
+        0x78 => F11,
+        0x79 => KeypadPlus,
+        0x7a => Keypad3,
+        0x7b => KeypadMinus,
+        0x7c => KeypadMultiply,
+        0x7d => Keypad9,
+        0x7e => ScrollLock,
+        0x83 => F7,
+        _ => return None,
+    })
+}
+
+/// Returns the key with the scancode after the extended prefix in set 2.
+///
+/// The shift keys that some keyboards send around the cursor keys are ignored.
+fn set_2_extended_key(code: u8) -> Option<KeyCode> {
+    use KeyCode::*;
+
+    Some(match code {
+        0x11 => RightAlt,
+        0x14 => RightControl,
+        0x1f => LeftGui,
+        0x27 => RightGui,
+        0x2f => Menu,
+        0x4a => KeypadDivide,
+        0x5a => KeypadEnter,
+        0x69 => End,
+        0x6b => Left,
+        0x6c => Home,
+        0x70 => Insert,
+        0x71 => Delete,
+        0x72 => Down,
+        0x74 => Right,
+        0x75 => Up,
+        0x7a => PageDown,
+        0x7c => PrintScreen,
+        0x7d => PageUp,
+        _ => return None,
+    })
+}
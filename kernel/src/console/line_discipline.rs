@@ -0,0 +1,299 @@
+//! Provides the line discipline that turns the characters typed on a console into lines.
+//!
+//! The characters are collected until a line is completed with a carriage return or a line feed.
+//! Until then the line can be edited with backspace, which erases the last character,
+//! with `Ctrl-U`, which erases the whole line, and with `Ctrl-C`, which discards it.
+//! Escape sequences sent by terminals, for example for the cursor keys, are ignored.
+//!
+//! The line discipline doesn't write anything itself, it returns the edits that should be echoed instead.
+//! Their `Display` implementation produces the text that shows them on a terminal.
+
+use core::{char::REPLACEMENT_CHARACTER, fmt, str};
+
+use super::ansi::{Action, Parser};
+
+/// The maximum length of a line in bytes.
+///
+/// Further characters are ignored until the line is completed.
+pub const MAXIMUM_LINE_LENGTH: usize = 256;
+
+/// The number of completed lines that are kept until they are read.
+///
+/// If more lines are completed, the oldest one is dropped.
+const LINE_COUNT: usize = 8;
+
+/// The character sent by the backspace key of most terminals.
+const DELETE: char = '\x7f';
+
+/// The character that erases the previous character.
+const BACKSPACE: char = '\x08';
+
+/// The character that erases the whole line, sent by `Ctrl-U`.
+const KILL: char = '\x15';
+
+/// The character that discards the line, sent by `Ctrl-C`.
+const INTERRUPT: char = '\x03';
+
+/// A change to the line that is being edited.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edit {
+    /// The character was appended to the line.
+    Insert(char),
+    /// The given number of characters were erased from the end of the line.
+    Erase(usize),
+    /// The line was completed.
+    Complete,
+    /// The line was discarded.
+    Cancel,
+}
+
+impl fmt::Display for Edit {
+    /// Writes the text that shows the edit on a terminal.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Edit::Insert(character) => write!(f, "{}", character),
+            Edit::Erase(count) => {
+                for _ in 0..count {
+                    f.write_str("\x08 \x08")?;
+                }
+
+                Ok(())
+            }
+            Edit::Complete => f.write_str("\n"),
+            Edit::Cancel => f.write_str("^C\n"),
+        }
+    }
+}
+
+/// A line of input.
+#[derive(Clone, Copy)]
+pub struct Line {
+    /// The bytes of the line.
+    bytes: [u8; MAXIMUM_LINE_LENGTH],
+    /// The length of the line in bytes.
+    length: usize,
+}
+
+impl Line {
+    /// An empty line.
+    const EMPTY: Line = Line {
+        bytes: [0; MAXIMUM_LINE_LENGTH],
+        length: 0,
+    };
+
+    /// Returns the text of the line, without the line ending.
+    pub fn as_str(&self) -> &str {
+        // Only whole characters are stored.
+        str::from_utf8(&self.bytes[..self.length]).unwrap_or("")
+    }
+
+    /// Appends the character, unless the line is full.
+    fn push(&mut self, character: char) -> bool {
+        let end = self.length + character.len_utf8();
+
+        if end > MAXIMUM_LINE_LENGTH {
+            return false;
+        }
+
+        character.encode_utf8(&mut self.bytes[self.length..end]);
+        self.length = end;
+
+        true
+    }
+
+    /// Removes the last character, if there is one.
+    fn pop(&mut self) -> Option<char> {
+        let character = self.as_str().chars().next_back()?;
+
+        self.length -= character.len_utf8();
+
+        Some(character)
+    }
+
+    /// Returns the number of characters in the line.
+    fn character_count(&self) -> usize {
+        self.as_str().chars().count()
+    }
+}
+
+/// Collects the characters typed on a console into lines.
+pub struct LineDiscipline {
+    /// The line that is being edited.
+    current: Line,
+    /// The completed lines that were not read yet, as a ring buffer.
+    lines: [Line; LINE_COUNT],
+    /// The index of the oldest completed line.
+    first_line: usize,
+    /// The number of completed lines.
+    line_count: usize,
+    /// Filters the escape sequences out of the input.
+    parser: Parser,
+    /// The bytes of a UTF-8 sequence that is not complete yet.
+    pending: [u8; 4],
+    /// The number of pending bytes.
+    pending_length: usize,
+    /// Whether the last character was a carriage return.
+    ///
+    /// A line feed right after it doesn't complete another line, since some terminals send both.
+    after_carriage_return: bool,
+    /// Whether the edits should be echoed.
+    echo: bool,
+}
+
+impl LineDiscipline {
+    /// Creates a line discipline with an empty line that echoes the input.
+    pub const fn new() -> LineDiscipline {
+        LineDiscipline {
+            current: Line::EMPTY,
+            lines: [Line::EMPTY; LINE_COUNT],
+            first_line: 0,
+            line_count: 0,
+            parser: Parser::new(),
+            pending: [0; 4],
+            pending_length: 0,
+            after_carriage_return: false,
+            echo: true,
+        }
+    }
+
+    /// Checks if the edits should be echoed.
+    pub fn echo(&self) -> bool {
+        self.echo
+    }
+
+    /// Sets whether the edits should be echoed.
+    pub fn set_echo(&mut self, echo: bool) {
+        self.echo = echo;
+    }
+
+    /// Feeds a byte of UTF-8 encoded input to the line discipline.
+    ///
+    /// Returns the edit, if the byte completed a character that changed the line.
+    /// Invalid bytes are replaced by the replacement character, while sequences that are cut short are dropped.
+    pub fn receive_byte(&mut self, byte: u8) -> Option<Edit> {
+        if self.pending_length > 0 && byte & 0xc0 != 0x80 {
+            self.pending_length = 0;
+        }
+
+        self.pending[self.pending_length] = byte;
+        self.pending_length += 1;
+
+        if self.pending_length < sequence_length(self.pending[0]) {
+            return None;
+        }
+
+        let character = str::from_utf8(&self.pending[..self.pending_length])
+            .ok()
+            .and_then(|string| string.chars().next())
+            .unwrap_or(REPLACEMENT_CHARACTER);
+
+        self.pending_length = 0;
+
+        self.receive(character)
+    }
+
+    /// Feeds a character to the line discipline.
+    ///
+    /// Returns the edit, if the character changed the line.
+    pub fn receive(&mut self, character: char) -> Option<Edit> {
+        let after_carriage_return = self.after_carriage_return;
+        self.after_carriage_return = false;
+
+        let character = match self.parser.advance(character)? {
+            Action::Character(character) => character,
+            Action::Escape(_) | Action::ControlSequence(_) => return None,
+        };
+
+        match character {
+            '\r' => {
+                self.after_carriage_return = true;
+                self.complete()
+            }
+            '\n' if after_carriage_return => None,
+            '\n' => self.complete(),
+            BACKSPACE | DELETE => self.current.pop().map(|_| Edit::Erase(1)),
+            KILL => {
+                let count = self.current.character_count();
+                self.current.length = 0;
+
+                if count > 0 {
+                    Some(Edit::Erase(count))
+                } else {
+                    None
+                }
+            }
+            INTERRUPT => {
+                self.current.length = 0;
+
+                Some(Edit::Cancel)
+            }
+            _ if character == '\t' || !character.is_control() => {
+                if self.current.push(character) {
+                    Some(Edit::Insert(character))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Completes the current line and queues it for reading.
+    fn complete(&mut self) -> Option<Edit> {
+        if self.line_count == LINE_COUNT {
+            self.first_line = (self.first_line + 1) % LINE_COUNT;
+            self.line_count -= 1;
+        }
+
+        self.lines[(self.first_line + self.line_count) % LINE_COUNT] = self.current;
+        self.line_count += 1;
+        self.current.length = 0;
+
+        Some(Edit::Complete)
+    }
+
+    /// Returns the oldest completed line that was not read yet.
+    pub fn read_line(&mut self) -> Option<Line> {
+        if self.line_count == 0 {
+            return None;
+        }
+
+        let line = self.lines[self.first_line];
+
+        self.first_line = (self.first_line + 1) % LINE_COUNT;
+        self.line_count -= 1;
+
+        Some(line)
+    }
+
+    /// Returns the line that is being edited.
+    pub fn current_line(&self) -> &str {
+        self.current.as_str()
+    }
+
+    /// Discards the line that is being edited and all completed lines.
+    pub fn clear(&mut self) {
+        self.current.length = 0;
+        self.line_count = 0;
+        self.pending_length = 0;
+        self.parser = Parser::new();
+    }
+}
+
+impl Default for LineDiscipline {
+    fn default() -> LineDiscipline {
+        LineDiscipline::new()
+    }
+}
+
+/// Returns the length of the UTF-8 sequence started by the byte.
+///
+/// Bytes that cannot start a sequence count as a sequence of their own.
+fn sequence_length(first: u8) -> usize {
+    match first {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 1,
+    }
+}
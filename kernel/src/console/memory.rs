@@ -0,0 +1,85 @@
+//! Provides a console that keeps the most recent output in memory.
+//!
+//! The buffer is a static array, so it can be used before the heap is set up.
+//! Once it is full, the oldest output is overwritten.
+
+use core::fmt::{self, Write};
+
+use super::Sink;
+use crate::sync::Mutex;
+
+/// The number of bytes of output that are kept.
+const BUFFER_SIZE: usize = 0x10000;
+
+/// The buffer of the memory console.
+static BUFFER: Mutex<RingBuffer> = Mutex::new(RingBuffer {
+    bytes: [0; BUFFER_SIZE],
+    end: 0,
+    length: 0,
+});
+
+/// A buffer that overwrites its oldest bytes when it is full.
+struct RingBuffer {
+    /// The stored bytes.
+    bytes: [u8; BUFFER_SIZE],
+    /// The index after the most recently written byte.
+    end: usize,
+    /// The number of stored bytes.
+    length: usize,
+}
+
+impl RingBuffer {
+    /// Returns the stored bytes as two slices, with the older bytes in the first one.
+    fn as_slices(&self) -> (&[u8], &[u8]) {
+        if self.length < BUFFER_SIZE {
+            (&self.bytes[self.end - self.length..self.end], &[])
+        } else {
+            (&self.bytes[self.end..], &self.bytes[..self.end])
+        }
+    }
+}
+
+impl Write for RingBuffer {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        for &byte in string.as_bytes() {
+            self.bytes[self.end] = byte;
+            self.end = (self.end + 1) % BUFFER_SIZE;
+            self.length = (self.length + 1).min(BUFFER_SIZE);
+        }
+
+        Ok(())
+    }
+}
+
+/// The console sink that keeps the output in memory.
+pub struct MemorySink;
+
+impl Sink for MemorySink {
+    fn write_fmt(&self, args: fmt::Arguments) {
+        // Writing to memory cannot fail.
+        BUFFER.lock().write_fmt(args).ok();
+    }
+}
+
+/// Calls the function with the kept output, from the oldest to the newest byte.
+///
+/// The output is passed in up to two parts, since the buffer may wrap around.
+/// The first part may start in the middle of a UTF-8 sequence, if older output was overwritten.
+pub fn read<F: FnMut(&[u8])>(mut function: F) {
+    let buffer = BUFFER.lock();
+    let (older, newer) = buffer.as_slices();
+
+    function(older);
+
+    if !newer.is_empty() {
+        function(newer);
+    }
+}
+
+/// Forgets the kept output.
+pub fn clear() {
+    let mut buffer = BUFFER.lock();
+
+    buffer.end = 0;
+    buffer.length = 0;
+}
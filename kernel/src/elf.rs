@@ -0,0 +1,144 @@
+//! This module loads ELF64 executables into user address spaces.
+//!
+//! Statically linked executables and static position independent executables are supported.
+//! Every malformed or unsupported binary is rejected with an `ElfError` describing the problem.
+
+mod header;
+mod loader;
+mod relocation;
+
+use core::fmt;
+
+pub use self::loader::{
+    load, LoadedProgram, INITIAL_STACK_SIZE, MAXIMUM_STACK_SIZE, PIE_LOAD_BASE, STACK_TOP,
+};
+use crate::memory::{MemoryError, VirtualAddress};
+
+/// The reasons an ELF file can be rejected for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ElfError {
+    /// The file ends before the named structure.
+    Truncated(&'static str),
+    /// The file does not start with the ELF magic number.
+    InvalidMagic,
+    /// The file is not a 64-bit ELF file.
+    UnsupportedClass(u8),
+    /// The file is not encoded in little endian.
+    UnsupportedEncoding(u8),
+    /// The ELF version is not the current version.
+    UnsupportedVersion(u32),
+    /// The file targets an unsupported operating system ABI.
+    UnsupportedAbi(u8),
+    /// The file is neither an executable nor a position independent executable.
+    UnsupportedType(u16),
+    /// The file was compiled for a different machine.
+    UnsupportedMachine(u16),
+    /// The size of the file header or of the program headers is wrong.
+    InvalidHeaderSize,
+    /// The file contains no loadable segments.
+    NoLoadableSegments,
+    /// The file requests a dynamic linker.
+    InterpreterRequested,
+    /// The segment with the given index refers to data outside of the file.
+    SegmentOutOfFile(usize),
+    /// The segment with the given index is larger in the file than in memory.
+    SegmentFileSizeTooLarge(usize),
+    /// The alignment of the segment with the given index is invalid.
+    InvalidSegmentAlignment(usize),
+    /// The segment with the given index would be loaded outside of user space.
+    SegmentOutsideUserSpace(usize),
+    /// The segments with the given indices overlap.
+    OverlappingSegments(usize, usize),
+    /// The entry point is not inside an executable segment.
+    InvalidEntryPoint(VirtualAddress),
+    /// The dynamic section is malformed.
+    InvalidDynamicSection,
+    /// The file contains a relocation of an unsupported type.
+    UnsupportedRelocation(u32),
+    /// A relocation refers to memory outside of the loaded segments.
+    RelocationOutOfBounds(VirtualAddress),
+    /// The arguments and environment don't fit onto the initial stack.
+    ArgumentsTooLarge,
+    /// Building the address space failed.
+    Memory(MemoryError),
+}
+
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ElfError::Truncated(what) => write!(f, "the file is too short to contain the {}", what),
+            ElfError::InvalidMagic => write!(f, "the file is not an ELF file"),
+            ElfError::UnsupportedClass(class) => write!(
+                f,
+                "the ELF class {} is not supported (expected 64-bit)",
+                class
+            ),
+            ElfError::UnsupportedEncoding(encoding) => write!(
+                f,
+                "the data encoding {} is not supported (expected little endian)",
+                encoding
+            ),
+            ElfError::UnsupportedVersion(version) => {
+                write!(f, "the ELF version {} is not supported", version)
+            }
+            ElfError::UnsupportedAbi(abi) => write!(f, "the OS ABI {} is not supported", abi),
+            ElfError::UnsupportedType(file_type) => write!(
+                f,
+                "the file type {} is not supported (expected an executable)",
+                file_type
+            ),
+            ElfError::UnsupportedMachine(machine) => write!(
+                f,
+                "the file was compiled for the wrong machine ({})",
+                machine
+            ),
+            ElfError::InvalidHeaderSize => write!(f, "the header sizes are invalid"),
+            ElfError::NoLoadableSegments => write!(f, "the file contains no loadable segments"),
+            ElfError::InterpreterRequested => write!(
+                f,
+                "the file requests a dynamic linker, but only static executables are supported"
+            ),
+            ElfError::SegmentOutOfFile(index) => {
+                write!(f, "segment {} refers to data outside of the file", index)
+            }
+            ElfError::SegmentFileSizeTooLarge(index) => {
+                write!(f, "segment {} is larger in the file than in memory", index)
+            }
+            ElfError::InvalidSegmentAlignment(index) => {
+                write!(f, "segment {} has an invalid alignment", index)
+            }
+            ElfError::SegmentOutsideUserSpace(index) => {
+                write!(f, "segment {} would be loaded outside of user space", index)
+            }
+            ElfError::OverlappingSegments(first, second) => {
+                write!(f, "the segments {} and {} overlap", first, second)
+            }
+            ElfError::InvalidEntryPoint(entry) => write!(
+                f,
+                "the entry point {} is not inside an executable segment",
+                entry
+            ),
+            ElfError::InvalidDynamicSection => write!(f, "the dynamic section is malformed"),
+            ElfError::UnsupportedRelocation(relocation_type) => write!(
+                f,
+                "the relocation type {} is not supported",
+                relocation_type
+            ),
+            ElfError::RelocationOutOfBounds(address) => write!(
+                f,
+                "the relocation at {} is outside of the loaded segments",
+                address
+            ),
+            ElfError::ArgumentsTooLarge => {
+                write!(f, "the arguments don't fit onto the initial stack")
+            }
+            ElfError::Memory(error) => write!(f, "could not build the address space: {}", error),
+        }
+    }
+}
+
+impl From<MemoryError> for ElfError {
+    fn from(error: MemoryError) -> ElfError {
+        ElfError::Memory(error)
+    }
+}
@@ -0,0 +1,317 @@
+//! Parses and validates the file header and the program headers of ELF64 files.
+
+use super::ElfError;
+use crate::memory::VirtualAddress;
+
+/// The size of the ELF64 file header.
+const FILE_HEADER_SIZE: usize = 64;
+
+/// The size of an ELF64 program header.
+const PROGRAM_HEADER_SIZE: usize = 56;
+
+/// The magic number at the start of every ELF file.
+const MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+
+/// The class of 64-bit ELF files.
+const CLASS_64: u8 = 2;
+
+/// The little endian data encoding.
+const ENCODING_LITTLE_ENDIAN: u8 = 1;
+
+/// The current ELF version.
+const VERSION_CURRENT: u32 = 1;
+
+/// The System V ABI.
+const ABI_SYSTEM_V: u8 = 0;
+
+/// The GNU ABI, which toolchains use for binaries using GNU extensions.
+const ABI_GNU: u8 = 3;
+
+/// The file type of executables.
+pub const TYPE_EXECUTABLE: u16 = 2;
+
+/// The file type of shared objects, which includes position independent executables.
+pub const TYPE_SHARED: u16 = 3;
+
+/// The machine the loaded files must be compiled for (x86_64).
+#[cfg(target_arch = "x86_64")]
+const MACHINE: u16 = 62;
+
+/// A segment that is loaded into memory.
+pub const SEGMENT_LOAD: u32 = 1;
+
+/// A segment containing the dynamic section.
+pub const SEGMENT_DYNAMIC: u32 = 2;
+
+/// A segment naming the dynamic linker.
+const SEGMENT_INTERPRETER: u32 = 3;
+
+/// A segment containing the program headers themselves.
+pub const SEGMENT_PROGRAM_HEADERS: u32 = 6;
+
+/// The segment is executable.
+pub const FLAG_EXECUTABLE: u32 = 1;
+
+/// The segment is writable.
+pub const FLAG_WRITABLE: u32 = 2;
+
+/// Reads a little endian integer of `size` bytes at `offset` in `data`.
+///
+/// `what` names the structure being read for the error message.
+pub fn read_integer(
+    data: &[u8],
+    offset: usize,
+    size: usize,
+    what: &'static str,
+) -> Result<u64, ElfError> {
+    let end = offset.checked_add(size).ok_or(ElfError::Truncated(what))?;
+    let bytes = data.get(offset..end).ok_or(ElfError::Truncated(what))?;
+
+    Ok(bytes
+        .iter()
+        .rev()
+        .fold(0, |value, &byte| value << 8 | u64::from(byte)))
+}
+
+/// A program header describing a segment.
+#[derive(Clone, Copy, Debug)]
+pub struct ProgramHeader {
+    /// The type of the segment.
+    pub segment_type: u32,
+    /// The permissions of the segment.
+    pub flags: u32,
+    /// The offset of the segment in the file.
+    pub offset: usize,
+    /// The virtual address of the segment.
+    pub virtual_address: usize,
+    /// The number of bytes of the segment stored in the file.
+    pub file_size: usize,
+    /// The number of bytes of the segment in memory.
+    pub memory_size: usize,
+    /// The alignment of the segment.
+    pub alignment: usize,
+}
+
+impl ProgramHeader {
+    /// Parses the program header at `offset` in `data`.
+    fn parse(data: &[u8], offset: usize) -> Result<ProgramHeader, ElfError> {
+        let field =
+            |field_offset, size| read_integer(data, offset + field_offset, size, "program header");
+
+        Ok(ProgramHeader {
+            segment_type: field(0, 4)? as u32,
+            flags: field(4, 4)? as u32,
+            offset: field(8, 8)? as usize,
+            virtual_address: field(16, 8)? as usize,
+            file_size: field(32, 8)? as usize,
+            memory_size: field(40, 8)? as usize,
+            alignment: field(48, 8)? as usize,
+        })
+    }
+
+    /// Checks if the segment is loaded into memory.
+    pub fn is_load(&self) -> bool {
+        self.segment_type == SEGMENT_LOAD
+    }
+
+    /// Checks if the given (unrelocated) address lies inside the segment in memory.
+    pub fn contains(&self, address: usize) -> bool {
+        address >= self.virtual_address && address - self.virtual_address < self.memory_size
+    }
+
+    /// Returns the file offset of the given (unrelocated) address, if it is backed by the file.
+    pub fn file_offset(&self, address: usize) -> Option<usize> {
+        if address >= self.virtual_address && address - self.virtual_address < self.file_size {
+            Some(self.offset + (address - self.virtual_address))
+        } else {
+            None
+        }
+    }
+}
+
+/// A validated ELF64 file.
+pub struct ElfFile<'a> {
+    /// The contents of the file.
+    data: &'a [u8],
+    /// The type of the file.
+    pub file_type: u16,
+    /// The (unrelocated) entry point.
+    pub entry_point: usize,
+    /// The offset of the program headers in the file.
+    pub program_header_offset: usize,
+    /// The number of program headers.
+    pub program_header_count: usize,
+}
+
+impl<'a> ElfFile<'a> {
+    /// Parses and validates the headers of the ELF file contained in `data`.
+    pub fn parse(data: &'a [u8]) -> Result<ElfFile<'a>, ElfError> {
+        if data.len() < FILE_HEADER_SIZE {
+            return Err(ElfError::Truncated("file header"));
+        }
+
+        if data[0..4] != MAGIC {
+            return Err(ElfError::InvalidMagic);
+        }
+        if data[4] != CLASS_64 {
+            return Err(ElfError::UnsupportedClass(data[4]));
+        }
+        if data[5] != ENCODING_LITTLE_ENDIAN {
+            return Err(ElfError::UnsupportedEncoding(data[5]));
+        }
+        if u32::from(data[6]) != VERSION_CURRENT {
+            return Err(ElfError::UnsupportedVersion(u32::from(data[6])));
+        }
+        if data[7] != ABI_SYSTEM_V && data[7] != ABI_GNU {
+            return Err(ElfError::UnsupportedAbi(data[7]));
+        }
+
+        let field = |offset, size| read_integer(data, offset, size, "file header");
+
+        let file_type = field(16, 2)? as u16;
+        if file_type != TYPE_EXECUTABLE && file_type != TYPE_SHARED {
+            return Err(ElfError::UnsupportedType(file_type));
+        }
+
+        let machine = field(18, 2)? as u16;
+        if machine != MACHINE {
+            return Err(ElfError::UnsupportedMachine(machine));
+        }
+
+        let version = field(20, 4)? as u32;
+        if version != VERSION_CURRENT {
+            return Err(ElfError::UnsupportedVersion(version));
+        }
+
+        if field(52, 2)? as usize != FILE_HEADER_SIZE
+            || field(54, 2)? as usize != PROGRAM_HEADER_SIZE
+        {
+            return Err(ElfError::InvalidHeaderSize);
+        }
+
+        let file = ElfFile {
+            data,
+            file_type,
+            entry_point: field(24, 8)? as usize,
+            program_header_offset: field(32, 8)? as usize,
+            program_header_count: field(56, 2)? as usize,
+        };
+
+        let program_headers_end = file
+            .program_header_count
+            .checked_mul(PROGRAM_HEADER_SIZE)
+            .and_then(|size| size.checked_add(file.program_header_offset));
+        match program_headers_end {
+            Some(end) if end <= data.len() => (),
+            _ => return Err(ElfError::Truncated("program headers")),
+        }
+
+        file.validate_segments()?;
+
+        Ok(file)
+    }
+
+    /// Validates the segments described by the program headers.
+    fn validate_segments(&self) -> Result<(), ElfError> {
+        let mut load_segments = 0;
+
+        for index in 0..self.program_header_count {
+            let segment = self.program_header(index)?;
+
+            if segment.segment_type == SEGMENT_INTERPRETER {
+                return Err(ElfError::InterpreterRequested);
+            }
+
+            if !segment.is_load() {
+                continue;
+            }
+
+            load_segments += 1;
+
+            match segment.offset.checked_add(segment.file_size) {
+                Some(end) if end <= self.data.len() => (),
+                _ => return Err(ElfError::SegmentOutOfFile(index)),
+            }
+
+            if segment.file_size > segment.memory_size {
+                return Err(ElfError::SegmentFileSizeTooLarge(index));
+            }
+
+            if segment.alignment > 1
+                && (!segment.alignment.is_power_of_two()
+                    || segment.virtual_address % segment.alignment
+                        != segment.offset % segment.alignment)
+            {
+                return Err(ElfError::InvalidSegmentAlignment(index));
+            }
+
+            if segment
+                .virtual_address
+                .checked_add(segment.memory_size)
+                .is_none()
+            {
+                return Err(ElfError::SegmentOutsideUserSpace(index));
+            }
+
+            for other_index in 0..index {
+                let other = self.program_header(other_index)?;
+
+                if other.is_load()
+                    && segment.memory_size > 0
+                    && other.memory_size > 0
+                    && segment.virtual_address < other.virtual_address + other.memory_size
+                    && other.virtual_address < segment.virtual_address + segment.memory_size
+                {
+                    return Err(ElfError::OverlappingSegments(other_index, index));
+                }
+            }
+        }
+
+        if load_segments == 0 {
+            return Err(ElfError::NoLoadableSegments);
+        }
+
+        let entry_point_valid = self.program_headers().any(|segment| {
+            segment.is_load()
+                && segment.flags & FLAG_EXECUTABLE != 0
+                && segment.contains(self.entry_point)
+        });
+        if !entry_point_valid {
+            return Err(ElfError::InvalidEntryPoint(VirtualAddress::new(
+                self.entry_point,
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the program header with the given index.
+    pub fn program_header(&self, index: usize) -> Result<ProgramHeader, ElfError> {
+        ProgramHeader::parse(
+            self.data,
+            self.program_header_offset + index * PROGRAM_HEADER_SIZE,
+        )
+    }
+
+    /// Returns an iterator over all program headers.
+    ///
+    /// This must only be used after validation, when all program headers are known to be readable.
+    pub fn program_headers<'b>(&'b self) -> impl Iterator<Item = ProgramHeader> + 'b {
+        (0..self.program_header_count).filter_map(move |index| self.program_header(index).ok())
+    }
+
+    /// Returns the contents of the file.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Returns the part of the file that is loaded for the given segment.
+    pub fn segment_data(&self, segment: &ProgramHeader) -> &'a [u8] {
+        &self.data[segment.offset..segment.offset + segment.file_size]
+    }
+
+    /// Returns the size of a program header.
+    pub fn program_header_size(&self) -> usize {
+        PROGRAM_HEADER_SIZE
+    }
+}
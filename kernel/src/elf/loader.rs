@@ -0,0 +1,290 @@
+//! Loads the segments of an ELF file into a fresh address space and prepares the initial stack.
+//!
+//! The initial stack follows the System V ABI: the stack pointer points to the argument count,
+//! followed by the argument pointers, the environment pointers and the auxiliary vector.
+
+use super::{
+    header::{ElfFile, FLAG_EXECUTABLE, FLAG_WRITABLE, SEGMENT_PROGRAM_HEADERS, TYPE_SHARED},
+    relocation::relocate,
+    ElfError,
+};
+use crate::{
+    arch::{Arch, Architecture},
+    memory::{AddressSpace, PageFlags, VirtualAddress, PAGE_SIZE},
+};
+
+/// The address at which position independent executables are loaded.
+pub const PIE_LOAD_BASE: VirtualAddress =
+    VirtualAddress::new(<Arch as Architecture>::USER_SPACE_START.as_usize() + 0x40_0000);
+
+/// The address above the initial stack.
+pub const STACK_TOP: VirtualAddress = <Arch as Architecture>::USER_SPACE_END;
+
+/// The maximum size of the arguments, environment variables and auxiliary vector on the initial stack in bytes.
+pub const INITIAL_STACK_SIZE: usize = 16 * PAGE_SIZE;
+
+/// The size the initial stack can grow to in bytes.
+///
+/// Stack pages are only mapped when they are used and a guard page lies below the stack.
+pub const MAXIMUM_STACK_SIZE: usize = 256 * PAGE_SIZE;
+
+/// The alignment of the stack pointer on program entry.
+const STACK_ALIGNMENT: usize = 16;
+
+/// The size of a word on the stack.
+const WORD_SIZE: usize = 8;
+
+/// Marks the end of the auxiliary vector.
+const AUXILIARY_NULL: u64 = 0;
+
+/// An auxiliary vector entry that should be ignored.
+const AUXILIARY_IGNORE: u64 = 1;
+
+/// The address of the program headers in memory.
+const AUXILIARY_PROGRAM_HEADERS: u64 = 3;
+
+/// The size of a program header.
+const AUXILIARY_PROGRAM_HEADER_SIZE: u64 = 4;
+
+/// The number of program headers.
+const AUXILIARY_PROGRAM_HEADER_COUNT: u64 = 5;
+
+/// The page size.
+const AUXILIARY_PAGE_SIZE: u64 = 6;
+
+/// The base address of the dynamic linker, which is zero for static executables.
+const AUXILIARY_BASE: u64 = 7;
+
+/// The entry point of the program.
+const AUXILIARY_ENTRY: u64 = 9;
+
+/// A program that was loaded into a fresh address space.
+pub struct LoadedProgram {
+    /// The address space containing the program and its initial stack.
+    pub address_space: AddressSpace,
+    /// The address at which execution should start.
+    pub entry_point: VirtualAddress,
+    /// The initial value of the stack pointer.
+    pub stack_pointer: VirtualAddress,
+}
+
+/// Converts the segment flags of a program header to page flags.
+fn page_flags(segment_flags: u32) -> PageFlags {
+    let mut flags = PageFlags::USER_ACCESSIBLE;
+
+    if segment_flags & FLAG_WRITABLE != 0 {
+        flags |= PageFlags::WRITABLE;
+    }
+    if segment_flags & FLAG_EXECUTABLE != 0 {
+        flags |= PageFlags::EXECUTABLE;
+    }
+
+    flags
+}
+
+/// Loads the ELF executable contained in `data` into a new address space.
+///
+/// The initial stack is set up with the given arguments and environment variables.
+pub fn load(
+    data: &[u8],
+    arguments: &[&str],
+    environment: &[&str],
+) -> Result<LoadedProgram, ElfError> {
+    let file = ElfFile::parse(data)?;
+
+    let load_bias = if file.file_type == TYPE_SHARED {
+        let lowest_address = file
+            .program_headers()
+            .filter(|segment| segment.is_load())
+            .map(|segment| segment.virtual_address)
+            .min()
+            .unwrap_or(0);
+
+        PIE_LOAD_BASE
+            .as_usize()
+            .wrapping_sub(lowest_address & !(PAGE_SIZE - 1))
+    } else {
+        0
+    };
+
+    let mut address_space = AddressSpace::new()?;
+
+    for index in 0..file.program_header_count {
+        let segment = file.program_header(index)?;
+
+        if !segment.is_load() {
+            continue;
+        }
+
+        let start = VirtualAddress::new(load_bias.wrapping_add(segment.virtual_address));
+
+        // The area of the initial stack and its guard page are reserved.
+        let below_stack = start
+            .checked_add(segment.memory_size)
+            .map(|end| end <= STACK_TOP - MAXIMUM_STACK_SIZE - PAGE_SIZE)
+            .unwrap_or(false);
+        if !AddressSpace::is_user_range(start, segment.memory_size) || !below_stack {
+            return Err(ElfError::SegmentOutsideUserSpace(index));
+        }
+
+        let flags = page_flags(segment.flags);
+        let mut page = start.page_align_down();
+
+        // Segments may share a page, in which case the page gets the permissions of both.
+        while page < start + segment.memory_size {
+            match address_space.flags(page) {
+                Some(existing_flags) => address_space.protect(page, existing_flags | flags)?,
+                None => address_space.map(page, flags)?,
+            }
+
+            page += PAGE_SIZE;
+        }
+
+        // The rest of the segment stays zeroed.
+        address_space.write(start, file.segment_data(&segment))?;
+    }
+
+    if file.file_type == TYPE_SHARED {
+        relocate(&file, &mut address_space, load_bias)?;
+    }
+
+    let entry_point = VirtualAddress::new(load_bias.wrapping_add(file.entry_point));
+
+    let stack_pointer = setup_stack(
+        &mut address_space,
+        &file,
+        load_bias,
+        entry_point,
+        arguments,
+        environment,
+    )?;
+
+    Ok(LoadedProgram {
+        address_space,
+        entry_point,
+        stack_pointer,
+    })
+}
+
+/// Returns the (relocated) address of the program headers in memory, if they are loaded.
+fn program_headers_address(file: &ElfFile, load_bias: usize) -> Option<usize> {
+    let address = file
+        .program_headers()
+        .find(|segment| segment.segment_type == SEGMENT_PROGRAM_HEADERS)
+        .map(|segment| segment.virtual_address)
+        .or_else(|| {
+            file.program_headers()
+                .filter(|segment| segment.is_load())
+                .find(|segment| {
+                    file.program_header_offset >= segment.offset
+                        && file.program_header_offset - segment.offset < segment.file_size
+                })
+                .map(|segment| {
+                    segment.virtual_address + (file.program_header_offset - segment.offset)
+                })
+        })?;
+
+    Some(load_bias.wrapping_add(address))
+}
+
+/// Writes a word to `address` and advances it.
+fn push_word(
+    address_space: &mut AddressSpace,
+    address: &mut VirtualAddress,
+    value: u64,
+) -> Result<(), ElfError> {
+    address_space.write(*address, &value.to_le_bytes())?;
+    *address += WORD_SIZE;
+
+    Ok(())
+}
+
+/// Writes a null terminated copy of `string` to `address` and advances it.
+fn push_string(
+    address_space: &mut AddressSpace,
+    address: &mut VirtualAddress,
+    string: &str,
+) -> Result<(), ElfError> {
+    address_space.write(*address, string.as_bytes())?;
+    address_space.write(*address + string.len(), &[0])?;
+    *address += string.len() + 1;
+
+    Ok(())
+}
+
+/// Reserves the initial stack and fills it according to the System V ABI.
+///
+/// Returns the initial stack pointer.
+fn setup_stack(
+    address_space: &mut AddressSpace,
+    file: &ElfFile,
+    load_bias: usize,
+    entry_point: VirtualAddress,
+    arguments: &[&str],
+    environment: &[&str],
+) -> Result<VirtualAddress, ElfError> {
+    address_space.reserve_stack(STACK_TOP, MAXIMUM_STACK_SIZE)?;
+
+    let auxiliary_vector = [
+        match program_headers_address(file, load_bias) {
+            Some(address) => (AUXILIARY_PROGRAM_HEADERS, address as u64),
+            None => (AUXILIARY_IGNORE, 0),
+        },
+        (
+            AUXILIARY_PROGRAM_HEADER_SIZE,
+            file.program_header_size() as u64,
+        ),
+        (
+            AUXILIARY_PROGRAM_HEADER_COUNT,
+            file.program_header_count as u64,
+        ),
+        (AUXILIARY_PAGE_SIZE, PAGE_SIZE as u64),
+        (AUXILIARY_BASE, 0),
+        (AUXILIARY_ENTRY, entry_point.as_usize() as u64),
+        (AUXILIARY_NULL, 0),
+    ];
+
+    let strings_size = arguments
+        .iter()
+        .chain(environment.iter())
+        .try_fold(0usize, |size, string| size.checked_add(string.len() + 1))
+        .ok_or(ElfError::ArgumentsTooLarge)?;
+    let word_count = (1 + arguments.len() + 1 + environment.len() + 1)
+        .checked_add(2 * auxiliary_vector.len())
+        .ok_or(ElfError::ArgumentsTooLarge)?;
+    let total_size = word_count
+        .checked_mul(WORD_SIZE)
+        .and_then(|size| size.checked_add(strings_size))
+        .and_then(|size| size.checked_add(STACK_ALIGNMENT))
+        .ok_or(ElfError::ArgumentsTooLarge)?;
+
+    if total_size > INITIAL_STACK_SIZE {
+        return Err(ElfError::ArgumentsTooLarge);
+    }
+
+    let mut string_address = STACK_TOP - strings_size;
+    let stack_pointer = (string_address - word_count * WORD_SIZE).align_down(STACK_ALIGNMENT);
+    let mut word_address = stack_pointer;
+
+    push_word(address_space, &mut word_address, arguments.len() as u64)?;
+
+    for strings in &[arguments, environment] {
+        for string in strings.iter() {
+            push_word(
+                address_space,
+                &mut word_address,
+                string_address.as_usize() as u64,
+            )?;
+            push_string(address_space, &mut string_address, string)?;
+        }
+
+        push_word(address_space, &mut word_address, 0)?;
+    }
+
+    for &(key, value) in auxiliary_vector.iter() {
+        push_word(address_space, &mut word_address, key)?;
+        push_word(address_space, &mut word_address, value)?;
+    }
+
+    Ok(stack_pointer)
+}
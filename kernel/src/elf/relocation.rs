@@ -0,0 +1,125 @@
+//! Applies the relocations of static position independent executables.
+
+use super::{
+    header::{read_integer, ElfFile, ProgramHeader, SEGMENT_DYNAMIC},
+    ElfError,
+};
+use crate::memory::{AddressSpace, VirtualAddress};
+
+/// Marks the end of the dynamic section.
+const DYNAMIC_NULL: u64 = 0;
+
+/// Names a needed shared library.
+const DYNAMIC_NEEDED: u64 = 1;
+
+/// The address of the relocation table with addends.
+const DYNAMIC_RELA: u64 = 7;
+
+/// The size of the relocation table with addends.
+const DYNAMIC_RELA_SIZE: u64 = 8;
+
+/// The size of an entry in the relocation table with addends.
+const DYNAMIC_RELA_ENTRY_SIZE: u64 = 9;
+
+/// The address of the relocation table without addends.
+const DYNAMIC_REL: u64 = 17;
+
+/// The size of an entry in the dynamic section.
+const DYNAMIC_ENTRY_SIZE: usize = 16;
+
+/// The size of a relocation with addend.
+const RELA_ENTRY_SIZE: usize = 24;
+
+/// The relocation type that does nothing.
+#[cfg(target_arch = "x86_64")]
+const RELOCATION_NONE: u32 = 0;
+
+/// The relocation type that adds the load bias to the addend.
+#[cfg(target_arch = "x86_64")]
+const RELOCATION_RELATIVE: u32 = 8;
+
+/// Returns the file offset of the given (unrelocated) address, if a load segment contains it.
+fn file_offset(file: &ElfFile, address: usize) -> Option<usize> {
+    file.program_headers()
+        .filter(ProgramHeader::is_load)
+        .find_map(|segment| segment.file_offset(address))
+}
+
+/// Applies the relocations of `file`, which was loaded with the given bias into `address_space`.
+///
+/// Only relative relocations are supported, since static executables don't reference any symbols.
+pub fn relocate(
+    file: &ElfFile,
+    address_space: &mut AddressSpace,
+    load_bias: usize,
+) -> Result<(), ElfError> {
+    let dynamic = match file
+        .program_headers()
+        .find(|segment| segment.segment_type == SEGMENT_DYNAMIC)
+    {
+        Some(dynamic) => dynamic,
+        None => return Ok(()),
+    };
+
+    let mut table = None;
+    let mut table_size = 0;
+    let mut entry_size = RELA_ENTRY_SIZE;
+
+    for index in 0..dynamic.file_size / DYNAMIC_ENTRY_SIZE {
+        let offset = dynamic.offset + index * DYNAMIC_ENTRY_SIZE;
+        let tag = read_integer(file.data(), offset, 8, "dynamic section")?;
+        let value = read_integer(file.data(), offset + 8, 8, "dynamic section")?;
+
+        match tag {
+            DYNAMIC_NULL => break,
+            DYNAMIC_NEEDED => return Err(ElfError::InterpreterRequested),
+            DYNAMIC_RELA => table = Some(value as usize),
+            DYNAMIC_RELA_SIZE => table_size = value as usize,
+            DYNAMIC_RELA_ENTRY_SIZE => entry_size = value as usize,
+            DYNAMIC_REL => return Err(ElfError::InvalidDynamicSection),
+            _ => (),
+        }
+    }
+
+    let table = match table {
+        Some(table) => table,
+        None if table_size == 0 => return Ok(()),
+        None => return Err(ElfError::InvalidDynamicSection),
+    };
+
+    if entry_size != RELA_ENTRY_SIZE || table_size % RELA_ENTRY_SIZE != 0 {
+        return Err(ElfError::InvalidDynamicSection);
+    }
+
+    let table_offset = file_offset(file, table).ok_or(ElfError::InvalidDynamicSection)?;
+
+    for index in 0..table_size / RELA_ENTRY_SIZE {
+        let offset = table_offset + index * RELA_ENTRY_SIZE;
+        let target = read_integer(file.data(), offset, 8, "relocation table")? as usize;
+        let info = read_integer(file.data(), offset + 8, 8, "relocation table")?;
+        let addend = read_integer(file.data(), offset + 16, 8, "relocation table")?;
+
+        match info as u32 {
+            RELOCATION_NONE => (),
+            RELOCATION_RELATIVE => {
+                let address = VirtualAddress::new(load_bias.wrapping_add(target));
+                let in_bounds = file.program_headers().any(|segment| {
+                    segment.is_load()
+                        && segment.contains(target)
+                        && segment.contains(target.wrapping_add(7))
+                });
+
+                if !in_bounds {
+                    return Err(ElfError::RelocationOutOfBounds(address));
+                }
+
+                let value = (load_bias as u64).wrapping_add(addend);
+
+                address_space.write(address, &value.to_le_bytes())?;
+            }
+            relocation_type => return Err(ElfError::UnsupportedRelocation(relocation_type)),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,248 @@
+//! Provides access to the initial ramdisk.
+//!
+//! The initial ramdisk is a cpio archive in the "newc" format, that is loaded by the boot code.
+//! It contains the programs needed to start user space, most importantly the first user space server.
+
+use core::{fmt, str};
+
+use crate::sync::GlobalRuntimeConfiguration;
+
+/// The magic number of a newc header.
+const MAGIC: &[u8] = b"070701";
+
+/// The magic number of a newc header with checksums.
+const MAGIC_WITH_CHECKSUM: &[u8] = b"070702";
+
+/// The size of a newc header.
+const HEADER_SIZE: usize = 110;
+
+/// The name of the entry marking the end of the archive.
+const TRAILER_NAME: &str = "TRAILER!!!";
+
+/// The bits of the mode that describe the file type.
+const MODE_TYPE_MASK: u32 = 0o170_000;
+
+/// The file type of regular files.
+const MODE_REGULAR_FILE: u32 = 0o100_000;
+
+/// The file type of directories.
+const MODE_DIRECTORY: u32 = 0o040_000;
+
+/// The initial ramdisk that was loaded during boot.
+static INITRD: GlobalRuntimeConfiguration<Initrd> = GlobalRuntimeConfiguration::new();
+
+/// The reasons the initial ramdisk can be rejected for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InitrdError {
+    /// The entry at the given offset is cut off.
+    Truncated(usize),
+    /// The entry at the given offset doesn't start with the newc magic number.
+    InvalidMagic(usize),
+    /// A header field of the entry at the given offset is not a hexadecimal number.
+    InvalidHeader(usize),
+    /// The name of the entry at the given offset is not valid UTF-8.
+    InvalidName(usize),
+    /// The archive is not terminated by a trailer entry.
+    MissingTrailer,
+}
+
+impl fmt::Display for InitrdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InitrdError::Truncated(offset) => write!(f, "the entry at {:#x} is cut off", offset),
+            InitrdError::InvalidMagic(offset) => {
+                write!(f, "the entry at {:#x} is not a cpio newc entry", offset)
+            }
+            InitrdError::InvalidHeader(offset) => {
+                write!(f, "the header of the entry at {:#x} is invalid", offset)
+            }
+            InitrdError::InvalidName(offset) => {
+                write!(f, "the name of the entry at {:#x} is invalid", offset)
+            }
+            InitrdError::MissingTrailer => write!(f, "the archive has no trailer"),
+        }
+    }
+}
+
+/// A file in the initial ramdisk.
+#[derive(Clone, Copy, Debug)]
+pub struct File {
+    /// The path of the file without a leading slash.
+    pub name: &'static str,
+    /// The mode of the file, containing the file type and the permissions.
+    pub mode: u32,
+    /// The contents of the file.
+    pub data: &'static [u8],
+}
+
+impl File {
+    /// Checks if the file is a regular file.
+    pub fn is_regular_file(&self) -> bool {
+        self.mode & MODE_TYPE_MASK == MODE_REGULAR_FILE
+    }
+
+    /// Checks if the file is a directory.
+    pub fn is_directory(&self) -> bool {
+        self.mode & MODE_TYPE_MASK == MODE_DIRECTORY
+    }
+}
+
+/// Rounds the offset up to the four byte alignment used in the archive.
+fn align(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+/// Removes leading "./" and "/" components from the path.
+fn normalize(mut path: &str) -> &str {
+    loop {
+        if path.starts_with("./") {
+            path = &path[2..];
+        } else if path.starts_with('/') {
+            path = &path[1..];
+        } else {
+            return path;
+        }
+    }
+}
+
+/// Parses the entry at `offset`.
+///
+/// Returns the file and the offset of the next entry or `None` if the entry is the trailer.
+fn parse_entry(data: &'static [u8], offset: usize) -> Result<Option<(File, usize)>, InitrdError> {
+    let header = data
+        .get(offset..offset + HEADER_SIZE)
+        .ok_or(InitrdError::Truncated(offset))?;
+
+    if &header[..6] != MAGIC && &header[..6] != MAGIC_WITH_CHECKSUM {
+        return Err(InitrdError::InvalidMagic(offset));
+    }
+
+    // Returns the header field with the given index.
+    let field = |index: usize| {
+        str::from_utf8(&header[6 + index * 8..6 + (index + 1) * 8])
+            .ok()
+            .and_then(|field| u32::from_str_radix(field, 16).ok())
+            .ok_or(InitrdError::InvalidHeader(offset))
+    };
+
+    let mode = field(1)?;
+    let file_size = field(6)? as usize;
+    let name_size = field(11)? as usize;
+
+    let name_start = offset + HEADER_SIZE;
+    let name = data
+        .get(name_start..name_start + name_size)
+        .ok_or(InitrdError::Truncated(offset))?;
+    // The name is null terminated.
+    let name = match name.split_last() {
+        Some((0, name)) => str::from_utf8(name).map_err(|_| InitrdError::InvalidName(offset))?,
+        _ => return Err(InitrdError::InvalidName(offset)),
+    };
+
+    if name == TRAILER_NAME {
+        return Ok(None);
+    }
+
+    let data_start = align(name_start + name_size);
+    let file_data = data
+        .get(data_start..data_start + file_size)
+        .ok_or(InitrdError::Truncated(offset))?;
+
+    Ok(Some((
+        File {
+            name: normalize(name),
+            mode,
+            data: file_data,
+        },
+        align(data_start + file_size),
+    )))
+}
+
+/// An initial ramdisk.
+pub struct Initrd {
+    /// The archive containing the files.
+    data: &'static [u8],
+}
+
+impl Initrd {
+    /// Validates the archive and creates an initial ramdisk from it.
+    pub fn parse(data: &'static [u8]) -> Result<Initrd, InitrdError> {
+        let mut offset = 0;
+
+        loop {
+            if offset >= data.len() {
+                return Err(InitrdError::MissingTrailer);
+            }
+
+            match parse_entry(data, offset)? {
+                Some((_, next)) => offset = next,
+                None => return Ok(Initrd { data }),
+            }
+        }
+    }
+
+    /// Returns an iterator over all files in the initial ramdisk.
+    pub fn files(&self) -> Files {
+        Files {
+            data: self.data,
+            offset: 0,
+        }
+    }
+
+    /// Returns the file at the given path.
+    pub fn find(&self, path: &str) -> Option<File> {
+        let path = normalize(path);
+
+        self.files().find(|file| file.name == path)
+    }
+}
+
+/// An iterator over the files in an initial ramdisk.
+pub struct Files {
+    /// The archive containing the files.
+    data: &'static [u8],
+    /// The offset of the next entry.
+    offset: usize,
+}
+
+impl Iterator for Files {
+    type Item = File;
+
+    fn next(&mut self) -> Option<File> {
+        loop {
+            // The archive was validated, so parsing can only end at the trailer.
+            let (file, next) = parse_entry(self.data, self.offset).ok()??;
+
+            self.offset = next;
+
+            // The archive may contain an entry for the root directory itself.
+            if !file.name.is_empty() && file.name != "." {
+                return Some(file);
+            }
+        }
+    }
+}
+
+/// Initializes the initial ramdisk from the archive loaded during boot.
+pub fn init(data: &'static [u8]) {
+    match Initrd::parse(data) {
+        Ok(initrd) => {
+            log::info!(
+                "Found an initial ramdisk containing {} files.",
+                initrd.files().count()
+            );
+
+            for file in initrd.files() {
+                log::debug!("{} ({} bytes)", file.name, file.data.len());
+            }
+
+            INITRD.init(initrd);
+        }
+        Err(error) => log::error!("The initial ramdisk is invalid: {}.", error),
+    }
+}
+
+/// Returns the initial ramdisk, if one was loaded.
+pub fn get() -> Option<&'static Initrd> {
+    INITRD.get()
+}
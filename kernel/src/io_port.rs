@@ -0,0 +1,152 @@
+//! Keeps track of the I/O ports that are handed out to user space drivers.
+//!
+//! Threads that were granted access to I/O ports can use them directly, without involving the kernel.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::sync::Mutex;
+
+/// The number of I/O ports.
+pub const IO_PORT_COUNT: usize = 0x10000;
+
+/// The number of bits in a word of a port bitmap.
+const BITS_PER_WORD: usize = 64;
+
+/// The number of words in a port bitmap.
+const BITMAP_WORDS: usize = IO_PORT_COUNT / BITS_PER_WORD;
+
+/// Records which I/O ports are currently owned by an `IoPortRange`.
+static CLAIMED_PORTS: Mutex<[u64; BITMAP_WORDS]> = Mutex::new([0; BITMAP_WORDS]);
+
+/// The identifier of the next version of an I/O port set.
+static NEXT_SET_VERSION: AtomicUsize = AtomicUsize::new(1);
+
+/// Returns the index of the word and the mask of the bit representing the port in a port bitmap.
+fn bit(port: usize) -> (usize, u64) {
+    (port / BITS_PER_WORD, 1 << (port % BITS_PER_WORD))
+}
+
+/// A contiguous range of I/O ports.
+///
+/// Each port is part of at most one `IoPortRange` at a time, which releases it when dropped.
+#[derive(Debug)]
+pub struct IoPortRange {
+    /// The first port in the range.
+    start: u16,
+    /// The number of ports in the range.
+    count: usize,
+}
+
+impl IoPortRange {
+    /// Claims the `count` ports starting at `start`.
+    ///
+    /// Returns `None` if the range is empty, does not exist or any of its ports is already claimed.
+    pub fn claim(start: u16, count: usize) -> Option<IoPortRange> {
+        let end = usize::from(start) + count;
+
+        if count == 0 || end > IO_PORT_COUNT {
+            return None;
+        }
+
+        let mut claimed_ports = CLAIMED_PORTS.lock();
+
+        for port in usize::from(start)..end {
+            let (index, mask) = bit(port);
+
+            if claimed_ports[index] & mask != 0 {
+                return None;
+            }
+        }
+
+        for port in usize::from(start)..end {
+            let (index, mask) = bit(port);
+
+            claimed_ports[index] |= mask;
+        }
+
+        Some(IoPortRange { start, count })
+    }
+
+    /// Returns the first port in the range.
+    pub fn start(&self) -> u16 {
+        self.start
+    }
+
+    /// Returns the number of ports in the range.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+}
+
+impl Drop for IoPortRange {
+    fn drop(&mut self) {
+        let mut claimed_ports = CLAIMED_PORTS.lock();
+
+        for port in usize::from(self.start)..usize::from(self.start) + self.count {
+            let (index, mask) = bit(port);
+
+            claimed_ports[index] &= !mask;
+        }
+    }
+}
+
+/// A set of I/O ports that a thread is allowed to access.
+///
+/// The ranges in the set stay claimed as long as the set exists.
+pub struct IoPortSet {
+    /// Identifies the contents of the set, changing whenever the set changes.
+    version: usize,
+    /// The bitmap of the allowed ports.
+    allowed: [u64; BITMAP_WORDS],
+    /// The ranges that were added to the set.
+    ranges: Vec<Arc<IoPortRange>>,
+}
+
+impl IoPortSet {
+    /// Creates an empty set.
+    pub fn new() -> IoPortSet {
+        IoPortSet {
+            version: NEXT_SET_VERSION.fetch_add(1, Ordering::Relaxed),
+            allowed: [0; BITMAP_WORDS],
+            ranges: Vec::new(),
+        }
+    }
+
+    /// Adds the ports of the range to the set.
+    pub fn allow(&mut self, range: &Arc<IoPortRange>) {
+        for port in usize::from(range.start)..usize::from(range.start) + range.count {
+            let (index, mask) = bit(port);
+
+            self.allowed[index] |= mask;
+        }
+
+        self.ranges.push(range.clone());
+        self.version = NEXT_SET_VERSION.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the bitmap of the allowed ports, in which bit `n` of word `m` represents the port `64 * m + n`.
+    pub fn bitmap(&self) -> &[u64] {
+        &self.allowed
+    }
+
+    /// Checks if the port is part of the set.
+    pub fn is_allowed(&self, port: u16) -> bool {
+        let (index, mask) = bit(usize::from(port));
+
+        self.allowed[index] & mask != 0
+    }
+
+    /// Returns a value that identifies the current contents of the set.
+    ///
+    /// No two sets ever have the same version.
+    pub fn version(&self) -> usize {
+        self.version
+    }
+}
+
+impl Default for IoPortSet {
+    fn default() -> IoPortSet {
+        IoPortSet::new()
+    }
+}
@@ -0,0 +1,116 @@
+//! Provides the inter-process communication primitives of the kernel.
+//!
+//! Threads exchange short messages synchronously through endpoints.
+//! A call sends a message and waits for the reply, which is sent back directly to the caller.
+//! Notifications deliver signals asynchronously, also to threads that wait for a message.
+//! Page faults in areas managed by a pager are sent to the pager as messages.
+
+mod endpoint;
+mod fault;
+mod message;
+mod notification;
+
+use alloc::sync::Arc;
+use core::fmt;
+
+pub use self::{
+    endpoint::Endpoint,
+    fault::{Pager, PAGE_FAULT_LABEL},
+    message::{
+        MessageInfo, IPC_BUFFER_LENGTH, MAX_CAPABILITIES, MAX_MESSAGE_LENGTH, NO_SLOT,
+        RECEIVE_SLOTS_INDEX, REGISTER_MESSAGE_LENGTH, SEND_SLOTS_INDEX,
+    },
+    notification::{Notification, NOTIFICATION_LABEL},
+};
+use crate::{
+    scheduler,
+    thread::{Thread, ThreadState},
+};
+
+/// The errors that can occur when exchanging messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IpcError {
+    /// The message info describes an invalid message.
+    InvalidMessage,
+    /// The message needs an IPC buffer, but the thread has none.
+    NoIpcBuffer,
+    /// The thread was not called by anyone it could reply to.
+    NoCaller,
+    /// The thread or the notification is already bound.
+    AlreadyBound,
+    /// The reply to a page fault does not supply a page that can be mapped.
+    InvalidFrame,
+}
+
+impl fmt::Display for IpcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IpcError::InvalidMessage => write!(f, "the message is invalid"),
+            IpcError::NoIpcBuffer => write!(f, "the thread has no IPC buffer"),
+            IpcError::NoCaller => write!(f, "there is no caller to reply to"),
+            IpcError::AlreadyBound => write!(f, "the thread or notification is already bound"),
+            IpcError::InvalidFrame => {
+                write!(f, "the reply does not supply a page that can be mapped")
+            }
+        }
+    }
+}
+
+/// Checks that the thread can send the message described by the word.
+pub fn check_message(thread: &Thread, info: usize) -> Result<(), IpcError> {
+    let info = MessageInfo::from_word(info)?;
+
+    if info.label() == NOTIFICATION_LABEL || info.label() == PAGE_FAULT_LABEL {
+        return Err(IpcError::InvalidMessage);
+    }
+
+    if info.needs_ipc_buffer() && thread.ipc_buffer().is_none() {
+        return Err(IpcError::NoIpcBuffer);
+    }
+
+    Ok(())
+}
+
+/// Sends the message of the current thread `replier` to the thread that called it.
+///
+/// Replies can always transfer capabilities, because the caller chooses whether to accept them.
+/// Replies to page faults supply a page instead and leave the registers of the caller untouched.
+/// Returns the caller, which is runnable again, but not queued yet.
+fn reply_to_caller(replier: &Arc<Thread>) -> Result<Arc<Thread>, IpcError> {
+    let caller = replier.take_caller().ok_or(IpcError::NoCaller)?;
+
+    // The caller may have been stopped while it waited for the reply.
+    match caller.state() {
+        ThreadState::BlockedOnReply => message::transfer(replier, &caller, 0, true),
+        ThreadState::BlockedOnPager => fault::resolve(replier, &caller)?,
+        _ => return Err(IpcError::NoCaller),
+    }
+
+    caller.set_state(ThreadState::Runnable);
+
+    Ok(caller)
+}
+
+/// Sends the message of the current thread `replier` to the thread that called it.
+pub fn reply(replier: &Arc<Thread>) -> Result<(), IpcError> {
+    scheduler::make_ready(reply_to_caller(replier)?);
+
+    Ok(())
+}
+
+/// Replies to the caller of the current thread `replier`, if there is one, and then receives from the endpoint.
+///
+/// If the replier has to wait for a message, the caller runs immediately.
+pub fn reply_receive(endpoint: &Arc<Endpoint>, replier: &Arc<Thread>) {
+    let caller = reply_to_caller(replier).ok();
+
+    Endpoint::receive(endpoint, replier);
+
+    if let Some(caller) = caller {
+        if replier.state().is_blocked_on_receive() {
+            scheduler::switch_to(caller);
+        } else {
+            scheduler::make_ready(caller);
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! Provides endpoints, through which threads exchange messages.
+
+use alloc::{collections::VecDeque, sync::Arc};
+
+use super::{fault, message::transfer};
+use crate::{
+    scheduler,
+    sync::Mutex,
+    thread::{Thread, ThreadState},
+};
+
+/// The threads waiting on an endpoint.
+///
+/// Only one of the queues is non-empty at a time.
+#[derive(Default)]
+struct Queues {
+    /// The threads waiting for a receiver.
+    senders: VecDeque<Arc<Thread>>,
+    /// The threads waiting for a sender.
+    receivers: VecDeque<Arc<Thread>>,
+}
+
+impl Queues {
+    /// Takes the first receiver that still waits for a message and makes it runnable.
+    ///
+    /// Receivers whose bound notification was signalled in the meantime are skipped,
+    /// since the signal already made them runnable.
+    fn pop_receiver(&mut self) -> Option<Arc<Thread>> {
+        while let Some(receiver) = self.receivers.pop_front() {
+            let receiving = receiver.transition_state(
+                |state| match state {
+                    ThreadState::BlockedOnReceive(_) => true,
+                    _ => false,
+                },
+                ThreadState::Runnable,
+            );
+
+            if receiving {
+                return Some(receiver);
+            }
+        }
+
+        None
+    }
+}
+
+/// An endpoint through which threads exchange messages.
+///
+/// Capabilities to endpoints can carry a badge, which identifies the sender to the receiver.
+/// Messages are transferred synchronously, so a sender blocks until a receiver takes its message and vice versa.
+#[derive(Default)]
+pub struct Endpoint {
+    /// The threads waiting on the endpoint.
+    queues: Mutex<Queues>,
+}
+
+impl Endpoint {
+    /// Creates a new endpoint.
+    pub fn new() -> Endpoint {
+        Endpoint::default()
+    }
+
+    /// Sends the message of the current thread `sender` through the endpoint.
+    ///
+    /// If `call` is set, the sender waits for a reply and the receiver runs immediately.
+    /// If no receiver is waiting, the sender blocks until one arrives.
+    pub fn send(
+        endpoint: &Arc<Endpoint>,
+        sender: &Arc<Thread>,
+        badge: u64,
+        grant: bool,
+        call: bool,
+    ) {
+        let receiver = {
+            let mut queues = endpoint.queues.lock();
+            let receiver = queues.pop_receiver();
+
+            if receiver.is_none() {
+                sender.set_state(ThreadState::BlockedOnSend {
+                    endpoint: endpoint.clone(),
+                    badge,
+                    grant,
+                    call,
+                });
+                queues.senders.push_back(sender.clone());
+            }
+
+            receiver
+        };
+
+        match receiver {
+            Some(receiver) => {
+                transfer(sender, &receiver, badge, grant);
+
+                if call {
+                    sender.set_state(ThreadState::BlockedOnReply);
+                    Thread::set_caller(&receiver, sender.clone());
+                    scheduler::switch_to(receiver);
+                } else {
+                    scheduler::make_ready(receiver);
+                }
+            }
+            None => scheduler::block_current(),
+        }
+    }
+
+    /// Sends the page fault of the current thread `faulting` to the pager receiving from the endpoint.
+    ///
+    /// The faulting thread waits for the reply of the pager like a caller, and the pager runs immediately.
+    /// If no pager is waiting, the faulting thread blocks until one arrives.
+    pub fn send_fault(endpoint: &Arc<Endpoint>, faulting: &Arc<Thread>, badge: u64) {
+        let receiver = {
+            let mut queues = endpoint.queues.lock();
+            let receiver = queues.pop_receiver();
+
+            if receiver.is_none() {
+                faulting.set_state(ThreadState::BlockedOnFault {
+                    endpoint: endpoint.clone(),
+                    badge,
+                });
+                queues.senders.push_back(faulting.clone());
+            }
+
+            receiver
+        };
+
+        match receiver {
+            Some(receiver) => {
+                fault::deliver(faulting, &receiver, badge);
+                faulting.set_state(ThreadState::BlockedOnPager);
+                Thread::set_caller(&receiver, faulting.clone());
+                scheduler::switch_to(receiver);
+            }
+            None => scheduler::block_current(),
+        }
+    }
+
+    /// Receives a message for the current thread `receiver` from the endpoint.
+    ///
+    /// If no sender is waiting, the receiver blocks until one arrives.
+    /// Signals of the notification bound to the receiver are received instead of a message.
+    pub fn receive(endpoint: &Arc<Endpoint>, receiver: &Arc<Thread>) {
+        if let Some(notification) = receiver.bound_notification() {
+            if notification.receive_pending(receiver) {
+                return;
+            }
+        }
+
+        let sender = {
+            let mut queues = endpoint.queues.lock();
+            let sender = queues.senders.pop_front();
+
+            if sender.is_none() {
+                receiver.set_state(ThreadState::BlockedOnReceive(endpoint.clone()));
+                queues.receivers.push_back(receiver.clone());
+            }
+
+            sender
+        };
+
+        match sender {
+            Some(sender) => match sender.state() {
+                ThreadState::BlockedOnSend {
+                    badge, grant, call, ..
+                } => {
+                    transfer(&sender, receiver, badge, grant);
+
+                    if call {
+                        sender.set_state(ThreadState::BlockedOnReply);
+                        Thread::set_caller(receiver, sender);
+                    } else {
+                        sender.set_state(ThreadState::Runnable);
+                        scheduler::make_ready(sender);
+                    }
+                }
+                ThreadState::BlockedOnFault { badge, .. } => {
+                    fault::deliver(&sender, receiver, badge);
+                    sender.set_state(ThreadState::BlockedOnPager);
+                    Thread::set_caller(receiver, sender);
+                }
+                _ => unreachable!("A thread in the send queue is not sending."),
+            },
+            None => scheduler::block_current(),
+        }
+    }
+
+    /// Removes the thread from the queues of the endpoint.
+    pub fn remove(&self, thread: &Arc<Thread>) {
+        let mut queues = self.queues.lock();
+
+        queues
+            .senders
+            .retain(|waiting| !Arc::ptr_eq(waiting, thread));
+        queues
+            .receivers
+            .retain(|waiting| !Arc::ptr_eq(waiting, thread));
+    }
+}
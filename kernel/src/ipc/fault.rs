@@ -0,0 +1,150 @@
+//! Provides pagers, user space servers that supply the frames of reserved areas.
+//!
+//! A page fault in an area managed by a pager is converted into a message that the faulting thread sends
+//! to the endpoint of the pager, like a call.
+//! The message has the label `PAGE_FAULT_LABEL` and carries the faulting address and the kind of access
+//! (0 for reads, 1 for writes and 2 for instruction fetches) in its first two words.
+//! The pager replies with the slot of a memory object in its capability space and the index of the page to map.
+//! The page is mapped with the permissions of the area and the faulting thread retries the access.
+//! If the reply does not supply a page that can be mapped, the fault is treated like any other unresolved fault.
+//! Several threads of an address space can fault on the same page, so a fault whose page was already mapped
+//! by an earlier reply is resolved without looking at the reply.
+
+use alloc::sync::Arc;
+
+use super::{
+    message::{MessageInfo, BADGE_ARGUMENT, FIRST_MESSAGE_ARGUMENT, INFO_ARGUMENT},
+    Endpoint, IpcError,
+};
+use crate::{
+    capability::Rights,
+    memory::{Access, VirtualAddress},
+    thread::{Fault, Thread},
+};
+
+/// The label of the messages that tell a pager about a page fault.
+///
+/// Messages cannot use this label.
+pub const PAGE_FAULT_LABEL: usize = (1 << 48) - 2;
+
+/// The number of message words a pager must reply with.
+const REPLY_LENGTH: usize = 2;
+
+/// The endpoint and badge used to send the page faults of an area to its pager.
+#[derive(Clone)]
+pub struct Pager {
+    /// The endpoint the pager receives the faults from.
+    endpoint: Arc<Endpoint>,
+    /// The badge the fault messages are sent with, which identifies the area to the pager.
+    badge: u64,
+}
+
+impl Pager {
+    /// Creates a pager that receives faults from the endpoint with the given badge.
+    pub fn new(endpoint: Arc<Endpoint>, badge: u64) -> Pager {
+        Pager { endpoint, badge }
+    }
+
+    /// Returns the endpoint the pager receives the faults from.
+    pub fn endpoint(&self) -> &Arc<Endpoint> {
+        &self.endpoint
+    }
+
+    /// Returns the badge the fault messages are sent with.
+    pub fn badge(&self) -> u64 {
+        self.badge
+    }
+}
+
+impl PartialEq for Pager {
+    fn eq(&self, other: &Pager) -> bool {
+        Arc::ptr_eq(&self.endpoint, &other.endpoint) && self.badge == other.badge
+    }
+}
+
+impl Eq for Pager {}
+
+/// Returns the faulting address and the kind of access of the page fault the thread sends to its pager.
+fn page_fault(thread: &Thread) -> (VirtualAddress, Access) {
+    match thread.fault() {
+        Some(Fault::Page { address, access }) => (address, access),
+        _ => unreachable!("A thread sending a page fault did not cause one."),
+    }
+}
+
+/// Delivers the page fault of `faulting` to `receiver` as a message with the given badge.
+pub fn deliver(faulting: &Thread, receiver: &Thread, badge: u64) {
+    let (address, access) = page_fault(faulting);
+    let info = MessageInfo::new(PAGE_FAULT_LABEL, 2, 0)
+        .expect("The page fault message info is invalid.")
+        .to_word();
+    let access = match access {
+        Access::Read => 0,
+        Access::Write => 1,
+        Access::Execute => 2,
+    };
+
+    receiver.modify_context(|context| {
+        context.set_result(0);
+        context.set_argument(BADGE_ARGUMENT, badge as usize);
+        context.set_argument(INFO_ARGUMENT, info);
+        context.set_argument(FIRST_MESSAGE_ARGUMENT, address.as_usize());
+        context.set_argument(FIRST_MESSAGE_ARGUMENT + 1, access);
+    });
+}
+
+/// Maps the page the current thread `pager` supplied in its reply to the page fault of `faulting`.
+///
+/// If the reply does not supply a page that can be mapped, the faulting thread is stopped.
+pub fn resolve(pager: &Thread, faulting: &Arc<Thread>) -> Result<(), IpcError> {
+    let (address, access) = page_fault(faulting);
+    let page = address.page_align_down();
+
+    // Another thread faulted on the same page and the reply to its fault mapped it already.
+    if faulting.address_space().lock().is_accessible(page, access) {
+        return Ok(());
+    }
+
+    let context = pager.context();
+    let info = MessageInfo::from_word(context.argument(INFO_ARGUMENT))
+        .expect("The message info was not validated before replying.");
+
+    let result = if info.length() < REPLY_LENGTH {
+        Err(IpcError::InvalidFrame)
+    } else {
+        let slot = context.argument(FIRST_MESSAGE_ARGUMENT);
+        let index = context.argument(FIRST_MESSAGE_ARGUMENT + 1);
+
+        pager
+            .cspace()
+            .lookup(slot)
+            .ok()
+            .and_then(|capability| {
+                let mut address_space = faulting.address_space().lock();
+                let flags =
+                    address_space.paged_flags(page)? | capability.object().memory_flags()?;
+                let frame = capability.object().memory_frame(index)?;
+
+                if !capability.rights().contains(Rights::to_map(flags)) {
+                    return None;
+                }
+
+                address_space
+                    .map_shared(page, frame, flags, capability.object().clone())
+                    .ok()
+            })
+            .ok_or(IpcError::InvalidFrame)
+    };
+
+    if result.is_err() {
+        log::debug!(
+            "The pager of thread {:?} did not supply a page for {}.",
+            faulting.id(),
+            address
+        );
+
+        Thread::stop_on_fault(faulting, Fault::Page { address, access });
+    }
+
+    result
+}
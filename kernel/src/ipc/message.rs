@@ -0,0 +1,231 @@
+//! Provides the layout of messages and their transfer between threads.
+//!
+//! The first message words are passed in registers, which is enough for most messages.
+//! Longer messages and capabilities are passed through the IPC buffer, a memory area that each thread can register.
+//! The IPC buffer consists of machine words with the following layout:
+//!
+//! | Words   | Content                                                        |
+//! |---------|----------------------------------------------------------------|
+//! | 0 - 63  | The message words, where words below `REGISTER_MESSAGE_LENGTH` are unused |
+//! | 64 - 66 | The slots of the capabilities to send                          |
+//! | 67 - 69 | The slots that received capabilities are stored in             |
+
+use alloc::sync::Arc;
+use core::{cmp::min, mem::size_of, slice};
+
+use super::IpcError;
+use crate::{capability::Rights, memory::VirtualAddress, thread::Thread};
+
+/// The maximum number of words in a message.
+pub const MAX_MESSAGE_LENGTH: usize = 64;
+
+/// The number of message words that are passed in registers.
+pub const REGISTER_MESSAGE_LENGTH: usize = 4;
+
+/// The maximum number of capabilities in a message.
+pub const MAX_CAPABILITIES: usize = 3;
+
+/// The index of the first capability slot to send in the IPC buffer.
+pub const SEND_SLOTS_INDEX: usize = MAX_MESSAGE_LENGTH;
+
+/// The index of the first slot to receive capabilities in in the IPC buffer.
+pub const RECEIVE_SLOTS_INDEX: usize = SEND_SLOTS_INDEX + MAX_CAPABILITIES;
+
+/// The number of words in the IPC buffer.
+pub const IPC_BUFFER_LENGTH: usize = RECEIVE_SLOTS_INDEX + MAX_CAPABILITIES;
+
+/// Marks a receive slot as unused.
+pub const NO_SLOT: usize = usize::max_value();
+
+/// The index of the system call argument holding the badge of a received message.
+pub const BADGE_ARGUMENT: usize = 0;
+
+/// The index of the system call argument holding the message info.
+pub const INFO_ARGUMENT: usize = 1;
+
+/// The index of the system call argument holding the first message word.
+pub const FIRST_MESSAGE_ARGUMENT: usize = 2;
+
+/// Describes the contents of a message.
+///
+/// The description is passed as a single word, which contains the length in bits 0 to 7,
+/// the number of capabilities in bits 8 and 9 and the label in the remaining bits starting at bit 16.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MessageInfo {
+    /// A value that is passed unchanged, which can be used to identify the operation.
+    label: usize,
+    /// The number of message words.
+    length: usize,
+    /// The number of capabilities sent with the message.
+    capability_count: usize,
+}
+
+impl MessageInfo {
+    /// Creates a message description.
+    pub fn new(
+        label: usize,
+        length: usize,
+        capability_count: usize,
+    ) -> Result<MessageInfo, IpcError> {
+        if length > MAX_MESSAGE_LENGTH || capability_count > MAX_CAPABILITIES || label >> 48 != 0 {
+            return Err(IpcError::InvalidMessage);
+        }
+
+        Ok(MessageInfo {
+            label,
+            length,
+            capability_count,
+        })
+    }
+
+    /// Decodes a message description from a word.
+    pub fn from_word(word: usize) -> Result<MessageInfo, IpcError> {
+        if word & 0xfc00 != 0 {
+            return Err(IpcError::InvalidMessage);
+        }
+
+        MessageInfo::new(word >> 16, word & 0xff, word >> 8 & 0x3)
+    }
+
+    /// Encodes the message description as a word.
+    pub fn to_word(self) -> usize {
+        self.label << 16 | self.capability_count << 8 | self.length
+    }
+
+    /// Returns the label of the message.
+    pub fn label(self) -> usize {
+        self.label
+    }
+
+    /// Returns the number of message words.
+    pub fn length(self) -> usize {
+        self.length
+    }
+
+    /// Returns the number of capabilities sent with the message.
+    pub fn capability_count(self) -> usize {
+        self.capability_count
+    }
+
+    /// Checks if the message needs an IPC buffer.
+    pub fn needs_ipc_buffer(self) -> bool {
+        self.length > REGISTER_MESSAGE_LENGTH || self.capability_count > 0
+    }
+}
+
+/// Reads the words of the IPC buffer of the thread.
+///
+/// Returns `None` if the thread has no accessible IPC buffer.
+fn read_ipc_buffer(thread: &Thread) -> Option<[usize; IPC_BUFFER_LENGTH]> {
+    let address = thread.ipc_buffer()?;
+    let mut words = [0; IPC_BUFFER_LENGTH];
+
+    // This is safe, because any bit pattern is a valid word.
+    let bytes = unsafe {
+        slice::from_raw_parts_mut(
+            words.as_mut_ptr() as *mut u8,
+            IPC_BUFFER_LENGTH * size_of::<usize>(),
+        )
+    };
+
+    thread.address_space().lock().read(address, bytes).ok()?;
+
+    Some(words)
+}
+
+/// Writes words to the IPC buffer of the thread, starting at the word with the given index.
+///
+/// This fails if the thread itself is not allowed to write to its IPC buffer.
+fn write_ipc_buffer(thread: &Thread, index: usize, words: &[usize]) -> Result<(), IpcError> {
+    let address = thread.ipc_buffer().ok_or(IpcError::NoIpcBuffer)?;
+
+    // This is safe, because the words are only read as bytes.
+    let bytes = unsafe {
+        slice::from_raw_parts(
+            words.as_ptr() as *const u8,
+            words.len() * size_of::<usize>(),
+        )
+    };
+
+    thread
+        .address_space()
+        .lock()
+        .write_user(
+            VirtualAddress::new(address.as_usize() + index * size_of::<usize>()),
+            bytes,
+        )
+        .map_err(|_| IpcError::NoIpcBuffer)
+}
+
+/// Transfers the message of `sender` to `receiver`.
+///
+/// The message info and the message words are taken from the sender's registers and IPC buffer.
+/// Parts of the message that cannot be stored, because either thread has no accessible IPC buffer, are dropped.
+/// Capabilities are only transferred if `grant` is set and the receiver provided slots for them.
+pub fn transfer(sender: &Arc<Thread>, receiver: &Arc<Thread>, badge: u64, grant: bool) {
+    let sender_context = sender.context();
+    let info = MessageInfo::from_word(sender_context.argument(INFO_ARGUMENT))
+        .expect("The message info was not validated before sending.");
+
+    let mut length = min(info.length(), REGISTER_MESSAGE_LENGTH);
+    let mut capability_count = 0;
+
+    if info.needs_ipc_buffer() {
+        if let (Some(sender_buffer), Some(receiver_buffer)) =
+            (read_ipc_buffer(sender), read_ipc_buffer(receiver))
+        {
+            if info.length() > REGISTER_MESSAGE_LENGTH
+                && write_ipc_buffer(
+                    receiver,
+                    REGISTER_MESSAGE_LENGTH,
+                    &sender_buffer[REGISTER_MESSAGE_LENGTH..info.length()],
+                )
+                .is_ok()
+            {
+                length = info.length();
+            }
+
+            if grant {
+                for index in 0..info.capability_count() {
+                    let source_slot = sender_buffer[SEND_SLOTS_INDEX + index];
+                    let destination_slot = receiver_buffer[RECEIVE_SLOTS_INDEX + index];
+
+                    if destination_slot == NO_SLOT
+                        || sender
+                            .cspace()
+                            .copy(
+                                source_slot,
+                                receiver.cspace(),
+                                destination_slot,
+                                Rights::all(),
+                            )
+                            .is_err()
+                    {
+                        break;
+                    }
+
+                    capability_count += 1;
+                }
+            }
+        }
+    }
+
+    let delivered_info = MessageInfo {
+        label: info.label(),
+        length,
+        capability_count,
+    };
+
+    receiver.modify_context(|context| {
+        context.set_result(0);
+        context.set_argument(BADGE_ARGUMENT, badge as usize);
+        context.set_argument(INFO_ARGUMENT, delivered_info.to_word());
+
+        for index in 0..min(length, REGISTER_MESSAGE_LENGTH) {
+            context.set_argument(
+                FIRST_MESSAGE_ARGUMENT + index,
+                sender_context.argument(FIRST_MESSAGE_ARGUMENT + index),
+            );
+        }
+    });
+}
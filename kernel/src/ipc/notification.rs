@@ -0,0 +1,212 @@
+//! Provides notifications, which deliver asynchronous signals to threads.
+//!
+//! A notification is a word of signal bits. Signalling sets the bits of the badge of the capability used,
+//! waiting returns all set bits and clears them.
+//! Hardware interrupts are delivered to user space drivers as signals on notifications.
+
+use alloc::{
+    collections::VecDeque,
+    sync::{Arc, Weak},
+};
+
+use super::{
+    message::{MessageInfo, BADGE_ARGUMENT, INFO_ARGUMENT},
+    IpcError,
+};
+use crate::{
+    scheduler,
+    sync::Mutex,
+    thread::{Thread, ThreadState},
+};
+
+/// The label of the message info that a thread receives instead of a message when its bound notification is signalled.
+///
+/// Messages cannot use this label.
+pub const NOTIFICATION_LABEL: usize = (1 << 48) - 1;
+
+/// The signal bits that are set when signalling through an unbadged capability.
+const UNBADGED_SIGNAL: u64 = 1;
+
+/// The mutable parts of a notification.
+#[derive(Default)]
+struct NotificationInner {
+    /// The signal bits that were set since the last wait.
+    signals: u64,
+    /// The threads waiting for signals.
+    waiting: VecDeque<Arc<Thread>>,
+    /// The thread that receives signals while it waits for a message.
+    bound_thread: Option<Weak<Thread>>,
+}
+
+/// An object that threads can signal and wait on.
+#[derive(Default)]
+pub struct Notification {
+    /// The mutable parts of the notification.
+    inner: Mutex<NotificationInner>,
+}
+
+/// Returns the message info word that tells a thread that it received signals instead of a message.
+fn notification_info() -> usize {
+    MessageInfo::new(NOTIFICATION_LABEL, 0, 0)
+        .expect("The notification message info is invalid.")
+        .to_word()
+}
+
+/// Passes the signal bits to the thread that was made runnable and schedules it.
+///
+/// If `receiving` is set, the thread was waiting for a message and is told that it received signals instead.
+fn wake(thread: Arc<Thread>, signals: u64, receiving: bool) {
+    thread.modify_context(|context| {
+        context.set_result(0);
+        context.set_argument(BADGE_ARGUMENT, signals as usize);
+
+        if receiving {
+            context.set_argument(INFO_ARGUMENT, notification_info());
+        }
+    });
+
+    scheduler::make_ready(thread);
+}
+
+impl Notification {
+    /// Creates a new notification without any set signal bits.
+    pub fn new() -> Notification {
+        Notification::default()
+    }
+
+    /// Signals the notification with the bits of `badge`.
+    ///
+    /// The first waiting thread is woken up. If there is none, the bound thread is woken up if it waits for a message.
+    /// Otherwise the bits are stored until the next wait.
+    ///
+    /// The state of a thread is checked and changed in one step under its lock,
+    /// because this runs in interrupt handlers and may race with the thread being suspended or receiving a message.
+    pub fn signal(&self, badge: u64) {
+        let signals = if badge == 0 { UNBADGED_SIGNAL } else { badge };
+        let mut inner = self.inner.lock();
+
+        while let Some(thread) = inner.waiting.pop_front() {
+            let waiting = thread.transition_state(
+                |state| match state {
+                    ThreadState::BlockedOnNotification(_) => true,
+                    _ => false,
+                },
+                ThreadState::Runnable,
+            );
+
+            if waiting {
+                drop(inner);
+
+                wake(thread, signals, false);
+                return;
+            }
+        }
+
+        if let Some(thread) = inner.bound_thread.as_ref().and_then(Weak::upgrade) {
+            let mut receiving_endpoint = None;
+
+            thread.transition_state(
+                |state| match state {
+                    ThreadState::BlockedOnReceive(endpoint) => {
+                        receiving_endpoint = Some(endpoint.clone());
+                        true
+                    }
+                    _ => false,
+                },
+                ThreadState::Runnable,
+            );
+
+            if let Some(endpoint) = receiving_endpoint {
+                drop(inner);
+
+                endpoint.remove(&thread);
+                wake(thread, signals, true);
+                return;
+            }
+        }
+
+        inner.signals |= signals;
+    }
+
+    /// Waits until the notification is signalled and passes the signal bits to the current thread `thread`.
+    pub fn wait(notification: &Arc<Notification>, thread: &Arc<Thread>) {
+        let mut inner = notification.inner.lock();
+
+        if inner.signals != 0 {
+            let signals = inner.signals;
+            inner.signals = 0;
+
+            thread.modify_context(|context| context.set_argument(BADGE_ARGUMENT, signals as usize));
+        } else {
+            thread.set_state(ThreadState::BlockedOnNotification(notification.clone()));
+            inner.waiting.push_back(thread.clone());
+
+            scheduler::block_current();
+        }
+    }
+
+    /// Returns and clears the signal bits without waiting.
+    pub fn poll(&self) -> u64 {
+        let mut inner = self.inner.lock();
+        let signals = inner.signals;
+        inner.signals = 0;
+
+        signals
+    }
+
+    /// Passes signals that arrived before the current thread `thread` started waiting for a message to it.
+    ///
+    /// Returns whether there were any signals.
+    pub fn receive_pending(&self, thread: &Arc<Thread>) -> bool {
+        let signals = self.poll();
+
+        if signals == 0 {
+            return false;
+        }
+
+        thread.modify_context(|context| {
+            context.set_argument(BADGE_ARGUMENT, signals as usize);
+            context.set_argument(INFO_ARGUMENT, notification_info());
+        });
+
+        true
+    }
+
+    /// Binds the notification to the thread, so that the thread receives signals while waiting for messages.
+    ///
+    /// A thread can only have one bound notification and a notification can only be bound to one thread.
+    pub fn bind(notification: &Arc<Notification>, thread: &Arc<Thread>) -> Result<(), IpcError> {
+        let mut inner = notification.inner.lock();
+
+        let notification_bound = inner
+            .bound_thread
+            .as_ref()
+            .and_then(Weak::upgrade)
+            .is_some();
+
+        if notification_bound || thread.bound_notification().is_some() {
+            return Err(IpcError::AlreadyBound);
+        }
+
+        inner.bound_thread = Some(Arc::downgrade(thread));
+        thread.set_bound_notification(Some(notification.clone()));
+
+        Ok(())
+    }
+
+    /// Removes the binding between the thread and its notification, if it has one.
+    pub fn unbind(thread: &Thread) {
+        if let Some(notification) = thread.bound_notification() {
+            notification.inner.lock().bound_thread = None;
+            thread.set_bound_notification(None);
+        }
+    }
+
+    /// Removes the thread from the threads waiting on the notification.
+    pub fn remove(&self, thread: &Arc<Thread>) {
+        self.inner
+            .lock()
+            .waiting
+            .retain(|waiting| !Arc::ptr_eq(waiting, thread));
+    }
+}
@@ -0,0 +1,138 @@
+//! Keeps track of the hardware interrupt lines that are handed out to user space drivers.
+//!
+//! Interrupts are delivered as signals on a notification chosen by the driver.
+//! The line stays masked after an interrupt until the driver acknowledges it.
+//!
+//! Drivers in the kernel claim their lines the same way, but handle the interrupts with a function instead.
+//! Their line is unmasked again as soon as the function returns.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::mem;
+use lazy_static::lazy_static;
+
+use crate::{
+    arch::{Arch, Architecture},
+    ipc::Notification,
+    sync::Mutex,
+};
+
+/// Records which interrupt lines are currently owned by an `IrqLine`.
+static CLAIMED_LINES: Mutex<[bool; Arch::IRQ_COUNT]> = Mutex::new([false; Arch::IRQ_COUNT]);
+
+lazy_static! {
+    /// The handler of each interrupt line.
+    static ref HANDLERS: Mutex<Vec<Option<Handler>>> =
+        Mutex::new((0..Arch::IRQ_COUNT).map(|_| None).collect());
+}
+
+/// A function in the kernel that handles the interrupts of a line, given the number of the line.
+///
+/// It runs with interrupts disabled and must not block.
+pub type KernelHandler = fn(usize);
+
+/// Receives the interrupts of a line.
+#[derive(Clone)]
+enum Handler {
+    /// The notification is signalled with the badge.
+    Notification(Arc<Notification>, u64),
+    /// The function in the kernel is called.
+    Kernel(KernelHandler),
+}
+
+/// A hardware interrupt line.
+///
+/// Each line is represented by at most one `IrqLine` at a time, which releases it when dropped.
+#[derive(Debug)]
+pub struct IrqLine {
+    /// The number of the interrupt line.
+    number: usize,
+}
+
+impl IrqLine {
+    /// Claims the interrupt line with the given number.
+    ///
+    /// Returns `None` if the line does not exist or is already claimed.
+    pub fn claim(number: usize) -> Option<IrqLine> {
+        let mut claimed_lines = CLAIMED_LINES.lock();
+        let claimed = claimed_lines.get_mut(number)?;
+
+        if *claimed {
+            None
+        } else {
+            *claimed = true;
+
+            Some(IrqLine { number })
+        }
+    }
+
+    /// Returns the number of the interrupt line.
+    pub fn number(&self) -> usize {
+        self.number
+    }
+
+    /// Sets the notification that is signalled with `badge` when an interrupt occurs.
+    ///
+    /// The line is unmasked if a notification is set and masked otherwise.
+    pub fn set_notification(&self, notification: Option<(Arc<Notification>, u64)>) {
+        self.set_handler(
+            notification.map(|(notification, badge)| Handler::Notification(notification, badge)),
+        );
+    }
+
+    /// Sets the function in the kernel that handles the interrupts.
+    ///
+    /// The line is unmasked if a function is set and masked otherwise.
+    pub fn set_kernel_handler(&self, handler: Option<KernelHandler>) {
+        self.set_handler(handler.map(Handler::Kernel));
+    }
+
+    /// Sets the handler of the line, unmasking it if there is one.
+    fn set_handler(&self, handler: Option<Handler>) {
+        let enabled = handler.is_some();
+        let previous = {
+            let mut handlers = HANDLERS.lock();
+
+            mem::replace(&mut handlers[self.number], handler)
+        };
+
+        if enabled {
+            Arch::unmask_irq(self.number);
+        } else {
+            Arch::mask_irq(self.number);
+        }
+
+        // The previous notification may be freed here, which is done outside of the lock.
+        drop(previous);
+    }
+
+    /// Acknowledges the last interrupt, so that the next one can be delivered.
+    pub fn acknowledge(&self) {
+        if HANDLERS.lock()[self.number].is_some() {
+            Arch::unmask_irq(self.number);
+        }
+    }
+}
+
+impl Drop for IrqLine {
+    fn drop(&mut self) {
+        self.set_handler(None);
+
+        CLAIMED_LINES.lock()[self.number] = false;
+    }
+}
+
+/// Handles an interrupt on the line with the given number, which was masked by the caller.
+///
+/// This signals the notification set for the line or calls its function in the kernel.
+pub fn handle(number: usize) {
+    let handler = HANDLERS.lock()[number].clone();
+
+    match handler {
+        Some(Handler::Notification(notification, badge)) => notification.signal(badge),
+        Some(Handler::Kernel(function)) => {
+            function(number);
+            Arch::unmask_irq(number);
+        }
+        None => log::warn!("Received a spurious interrupt on line {}.", number),
+    }
+}
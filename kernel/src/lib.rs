@@ -3,16 +3,40 @@
 //! It is a µ-kernel implemented in Rust.
 
 #![no_std]
+#![feature(alloc, alloc_error_handler, global_asm)]
+
+extern crate alloc;
 
 #[macro_use]
 pub mod arch;
+pub mod capability;
+pub mod cmdline;
+pub mod console;
+pub mod elf;
+pub mod initrd;
+pub mod io_port;
+pub mod ipc;
+pub mod irq;
+pub mod log_buffer;
+pub mod log_filter;
+pub mod memory;
+pub mod object;
+pub mod process;
+pub mod scheduler;
 pub mod sync;
+pub mod syscall;
+pub mod thread;
+pub mod time;
 
-/// Sets the log level for the kernel.
+/// The log level of the kernel, unless the command line sets another one.
 const LOG_LEVEL: log::LevelFilter = log::LevelFilter::Info;
 
 use crate::arch::{Arch, Architecture};
 
+/// The allocator that is used for all heap allocations in the kernel.
+#[global_allocator]
+static ALLOCATOR: memory::heap::KernelAllocator = memory::heap::KernelAllocator;
+
 /// The main function for the kernel.
 ///
 /// This is called by the architecture specific code after initialization.
@@ -27,5 +51,17 @@ pub fn main() -> ! {
 
     Arch::enable_interrupts();
 
-    loop {}
+    // The first program is kept alive here, since processes are only referenced by capabilities otherwise.
+    if let Some(init) = process::spawn_init() {
+        scheduler::run();
+
+        log::info!(
+            "All user space programs exited, the first one with status {:?}.",
+            init.exit_status()
+        );
+    }
+
+    loop {
+        Arch::wait_for_interrupt();
+    }
 }
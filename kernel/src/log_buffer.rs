@@ -0,0 +1,392 @@
+//! Keeps the most recent log records of the kernel in memory.
+//!
+//! The records are stored in a static ring buffer, so logging works from the first instruction of the kernel on,
+//! long before the heap or any console is set up.
+//! Consoles that come online later replay the records, and user space can read them with the `LOG_READ` system call.
+//!
+//! Writing a record doesn't take a lock, not even to read its timestamp, so records can be added in any context.
+//! The logger still takes the locks of the console sinks, though, so it must not be used while one of them is held.
+//! Every record gets a sequence number, which selects its slot in the buffer.
+//! The slot is claimed with an atomic operation and marked complete once the record is written,
+//! which readers check before and after copying a record to detect records that were overwritten meanwhile.
+//! If two writers race for the same slot, which requires the buffer to wrap around during a single write,
+//! the older record is dropped.
+
+use core::{
+    cell::UnsafeCell,
+    fmt::{self, Write},
+    ptr, str,
+    sync::atomic::{fence, AtomicUsize, Ordering},
+};
+use log::Level;
+
+use crate::{
+    arch::{Arch, Architecture},
+    time::Instant,
+};
+
+/// The number of records that are kept.
+const RECORD_COUNT: usize = 512;
+
+/// The maximum length of a module path in bytes.
+const MODULE_LENGTH: usize = 48;
+
+/// The maximum length of a file name in bytes.
+const FILE_LENGTH: usize = 48;
+
+/// The maximum length of a message in bytes.
+///
+/// Longer messages are truncated.
+const MESSAGE_LENGTH: usize = 160;
+
+/// The state of a slot that was never written.
+const EMPTY: usize = 0;
+
+/// The sequence number of the next record.
+static NEXT_SEQUENCE: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of records that were dropped, because another writer used their slot.
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+/// The slots of the ring buffer.
+static SLOTS: Slots = Slots(UnsafeCell::new(
+    [Slot {
+        state: EMPTY,
+        record: Record {
+            sequence: 0,
+            timestamp: Instant::from_nanoseconds(0),
+            cpu: 0,
+            level: Level::Error,
+            line: 0,
+            module: [0; MODULE_LENGTH],
+            module_length: 0,
+            file: [0; FILE_LENGTH],
+            file_length: 0,
+            message: [0; MESSAGE_LENGTH],
+            message_length: 0,
+        },
+    }; RECORD_COUNT],
+));
+
+/// The storage of the ring buffer.
+struct Slots(UnsafeCell<[Slot; RECORD_COUNT]>);
+
+// The slots are only accessed through their states, which synchronize the access.
+unsafe impl Sync for Slots {}
+
+impl Slots {
+    /// Returns the slot of the record with the given sequence number.
+    fn get(&self, sequence: usize) -> *mut Slot {
+        // This is safe, because the index is within the array.
+        unsafe { (self.0.get() as *mut Slot).add(sequence % RECORD_COUNT) }
+    }
+
+    /// Returns the state of the slot of the record with the given sequence number.
+    fn state(&self, sequence: usize) -> &AtomicUsize {
+        // This is safe, because the state is the first field of the slot and has the layout of an atomic integer.
+        unsafe { &*(self.get(sequence) as *const AtomicUsize) }
+    }
+}
+
+/// A slot in the ring buffer.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Slot {
+    /// The state of the slot, which is accessed atomically.
+    ///
+    /// It is `EMPTY`, `writing(sequence)` while a record is written or `complete(sequence)` afterwards.
+    state: usize,
+    /// The record in the slot.
+    record: Record,
+}
+
+/// Returns the state of a slot while the record with the sequence number is written to it.
+fn writing(sequence: usize) -> usize {
+    sequence * 2 + 1
+}
+
+/// Returns the state of a slot that holds the complete record with the sequence number.
+fn complete(sequence: usize) -> usize {
+    sequence * 2 + 2
+}
+
+/// Writes whole characters to a byte buffer until it is full.
+struct TruncatingWriter<'a> {
+    /// The buffer that is written to.
+    bytes: &'a mut [u8],
+    /// The number of bytes written.
+    length: usize,
+}
+
+impl<'a> TruncatingWriter<'a> {
+    /// Writes the formatted string to the buffer and returns the number of bytes written.
+    fn write(bytes: &'a mut [u8], args: fmt::Arguments) -> u8 {
+        let mut writer = TruncatingWriter { bytes, length: 0 };
+
+        // Writing cannot fail, it only truncates.
+        writer.write_fmt(args).ok();
+
+        writer.length as u8
+    }
+}
+
+impl<'a> Write for TruncatingWriter<'a> {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        for character in string.chars() {
+            let start = self.length;
+            let end = start + character.len_utf8();
+
+            if end > self.bytes.len() {
+                break;
+            }
+
+            character.encode_utf8(&mut self.bytes[start..end]);
+            self.length = end;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the string stored in the first `length` bytes.
+fn text(bytes: &[u8], length: u8) -> &str {
+    // Only whole characters are stored.
+    str::from_utf8(&bytes[..usize::from(length)]).unwrap_or("")
+}
+
+/// A log record kept in the buffer.
+///
+/// The module path, the file name and the message are truncated if they are too long.
+#[derive(Clone, Copy)]
+pub struct Record {
+    /// The sequence number of the record.
+    sequence: usize,
+    /// The time the record was logged at.
+    timestamp: Instant,
+    /// The processor that logged the record.
+    cpu: usize,
+    /// The level of the record.
+    level: Level,
+    /// The line in the source file that logged the record.
+    line: u32,
+    /// The path of the module that logged the record.
+    module: [u8; MODULE_LENGTH],
+    /// The length of the module path.
+    module_length: u8,
+    /// The source file that logged the record.
+    file: [u8; FILE_LENGTH],
+    /// The length of the file name.
+    file_length: u8,
+    /// The message of the record.
+    message: [u8; MESSAGE_LENGTH],
+    /// The length of the message.
+    message_length: u8,
+}
+
+impl Record {
+    /// Returns the sequence number of the record.
+    ///
+    /// The sequence numbers count the records logged since boot.
+    pub fn sequence(&self) -> usize {
+        self.sequence
+    }
+
+    /// Returns the time the record was logged at.
+    pub fn timestamp(&self) -> Instant {
+        self.timestamp
+    }
+
+    /// Returns the processor that logged the record.
+    pub fn cpu(&self) -> usize {
+        self.cpu
+    }
+
+    /// Returns the level of the record.
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    /// Returns the path of the module that logged the record.
+    pub fn module(&self) -> &str {
+        text(&self.module, self.module_length)
+    }
+
+    /// Returns the source file that logged the record.
+    pub fn file(&self) -> &str {
+        text(&self.file, self.file_length)
+    }
+
+    /// Returns the line in the source file that logged the record.
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    /// Returns the message of the record.
+    pub fn message(&self) -> &str {
+        text(&self.message, self.message_length)
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let nanoseconds = self.timestamp.as_nanoseconds();
+
+        write!(
+            f,
+            "[{:5}.{:06}] CPU{} {:5} {}: {} ({}:{})",
+            nanoseconds / 1_000_000_000,
+            nanoseconds % 1_000_000_000 / 1000,
+            self.cpu,
+            self.level,
+            self.module(),
+            self.message(),
+            self.file(),
+            self.line
+        )
+    }
+}
+
+/// Adds the log record to the buffer, overwriting the oldest record if the buffer is full.
+///
+/// Returns the record as it was stored, which is also returned if it had to be dropped.
+pub fn push(record: &log::Record) -> Record {
+    let sequence = NEXT_SEQUENCE.fetch_add(1, Ordering::SeqCst);
+
+    let mut stored = Record {
+        sequence,
+        timestamp: Instant::now_or_latest(),
+        cpu: Arch::current_cpu(),
+        level: record.level(),
+        line: record.line().unwrap_or(0),
+        module: [0; MODULE_LENGTH],
+        module_length: 0,
+        file: [0; FILE_LENGTH],
+        file_length: 0,
+        message: [0; MESSAGE_LENGTH],
+        message_length: 0,
+    };
+
+    stored.module_length = TruncatingWriter::write(
+        &mut stored.module,
+        format_args!("{}", record.module_path().unwrap_or("")),
+    );
+    stored.file_length = TruncatingWriter::write(
+        &mut stored.file,
+        format_args!("{}", record.file().unwrap_or("")),
+    );
+    stored.message_length = TruncatingWriter::write(&mut stored.message, *record.args());
+
+    let slot = SLOTS.get(sequence);
+    let state = SLOTS.state(sequence);
+    let current = state.load(Ordering::Acquire);
+
+    // The slot is only claimed if it doesn't belong to a newer record.
+    // An older record that is still being written is abandoned, since its writer may never finish.
+    if current >= writing(sequence)
+        || state
+            .compare_exchange(
+                current,
+                writing(sequence),
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .is_err()
+    {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+        return stored;
+    }
+
+    // Readers must see that the slot is being written before the record changes.
+    fence(Ordering::Release);
+
+    // This is safe, because the slot was claimed above, so no one else writes to it.
+    unsafe { ptr::write_volatile(&mut (*slot).record, stored) };
+
+    // The record is lost if a newer record claimed the slot meanwhile.
+    if state
+        .compare_exchange(
+            writing(sequence),
+            complete(sequence),
+            Ordering::Release,
+            Ordering::Relaxed,
+        )
+        .is_err()
+    {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+
+    stored
+}
+
+/// Returns the record with the given sequence number, if it is still in the buffer.
+pub fn get(sequence: usize) -> Option<Record> {
+    let slot = SLOTS.get(sequence);
+    let state = SLOTS.state(sequence);
+
+    if state.load(Ordering::Acquire) != complete(sequence) {
+        return None;
+    }
+
+    // This is safe, because the record is only used if it wasn't modified while it was copied.
+    let record = unsafe { ptr::read_volatile(&(*slot).record) };
+
+    fence(Ordering::Acquire);
+
+    if state.load(Ordering::Relaxed) == complete(sequence) {
+        Some(record)
+    } else {
+        None
+    }
+}
+
+/// Returns the sequence number that the next record will get.
+pub fn next_sequence() -> usize {
+    NEXT_SEQUENCE.load(Ordering::SeqCst)
+}
+
+/// Returns the number of records that were dropped, because a newer record was written to their slot at the same time.
+pub fn dropped_count() -> usize {
+    DROPPED.load(Ordering::Relaxed)
+}
+
+/// Returns an iterator over the records in the buffer, from the oldest to the newest.
+pub fn records() -> Records {
+    records_since(0)
+}
+
+/// Returns an iterator over the records in the buffer with a sequence number of at least `sequence`.
+pub fn records_since(sequence: usize) -> Records {
+    let end = next_sequence();
+
+    Records {
+        next: sequence.max(end.saturating_sub(RECORD_COUNT)),
+        end,
+    }
+}
+
+/// An iterator over the records in the buffer.
+///
+/// Records that were overwritten or are not complete yet are skipped.
+pub struct Records {
+    /// The sequence number of the next record.
+    next: usize,
+    /// The sequence number after the last record.
+    end: usize,
+}
+
+impl Iterator for Records {
+    type Item = Record;
+
+    fn next(&mut self) -> Option<Record> {
+        while self.next < self.end {
+            let sequence = self.next;
+            self.next += 1;
+
+            if let Some(record) = get(sequence) {
+                return Some(record);
+            }
+        }
+
+        None
+    }
+}
@@ -0,0 +1,385 @@
+//! Decides which log messages are logged and how they are shown.
+//!
+//! Every module can have its own log level, which also applies to its submodules.
+//! The levels are given as directives in the style of `env_logger`:
+//! a directive is either a level, which applies to all modules without their own level,
+//! or `<module path>=<level>`.
+//! The filter is set up from the `loglevel` option on the command line and can be changed at runtime.
+//!
+//! Log messages can additionally be prefixed with their timestamp, their processor and their source location,
+//! which is selected by the `logprefix` option on the command line.
+
+use bitflags::bitflags;
+use core::{
+    fmt,
+    str::{self, FromStr},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use log::{LevelFilter, Metadata};
+
+use crate::{cmdline::Configuration, sync::Mutex};
+
+/// The maximum number of modules with their own log level.
+const MAXIMUM_DIRECTIVE_COUNT: usize = 16;
+
+/// The maximum length of the module path of a directive in bytes.
+const MAXIMUM_MODULE_LENGTH: usize = 64;
+
+/// The log levels of the modules.
+///
+/// The filter has a fixed size, because it is used before the heap is set up.
+static FILTER: Mutex<Filter> = Mutex::new(Filter {
+    default: crate::LOG_LEVEL,
+    directives: [None; MAXIMUM_DIRECTIVE_COUNT],
+});
+
+/// The bits of the prefixes that are shown before log messages.
+static PREFIXES: AtomicUsize = AtomicUsize::new(0);
+
+bitflags! {
+    /// The information that is shown before log messages.
+    pub struct Prefixes: u8 {
+        /// The time since boot.
+        const TIMESTAMP = 1 << 0;
+        /// The processor that logged the message.
+        const CPU = 1 << 1;
+        /// The source file and line that logged the message.
+        const LOCATION = 1 << 2;
+    }
+}
+
+impl FromStr for Prefixes {
+    type Err = LogFilterError;
+
+    /// Parses comma separated prefix names, which are `timestamp`, `cpu` and `location`.
+    ///
+    /// `none` or an empty string select no prefixes.
+    fn from_str(names: &str) -> Result<Prefixes, LogFilterError> {
+        let mut prefixes = Prefixes::empty();
+
+        for name in names.split(',') {
+            prefixes |= match name {
+                "timestamp" => Prefixes::TIMESTAMP,
+                "cpu" => Prefixes::CPU,
+                "location" => Prefixes::LOCATION,
+                "none" | "" => Prefixes::empty(),
+                _ => return Err(LogFilterError::InvalidPrefix),
+            };
+        }
+
+        Ok(prefixes)
+    }
+}
+
+/// The errors that can occur when changing the log filter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFilterError {
+    /// A directive is neither a level nor a module path followed by a level.
+    InvalidDirective,
+    /// The name of a prefix is unknown.
+    InvalidPrefix,
+    /// The module path is longer than the filter can store.
+    ModulePathTooLong,
+    /// The maximum number of modules with their own level was reached.
+    TooManyDirectives,
+    /// The filter is in use and could not be changed without waiting.
+    Busy,
+}
+
+impl fmt::Display for LogFilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LogFilterError::InvalidDirective => write!(f, "the log directive is invalid"),
+            LogFilterError::InvalidPrefix => write!(f, "the log prefix is unknown"),
+            LogFilterError::ModulePathTooLong => write!(f, "the module path is too long"),
+            LogFilterError::TooManyDirectives => {
+                write!(f, "too many modules have their own log level")
+            }
+            LogFilterError::Busy => write!(f, "the log filter is busy"),
+        }
+    }
+}
+
+/// The log level of a module.
+#[derive(Clone, Copy)]
+struct Directive {
+    /// The bytes of the module path.
+    module: [u8; MAXIMUM_MODULE_LENGTH],
+    /// The length of the module path.
+    length: usize,
+    /// The log level of the module and its submodules.
+    level: LevelFilter,
+}
+
+impl Directive {
+    /// Returns the module path of the directive.
+    fn module(&self) -> &str {
+        // Only whole strings are stored.
+        str::from_utf8(&self.module[..self.length]).unwrap_or("")
+    }
+}
+
+/// The log levels of all modules.
+#[derive(Clone, Copy)]
+struct Filter {
+    /// The log level of modules without their own level.
+    default: LevelFilter,
+    /// The modules with their own level.
+    directives: [Option<Directive>; MAXIMUM_DIRECTIVE_COUNT],
+}
+
+impl Filter {
+    /// Returns the log level of the module with the given path.
+    fn level(&self, module_path: &str) -> LevelFilter {
+        self.directives
+            .iter()
+            .filter_map(|directive| directive.as_ref())
+            .filter(|directive| module_matches(module_path, directive.module()))
+            .max_by_key(|directive| directive.length)
+            .map_or(self.default, |directive| directive.level)
+    }
+
+    /// Sets the log level of the module and its submodules.
+    fn set_level(&mut self, module: &str, level: LevelFilter) -> Result<(), LogFilterError> {
+        if module.len() > MAXIMUM_MODULE_LENGTH {
+            return Err(LogFilterError::ModulePathTooLong);
+        }
+
+        let mut directive = Directive {
+            module: [0; MAXIMUM_MODULE_LENGTH],
+            length: module.len(),
+            level,
+        };
+        directive.module[..module.len()].copy_from_slice(module.as_bytes());
+
+        let index = match self.position(module) {
+            Some(index) => index,
+            None => self
+                .directives
+                .iter()
+                .position(|directive| directive.is_none())
+                .ok_or(LogFilterError::TooManyDirectives)?,
+        };
+
+        self.directives[index] = Some(directive);
+
+        Ok(())
+    }
+
+    /// Returns the index of the directive for the module.
+    fn position(&self, module: &str) -> Option<usize> {
+        self.directives
+            .iter()
+            .position(|directive| directive.map_or(false, |directive| directive.module() == module))
+    }
+
+    /// Applies the comma separated directives, stopping at the first invalid one.
+    fn apply(&mut self, directives: &str) -> Result<(), LogFilterError> {
+        for directive in directives
+            .split(',')
+            .filter(|directive| !directive.is_empty())
+        {
+            match parse_directive(directive) {
+                Some((Some(module), level)) => self.set_level(module, level)?,
+                Some((None, level)) => self.default = level,
+                None => return Err(LogFilterError::InvalidDirective),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the most verbose log level of all modules.
+    fn max_level(&self) -> LevelFilter {
+        self.directives
+            .iter()
+            .filter_map(|directive| directive.as_ref())
+            .map(|directive| directive.level)
+            .fold(self.default, |maximum, level| maximum.max(level))
+    }
+
+    /// Calls the function with the default level and with every module that has its own level.
+    fn for_each_level<F: FnMut(Option<&str>, LevelFilter)>(&self, mut function: F) {
+        function(None, self.default);
+
+        for directive in self
+            .directives
+            .iter()
+            .filter_map(|directive| directive.as_ref())
+        {
+            function(Some(directive.module()), directive.level);
+        }
+    }
+
+    /// Lets the `log` crate discard messages that no module would log.
+    fn update_max_level(&self) {
+        log::set_max_level(self.max_level());
+    }
+}
+
+/// Checks if the module path names the module or one of its submodules.
+pub fn module_matches(module_path: &str, module: &str) -> bool {
+    module_path.starts_with(module)
+        && (module_path.len() == module.len() || module_path[module.len()..].starts_with("::"))
+}
+
+/// Parses a directive, which is either a level or `<module path>=<level>`.
+///
+/// Returns the module path, if one was given, and the level.
+pub fn parse_directive(directive: &str) -> Option<(Option<&str>, LevelFilter)> {
+    match directive.find('=') {
+        Some(index) => LevelFilter::from_str(&directive[index + 1..])
+            .ok()
+            .map(|level| (Some(&directive[..index]), level)),
+        None => LevelFilter::from_str(directive)
+            .ok()
+            .map(|level| (None, level)),
+    }
+}
+
+/// Returns the log level of the module with the given path.
+pub fn level(module_path: &str) -> LevelFilter {
+    FILTER.lock().level(module_path)
+}
+
+/// Checks if a message with the metadata should be logged.
+///
+/// Messages that no module would log are discarded without taking the lock of the filter.
+/// If the filter is in use, the message is logged as long as any module would log it.
+pub fn enabled(metadata: &Metadata) -> bool {
+    if metadata.level() > log::max_level() {
+        return false;
+    }
+
+    FILTER.try_lock().map_or(true, |filter| {
+        metadata.level() <= filter.level(metadata.target())
+    })
+}
+
+/// Sets the log level of the modules without their own level.
+pub fn set_default_level(level: LevelFilter) {
+    let mut filter = FILTER.lock();
+
+    filter.default = level;
+    filter.update_max_level();
+}
+
+/// Sets the log level of the module and its submodules.
+pub fn set_level(module: &str, level: LevelFilter) -> Result<(), LogFilterError> {
+    let mut filter = FILTER.lock();
+
+    filter.set_level(module, level)?;
+    filter.update_max_level();
+
+    Ok(())
+}
+
+/// Removes the log level of the module, so that it uses the level of its parent module again.
+///
+/// Returns `false` if the module had no own level.
+pub fn remove_level(module: &str) -> bool {
+    let mut filter = FILTER.lock();
+
+    match filter.position(module) {
+        Some(index) => {
+            filter.directives[index] = None;
+            filter.update_max_level();
+
+            true
+        }
+        None => false,
+    }
+}
+
+/// Replaces the log levels of all modules with the comma separated directives.
+///
+/// Nothing is changed if a directive is invalid.
+pub fn set_directives(directives: &str) -> Result<(), LogFilterError> {
+    let mut filter = FILTER.lock();
+    let mut changed = Filter {
+        default: crate::LOG_LEVEL,
+        directives: [None; MAXIMUM_DIRECTIVE_COUNT],
+    };
+
+    changed.apply(directives)?;
+    *filter = changed;
+    filter.update_max_level();
+
+    Ok(())
+}
+
+/// Calls the function with the log level of the modules without their own level
+/// and with every module that has its own level.
+///
+/// The module path is `None` for the default level.
+pub fn for_each_level<F: FnMut(Option<&str>, LevelFilter)>(function: F) {
+    // The function is called without holding the lock, so that it can log.
+    let filter = *FILTER.lock();
+
+    filter.for_each_level(function);
+}
+
+/// Applies a single directive, which may also be `<module path>=` to remove the level of the module,
+/// without waiting for the filter.
+///
+/// Returns `false` if the level of a module was to be removed, but the module had no own level.
+/// Fails with `LogFilterError::Busy` if the filter is in use, which makes this usable from the debug shell.
+pub fn try_apply(directive: &str) -> Result<bool, LogFilterError> {
+    let mut filter = FILTER.try_lock().ok_or(LogFilterError::Busy)?;
+
+    let found = if directive.ends_with('=') {
+        match filter.position(&directive[..directive.len() - 1]) {
+            Some(index) => {
+                filter.directives[index] = None;
+                true
+            }
+            None => false,
+        }
+    } else {
+        match parse_directive(directive) {
+            Some((Some(module), level)) => filter.set_level(module, level)?,
+            Some((None, level)) => filter.default = level,
+            None => return Err(LogFilterError::InvalidDirective),
+        }
+
+        true
+    };
+
+    filter.update_max_level();
+
+    Ok(found)
+}
+
+/// Like `for_each_level`, but fails with `LogFilterError::Busy` instead of waiting if the filter is in use.
+pub fn try_for_each_level<F: FnMut(Option<&str>, LevelFilter)>(
+    function: F,
+) -> Result<(), LogFilterError> {
+    let filter = *FILTER.try_lock().ok_or(LogFilterError::Busy)?;
+
+    filter.for_each_level(function);
+
+    Ok(())
+}
+
+/// Returns the prefixes shown before log messages.
+pub fn prefixes() -> Prefixes {
+    Prefixes::from_bits_truncate(PREFIXES.load(Ordering::Relaxed) as u8)
+}
+
+/// Selects the prefixes shown before log messages.
+pub fn set_prefixes(prefixes: Prefixes) {
+    PREFIXES.store(usize::from(prefixes.bits()), Ordering::Relaxed);
+}
+
+/// Sets up the log filter and the prefixes from the command line.
+pub fn configure(configuration: &Configuration) {
+    set_default_level(configuration.default_log_level());
+
+    for (module, level) in configuration.log_filters() {
+        if let Err(error) = set_level(module, level) {
+            log::warn!("Ignoring the log directive for \"{}\": {}.", module, error);
+        }
+    }
+
+    set_prefixes(configuration.log_prefixes());
+}
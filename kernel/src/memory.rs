@@ -0,0 +1,37 @@
+//! This module handles the memory management of the kernel.
+//!
+//! It keeps track of the free physical memory, provides the kernel heap and provides address spaces for user programs.
+//! The architecture specific paging code is hidden behind the `AddressSpace` type.
+
+mod address;
+mod address_space;
+mod device_memory;
+mod frame_allocator;
+pub mod heap;
+mod memory_region;
+
+pub use self::{
+    address::{PhysicalAddress, VirtualAddress},
+    address_space::{Access, AddressSpace, MemoryError, PageFlags},
+    device_memory::DeviceMemory,
+    frame_allocator::{FrameAllocator, FRAME_ALLOCATOR},
+    memory_region::{MemoryQuota, MemoryRegion},
+};
+use crate::arch::{Arch, Architecture};
+
+/// The size of a page in bytes.
+pub const PAGE_SIZE: usize = Arch::PAGE_SIZE;
+
+/// Allocates a physical frame and fills it with zeroes.
+pub fn allocate_zeroed_frame() -> Option<PhysicalAddress> {
+    let frame = FRAME_ALLOCATOR.lock().allocate()?;
+
+    // This is safe, because the frame was just allocated and is not used by anything else.
+    unsafe {
+        Arch::physical_to_virtual(frame)
+            .as_mut_ptr::<u8>()
+            .write_bytes(0, PAGE_SIZE);
+    }
+
+    Some(frame)
+}
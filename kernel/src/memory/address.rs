@@ -0,0 +1,137 @@
+//! Provides types for physical and virtual addresses.
+
+use core::{
+    fmt,
+    ops::{Add, AddAssign, Sub, SubAssign},
+};
+
+use super::PAGE_SIZE;
+
+/// Implements the common functionality of the address types.
+macro_rules! address_type {
+    ($(#[$attr:meta])* $name:ident) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        #[repr(transparent)]
+        pub struct $name(usize);
+
+        impl $name {
+            /// Creates a new address.
+            pub const fn new(address: usize) -> $name {
+                $name(address)
+            }
+
+            /// Returns the address as an integer.
+            pub const fn as_usize(self) -> usize {
+                self.0
+            }
+
+            /// Rounds the address down to the given alignment, which must be a power of two.
+            pub fn align_down(self, alignment: usize) -> $name {
+                debug_assert!(alignment.is_power_of_two());
+
+                $name(self.0 & !(alignment - 1))
+            }
+
+            /// Rounds the address up to the given alignment, which must be a power of two.
+            ///
+            /// Returns `None` if the aligned address would overflow.
+            pub fn align_up(self, alignment: usize) -> Option<$name> {
+                debug_assert!(alignment.is_power_of_two());
+
+                self.0
+                    .checked_add(alignment - 1)
+                    .map(|address| $name(address & !(alignment - 1)))
+            }
+
+            /// Returns the start address of the page containing this address.
+            pub fn page_align_down(self) -> $name {
+                self.align_down(PAGE_SIZE)
+            }
+
+            /// Returns the start address of the first page that starts at or after this address.
+            pub fn page_align_up(self) -> Option<$name> {
+                self.align_up(PAGE_SIZE)
+            }
+
+            /// Checks if the address is the start of a page.
+            pub fn is_page_aligned(self) -> bool {
+                self.offset_in_page() == 0
+            }
+
+            /// Returns the offset of this address from the start of its page.
+            pub fn offset_in_page(self) -> usize {
+                self.0 % PAGE_SIZE
+            }
+
+            /// Adds the given offset to the address, returning `None` on overflow.
+            pub fn checked_add(self, offset: usize) -> Option<$name> {
+                self.0.checked_add(offset).map($name)
+            }
+        }
+
+        impl Add<usize> for $name {
+            type Output = $name;
+
+            fn add(self, offset: usize) -> $name {
+                $name(self.0 + offset)
+            }
+        }
+
+        impl AddAssign<usize> for $name {
+            fn add_assign(&mut self, offset: usize) {
+                self.0 += offset;
+            }
+        }
+
+        impl Sub<usize> for $name {
+            type Output = $name;
+
+            fn sub(self, offset: usize) -> $name {
+                $name(self.0 - offset)
+            }
+        }
+
+        impl SubAssign<usize> for $name {
+            fn sub_assign(&mut self, offset: usize) {
+                self.0 -= offset;
+            }
+        }
+
+        impl Sub<$name> for $name {
+            type Output = usize;
+
+            fn sub(self, other: $name) -> usize {
+                self.0 - other.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{:#x}", self.0)
+            }
+        }
+    };
+}
+
+address_type!(
+    /// Represents an address in physical memory.
+    PhysicalAddress
+);
+
+address_type!(
+    /// Represents an address in a virtual address space.
+    VirtualAddress
+);
+
+impl VirtualAddress {
+    /// Returns a pointer to the address.
+    pub fn as_ptr<T>(self) -> *const T {
+        self.0 as *const T
+    }
+
+    /// Returns a mutable pointer to the address.
+    pub fn as_mut_ptr<T>(self) -> *mut T {
+        self.0 as *mut T
+    }
+}
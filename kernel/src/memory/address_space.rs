@@ -0,0 +1,696 @@
+//! Provides the address spaces used by user programs.
+//!
+//! Pages can be mapped eagerly or reserved, in which case their frames are allocated on the first access.
+//! Duplicated address spaces share their frames copy-on-write until one of them writes to a page.
+//! The frames of areas reserved for a pager are supplied by the pager instead.
+
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    vec::Vec,
+};
+use bitflags::bitflags;
+use core::{cmp::min, fmt};
+use lazy_static::lazy_static;
+
+use super::{allocate_zeroed_frame, PhysicalAddress, VirtualAddress, FRAME_ALLOCATOR, PAGE_SIZE};
+use crate::{
+    arch::{Arch, Architecture, PageTable},
+    ipc::Pager,
+    object::KernelObject,
+    sync::Mutex,
+};
+
+bitflags! {
+    /// The access permissions of a mapped page.
+    pub struct PageFlags: u8 {
+        /// The page can be written to.
+        const WRITABLE = 1 << 0;
+        /// Code on the page can be executed.
+        const EXECUTABLE = 1 << 1;
+        /// The page can be accessed from user mode.
+        const USER_ACCESSIBLE = 1 << 2;
+        /// The page is not cached.
+        const NO_CACHE = 1 << 3;
+        /// The page is logically writable, but its frame is copied before the first write.
+        const COPY_ON_WRITE = 1 << 4;
+    }
+}
+
+/// The kinds of memory accesses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    /// Data is read from memory.
+    Read,
+    /// Data is written to memory.
+    Write,
+    /// Code is fetched from memory.
+    Execute,
+}
+
+/// The errors that can occur when modifying an address space.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryError {
+    /// There is not enough free physical memory.
+    OutOfMemory,
+    /// The page at the given address is already mapped.
+    AlreadyMapped(VirtualAddress),
+    /// The page at the given address is not mapped.
+    NotMapped(VirtualAddress),
+    /// The given address is not part of user space.
+    OutsideUserSpace(VirtualAddress),
+    /// The page at the given address does not allow the access.
+    AccessViolation(VirtualAddress),
+    /// The page at the given address is a guard page, which is never mapped.
+    GuardPage(VirtualAddress),
+    /// The memory quota doesn't allow more pages.
+    QuotaExceeded,
+    /// The kernel heap has too little free memory for the objects.
+    HeapExhausted,
+}
+
+impl fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MemoryError::OutOfMemory => write!(f, "out of physical memory"),
+            MemoryError::AlreadyMapped(address) => {
+                write!(f, "the page at {} is already mapped", address)
+            }
+            MemoryError::NotMapped(address) => write!(f, "the page at {} is not mapped", address),
+            MemoryError::OutsideUserSpace(address) => {
+                write!(f, "the address {} is outside of user space", address)
+            }
+            MemoryError::AccessViolation(address) => {
+                write!(f, "the access to {} is not allowed", address)
+            }
+            MemoryError::GuardPage(address) => write!(f, "the page at {} is a guard page", address),
+            MemoryError::QuotaExceeded => write!(f, "the memory quota is exhausted"),
+            MemoryError::HeapExhausted => write!(f, "the kernel heap is exhausted"),
+        }
+    }
+}
+
+lazy_static! {
+    /// Counts the address spaces sharing each frame that is mapped copy-on-write.
+    ///
+    /// Frames that are owned by a single address space are not contained.
+    static ref SHARED_FRAMES: Mutex<BTreeMap<PhysicalAddress, usize>> = Mutex::new(BTreeMap::new());
+}
+
+/// Frees the frame of an address space, unless other address spaces still share it.
+fn release_frame(frame: PhysicalAddress) {
+    let mut shared_frames = SHARED_FRAMES.lock();
+
+    match shared_frames.get(&frame).cloned() {
+        Some(count) if count > 2 => {
+            shared_frames.insert(frame, count - 1);
+        }
+        // The last remaining address space owns the frame on its own now.
+        Some(_) => {
+            shared_frames.remove(&frame);
+        }
+        // This is safe, because the frame was only owned by the address space that released it.
+        None => unsafe { FRAME_ALLOCATOR.lock().deallocate(frame) },
+    }
+}
+
+/// Checks if the page flags allow the access.
+fn allows(flags: PageFlags, access: Access) -> bool {
+    match access {
+        Access::Read => true,
+        Access::Write => flags.contains(PageFlags::WRITABLE),
+        Access::Execute => flags.contains(PageFlags::EXECUTABLE),
+    }
+}
+
+/// A range of pages whose frames are allocated on their first access.
+#[derive(Clone)]
+struct Area {
+    /// The address just above the last page of the area.
+    end: VirtualAddress,
+    /// The access permissions the pages are mapped with.
+    flags: PageFlags,
+    /// The pager that supplies the frames, instead of zeroed frames being allocated.
+    pager: Option<Pager>,
+}
+
+/// A user address space.
+///
+/// The kernel is mapped into every address space, but only the user part can be modified.
+/// Frames mapped into the address space are owned by it and freed when it is dropped,
+/// unless they were mapped from another kernel object or are still shared copy-on-write.
+pub struct AddressSpace {
+    /// The page table hierarchy that backs the address space.
+    page_table: PageTable,
+    /// The objects owning the frames of the pages that are not owned by the address space.
+    shared: BTreeMap<VirtualAddress, KernelObject>,
+    /// The reserved areas, indexed by their first page.
+    areas: BTreeMap<VirtualAddress, Area>,
+    /// The pages that must never be mapped, so that running over the end of a stack faults.
+    guard_pages: BTreeSet<VirtualAddress>,
+}
+
+impl AddressSpace {
+    /// Creates a new address space without any user mappings.
+    pub fn new() -> Result<AddressSpace, MemoryError> {
+        Ok(AddressSpace {
+            page_table: PageTable::new().ok_or(MemoryError::OutOfMemory)?,
+            shared: BTreeMap::new(),
+            areas: BTreeMap::new(),
+            guard_pages: BTreeSet::new(),
+        })
+    }
+
+    /// Checks if the range of `length` bytes starting at `start` lies completely in user space.
+    pub fn is_user_range(start: VirtualAddress, length: usize) -> bool {
+        match start.checked_add(length) {
+            Some(end) => start >= Arch::USER_SPACE_START && end <= Arch::USER_SPACE_END,
+            None => false,
+        }
+    }
+
+    /// Returns an error if the page starting at `page` is not part of user space.
+    fn check_user_page(page: VirtualAddress) -> Result<(), MemoryError> {
+        debug_assert!(page.is_page_aligned());
+
+        if AddressSpace::is_user_range(page, PAGE_SIZE) {
+            Ok(())
+        } else {
+            Err(MemoryError::OutsideUserSpace(page))
+        }
+    }
+
+    /// Returns an error if the page starting at `page` cannot be mapped.
+    fn check_unmapped_page(&self, page: VirtualAddress) -> Result<(), MemoryError> {
+        AddressSpace::check_user_page(page)?;
+
+        if self.guard_pages.contains(&page) {
+            Err(MemoryError::GuardPage(page))
+        } else if self.page_table.translate(page).is_some() {
+            Err(MemoryError::AlreadyMapped(page))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns the reserved area containing the page starting at `page`.
+    fn area(&self, page: VirtualAddress) -> Option<Area> {
+        self.areas
+            .range(..=page)
+            .next_back()
+            .map(|(_, area)| area.clone())
+            .filter(|area| page < area.end)
+    }
+
+    /// Maps a new zeroed frame at the page starting at `page`.
+    pub fn map(&mut self, page: VirtualAddress, flags: PageFlags) -> Result<(), MemoryError> {
+        self.check_unmapped_page(page)?;
+
+        let frame = allocate_zeroed_frame().ok_or(MemoryError::OutOfMemory)?;
+
+        self.page_table.map(page, frame, flags).map_err(|error| {
+            // This is safe, because the frame was never mapped anywhere.
+            unsafe { FRAME_ALLOCATOR.lock().deallocate(frame) };
+
+            error
+        })
+    }
+
+    /// Maps new zeroed frames for all pages overlapping the `length` bytes starting at `start`.
+    pub fn map_range(
+        &mut self,
+        start: VirtualAddress,
+        length: usize,
+        flags: PageFlags,
+    ) -> Result<(), MemoryError> {
+        if !AddressSpace::is_user_range(start, length) {
+            return Err(MemoryError::OutsideUserSpace(start));
+        }
+
+        let mut page = start.page_align_down();
+
+        while page < start + length {
+            self.map(page, flags)?;
+            page += PAGE_SIZE;
+        }
+
+        Ok(())
+    }
+
+    /// Maps `frame`, which is owned by `owner`, at the page starting at `page`.
+    ///
+    /// The owner is kept alive while the frame is mapped and the frame is not freed by the address space.
+    pub fn map_shared(
+        &mut self,
+        page: VirtualAddress,
+        frame: PhysicalAddress,
+        flags: PageFlags,
+        owner: KernelObject,
+    ) -> Result<(), MemoryError> {
+        self.check_unmapped_page(page)?;
+
+        self.page_table.map(page, frame, flags)?;
+        self.shared.insert(page, owner);
+
+        Ok(())
+    }
+
+    /// Reserves all pages overlapping the `length` bytes starting at `start`.
+    ///
+    /// The pages are mapped with zeroed frames when they are accessed for the first time.
+    pub fn reserve(
+        &mut self,
+        start: VirtualAddress,
+        length: usize,
+        flags: PageFlags,
+    ) -> Result<(), MemoryError> {
+        self.reserve_area(start, length, flags, None)
+    }
+
+    /// Reserves all pages overlapping the `length` bytes starting at `start` for the pager.
+    ///
+    /// Accesses to pages that are not mapped yet are sent to the pager, which supplies their frames.
+    pub fn reserve_paged(
+        &mut self,
+        start: VirtualAddress,
+        length: usize,
+        flags: PageFlags,
+        pager: Pager,
+    ) -> Result<(), MemoryError> {
+        self.reserve_area(start, length, flags, Some(pager))
+    }
+
+    /// Reserves all pages overlapping the `length` bytes starting at `start` as one area.
+    fn reserve_area(
+        &mut self,
+        start: VirtualAddress,
+        length: usize,
+        flags: PageFlags,
+        pager: Option<Pager>,
+    ) -> Result<(), MemoryError> {
+        if length == 0 || !AddressSpace::is_user_range(start, length) {
+            return Err(MemoryError::OutsideUserSpace(start));
+        }
+
+        let first_page = start.page_align_down();
+        let end = (start + length)
+            .page_align_up()
+            .ok_or(MemoryError::OutsideUserSpace(start))?;
+
+        if let Some(&page) = self.guard_pages.range(first_page..end).next() {
+            return Err(MemoryError::GuardPage(page));
+        }
+
+        let overlapping = self
+            .areas
+            .range(..end)
+            .next_back()
+            .filter(|(_, area)| area.end > first_page);
+
+        if let Some((&area_start, _)) = overlapping {
+            return Err(MemoryError::AlreadyMapped(area_start.max(first_page)));
+        }
+
+        self.areas.insert(first_page, Area { end, flags, pager });
+
+        Ok(())
+    }
+
+    /// Reserves a stack of up to `size` bytes below `top`, which grows on demand.
+    ///
+    /// The page below the stack is a guard page, so that overflowing the stack faults.
+    pub fn reserve_stack(&mut self, top: VirtualAddress, size: usize) -> Result<(), MemoryError> {
+        let bottom = top
+            .as_usize()
+            .checked_sub(size)
+            .map(VirtualAddress::new)
+            .filter(|bottom| bottom.is_page_aligned())
+            .ok_or(MemoryError::OutsideUserSpace(top))?;
+        let guard_page = bottom
+            .as_usize()
+            .checked_sub(PAGE_SIZE)
+            .map(VirtualAddress::new)
+            .ok_or(MemoryError::OutsideUserSpace(bottom))?;
+
+        self.check_unmapped_page(guard_page)?;
+
+        if self.area(guard_page).is_some() {
+            return Err(MemoryError::AlreadyMapped(guard_page));
+        }
+
+        self.reserve(
+            bottom,
+            size,
+            PageFlags::USER_ACCESSIBLE | PageFlags::WRITABLE,
+        )?;
+        self.guard_pages.insert(guard_page);
+
+        Ok(())
+    }
+
+    /// Unmaps the page starting at `page` and frees its frame, unless it is owned by another object.
+    ///
+    /// The page stays reserved if it is part of a reserved area.
+    pub fn unmap(&mut self, page: VirtualAddress) -> Result<(), MemoryError> {
+        AddressSpace::check_user_page(page)?;
+
+        let frame = self
+            .page_table
+            .unmap(page)
+            .ok_or(MemoryError::NotMapped(page))?;
+
+        if self.shared.remove(&page).is_none() {
+            release_frame(frame);
+        }
+
+        Ok(())
+    }
+
+    /// Changes the access permissions of the page starting at `page`.
+    pub fn protect(&mut self, page: VirtualAddress, flags: PageFlags) -> Result<(), MemoryError> {
+        AddressSpace::check_user_page(page)?;
+
+        let (frame, _) = self
+            .page_table
+            .translate(page)
+            .ok_or(MemoryError::NotMapped(page))?;
+        let mut flags = flags - PageFlags::COPY_ON_WRITE;
+
+        // Shared frames must be copied before they can be written.
+        if flags.contains(PageFlags::WRITABLE) && SHARED_FRAMES.lock().contains_key(&frame) {
+            flags = flags - PageFlags::WRITABLE | PageFlags::COPY_ON_WRITE;
+        }
+
+        self.page_table.set_flags(page, flags)
+    }
+
+    /// Returns the access permissions of the page starting at `page`, if it is mapped.
+    pub fn flags(&self, page: VirtualAddress) -> Option<PageFlags> {
+        self.page_table.translate(page).map(|(_, flags)| flags)
+    }
+
+    /// Checks if the page starting at `page` is mapped with permissions that allow the access.
+    pub fn is_accessible(&self, page: VirtualAddress, access: Access) -> bool {
+        self.flags(page)
+            .map_or(false, |flags| allows(flags, access))
+    }
+
+    /// Returns the access permissions of the area containing the page starting at `page`,
+    /// if it is managed by a pager and the page is not mapped yet.
+    pub fn paged_flags(&self, page: VirtualAddress) -> Option<PageFlags> {
+        if self.page_table.translate(page).is_some() {
+            return None;
+        }
+
+        self.area(page)
+            .filter(|area| area.pager.is_some())
+            .map(|area| area.flags)
+    }
+
+    /// Returns the physical address that `address` is mapped to.
+    pub fn translate(&self, address: VirtualAddress) -> Option<PhysicalAddress> {
+        self.page_table
+            .translate(address.page_align_down())
+            .map(|(frame, _)| frame + address.offset_in_page())
+    }
+
+    /// Gives the page starting at `page` its own writable frame, if it is mapped copy-on-write.
+    fn copy_on_write(
+        &mut self,
+        page: VirtualAddress,
+        frame: PhysicalAddress,
+        flags: PageFlags,
+    ) -> Result<(), MemoryError> {
+        let flags = flags - PageFlags::COPY_ON_WRITE | PageFlags::WRITABLE;
+        let mut shared_frames = SHARED_FRAMES.lock();
+
+        let count = match shared_frames.get_mut(&frame) {
+            Some(count) => count,
+            // The other address spaces already stopped sharing the frame.
+            None => return self.page_table.set_flags(page, flags),
+        };
+
+        let copy = FRAME_ALLOCATOR
+            .lock()
+            .allocate()
+            .ok_or(MemoryError::OutOfMemory)?;
+
+        // This is safe, because the copy was just allocated and the frame is only read.
+        unsafe {
+            Arch::physical_to_virtual(copy)
+                .as_mut_ptr::<u8>()
+                .copy_from_nonoverlapping(Arch::physical_to_virtual(frame).as_ptr(), PAGE_SIZE);
+        }
+
+        *count -= 1;
+
+        if *count == 1 {
+            shared_frames.remove(&frame);
+        }
+
+        self.page_table.unmap(page);
+        self.page_table.map(page, copy, flags)
+    }
+
+    /// Handles a fault caused by the access to `address`.
+    ///
+    /// Reserved pages are mapped on their first access and pages mapped copy-on-write are copied on their first write.
+    /// Returns the pager of the area if the fault has to be resolved by it.
+    /// Returns an error if the access is not allowed, in which case the fault is an error of the user program.
+    pub fn handle_fault(
+        &mut self,
+        address: VirtualAddress,
+        access: Access,
+    ) -> Result<Option<Pager>, MemoryError> {
+        let page = address.page_align_down();
+
+        AddressSpace::check_user_page(page)?;
+
+        match self.page_table.translate(page) {
+            // Another thread may have resolved the fault already.
+            Some((_, flags)) if allows(flags, access) => Ok(None),
+            Some((frame, flags))
+                if access == Access::Write && flags.contains(PageFlags::COPY_ON_WRITE) =>
+            {
+                self.copy_on_write(page, frame, flags).map(|_| None)
+            }
+            Some(_) => Err(MemoryError::AccessViolation(address)),
+            None => match self.area(page) {
+                Some(ref area) if !allows(area.flags, access) => {
+                    Err(MemoryError::AccessViolation(address))
+                }
+                Some(Area {
+                    pager: Some(pager), ..
+                }) => Ok(Some(pager)),
+                Some(area) => self.map(page, area.flags).map(|_| None),
+                None if self.guard_pages.contains(&page) => Err(MemoryError::GuardPage(page)),
+                None => Err(MemoryError::NotMapped(page)),
+            },
+        }
+    }
+
+    /// Makes sure that the kernel can access the page starting at `page`, ignoring its access permissions.
+    fn prepare_kernel_access(
+        &mut self,
+        page: VirtualAddress,
+        write: bool,
+    ) -> Result<(), MemoryError> {
+        match self.page_table.translate(page) {
+            Some((frame, flags)) if write && flags.contains(PageFlags::COPY_ON_WRITE) => {
+                self.copy_on_write(page, frame, flags)
+            }
+            Some(_) => Ok(()),
+            None => match self.area(page) {
+                // Only the pager can supply the frames of its area.
+                Some(Area { pager: Some(_), .. }) | None => Err(MemoryError::NotMapped(page)),
+                Some(area) => self.map(page, area.flags),
+            },
+        }
+    }
+
+    /// Calls `f` with the kernel address and length of every page sized chunk of the given range.
+    ///
+    /// Missing pages of reserved areas are mapped and, if `write` is set, pages mapped copy-on-write are copied.
+    fn for_each_chunk<F>(
+        &mut self,
+        start: VirtualAddress,
+        length: usize,
+        write: bool,
+        mut f: F,
+    ) -> Result<(), MemoryError>
+    where
+        F: FnMut(VirtualAddress, usize, usize),
+    {
+        if !AddressSpace::is_user_range(start, length) {
+            return Err(MemoryError::OutsideUserSpace(start));
+        }
+
+        let mut done = 0;
+
+        while done < length {
+            let address = start + done;
+            let chunk_length = min(PAGE_SIZE - address.offset_in_page(), length - done);
+
+            self.prepare_kernel_access(address.page_align_down(), write)?;
+
+            let physical_address = self
+                .translate(address)
+                .ok_or_else(|| MemoryError::NotMapped(address.page_align_down()))?;
+
+            f(
+                Arch::physical_to_virtual(physical_address),
+                done,
+                chunk_length,
+            );
+
+            done += chunk_length;
+        }
+
+        Ok(())
+    }
+
+    /// Copies the memory starting at `address` into `buffer`.
+    pub fn read(&mut self, address: VirtualAddress, buffer: &mut [u8]) -> Result<(), MemoryError> {
+        self.for_each_chunk(address, buffer.len(), false, |source, offset, length| {
+            // This is safe, because the source is a mapped frame of this address space.
+            unsafe {
+                source
+                    .as_ptr::<u8>()
+                    .copy_to_nonoverlapping(buffer[offset..].as_mut_ptr(), length)
+            }
+        })
+    }
+
+    /// Copies `data` into the address space starting at `address`.
+    ///
+    /// This ignores the access permissions of the pages.
+    pub fn write(&mut self, address: VirtualAddress, data: &[u8]) -> Result<(), MemoryError> {
+        self.for_each_chunk(address, data.len(), true, |target, offset, length| {
+            // This is safe, because the target is a mapped frame of this address space.
+            unsafe {
+                target
+                    .as_mut_ptr::<u8>()
+                    .copy_from_nonoverlapping(data[offset..].as_ptr(), length)
+            }
+        })
+    }
+
+    /// Checks that user mode is allowed to write to the range, so that the kernel can write to it on its behalf.
+    ///
+    /// Every page must be user accessible and writable or copy-on-write.
+    /// Pages that are not mapped yet are checked against the permissions of their reserved area.
+    pub fn check_user_write(
+        &self,
+        start: VirtualAddress,
+        length: usize,
+    ) -> Result<(), MemoryError> {
+        if !AddressSpace::is_user_range(start, length) {
+            return Err(MemoryError::OutsideUserSpace(start));
+        }
+
+        let mut page = start.page_align_down();
+
+        while page < start + length {
+            let flags = match self.page_table.translate(page) {
+                Some((_, flags)) => flags,
+                None => match self.area(page) {
+                    Some(area) => area.flags,
+                    None if self.guard_pages.contains(&page) => {
+                        return Err(MemoryError::GuardPage(page));
+                    }
+                    None => return Err(MemoryError::NotMapped(page)),
+                },
+            };
+
+            if !flags.contains(PageFlags::USER_ACCESSIBLE)
+                || !flags.intersects(PageFlags::WRITABLE | PageFlags::COPY_ON_WRITE)
+            {
+                return Err(MemoryError::AccessViolation(page));
+            }
+
+            page += PAGE_SIZE;
+        }
+
+        Ok(())
+    }
+
+    /// Copies `data` into the address space starting at `address` on behalf of user mode.
+    ///
+    /// Unlike `write`, this fails unless user mode is allowed to write to every page.
+    /// Pages mapped copy-on-write are copied first.
+    pub fn write_user(&mut self, address: VirtualAddress, data: &[u8]) -> Result<(), MemoryError> {
+        self.check_user_write(address, data.len())?;
+        self.write(address, data)
+    }
+
+    /// Returns the number of mapped pages in user space.
+    pub fn mapped_page_count(&self) -> usize {
+        let mut count = 0;
+
+        self.page_table.for_each_mapping(|_, _, _| count += 1);
+
+        count
+    }
+
+    /// Creates a copy of the address space.
+    ///
+    /// Frames owned by the address space are shared with the copy and writable pages become copy-on-write in both.
+    /// Frames of other objects are shared directly and reserved areas are reserved in the copy as well.
+    pub fn duplicate(&mut self) -> Result<AddressSpace, MemoryError> {
+        let mut copy = AddressSpace::new()?;
+        let mut mappings = Vec::new();
+
+        copy.areas = self.areas.clone();
+        copy.guard_pages = self.guard_pages.clone();
+
+        self.page_table
+            .for_each_mapping(|page, frame, flags| mappings.push((page, frame, flags)));
+
+        for (page, frame, flags) in mappings {
+            if let Some(owner) = self.shared.get(&page) {
+                copy.map_shared(page, frame, flags, owner.clone())?;
+                continue;
+            }
+
+            let flags = if flags.contains(PageFlags::WRITABLE) {
+                flags - PageFlags::WRITABLE | PageFlags::COPY_ON_WRITE
+            } else {
+                flags
+            };
+
+            *SHARED_FRAMES.lock().entry(frame).or_insert(1) += 1;
+
+            if let Err(error) = copy.page_table.map(page, frame, flags) {
+                release_frame(frame);
+                return Err(error);
+            }
+
+            self.page_table.set_flags(page, flags)?;
+        }
+
+        Ok(copy)
+    }
+
+    /// Makes this address space the active one.
+    ///
+    /// # Safety
+    /// The address space must not be dropped while it is active.
+    pub unsafe fn activate(&self) {
+        self.page_table.activate();
+    }
+}
+
+impl Drop for AddressSpace {
+    fn drop(&mut self) {
+        if self.page_table.is_active() {
+            PageTable::activate_kernel();
+        }
+
+        let shared = &self.shared;
+
+        self.page_table.unmap_all(|page, frame, _| {
+            if !shared.contains_key(&page) {
+                release_frame(frame);
+            }
+        });
+    }
+}
@@ -0,0 +1,46 @@
+//! Provides device memory, which are kernel objects representing memory mapped device registers.
+
+use super::{PhysicalAddress, PAGE_SIZE};
+
+/// A region of physical memory that belongs to a device.
+///
+/// Unlike a `MemoryRegion`, the region is not part of the usable memory and never freed.
+#[derive(Debug)]
+pub struct DeviceMemory {
+    /// The first frame of the region.
+    start: PhysicalAddress,
+    /// The number of pages in the region.
+    page_count: usize,
+}
+
+impl DeviceMemory {
+    /// Creates the region of `page_count` pages starting at `start`.
+    ///
+    /// # Safety
+    /// The region must only contain device memory that is not used by the kernel
+    /// and there must be no other `DeviceMemory` overlapping it.
+    pub unsafe fn new(start: PhysicalAddress, page_count: usize) -> DeviceMemory {
+        debug_assert!(start.is_page_aligned());
+
+        DeviceMemory { start, page_count }
+    }
+
+    /// Returns the number of pages in the region.
+    pub fn page_count(&self) -> usize {
+        self.page_count
+    }
+
+    /// Returns the size of the region in bytes.
+    pub fn size(&self) -> usize {
+        self.page_count * PAGE_SIZE
+    }
+
+    /// Returns the frame of the page with the given index.
+    pub fn frame(&self, index: usize) -> Option<PhysicalAddress> {
+        if index < self.page_count {
+            Some(self.start + index * PAGE_SIZE)
+        } else {
+            None
+        }
+    }
+}
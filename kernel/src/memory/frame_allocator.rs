@@ -0,0 +1,163 @@
+//! Manages the free physical frames of the system.
+//!
+//! Frames that were never handed out are taken from a small list of regions, while
+//! frames that were returned are kept in a linked list that is stored inside the free frames themselves.
+
+use super::{PhysicalAddress, PAGE_SIZE};
+use crate::{
+    arch::{Arch, Architecture},
+    sync::Mutex,
+};
+
+/// The maximum number of separate memory regions the frame allocator can keep track of.
+const MAX_REGIONS: usize = 128;
+
+/// Physical memory below this address is never handed out.
+///
+/// This keeps the null frame unused and leaves the legacy memory area for firmware and trampolines.
+const LOWEST_MANAGED_ADDRESS: PhysicalAddress = PhysicalAddress::new(0x10_0000);
+
+/// The frame allocator of the kernel.
+pub static FRAME_ALLOCATOR: Mutex<FrameAllocator> = Mutex::new(FrameAllocator::new());
+
+/// A contiguous region of frames that have never been allocated.
+#[derive(Clone, Copy)]
+struct Region {
+    /// The first frame in the region.
+    start: PhysicalAddress,
+    /// The number of frames left in the region.
+    frame_count: usize,
+}
+
+/// An empty region, used to fill the unused slots.
+const EMPTY_REGION: Region = Region {
+    start: PhysicalAddress::new(0),
+    frame_count: 0,
+};
+
+/// Keeps track of the free physical frames.
+pub struct FrameAllocator {
+    /// The regions of frames that were never allocated.
+    regions: [Region; MAX_REGIONS],
+    /// The number of used entries in `regions`.
+    region_count: usize,
+    /// The first frame in the list of deallocated frames.
+    ///
+    /// Each frame in the list stores the address of the next frame in its first word.
+    free_list: Option<PhysicalAddress>,
+    /// The total number of free frames.
+    free_frames: usize,
+}
+
+impl FrameAllocator {
+    /// Creates a frame allocator without any free frames.
+    pub const fn new() -> FrameAllocator {
+        FrameAllocator {
+            regions: [EMPTY_REGION; MAX_REGIONS],
+            region_count: 0,
+            free_list: None,
+            free_frames: 0,
+        }
+    }
+
+    /// Adds a region of free memory to the frame allocator.
+    ///
+    /// # Safety
+    /// The region must be unused memory that is accessible through `Arch::physical_to_virtual`.
+    pub unsafe fn add_region(&mut self, start: PhysicalAddress, frame_count: usize) {
+        let end = start + frame_count * PAGE_SIZE;
+        let start = if start < LOWEST_MANAGED_ADDRESS {
+            LOWEST_MANAGED_ADDRESS
+        } else {
+            start
+        };
+
+        if start >= end {
+            return;
+        }
+
+        if self.region_count == MAX_REGIONS {
+            log::warn!(
+                "Too many memory regions, ignoring {} frames at {}.",
+                (end - start) / PAGE_SIZE,
+                start
+            );
+            return;
+        }
+
+        self.regions[self.region_count] = Region {
+            start,
+            frame_count: (end - start) / PAGE_SIZE,
+        };
+        self.region_count += 1;
+        self.free_frames += (end - start) / PAGE_SIZE;
+    }
+
+    /// Allocates a single frame.
+    ///
+    /// The contents of the frame are undefined.
+    pub fn allocate(&mut self) -> Option<PhysicalAddress> {
+        if let Some(frame) = self.free_list {
+            // This is safe, because every frame in the free list stores the address of the next one.
+            let next = unsafe { *Arch::physical_to_virtual(frame).as_ptr::<usize>() };
+
+            self.free_list = if next == 0 {
+                None
+            } else {
+                Some(PhysicalAddress::new(next))
+            };
+            self.free_frames -= 1;
+
+            return Some(frame);
+        }
+
+        for region in self.regions[..self.region_count].iter_mut() {
+            if region.frame_count > 0 {
+                let frame = region.start;
+
+                region.start += PAGE_SIZE;
+                region.frame_count -= 1;
+                self.free_frames -= 1;
+
+                return Some(frame);
+            }
+        }
+
+        None
+    }
+
+    /// Allocates `count` physically contiguous frames, returning the first one.
+    ///
+    /// Only frames that were never allocated before are considered.
+    pub fn allocate_contiguous(&mut self, count: usize) -> Option<PhysicalAddress> {
+        let region = self.regions[..self.region_count]
+            .iter_mut()
+            .find(|region| region.frame_count >= count)?;
+        let start = region.start;
+
+        region.start += count * PAGE_SIZE;
+        region.frame_count -= count;
+        self.free_frames -= count;
+
+        Some(start)
+    }
+
+    /// Returns a frame to the allocator.
+    ///
+    /// # Safety
+    /// The frame must have been allocated by this allocator and must not be used anymore.
+    pub unsafe fn deallocate(&mut self, frame: PhysicalAddress) {
+        debug_assert!(frame.is_page_aligned());
+
+        *Arch::physical_to_virtual(frame).as_mut_ptr::<usize>() =
+            self.free_list.map(|next| next.as_usize()).unwrap_or(0);
+
+        self.free_list = Some(frame);
+        self.free_frames += 1;
+    }
+
+    /// Returns the number of frames that are currently free.
+    pub fn free_frames(&self) -> usize {
+        self.free_frames
+    }
+}
@@ -0,0 +1,241 @@
+//! Provides the kernel heap.
+//!
+//! The heap is a contiguous range of physical memory that is taken from the frame allocator once during boot.
+//! Free blocks are kept in a list sorted by address, so that neighbouring blocks can be merged again.
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    cmp::max,
+    mem::size_of,
+    ptr,
+};
+
+use super::{MemoryError, FRAME_ALLOCATOR, PAGE_SIZE};
+use crate::{
+    arch::{Arch, Architecture},
+    sync::Mutex,
+};
+
+/// The size of the kernel heap in bytes.
+pub const HEAP_SIZE: usize = 8 * 1024 * 1024;
+
+/// The number of bytes on the kernel heap that objects created for user space must leave free.
+///
+/// The kernel's own allocations use them, so that user space cannot make them fail.
+pub const HEAP_RESERVE: usize = 1024 * 1024;
+
+/// The alignment and size granularity of all blocks on the heap.
+const BLOCK_ALIGNMENT: usize = 16;
+
+/// The heap of the kernel.
+static HEAP: Mutex<Heap> = Mutex::new(Heap::empty());
+
+/// A free block on the heap.
+///
+/// The header is stored at the start of the free memory it describes.
+struct FreeBlock {
+    /// The size of the block in bytes, including the header.
+    size: usize,
+    /// The next free block, which has a higher address.
+    next: *mut FreeBlock,
+}
+
+/// A heap that keeps its free blocks in a sorted list.
+struct Heap {
+    /// The free block with the lowest address.
+    head: *mut FreeBlock,
+    /// The number of free bytes on the heap.
+    free: usize,
+}
+
+// This is safe, because the heap owns the memory its pointers refer to.
+unsafe impl Send for Heap {}
+
+/// Returns the size of the block that is used to satisfy an allocation with the given layout.
+fn block_size(layout: Layout) -> usize {
+    let size = max(layout.size(), size_of::<FreeBlock>());
+
+    (size + BLOCK_ALIGNMENT - 1) & !(BLOCK_ALIGNMENT - 1)
+}
+
+impl Heap {
+    /// Creates a heap without any memory.
+    const fn empty() -> Heap {
+        Heap {
+            head: ptr::null_mut(),
+            free: 0,
+        }
+    }
+
+    /// Adds the block of `size` bytes at `address` to the free list, merging it with its neighbours.
+    ///
+    /// # Safety
+    /// The block must be unused memory that is aligned to `BLOCK_ALIGNMENT`.
+    unsafe fn add_block(&mut self, address: usize, size: usize) {
+        debug_assert!(address % BLOCK_ALIGNMENT == 0 && size % BLOCK_ALIGNMENT == 0);
+
+        let mut previous: *mut FreeBlock = ptr::null_mut();
+        let mut current = self.head;
+
+        while !current.is_null() && (current as usize) < address {
+            previous = current;
+            current = (*current).next;
+        }
+
+        let block = address as *mut FreeBlock;
+        block.write(FreeBlock {
+            size,
+            next: current,
+        });
+
+        if previous.is_null() {
+            self.head = block;
+        } else {
+            (*previous).next = block;
+        }
+
+        if !current.is_null() && address + size == current as usize {
+            (*block).size += (*current).size;
+            (*block).next = (*current).next;
+        }
+
+        if !previous.is_null() && previous as usize + (*previous).size == address {
+            (*previous).size += (*block).size;
+            (*previous).next = (*block).next;
+        }
+
+        self.free += size;
+    }
+
+    /// Allocates memory for the given layout, returning a null pointer if there is not enough memory.
+    fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        let size = block_size(layout);
+        let alignment = max(layout.align(), BLOCK_ALIGNMENT);
+
+        let mut previous: *mut FreeBlock = ptr::null_mut();
+        let mut current = self.head;
+
+        // This is safe, because the free list only contains valid blocks.
+        unsafe {
+            while !current.is_null() {
+                let block_start = current as usize;
+                let block_end = block_start + (*current).size;
+                let start = (block_start + alignment - 1) & !(alignment - 1);
+
+                if start + size <= block_end {
+                    let next = (*current).next;
+
+                    if previous.is_null() {
+                        self.head = next;
+                    } else {
+                        (*previous).next = next;
+                    }
+                    self.free -= block_end - block_start;
+
+                    // Return the unused parts of the block to the free list.
+                    if start > block_start {
+                        self.add_block(block_start, start - block_start);
+                    }
+                    if block_end > start + size {
+                        self.add_block(start + size, block_end - (start + size));
+                    }
+
+                    return start as *mut u8;
+                }
+
+                previous = current;
+                current = (*current).next;
+            }
+        }
+
+        ptr::null_mut()
+    }
+
+    /// Returns the size of the largest free block in bytes.
+    fn largest_block(&self) -> usize {
+        let mut largest = 0;
+        let mut current = self.head;
+
+        // This is safe, because the free list only contains valid blocks.
+        unsafe {
+            while !current.is_null() {
+                largest = max(largest, (*current).size);
+                current = (*current).next;
+            }
+        }
+
+        largest
+    }
+
+    /// Returns the memory of an allocation to the heap.
+    ///
+    /// # Safety
+    /// The memory must have been allocated with the same layout by this heap.
+    unsafe fn deallocate(&mut self, pointer: *mut u8, layout: Layout) {
+        self.add_block(pointer as usize, block_size(layout));
+    }
+}
+
+/// The global allocator of the kernel, which allocates from the kernel heap.
+pub struct KernelAllocator;
+
+unsafe impl GlobalAlloc for KernelAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        HEAP.lock().allocate(layout)
+    }
+
+    unsafe fn dealloc(&self, pointer: *mut u8, layout: Layout) {
+        HEAP.lock().deallocate(pointer, layout)
+    }
+}
+
+/// Handles failed heap allocations.
+#[alloc_error_handler]
+fn allocation_error(layout: Layout) -> ! {
+    panic!(
+        "Could not allocate {} bytes with an alignment of {} on the kernel heap.",
+        layout.size(),
+        layout.align()
+    );
+}
+
+/// Initializes the kernel heap.
+///
+/// This must be called after the frame allocator knows about the free memory.
+pub fn init() {
+    let start = FRAME_ALLOCATOR
+        .lock()
+        .allocate_contiguous(HEAP_SIZE / PAGE_SIZE)
+        .expect("Could not allocate the kernel heap.");
+
+    // This is safe, because the frames were just allocated and are used for nothing else.
+    unsafe {
+        HEAP.lock()
+            .add_block(Arch::physical_to_virtual(start).as_usize(), HEAP_SIZE);
+    }
+
+    log::debug!("The kernel heap starts at {}.", start);
+}
+
+/// Returns the number of free bytes on the kernel heap.
+pub fn free_memory() -> usize {
+    HEAP.lock().free
+}
+
+/// Returns the number of free bytes on the kernel heap, unless the heap is in use.
+pub fn try_free_memory() -> Option<usize> {
+    HEAP.try_lock().map(|heap| heap.free)
+}
+
+/// Checks that objects of `size` bytes in total can be allocated on the kernel heap for user space.
+///
+/// Failed heap allocations panic, so system calls check this before creating objects.
+/// Fails if no free block can hold the objects or if fewer than `HEAP_RESERVE` bytes would remain free.
+pub fn check_available(size: usize) -> Result<(), MemoryError> {
+    let heap = HEAP.lock();
+
+    match size.checked_add(HEAP_RESERVE) {
+        Some(required) if required <= heap.free && size <= heap.largest_block() => Ok(()),
+        _ => Err(MemoryError::HeapExhausted),
+    }
+}
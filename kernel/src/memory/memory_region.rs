@@ -0,0 +1,145 @@
+//! Provides memory regions, which are kernel objects representing physical memory.
+//!
+//! The pages of regions created for user programs are charged to a quota,
+//! so that a program cannot take all physical memory or exhaust the kernel heap with the lists of frames.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use super::{allocate_zeroed_frame, MemoryError, PhysicalAddress, FRAME_ALLOCATOR, PAGE_SIZE};
+
+/// Limits the number of pages of the memory regions that are charged to it.
+///
+/// The pages are charged when a region is created and released when it is dropped.
+#[derive(Debug)]
+pub struct MemoryQuota {
+    /// The maximum number of pages.
+    limit: usize,
+    /// The number of pages that are currently charged.
+    used: AtomicUsize,
+}
+
+impl MemoryQuota {
+    /// Creates a quota that allows `limit` pages.
+    pub const fn new(limit: usize) -> MemoryQuota {
+        MemoryQuota {
+            limit,
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the maximum number of pages.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Returns the number of pages that are currently charged.
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::SeqCst)
+    }
+
+    /// Charges `page_count` pages, unless that exceeds the limit.
+    fn charge(&self, page_count: usize) -> Result<(), MemoryError> {
+        let mut used = self.used.load(Ordering::SeqCst);
+
+        loop {
+            let new_used = used
+                .checked_add(page_count)
+                .filter(|&new_used| new_used <= self.limit)
+                .ok_or(MemoryError::QuotaExceeded)?;
+
+            match self
+                .used
+                .compare_exchange(used, new_used, Ordering::SeqCst, Ordering::SeqCst)
+            {
+                Ok(_) => return Ok(()),
+                Err(current) => used = current,
+            }
+        }
+    }
+
+    /// Releases `page_count` pages that were charged before.
+    fn release(&self, page_count: usize) {
+        self.used.fetch_sub(page_count, Ordering::SeqCst);
+    }
+}
+
+/// A region of physical memory that can be handed out to user space.
+///
+/// The frames of the region are owned by it and freed when it is dropped.
+#[derive(Debug)]
+pub struct MemoryRegion {
+    /// The frames that make up the region.
+    frames: Vec<PhysicalAddress>,
+    /// The quota the pages of the region are charged to.
+    quota: Option<(Arc<MemoryQuota>, usize)>,
+}
+
+impl MemoryRegion {
+    /// Allocates a region of `page_count` zeroed pages.
+    pub fn new(page_count: usize) -> Result<MemoryRegion, MemoryError> {
+        MemoryRegion::allocate(page_count, None)
+    }
+
+    /// Allocates a region of `page_count` zeroed pages, which are charged to the quota.
+    ///
+    /// Nothing is allocated if the quota doesn't allow that many pages.
+    pub fn with_quota(
+        page_count: usize,
+        quota: Arc<MemoryQuota>,
+    ) -> Result<MemoryRegion, MemoryError> {
+        quota.charge(page_count)?;
+
+        MemoryRegion::allocate(page_count, Some((quota, page_count)))
+    }
+
+    /// Allocates a region of `page_count` zeroed pages, which were charged to the quota, if there is one.
+    fn allocate(
+        page_count: usize,
+        quota: Option<(Arc<MemoryQuota>, usize)>,
+    ) -> Result<MemoryRegion, MemoryError> {
+        let mut region = MemoryRegion {
+            frames: Vec::with_capacity(page_count),
+            quota,
+        };
+
+        for _ in 0..page_count {
+            // If the allocation fails, the frames allocated so far are freed by dropping the region.
+            region
+                .frames
+                .push(allocate_zeroed_frame().ok_or(MemoryError::OutOfMemory)?);
+        }
+
+        Ok(region)
+    }
+
+    /// Returns the number of pages in the region.
+    pub fn page_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns the size of the region in bytes.
+    pub fn size(&self) -> usize {
+        self.frames.len() * PAGE_SIZE
+    }
+
+    /// Returns the frame backing the page with the given index.
+    pub fn frame(&self, index: usize) -> Option<PhysicalAddress> {
+        self.frames.get(index).cloned()
+    }
+}
+
+impl Drop for MemoryRegion {
+    fn drop(&mut self) {
+        let mut frame_allocator = FRAME_ALLOCATOR.lock();
+
+        for &frame in &self.frames {
+            // This is safe, because the frames are owned by the region, which is no longer in use.
+            unsafe { frame_allocator.deallocate(frame) };
+        }
+
+        if let Some((quota, page_count)) = &self.quota {
+            quota.release(*page_count);
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! Provides the kernel objects that can be referenced by capabilities.
+
+use alloc::sync::Arc;
+use core::fmt;
+
+use crate::{
+    io_port::IoPortRange,
+    ipc::{Endpoint, Notification},
+    irq::IrqLine,
+    memory::{AddressSpace, DeviceMemory, MemoryRegion, PageFlags, PhysicalAddress},
+    process::Process,
+    sync::Mutex,
+    thread::Thread,
+};
+
+/// The types of kernel objects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ObjectType {
+    /// A thread.
+    Thread,
+    /// An address space.
+    AddressSpace,
+    /// An IPC endpoint.
+    Endpoint,
+    /// A notification.
+    Notification,
+    /// A region of physical memory.
+    MemoryRegion,
+    /// A hardware interrupt line.
+    Irq,
+    /// A range of I/O ports.
+    IoPorts,
+    /// A region of device memory.
+    DeviceMemory,
+    /// A process.
+    Process,
+    /// The kernel log.
+    Log,
+}
+
+/// A reference to a kernel object.
+///
+/// The object lives as long as there are references to it.
+#[derive(Clone)]
+pub enum KernelObject {
+    /// A thread.
+    Thread(Arc<Thread>),
+    /// An address space.
+    AddressSpace(Arc<Mutex<AddressSpace>>),
+    /// An IPC endpoint.
+    Endpoint(Arc<Endpoint>),
+    /// A notification.
+    Notification(Arc<Notification>),
+    /// A region of physical memory.
+    MemoryRegion(Arc<MemoryRegion>),
+    /// A hardware interrupt line.
+    Irq(Arc<IrqLine>),
+    /// A range of I/O ports.
+    IoPorts(Arc<IoPortRange>),
+    /// A region of device memory.
+    DeviceMemory(Arc<DeviceMemory>),
+    /// A process.
+    Process(Arc<Process>),
+    /// The kernel log, whose records can be read with a capability to it.
+    Log,
+}
+
+impl KernelObject {
+    /// Returns the type of the object.
+    pub fn object_type(&self) -> ObjectType {
+        match self {
+            KernelObject::Thread(_) => ObjectType::Thread,
+            KernelObject::AddressSpace(_) => ObjectType::AddressSpace,
+            KernelObject::Endpoint(_) => ObjectType::Endpoint,
+            KernelObject::Notification(_) => ObjectType::Notification,
+            KernelObject::MemoryRegion(_) => ObjectType::MemoryRegion,
+            KernelObject::Irq(_) => ObjectType::Irq,
+            KernelObject::IoPorts(_) => ObjectType::IoPorts,
+            KernelObject::DeviceMemory(_) => ObjectType::DeviceMemory,
+            KernelObject::Process(_) => ObjectType::Process,
+            KernelObject::Log => ObjectType::Log,
+        }
+    }
+
+    /// Checks if capabilities to the object can carry a badge.
+    pub fn is_badgeable(&self) -> bool {
+        match self {
+            KernelObject::Endpoint(_) | KernelObject::Notification(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the frame backing the page with the given index, if the object is a memory object.
+    pub fn memory_frame(&self, index: usize) -> Option<PhysicalAddress> {
+        match self {
+            KernelObject::MemoryRegion(region) => region.frame(index),
+            KernelObject::DeviceMemory(memory) => memory.frame(index),
+            _ => None,
+        }
+    }
+
+    /// Returns the page flags that the pages of the memory object are always mapped with.
+    pub fn memory_flags(&self) -> Option<PageFlags> {
+        match self {
+            KernelObject::MemoryRegion(_) => Some(PageFlags::USER_ACCESSIBLE),
+            KernelObject::DeviceMemory(_) => Some(PageFlags::USER_ACCESSIBLE | PageFlags::NO_CACHE),
+            _ => None,
+        }
+    }
+
+    /// Checks if both references refer to the same object.
+    pub fn is_same_object(&self, other: &KernelObject) -> bool {
+        match (self, other) {
+            (KernelObject::Thread(a), KernelObject::Thread(b)) => Arc::ptr_eq(a, b),
+            (KernelObject::AddressSpace(a), KernelObject::AddressSpace(b)) => Arc::ptr_eq(a, b),
+            (KernelObject::Endpoint(a), KernelObject::Endpoint(b)) => Arc::ptr_eq(a, b),
+            (KernelObject::Notification(a), KernelObject::Notification(b)) => Arc::ptr_eq(a, b),
+            (KernelObject::MemoryRegion(a), KernelObject::MemoryRegion(b)) => Arc::ptr_eq(a, b),
+            (KernelObject::Irq(a), KernelObject::Irq(b)) => Arc::ptr_eq(a, b),
+            (KernelObject::IoPorts(a), KernelObject::IoPorts(b)) => Arc::ptr_eq(a, b),
+            (KernelObject::DeviceMemory(a), KernelObject::DeviceMemory(b)) => Arc::ptr_eq(a, b),
+            (KernelObject::Process(a), KernelObject::Process(b)) => Arc::ptr_eq(a, b),
+            (KernelObject::Log, KernelObject::Log) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Debug for KernelObject {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.object_type())
+    }
+}
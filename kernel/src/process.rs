@@ -0,0 +1,348 @@
+//! Provides processes, which group threads with the address space and the capabilities they share.
+//!
+//! A process runs until it is killed or one of its threads exits it.
+//! Exiting stops all threads of the process, deletes its capabilities and releases its address space.
+//! The exit status is kept, so that other threads can wait for it.
+
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    mem, ptr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use lazy_static::lazy_static;
+
+use crate::{
+    capability::{CSpace, Rights},
+    cmdline,
+    elf::{self, ElfError},
+    initrd,
+    ipc::Notification,
+    memory::{AddressSpace, MemoryQuota, VirtualAddress},
+    object::KernelObject,
+    scheduler,
+    sync::Mutex,
+    thread::{Thread, ThreadState},
+};
+
+/// The exit status of processes that were killed because one of their threads caused a fault.
+pub const FAULT_EXIT_STATUS: usize = usize::max_value();
+
+/// The number of pages of memory regions that the threads of a process can create.
+pub const MEMORY_QUOTA_PAGES: usize = 4096;
+
+/// The path of the first user space program in the initial ramdisk, unless the command line names another one.
+pub const DEFAULT_INIT_PATH: &str = "init";
+
+/// The number of slots in the capability space of the first user space program.
+pub const INIT_CSPACE_SIZE: usize = 64;
+
+/// The slot of the capability to the kernel log in the capability space of the first user space program.
+pub const INIT_LOG_SLOT: usize = 0;
+
+/// The identifier of the next process that is created.
+static NEXT_PROCESS_ID: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static! {
+    /// All processes that were created and are still referenced somewhere.
+    static ref PROCESSES: Mutex<Vec<Weak<Process>>> = Mutex::new(Vec::new());
+}
+
+/// Uniquely identifies a process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ProcessId(usize);
+
+impl ProcessId {
+    /// Returns the identifier as an integer.
+    pub fn as_usize(self) -> usize {
+        self.0
+    }
+}
+
+/// The lifecycle states of a process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProcessState {
+    /// The process was created, but not started yet.
+    Created,
+    /// The process was started and has not exited yet.
+    Running,
+    /// The process exited with the given status.
+    Exited(usize),
+}
+
+/// The mutable parts of a process.
+struct ProcessInner {
+    /// The lifecycle state of the process.
+    state: ProcessState,
+    /// The threads of the process.
+    threads: Vec<Arc<Thread>>,
+    /// The threads waiting for the process to exit.
+    waiting: Vec<Arc<Thread>>,
+    /// The address space of the process, which is released when the process exits.
+    address_space: Option<Arc<Mutex<AddressSpace>>>,
+}
+
+/// A process, which owns an address space, a capability space and the threads running in them.
+pub struct Process {
+    /// The identifier of the process.
+    id: ProcessId,
+    /// The capabilities of the process.
+    cspace: Arc<CSpace>,
+    /// The quota of the memory regions created by the process.
+    memory_quota: Arc<MemoryQuota>,
+    /// The mutable parts of the process.
+    inner: Mutex<ProcessInner>,
+}
+
+/// Makes the thread that waited for the process runnable and passes it the exit status.
+///
+/// Nothing happens if the thread doesn't wait for the process anymore,
+/// for example because it belonged to the process and was suspended when the process was killed.
+fn wake(process: &Process, thread: Arc<Thread>, status: usize) {
+    let waiting = thread.transition_state(
+        |state| match state {
+            ThreadState::BlockedOnProcess(waited) => ptr::eq(&**waited, process),
+            _ => false,
+        },
+        ThreadState::Runnable,
+    );
+
+    if !waiting {
+        return;
+    }
+
+    thread.modify_context(|context| {
+        context.set_result(0);
+        context.set_argument(0, status);
+    });
+
+    scheduler::make_ready(thread);
+}
+
+impl Process {
+    /// Creates a process without threads, using the address space and a new capability space with `cspace_size` slots.
+    pub fn new(address_space: AddressSpace, cspace_size: usize) -> Arc<Process> {
+        let process = Arc::new(Process {
+            id: ProcessId(NEXT_PROCESS_ID.fetch_add(1, Ordering::Relaxed)),
+            cspace: CSpace::new(cspace_size),
+            memory_quota: Arc::new(MemoryQuota::new(MEMORY_QUOTA_PAGES)),
+            inner: Mutex::new(ProcessInner {
+                state: ProcessState::Created,
+                threads: Vec::new(),
+                waiting: Vec::new(),
+                address_space: Some(Arc::new(Mutex::new(address_space))),
+            }),
+        });
+
+        let mut processes = PROCESSES.lock();
+        processes.retain(|process| process.upgrade().is_some());
+        processes.push(Arc::downgrade(&process));
+
+        process
+    }
+
+    /// Loads the ELF executable contained in `data` into a new process and starts it.
+    ///
+    /// The arguments and environment variables are passed on the initial stack.
+    pub fn spawn(
+        data: &[u8],
+        arguments: &[&str],
+        environment: &[&str],
+        cspace_size: usize,
+    ) -> Result<Arc<Process>, ElfError> {
+        let program = elf::load(data, arguments, environment)?;
+        let process = Process::new(program.address_space, cspace_size);
+
+        Process::start(&process, program.entry_point, program.stack_pointer);
+
+        Ok(process)
+    }
+
+    /// Creates a new inactive thread in the process.
+    ///
+    /// Returns `None` if the process has already exited.
+    pub fn create_thread(process: &Arc<Process>) -> Option<Arc<Thread>> {
+        let mut inner = process.inner.lock();
+        let address_space = inner.address_space.clone()?;
+
+        let thread = Arc::new(Thread::new(process.cspace.clone(), address_space));
+        thread.set_process(Arc::downgrade(process));
+        inner.threads.push(thread.clone());
+
+        Some(thread)
+    }
+
+    /// Starts the process with a new thread at `entry` with the stack pointer `stack`.
+    ///
+    /// Returns the new thread or `None` if the process has already exited.
+    pub fn start(
+        process: &Arc<Process>,
+        entry: VirtualAddress,
+        stack: VirtualAddress,
+    ) -> Option<Arc<Thread>> {
+        let thread = Process::create_thread(process)?;
+
+        {
+            let mut inner = process.inner.lock();
+
+            if inner.state == ProcessState::Created {
+                inner.state = ProcessState::Running;
+            }
+        }
+
+        Thread::start(&thread, entry, stack);
+
+        Some(thread)
+    }
+
+    /// Ends the process with the given exit status.
+    ///
+    /// All threads of the process are stopped, its capabilities are deleted and its address space is released.
+    /// Threads waiting for the process are woken up with the exit status.
+    /// Killing a process that has already exited does nothing.
+    pub fn kill(process: &Arc<Process>, status: usize) {
+        let (threads, waiting, address_space) = {
+            let mut inner = process.inner.lock();
+
+            if let ProcessState::Exited(_) = inner.state {
+                return;
+            }
+
+            inner.state = ProcessState::Exited(status);
+
+            (
+                mem::replace(&mut inner.threads, Vec::new()),
+                mem::replace(&mut inner.waiting, Vec::new()),
+                inner.address_space.take(),
+            )
+        };
+
+        for thread in &threads {
+            Notification::unbind(thread);
+            Thread::suspend(thread);
+        }
+
+        // The capabilities may refer to the process or its threads, so they are deleted explicitly.
+        process.cspace.clear();
+
+        for thread in waiting {
+            wake(process, thread, status);
+        }
+
+        // The frames of the address space are freed here, unless other objects still refer to it.
+        drop(threads);
+        drop(address_space);
+
+        log::debug!("Process {:?} exited with status {}.", process.id, status);
+    }
+
+    /// Waits until the process exits and passes the exit status to the current thread `thread`.
+    pub fn wait(process: &Arc<Process>, thread: &Arc<Thread>) {
+        let mut inner = process.inner.lock();
+
+        if let ProcessState::Exited(status) = inner.state {
+            thread.modify_context(|context| context.set_argument(0, status));
+        } else {
+            thread.set_state(ThreadState::BlockedOnProcess(process.clone()));
+            inner.waiting.push(thread.clone());
+
+            scheduler::block_current();
+        }
+    }
+
+    /// Removes the thread from the threads waiting for the process to exit.
+    pub fn remove(&self, thread: &Arc<Thread>) {
+        self.inner
+            .lock()
+            .waiting
+            .retain(|waiting| !Arc::ptr_eq(waiting, thread));
+    }
+
+    /// Returns the identifier of the process.
+    pub fn id(&self) -> ProcessId {
+        self.id
+    }
+
+    /// Returns the lifecycle state of the process.
+    pub fn state(&self) -> ProcessState {
+        self.inner.lock().state
+    }
+
+    /// Returns the exit status of the process, if it has exited.
+    pub fn exit_status(&self) -> Option<usize> {
+        match self.state() {
+            ProcessState::Exited(status) => Some(status),
+            _ => None,
+        }
+    }
+
+    /// Returns the capability space of the process.
+    pub fn cspace(&self) -> &Arc<CSpace> {
+        &self.cspace
+    }
+
+    /// Returns the quota that the memory regions created by the process are charged to.
+    pub fn memory_quota(&self) -> &Arc<MemoryQuota> {
+        &self.memory_quota
+    }
+
+    /// Returns the address space of the process, unless it has exited.
+    pub fn address_space(&self) -> Option<Arc<Mutex<AddressSpace>>> {
+        self.inner.lock().address_space.clone()
+    }
+
+    /// Returns the threads of the process.
+    pub fn threads(&self) -> Vec<Arc<Thread>> {
+        self.inner.lock().threads.clone()
+    }
+}
+
+/// Starts the first user space program from the initial ramdisk.
+///
+/// The program is the one named by the `init=` option of the command line, or `DEFAULT_INIT_PATH`.
+/// It receives its path as its only argument and a capability to read the kernel log in `INIT_LOG_SLOT`.
+/// Returns `None` if the program could not be started, which is logged.
+pub fn spawn_init() -> Option<Arc<Process>> {
+    let path = cmdline::get()
+        .and_then(|configuration| configuration.init())
+        .unwrap_or(DEFAULT_INIT_PATH);
+
+    let file = match initrd::get().and_then(|initrd| initrd.find(path)) {
+        Some(file) if file.is_regular_file() => file,
+        _ => {
+            log::warn!("The initial ramdisk contains no program \"{}\".", path);
+            return None;
+        }
+    };
+
+    let process = match Process::spawn(file.data, &[path], &[], INIT_CSPACE_SIZE) {
+        Ok(process) => process,
+        Err(error) => {
+            log::error!("Could not load the program \"{}\": {}.", path, error);
+            return None;
+        }
+    };
+
+    CSpace::insert(
+        process.cspace(),
+        INIT_LOG_SLOT,
+        KernelObject::Log,
+        Rights::READ,
+    )
+    .expect("The capability space of the first program is not empty.");
+
+    log::info!("Started the first user space program \"{}\".", path);
+
+    Some(process)
+}
+
+/// Returns all processes that still exist, ordered by their identifiers.
+pub fn processes() -> Vec<Arc<Process>> {
+    PROCESSES
+        .lock()
+        .iter()
+        .filter_map(|process| process.upgrade())
+        .collect()
+}
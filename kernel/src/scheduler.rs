@@ -0,0 +1,195 @@
+//! Provides the scheduler of the kernel.
+//!
+//! Runnable threads are kept in a queue and run in order.
+//! Threads that communicate through IPC can hand the processor directly to each other,
+//! which skips the queue.
+//! When no thread is runnable, the processor sleeps until an interrupt makes one ready.
+
+use alloc::{collections::VecDeque, sync::Arc};
+use lazy_static::lazy_static;
+
+use crate::{
+    arch::{Arch, Architecture, KernelEntry, PageTable},
+    ipc::Endpoint,
+    sync::Mutex,
+    syscall,
+    thread::{self, Fault, Thread},
+    time,
+};
+
+lazy_static! {
+    /// The threads that are waiting to run.
+    static ref READY: Mutex<VecDeque<Arc<Thread>>> = Mutex::new(VecDeque::new());
+}
+
+/// The thread that runs next.
+///
+/// This lock must be taken before the lock of the ready queue.
+static CURRENT: Mutex<Option<Arc<Thread>>> = Mutex::new(None);
+
+/// Adds the thread to the end of the ready queue.
+pub fn make_ready(thread: Arc<Thread>) {
+    READY.lock().push_back(thread);
+}
+
+/// Makes the thread the one that runs next, bypassing the ready queue.
+///
+/// The thread that was running before must have been blocked or queued.
+pub fn switch_to(thread: Arc<Thread>) {
+    *CURRENT.lock() = Some(thread);
+}
+
+/// Stops running the current thread until it is made ready again.
+pub fn block_current() {
+    *CURRENT.lock() = None;
+}
+
+/// Returns the thread that runs next, if there is one.
+pub fn current() -> Option<Arc<Thread>> {
+    CURRENT.lock().clone()
+}
+
+/// Moves the current thread to the end of the ready queue.
+pub fn yield_current() {
+    let mut current = CURRENT.lock();
+    let mut ready = READY.lock();
+
+    if let Some(thread) = current.take() {
+        ready.push_back(thread);
+    }
+}
+
+/// Removes the thread from the ready queue and stops it if it is the current thread.
+pub fn remove(thread: &Arc<Thread>) {
+    let mut current = CURRENT.lock();
+
+    if current
+        .as_ref()
+        .map_or(false, |current| Arc::ptr_eq(current, thread))
+    {
+        *current = None;
+    }
+
+    READY.lock().retain(|ready| !Arc::ptr_eq(ready, thread));
+}
+
+/// Returns the thread that should run next.
+fn next_thread() -> Option<Arc<Thread>> {
+    let mut current = CURRENT.lock();
+
+    if current.is_none() {
+        *current = READY.lock().pop_front();
+    }
+
+    current.clone()
+}
+
+/// Sleeps until an interrupt occurs, which may make a thread ready.
+///
+/// While threads exist, an interrupt, a timer or another processor may still wake one of them.
+/// If `until_idle` is set, the processor only sleeps while a timer is pending.
+/// Returns `false` if it did not sleep, because nothing could make a thread ready anymore.
+fn idle(until_idle: bool) -> bool {
+    let interrupts_enabled = Arch::interrupts_enabled();
+
+    // Interrupts are disabled while checking, so that a thread that becomes ready in between still wakes the processor.
+    Arch::disable_interrupts();
+
+    let ready = !READY.lock().is_empty();
+    let sleep = !ready && (time::next_deadline().is_some() || !until_idle && thread::count() != 0);
+
+    if sleep {
+        Arch::wait_for_interrupt();
+    }
+
+    Arch::set_interrupts_enabled(interrupts_enabled);
+
+    ready || sleep
+}
+
+/// Stops the current thread `thread` because of a fault that could not be resolved.
+fn fault(thread: &Arc<Thread>, fault: Fault) {
+    log::warn!(
+        "Thread {:?} caused {:?} at {}.",
+        thread.id(),
+        fault,
+        thread.context().instruction_pointer()
+    );
+
+    Thread::stop_on_fault(thread, fault);
+}
+
+/// Runs user threads until no thread exists and no timeout is pending anymore.
+///
+/// While all threads are blocked, the processor sleeps until an interrupt wakes one of them.
+pub fn run() {
+    schedule(false);
+}
+
+/// Runs user threads until no thread is runnable and no timeout is pending anymore.
+///
+/// Unlike `run`, this returns while threads are still blocked, for example on an interrupt that never arrives.
+pub fn run_until_idle() {
+    schedule(true);
+}
+
+/// Runs user threads, sleeping while none is runnable.
+///
+/// If `until_idle` is set, this stops once only blocked threads without pending timeouts are left.
+fn schedule(until_idle: bool) {
+    loop {
+        let thread = match next_thread() {
+            Some(thread) => thread,
+            None if idle(until_idle) => continue,
+            None => break,
+        };
+
+        let mut context = thread.context();
+
+        thread.with_io_ports(Arch::set_io_ports);
+
+        // This is safe, because the thread keeps its address space alive while it runs.
+        let entry = unsafe {
+            thread.address_space().lock().activate();
+
+            Arch::enter_user(&mut context)
+        };
+
+        thread.set_context(context);
+
+        match entry {
+            KernelEntry::Syscall => syscall::dispatch(&thread),
+            // The interrupt was already handled, so the thread just continues.
+            KernelEntry::Interrupt => (),
+            KernelEntry::PageFault { address, access } => {
+                let result = thread.address_space().lock().handle_fault(address, access);
+
+                match result {
+                    Ok(None) => (),
+                    // The thread continues after the pager has supplied the page.
+                    Ok(Some(pager)) => {
+                        thread.set_fault(Fault::Page { address, access });
+                        Endpoint::send_fault(pager.endpoint(), &thread, pager.badge());
+                    }
+                    Err(error) => {
+                        log::warn!(
+                            "Thread {:?} could not resolve the page fault at {} ({:?}): {}.",
+                            thread.id(),
+                            address,
+                            access,
+                            error
+                        );
+
+                        fault(&thread, Fault::Page { address, access });
+                    }
+                }
+            }
+            KernelEntry::Exception { vector, error_code } => {
+                fault(&thread, Fault::Exception { vector, error_code })
+            }
+        }
+    }
+
+    Arch::set_io_ports(None);
+    PageTable::activate_kernel();
+}
@@ -0,0 +1,860 @@
+//! Implements the system calls of the kernel.
+//!
+//! The scheduler passes threads that requested a system call to `dispatch`,
+//! which reads the system call number and its arguments from the registers of the thread.
+//! Capabilities are always named by their slot in the capability space of the calling thread.
+//! Successful system calls return zero, failed ones return the code of the error.
+//! System calls that allocate kernel objects check the free memory on the kernel heap first,
+//! so that user space cannot exhaust it.
+
+use alloc::{string::ToString, sync::Arc};
+use core::{cmp::min, fmt, mem::size_of};
+use lazy_static::lazy_static;
+
+use crate::{
+    capability::{CSpace, Capability, CapabilityError, Rights},
+    ipc::{self, Endpoint, IpcError, Notification, Pager, IPC_BUFFER_LENGTH, NO_SLOT},
+    irq::IrqLine,
+    log_buffer,
+    memory::{
+        heap, AddressSpace, MemoryError, MemoryQuota, MemoryRegion, PageFlags, PhysicalAddress,
+        VirtualAddress, PAGE_SIZE,
+    },
+    object::KernelObject,
+    process::{self, Process},
+    scheduler,
+    thread::Thread,
+    time::{self, Duration, Instant},
+};
+
+/// The clocks that can be read with the `CLOCK_READ` system call.
+pub mod clock {
+    /// The monotonic clock, which starts at boot.
+    pub const MONOTONIC: usize = 0;
+
+    /// The wall clock, which fails to be read if the time is unknown.
+    pub const WALL: usize = 1;
+}
+
+/// The numbers of the system calls.
+pub mod number {
+    /// Lets other threads run.
+    pub const YIELD: usize = 0x01;
+
+    /// Sets the IPC buffer of the thread, which must be page aligned and writable by the thread.
+    ///
+    /// Arguments: address of the IPC buffer or zero to remove it.
+    pub const SET_IPC_BUFFER: usize = 0x02;
+
+    /// Ends the process of the thread, or only the thread if it belongs to no process.
+    ///
+    /// Arguments: exit status.
+    pub const EXIT: usize = 0x03;
+
+    /// Lets the thread sleep.
+    ///
+    /// Arguments: duration in nanoseconds.
+    pub const SLEEP: usize = 0x04;
+
+    /// Reads a clock.
+    ///
+    /// Arguments: clock (0 for the monotonic clock, 1 for the wall clock).
+    /// Returns the nanoseconds since the clock was started or since the first of January 1970 in the first argument.
+    pub const CLOCK_READ: usize = 0x05;
+
+    /// Reads a record from the kernel log.
+    ///
+    /// The oldest record that is still kept and has at least the given sequence number is read.
+    /// Its text is copied to the buffer and truncated if the buffer is too small.
+    ///
+    /// The capability to the log must grant reading.
+    ///
+    /// Arguments: log slot, sequence number, buffer address, buffer length.
+    /// Returns the sequence number of the record in the first argument and the length of its text in the second one.
+    /// If there is no such record, the sequence number of the next record and a length of zero are returned.
+    pub const LOG_READ: usize = 0x06;
+
+    /// Copies a capability.
+    ///
+    /// Arguments: source slot, destination slot, rights mask.
+    pub const CAPABILITY_COPY: usize = 0x10;
+
+    /// Copies a capability and gives the copy a badge.
+    ///
+    /// Arguments: source slot, destination slot, rights mask, badge.
+    pub const CAPABILITY_MINT: usize = 0x11;
+
+    /// Moves a capability to another slot.
+    ///
+    /// Arguments: source slot, destination slot.
+    pub const CAPABILITY_MOVE: usize = 0x12;
+
+    /// Deletes a capability.
+    ///
+    /// Arguments: slot.
+    pub const CAPABILITY_DELETE: usize = 0x13;
+
+    /// Deletes all capabilities derived from a capability.
+    ///
+    /// Arguments: slot.
+    pub const CAPABILITY_REVOKE: usize = 0x14;
+
+    /// Sends a message through an endpoint, waiting for a receiver.
+    ///
+    /// Arguments: endpoint slot, message info, message words 0 to 3.
+    pub const SEND: usize = 0x20;
+
+    /// Receives a message from an endpoint, waiting for a sender.
+    ///
+    /// Arguments: endpoint slot.
+    /// Returns the badge, the message info and the message words 0 to 3 in the arguments.
+    pub const RECEIVE: usize = 0x21;
+
+    /// Sends a message through an endpoint and waits for the reply.
+    ///
+    /// Arguments: endpoint slot, message info, message words 0 to 3.
+    /// Returns the reply like `RECEIVE`.
+    pub const CALL: usize = 0x22;
+
+    /// Replies to the thread that called this thread.
+    ///
+    /// Arguments: unused, message info, message words 0 to 3.
+    pub const REPLY: usize = 0x23;
+
+    /// Replies to the thread that called this thread, if any, and receives the next message.
+    ///
+    /// Arguments: endpoint slot, message info, message words 0 to 3.
+    /// Returns the next message like `RECEIVE`.
+    pub const REPLY_RECEIVE: usize = 0x24;
+
+    /// Signals a notification with the badge of the capability.
+    ///
+    /// Arguments: notification slot.
+    pub const SIGNAL: usize = 0x28;
+
+    /// Waits for signals on a notification.
+    ///
+    /// Arguments: notification slot.
+    /// Returns the signal bits in the first argument.
+    pub const WAIT: usize = 0x29;
+
+    /// Returns the signals of a notification without waiting.
+    ///
+    /// Arguments: notification slot.
+    /// Returns the signal bits in the first argument, which are zero if there were none.
+    pub const POLL: usize = 0x2a;
+
+    /// Binds a notification to the thread, so that its signals are received while waiting for messages.
+    ///
+    /// Arguments: notification slot.
+    pub const BIND_NOTIFICATION: usize = 0x2b;
+
+    /// Removes the binding of the notification bound to the thread.
+    pub const UNBIND_NOTIFICATION: usize = 0x2c;
+
+    /// Allows the thread to access the I/O ports of the range directly.
+    ///
+    /// Arguments: I/O port range slot.
+    pub const IO_PORT_ENABLE: usize = 0x30;
+
+    /// Maps device memory uncached into the address space of the thread, which must be page aligned.
+    ///
+    /// The pages are writable if the capability grants writing.
+    /// `MEMORY_MAP` replaces this, it is only kept for existing programs.
+    ///
+    /// Arguments: device memory slot, start address.
+    pub const MAP_DEVICE_MEMORY: usize = 0x31;
+
+    /// Sets the notification that is signalled with the badge of its capability when the interrupt line fires.
+    ///
+    /// Arguments: interrupt line slot, notification slot or `NO_SLOT` to stop receiving interrupts.
+    pub const IRQ_SET_NOTIFICATION: usize = 0x32;
+
+    /// Acknowledges the last interrupt of the interrupt line, so that the next one can be delivered.
+    ///
+    /// Arguments: interrupt line slot.
+    pub const IRQ_ACKNOWLEDGE: usize = 0x33;
+
+    /// Ends a process with the given exit status.
+    ///
+    /// Arguments: process slot, exit status.
+    pub const PROCESS_KILL: usize = 0x40;
+
+    /// Waits until a process exits.
+    ///
+    /// Arguments: process slot.
+    /// Returns the exit status in the first argument.
+    pub const PROCESS_WAIT: usize = 0x41;
+
+    /// Creates a copy of the process of the thread and starts a thread in it.
+    ///
+    /// The copy shares the pages of the address space copy-on-write and starts with an empty capability space
+    /// of the same size.
+    ///
+    /// Arguments: destination slot, entry point, stack pointer.
+    pub const PROCESS_DUPLICATE: usize = 0x42;
+
+    /// Creates a memory region of zeroed pages.
+    ///
+    /// A region has at most `MAXIMUM_REGION_PAGES` pages and its pages are charged to the quota of the process.
+    ///
+    /// Arguments: number of pages, destination slot.
+    pub const MEMORY_CREATE: usize = 0x50;
+
+    /// Maps a memory region or device memory into the address space of the thread.
+    ///
+    /// The pages are always readable, the flags can make them writable (1) or executable (2).
+    /// Device memory is mapped uncached.
+    ///
+    /// Arguments: memory slot, page aligned start address, flags.
+    pub const MEMORY_MAP: usize = 0x51;
+
+    /// Unmaps pages from the address space of the thread.
+    ///
+    /// Arguments: page aligned start address, number of pages.
+    pub const MEMORY_UNMAP: usize = 0x52;
+
+    /// Reserves pages in the address space of the thread, which are mapped with zeroed frames on their first access.
+    ///
+    /// The pages are always readable, the flags can make them writable (1) or executable (2).
+    ///
+    /// Arguments: page aligned start address, number of pages, flags.
+    pub const MEMORY_RESERVE: usize = 0x53;
+
+    /// Reserves pages in the address space of the thread, whose frames are supplied by a pager.
+    ///
+    /// Accesses to pages that are not mapped yet are sent as messages through the endpoint,
+    /// using the badge of its capability.
+    /// The flags are the same as for `MEMORY_RESERVE`.
+    ///
+    /// Arguments: page aligned start address, number of pages, flags, endpoint slot.
+    pub const MEMORY_RESERVE_PAGED: usize = 0x54;
+}
+
+/// The number of arguments a system call can take.
+pub const ARGUMENT_COUNT: usize = 6;
+
+/// The maximum number of pages of a memory region created with `MEMORY_CREATE`.
+pub const MAXIMUM_REGION_PAGES: usize = 1024;
+
+/// The number of heap bytes that the bookkeeping of a kernel object takes at most, besides the object itself.
+const OBJECT_OVERHEAD: usize = 256;
+
+/// The number of heap bytes that the bookkeeping of a page mapped into an address space takes at most.
+const PAGE_OVERHEAD: usize = 96;
+
+lazy_static! {
+    /// The quota of the memory regions created by threads that belong to no process.
+    static ref UNOWNED_MEMORY_QUOTA: Arc<MemoryQuota> =
+        Arc::new(MemoryQuota::new(process::MEMORY_QUOTA_PAGES));
+}
+
+/// The errors a system call can return.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyscallError {
+    /// There is no system call with the given number.
+    UnknownSyscall(usize),
+    /// An argument has an invalid value.
+    InvalidArgument,
+    /// Using a capability failed.
+    Capability(CapabilityError),
+    /// Exchanging a message failed.
+    Ipc(IpcError),
+    /// Modifying the address space failed.
+    Memory(MemoryError),
+}
+
+impl SyscallError {
+    /// Returns the error code that is passed to user space.
+    ///
+    /// Successful system calls return zero.
+    pub fn code(&self) -> usize {
+        match self {
+            SyscallError::UnknownSyscall(_) => 1,
+            SyscallError::InvalidArgument => 2,
+            SyscallError::Capability(CapabilityError::InvalidSlot(_)) => 3,
+            SyscallError::Capability(CapabilityError::EmptySlot(_)) => 4,
+            SyscallError::Capability(CapabilityError::SlotOccupied(_)) => 5,
+            SyscallError::Capability(CapabilityError::NotBadgeable) => 6,
+            SyscallError::Capability(CapabilityError::AlreadyBadged) => 7,
+            SyscallError::Capability(CapabilityError::InvalidBadge) => 8,
+            SyscallError::Capability(CapabilityError::InvalidType) => 9,
+            SyscallError::Capability(CapabilityError::InsufficientRights) => 10,
+            SyscallError::Ipc(IpcError::InvalidMessage) => 11,
+            SyscallError::Ipc(IpcError::NoIpcBuffer) => 12,
+            SyscallError::Ipc(IpcError::NoCaller) => 13,
+            SyscallError::Ipc(IpcError::AlreadyBound) => 14,
+            SyscallError::Memory(MemoryError::OutOfMemory) => 15,
+            SyscallError::Memory(MemoryError::AlreadyMapped(_)) => 16,
+            SyscallError::Memory(MemoryError::NotMapped(_)) => 17,
+            SyscallError::Memory(MemoryError::OutsideUserSpace(_)) => 18,
+            SyscallError::Memory(MemoryError::AccessViolation(_)) => 19,
+            SyscallError::Memory(MemoryError::GuardPage(_)) => 20,
+            SyscallError::Ipc(IpcError::InvalidFrame) => 21,
+            SyscallError::Memory(MemoryError::QuotaExceeded) => 22,
+            SyscallError::Memory(MemoryError::HeapExhausted) => 23,
+        }
+    }
+}
+
+impl fmt::Display for SyscallError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SyscallError::UnknownSyscall(number) => {
+                write!(f, "there is no system call with number {:#x}", number)
+            }
+            SyscallError::InvalidArgument => write!(f, "an argument is invalid"),
+            SyscallError::Capability(error) => write!(f, "{}", error),
+            SyscallError::Ipc(error) => write!(f, "{}", error),
+            SyscallError::Memory(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl From<CapabilityError> for SyscallError {
+    fn from(error: CapabilityError) -> SyscallError {
+        SyscallError::Capability(error)
+    }
+}
+
+impl From<IpcError> for SyscallError {
+    fn from(error: IpcError) -> SyscallError {
+        SyscallError::Ipc(error)
+    }
+}
+
+impl From<MemoryError> for SyscallError {
+    fn from(error: MemoryError) -> SyscallError {
+        SyscallError::Memory(error)
+    }
+}
+
+/// Converts the argument to a rights mask.
+fn rights(argument: usize) -> Result<Rights, SyscallError> {
+    if argument > usize::from(u8::max_value()) {
+        return Err(SyscallError::InvalidArgument);
+    }
+
+    Rights::from_bits(argument as u8).ok_or(SyscallError::InvalidArgument)
+}
+
+/// Looks up the endpoint in the slot, which must grant `rights`.
+///
+/// Returns the endpoint and the badge of the capability.
+fn endpoint(
+    thread: &Thread,
+    slot: usize,
+    rights: Rights,
+) -> Result<(Arc<Endpoint>, u64, Rights), SyscallError> {
+    let capability = thread.cspace().lookup(slot)?;
+
+    if !capability.rights().contains(rights) {
+        return Err(CapabilityError::InsufficientRights.into());
+    }
+
+    match capability.object() {
+        KernelObject::Endpoint(endpoint) => {
+            Ok((endpoint.clone(), capability.badge(), capability.rights()))
+        }
+        _ => Err(CapabilityError::InvalidType.into()),
+    }
+}
+
+/// Looks up the notification in the slot, which must grant `rights`.
+///
+/// Returns the notification and the badge of the capability.
+fn notification(
+    thread: &Thread,
+    slot: usize,
+    rights: Rights,
+) -> Result<(Arc<Notification>, u64), SyscallError> {
+    let capability = thread.cspace().lookup(slot)?;
+
+    if !capability.rights().contains(rights) {
+        return Err(CapabilityError::InsufficientRights.into());
+    }
+
+    match capability.object() {
+        KernelObject::Notification(notification) => Ok((notification.clone(), capability.badge())),
+        _ => Err(CapabilityError::InvalidType.into()),
+    }
+}
+
+/// Looks up the interrupt line in the slot, which must grant `rights`.
+fn irq_line(thread: &Thread, slot: usize, rights: Rights) -> Result<Arc<IrqLine>, SyscallError> {
+    let capability = thread.cspace().lookup(slot)?;
+
+    if !capability.rights().contains(rights) {
+        return Err(CapabilityError::InsufficientRights.into());
+    }
+
+    match capability.object() {
+        KernelObject::Irq(line) => Ok(line.clone()),
+        _ => Err(CapabilityError::InvalidType.into()),
+    }
+}
+
+/// Looks up the process in the slot, which must grant `rights`.
+fn process(thread: &Thread, slot: usize, rights: Rights) -> Result<Arc<Process>, SyscallError> {
+    let capability = thread.cspace().lookup(slot)?;
+
+    if !capability.rights().contains(rights) {
+        return Err(CapabilityError::InsufficientRights.into());
+    }
+
+    match capability.object() {
+        KernelObject::Process(process) => Ok(process.clone()),
+        _ => Err(CapabilityError::InvalidType.into()),
+    }
+}
+
+/// Copies the address space of the thread into a new process and starts a thread in it at `entry` with `stack`.
+///
+/// The capability to the new process is inserted into the slot before the process is started.
+fn duplicate_process(
+    thread: &Thread,
+    slot: usize,
+    entry: usize,
+    stack: usize,
+) -> Result<(), SyscallError> {
+    let cspace_size = thread.cspace().size();
+    let address_space = {
+        let mut address_space = thread.address_space().lock();
+
+        heap::check_available(
+            size_of::<Process>()
+                + size_of::<Thread>()
+                + cspace_size * size_of::<Option<Capability>>()
+                + address_space.mapped_page_count() * PAGE_OVERHEAD
+                + 3 * OBJECT_OVERHEAD,
+        )?;
+
+        address_space.duplicate()?
+    };
+    let copy = Process::new(address_space, cspace_size);
+
+    CSpace::insert(
+        thread.cspace(),
+        slot,
+        KernelObject::Process(copy.clone()),
+        Rights::all(),
+    )?;
+    Process::start(
+        &copy,
+        VirtualAddress::new(entry),
+        VirtualAddress::new(stack),
+    );
+
+    Ok(())
+}
+
+/// Checks that the slot holds a capability to the kernel log, which must grant `rights`.
+fn check_log(thread: &Thread, slot: usize, rights: Rights) -> Result<(), SyscallError> {
+    let capability = thread.cspace().lookup(slot)?;
+
+    if !capability.rights().contains(rights) {
+        return Err(CapabilityError::InsufficientRights.into());
+    }
+
+    match capability.object() {
+        KernelObject::Log => Ok(()),
+        _ => Err(CapabilityError::InvalidType.into()),
+    }
+}
+
+/// Maps the memory object in the slot into the address space of the thread, starting at `address`.
+///
+/// The requested flags must be allowed by the rights of the capability.
+/// If mapping any page fails, the pages mapped so far are unmapped again.
+fn map_memory(
+    thread: &Thread,
+    slot: usize,
+    address: usize,
+    flags: usize,
+) -> Result<(), SyscallError> {
+    if flags > usize::from(u8::max_value()) {
+        return Err(SyscallError::InvalidArgument);
+    }
+
+    let requested = PageFlags::from_bits(flags as u8)
+        .filter(|requested| (PageFlags::WRITABLE | PageFlags::EXECUTABLE).contains(*requested))
+        .ok_or(SyscallError::InvalidArgument)?;
+    let capability = thread.cspace().lookup(slot)?;
+
+    if !capability.rights().contains(Rights::to_map(requested)) {
+        return Err(CapabilityError::InsufficientRights.into());
+    }
+
+    let page_count = match capability.object() {
+        KernelObject::MemoryRegion(region) => region.page_count(),
+        KernelObject::DeviceMemory(memory) => memory.page_count(),
+        _ => return Err(CapabilityError::InvalidType.into()),
+    };
+    let flags = capability
+        .object()
+        .memory_flags()
+        .expect("A memory object has no page flags.");
+
+    let start = VirtualAddress::new(address);
+
+    if !start.is_page_aligned() {
+        return Err(SyscallError::InvalidArgument);
+    }
+
+    match page_count.checked_mul(PAGE_SIZE) {
+        Some(size) if AddressSpace::is_user_range(start, size) => (),
+        _ => return Err(MemoryError::OutsideUserSpace(start).into()),
+    }
+
+    heap::check_available(page_count * PAGE_OVERHEAD)?;
+
+    let mut address_space = thread.address_space().lock();
+
+    for index in 0..page_count {
+        let frame = capability
+            .object()
+            .memory_frame(index)
+            .expect("The page index is out of range.");
+        let result = address_space.map_shared(
+            start + index * PAGE_SIZE,
+            frame,
+            flags | requested,
+            capability.object().clone(),
+        );
+
+        if let Err(error) = result {
+            for mapped in 0..index {
+                address_space
+                    .unmap(start + mapped * PAGE_SIZE)
+                    .expect("A page that was just mapped is not mapped.");
+            }
+
+            return Err(error.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Unmaps the `page_count` pages starting at `address` from the address space of the thread.
+///
+/// Nothing is unmapped if any of the pages is not mapped.
+fn unmap_memory(thread: &Thread, address: usize, page_count: usize) -> Result<(), SyscallError> {
+    let start = VirtualAddress::new(address);
+
+    if !start.is_page_aligned() {
+        return Err(SyscallError::InvalidArgument);
+    }
+
+    match page_count.checked_mul(PAGE_SIZE) {
+        Some(size) if AddressSpace::is_user_range(start, size) => (),
+        _ => return Err(MemoryError::OutsideUserSpace(start).into()),
+    }
+
+    let mut address_space = thread.address_space().lock();
+    let pages = (0..page_count).map(|index| start + index * PAGE_SIZE);
+
+    if let Some(page) = pages
+        .clone()
+        .find(|&page| address_space.flags(page).is_none())
+    {
+        return Err(MemoryError::NotMapped(page).into());
+    }
+
+    for page in pages {
+        address_space
+            .unmap(page)
+            .expect("A mapped page could not be unmapped.");
+    }
+
+    Ok(())
+}
+
+/// Reserves the `page_count` pages starting at `address` in the address space of the thread.
+///
+/// If a pager is given, it supplies the frames of the pages.
+fn reserve_memory(
+    thread: &Thread,
+    address: usize,
+    page_count: usize,
+    flags: usize,
+    pager: Option<Pager>,
+) -> Result<(), SyscallError> {
+    let start = VirtualAddress::new(address);
+
+    if !start.is_page_aligned() || page_count == 0 || flags > usize::from(u8::max_value()) {
+        return Err(SyscallError::InvalidArgument);
+    }
+
+    let requested = PageFlags::from_bits(flags as u8)
+        .filter(|requested| (PageFlags::WRITABLE | PageFlags::EXECUTABLE).contains(*requested))
+        .ok_or(SyscallError::InvalidArgument)?;
+    let size = page_count
+        .checked_mul(PAGE_SIZE)
+        .ok_or(MemoryError::OutsideUserSpace(start))?;
+
+    heap::check_available(OBJECT_OVERHEAD)?;
+
+    let flags = PageFlags::USER_ACCESSIBLE | requested;
+    let mut address_space = thread.address_space().lock();
+
+    match pager {
+        Some(pager) => address_space.reserve_paged(start, size, flags, pager)?,
+        None => address_space.reserve(start, size, flags)?,
+    }
+
+    Ok(())
+}
+
+/// Handles the system call requested by the thread, which is the current thread.
+///
+/// The result is stored in the registers of the thread.
+pub fn dispatch(thread: &Arc<Thread>) {
+    let context = thread.context();
+    let mut arguments = [0; ARGUMENT_COUNT];
+
+    for (index, argument) in arguments.iter_mut().enumerate() {
+        *argument = context.argument(index);
+    }
+
+    let result = match handle(thread, context.syscall_number(), arguments) {
+        Ok(value) => value,
+        Err(error) => {
+            log::debug!(
+                "A system call of thread {:?} failed: {}.",
+                thread.id(),
+                error
+            );
+
+            error.code()
+        }
+    };
+
+    // Receiving a message changes the registers, so only the result is set here.
+    thread.modify_context(|context| context.set_result(result));
+}
+
+/// Handles the system call with the given number for the thread, which is the current thread.
+///
+/// Returns the result of the system call.
+pub fn handle(
+    thread: &Arc<Thread>,
+    number: usize,
+    arguments: [usize; ARGUMENT_COUNT],
+) -> Result<usize, SyscallError> {
+    let cspace = thread.cspace();
+
+    match number {
+        number::YIELD => scheduler::yield_current(),
+        number::SET_IPC_BUFFER => {
+            let address = VirtualAddress::new(arguments[0]);
+
+            if address.as_usize() == 0 {
+                thread.set_ipc_buffer(None);
+            } else if address.is_page_aligned() && AddressSpace::is_user_range(address, 1) {
+                thread
+                    .address_space()
+                    .lock()
+                    .check_user_write(address, IPC_BUFFER_LENGTH * size_of::<usize>())?;
+                thread.set_ipc_buffer(Some(address));
+            } else {
+                return Err(SyscallError::InvalidArgument);
+            }
+        }
+        number::EXIT => match thread.process() {
+            Some(process) => Process::kill(&process, arguments[0]),
+            None => Thread::suspend(thread),
+        },
+        number::SLEEP => {
+            let duration = Duration::from_nanos(arguments[0] as u64);
+
+            Thread::sleep(thread, Instant::now() + duration);
+        }
+        number::CLOCK_READ => {
+            let nanoseconds = match arguments[0] {
+                clock::MONOTONIC => Instant::now().as_nanoseconds(),
+                clock::WALL => {
+                    let time = time::unix_time().ok_or(SyscallError::InvalidArgument)?;
+
+                    time.as_nanos() as u64
+                }
+                _ => return Err(SyscallError::InvalidArgument),
+            };
+
+            thread.modify_context(|context| context.set_argument(0, nanoseconds as usize));
+        }
+        number::LOG_READ => {
+            check_log(thread, arguments[0], Rights::READ)?;
+
+            let (sequence, length) = match log_buffer::records_since(arguments[1]).next() {
+                Some(record) => {
+                    let text = record.to_string();
+                    let length = min(text.len(), arguments[3]);
+
+                    thread.address_space().lock().write_user(
+                        VirtualAddress::new(arguments[2]),
+                        &text.as_bytes()[..length],
+                    )?;
+
+                    (record.sequence(), length)
+                }
+                None => (log_buffer::next_sequence(), 0),
+            };
+
+            thread.modify_context(|context| {
+                context.set_argument(0, sequence);
+                context.set_argument(1, length);
+            });
+        }
+        number::CAPABILITY_COPY => {
+            cspace.copy(arguments[0], cspace, arguments[1], rights(arguments[2])?)?
+        }
+        number::CAPABILITY_MINT => cspace.mint(
+            arguments[0],
+            cspace,
+            arguments[1],
+            rights(arguments[2])?,
+            arguments[3] as u64,
+        )?,
+        number::CAPABILITY_MOVE => cspace.move_capability(arguments[0], cspace, arguments[1])?,
+        number::CAPABILITY_DELETE => cspace.delete(arguments[0])?,
+        number::CAPABILITY_REVOKE => cspace.revoke(arguments[0])?,
+        number::SEND | number::CALL => {
+            let (endpoint, badge, rights) = endpoint(thread, arguments[0], Rights::WRITE)?;
+            ipc::check_message(thread, arguments[1])?;
+
+            Endpoint::send(
+                &endpoint,
+                thread,
+                badge,
+                rights.contains(Rights::GRANT),
+                number == number::CALL,
+            );
+        }
+        number::RECEIVE => {
+            let (endpoint, _, _) = endpoint(thread, arguments[0], Rights::READ)?;
+
+            Endpoint::receive(&endpoint, thread);
+        }
+        number::REPLY => {
+            ipc::check_message(thread, arguments[1])?;
+            ipc::reply(thread)?;
+        }
+        number::REPLY_RECEIVE => {
+            let (endpoint, _, _) = endpoint(thread, arguments[0], Rights::READ)?;
+            ipc::check_message(thread, arguments[1])?;
+
+            ipc::reply_receive(&endpoint, thread);
+        }
+        number::SIGNAL => {
+            let (notification, badge) = notification(thread, arguments[0], Rights::WRITE)?;
+
+            notification.signal(badge);
+        }
+        number::WAIT => {
+            let (notification, _) = notification(thread, arguments[0], Rights::READ)?;
+
+            Notification::wait(&notification, thread);
+        }
+        number::POLL => {
+            let (notification, _) = notification(thread, arguments[0], Rights::READ)?;
+            let signals = notification.poll();
+
+            thread.modify_context(|context| context.set_argument(0, signals as usize));
+        }
+        number::BIND_NOTIFICATION => {
+            let (notification, _) = notification(thread, arguments[0], Rights::READ)?;
+
+            Notification::bind(&notification, thread)?;
+        }
+        number::UNBIND_NOTIFICATION => Notification::unbind(thread),
+        number::IO_PORT_ENABLE => {
+            let capability = cspace.lookup(arguments[0])?;
+
+            if !capability.rights().contains(Rights::READ | Rights::WRITE) {
+                return Err(CapabilityError::InsufficientRights.into());
+            }
+
+            match capability.object() {
+                KernelObject::IoPorts(range) => thread.allow_io_ports(range),
+                _ => return Err(CapabilityError::InvalidType.into()),
+            }
+        }
+        number::MAP_DEVICE_MEMORY => {
+            let capability = cspace.lookup(arguments[0])?;
+            let flags = match capability.object() {
+                KernelObject::DeviceMemory(_) if capability.rights().contains(Rights::WRITE) => {
+                    PageFlags::WRITABLE
+                }
+                KernelObject::DeviceMemory(_) => PageFlags::empty(),
+                _ => return Err(CapabilityError::InvalidType.into()),
+            };
+
+            map_memory(thread, arguments[0], arguments[1], flags.bits() as usize)?
+        }
+        number::IRQ_SET_NOTIFICATION => {
+            let line = irq_line(thread, arguments[0], Rights::WRITE)?;
+
+            if arguments[1] == NO_SLOT {
+                line.set_notification(None);
+            } else {
+                let (notification, badge) = notification(thread, arguments[1], Rights::WRITE)?;
+
+                line.set_notification(Some((notification, badge)));
+            }
+        }
+        number::IRQ_ACKNOWLEDGE => irq_line(thread, arguments[0], Rights::WRITE)?.acknowledge(),
+        number::PROCESS_KILL => {
+            Process::kill(&process(thread, arguments[0], Rights::WRITE)?, arguments[1])
+        }
+        number::PROCESS_WAIT => {
+            Process::wait(&process(thread, arguments[0], Rights::READ)?, thread)
+        }
+        number::PROCESS_DUPLICATE => {
+            duplicate_process(thread, arguments[0], arguments[1], arguments[2])?
+        }
+        number::MEMORY_CREATE => {
+            if arguments[0] == 0 || arguments[0] > MAXIMUM_REGION_PAGES {
+                return Err(SyscallError::InvalidArgument);
+            }
+
+            heap::check_available(
+                size_of::<MemoryRegion>()
+                    + arguments[0] * size_of::<PhysicalAddress>()
+                    + OBJECT_OVERHEAD,
+            )?;
+
+            let quota = match thread.process() {
+                Some(process) => process.memory_quota().clone(),
+                None => UNOWNED_MEMORY_QUOTA.clone(),
+            };
+            let region = MemoryRegion::with_quota(arguments[0], quota)?;
+
+            CSpace::insert(
+                cspace,
+                arguments[1],
+                KernelObject::MemoryRegion(Arc::new(region)),
+                Rights::all(),
+            )?;
+        }
+        number::MEMORY_MAP => map_memory(thread, arguments[0], arguments[1], arguments[2])?,
+        number::MEMORY_UNMAP => unmap_memory(thread, arguments[0], arguments[1])?,
+        number::MEMORY_RESERVE => {
+            reserve_memory(thread, arguments[0], arguments[1], arguments[2], None)?
+        }
+        number::MEMORY_RESERVE_PAGED => {
+            let (endpoint, badge, _) = endpoint(thread, arguments[3], Rights::WRITE)?;
+
+            reserve_memory(
+                thread,
+                arguments[0],
+                arguments[1],
+                arguments[2],
+                Some(Pager::new(endpoint, badge)),
+            )?
+        }
+        _ => return Err(SyscallError::UnknownSyscall(number)),
+    }
+
+    Ok(0)
+}
@@ -0,0 +1,432 @@
+//! Provides the threads of the kernel.
+
+use alloc::{
+    boxed::Box,
+    sync::{Arc, Weak},
+};
+use core::{
+    mem,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{
+    arch::UserContext,
+    capability::CSpace,
+    io_port::{IoPortRange, IoPortSet},
+    ipc::{Endpoint, Notification},
+    memory::{Access, AddressSpace, VirtualAddress},
+    process::{self, Process},
+    scheduler,
+    sync::Mutex,
+    time::{self, HrTimerId, Instant},
+};
+
+/// The identifier of the next thread that is created.
+static NEXT_THREAD_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of threads that exist.
+static THREAD_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Uniquely identifies a thread.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ThreadId(usize);
+
+impl ThreadId {
+    /// Returns the identifier as an integer.
+    pub fn as_usize(self) -> usize {
+        self.0
+    }
+}
+
+/// The scheduling states of a thread.
+#[derive(Clone)]
+pub enum ThreadState {
+    /// The thread is running or waiting to run.
+    Runnable,
+    /// The thread waits for a receiver on the endpoint.
+    BlockedOnSend {
+        /// The endpoint the message is sent to.
+        endpoint: Arc<Endpoint>,
+        /// The badge of the capability used for sending.
+        badge: u64,
+        /// Whether capabilities can be transferred with the message.
+        grant: bool,
+        /// Whether the thread waits for a reply after the message was received.
+        call: bool,
+    },
+    /// The thread waits for a message on the endpoint.
+    BlockedOnReceive(Arc<Endpoint>),
+    /// The thread caused a page fault and waits for a pager to receive it from the endpoint.
+    BlockedOnFault {
+        /// The endpoint of the pager.
+        endpoint: Arc<Endpoint>,
+        /// The badge the fault is sent with.
+        badge: u64,
+    },
+    /// The thread waits for a signal on the notification.
+    BlockedOnNotification(Arc<Notification>),
+    /// The thread waits for the process to exit.
+    BlockedOnProcess(Arc<Process>),
+    /// The thread called another thread and waits for its reply.
+    BlockedOnReply,
+    /// A pager received the page fault of the thread and the thread waits for its reply.
+    BlockedOnPager,
+    /// The thread sleeps until the high resolution timer expires.
+    Sleeping(HrTimerId),
+    /// The thread was never started.
+    Inactive,
+    /// The thread caused an exception and was stopped.
+    Faulted,
+}
+
+impl ThreadState {
+    /// Checks if the thread is waiting for a message on an endpoint.
+    pub fn is_blocked_on_receive(&self) -> bool {
+        match self {
+            ThreadState::BlockedOnReceive(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns a short description of the state.
+    pub fn description(&self) -> &'static str {
+        match self {
+            ThreadState::Runnable => "runnable",
+            ThreadState::BlockedOnSend { call: true, .. } => "calling",
+            ThreadState::BlockedOnSend { .. } => "sending",
+            ThreadState::BlockedOnReceive(_) => "receiving",
+            ThreadState::BlockedOnFault { .. } => "waiting for a pager",
+            ThreadState::BlockedOnNotification(_) => "waiting for a notification",
+            ThreadState::BlockedOnProcess(_) => "waiting for a process",
+            ThreadState::BlockedOnReply => "waiting for a reply",
+            ThreadState::BlockedOnPager => "waiting for a page fault to be handled",
+            ThreadState::Sleeping(_) => "sleeping",
+            ThreadState::Inactive => "inactive",
+            ThreadState::Faulted => "faulted",
+        }
+    }
+}
+
+/// The faults that stop a thread.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// The thread accessed memory that it cannot access.
+    Page {
+        /// The address that was accessed.
+        address: VirtualAddress,
+        /// The kind of the access.
+        access: Access,
+    },
+    /// The thread caused another exception.
+    Exception {
+        /// The architecture specific number of the exception.
+        vector: usize,
+        /// The architecture specific error code of the exception.
+        error_code: usize,
+    },
+}
+
+/// The mutable parts of a thread.
+struct ThreadInner {
+    /// The user mode registers of the thread.
+    context: UserContext,
+    /// The scheduling state of the thread.
+    state: ThreadState,
+    /// The address of the IPC buffer of the thread.
+    ipc_buffer: Option<VirtualAddress>,
+    /// The thread that called this thread and waits for a reply.
+    caller: Option<Arc<Thread>>,
+    /// The thread this thread last called, which holds it as its caller until it replies.
+    callee: Option<Weak<Thread>>,
+    /// The notification whose signals the thread receives while waiting for messages.
+    bound_notification: Option<Arc<Notification>>,
+    /// The I/O ports the thread can access directly.
+    io_ports: Option<Box<IoPortSet>>,
+    /// The process the thread belongs to.
+    process: Option<Weak<Process>>,
+    /// The last fault caused by the thread.
+    fault: Option<Fault>,
+}
+
+/// A thread of execution.
+///
+/// The thread control block stores everything the kernel needs to know about a thread.
+pub struct Thread {
+    /// The identifier of the thread.
+    id: ThreadId,
+    /// The capabilities the thread can use.
+    cspace: Arc<CSpace>,
+    /// The address space the thread runs in.
+    address_space: Arc<Mutex<AddressSpace>>,
+    /// The mutable parts of the thread.
+    inner: Mutex<ThreadInner>,
+}
+
+impl Thread {
+    /// Creates a new thread using the given capability space and address space.
+    ///
+    /// The thread is inactive until it is started.
+    pub fn new(cspace: Arc<CSpace>, address_space: Arc<Mutex<AddressSpace>>) -> Thread {
+        THREAD_COUNT.fetch_add(1, Ordering::Relaxed);
+
+        Thread {
+            id: ThreadId(NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed)),
+            cspace,
+            address_space,
+            inner: Mutex::new(ThreadInner {
+                context: UserContext::new(VirtualAddress::new(0), VirtualAddress::new(0)),
+                state: ThreadState::Inactive,
+                ipc_buffer: None,
+                caller: None,
+                callee: None,
+                bound_notification: None,
+                io_ports: None,
+                process: None,
+                fault: None,
+            }),
+        }
+    }
+
+    /// Starts the thread at `entry` with the stack pointer `stack`.
+    pub fn start(thread: &Arc<Thread>, entry: VirtualAddress, stack: VirtualAddress) {
+        {
+            let mut inner = thread.inner.lock();
+
+            inner.context = UserContext::new(entry, stack);
+            inner.state = ThreadState::Runnable;
+        }
+
+        scheduler::make_ready(thread.clone());
+    }
+
+    /// Stops the thread, removing it from the scheduler and from any endpoint it waits on.
+    ///
+    /// A thread it called forgets it as its caller, so that a later reply doesn't reach it.
+    /// The thread stays inactive until it is started again.
+    pub fn suspend(thread: &Arc<Thread>) {
+        let (state, callee) = {
+            let mut inner = thread.inner.lock();
+
+            inner.caller = None;
+            (
+                mem::replace(&mut inner.state, ThreadState::Inactive),
+                inner.callee.take(),
+            )
+        };
+
+        if let Some(callee) = callee.as_ref().and_then(Weak::upgrade) {
+            let mut callee = callee.inner.lock();
+
+            if callee
+                .caller
+                .as_ref()
+                .map_or(false, |caller| Arc::ptr_eq(caller, thread))
+            {
+                callee.caller = None;
+            }
+        }
+
+        match state {
+            ThreadState::BlockedOnSend { endpoint, .. }
+            | ThreadState::BlockedOnReceive(endpoint)
+            | ThreadState::BlockedOnFault { endpoint, .. } => endpoint.remove(thread),
+            ThreadState::BlockedOnNotification(notification) => notification.remove(thread),
+            ThreadState::BlockedOnProcess(process) => process.remove(thread),
+            ThreadState::Sleeping(timer) => {
+                time::cancel_hrtimer(timer);
+            }
+            _ => scheduler::remove(thread),
+        }
+    }
+
+    /// Lets the current thread `thread` sleep until `deadline` has passed.
+    pub fn sleep(thread: &Arc<Thread>, deadline: Instant) {
+        let sleeper = Arc::downgrade(thread);
+        let mut inner = thread.inner.lock();
+
+        // The timer cannot expire before the state is set, because it locks the thread.
+        let timer = time::add_hrtimer(deadline, move || {
+            if let Some(thread) = sleeper.upgrade() {
+                Thread::wake(&thread);
+            }
+        });
+
+        inner.state = ThreadState::Sleeping(timer);
+        drop(inner);
+
+        scheduler::block_current();
+    }
+
+    /// Makes the thread runnable again after its sleep timer expired.
+    fn wake(thread: &Arc<Thread>) {
+        {
+            let mut inner = thread.inner.lock();
+
+            match inner.state {
+                ThreadState::Sleeping(_) => inner.state = ThreadState::Runnable,
+                _ => return,
+            }
+        }
+
+        scheduler::make_ready(thread.clone());
+    }
+
+    /// Stops the thread because of a fault that could not be resolved.
+    ///
+    /// The fault is delivered to the process of the thread, which is killed with `FAULT_EXIT_STATUS`.
+    pub fn stop_on_fault(thread: &Arc<Thread>, fault: Fault) {
+        {
+            let mut inner = thread.inner.lock();
+
+            inner.fault = Some(fault);
+            inner.state = ThreadState::Faulted;
+        }
+
+        scheduler::remove(thread);
+
+        if let Some(process) = thread.process() {
+            Process::kill(&process, process::FAULT_EXIT_STATUS);
+        }
+    }
+
+    /// Returns the identifier of the thread.
+    pub fn id(&self) -> ThreadId {
+        self.id
+    }
+
+    /// Returns the capability space of the thread.
+    pub fn cspace(&self) -> &Arc<CSpace> {
+        &self.cspace
+    }
+
+    /// Returns the address space of the thread.
+    pub fn address_space(&self) -> &Arc<Mutex<AddressSpace>> {
+        &self.address_space
+    }
+
+    /// Returns a copy of the user mode registers of the thread.
+    pub fn context(&self) -> UserContext {
+        self.inner.lock().context
+    }
+
+    /// Replaces the user mode registers of the thread.
+    pub fn set_context(&self, context: UserContext) {
+        self.inner.lock().context = context;
+    }
+
+    /// Calls `f` to modify the user mode registers of the thread.
+    pub fn modify_context<F: FnOnce(&mut UserContext)>(&self, f: F) {
+        f(&mut self.inner.lock().context);
+    }
+
+    /// Returns the scheduling state of the thread.
+    pub fn state(&self) -> ThreadState {
+        self.inner.lock().state.clone()
+    }
+
+    /// Changes the scheduling state of the thread.
+    pub fn set_state(&self, state: ThreadState) {
+        self.inner.lock().state = state;
+    }
+
+    /// Changes the scheduling state of the thread to `state`, if `condition` holds for the current state.
+    ///
+    /// The state is checked and changed under the lock of the thread, so no other change can come in between.
+    /// Returns whether the state was changed.
+    pub fn transition_state<F: FnOnce(&ThreadState) -> bool>(
+        &self,
+        condition: F,
+        state: ThreadState,
+    ) -> bool {
+        let mut inner = self.inner.lock();
+
+        if condition(&inner.state) {
+            inner.state = state;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the address of the IPC buffer of the thread.
+    pub fn ipc_buffer(&self) -> Option<VirtualAddress> {
+        self.inner.lock().ipc_buffer
+    }
+
+    /// Sets the address of the IPC buffer of the thread.
+    pub fn set_ipc_buffer(&self, ipc_buffer: Option<VirtualAddress>) {
+        self.inner.lock().ipc_buffer = ipc_buffer;
+    }
+
+    /// Sets the thread that waits for a reply from the thread `thread`.
+    pub fn set_caller(thread: &Arc<Thread>, caller: Arc<Thread>) {
+        caller.inner.lock().callee = Some(Arc::downgrade(thread));
+        thread.inner.lock().caller = Some(caller);
+    }
+
+    /// Removes and returns the thread that waits for a reply from this thread.
+    pub fn take_caller(&self) -> Option<Arc<Thread>> {
+        self.inner.lock().caller.take()
+    }
+
+    /// Returns the notification bound to the thread.
+    pub fn bound_notification(&self) -> Option<Arc<Notification>> {
+        self.inner.lock().bound_notification.clone()
+    }
+
+    /// Sets the notification bound to the thread.
+    ///
+    /// This is only used by the notification itself, which keeps track of its bound thread as well.
+    pub fn set_bound_notification(&self, notification: Option<Arc<Notification>>) {
+        self.inner.lock().bound_notification = notification;
+    }
+
+    /// Allows the thread to access the I/O ports of the range directly.
+    pub fn allow_io_ports(&self, range: &Arc<IoPortRange>) {
+        self.inner
+            .lock()
+            .io_ports
+            .get_or_insert_with(|| Box::new(IoPortSet::new()))
+            .allow(range);
+    }
+
+    /// Calls `f` with the I/O ports the thread can access, if it can access any.
+    pub fn with_io_ports<F: FnOnce(Option<&IoPortSet>)>(&self, f: F) {
+        f(self.inner.lock().io_ports.as_ref().map(|ports| &**ports));
+    }
+
+    /// Returns the process the thread belongs to, if it belongs to one that still exists.
+    pub fn process(&self) -> Option<Arc<Process>> {
+        self.inner.lock().process.as_ref().and_then(Weak::upgrade)
+    }
+
+    /// Sets the process the thread belongs to.
+    ///
+    /// This is only used by the process itself, which keeps track of its threads as well.
+    pub fn set_process(&self, process: Weak<Process>) {
+        self.inner.lock().process = Some(process);
+    }
+
+    /// Returns the last fault caused by the thread.
+    pub fn fault(&self) -> Option<Fault> {
+        self.inner.lock().fault
+    }
+
+    /// Records the fault caused by the thread.
+    ///
+    /// This is used for faults that are resolved by a pager, other faults stop the thread with `stop_on_fault`.
+    pub fn set_fault(&self, fault: Fault) {
+        self.inner.lock().fault = Some(fault);
+    }
+}
+
+impl Drop for Thread {
+    fn drop(&mut self) {
+        THREAD_COUNT.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Returns the number of threads that exist, whether they run, are blocked or are inactive.
+pub fn count() -> usize {
+    THREAD_COUNT.load(Ordering::Relaxed)
+}
@@ -0,0 +1,137 @@
+//! Provides the clocks of the kernel and timeouts.
+//!
+//! The architecture specific code picks the best clock source of the machine, which drives the monotonic clock.
+//! The wall clock is set from the time read from the firmware or the hardware during boot.
+//! Timeouts are kept in a timer wheel and events that need to happen on time in the high resolution timer queue
+//! of the processor. There is no periodic tick, instead the timer interrupt of each processor is programmed
+//! for the earliest deadline and calls `run_timers`.
+
+mod clock_source;
+mod hrtimer;
+mod timer_wheel;
+mod wall_clock;
+
+use alloc::{boxed::Box, vec::Vec};
+use core::{cmp::min, sync::atomic::spin_loop_hint};
+use lazy_static::lazy_static;
+
+pub use self::{
+    clock_source::{clock_source, measure_frequency, ClockSource, Instant},
+    hrtimer::{HrTimerId, HrTimerQueue},
+    timer_wheel::{TimerCallback, TimerId, TimerWheel},
+    wall_clock::{set_wall_clock, unix_time, wall_clock, DateTime},
+};
+use crate::{
+    arch::{Arch, Architecture},
+    sync::Mutex,
+};
+pub use core::time::Duration;
+
+lazy_static! {
+    /// The pending timeouts of the kernel.
+    static ref TIMERS: Mutex<TimerWheel> = Mutex::new(TimerWheel::new());
+
+    /// The high resolution timers of each processor.
+    static ref HRTIMERS: Vec<Mutex<HrTimerQueue>> = (0..Arch::MAXIMUM_CPU_COUNT)
+        .map(|cpu| Mutex::new(HrTimerQueue::new(cpu)))
+        .collect();
+}
+
+/// Programs the timer interrupt of the current processor for the next deadline.
+fn program_timer() {
+    Arch::set_timer(next_deadline());
+}
+
+/// Starts the monotonic clock, driven by the given clock source.
+///
+/// This is called once by the architecture specific code during boot.
+pub fn init(source: &'static dyn ClockSource) {
+    clock_source::start(source);
+}
+
+/// Waits for the duration to pass without letting anything else run.
+///
+/// This is meant for short waits, like the ones required by hardware.
+pub fn delay(duration: Duration) {
+    let deadline = Instant::now() + duration;
+
+    while Instant::now() < deadline {
+        spin_loop_hint();
+    }
+}
+
+/// Adds a timeout that calls `callback` once `deadline` has passed.
+///
+/// The callback is called without holding any locks of the timer wheel,
+/// so it can add or cancel timeouts itself.
+pub fn add_timer<F: FnMut() + Send + 'static>(deadline: Instant, callback: F) -> TimerId {
+    let id = TIMERS.lock().add(deadline, Box::new(callback));
+
+    program_timer();
+
+    id
+}
+
+/// Cancels the timeout before it expires.
+///
+/// Returns `false` if the timeout already expired or was cancelled before.
+pub fn cancel_timer(id: TimerId) -> bool {
+    let cancelled = TIMERS.lock().cancel(id);
+
+    program_timer();
+
+    cancelled
+}
+
+/// Adds a high resolution timer to the current processor that calls `callback` once `deadline` has passed.
+///
+/// The callback is called in the timer interrupt without holding any locks of the timer queue.
+pub fn add_hrtimer<F: FnMut() + Send + 'static>(deadline: Instant, callback: F) -> HrTimerId {
+    let id = HRTIMERS[Arch::current_cpu()]
+        .lock()
+        .add(deadline, Box::new(callback));
+
+    program_timer();
+
+    id
+}
+
+/// Cancels the high resolution timer before it expires.
+///
+/// Returns `false` if the timer already expired or was cancelled before.
+pub fn cancel_hrtimer(id: HrTimerId) -> bool {
+    let cancelled = HRTIMERS[id.cpu()].lock().cancel(id);
+
+    if id.cpu() == Arch::current_cpu() {
+        program_timer();
+    }
+
+    cancelled
+}
+
+/// Returns the earliest deadline of the timeouts and the high resolution timers of the current processor.
+pub fn next_deadline() -> Option<Instant> {
+    let timeout = TIMERS.lock().next_deadline();
+    let hrtimer = HRTIMERS[Arch::current_cpu()].lock().next_deadline();
+
+    match (timeout, hrtimer) {
+        (Some(timeout), Some(hrtimer)) => Some(min(timeout, hrtimer)),
+        (timeout, hrtimer) => timeout.or(hrtimer),
+    }
+}
+
+/// Calls the callbacks of the high resolution timers of the current processor and of the timeouts
+/// whose deadline has passed.
+///
+/// This is called by the timer interrupt, which is then programmed for the next deadline.
+pub fn run_timers() {
+    let now = Instant::now();
+    let expired_hrtimers = HRTIMERS[Arch::current_cpu()].lock().expire(now);
+    let expired_timeouts = TIMERS.lock().expire(now);
+
+    for mut callback in expired_hrtimers.into_iter().chain(expired_timeouts) {
+        callback();
+    }
+
+    program_timer();
+}
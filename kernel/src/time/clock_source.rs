@@ -0,0 +1,199 @@
+//! Provides the clock sources and the monotonic clock that is driven by one of them.
+//!
+//! The counter of the clock source is converted to nanoseconds with a fixed point multiplier.
+//! Counters that wrap around are handled, as long as the clock is read at least once per period of the counter.
+
+use core::{
+    ops::{Add, Sub},
+    sync::atomic::{spin_loop_hint, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use crate::sync::Mutex;
+
+/// The number of nanoseconds in a second.
+pub(super) const NANOSECONDS_PER_SECOND: u64 = 1_000_000_000;
+
+/// The number of fractional bits of the multiplier that converts counter ticks to nanoseconds.
+const MULTIPLIER_SHIFT: u32 = 32;
+
+/// A counter that increases at a fixed frequency.
+pub trait ClockSource: Sync {
+    /// Returns the name of the clock source.
+    fn name(&self) -> &'static str;
+
+    /// Reads the current value of the counter.
+    fn read(&self) -> u64;
+
+    /// Returns the mask of the bits the counter uses.
+    ///
+    /// The counter wraps around to zero after reaching the mask.
+    fn mask(&self) -> u64;
+
+    /// Returns the number of ticks of the counter per second.
+    fn frequency(&self) -> u64;
+}
+
+/// Keeps the monotonic clock up to date with the clock source.
+struct Timekeeper {
+    /// The clock source that drives the clock.
+    source: &'static dyn ClockSource,
+    /// The number of nanoseconds per tick, with `MULTIPLIER_SHIFT` fractional bits.
+    multiplier: u64,
+    /// The value of the counter when the clock was last updated.
+    last_count: u64,
+    /// The nanoseconds since the clock was started.
+    nanoseconds: u64,
+    /// The fraction of a nanosecond that was not yet added to `nanoseconds`.
+    fraction: u64,
+}
+
+impl Timekeeper {
+    /// Creates a clock that starts at zero now.
+    fn new(source: &'static dyn ClockSource) -> Timekeeper {
+        Timekeeper {
+            source,
+            multiplier: (NANOSECONDS_PER_SECOND << MULTIPLIER_SHIFT) / source.frequency(),
+            last_count: source.read(),
+            nanoseconds: 0,
+            fraction: 0,
+        }
+    }
+
+    /// Adds the ticks that passed since the last update and returns the current nanoseconds.
+    fn update(&mut self) -> u64 {
+        let count = self.source.read();
+        let ticks = count.wrapping_sub(self.last_count) & self.source.mask();
+        let shifted = u128::from(ticks) * u128::from(self.multiplier) + u128::from(self.fraction);
+
+        self.last_count = count;
+        self.nanoseconds += (shifted >> MULTIPLIER_SHIFT) as u64;
+        self.fraction = (shifted & ((1 << MULTIPLIER_SHIFT) - 1)) as u64;
+        LATEST_NANOSECONDS.store(self.nanoseconds as usize, Ordering::Relaxed);
+
+        self.nanoseconds
+    }
+}
+
+/// The state of the monotonic clock, which exists once it is started.
+static TIMEKEEPER: Mutex<Option<Timekeeper>> = Mutex::new(None);
+
+/// The nanoseconds of the monotonic clock when it was last updated, which can be read without the lock.
+static LATEST_NANOSECONDS: AtomicUsize = AtomicUsize::new(0);
+
+/// Starts the monotonic clock at zero, driven by the clock source.
+pub(super) fn start(source: &'static dyn ClockSource) {
+    log::info!(
+        "Using the {} clock source running at {} Hz.",
+        source.name(),
+        source.frequency()
+    );
+
+    *TIMEKEEPER.lock() = Some(Timekeeper::new(source));
+}
+
+/// Returns the clock source that drives the monotonic clock.
+pub fn clock_source() -> Option<&'static dyn ClockSource> {
+    TIMEKEEPER
+        .lock()
+        .as_ref()
+        .map(|timekeeper| timekeeper.source)
+}
+
+/// Converts the duration to nanoseconds, saturating if it does not fit.
+pub(super) fn to_nanoseconds(duration: Duration) -> u64 {
+    duration
+        .as_secs()
+        .saturating_mul(NANOSECONDS_PER_SECOND)
+        .saturating_add(u64::from(duration.subsec_nanos()))
+}
+
+/// A point in time measured by the monotonic clock.
+///
+/// The monotonic clock counts the nanoseconds since it was started during boot and never goes backwards.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant(u64);
+
+impl Instant {
+    /// Returns the current time.
+    ///
+    /// This is zero until the clock is started.
+    pub fn now() -> Instant {
+        Instant(TIMEKEEPER.lock().as_mut().map_or(0, Timekeeper::update))
+    }
+
+    /// Returns the current time without waiting for the clock.
+    ///
+    /// If the clock is in use, the time of its last update is returned instead.
+    /// This can be called in any context, even while the current processor reads the clock.
+    pub fn now_or_latest() -> Instant {
+        match TIMEKEEPER.try_lock() {
+            Some(mut timekeeper) => Instant(timekeeper.as_mut().map_or(0, Timekeeper::update)),
+            None => Instant(LATEST_NANOSECONDS.load(Ordering::Relaxed) as u64),
+        }
+    }
+
+    /// Returns the instant the given number of nanoseconds after the clock was started.
+    pub const fn from_nanoseconds(nanoseconds: u64) -> Instant {
+        Instant(nanoseconds)
+    }
+
+    /// Returns the number of nanoseconds since the clock was started.
+    pub fn as_nanoseconds(self) -> u64 {
+        self.0
+    }
+
+    /// Returns the time that passed from `earlier` to this instant, or zero if `earlier` is later.
+    pub fn duration_since(self, earlier: Instant) -> Duration {
+        Duration::from_nanos(self.0.saturating_sub(earlier.0))
+    }
+
+    /// Returns the time that passed since this instant.
+    pub fn elapsed(self) -> Duration {
+        Instant::now().duration_since(self)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        Instant(self.0.saturating_add(to_nanoseconds(duration)))
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    fn sub(self, duration: Duration) -> Instant {
+        Instant(self.0.saturating_sub(to_nanoseconds(duration)))
+    }
+}
+
+/// Measures the frequency of the counter read by `read` against the reference clock source.
+///
+/// The measurement takes `duration`, which must be shorter than the period of the reference counter.
+pub fn measure_frequency<F: Fn() -> u64>(
+    read: F,
+    reference: &dyn ClockSource,
+    duration: Duration,
+) -> u64 {
+    let reference_ticks = u128::from(to_nanoseconds(duration)) * u128::from(reference.frequency())
+        / u128::from(NANOSECONDS_PER_SECOND);
+    let reference_start = reference.read();
+    let start = read();
+
+    let reference_elapsed = loop {
+        let elapsed = reference.read().wrapping_sub(reference_start) & reference.mask();
+
+        if u128::from(elapsed) >= reference_ticks.max(1) {
+            break elapsed;
+        }
+
+        spin_loop_hint();
+    };
+
+    let elapsed = read().wrapping_sub(start);
+
+    (u128::from(elapsed) * u128::from(reference.frequency()) / u128::from(reference_elapsed)) as u64
+}
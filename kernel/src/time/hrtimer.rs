@@ -0,0 +1,89 @@
+//! Provides the queues of high resolution timers, of which each processor has one.
+//!
+//! High resolution timers expire as close to their deadline as the timer interrupt allows,
+//! since the timer interrupt of each processor is programmed for the earliest deadline in its queue.
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::mem;
+
+use super::{Instant, TimerCallback};
+
+/// Identifies a high resolution timer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HrTimerId {
+    /// The processor whose queue holds the timer.
+    cpu: usize,
+    /// The deadline of the timer, which sorts the queue.
+    deadline: Instant,
+    /// The number of the timer, which is unique in its queue.
+    number: u64,
+}
+
+impl HrTimerId {
+    /// Returns the processor whose queue holds the timer.
+    pub fn cpu(&self) -> usize {
+        self.cpu
+    }
+}
+
+/// The high resolution timers of a processor, sorted by their deadline.
+pub struct HrTimerQueue {
+    /// The processor the queue belongs to.
+    cpu: usize,
+    /// The callbacks of the timers, keyed by their deadline and number.
+    timers: BTreeMap<(Instant, u64), TimerCallback>,
+    /// The number of the next timer that is added.
+    next_number: u64,
+}
+
+impl HrTimerQueue {
+    /// Creates an empty queue for the processor.
+    pub fn new(cpu: usize) -> HrTimerQueue {
+        HrTimerQueue {
+            cpu,
+            timers: BTreeMap::new(),
+            next_number: 0,
+        }
+    }
+
+    /// Adds a timer that calls `callback` once `deadline` has passed.
+    pub fn add(&mut self, deadline: Instant, callback: TimerCallback) -> HrTimerId {
+        let number = self.next_number;
+
+        self.next_number += 1;
+        self.timers.insert((deadline, number), callback);
+
+        HrTimerId {
+            cpu: self.cpu,
+            deadline,
+            number,
+        }
+    }
+
+    /// Removes the timer before it expires.
+    ///
+    /// Returns `false` if the timer already expired or was cancelled before.
+    pub fn cancel(&mut self, id: HrTimerId) -> bool {
+        debug_assert_eq!(id.cpu, self.cpu);
+
+        self.timers.remove(&(id.deadline, id.number)).is_some()
+    }
+
+    /// Checks if no timers are pending.
+    pub fn is_empty(&self) -> bool {
+        self.timers.is_empty()
+    }
+
+    /// Returns the earliest deadline of the pending timers.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.timers.keys().next().map(|&(deadline, _)| deadline)
+    }
+
+    /// Removes the timers whose deadline is not after `now` and returns their callbacks in order of their deadlines.
+    pub fn expire(&mut self, now: Instant) -> Vec<TimerCallback> {
+        let pending = self.timers.split_off(&(now, u64::max_value()));
+        let expired = mem::replace(&mut self.timers, pending);
+
+        expired.into_iter().map(|(_, callback)| callback).collect()
+    }
+}
@@ -0,0 +1,148 @@
+//! Provides a timer wheel, which sorts timeouts into slots by their deadline.
+//!
+//! Each slot covers `GRANULARITY` nanoseconds and the slots are reused after a full revolution of the wheel.
+//! Adding and cancelling a timer only touches one slot and expiring timers only visits the slots that passed.
+
+use alloc::{boxed::Box, vec::Vec};
+use core::cmp;
+
+use super::Instant;
+
+/// The number of slots of the wheel.
+const SLOT_COUNT: usize = 256;
+
+/// The number of nanoseconds covered by a slot.
+const GRANULARITY: u64 = 1_000_000;
+
+/// The function that is called when a timer expires.
+pub type TimerCallback = Box<dyn FnMut() + Send>;
+
+/// Identifies a timer in a timer wheel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TimerId {
+    /// The number of the timer, which is unique in its wheel.
+    number: u64,
+    /// The slot that holds the timer.
+    slot: usize,
+}
+
+/// A pending timeout.
+struct Timer {
+    /// The identifier of the timer.
+    id: TimerId,
+    /// The time at which the timer expires.
+    deadline: Instant,
+    /// The function to call when the timer expires.
+    callback: TimerCallback,
+}
+
+/// A set of timers sorted into slots by their deadline.
+pub struct TimerWheel {
+    /// The timers in each slot.
+    slots: Vec<Vec<Timer>>,
+    /// The tick of the earliest slot that may still contain expired timers.
+    current_tick: u64,
+    /// The number of the next timer that is added.
+    next_number: u64,
+}
+
+/// Returns the tick the instant lies in.
+fn tick(instant: Instant) -> u64 {
+    instant.as_nanoseconds() / GRANULARITY
+}
+
+impl TimerWheel {
+    /// Creates an empty timer wheel.
+    pub fn new() -> TimerWheel {
+        TimerWheel {
+            slots: (0..SLOT_COUNT).map(|_| Vec::new()).collect(),
+            current_tick: 0,
+            next_number: 0,
+        }
+    }
+
+    /// Adds a timer that calls `callback` once `deadline` has passed.
+    ///
+    /// Timers whose deadline already passed expire the next time the wheel is expired.
+    pub fn add(&mut self, deadline: Instant, callback: TimerCallback) -> TimerId {
+        let slot = (cmp::max(tick(deadline), self.current_tick) % SLOT_COUNT as u64) as usize;
+        let id = TimerId {
+            number: self.next_number,
+            slot,
+        };
+
+        self.next_number += 1;
+        self.slots[slot].push(Timer {
+            id,
+            deadline,
+            callback,
+        });
+
+        id
+    }
+
+    /// Removes the timer before it expires.
+    ///
+    /// Returns `false` if the timer already expired or was cancelled before.
+    pub fn cancel(&mut self, id: TimerId) -> bool {
+        let slot = &mut self.slots[id.slot];
+
+        match slot.iter().position(|timer| timer.id == id) {
+            Some(index) => {
+                slot.swap_remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Checks if no timers are pending.
+    pub fn is_empty(&self) -> bool {
+        self.slots.iter().all(Vec::is_empty)
+    }
+
+    /// Returns the earliest deadline of the pending timers.
+    ///
+    /// This visits every pending timer.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.slots
+            .iter()
+            .flat_map(|slot| slot.iter().map(|timer| timer.deadline))
+            .min()
+    }
+
+    /// Removes the timers whose deadline is not after `now` and returns their callbacks in order of their deadlines.
+    ///
+    /// The callbacks are returned instead of being called, so that they can be called without holding a lock.
+    pub fn expire(&mut self, now: Instant) -> Vec<TimerCallback> {
+        let now_tick = cmp::max(tick(now), self.current_tick);
+        let passed_slots = cmp::min(now_tick - self.current_tick + 1, SLOT_COUNT as u64);
+        let mut expired = Vec::new();
+
+        for offset in 0..passed_slots {
+            let slot = &mut self.slots[((self.current_tick + offset) % SLOT_COUNT as u64) as usize];
+            let mut index = 0;
+
+            while index < slot.len() {
+                if slot[index].deadline <= now {
+                    expired.push(slot.swap_remove(index));
+                } else {
+                    index += 1;
+                }
+            }
+        }
+
+        // Timers may still be added to the slot of the current tick, so it is visited again next time.
+        self.current_tick = now_tick;
+
+        expired.sort_by_key(|timer| timer.deadline);
+
+        expired.into_iter().map(|timer| timer.callback).collect()
+    }
+}
+
+impl Default for TimerWheel {
+    fn default() -> TimerWheel {
+        TimerWheel::new()
+    }
+}
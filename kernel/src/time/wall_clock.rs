@@ -0,0 +1,222 @@
+//! Provides the wall clock, which tells the current date and time in UTC.
+//!
+//! The wall clock is set once during boot and then follows the monotonic clock.
+
+use core::{fmt, time::Duration};
+
+use super::{clock_source::NANOSECONDS_PER_SECOND, Instant};
+use crate::sync::Mutex;
+
+/// The number of seconds in a day.
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// The number of days from the first of March of year zero to the first of January 1970.
+///
+/// Counting from March puts the leap day at the end of the year, which simplifies the calendar calculations.
+const UNIX_EPOCH_DAYS: u64 = 719_468;
+
+/// The number of days in a cycle of 400 years, after which the calendar repeats.
+const DAYS_PER_ERA: u64 = 146_097;
+
+/// The earliest year a date can have.
+const MINIMUM_YEAR: u16 = 1970;
+
+/// The latest year a date can have.
+const MAXIMUM_YEAR: u16 = 9999;
+
+/// The time of the wall clock at the start of the monotonic clock.
+static BOOT_TIME: Mutex<Option<Duration>> = Mutex::new(None);
+
+/// A date and time in UTC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DateTime {
+    /// The year.
+    year: u16,
+    /// The month, starting at one.
+    month: u8,
+    /// The day of the month, starting at one.
+    day: u8,
+    /// The hour of the day.
+    hour: u8,
+    /// The minute of the hour.
+    minute: u8,
+    /// The second of the minute.
+    second: u8,
+    /// The nanosecond of the second.
+    nanosecond: u32,
+}
+
+/// Checks if the year is a leap year.
+fn is_leap_year(year: u16) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+/// Returns the number of days in the month of the year.
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl DateTime {
+    /// Creates a date and time from its parts.
+    ///
+    /// Returns `None` if the parts do not form a valid date between 1970 and 9999.
+    /// Leap seconds are not supported.
+    pub fn new(
+        year: u16,
+        month: u8,
+        day: u8,
+        hour: u8,
+        minute: u8,
+        second: u8,
+        nanosecond: u32,
+    ) -> Option<DateTime> {
+        let valid = year >= MINIMUM_YEAR
+            && year <= MAXIMUM_YEAR
+            && month >= 1
+            && month <= 12
+            && day >= 1
+            && day <= days_in_month(year, month)
+            && hour < 24
+            && minute < 60
+            && second < 60
+            && u64::from(nanosecond) < NANOSECONDS_PER_SECOND;
+
+        if valid {
+            Some(DateTime {
+                year,
+                month,
+                day,
+                hour,
+                minute,
+                second,
+                nanosecond,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the date and time the given time after the first of January 1970.
+    ///
+    /// Times after the year 9999 are not supported.
+    pub fn from_unix_time(time: Duration) -> DateTime {
+        let seconds = time.as_secs();
+        let days = seconds / SECONDS_PER_DAY + UNIX_EPOCH_DAYS;
+        let seconds_of_day = seconds % SECONDS_PER_DAY;
+
+        let era = days / DAYS_PER_ERA;
+        let day_of_era = days % DAYS_PER_ERA;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+        let year = era * 400 + year_of_era + if month <= 2 { 1 } else { 0 };
+
+        DateTime {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (seconds_of_day / 3600) as u8,
+            minute: (seconds_of_day / 60 % 60) as u8,
+            second: (seconds_of_day % 60) as u8,
+            nanosecond: time.subsec_nanos(),
+        }
+    }
+
+    /// Returns the time since the first of January 1970.
+    pub fn unix_time(&self) -> Duration {
+        let month = u64::from(self.month);
+        let year = u64::from(self.year) - if month <= 2 { 1 } else { 0 };
+        let era = year / 400;
+        let year_of_era = year % 400;
+        let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+        let day_of_year = (153 * shifted_month + 2) / 5 + u64::from(self.day) - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * DAYS_PER_ERA + day_of_era - UNIX_EPOCH_DAYS;
+        let seconds = days * SECONDS_PER_DAY
+            + u64::from(self.hour) * 3600
+            + u64::from(self.minute) * 60
+            + u64::from(self.second);
+
+        Duration::new(seconds, self.nanosecond)
+    }
+
+    /// Returns the year.
+    pub fn year(&self) -> u16 {
+        self.year
+    }
+
+    /// Returns the month, starting at one.
+    pub fn month(&self) -> u8 {
+        self.month
+    }
+
+    /// Returns the day of the month, starting at one.
+    pub fn day(&self) -> u8 {
+        self.day
+    }
+
+    /// Returns the hour of the day.
+    pub fn hour(&self) -> u8 {
+        self.hour
+    }
+
+    /// Returns the minute of the hour.
+    pub fn minute(&self) -> u8 {
+        self.minute
+    }
+
+    /// Returns the second of the minute.
+    pub fn second(&self) -> u8 {
+        self.second
+    }
+
+    /// Returns the nanosecond of the second.
+    pub fn nanosecond(&self) -> u32 {
+        self.nanosecond
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// Sets the wall clock to the given date and time.
+pub fn set_wall_clock(now: DateTime) {
+    let since_boot = Duration::from_nanos(Instant::now().as_nanoseconds());
+
+    *BOOT_TIME.lock() = Some(
+        now.unix_time()
+            .checked_sub(since_boot)
+            .unwrap_or_else(|| Duration::from_secs(0)),
+    );
+}
+
+/// Returns the time since the first of January 1970, if the wall clock was set.
+pub fn unix_time() -> Option<Duration> {
+    let boot_time = (*BOOT_TIME.lock())?;
+
+    Some(boot_time + Duration::from_nanos(Instant::now().as_nanoseconds()))
+}
+
+/// Returns the current date and time, if the wall clock was set.
+pub fn wall_clock() -> Option<DateTime> {
+    unix_time().map(DateTime::from_unix_time)
+}
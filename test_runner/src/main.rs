@@ -194,6 +194,15 @@ fn run_test(config: &Config, name: &str) -> Result<(), TestFailReason> {
                 .stdout(Stdio::piped())
                 .stderr(Stdio::null());
 
+            // Only the MSI test needs the devices that use message signaled interrupts.
+            if get_test_short_name(config, name) == "msi" {
+                command
+                    .arg("-device")
+                    .arg("edu")
+                    .arg("-device")
+                    .arg("virtio-rng-pci");
+            }
+
             let mut child = command.spawn().map_err(|err| {
                 TestFailReason::FailedToPrepare(format!("Could not run qemu: {}", err))
             })?;
@@ -266,6 +275,12 @@ fn prepare_test(config: &Config, name: &str) -> Result<(), TestFailReason> {
             copy(config.result_dir.join(name), boot_path.join("BOOTX64.EFI"))
                 .map_err(|err| TestFailReason::FailedToPrepare(format!("cp: {}", err)))?;
 
+            // Copy the initial ramdisk next to the binary, if there is one.
+            if let Some(initrd) = &config.initrd {
+                copy(initrd, boot_path.join("initrd.cpio"))
+                    .map_err(|err| TestFailReason::FailedToPrepare(format!("cp: {}", err)))?;
+            }
+
             // Create the esp image.
             let esp_path = tmp_dir.path().join("esp.img");
             {
@@ -443,6 +458,8 @@ struct Config {
     bios: String,
     /// The timeout in seconds.
     timeout: u64,
+    /// The initial ramdisk to place on the ESP.
+    initrd: Option<PathBuf>,
 }
 
 /// Returns the configuration for this run of test_runner.
@@ -522,6 +539,12 @@ fn get_config() -> Result<Config, String> {
             .long("timeout")
             .help("The timeout, in seconds, used for tests")
             .long_help("Specifies the timeout that is used for tests in seconds. The default is 30."))
+        .arg(Arg::with_name("initrd")
+            .required(false)
+            .takes_value(true)
+            .long("initrd")
+            .help("The initial ramdisk to use")
+            .long_help("Specifies the path to the initial ramdisk that is placed next to the test binary. By default no initial ramdisk is used."))
         .get_matches();
 
     let arch = matches.value_of("arch").unwrap().to_string();
@@ -586,5 +609,6 @@ fn get_config() -> Result<Config, String> {
             .unwrap_or("/usr/share/ovmf/OVMF.fd")
             .to_string(),
         timeout: value_t!(matches, "timeout", u64).unwrap_or(30),
+        initrd: matches.value_of("initrd").map(PathBuf::from),
     })
 }